@@ -0,0 +1,200 @@
+use serenity::http::Http;
+use serenity::model::id::ChannelId;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::field::{Field, Visit};
+use tracing::{warn, Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+use crate::scheduler::unix_now;
+use crate::{db, markdown, trace, Handler};
+
+/// How often queued errors are posted to the error channel.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+/// Most distinct errors posted per flush; the rest are only counted.
+const MAX_ALERTS_PER_FLUSH: usize = 5;
+/// How long an error stays quiet after being posted, however often it recurs.
+const DEDUP_WINDOW_SECS: i64 = 15 * 60;
+/// Errors queued between flushes at most, so a storm can't eat memory.
+const MAX_PENDING: usize = 200;
+
+/// An error logged somewhere in the bot.
+#[derive(Debug, Clone)]
+struct Alert {
+    target: String,
+    message: String,
+    correlation_id: Option<String>,
+}
+
+impl Alert {
+    /// Errors that differ only in numbers (IDs, statuses, counts) count as one.
+    fn key(&self) -> String {
+        let message: String = self
+            .message
+            .chars()
+            .map(|c| if c.is_ascii_digit() { '#' } else { c })
+            .collect();
+        format!("{} {}", self.target, message)
+    }
+}
+
+static PENDING: Mutex<Vec<Alert>> = Mutex::new(Vec::new());
+
+/// A tracing layer that queues every `error!` for the error channel.
+pub struct AlertLayer;
+
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        } else {
+            self.0.push_str(&format!(" {}={:?}", field.name(), value));
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for AlertLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if *event.metadata().level() != Level::ERROR {
+            return;
+        }
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let alert = Alert {
+            target: event.metadata().target().to_string(),
+            message: visitor.0,
+            correlation_id: trace::current(),
+        };
+        if let Ok(mut pending) = PENDING.lock() {
+            if pending.len() < MAX_PENDING {
+                pending.push(alert);
+            }
+        }
+    }
+}
+
+/// Turns the errors queued since the last flush into lines to post: one per
+/// distinct error with how often it happened, skipping errors posted in the
+/// last [`DEDUP_WINDOW_SECS`], at most [`MAX_ALERTS_PER_FLUSH`] of them.
+fn digest(alerts: Vec<Alert>, last_posted: &mut HashMap<String, i64>, now: i64) -> Vec<String> {
+    let mut grouped: Vec<(String, Alert, usize)> = Vec::new();
+    for alert in alerts {
+        let key = alert.key();
+        match grouped.iter_mut().find(|(k, _, _)| *k == key) {
+            Some((_, _, count)) => *count += 1,
+            None => grouped.push((key, alert, 1)),
+        }
+    }
+    last_posted.retain(|_, at| now - *at < DEDUP_WINDOW_SECS);
+    grouped.retain(|(key, _, _)| !last_posted.contains_key(key));
+
+    let more = grouped.len().saturating_sub(MAX_ALERTS_PER_FLUSH);
+    let mut lines: Vec<String> = grouped
+        .into_iter()
+        .take(MAX_ALERTS_PER_FLUSH)
+        .map(|(key, alert, count)| {
+            last_posted.insert(key, now);
+            let mut line = format!("🚨 `{}` {}", alert.target, alert.message);
+            if count > 1 {
+                line.push_str(&format!(" (×{})", count));
+            }
+            if let Some(id) = alert.correlation_id {
+                line.push_str(&format!(" (ref: {})", id));
+            }
+            line
+        })
+        .collect();
+    if more > 0 {
+        lines.push(format!("…and {} more kinds of error; see the logs.", more));
+    }
+    lines
+}
+
+/// Starts the loop that posts queued errors to the `!errorchannel`.
+pub fn spawn(handler: Arc<Handler>, http: Arc<Http>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(FLUSH_INTERVAL);
+        let mut last_posted = HashMap::new();
+        loop {
+            ticker.tick().await;
+            handler.post_alerts(&http, &mut last_posted).await;
+        }
+    });
+}
+
+impl Handler {
+    async fn post_alerts(&self, http: &Http, last_posted: &mut HashMap<String, i64>) {
+        let alerts = match PENDING.lock() {
+            Ok(mut pending) => std::mem::take(&mut *pending),
+            Err(_) => return,
+        };
+        if alerts.is_empty() {
+            return;
+        }
+        let channel = {
+            let conn = self.db.lock().await;
+            db::get_config(&conn, None, "error_channel").ok().flatten()
+        };
+        let Some(channel_id) = channel.and_then(|c| c.parse().ok()).map(ChannelId::new) else {
+            return;
+        };
+        let lines = digest(alerts, last_posted, unix_now());
+        if lines.is_empty() {
+            return;
+        }
+        let content = markdown::truncate(&lines.join("\n"), markdown::DISCORD_MESSAGE_MAX);
+        // Not error!, which would queue another alert about the alert
+        if let Err(why) = channel_id.say(http, content).await {
+            warn!("Failed to post to the error channel: {:?}", why);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alert(message: &str) -> Alert {
+        Alert {
+            target: "discord_bot::db".to_string(),
+            message: message.to_string(),
+            correlation_id: None,
+        }
+    }
+
+    #[test]
+    fn test_digest_groups_and_dedups() {
+        let mut last_posted = HashMap::new();
+        let lines = digest(
+            vec![alert("LLM error: status 500"), alert("LLM error: status 502"), alert("DB locked")],
+            &mut last_posted,
+            1000,
+        );
+        assert_eq!(lines, ["🚨 `discord_bot::db` LLM error: status 500 (×2)", "🚨 `discord_bot::db` DB locked"]);
+
+        // Quiet within the window, posted again after it
+        assert!(digest(vec![alert("DB locked")], &mut last_posted, 1000 + 60).is_empty());
+        assert_eq!(digest(vec![alert("DB locked")], &mut last_posted, 1000 + DEDUP_WINDOW_SECS).len(), 1);
+    }
+
+    #[test]
+    fn test_digest_rate_limits() {
+        let alerts = (0..MAX_ALERTS_PER_FLUSH + 2).map(|i| alert(&"x".repeat(i + 1))).collect();
+        let lines = digest(alerts, &mut HashMap::new(), 0);
+        assert_eq!(lines.len(), MAX_ALERTS_PER_FLUSH + 1);
+        assert_eq!(lines.last().unwrap(), "…and 2 more kinds of error; see the logs.");
+    }
+
+    #[test]
+    fn test_alert_carries_reference() {
+        let mut with_ref = alert("boom");
+        with_ref.correlation_id = Some("abc123".to_string());
+        let lines = digest(vec![with_ref], &mut HashMap::new(), 0);
+        assert_eq!(lines, ["🚨 `discord_bot::db` boom (ref: abc123)"]);
+    }
+}
@@ -0,0 +1,113 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rusqlite::Connection;
+use serde_json::json;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::db::{self, ArchivableMessage};
+
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+/// Where the archives for the database at `db_path` go: a directory beside it.
+pub fn dir_for(db_path: &str) -> PathBuf {
+    Path::new(db_path).with_extension("archive")
+}
+
+/// One JSON line of an archive file.
+fn message_line(message: &ArchivableMessage) -> String {
+    json!({
+        "history": message.history_key,
+        "role": message.role,
+        "author": message.author,
+        "content": message.content,
+        "timestamp": message.timestamp,
+        "message_id": message.message_id,
+    })
+    .to_string()
+}
+
+/// Writes `messages` to a gzipped JSONL file at `path`, through a temporary
+/// file so a crash never leaves half an archive behind.
+fn write_archive(path: &Path, messages: &[ArchivableMessage]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let partial = path.with_extension("partial");
+    let mut encoder = GzEncoder::new(File::create(&partial)?, Compression::default());
+    for message in messages {
+        writeln!(encoder, "{}", message_line(message))?;
+    }
+    encoder.finish()?.sync_all()?;
+    fs::rename(&partial, path)
+}
+
+/// Moves messages in listening channels older than the `!retention archive`
+/// setting out of the database into one archive file per channel under
+/// `dir`. Returns how many were archived.
+pub fn run(conn: &Connection, dir: &Path, now: i64) -> Result<usize, String> {
+    let Some(days) = db::get_retention_days(conn, "archive").map_err(|e| e.to_string())? else {
+        return Ok(0);
+    };
+    let before = now - days as i64 * DAY_SECS;
+    let mut archived = 0;
+    for channel in db::get_listening_channels(conn).map_err(|e| e.to_string())? {
+        let messages = db::get_messages_to_archive(conn, &channel, before).map_err(|e| e.to_string())?;
+        let (Some(first), Some(last)) = (messages.first(), messages.last()) else {
+            continue;
+        };
+        let path = dir
+            .join(&channel)
+            .join(format!("{}-{}.jsonl.gz", first.timestamp, last.timestamp));
+        write_archive(&path, &messages).map_err(|e| format!("Can't write {}: {}", path.display(), e))?;
+        db::record_message_archive(conn, &channel, before, &path.to_string_lossy(), &messages)
+            .map_err(|e| e.to_string())?;
+        archived += messages.len();
+    }
+    Ok(archived)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::{BufRead, BufReader};
+
+    fn read_archive(path: &Path) -> Vec<serde_json::Value> {
+        let reader = BufReader::new(GzDecoder::new(File::open(path).unwrap()));
+        reader.lines().map(|line| serde_json::from_str(&line.unwrap()).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_dir_for() {
+        assert_eq!(dir_for("./discord-bot.db"), Path::new("./discord-bot.archive"));
+    }
+
+    #[test]
+    fn test_run() {
+        let dir = std::env::temp_dir().join(format!("discord-bot-archive-{}", std::process::id()));
+        let conn = Connection::open_in_memory().unwrap();
+        db::init(&conn).unwrap();
+        db::set_listening(&conn, "chan1", true).unwrap();
+        db::store_message_from(&conn, "chan1", "user", Some("Joe"), None, Some("10"), "old news").unwrap();
+        db::store_message(&conn, "quiet", "user", "not listened to").unwrap();
+        conn.execute("UPDATE messages SET timestamp = 0", []).unwrap();
+
+        // Nothing is archived without a setting
+        assert_eq!(run(&conn, &dir, 100 * DAY_SECS).unwrap(), 0);
+
+        db::set_retention_days(&conn, "archive", Some(30)).unwrap();
+        assert_eq!(run(&conn, &dir, 100 * DAY_SECS).unwrap(), 1);
+        assert_eq!(run(&conn, &dir, 100 * DAY_SECS).unwrap(), 0);
+        assert_eq!(db::get_recent_messages(&conn, "quiet", 10).unwrap().len(), 1);
+
+        let archives = db::get_message_archives(&conn, "chan1").unwrap();
+        assert_eq!(archives.len(), 1);
+        let lines = read_archive(Path::new(&archives[0].path));
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["content"], "old news");
+        assert_eq!(lines[0]["author"], "Joe");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
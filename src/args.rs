@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Prefix that marks a chat message as a bot command.
+pub const COMMAND_PREFIX: char = '!';
+
+/// Parsed command arguments: positional words (quotes group words together)
+/// plus `--flag` / `--key=value` options.
+#[derive(Debug, Default, PartialEq)]
+pub struct Args {
+    raw: String,
+    positional: Vec<String>,
+    flags: HashMap<String, Option<String>>,
+}
+
+impl Args {
+    pub fn parse(input: &str) -> Args {
+        let mut positional = Vec::new();
+        let mut flags = HashMap::new();
+        let mut flags_done = false;
+
+        for (token, quoted) in tokenize(input) {
+            if quoted || flags_done {
+                positional.push(token);
+                continue;
+            }
+            if token == "--" {
+                flags_done = true;
+                continue;
+            }
+            match token.strip_prefix("--") {
+                Some(flag) if !flag.is_empty() => match flag.split_once('=') {
+                    Some((name, value)) => {
+                        flags.insert(name.to_lowercase(), Some(value.to_string()));
+                    }
+                    None => {
+                        flags.insert(flag.to_lowercase(), None);
+                    }
+                },
+                _ => positional.push(token),
+            }
+        }
+
+        Args {
+            raw: input.trim().to_string(),
+            positional,
+            flags,
+        }
+    }
+
+    /// The argument text exactly as typed, for commands that take free-form text.
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positional.is_empty() && self.flags.is_empty()
+    }
+
+    pub fn positional(&self) -> &[String] {
+        &self.positional
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.positional.get(index).map(String::as_str)
+    }
+
+    /// Positional argument at `index` converted to `T`, or `None` if it is missing.
+    pub fn parsed<T: FromStr>(&self, index: usize) -> Option<Result<T, String>> {
+        let value = self.get(index)?;
+        Some(
+            value
+                .parse::<T>()
+                .map_err(|_| format!("`{}` is not a valid value.", value)),
+        )
+    }
+
+    /// Splits off the first positional argument as a lowercased subcommand name,
+    /// returning it with the remaining arguments.
+    pub fn subcommand(&self) -> Option<(String, Args)> {
+        let name = self.positional.first()?.to_lowercase();
+        let raw = self
+            .raw
+            .split_once(char::is_whitespace)
+            .map(|(_, rest)| rest.trim().to_string())
+            .unwrap_or_default();
+        let rest = Args {
+            raw,
+            positional: self.positional[1..].to_vec(),
+            flags: self.flags.clone(),
+        };
+        Some((name, rest))
+    }
+
+    /// True if `--name` or `--name=...` was given.
+    pub fn flag(&self, name: &str) -> bool {
+        self.flags.contains_key(name)
+    }
+
+    /// Value of `--name=value`, or `None` if the flag is missing or has no value.
+    pub fn flag_value(&self, name: &str) -> Option<&str> {
+        self.flags.get(name)?.as_deref()
+    }
+}
+
+/// Splits `!command args...` into the lowercased command name and its parsed arguments.
+/// Returns `None` if the message isn't a command at all.
+pub fn parse_command(content: &str) -> Option<(String, Args)> {
+    let body = content.strip_prefix(COMMAND_PREFIX)?;
+    let (name, rest) = match body.split_once(char::is_whitespace) {
+        Some((name, rest)) => (name, rest),
+        None => (body, ""),
+    };
+    if name.is_empty() {
+        return None;
+    }
+    Some((name.to_lowercase(), Args::parse(rest)))
+}
+
+/// Splits on whitespace, keeping "double" or 'single' quoted runs together.
+/// Quotes only group words at the start of a token and when they are closed, so
+/// apostrophes in ordinary text ("don't") are left alone.
+/// Each token is paired with whether it was quoted.
+fn tokenize(input: &str) -> Vec<(String, bool)> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_token = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            if in_token {
+                tokens.push((std::mem::take(&mut current), false));
+                in_token = false;
+            }
+            i += 1;
+            continue;
+        }
+
+        if !in_token && (c == '"' || c == '\'') {
+            if let Some((quoted, end)) = quoted_run(&chars, i) {
+                let followed_by_space = chars.get(end).is_none_or(|c| c.is_whitespace());
+                if followed_by_space {
+                    tokens.push((quoted, true));
+                    i = end;
+                    continue;
+                }
+            }
+        }
+
+        in_token = true;
+        current.push(c);
+        i += 1;
+    }
+    if in_token {
+        tokens.push((current, false));
+    }
+
+    tokens
+}
+
+/// Reads the quoted run opening at `start`, returning its unescaped contents and
+/// the index just past the closing quote, or `None` if the quote is never closed.
+fn quoted_run(chars: &[char], start: usize) -> Option<(String, usize)> {
+    let quote = chars[start];
+    let mut value = String::new();
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            c if c == quote => return Some((value, i + 1)),
+            '\\' if quote == '"' && i + 1 < chars.len() => {
+                value.push(chars[i + 1]);
+                i += 2;
+            }
+            c => {
+                value.push(c);
+                i += 1;
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_words() {
+        let args = Args::parse("  add  Pyuul ");
+        assert_eq!(args.positional(), ["add", "Pyuul"]);
+        assert_eq!(args.raw(), "add  Pyuul");
+        assert!(!args.is_empty());
+    }
+
+    #[test]
+    fn test_empty_input() {
+        let args = Args::parse("   ");
+        assert!(args.is_empty());
+        assert_eq!(args.get(0), None);
+    }
+
+    #[test]
+    fn test_quoted_strings() {
+        let args = Args::parse(r#"say "hello there" 'single quoted' "esc\"aped""#);
+        assert_eq!(args.positional(), ["say", "hello there", "single quoted", "esc\"aped"]);
+    }
+
+    #[test]
+    fn test_empty_quotes_is_an_argument() {
+        let args = Args::parse(r#"a "" b"#);
+        assert_eq!(args.positional(), ["a", "", "b"]);
+    }
+
+    #[test]
+    fn test_apostrophes_are_literal() {
+        let args = Args::parse("don't stop 'til you're done");
+        assert_eq!(args.positional(), ["don't", "stop", "'til", "you're", "done"]);
+    }
+
+    #[test]
+    fn test_unterminated_quote_is_literal() {
+        let args = Args::parse(r#"say "oops here"#);
+        assert_eq!(args.positional(), ["say", "\"oops", "here"]);
+    }
+
+    #[test]
+    fn test_quote_glued_to_text_is_literal() {
+        let args = Args::parse(r#""a"b c"#);
+        assert_eq!(args.positional(), ["\"a\"b", "c"]);
+    }
+
+    #[test]
+    fn test_flags() {
+        let args = Args::parse("Pyuul --raw --realm=Nightslayer --Verbose");
+        assert_eq!(args.positional(), ["Pyuul"]);
+        assert!(args.flag("raw"));
+        assert!(args.flag("verbose"));
+        assert!(!args.flag("missing"));
+        assert!(args.flag("realm"));
+        assert_eq!(args.flag_value("realm"), Some("Nightslayer"));
+        assert_eq!(args.flag_value("raw"), None);
+        assert_eq!(args.flag_value("missing"), None);
+    }
+
+    #[test]
+    fn test_quoted_and_terminated_flags_are_positional() {
+        let args = Args::parse(r#""--not-a-flag" -- --also-not"#);
+        assert_eq!(args.positional(), ["--not-a-flag", "--also-not"]);
+        assert!(!args.flag("not-a-flag"));
+    }
+
+    #[test]
+    fn test_typed_extraction() {
+        let args = Args::parse("42 abc");
+        assert_eq!(args.parsed::<u32>(0), Some(Ok(42)));
+        assert!(matches!(args.parsed::<u32>(1), Some(Err(_))));
+        assert_eq!(args.parsed::<u32>(2), None);
+    }
+
+    #[test]
+    fn test_subcommand() {
+        let args = Args::parse("Add Pyuul --raw");
+        let (name, rest) = args.subcommand().unwrap();
+        assert_eq!(name, "add");
+        assert_eq!(rest.positional(), ["Pyuul"]);
+        assert_eq!(rest.raw(), "Pyuul --raw");
+        assert!(rest.flag("raw"));
+
+        let (name, rest) = Args::parse("list").subcommand().unwrap();
+        assert_eq!(name, "list");
+        assert!(rest.is_empty());
+
+        assert!(Args::parse("").subcommand().is_none());
+    }
+
+    #[test]
+    fn test_parse_command() {
+        let (name, args) = parse_command("!AddCharacter Pyuul").unwrap();
+        assert_eq!(name, "addcharacter");
+        assert_eq!(args.positional(), ["Pyuul"]);
+
+        let (name, args) = parse_command("!ping").unwrap();
+        assert_eq!(name, "ping");
+        assert!(args.is_empty());
+
+        assert!(parse_command("hello !ping").is_none());
+        assert!(parse_command("! ping").is_none());
+    }
+}
@@ -0,0 +1,250 @@
+use futures::future::join_all;
+use std::collections::HashMap;
+use tracing::error;
+
+use crate::{db, Handler};
+
+/// Who a step is marked done by when it's read from the character's completed quests.
+const QUEST_LOG: &str = "battle.net";
+
+/// A raid that needs attuning to.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Raid {
+    MoltenCore,
+    Onyxia,
+    Blackwing,
+}
+
+impl Raid {
+    pub const ALL: [Raid; 3] = [Raid::MoltenCore, Raid::Onyxia, Raid::Blackwing];
+
+    /// Short name used in commands and the database.
+    pub fn slug(self) -> &'static str {
+        match self {
+            Raid::MoltenCore => "mc",
+            Raid::Onyxia => "ony",
+            Raid::Blackwing => "bwl",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Raid::MoltenCore => "Molten Core",
+            Raid::Onyxia => "Onyxia's Lair",
+            Raid::Blackwing => "Blackwing Lair",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Raid> {
+        let name = name.to_lowercase();
+        match name.as_str() {
+            "mc" | "molten" | "moltencore" => Some(Raid::MoltenCore),
+            "ony" | "onyxia" | "onyxias" => Some(Raid::Onyxia),
+            "bwl" | "blackwing" | "blackwinglair" => Some(Raid::Blackwing),
+            _ => None,
+        }
+    }
+
+    /// The checklist, in order.
+    pub fn steps(self) -> &'static [&'static str] {
+        match self {
+            Raid::MoltenCore => &[
+                "Reach Lothos Riftwaker in Blackrock Depths",
+                "Loot a Core Fragment at the Molten Core portal",
+                "Turn in Attunement to the Core",
+            ],
+            Raid::Onyxia => &[
+                "Start the chain (Alliance: Dragonkin Menace; Horde: Warlord's Command)",
+                "Kill General Drakkisath in Upper Blackrock Spire",
+                "Turn in Drakefire Amulet (Alliance) or Blood of the Black Dragon Champion (Horde)",
+            ],
+            Raid::Blackwing => &[
+                "Loot Blackhand's Command from the Scarshield Quartermaster",
+                "Kill General Drakkisath in Upper Blackrock Spire",
+                "Touch the Orb of Command behind him",
+            ],
+        }
+    }
+
+    /// Quests whose completion means the attunement is done.
+    fn final_quests(self) -> &'static [u32] {
+        match self {
+            Raid::MoltenCore => &[7848],
+            Raid::Onyxia => &[6502, 6602],
+            Raid::Blackwing => &[7761],
+        }
+    }
+
+    fn raid_names() -> String {
+        let names: Vec<_> = Raid::ALL.iter().map(|r| format!("`{}`", r.slug())).collect();
+        names.join(", ")
+    }
+}
+
+pub fn unknown_raid(name: &str) -> String {
+    format!("Unknown raid `{}`. Try {}.", name, Raid::raid_names())
+}
+
+/// A character's checklist for `raid`, with the steps in `done` (1-based) ticked.
+pub fn checklist(name: &str, raid: Raid, done: &[u32]) -> String {
+    let mut response = format!("**{} attunement — {}**\n", raid.name(), name);
+    for (i, step) in raid.steps().iter().enumerate() {
+        let mark = if done.contains(&(i as u32 + 1)) { "✅" } else { "⬜" };
+        response.push_str(&format!("{} {}. {}\n", mark, i + 1, step));
+    }
+    if done.len() >= raid.steps().len() {
+        response.push_str("Attuned!");
+    } else {
+        response.push_str(&format!(
+            "Tick a step with `!attune {} {} <step>`, or `all` when done.",
+            name,
+            raid.slug()
+        ));
+    }
+    response
+}
+
+/// Who in the roster still needs each raid's attunement, given everyone's
+/// ticked steps per raid.
+pub fn roster_report(names: &[String], progress: &HashMap<(String, Raid), Vec<u32>>, raids: &[Raid]) -> String {
+    let mut response = String::from("**Attunements**\n");
+    for &raid in raids {
+        let total = raid.steps().len();
+        let missing: Vec<String> = names
+            .iter()
+            .filter_map(|name| {
+                let done = progress.get(&(name.to_lowercase(), raid)).map_or(0, Vec::len);
+                (done < total).then(|| format!("{} ({}/{})", name, done, total))
+            })
+            .collect();
+        if missing.is_empty() {
+            response.push_str(&format!("  {}: everyone's attuned ✅\n", raid.name()));
+        } else {
+            response.push_str(&format!("  {}: {}\n", raid.name(), missing.join(", ")));
+        }
+    }
+    response
+}
+
+impl Handler {
+    /// Ticks every step of the raids `name` has finished the final quest for,
+    /// when Battle.net lists the character's completed quests (retail does;
+    /// Classic realms mostly don't, so this quietly does nothing there).
+    async fn sync_attunements(&self, name: &str) {
+        if self.blizzard.is_none() {
+            return;
+        }
+        let Ok(completed) = self.fetch_completed_quests(name).await else {
+            return;
+        };
+        let conn = self.db.lock().await;
+        for raid in Raid::ALL {
+            if !raid.final_quests().iter().any(|q| completed.contains(q)) {
+                continue;
+            }
+            for step in 1..=raid.steps().len() as u32 {
+                if let Err(e) = db::set_attunement_step(&conn, name, raid.slug(), step, Some(QUEST_LOG)) {
+                    error!("Failed to save attunement for {}: {}", name, e);
+                }
+            }
+        }
+    }
+
+    /// The `!attune <name> <raid>` checklist, after picking up anything the
+    /// quest log shows.
+    pub(crate) async fn attunement_checklist(&self, name: &str, raid: Raid) -> String {
+        self.sync_attunements(name).await;
+        let conn = self.db.lock().await;
+        match db::get_attunement_steps(&conn, name, raid.slug()) {
+            Ok(done) => checklist(name, raid, &done),
+            Err(e) => {
+                error!("Failed to load attunements for {}: {}", name, e);
+                "Failed to load attunements.".to_string()
+            }
+        }
+    }
+
+    /// Ticks (or with `done` false, unticks) `steps` of `name`'s checklist and
+    /// returns the updated checklist.
+    pub(crate) async fn update_attunement(&self, name: &str, raid: Raid, steps: &[u32], done: bool, by: &str) -> String {
+        {
+            let conn = self.db.lock().await;
+            for &step in steps {
+                if let Err(e) = db::set_attunement_step(&conn, name, raid.slug(), step, done.then_some(by)) {
+                    error!("Failed to save attunement for {}: {}", name, e);
+                    return "Failed to save attunement.".to_string();
+                }
+            }
+        }
+        self.attunement_checklist(name, raid).await
+    }
+
+    /// `!attunements`: who among the tracked characters still needs `raid`
+    /// (or any raid).
+    pub(crate) async fn attunements_report(&self, raid: Option<Raid>) -> String {
+        let names = {
+            let conn = self.db.lock().await;
+            db::get_tracked_characters(&conn).unwrap_or_default()
+        };
+        if names.is_empty() {
+            return "No characters tracked. Use `!character add <name>` to add one.".to_string();
+        }
+        join_all(names.iter().map(|name| self.sync_attunements(name))).await;
+
+        let raids = match raid {
+            Some(raid) => vec![raid],
+            None => Raid::ALL.to_vec(),
+        };
+        let conn = self.db.lock().await;
+        let mut progress = HashMap::new();
+        for name in &names {
+            for &raid in &raids {
+                let done = db::get_attunement_steps(&conn, name, raid.slug()).unwrap_or_else(|e| {
+                    error!("Failed to load attunements for {}: {}", name, e);
+                    Vec::new()
+                });
+                progress.insert((name.to_lowercase(), raid), done);
+            }
+        }
+        roster_report(&names, &progress, &raids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(Raid::from_name("MC"), Some(Raid::MoltenCore));
+        assert_eq!(Raid::from_name("onyxia"), Some(Raid::Onyxia));
+        assert_eq!(Raid::from_name("naxx"), None);
+        assert_eq!(unknown_raid("naxx"), "Unknown raid `naxx`. Try `mc`, `ony`, `bwl`.");
+    }
+
+    #[test]
+    fn test_checklist() {
+        let text = checklist("Pyuul", Raid::MoltenCore, &[1]);
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines[0], "**Molten Core attunement — Pyuul**");
+        assert!(lines[1].starts_with("✅ 1."));
+        assert!(lines[2].starts_with("⬜ 2."));
+        assert!(lines[4].contains("`!attune Pyuul mc <step>`"));
+        assert!(checklist("Pyuul", Raid::MoltenCore, &[1, 2, 3]).ends_with("Attuned!"));
+    }
+
+    #[test]
+    fn test_roster_report() {
+        let names = vec!["Pyuul".to_string(), "Zara".to_string()];
+        let mut progress = HashMap::new();
+        progress.insert(("zara".to_string(), Raid::MoltenCore), vec![1, 2, 3]);
+        progress.insert(("pyuul".to_string(), Raid::MoltenCore), vec![2]);
+        let report = roster_report(&names, &progress, &[Raid::MoltenCore, Raid::Blackwing]);
+        let lines: Vec<_> = report.lines().collect();
+        assert_eq!(lines[1], "  Molten Core: Pyuul (1/3)");
+        assert_eq!(lines[2], "  Blackwing Lair: Pyuul (0/3), Zara (0/3)");
+
+        progress.insert(("pyuul".to_string(), Raid::MoltenCore), vec![1, 2, 3]);
+        assert!(roster_report(&names, &progress, &[Raid::MoltenCore]).contains("everyone's attuned"));
+    }
+}
@@ -0,0 +1,184 @@
+use serenity::builder::EditAutoModRule;
+use serenity::http::Http;
+use serenity::model::guild::automod::{Action, ActionExecution, Rule, Trigger};
+use serenity::model::id::{GuildId, UserId};
+use tracing::{error, info};
+
+use crate::{db, Handler};
+
+/// Name of the keyword rule `!automod addword` keeps, so the bot only edits
+/// its own rule and leaves the server's others alone.
+pub const RULE_NAME: &str = "Bot word filter";
+/// Discord's limits for a keyword rule.
+const MAX_KEYWORD_CHARS: usize = 60;
+const MAX_KEYWORDS: usize = 1000;
+/// Entries `!automod log` shows.
+pub const LOG_SHOWN: usize = 10;
+
+/// The keywords of the bot's rule, if `rule` is it.
+fn bot_keywords(rule: &Rule) -> Option<&[String]> {
+    match &rule.trigger {
+        Trigger::Keyword { strings, .. } if rule.name == RULE_NAME => Some(strings),
+        _ => None,
+    }
+}
+
+/// `keywords` with `word` added, or why it can't be.
+pub fn add_keyword(keywords: &[String], word: &str) -> Result<Vec<String>, String> {
+    if word.chars().count() > MAX_KEYWORD_CHARS {
+        return Err(format!("AutoMod keywords can be up to {} characters.", MAX_KEYWORD_CHARS));
+    }
+    if keywords.iter().any(|k| k.eq_ignore_ascii_case(word)) {
+        return Err(format!("`{}` is already filtered.", word));
+    }
+    if keywords.len() >= MAX_KEYWORDS {
+        return Err(format!("The filter is full ({} words).", MAX_KEYWORDS));
+    }
+    let mut keywords = keywords.to_vec();
+    keywords.push(word.to_string());
+    Ok(keywords)
+}
+
+/// One line of `!automod list`.
+pub fn describe_rule(rule: &Rule) -> String {
+    let state = if rule.enabled { "" } else { " (disabled)" };
+    let trigger = match &rule.trigger {
+        Trigger::Keyword { strings, regex_patterns, .. } => {
+            let mut words: Vec<String> = strings.iter().map(|s| format!("`{}`", s)).collect();
+            words.extend(regex_patterns.iter().map(|p| format!("`/{}/`", p)));
+            format!("keywords {}", words.join(", "))
+        }
+        Trigger::KeywordPreset { .. } => "Discord's preset word lists".to_string(),
+        Trigger::Spam => "suspected spam".to_string(),
+        Trigger::MentionSpam { mention_total_limit } => format!("more than {} mentions", mention_total_limit),
+        _ => "another trigger".to_string(),
+    };
+    format!("  **{}**{} — {}", rule.name, state, trigger)
+}
+
+/// What an AutoMod execution did, for the moderation log.
+pub fn execution_detail(execution: &ActionExecution) -> String {
+    let action = match &execution.action {
+        Action::BlockMessage { .. } => "blocked a message".to_string(),
+        Action::Alert(channel) => format!("alerted <#{}>", channel),
+        Action::Timeout(duration) => format!("timed out for {}s", duration.as_secs()),
+        _ => "acted".to_string(),
+    };
+    let matched = execution
+        .matched_keyword
+        .as_deref()
+        .map(|k| format!(" (matched `{}`)", k))
+        .unwrap_or_default();
+    let place = execution.channel_id.map(|c| format!(" in <#{}>", c)).unwrap_or_default();
+    format!("AutoMod {}{}{}", action, place, matched)
+}
+
+impl Handler {
+    /// `!automod addword|removeword <word>`: edits the bot's keyword rule,
+    /// creating it on the first word and deleting it with the last.
+    pub(crate) async fn automod_edit_word(&self, http: &Http, guild_id: GuildId, word: &str, add: bool, by: UserId) -> String {
+        let rules = match guild_id.automod_rules(http).await {
+            Ok(rules) => rules,
+            Err(e) => {
+                error!("Failed to fetch AutoMod rules in {}: {:?}", guild_id, e);
+                return "Couldn't read the AutoMod rules; I need the Manage Server permission.".to_string();
+            }
+        };
+        let existing = rules.iter().find_map(|rule| bot_keywords(rule).map(|keywords| (rule.id, keywords)));
+        let keywords = existing.map(|(_, keywords)| keywords).unwrap_or_default();
+        let keywords = if add {
+            match add_keyword(keywords, word) {
+                Ok(keywords) => keywords,
+                Err(e) => return e,
+            }
+        } else {
+            if !keywords.iter().any(|k| k.eq_ignore_ascii_case(word)) {
+                return format!("`{}` isn't filtered.", word);
+            }
+            keywords.iter().filter(|k| !k.eq_ignore_ascii_case(word)).cloned().collect()
+        };
+
+        let trigger = Trigger::Keyword {
+            strings: keywords.clone(),
+            regex_patterns: Vec::new(),
+            allow_list: Vec::new(),
+        };
+        let result = match existing {
+            Some((rule_id, _)) if keywords.is_empty() => guild_id.delete_automod_rule(http, rule_id).await,
+            Some((rule_id, _)) => guild_id
+                .edit_automod_rule(http, rule_id, EditAutoModRule::new().trigger(trigger))
+                .await
+                .map(|_| ()),
+            None => {
+                let rule = EditAutoModRule::new()
+                    .name(RULE_NAME)
+                    .trigger(trigger)
+                    .actions(vec![Action::BlockMessage { custom_message: None }])
+                    .enabled(true);
+                guild_id.create_automod_rule(http, rule).await.map(|_| ())
+            }
+        };
+        if let Err(e) = result {
+            error!("Failed to update AutoMod rule in {}: {:?}", guild_id, e);
+            return "Discord refused the AutoMod change; I need the Manage Server permission.".to_string();
+        }
+
+        let action = if add { "automod_addword" } else { "automod_removeword" };
+        info!("{} {} {:?} in guild {}", by, action, word, guild_id);
+        let conn = self.db.lock().await;
+        if let Err(e) = db::log_moderation(&conn, &guild_id.to_string(), &by.to_string(), action, word) {
+            error!("Failed to log moderation action: {}", e);
+        }
+        if add {
+            format!("AutoMod now blocks messages containing `{}`.", word)
+        } else {
+            format!("AutoMod no longer blocks `{}`.", word)
+        }
+    }
+
+    /// `!automod list`: every AutoMod rule in the server, the bot's or not.
+    pub(crate) async fn automod_rules_report(&self, http: &Http, guild_id: GuildId) -> String {
+        match guild_id.automod_rules(http).await {
+            Ok(rules) if rules.is_empty() => "No AutoMod rules. Add a word with `!automod addword <word>`.".to_string(),
+            Ok(rules) => {
+                let lines: Vec<String> = rules.iter().map(describe_rule).collect();
+                format!("**AutoMod rules**\n{}", lines.join("\n"))
+            }
+            Err(e) => {
+                error!("Failed to fetch AutoMod rules in {}: {:?}", guild_id, e);
+                "Couldn't read the AutoMod rules; I need the Manage Server permission.".to_string()
+            }
+        }
+    }
+
+    /// Records an AutoMod execution in the moderation log.
+    pub(crate) async fn record_automod_execution(&self, execution: &ActionExecution) {
+        // Every bot identity gets the event; one entry is enough
+        if !self.identity.is_primary() {
+            return;
+        }
+        let conn = self.db.lock().await;
+        if let Err(e) = db::log_moderation(
+            &conn,
+            &execution.guild_id.to_string(),
+            &execution.user_id.to_string(),
+            "automod",
+            &execution_detail(execution),
+        ) {
+            error!("Failed to log AutoMod execution: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_keyword() {
+        let keywords = vec!["darn".to_string()];
+        assert_eq!(add_keyword(&keywords, "heck").unwrap(), ["darn", "heck"]);
+        assert_eq!(add_keyword(&keywords, "DARN").unwrap_err(), "`DARN` is already filtered.");
+        assert!(add_keyword(&keywords, &"a".repeat(61)).is_err());
+    }
+}
@@ -0,0 +1,138 @@
+use regex::{Regex, RegexBuilder};
+use serenity::model::id::GuildId;
+use tracing::{error, warn};
+
+use crate::{db, Handler};
+
+/// Replaces each character of a blocked match.
+const MASK_CHAR: char = '█';
+
+/// What to do when an LLM reply contains a blocked word.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Blank out the match.
+    Mask,
+    /// Ask the LLM again, masking whatever is still blocked after the last try.
+    Regenerate,
+}
+
+impl Mode {
+    /// How many times [`Mode::Regenerate`] asks again before falling back to masking.
+    pub const MAX_REGENERATIONS: usize = 2;
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Mask => "mask",
+            Mode::Regenerate => "regenerate",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Mode> {
+        match name.to_lowercase().as_str() {
+            "mask" => Some(Mode::Mask),
+            "regenerate" => Some(Mode::Regenerate),
+            _ => None,
+        }
+    }
+}
+
+/// Compiles a blocklist entry: `/regex/`, or a word or phrase matched as whole
+/// words. Both ignore case.
+pub fn compile_entry(entry: &str) -> Result<Regex, String> {
+    let pattern = match entry.strip_prefix('/').and_then(|e| e.strip_suffix('/')) {
+        Some(regex) if !regex.is_empty() => regex.to_string(),
+        _ => format!(r"\b{}\b", regex::escape(entry)),
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(true)
+        .size_limit(1 << 20)
+        .build()
+        .map_err(|e| format!("Invalid pattern: {}", e))
+}
+
+/// A guild's blocked words and what to do about them.
+pub struct Blocklist {
+    patterns: Vec<Regex>,
+    pub mode: Mode,
+}
+
+impl Blocklist {
+    /// Compiles `entries`, skipping (and logging) any that no longer compile.
+    pub fn new(entries: &[String], mode: Mode) -> Blocklist {
+        let patterns = entries
+            .iter()
+            .filter_map(|entry| match compile_entry(entry) {
+                Ok(regex) => Some(regex),
+                Err(e) => {
+                    warn!("Skipping blocklist entry {:?}: {}", entry, e);
+                    None
+                }
+            })
+            .collect();
+        Blocklist { patterns, mode }
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.patterns.iter().any(|p| p.is_match(text))
+    }
+
+    pub fn mask(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for pattern in &self.patterns {
+            text = pattern
+                .replace_all(&text, |caps: &regex::Captures| {
+                    caps[0].chars().map(|_| MASK_CHAR).collect::<String>()
+                })
+                .into_owned();
+        }
+        text
+    }
+}
+
+impl Handler {
+    /// The blocklist for `guild_id`; DMs have none.
+    pub(crate) async fn blocklist(&self, guild_id: Option<GuildId>) -> Blocklist {
+        let Some(guild_id) = guild_id else {
+            return Blocklist::new(&[], Mode::Mask);
+        };
+        let conn = self.db.lock().await;
+        let guild_id = guild_id.to_string();
+        let entries = db::get_blocklist(&conn, &guild_id).unwrap_or_else(|e| {
+            error!("Failed to load blocklist: {}", e);
+            Vec::new()
+        });
+        let mode = db::get_blocklist_mode(&conn, &guild_id)
+            .ok()
+            .flatten()
+            .and_then(|m| Mode::from_name(&m))
+            .unwrap_or(Mode::Mask);
+        Blocklist::new(&entries, mode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocklist(entries: &[&str]) -> Blocklist {
+        let entries: Vec<String> = entries.iter().map(|e| e.to_string()).collect();
+        Blocklist::new(&entries, Mode::Mask)
+    }
+
+    #[test]
+    fn test_words_match_whole_words() {
+        let list = blocklist(&["darn", "heck off"]);
+        assert!(list.is_match("Well DARN it"));
+        assert!(!list.is_match("darned"));
+        assert_eq!(list.mask("darn, just heck off"), "████, just ████████");
+    }
+
+    #[test]
+    fn test_regex_entries() {
+        let list = blocklist(&["/fr[ie]+nd/", "/(/"]);
+        assert!(list.is_match("hello FRIEND"));
+        assert_eq!(list.mask("frend"), "█████");
+        assert!(compile_entry("/(/").is_err());
+        assert!(!blocklist(&[]).is_match("anything"));
+    }
+}
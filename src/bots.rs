@@ -0,0 +1,177 @@
+use rusqlite::Connection;
+use serenity::http::Http;
+use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+use serenity::model::user::User;
+use tracing::error;
+
+use crate::{db, Handler};
+
+/// Which bot account a [`Handler`] speaks for. The primary bot (`DISCORD_TOKEN`)
+/// runs every command; extra bots configured through `EXTRA_BOTS` only chat, each
+/// with its own system prompt, history and channel bindings, while sharing the
+/// database, llama.cpp and Battle.net clients with the primary.
+#[derive(Clone)]
+pub struct Identity {
+    /// `None` for the primary bot.
+    pub name: Option<String>,
+    /// Overrides the `system_prompt` config for this bot.
+    pub system_prompt: Option<String>,
+    /// Channels this bot answers in; empty means everywhere.
+    pub channels: Vec<ChannelId>,
+}
+
+impl Identity {
+    pub fn primary() -> Identity {
+        Identity {
+            name: None,
+            system_prompt: None,
+            channels: Vec::new(),
+        }
+    }
+
+    pub fn is_primary(&self) -> bool {
+        self.name.is_none()
+    }
+
+    pub fn serves(&self, channel: ChannelId) -> bool {
+        self.channels.is_empty() || self.channels.contains(&channel)
+    }
+}
+
+/// Where a chat message was sent: which history it belongs to, and whose
+/// channel and server settings shape the reply.
+pub struct Conversation {
+    pub guild_id: Option<GuildId>,
+    pub channel_id: ChannelId,
+    /// Conversation history key, from [`Handler::history_key`].
+    pub key: String,
+    /// The Discord message being answered, so a redelivered one isn't stored twice.
+    pub message_id: Option<MessageId>,
+    /// Who is asking, so only they can redo or delete the reply.
+    pub user_id: Option<UserId>,
+    /// Display name of whoever is talking, so shared histories say who said what.
+    pub speaker: Option<String>,
+    /// Extra instructions appended to the system prompt for this conversation.
+    pub notes: Vec<String>,
+}
+
+/// What to call someone in prompts: their server nickname, else their display name.
+pub fn speaker_name(user: &User, nick: Option<&str>) -> String {
+    nick.unwrap_or_else(|| user.display_name()).to_string()
+}
+
+/// Whether `user` has Manage Server in `guild_id`, which makes them an
+/// officer for commands like `!protect`.
+pub async fn can_manage_guild(http: &Http, guild_id: GuildId, user: UserId) -> bool {
+    let (guild, member) = tokio::join!(guild_id.to_partial_guild(http), guild_id.member(http, user));
+    match (guild, member) {
+        (Ok(guild), Ok(member)) => guild.member_permissions(&member).manage_guild(),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("Failed to check permissions in {}: {}", guild_id, e);
+            false
+        }
+    }
+}
+
+/// Whether `user` owns the bot's Discord application, or its team.
+pub async fn is_owner(http: &Http, user: UserId) -> bool {
+    match http.get_current_application_info().await {
+        Ok(info) => {
+            info.owner.is_some_and(|owner| owner.id == user)
+                || info.team.is_some_and(|team| team.owner_user_id == user)
+        }
+        Err(e) => {
+            error!("Failed to fetch application info: {}", e);
+            false
+        }
+    }
+}
+
+impl Handler {
+    /// A handler for another bot account that shares this one's clients and database.
+    pub(crate) fn for_identity(&self, identity: Identity) -> Handler {
+        Handler {
+            llm: self.llm.clone(),
+            blizzard: self.blizzard.clone(),
+            steam: self.steam.clone(),
+            riot: self.riot.clone(),
+            wow_region: self.wow_region,
+            wow_version: self.wow_version,
+            db: self.db.clone(),
+            events: self.events.clone(),
+            identity,
+            modules: self.modules.clone(),
+            middleware: self.middleware.clone(),
+            topics: self.topics.clone(),
+        }
+    }
+
+    /// Conversation history key for a message, kept apart per bot so two bots in
+    /// one channel don't read each other's conversations as their own.
+    pub(crate) fn history_key(&self, conn: &Connection, channel_id: &str, user_id: &str) -> String {
+        let key = db::context_key(conn, channel_id, user_id);
+        match &self.identity.name {
+            Some(name) => format!("{}@{}", name, key),
+            None => key,
+        }
+    }
+
+    pub(crate) fn conversation(
+        &self,
+        conn: &Connection,
+        guild_id: Option<GuildId>,
+        channel_id: ChannelId,
+        user: &User,
+        nick: Option<&str>,
+    ) -> Conversation {
+        Conversation {
+            guild_id,
+            channel_id,
+            key: self.history_key(conn, &channel_id.to_string(), &user.id.to_string()),
+            message_id: None,
+            user_id: Some(user.id),
+            speaker: Some(speaker_name(user, nick)),
+            notes: Vec::new(),
+        }
+    }
+
+    pub(crate) fn system_prompt(&self, conn: &Connection) -> rusqlite::Result<String> {
+        if let Some(prompt) = &self.identity.system_prompt {
+            return Ok(prompt.clone());
+        }
+        Ok(db::get_config(conn, None, "system_prompt")?.unwrap_or_default())
+    }
+
+    /// The system prompt in `guild_id`: the persona picked for it if there is
+    /// one, else its own prompt, else [`Handler::system_prompt`].
+    pub(crate) fn guild_system_prompt(&self, conn: &Connection, guild_id: Option<GuildId>) -> rusqlite::Result<String> {
+        let Some(guild_id) = guild_id.filter(|_| self.identity.system_prompt.is_none()) else {
+            return self.system_prompt(conn);
+        };
+        let guild = guild_id.to_string();
+        let name = db::get_guild_config(conn, &guild, "persona")?;
+        if let Some(persona) = name.map(|n| db::get_persona(conn, &n)).transpose()?.flatten() {
+            return Ok(persona.system_prompt);
+        }
+        Ok(db::get_guild_config(conn, &guild, "system_prompt")?.unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_bindings() {
+        assert!(Identity::primary().serves(ChannelId::new(1)));
+
+        let bound = Identity {
+            name: Some("helpful".to_string()),
+            system_prompt: None,
+            channels: vec![ChannelId::new(1)],
+        };
+        assert!(!bound.is_primary());
+        assert!(bound.serves(ChannelId::new(1)));
+        assert!(!bound.serves(ChannelId::new(2)));
+    }
+}
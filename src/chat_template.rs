@@ -0,0 +1,171 @@
+use crate::clients::ChatMessage;
+
+/// Stop tokens used when the model's template is unknown: the end-of-turn
+/// markers of the common formats.
+pub const FALLBACK_STOP: [&str; 4] = ["<|im_end|>", "<|im_start|>", "</s>", "[INST]"];
+
+/// A prompt format models are trained on. llama.cpp applies the model's own
+/// template server-side; knowing which one it is picks the stop tokens, and
+/// `LLAMA_CHAT_TEMPLATE` can force one for models whose GGUF metadata is wrong.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChatTemplate {
+    ChatMl,
+    Llama2,
+    Llama3,
+    Mistral,
+    Gemma,
+}
+
+impl ChatTemplate {
+    pub const ALL: [ChatTemplate; 5] = [
+        ChatTemplate::ChatMl,
+        ChatTemplate::Llama2,
+        ChatTemplate::Llama3,
+        ChatTemplate::Mistral,
+        ChatTemplate::Gemma,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ChatTemplate::ChatMl => "chatml",
+            ChatTemplate::Llama2 => "llama2",
+            ChatTemplate::Llama3 => "llama3",
+            ChatTemplate::Mistral => "mistral",
+            ChatTemplate::Gemma => "gemma",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ChatTemplate> {
+        ChatTemplate::ALL
+            .into_iter()
+            .find(|t| t.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Recognizes the Jinja chat template llama.cpp reports in `/props` by the
+    /// special tokens it writes.
+    pub fn detect(jinja: &str) -> Option<ChatTemplate> {
+        if jinja.contains("<|im_start|>") {
+            Some(ChatTemplate::ChatMl)
+        } else if jinja.contains("<|start_header_id|>") {
+            Some(ChatTemplate::Llama3)
+        } else if jinja.contains("<start_of_turn>") {
+            Some(ChatTemplate::Gemma)
+        } else if jinja.contains("<<SYS>>") {
+            Some(ChatTemplate::Llama2)
+        } else if jinja.contains("[INST]") {
+            Some(ChatTemplate::Mistral)
+        } else {
+            None
+        }
+    }
+
+    /// Tokens that end the model's turn, so generation stops there.
+    pub fn stop(self) -> &'static [&'static str] {
+        match self {
+            ChatTemplate::ChatMl => &["<|im_end|>", "<|im_start|>"],
+            ChatTemplate::Llama2 | ChatTemplate::Mistral => &["</s>", "[INST]"],
+            ChatTemplate::Llama3 => &["<|eot_id|>", "<|start_header_id|>"],
+            ChatTemplate::Gemma => &["<end_of_turn>", "<start_of_turn>"],
+        }
+    }
+
+    /// `messages` as a raw prompt in this format, ending where the assistant's
+    /// reply starts. Used with a forced template, where the server's own can't
+    /// be trusted.
+    pub fn render(self, messages: &[ChatMessage]) -> String {
+        let mut prompt = String::new();
+        match self {
+            ChatTemplate::ChatMl => {
+                for m in messages {
+                    prompt.push_str(&format!("<|im_start|>{}\n{}<|im_end|>\n", m.role, m.content));
+                }
+                prompt.push_str("<|im_start|>assistant\n");
+            }
+            ChatTemplate::Llama3 => {
+                prompt.push_str("<|begin_of_text|>");
+                for m in messages {
+                    prompt.push_str(&format!("<|start_header_id|>{}<|end_header_id|>\n\n{}<|eot_id|>", m.role, m.content));
+                }
+                prompt.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
+            }
+            ChatTemplate::Gemma => {
+                // Gemma has no system role; the system prompt leads the first user turn
+                let mut system = String::new();
+                for m in messages {
+                    match m.role.as_str() {
+                        "system" => system = format!("{}\n\n", m.content),
+                        "assistant" => prompt.push_str(&format!("<start_of_turn>model\n{}<end_of_turn>\n", m.content)),
+                        _ => prompt.push_str(&format!(
+                            "<start_of_turn>user\n{}{}<end_of_turn>\n",
+                            std::mem::take(&mut system),
+                            m.content
+                        )),
+                    }
+                }
+                prompt.push_str("<start_of_turn>model\n");
+            }
+            ChatTemplate::Llama2 | ChatTemplate::Mistral => {
+                let mut system = String::new();
+                for m in messages {
+                    match m.role.as_str() {
+                        "system" if self == ChatTemplate::Llama2 => system = format!("<<SYS>>\n{}\n<</SYS>>\n\n", m.content),
+                        "system" => system = format!("{}\n\n", m.content),
+                        "assistant" => prompt.push_str(&format!(" {}</s>", m.content)),
+                        _ => prompt.push_str(&format!("<s>[INST] {}{} [/INST]", std::mem::take(&mut system), m.content)),
+                    }
+                }
+            }
+        }
+        prompt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let chatml = "{% for message in messages %}{{'<|im_start|>' + message['role'] + '\n'}}{% endfor %}";
+        assert_eq!(ChatTemplate::detect(chatml), Some(ChatTemplate::ChatMl));
+        let llama3 = "{{ '<|start_header_id|>' + message['role'] + '<|end_header_id|>' }}";
+        assert_eq!(ChatTemplate::detect(llama3), Some(ChatTemplate::Llama3));
+        let llama2 = "{{ '<<SYS>>\\n' + system + '\\n<</SYS>>' }}{{ '[INST] ' + content }}";
+        assert_eq!(ChatTemplate::detect(llama2), Some(ChatTemplate::Llama2));
+        assert_eq!(ChatTemplate::detect("{{ '[INST] ' + content + ' [/INST]' }}"), Some(ChatTemplate::Mistral));
+        assert_eq!(ChatTemplate::detect("{{ '<start_of_turn>user' }}"), Some(ChatTemplate::Gemma));
+        assert_eq!(ChatTemplate::detect("{{ content }}"), None);
+    }
+
+    #[test]
+    fn test_names() {
+        for template in ChatTemplate::ALL {
+            assert_eq!(ChatTemplate::from_name(template.name()), Some(template));
+        }
+        assert_eq!(ChatTemplate::from_name("ChatML"), Some(ChatTemplate::ChatMl));
+        assert_eq!(ChatTemplate::from_name("alpaca"), None);
+    }
+
+    #[test]
+    fn test_render() {
+        let messages = [
+            ChatMessage::new("system", "Be rude."),
+            ChatMessage::new("user", "hi"),
+            ChatMessage::new("assistant", "go away"),
+            ChatMessage::new("user", "please"),
+        ];
+        assert_eq!(
+            ChatTemplate::ChatMl.render(&messages),
+            "<|im_start|>system\nBe rude.<|im_end|>\n<|im_start|>user\nhi<|im_end|>\n\
+             <|im_start|>assistant\ngo away<|im_end|>\n<|im_start|>user\nplease<|im_end|>\n\
+             <|im_start|>assistant\n"
+        );
+        assert_eq!(
+            ChatTemplate::Mistral.render(&messages),
+            "<s>[INST] Be rude.\n\nhi [/INST] go away</s><s>[INST] please [/INST]"
+        );
+        assert!(ChatTemplate::Llama2.render(&messages).starts_with("<s>[INST] <<SYS>>\nBe rude.\n<</SYS>>\n\nhi [/INST]"));
+        assert!(ChatTemplate::Gemma.render(&messages).starts_with("<start_of_turn>user\nBe rude.\n\nhi<end_of_turn>\n"));
+        assert!(ChatTemplate::Llama3.render(&messages).ends_with("<|start_header_id|>assistant<|end_header_id|>\n\n"));
+    }
+}
@@ -0,0 +1,254 @@
+use futures::future::join_all;
+use serenity::builder::{
+    CreateActionRow, CreateButton, CreateEmbed, CreateEmbedFooter, CreateInteractionResponse,
+    CreateInteractionResponseMessage,
+};
+use serenity::model::application::{ButtonStyle, ComponentInteraction};
+use serenity::prelude::Context;
+use tracing::{error, info};
+
+use crate::{db, Handler};
+
+/// Prefix for the `custom_id` of a checklist button; the character, class and
+/// item number follow, so a click can redraw the list without asking Battle.net.
+pub const CUSTOM_ID_PREFIX: &str = "checklist:";
+
+/// Template class whose items every character gets.
+pub const ALL_CLASSES: &str = "all";
+
+/// Items per checklist at most: one button each, five rows of five.
+pub const MAX_ITEMS: usize = 25;
+
+/// The checklist used until someone sets up a template.
+const DEFAULT_ITEMS: [&str; 5] = [
+    "Flasks",
+    "Elixirs and potions",
+    "Food buffs",
+    "Fire resistance gear",
+    "Pre-raid BiS",
+];
+
+/// A character's checklist: the shared items, then their class's.
+pub fn items(shared: Vec<String>, class: Vec<String>) -> Vec<String> {
+    let mut items: Vec<String> = shared.into_iter().chain(class).collect();
+    if items.is_empty() {
+        items = DEFAULT_ITEMS.iter().map(|i| i.to_string()).collect();
+    }
+    items.truncate(MAX_ITEMS);
+    items
+}
+
+pub fn custom_id(name: &str, class: &str, index: usize) -> String {
+    format!("{}{}:{}:{}", CUSTOM_ID_PREFIX, name, class, index)
+}
+
+/// The character, class and item index from a checklist button's `custom_id`.
+pub fn from_custom_id(custom_id: &str) -> Option<(String, String, usize)> {
+    let mut parts = custom_id.strip_prefix(CUSTOM_ID_PREFIX)?.splitn(3, ':');
+    let name = parts.next()?.to_string();
+    let class = parts.next()?.to_string();
+    let index = parts.next()?.parse().ok()?;
+    Some((name, class, index))
+}
+
+/// The checklist embed with one button per item, green when ticked.
+pub fn view(name: &str, class: &str, items: &[String], ticked: &[String]) -> (CreateEmbed, Vec<CreateActionRow>) {
+    let is_ticked = |item: &String| ticked.iter().any(|t| t.eq_ignore_ascii_case(item));
+    let description: Vec<String> = items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| format!("{} {}. {}", if is_ticked(item) { "✅" } else { "⬜" }, i + 1, item))
+        .collect();
+    let done = items.iter().filter(|i| is_ticked(i)).count();
+    let title = match class {
+        "" => format!("Raid checklist — {}", name),
+        class => format!("Raid checklist — {} ({})", name, class),
+    };
+    let embed = CreateEmbed::new()
+        .title(title)
+        .description(description.join("\n"))
+        .footer(CreateEmbedFooter::new(format!(
+            "{}/{} ready · the character's owner can tick items below",
+            done,
+            items.len()
+        )));
+
+    let buttons: Vec<CreateButton> = items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            CreateButton::new(custom_id(name, class, i))
+                .label((i + 1).to_string())
+                .style(if is_ticked(item) { ButtonStyle::Success } else { ButtonStyle::Secondary })
+        })
+        .collect();
+    let rows = buttons.chunks(5).map(|row| CreateActionRow::Buttons(row.to_vec())).collect();
+    (embed, rows)
+}
+
+/// One line per character of the officer summary, readiest first.
+pub fn summary(mut readiness: Vec<(String, usize, usize)>) -> String {
+    readiness.sort_by_key(|(_, done, total)| std::cmp::Reverse(done * 100 / (*total).max(1)));
+    let ready = readiness.iter().filter(|(_, done, total)| done >= total).count();
+    let mut response = format!("**Raid readiness** — {}/{} ready\n", ready, readiness.len());
+    for (name, done, total) in &readiness {
+        let mark = if done >= total { "✅" } else { "⬜" };
+        response.push_str(&format!("  {} {} — {}/{}\n", mark, name, done, total));
+    }
+    response
+}
+
+impl Handler {
+    /// The character's class from Battle.net, or `""` when it can't be had.
+    async fn character_class(&self, name: &str) -> String {
+        if self.blizzard.is_none() {
+            return String::new();
+        }
+        self.fetch_wow_character(name)
+            .await
+            .map(|c| c.character_class.name)
+            .unwrap_or_default()
+    }
+
+    async fn checklist_items(&self, class: &str) -> Vec<String> {
+        let conn = self.db.lock().await;
+        let load = |class: &str| {
+            db::get_checklist_items(&conn, class).unwrap_or_else(|e| {
+                error!("Failed to load {} checklist: {}", class, e);
+                Vec::new()
+            })
+        };
+        let own = if class.is_empty() { Vec::new() } else { load(class) };
+        items(load(ALL_CLASSES), own)
+    }
+
+    /// `!checklist <name>`: the character's checklist with buttons to tick it.
+    pub(crate) async fn checklist_view(&self, name: &str) -> Result<(CreateEmbed, Vec<CreateActionRow>), String> {
+        let character = {
+            let conn = self.db.lock().await;
+            db::get_tracked_character(&conn, name).ok().flatten()
+        };
+        let Some(character) = character else {
+            return Err(format!("**{}** is not being tracked.", name));
+        };
+        let class = self.character_class(&character.name).await;
+        let items = self.checklist_items(&class).await;
+        let ticked = {
+            let conn = self.db.lock().await;
+            db::get_checklist_ticks(&conn, &character.name).unwrap_or_default()
+        };
+        Ok(view(&character.name, &class, &items, &ticked))
+    }
+
+    /// `!checklist summary`: how many checklist items each tracked character has done.
+    pub(crate) async fn checklist_summary(&self) -> String {
+        let names = {
+            let conn = self.db.lock().await;
+            db::get_tracked_characters(&conn).unwrap_or_default()
+        };
+        if names.is_empty() {
+            return "No characters tracked. Use `!character add <name>` to add one.".to_string();
+        }
+        let classes = join_all(names.iter().map(|name| self.character_class(name))).await;
+        let mut readiness = Vec::new();
+        for (name, class) in names.into_iter().zip(classes) {
+            let items = self.checklist_items(&class).await;
+            let ticked = {
+                let conn = self.db.lock().await;
+                db::get_checklist_ticks(&conn, &name).unwrap_or_default()
+            };
+            let done = items.iter().filter(|i| ticked.iter().any(|t| t.eq_ignore_ascii_case(i))).count();
+            readiness.push((name, done, items.len()));
+        }
+        summary(readiness)
+    }
+
+    /// A checklist button: ticks or unticks the item if the clicker added the
+    /// character, and redraws the list.
+    pub(crate) async fn handle_checklist_button(
+        &self,
+        ctx: &Context,
+        component: &ComponentInteraction,
+        name: &str,
+        class: &str,
+        index: usize,
+    ) {
+        let owner = {
+            let conn = self.db.lock().await;
+            db::get_tracked_character(&conn, name).ok().flatten().map(|c| c.added_by)
+        };
+        if owner.as_deref() != Some(component.user.id.to_string().as_str()) {
+            let content = match owner {
+                Some(owner) => format!("Only <@{}>, who added {}, can tick off their checklist.", owner, name),
+                None => format!("**{}** is no longer tracked.", name),
+            };
+            let response = CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new().content(content).ephemeral(true),
+            );
+            if let Err(why) = component.create_response(&ctx.http, response).await {
+                error!("Error responding to interaction: {:?}", why);
+            }
+            return;
+        }
+
+        let items = self.checklist_items(class).await;
+        let ticked = {
+            let conn = self.db.lock().await;
+            if let Some(item) = items.get(index) {
+                let ticked = db::get_checklist_ticks(&conn, name).unwrap_or_default();
+                let done = !ticked.iter().any(|t| t.eq_ignore_ascii_case(item));
+                match db::set_checklist_tick(&conn, name, item, done) {
+                    Ok(()) => info!("{} {} {:?} for {}", component.user.name, if done { "ticked" } else { "unticked" }, item, name),
+                    Err(e) => error!("Failed to save checklist for {}: {}", name, e),
+                }
+            }
+            db::get_checklist_ticks(&conn, name).unwrap_or_default()
+        };
+        let (embed, components) = view(name, class, &items, &ticked);
+        let response = CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::new().embed(embed).components(components),
+        );
+        if let Err(why) = component.create_response(&ctx.http, response).await {
+            error!("Error responding to interaction: {:?}", why);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_items() {
+        assert_eq!(items(vec![], vec![]).len(), DEFAULT_ITEMS.len());
+        let list = items(vec!["Flasks".to_string()], vec!["Rogue poisons".to_string()]);
+        assert_eq!(list, ["Flasks", "Rogue poisons"]);
+        assert_eq!(items((0..30).map(|i| i.to_string()).collect(), vec![]).len(), MAX_ITEMS);
+    }
+
+    #[test]
+    fn test_custom_id() {
+        let id = custom_id("Pyuul", "Death Knight", 3);
+        assert_eq!(from_custom_id(&id), Some(("Pyuul".to_string(), "Death Knight".to_string(), 3)));
+        assert_eq!(from_custom_id("checklist:Pyuul::0"), Some(("Pyuul".to_string(), String::new(), 0)));
+        assert_eq!(from_custom_id("onboard:persona:1"), None);
+    }
+
+    #[test]
+    fn test_view() {
+        let items = vec!["Flasks".to_string(), "Food buffs".to_string()];
+        let (embed, rows) = view("Pyuul", "Druid", &items, &["flasks".to_string()]);
+        let embed = serde_json::to_value(embed).unwrap();
+        assert_eq!(embed["title"], "Raid checklist — Pyuul (Druid)");
+        assert_eq!(embed["description"], "✅ 1. Flasks\n⬜ 2. Food buffs");
+        assert!(embed["footer"]["text"].as_str().unwrap().starts_with("1/2 ready"));
+        assert_eq!(rows.len(), 1);
+    }
+
+    #[test]
+    fn test_summary() {
+        let report = summary(vec![("Pyuul".to_string(), 1, 4), ("Zara".to_string(), 4, 4)]);
+        let lines: Vec<_> = report.lines().collect();
+        assert_eq!(lines, ["**Raid readiness** — 1/2 ready", "  ✅ Zara — 4/4", "  ⬜ Pyuul — 1/4"]);
+    }
+}
@@ -0,0 +1,168 @@
+use clap::{Parser, Subcommand};
+use rusqlite::Connection;
+use serde_json::json;
+use std::fs;
+
+use crate::scheduler::unix_now;
+use crate::{db, encryption, maintenance};
+
+#[derive(Parser)]
+#[command(version, about = "Discord bot with llama.cpp chat and WoW character tracking")]
+pub struct Cli {
+    /// SQLite database file
+    #[arg(long, env = "DATABASE_PATH", default_value = "./discord-bot.db", global = true)]
+    pub database: String,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Connect to Discord and run the bot (the default)
+    Run {
+        /// Refuse to start if a configured integration fails its startup check
+        #[arg(long)]
+        strict: bool,
+        /// Answer chat from a built-in echo server instead of llama.cpp, for local development
+        #[arg(long)]
+        mock_llm: bool,
+        /// Process and log everything but send nothing to Discord, for trying changes on live traffic
+        #[arg(long)]
+        read_only: bool,
+    },
+    /// Create or upgrade the database schema, then exit
+    Migrate,
+    /// Run the nightly database maintenance now: prune, vacuum and optimize
+    Maintain,
+    /// Print a conversation's stored history as JSON
+    ExportHistory {
+        /// Context key: a channel ID, or `channel:user` in per-user mode
+        context: String,
+        /// Most recent messages to include
+        #[arg(long, default_value_t = 1000)]
+        limit: usize,
+    },
+    /// Manage the system prompt
+    Prompt {
+        #[command(subcommand)]
+        command: PromptCommand,
+    },
+    /// Inspect tracked WoW characters
+    Character {
+        #[command(subcommand)]
+        command: CharacterCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PromptCommand {
+    /// Replace the system prompt with the contents of a file
+    Set { file: String },
+    /// Print the current system prompt
+    Show,
+}
+
+#[derive(Subcommand)]
+pub enum CharacterCommand {
+    /// List tracked characters with their last known level
+    List,
+}
+
+fn open(path: &str) -> Result<Connection, String> {
+    let conn = Connection::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    db::init(&conn).map_err(|e| format!("Failed to initialize database schema: {}", e))?;
+    encryption::enable(&conn)?;
+    Ok(conn)
+}
+
+/// Runs an offline subcommand against the database, printing results to stdout.
+/// `Run` is handled by the caller since it needs the gateway.
+pub fn run(database: &str, command: Command) -> Result<(), String> {
+    let conn = open(database)?;
+    match command {
+        Command::Run { .. } => unreachable!("`run` starts the bot"),
+        Command::Migrate => println!("Database at {} is up to date.", database),
+        Command::Maintain => {
+            let report = maintenance::run(&conn, unix_now()).map_err(|e| format!("Maintenance failed: {}", e))?;
+            println!("{}.", report.summary());
+        }
+        Command::ExportHistory { context, limit } => {
+            let messages: Vec<_> = db::get_recent_messages(&conn, &context, limit)
+                .map_err(|e| format!("Failed to read history: {}", e))?
+                .into_iter()
+                .map(|m| json!({ "role": m.role, "content": m.content }))
+                .collect();
+            let output = json!({ "context": context, "messages": messages });
+            println!("{}", serde_json::to_string_pretty(&output).unwrap_or_default());
+        }
+        Command::Prompt { command: PromptCommand::Set { file } } => {
+            let prompt = fs::read_to_string(&file).map_err(|e| format!("Failed to read {}: {}", file, e))?;
+            let prompt = prompt.trim();
+            if prompt.is_empty() {
+                return Err(format!("{} is empty", file));
+            }
+            db::set_config(&conn, None, "system_prompt", prompt)
+                .map_err(|e| format!("Failed to save system prompt: {}", e))?;
+            println!("System prompt updated ({} characters).", prompt.chars().count());
+        }
+        Command::Prompt { command: PromptCommand::Show } => {
+            let prompt = db::get_config(&conn, None, "system_prompt")
+                .map_err(|e| format!("Failed to read system prompt: {}", e))?
+                .unwrap_or_default();
+            println!("{}", prompt);
+        }
+        Command::Character { command: CharacterCommand::List } => {
+            let characters = db::get_tracked_character_details(&conn)
+                .map_err(|e| format!("Failed to read characters: {}", e))?;
+            if characters.is_empty() {
+                println!("No characters tracked.");
+            }
+            for c in characters {
+                let level = db::latest_snapshot(&conn, &c.name)
+                    .ok()
+                    .flatten()
+                    .map(|s| s.level.to_string())
+                    .unwrap_or_else(|| "?".to_string());
+                println!("{}\tlevel {}\tadded by {}", c.name, level, c.added_by);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse_subcommands() {
+        let cli = Cli::parse_from(["discord-bot"]);
+        assert!(cli.command.is_none());
+
+        let cli = Cli::parse_from(["discord-bot", "--database", "x.db", "prompt", "set", "prompt.txt"]);
+        assert_eq!(cli.database, "x.db");
+        assert!(matches!(
+            cli.command,
+            Some(Command::Prompt { command: PromptCommand::Set { file } }) if file == "prompt.txt"
+        ));
+
+        let cli = Cli::parse_from(["discord-bot", "run", "--mock-llm"]);
+        assert!(matches!(cli.command, Some(Command::Run { strict: false, mock_llm: true, read_only: false })));
+
+        let cli = Cli::parse_from(["discord-bot", "run", "--read-only"]);
+        assert!(matches!(cli.command, Some(Command::Run { read_only: true, .. })));
+
+        let cli = Cli::parse_from(["discord-bot", "export-history", "123", "--limit", "5"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::ExportHistory { context, limit: 5 }) if context == "123"
+        ));
+    }
+}
@@ -0,0 +1,789 @@
+use reqwest::Client as HttpClient;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serenity::async_trait;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, MutexGuard, Semaphore};
+
+use crate::chat_template::{ChatTemplate, FALLBACK_STOP};
+use crate::trace;
+use crate::riot::Platform;
+use crate::wow::Region;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn new(role: &str, content: impl Into<String>) -> ChatMessage {
+        ChatMessage {
+            role: role.to_string(),
+            content: content.into(),
+        }
+    }
+}
+
+/// A chat-completions backend.
+#[async_trait]
+pub trait LlmClient: Send + Sync {
+    /// The model's reply to `messages`.
+    async fn complete(&self, messages: Vec<ChatMessage>) -> Result<String, String>;
+
+    /// Checks the backend is reachable, describing it on success.
+    async fn probe(&self, timeout: Duration) -> Result<String, String>;
+
+    /// An embedding vector for `text`, for comparing meaning.
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, String>;
+}
+
+/// Battle.net API access. Implementations handle OAuth themselves.
+#[async_trait]
+pub trait BlizzardClient: Send + Sync {
+    /// An OAuth access token, refreshed when it has expired.
+    async fn token(&self) -> Result<String, String>;
+
+    /// GETs an API path such as `/profile/wow/character/...?namespace=...` as JSON.
+    /// Returns `None` when the API says 404.
+    async fn get_json(&self, path: &str) -> Result<Option<Value>, String>;
+
+    /// Downloads a file, e.g. a media asset URL returned by the API.
+    async fn download(&self, url: &str) -> Result<Vec<u8>, String>;
+}
+
+/// Steam Web API access, for what players who linked their Steam ID are playing.
+#[async_trait]
+pub trait SteamClient: Send + Sync {
+    /// Profiles for 64-bit Steam IDs (at most 100); unknown IDs are left out.
+    async fn player_summaries(&self, steam_ids: &[String]) -> Result<Vec<SteamPlayer>, String>;
+
+    /// The Steam ID behind a custom profile URL name, if there is one.
+    async fn resolve_vanity_url(&self, name: &str) -> Result<Option<String>, String>;
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SteamPlayer {
+    pub steamid: String,
+    pub personaname: String,
+    /// The game being played; missing when not in a game or the profile is private.
+    pub gameextrainfo: Option<String>,
+}
+
+/// Which Riot API host a request goes to: account and match data are on the
+/// regional host (`americas`), ranked data on the platform's (`na1`).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RiotRoute {
+    Regional,
+    Platform,
+}
+
+/// Riot Games API access.
+#[async_trait]
+pub trait RiotClient: Send + Sync {
+    /// GETs an API path such as `/lol/league/v4/entries/by-puuid/...` as JSON.
+    /// Returns `None` when the API says 404.
+    async fn get_json(&self, route: RiotRoute, path: &str) -> Result<Option<Value>, String>;
+}
+
+/// The bot's database. Everything goes through the free functions in
+/// [`crate::db`] on the locked connection.
+#[async_trait]
+pub trait Store: Send + Sync {
+    async fn lock(&self) -> MutexGuard<'_, Connection>;
+}
+
+#[async_trait]
+impl Store for Mutex<Connection> {
+    async fn lock(&self) -> MutexGuard<'_, Connection> {
+        Mutex::lock(self).await
+    }
+}
+
+/// A client whose calls, together with those of the other clients sharing
+/// `slots`, wait their turn once the slots are taken. A burst of chat replies
+/// and `!levelcheck`s then can't pile onto llama.cpp and Battle.net at once,
+/// while commands that call neither never wait.
+pub struct Bounded<C: ?Sized> {
+    slots: Arc<Semaphore>,
+    inner: Arc<C>,
+}
+
+impl<C: ?Sized> Bounded<C> {
+    pub fn new(slots: Arc<Semaphore>, inner: Arc<C>) -> Bounded<C> {
+        Bounded { slots, inner }
+    }
+}
+
+#[async_trait]
+impl<C: LlmClient + ?Sized> LlmClient for Bounded<C> {
+    async fn complete(&self, messages: Vec<ChatMessage>) -> Result<String, String> {
+        // The semaphore is never closed, so acquiring can't fail
+        let _slot = self.slots.acquire().await.ok();
+        self.inner.complete(messages).await
+    }
+
+    async fn probe(&self, timeout: Duration) -> Result<String, String> {
+        // A health check shouldn't report a busy backend as down
+        self.inner.probe(timeout).await
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, String> {
+        let _slot = self.slots.acquire().await.ok();
+        self.inner.embed(text).await
+    }
+}
+
+#[async_trait]
+impl<C: BlizzardClient + ?Sized> BlizzardClient for Bounded<C> {
+    async fn token(&self) -> Result<String, String> {
+        let _slot = self.slots.acquire().await.ok();
+        self.inner.token().await
+    }
+
+    async fn get_json(&self, path: &str) -> Result<Option<Value>, String> {
+        let _slot = self.slots.acquire().await.ok();
+        self.inner.get_json(path).await
+    }
+
+    async fn download(&self, url: &str) -> Result<Vec<u8>, String> {
+        let _slot = self.slots.acquire().await.ok();
+        self.inner.download(url).await
+    }
+}
+
+#[derive(Serialize)]
+struct ChatRequest {
+    messages: Vec<ChatMessage>,
+    temperature: f32,
+    stop: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    choices: Vec<Choice>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: ChatMessage,
+}
+
+/// A raw completion for a prompt we formatted ourselves (`/completion`).
+#[derive(Serialize)]
+struct CompletionRequest {
+    prompt: String,
+    temperature: f32,
+    stop: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct CompletionResponse {
+    content: String,
+}
+
+#[derive(Serialize)]
+struct EmbeddingRequest<'a> {
+    input: &'a str,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<Embedding>,
+}
+
+#[derive(Deserialize)]
+struct Embedding {
+    embedding: Vec<f32>,
+}
+
+/// What llama.cpp's `/props` says about the loaded model.
+#[derive(Deserialize)]
+struct Props {
+    #[serde(default)]
+    chat_template: String,
+}
+
+/// A llama.cpp server's OpenAI-compatible API.
+pub struct LlamaCpp {
+    http: HttpClient,
+    url: String,
+    /// The model's prompt format, for stop tokens, if known.
+    template: Option<ChatTemplate>,
+    /// Format prompts with `template` ourselves instead of letting the server do it.
+    format_locally: bool,
+}
+
+impl LlamaCpp {
+    pub fn new(http: HttpClient, url: String) -> LlamaCpp {
+        LlamaCpp {
+            http,
+            url,
+            template: None,
+            format_locally: false,
+        }
+    }
+
+    /// Uses `template`'s stop tokens; with `format_locally`, prompts are also
+    /// rendered in it and sent to `/completion`, bypassing the server's template.
+    pub fn with_template(mut self, template: ChatTemplate, format_locally: bool) -> LlamaCpp {
+        self.template = Some(template);
+        self.format_locally = format_locally;
+        self
+    }
+
+    /// Asks the server which chat template the loaded model uses.
+    pub async fn detect_template(&self) -> Result<Option<ChatTemplate>, String> {
+        let response = trace::tag(self.http.get(format!("{}/props", self.url)))
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach llama.cpp: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("llama.cpp returned status {}", response.status()));
+        }
+        let props: Props = response
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse /props: {}", e))?;
+        Ok(ChatTemplate::detect(&props.chat_template))
+    }
+
+    fn stop(&self) -> Vec<String> {
+        let stop = match self.template {
+            Some(template) => template.stop(),
+            None => &FALLBACK_STOP[..],
+        };
+        stop.iter().map(|s| s.to_string()).collect()
+    }
+
+    async fn complete_raw(&self, template: ChatTemplate, messages: &[ChatMessage]) -> Result<String, String> {
+        let request = CompletionRequest {
+            prompt: template.render(messages),
+            temperature: 0.4,
+            stop: self.stop(),
+        };
+        let response = trace::tag(self.http.post(format!("{}/completion", self.url)))
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach llama.cpp: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("llama.cpp returned status {}", response.status()));
+        }
+        let completion: CompletionResponse = response
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse response: {}", e))?;
+        Ok(completion.content.trim().to_string())
+    }
+}
+
+#[async_trait]
+impl LlmClient for LlamaCpp {
+    async fn complete(&self, messages: Vec<ChatMessage>) -> Result<String, String> {
+        if let Some(template) = self.template.filter(|_| self.format_locally) {
+            return self.complete_raw(template, &messages).await;
+        }
+        let request = ChatRequest {
+            messages,
+            temperature: 0.4,
+            stop: self.stop(),
+        };
+
+        let response = trace::tag(self.http.post(format!("{}/v1/chat/completions", self.url)))
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach llama.cpp: {}", e))?;
+
+        if !response.status().is_success() {
+            return Err(format!("llama.cpp returned status {}", response.status()));
+        }
+
+        let chat_response: ChatResponse = response
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse response: {}", e))?;
+
+        chat_response
+            .choices
+            .into_iter()
+            .next()
+            .map(|c| c.message.content)
+            .ok_or_else(|| "No response from model".to_string())
+    }
+
+    async fn probe(&self, timeout: Duration) -> Result<String, String> {
+        let probe = trace::tag(self.http.get(format!("{}/v1/models", self.url)))
+            .timeout(timeout)
+            .send()
+            .await;
+        match probe {
+            Ok(resp) if resp.status().is_success() => Ok(self.url.clone()),
+            Ok(resp) => Err(format!("{} returned status {}", self.url, resp.status())),
+            Err(e) => Err(format!("can't reach {}: {}", self.url, e)),
+        }
+    }
+
+    /// Needs llama.cpp started with `--embeddings`.
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, String> {
+        let response = trace::tag(self.http.post(format!("{}/v1/embeddings", self.url)))
+            .json(&EmbeddingRequest { input: text })
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach llama.cpp: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("llama.cpp returned status {} for embeddings", response.status()));
+        }
+        let embeddings: EmbeddingResponse = response
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse embeddings: {}", e))?;
+        embeddings
+            .data
+            .into_iter()
+            .next()
+            .map(|e| e.embedding)
+            .ok_or_else(|| "No embedding from model".to_string())
+    }
+}
+
+struct OAuthToken {
+    token: String,
+    expires_at: Instant,
+}
+
+#[derive(Deserialize)]
+struct OAuthTokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// The Battle.net API with client-credentials OAuth.
+pub struct BattleNet {
+    http: HttpClient,
+    api_base: String,
+    oauth_url: String,
+    client_id: String,
+    client_secret: String,
+    token: Mutex<Option<OAuthToken>>,
+}
+
+impl BattleNet {
+    pub fn new(http: HttpClient, region: Region, client_id: String, client_secret: String) -> BattleNet {
+        BattleNet {
+            http,
+            api_base: region.api_base(),
+            oauth_url: "https://oauth.battle.net/token".to_string(),
+            client_id,
+            client_secret,
+            token: Mutex::new(None),
+        }
+    }
+
+    /// Points the client at another server, e.g. a mock in tests.
+    #[cfg(test)]
+    pub fn with_base_urls(mut self, api_base: &str, oauth_url: &str) -> BattleNet {
+        self.api_base = api_base.to_string();
+        self.oauth_url = oauth_url.to_string();
+        self
+    }
+}
+
+#[async_trait]
+impl BlizzardClient for BattleNet {
+    async fn token(&self) -> Result<String, String> {
+        let mut token = self.token.lock().await;
+        if let Some(current) = token.as_ref().filter(|t| Instant::now() < t.expires_at) {
+            return Ok(current.token.clone());
+        }
+
+        let resp = trace::tag(self.http.post(&self.oauth_url))
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(&[("grant_type", "client_credentials")])
+            .send()
+            .await
+            .map_err(|e| format!("OAuth request failed: {}", e))?;
+
+        if !resp.status().is_success() {
+            return Err(format!("OAuth returned status {}", resp.status()));
+        }
+
+        let token_resp: OAuthTokenResponse = resp
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse OAuth response: {}", e))?;
+
+        // Expire 60s early to avoid edge cases
+        *token = Some(OAuthToken {
+            token: token_resp.access_token.clone(),
+            expires_at: Instant::now() + Duration::from_secs(token_resp.expires_in.saturating_sub(60)),
+        });
+        Ok(token_resp.access_token)
+    }
+
+    async fn get_json(&self, path: &str) -> Result<Option<Value>, String> {
+        let token = self.token().await?;
+        let resp = trace::tag(self.http.get(format!("{}{}", self.api_base, path)))
+            .bearer_auth(&token)
+            .send()
+            .await
+            .map_err(|e| format!("API request failed: {}", e))?;
+
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !resp.status().is_success() {
+            return Err(format!("Blizzard API returned status {}", resp.status()));
+        }
+
+        resp.json()
+            .await
+            .map(Some)
+            .map_err(|e| format!("Failed to parse API response: {}", e))
+    }
+
+    async fn download(&self, url: &str) -> Result<Vec<u8>, String> {
+        let bytes = self
+            .http
+            .get(url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("Download failed: {}", e))?
+            .bytes()
+            .await
+            .map_err(|e| format!("Download failed: {}", e))?;
+        Ok(bytes.to_vec())
+    }
+}
+
+#[derive(Deserialize)]
+struct SteamResponse<T> {
+    response: T,
+}
+
+#[derive(Deserialize)]
+struct SteamPlayers {
+    players: Vec<SteamPlayer>,
+}
+
+#[derive(Deserialize)]
+struct SteamVanity {
+    /// 1 when found, 42 when no profile has that name.
+    success: u32,
+    steamid: Option<String>,
+}
+
+/// The Steam Web API with a publisher-free API key.
+pub struct SteamWebApi {
+    http: HttpClient,
+    api_base: String,
+    key: String,
+}
+
+impl SteamWebApi {
+    pub fn new(http: HttpClient, key: String) -> SteamWebApi {
+        SteamWebApi {
+            http,
+            api_base: "https://api.steampowered.com".to_string(),
+            key,
+        }
+    }
+
+    /// Points the client at another server, e.g. a mock in tests.
+    #[cfg(test)]
+    pub fn with_base_url(mut self, api_base: &str) -> SteamWebApi {
+        self.api_base = api_base.to_string();
+        self
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(&self, path: &str, query: &[(&str, &str)]) -> Result<T, String> {
+        let resp = trace::tag(self.http.get(format!("{}{}", self.api_base, path)))
+            .query(&[("key", self.key.as_str())])
+            .query(query)
+            .send()
+            .await
+            .map_err(|e| format!("Steam request failed: {}", e))?;
+        if !resp.status().is_success() {
+            return Err(format!("Steam API returned status {}", resp.status()));
+        }
+        resp.json::<SteamResponse<T>>()
+            .await
+            .map(|r| r.response)
+            .map_err(|e| format!("Failed to parse Steam response: {}", e))
+    }
+}
+
+#[async_trait]
+impl SteamClient for SteamWebApi {
+    async fn player_summaries(&self, steam_ids: &[String]) -> Result<Vec<SteamPlayer>, String> {
+        let ids = steam_ids.join(",");
+        let players: SteamPlayers = self
+            .get("/ISteamUser/GetPlayerSummaries/v0002/", &[("steamids", ids.as_str())])
+            .await?;
+        Ok(players.players)
+    }
+
+    async fn resolve_vanity_url(&self, name: &str) -> Result<Option<String>, String> {
+        let vanity: SteamVanity = self
+            .get("/ISteamUser/ResolveVanityURL/v0001/", &[("vanityurl", name)])
+            .await?;
+        Ok(vanity.steamid.filter(|_| vanity.success == 1))
+    }
+}
+
+/// The Riot Games API with a developer or production key.
+pub struct RiotApi {
+    http: HttpClient,
+    regional_base: String,
+    platform_base: String,
+    key: String,
+}
+
+impl RiotApi {
+    pub fn new(http: HttpClient, platform: Platform, key: String) -> RiotApi {
+        RiotApi {
+            http,
+            regional_base: platform.regional_api_base(),
+            platform_base: platform.api_base(),
+            key,
+        }
+    }
+
+    /// Points both hosts at another server, e.g. a mock in tests.
+    #[cfg(test)]
+    pub fn with_base_url(mut self, base: &str) -> RiotApi {
+        self.regional_base = base.to_string();
+        self.platform_base = base.to_string();
+        self
+    }
+}
+
+#[async_trait]
+impl RiotClient for RiotApi {
+    async fn get_json(&self, route: RiotRoute, path: &str) -> Result<Option<Value>, String> {
+        let base = match route {
+            RiotRoute::Regional => &self.regional_base,
+            RiotRoute::Platform => &self.platform_base,
+        };
+        let resp = trace::tag(self.http.get(format!("{}{}", base, path)))
+            .header("X-Riot-Token", &self.key)
+            .send()
+            .await
+            .map_err(|e| format!("Riot request failed: {}", e))?;
+
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !resp.status().is_success() {
+            return Err(format!("Riot API returned status {}", resp.status()));
+        }
+        resp.json()
+            .await
+            .map(Some)
+            .map_err(|e| format!("Failed to parse Riot response: {}", e))
+    }
+}
+
+/// In-memory stand-ins for the clients, for unit tests.
+#[cfg(test)]
+pub mod mock {
+    use super::*;
+    use std::collections::{HashMap, VecDeque};
+    use std::sync::{Arc, Mutex as StdMutex};
+
+    use crate::{bots, db, events, middleware, wow, Handler};
+
+    /// Replies with queued answers (or echoes the last message) and records
+    /// every request.
+    #[derive(Default)]
+    pub struct MockLlm {
+        pub replies: StdMutex<VecDeque<Result<String, String>>>,
+        pub requests: StdMutex<Vec<Vec<ChatMessage>>>,
+    }
+
+    impl MockLlm {
+        pub fn replying(replies: &[&str]) -> MockLlm {
+            MockLlm {
+                replies: StdMutex::new(replies.iter().map(|r| Ok(r.to_string())).collect()),
+                ..Default::default()
+            }
+        }
+    }
+
+    #[async_trait]
+    impl LlmClient for MockLlm {
+        async fn complete(&self, messages: Vec<ChatMessage>) -> Result<String, String> {
+            let echo = messages.last().map(|m| m.content.clone()).unwrap_or_default();
+            self.requests.lock().unwrap().push(messages);
+            self.replies.lock().unwrap().pop_front().unwrap_or(Ok(echo))
+        }
+
+        async fn probe(&self, _timeout: Duration) -> Result<String, String> {
+            Ok("mock".to_string())
+        }
+
+        async fn embed(&self, text: &str) -> Result<Vec<f32>, String> {
+            Ok(crate::mock_llm::hashed_embedding(text))
+        }
+    }
+
+    /// Serves canned JSON by path substring; anything unmatched is a 404.
+    #[derive(Default)]
+    pub struct MockBlizzard {
+        pub responses: HashMap<String, Value>,
+    }
+
+    impl MockBlizzard {
+        /// Adds a character summary for `name`.
+        pub fn with_character(mut self, name: &str, level: u32, race: &str, class: &str) -> MockBlizzard {
+            self.responses.insert(
+                format!("/character/{}/{}?", "nightslayer", name.to_lowercase()),
+                serde_json::json!({
+                    "name": name,
+                    "level": level,
+                    "race": { "name": race },
+                    "character_class": { "name": class },
+                }),
+            );
+            self
+        }
+
+        /// Adds character media for `name` with an avatar at `url`.
+        pub fn with_avatar(mut self, name: &str, url: &str) -> MockBlizzard {
+            self.responses.insert(
+                format!("/character/{}/{}/character-media?", "nightslayer", name.to_lowercase()),
+                serde_json::json!({ "assets": [{ "key": "avatar", "value": url }] }),
+            );
+            self
+        }
+    }
+
+    #[async_trait]
+    impl BlizzardClient for MockBlizzard {
+        async fn token(&self) -> Result<String, String> {
+            Ok("token".to_string())
+        }
+
+        async fn get_json(&self, path: &str) -> Result<Option<Value>, String> {
+            Ok(self
+                .responses
+                .iter()
+                .find(|(key, _)| path.contains(key.as_str()))
+                .map(|(_, value)| value.clone()))
+        }
+
+        async fn download(&self, _url: &str) -> Result<Vec<u8>, String> {
+            Err("No downloads in tests".to_string())
+        }
+    }
+
+    /// Knows a fixed set of players and custom profile names.
+    #[derive(Default)]
+    pub struct MockSteam {
+        pub players: Vec<SteamPlayer>,
+        pub vanity: HashMap<String, String>,
+    }
+
+    impl MockSteam {
+        /// Adds a player, in `game` if set, reachable by ID or by `name` as a custom URL.
+        pub fn with_player(mut self, steam_id: &str, name: &str, game: Option<&str>) -> MockSteam {
+            self.players.push(SteamPlayer {
+                steamid: steam_id.to_string(),
+                personaname: name.to_string(),
+                gameextrainfo: game.map(str::to_string),
+            });
+            self.vanity.insert(name.to_lowercase(), steam_id.to_string());
+            self
+        }
+    }
+
+    #[async_trait]
+    impl SteamClient for MockSteam {
+        async fn player_summaries(&self, steam_ids: &[String]) -> Result<Vec<SteamPlayer>, String> {
+            Ok(self.players.iter().filter(|p| steam_ids.contains(&p.steamid)).cloned().collect())
+        }
+
+        async fn resolve_vanity_url(&self, name: &str) -> Result<Option<String>, String> {
+            Ok(self.vanity.get(&name.to_lowercase()).cloned())
+        }
+    }
+
+    /// Serves canned JSON by path substring, on either route; anything
+    /// unmatched is a 404.
+    #[derive(Default)]
+    pub struct MockRiot {
+        pub responses: StdMutex<HashMap<String, Value>>,
+    }
+
+    impl MockRiot {
+        /// Gives the player with `puuid` a solo queue rank.
+        pub fn with_rank(self, puuid: &str, tier: &str, division: &str) -> MockRiot {
+            self.set_rank(puuid, tier, division);
+            self
+        }
+
+        pub fn set_rank(&self, puuid: &str, tier: &str, division: &str) {
+            self.responses.lock().unwrap().insert(
+                format!("/entries/by-puuid/{}", puuid),
+                serde_json::json!([{
+                    "queueType": crate::riot::SOLO_QUEUE,
+                    "tier": tier,
+                    "rank": division,
+                    "leaguePoints": 50,
+                    "wins": 10,
+                    "losses": 8,
+                }]),
+            );
+        }
+    }
+
+    #[async_trait]
+    impl RiotClient for MockRiot {
+        async fn get_json(&self, _route: RiotRoute, path: &str) -> Result<Option<Value>, String> {
+            Ok(self
+                .responses
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|(key, _)| path.contains(key.as_str()))
+                .map(|(_, value)| value.clone()))
+        }
+    }
+
+    /// A conversation keyed `key` in channel 1, outside any guild.
+    pub fn conversation(key: &str) -> bots::Conversation {
+        bots::Conversation {
+            guild_id: None,
+            channel_id: serenity::model::id::ChannelId::new(1),
+            key: key.to_string(),
+            message_id: None,
+            user_id: None,
+            speaker: None,
+            notes: Vec::new(),
+        }
+    }
+
+    /// A handler over an in-memory database with the given clients.
+    pub fn handler(llm: Option<Arc<dyn LlmClient>>, blizzard: Option<Arc<dyn BlizzardClient>>) -> Handler {
+        let conn = Connection::open_in_memory().unwrap();
+        db::init(&conn).unwrap();
+        Handler {
+            llm,
+            blizzard,
+            steam: None,
+            riot: None,
+            wow_region: wow::Region::Us,
+            wow_version: wow::GameVersion::Anniversary,
+            db: Arc::new(Mutex::new(conn)),
+            events: events::channel(),
+            identity: bots::Identity::primary(),
+            modules: Vec::new(),
+            middleware: middleware::chain(),
+            topics: Arc::default(),
+        }
+    }
+}
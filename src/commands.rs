@@ -0,0 +1,115 @@
+use serenity::async_trait;
+use serenity::model::channel::Message;
+use serenity::prelude::Context;
+
+use crate::args::Args;
+use crate::help::Category;
+use crate::Handler;
+
+mod archives;
+mod hello;
+mod help;
+mod invites;
+mod loglevel;
+mod ping;
+mod purgeme;
+mod purgeuser;
+
+pub use archives::Archives;
+pub use hello::Hello;
+pub use help::Help;
+pub use invites::Invites;
+pub use loglevel::LogLevel;
+pub use ping::Ping;
+pub use purgeme::PurgeMe;
+pub use purgeuser::PurgeUser;
+
+/// How a command is listed in `!help`.
+pub struct Usage {
+    pub category: Category,
+    /// What to type, without the `!`, as in `tag add <name> <text>`. Slash
+    /// commands keep their `/`, and tips that aren't a command leave it empty.
+    pub syntax: &'static str,
+    pub description: &'static str,
+}
+
+impl Usage {
+    pub const fn new(category: Category, syntax: &'static str, description: &'static str) -> Usage {
+        Usage {
+            category,
+            syntax,
+            description,
+        }
+    }
+
+    /// The line in `!help`.
+    pub fn line(&self) -> String {
+        match self.syntax {
+            "" => self.description.to_string(),
+            slash if slash.starts_with('/') => format!("`{}` — {}", slash, self.description),
+            syntax => format!("`!{}` — {}", syntax, self.description),
+        }
+    }
+}
+
+/// A single `!command` in its own file. Registered commands are dispatched
+/// by name before the modules see the message, and `!help` lists them from
+/// [`Command::usage`], so adding one is a new file plus a [`REGISTRY`] entry.
+#[async_trait]
+pub trait Command: Send + Sync {
+    /// The name it's invoked by, after aliases and groups are folded in
+    /// (so `"tag add"` for `!tag add`).
+    fn name(&self) -> &'static str;
+
+    fn usage(&self) -> Usage;
+
+    async fn execute(&self, handler: &Handler, ctx: &Context, msg: &Message, args: &Args);
+}
+
+/// Every registered command, in the order `!help` lists them.
+pub static REGISTRY: &[&dyn Command] = &[&Help, &Ping, &Hello, &PurgeMe, &Invites, &Archives, &PurgeUser, &LogLevel];
+
+pub fn find(name: &str) -> Option<&'static dyn Command> {
+    REGISTRY.iter().copied().find(|command| command.name() == name)
+}
+
+/// `!help` lines for the registered commands in `category`.
+pub fn help_lines(category: Category) -> Vec<String> {
+    REGISTRY
+        .iter()
+        .map(|command| command.usage())
+        .filter(|usage| usage.category == category)
+        .map(|usage| usage.line())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_registry() {
+        let names: HashSet<_> = REGISTRY.iter().map(|c| c.name()).collect();
+        assert_eq!(names.len(), REGISTRY.len(), "command names must be unique");
+        assert_eq!(find("ping").map(|c| c.name()), Some("ping"));
+        assert!(find("pong").is_none());
+    }
+
+    #[test]
+    fn test_help_lines() {
+        assert_eq!(
+            help_lines(Category::Fun),
+            ["`!help` — Show this message", "`!ping` — Pong!", "`!hello` — Greet the bot"]
+        );
+        assert!(help_lines(Category::Wow).is_empty());
+        assert_eq!(help_lines(Category::Admin).len(), 4);
+    }
+
+    #[test]
+    fn test_usage_line() {
+        assert_eq!(Usage::new(Category::Fun, "tag list", "All tags").line(), "`!tag list` — All tags");
+        assert_eq!(Usage::new(Category::Wow, "/levelcheck", "Slash version").line(), "`/levelcheck` — Slash version");
+        assert_eq!(Usage::new(Category::Chat, "", "Mention me to chat").line(), "Mention me to chat");
+    }
+}
@@ -0,0 +1,92 @@
+use serenity::async_trait;
+use serenity::builder::{CreateAttachment, CreateMessage};
+use serenity::model::channel::Message;
+use serenity::prelude::Context;
+use tracing::{error, info};
+
+use super::{Command, Usage};
+use crate::args::Args;
+use crate::help::Category;
+use crate::{bots, db, Handler};
+
+const USAGE: &str = "Usage: `!archives` or `!archives get <number>`";
+
+/// `!archives [get <number>]`: this channel's archived history files, which
+/// nightly maintenance writes with `!retention archive` on.
+pub struct Archives;
+
+#[async_trait]
+impl Command for Archives {
+    fn name(&self) -> &'static str {
+        "archives"
+    }
+
+    fn usage(&self) -> Usage {
+        Usage {
+            category: Category::Admin,
+            syntax: "archives [get <number>]",
+            description: "This channel's archived history, and downloading it as gzipped JSON lines",
+        }
+    }
+
+    async fn execute(&self, handler: &Handler, ctx: &Context, msg: &Message, args: &Args) {
+        let message = match archives_message(handler, ctx, msg, args).await {
+            Ok(message) => message,
+            Err(response) => CreateMessage::new().content(response),
+        };
+        if let Err(why) = msg.channel_id.send_message(&ctx.http, message).await {
+            error!("Error sending message: {:?}", why);
+        }
+    }
+}
+
+async fn archives_message(handler: &Handler, ctx: &Context, msg: &Message, args: &Args) -> Result<CreateMessage, String> {
+    let Some(guild_id) = msg.guild_id else {
+        return Err("Archives are kept for server channels only.".to_string());
+    };
+    // Archives hold everyone's messages, so they're for server managers
+    if !bots::can_manage_guild(&ctx.http, guild_id, msg.author.id).await {
+        return Err("You need the Manage Server permission to see archived history.".to_string());
+    }
+    let archives = {
+        let conn = handler.db.lock().await;
+        db::get_message_archives(&conn, &msg.channel_id.to_string()).map_err(|e| {
+            error!("Failed to load message archives: {}", e);
+            "Failed to load archives.".to_string()
+        })?
+    };
+    match (args.get(0), args.parsed::<usize>(1)) {
+        (None, _) if archives.is_empty() => Err("Nothing from this channel has been archived.".to_string()),
+        (None, _) => {
+            let lines: Vec<String> = archives
+                .iter()
+                .enumerate()
+                .map(|(i, a)| {
+                    format!(
+                        "  `{}` <t:{}:d> to <t:{}:d> — {} messages",
+                        i + 1,
+                        a.first_timestamp,
+                        a.last_timestamp,
+                        a.message_count
+                    )
+                })
+                .collect();
+            Ok(CreateMessage::new().content(format!("**Archived history**\n{}\n{}", lines.join("\n"), USAGE)))
+        }
+        (Some("get"), Some(Ok(number))) => {
+            let archive = number
+                .checked_sub(1)
+                .and_then(|i| archives.get(i))
+                .ok_or_else(|| format!("There's no archive `{}`; see `!archives`.", number))?;
+            let attachment = CreateAttachment::path(&archive.path).await.map_err(|e| {
+                error!("Failed to read archive {}: {:?}", archive.path, e);
+                "That archive's file is missing.".to_string()
+            })?;
+            info!("{} downloaded archive {} of channel {}", msg.author.name, archive.id, archive.channel_id);
+            Ok(CreateMessage::new()
+                .content(format!("{} messages, one JSON object per line.", archive.message_count))
+                .add_file(attachment))
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
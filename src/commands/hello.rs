@@ -0,0 +1,33 @@
+use serenity::async_trait;
+use serenity::model::channel::Message;
+use serenity::prelude::Context;
+use tracing::error;
+
+use super::{Command, Usage};
+use crate::args::Args;
+use crate::help::Category;
+use crate::Handler;
+
+pub struct Hello;
+
+#[async_trait]
+impl Command for Hello {
+    fn name(&self) -> &'static str {
+        "hello"
+    }
+
+    fn usage(&self) -> Usage {
+        Usage {
+            category: Category::Fun,
+            syntax: "hello",
+            description: "Greet the bot",
+        }
+    }
+
+    async fn execute(&self, _handler: &Handler, ctx: &Context, msg: &Message, _args: &Args) {
+        let response = "IT'S CHRISTINITH! ARE YOU STUPID OR ARE YOU DEAF?!";
+        if let Err(why) = msg.channel_id.say(&ctx.http, response).await {
+            error!("Error sending message: {:?}", why);
+        }
+    }
+}
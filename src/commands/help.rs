@@ -0,0 +1,41 @@
+use serenity::async_trait;
+use serenity::builder::CreateMessage;
+use serenity::model::channel::Message;
+use serenity::prelude::Context;
+use tracing::error;
+
+use super::{Command, Usage};
+use crate::args::Args;
+use crate::help::{self, Category};
+use crate::{db, Handler};
+
+/// `!help`: the command list, with buttons to switch category.
+pub struct Help;
+
+#[async_trait]
+impl Command for Help {
+    fn name(&self) -> &'static str {
+        "help"
+    }
+
+    fn usage(&self) -> Usage {
+        Usage {
+            category: Category::Fun,
+            syntax: "help",
+            description: "Show this message",
+        }
+    }
+
+    async fn execute(&self, handler: &Handler, ctx: &Context, msg: &Message, _args: &Args) {
+        let cap = {
+            let conn = handler.db.lock().await;
+            db::get_response_cap(&conn, msg.guild_id.map(|g| g.to_string()).as_deref())
+        };
+        let message = CreateMessage::new()
+            .embed(help::embed(Category::Chat, &handler.modules, cap))
+            .components(help::buttons(Category::Chat));
+        if let Err(why) = msg.channel_id.send_message(&ctx.http, message).await {
+            error!("Error sending message: {:?}", why);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use serenity::async_trait;
+use serenity::builder::CreateMessage;
+use serenity::model::channel::Message;
+use serenity::prelude::Context;
+use serenity::utils::parse_user_mention;
+use tracing::error;
+
+use super::{Command, Usage};
+use crate::args::Args;
+use crate::help::Category;
+use crate::{mentions, Handler};
+
+/// `!invites [@user]`: which invites members joined through.
+pub struct Invites;
+
+#[async_trait]
+impl Command for Invites {
+    fn name(&self) -> &'static str {
+        "invites"
+    }
+
+    fn usage(&self) -> Usage {
+        Usage {
+            category: Category::Admin,
+            syntax: "invites [@user]",
+            description: "Which invites new members joined through, or who someone brought in",
+        }
+    }
+
+    async fn execute(&self, handler: &Handler, ctx: &Context, msg: &Message, args: &Args) {
+        let response = match msg.guild_id {
+            Some(guild_id) => {
+                let inviter = args.get(0).and_then(parse_user_mention);
+                if args.get(0).is_some() && inviter.is_none() {
+                    "Usage: `!invites [@user]`".to_string()
+                } else {
+                    handler.invites_report(guild_id, inviter).await
+                }
+            }
+            None => "Invites can only be checked in a server.".to_string(),
+        };
+        // Lists inviters, who shouldn't be pinged by someone checking stats
+        let message = CreateMessage::new().content(response).allowed_mentions(mentions::allowed(false));
+        if let Err(why) = msg.channel_id.send_message(&ctx.http, message).await {
+            error!("Error sending message: {:?}", why);
+        }
+    }
+}
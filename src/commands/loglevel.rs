@@ -0,0 +1,59 @@
+use serenity::async_trait;
+use serenity::model::channel::Message;
+use serenity::prelude::Context;
+use tracing::{error, info};
+
+use super::{Command, Usage};
+use crate::args::Args;
+use crate::help::Category;
+use crate::{bots, logging, Handler};
+
+const USAGE: &str = "Usage: `!loglevel <filter>` (like `info,discord_bot::wow=debug`) or `!loglevel reset`";
+
+/// `!loglevel [<filter>|reset]`: shows or changes what the bot logs, per
+/// module, without a restart.
+pub struct LogLevel;
+
+#[async_trait]
+impl Command for LogLevel {
+    fn name(&self) -> &'static str {
+        "loglevel"
+    }
+
+    fn usage(&self) -> Usage {
+        Usage {
+            category: Category::Admin,
+            syntax: "loglevel [<filter>|reset]",
+            description: "Show or change log levels per module, e.g. `info,discord_bot::wow=debug` (bot owner)",
+        }
+    }
+
+    async fn execute(&self, _handler: &Handler, ctx: &Context, msg: &Message, args: &Args) {
+        let response = if !bots::is_owner(&ctx.http, msg.author.id).await {
+            "Only the bot owner can change logging.".to_string()
+        } else {
+            loglevel_response(&msg.author.name, args)
+        };
+        if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+            error!("Error sending message: {:?}", why);
+        }
+    }
+}
+
+fn loglevel_response(author: &str, args: &Args) -> String {
+    let directives = match args.raw() {
+        "" => {
+            let current = logging::current_filter().unwrap_or_else(|| logging::DEFAULT_FILTER.to_string());
+            return format!("Log filter: `{}`. {}", current, USAGE);
+        }
+        "reset" => None,
+        directives => Some(directives),
+    };
+    match logging::set_filter(directives) {
+        Ok(filter) => {
+            info!("{} set the log filter to {}", author, filter);
+            format!("Log filter set to `{}`.", filter)
+        }
+        Err(e) => format!("That isn't a valid filter: {}\n{}", e, USAGE),
+    }
+}
@@ -0,0 +1,32 @@
+use serenity::async_trait;
+use serenity::model::channel::Message;
+use serenity::prelude::Context;
+use tracing::error;
+
+use super::{Command, Usage};
+use crate::args::Args;
+use crate::help::Category;
+use crate::Handler;
+
+pub struct Ping;
+
+#[async_trait]
+impl Command for Ping {
+    fn name(&self) -> &'static str {
+        "ping"
+    }
+
+    fn usage(&self) -> Usage {
+        Usage {
+            category: Category::Fun,
+            syntax: "ping",
+            description: "Pong!",
+        }
+    }
+
+    async fn execute(&self, _handler: &Handler, ctx: &Context, msg: &Message, _args: &Args) {
+        if let Err(why) = msg.channel_id.say(&ctx.http, "Pong! 🏓").await {
+            error!("Error sending message: {:?}", why);
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use serenity::async_trait;
+use serenity::builder::CreateMessage;
+use serenity::model::channel::Message;
+use serenity::prelude::Context;
+use tracing::error;
+
+use super::{Command, Usage};
+use crate::args::Args;
+use crate::help::Category;
+use crate::{purge, Handler};
+
+/// `!purgeme`: deletes everything the bot stores about the author, once they
+/// confirm with a button.
+pub struct PurgeMe;
+
+#[async_trait]
+impl Command for PurgeMe {
+    fn name(&self) -> &'static str {
+        "purgeme"
+    }
+
+    fn usage(&self) -> Usage {
+        Usage {
+            category: Category::Chat,
+            syntax: "purgeme",
+            description: "Delete everything I've stored about you (asks first)",
+        }
+    }
+
+    async fn execute(&self, _handler: &Handler, ctx: &Context, msg: &Message, _args: &Args) {
+        let message = CreateMessage::new()
+            .content(format!(
+                "<@{}>, this deletes your messages and private histories, memories of your prompts, \
+                 feedback, preferences, linked accounts, tickets, `!rp` turns, todos and confessions, \
+                 in every server. It can't be undone. {}",
+                msg.author.id,
+                purge::KEPT
+            ))
+            .components(purge::buttons(msg.author.id));
+        if let Err(why) = msg.channel_id.send_message(&ctx.http, message).await {
+            error!("Error sending message: {:?}", why);
+        }
+    }
+}
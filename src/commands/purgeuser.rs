@@ -0,0 +1,47 @@
+use serenity::async_trait;
+use serenity::model::channel::Message;
+use serenity::model::id::UserId;
+use serenity::prelude::Context;
+use serenity::utils::parse_user_mention;
+use tracing::error;
+
+use super::{Command, Usage};
+use crate::args::Args;
+use crate::help::Category;
+use crate::{bots, Handler};
+
+/// `!purgeuser <id>`: the owner's `!purgeme` for someone else, like a
+/// deletion request that came in by email.
+pub struct PurgeUser;
+
+#[async_trait]
+impl Command for PurgeUser {
+    fn name(&self) -> &'static str {
+        "purgeuser"
+    }
+
+    fn usage(&self) -> Usage {
+        Usage {
+            category: Category::Admin,
+            syntax: "purgeuser <user ID or @user>",
+            description: "Delete everything stored about a user (bot owner)",
+        }
+    }
+
+    async fn execute(&self, handler: &Handler, ctx: &Context, msg: &Message, args: &Args) {
+        let user = args
+            .get(0)
+            .and_then(|arg| parse_user_mention(arg).or_else(|| arg.parse().ok().filter(|&id| id != 0).map(UserId::new)));
+        let response = if !bots::is_owner(&ctx.http, msg.author.id).await {
+            "Only the bot owner can purge someone else's data. Use `!purgeme` for your own.".to_string()
+        } else {
+            match user {
+                Some(user) => handler.purge_user_data(&ctx.http, user, &msg.author.name).await,
+                None => "Usage: `!purgeuser <user ID or @user>`".to_string(),
+            }
+        };
+        if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+            error!("Error sending message: {:?}", why);
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use std::env;
+use std::fs;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::chat_template::ChatTemplate;
+use crate::{riot, wow};
+
+const DEFAULT_POLL_INTERVAL_SECS: u64 = 60 * 60;
+
+/// Startup settings read from the environment. Any variable can instead be
+/// read from a file by setting `<NAME>_FILE` to its path, which is how Docker
+/// and Kubernetes mount secrets.
+pub struct Config {
+    pub discord_token: String,
+    pub llama_api_url: Option<String>,
+    /// Forces a prompt format instead of detecting the model's.
+    pub llama_chat_template: Option<ChatTemplate>,
+    /// Client ID and secret, if both are set.
+    pub battlenet_credentials: Option<(String, String)>,
+    pub steam_api_key: Option<String>,
+    pub riot_api_key: Option<String>,
+    /// League of Legends server for Riot API lookups.
+    pub riot_platform: riot::Platform,
+    pub wow_region: wow::Region,
+    pub wow_version: wow::GameVersion,
+    pub poll_interval: Duration,
+    /// Attribute joins to invites, which needs the privileged Server Members
+    /// intent.
+    pub track_invites: bool,
+    /// Dashboard address and auth token, if both are set.
+    pub web: Option<(SocketAddr, String)>,
+    pub extra_bots: Vec<BotConfig>,
+}
+
+/// An extra bot account from `EXTRA_BOTS=name,...`, configured by
+/// `BOT_<NAME>_TOKEN`, `BOT_<NAME>_SYSTEM_PROMPT` and `BOT_<NAME>_CHANNELS`.
+pub struct BotConfig {
+    pub name: String,
+    pub token: String,
+    pub system_prompt: Option<String>,
+    pub channels: Vec<u64>,
+}
+
+/// Looks up `name` through `lookup`, or the file named by `<name>_FILE`. Setting
+/// both is an error so it's never a guess which one won. Surrounding whitespace
+/// is trimmed, since secret files usually end in a newline.
+pub(crate) fn var_from(lookup: &impl Fn(&str) -> Option<String>, name: &str) -> Result<Option<String>, String> {
+    let file_var = format!("{}_FILE", name);
+    match (lookup(name), lookup(&file_var)) {
+        (Some(_), Some(_)) => Err(format!("Set {} or {}, not both", name, file_var)),
+        (Some(value), None) => Ok(Some(value)),
+        (None, Some(path)) => fs::read_to_string(&path)
+            .map(|contents| Some(contents.trim().to_string()))
+            .map_err(|e| format!("{}: can't read {}: {}", file_var, path, e)),
+        (None, None) => Ok(None),
+    }
+}
+
+/// `name` from the environment or its `<name>_FILE`, for settings needed
+/// outside [`Config`], like by the offline CLI commands.
+pub fn env_var(name: &str) -> Result<Option<String>, String> {
+    var_from(&|name: &str| env::var(name).ok(), name)
+}
+
+impl Config {
+    pub fn from_env() -> Result<Config, String> {
+        Config::from_lookup(|name| env::var(name).ok())
+    }
+
+    pub(crate) fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Config, String> {
+        let var = |name: &str| var_from(&lookup, name);
+
+        let discord_token = var("DISCORD_TOKEN")?.ok_or("DISCORD_TOKEN is not set")?;
+
+        let battlenet_credentials = match (var("BATTLENET_CLIENT_ID")?, var("BATTLENET_CLIENT_SECRET")?) {
+            (Some(id), Some(secret)) => Some((id, secret)),
+            _ => None,
+        };
+
+        let wow_region = match var("BATTLENET_REGION")? {
+            Some(v) => wow::Region::from_name(&v)
+                .ok_or_else(|| format!("BATTLENET_REGION must be one of us, eu, kr, tw (got {})", v))?,
+            None => wow::Region::Us,
+        };
+        let wow_version = match var("WOW_GAME_VERSION")? {
+            Some(v) => wow::GameVersion::from_name(&v).ok_or_else(|| {
+                format!("WOW_GAME_VERSION must be one of era, anniversary, cata, retail (got {})", v)
+            })?,
+            None => wow::GameVersion::Anniversary,
+        };
+
+        let riot_platform = match var("RIOT_PLATFORM")? {
+            Some(v) => riot::Platform::from_name(&v).ok_or_else(|| {
+                let names: Vec<_> = riot::Platform::ALL.iter().map(|p| p.slug()).collect();
+                format!("RIOT_PLATFORM must be one of {} (got {})", names.join(", "), v)
+            })?,
+            None => riot::Platform::Na,
+        };
+
+        let llama_chat_template = match var("LLAMA_CHAT_TEMPLATE")? {
+            Some(v) => Some(ChatTemplate::from_name(&v).ok_or_else(|| {
+                let names: Vec<_> = ChatTemplate::ALL.iter().map(|t| t.name()).collect();
+                format!("LLAMA_CHAT_TEMPLATE must be one of {} (got {})", names.join(", "), v)
+            })?),
+            None => None,
+        };
+
+        let poll_interval = match var("CHARACTER_POLL_INTERVAL_SECS")? {
+            Some(v) => v
+                .parse::<u64>()
+                .map_err(|_| format!("CHARACTER_POLL_INTERVAL_SECS must be a number of seconds (got {})", v))?,
+            None => DEFAULT_POLL_INTERVAL_SECS,
+        };
+
+        let track_invites = match var("TRACK_INVITES")?.map(|v| v.to_lowercase()).as_deref() {
+            Some("1" | "true" | "yes" | "on") => true,
+            Some("0" | "false" | "no" | "off") | None => false,
+            Some(v) => return Err(format!("TRACK_INVITES must be true or false (got {})", v)),
+        };
+
+        // The dashboard refuses to start without a token
+        let web = match (var("WEB_BIND_ADDR")?, var("WEB_AUTH_TOKEN")?) {
+            (Some(addr), Some(token)) if !token.is_empty() => {
+                let addr = addr
+                    .parse()
+                    .map_err(|e| format!("Invalid WEB_BIND_ADDR {}: {}", addr, e))?;
+                Some((addr, token))
+            }
+            (Some(_), _) => return Err("WEB_BIND_ADDR is set but WEB_AUTH_TOKEN is not".to_string()),
+            _ => None,
+        };
+
+        let mut extra_bots = Vec::new();
+        for name in var("EXTRA_BOTS")?.unwrap_or_default().split(',') {
+            let name = name.trim().to_lowercase();
+            if name.is_empty() {
+                continue;
+            }
+            let prefix = format!("BOT_{}", name.to_uppercase());
+            let token = var(&format!("{}_TOKEN", prefix))?.ok_or_else(|| format!("{}_TOKEN is not set", prefix))?;
+            let channels = var(&format!("{}_CHANNELS", prefix))?
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|c| !c.is_empty())
+                .map(|c| {
+                    c.parse::<u64>()
+                        .map_err(|_| format!("{}_CHANNELS: `{}` is not a channel ID", prefix, c))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            extra_bots.push(BotConfig {
+                system_prompt: var(&format!("{}_SYSTEM_PROMPT", prefix))?,
+                name,
+                token,
+                channels,
+            });
+        }
+
+        Ok(Config {
+            discord_token,
+            llama_api_url: var("LLAMA_API_URL")?,
+            llama_chat_template,
+            battlenet_credentials,
+            steam_api_key: var("STEAM_API_KEY")?,
+            riot_api_key: var("RIOT_API_KEY")?,
+            riot_platform,
+            wow_region,
+            wow_version,
+            poll_interval: Duration::from_secs(poll_interval),
+            track_invites,
+            web,
+            extra_bots,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn load(vars: &[(&str, &str)]) -> Result<Config, String> {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Config::from_lookup(move |name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = load(&[("DISCORD_TOKEN", "abc")]).unwrap();
+        assert_eq!(config.discord_token, "abc");
+        assert_eq!(config.llama_api_url, None);
+        assert_eq!(config.llama_chat_template, None);
+        assert!(config.battlenet_credentials.is_none());
+        assert_eq!(config.wow_region, wow::Region::Us);
+        assert_eq!(config.poll_interval, Duration::from_secs(DEFAULT_POLL_INTERVAL_SECS));
+        assert!(config.web.is_none());
+        assert!(!config.track_invites);
+
+        assert!(load(&[]).is_err());
+    }
+
+    #[test]
+    fn test_secret_files() {
+        let path = std::env::temp_dir().join(format!("discord-token-{}", std::process::id()));
+        fs::write(&path, "from-file\n").unwrap();
+        let path = path.to_str().unwrap();
+
+        let config = load(&[("DISCORD_TOKEN_FILE", path), ("BATTLENET_CLIENT_ID", "id"), ("BATTLENET_CLIENT_SECRET_FILE", path)]).unwrap();
+        assert_eq!(config.discord_token, "from-file");
+        assert_eq!(config.battlenet_credentials, Some(("id".to_string(), "from-file".to_string())));
+
+        assert!(load(&[("DISCORD_TOKEN", "abc"), ("DISCORD_TOKEN_FILE", path)]).is_err());
+        assert!(load(&[("DISCORD_TOKEN_FILE", "/nonexistent/token")]).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_extra_bots() {
+        let config = load(&[
+            ("DISCORD_TOKEN", "abc"),
+            ("EXTRA_BOTS", "Helpful, quiet"),
+            ("BOT_HELPFUL_TOKEN", "h"),
+            ("BOT_HELPFUL_SYSTEM_PROMPT", "Be kind."),
+            ("BOT_HELPFUL_CHANNELS", "1, 2"),
+            ("BOT_QUIET_TOKEN", "q"),
+        ])
+        .unwrap();
+        assert_eq!(config.extra_bots.len(), 2);
+        let helpful = &config.extra_bots[0];
+        assert_eq!(helpful.name, "helpful");
+        assert_eq!(helpful.token, "h");
+        assert_eq!(helpful.system_prompt.as_deref(), Some("Be kind."));
+        assert_eq!(helpful.channels, [1, 2]);
+        assert!(config.extra_bots[1].channels.is_empty());
+
+        assert!(load(&[("DISCORD_TOKEN", "abc"), ("EXTRA_BOTS", "helpful")]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        assert!(load(&[("DISCORD_TOKEN", "abc"), ("BATTLENET_REGION", "cn")]).is_err());
+        assert!(load(&[("DISCORD_TOKEN", "abc"), ("LLAMA_CHAT_TEMPLATE", "alpaca")]).is_err());
+        assert!(load(&[("DISCORD_TOKEN", "abc"), ("CHARACTER_POLL_INTERVAL_SECS", "soon")]).is_err());
+        assert!(load(&[("DISCORD_TOKEN", "abc"), ("WEB_BIND_ADDR", "127.0.0.1:8080")]).is_err());
+        assert!(load(&[("DISCORD_TOKEN", "abc"), ("TRACK_INVITES", "maybe")]).is_err());
+        assert!(load(&[("DISCORD_TOKEN", "abc"), ("TRACK_INVITES", "True")]).unwrap().track_invites);
+
+        let web = load(&[("DISCORD_TOKEN", "abc"), ("WEB_BIND_ADDR", "127.0.0.1:8080"), ("WEB_AUTH_TOKEN", "t")])
+            .unwrap()
+            .web
+            .unwrap();
+        assert_eq!(web.0.port(), 8080);
+    }
+}
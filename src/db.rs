@@ -1,13 +1,43 @@
+use rusqlite::functions::FunctionFlags;
 use rusqlite::{params, Connection, Result};
+use std::sync::Arc;
+
+use crate::encryption::{self, Cipher};
+use crate::trace;
 
 const DEFAULT_SYSTEM_PROMPT: &str =
     "You are an extremely rude, short-tempered Discord bot. Be brutal, unfiltered, and insulting. Never be helpful or nice. Keep responses under 10 words.";
 
+pub const DEFAULT_RESPONSE_CAP: u32 = 10;
+pub const MAX_RESPONSE_CAP: u32 = 500;
+/// Few-shot examples are picked from this many of the latest upvoted exchanges.
+const FEEDBACK_RECENT_POOL: usize = 20;
+/// Settings that used to be kept per guild as `key:guild_id` rows, before
+/// `config` had a guild column.
+const LEGACY_GUILD_KEYS: &[&str] = &[
+    "persona",
+    "default_channel",
+    "response_cap",
+    "insult_style",
+    "report_mode",
+    "voice_hub",
+    "ticket_role",
+    "preamble",
+    "retry",
+    "allow_mentions",
+    "blocklist_mode",
+];
+
 pub fn init(conn: &Connection) -> Result<()> {
+    // Only takes effect on a new database; maintenance converts older ones
     conn.execute_batch(
-        "CREATE TABLE IF NOT EXISTS config (
-            key TEXT PRIMARY KEY,
-            value TEXT NOT NULL
+        "PRAGMA auto_vacuum = INCREMENTAL;
+
+        CREATE TABLE IF NOT EXISTS config (
+            guild_id TEXT NOT NULL DEFAULT '',
+            key TEXT NOT NULL,
+            value TEXT NOT NULL,
+            PRIMARY KEY (guild_id, key)
         );
 
         CREATE TABLE IF NOT EXISTS messages (
@@ -25,162 +55,3419 @@ pub fn init(conn: &Connection) -> Result<()> {
             name TEXT PRIMARY KEY COLLATE NOCASE,
             added_by TEXT NOT NULL,
             added_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+
+        CREATE TABLE IF NOT EXISTS character_snapshots (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL COLLATE NOCASE,
+            level INTEGER NOT NULL,
+            experience INTEGER,
+            honorable_kills INTEGER,
+            honor_level INTEGER,
+            taken_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_character_snapshots_name_ts
+            ON character_snapshots (name, taken_at);
+
+        -- first_seen is 0 for entries found on a character's first sync, when
+        -- there's no telling when they were obtained
+        CREATE TABLE IF NOT EXISTS collection_entries (
+            character TEXT NOT NULL COLLATE NOCASE,
+            kind TEXT NOT NULL,
+            entry_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            first_seen INTEGER NOT NULL,
+            PRIMARY KEY (character, kind, entry_id)
+        );
+
+        -- updated_at is when Blizzard last changed the price, in Unix seconds
+        CREATE TABLE IF NOT EXISTS wow_token_prices (
+            updated_at INTEGER PRIMARY KEY,
+            price INTEGER NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS guild_features (
+            guild_id TEXT NOT NULL,
+            feature TEXT NOT NULL,
+            enabled INTEGER NOT NULL,
+            PRIMARY KEY (guild_id, feature)
+        );
+
+        CREATE TABLE IF NOT EXISTS blocklist (
+            guild_id TEXT NOT NULL,
+            entry TEXT NOT NULL COLLATE NOCASE,
+            added_by TEXT NOT NULL,
+            PRIMARY KEY (guild_id, entry)
+        );
+
+        CREATE TABLE IF NOT EXISTS wake_words (
+            guild_id TEXT NOT NULL,
+            word TEXT NOT NULL COLLATE NOCASE,
+            added_by TEXT NOT NULL,
+            PRIMARY KEY (guild_id, word)
+        );
+
+        CREATE TABLE IF NOT EXISTS feedback (
+            message_id TEXT PRIMARY KEY,
+            persona TEXT NOT NULL,
+            prompt TEXT NOT NULL,
+            reply TEXT NOT NULL,
+            score INTEGER NOT NULL DEFAULT 0,
+            created_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+
+        CREATE TABLE IF NOT EXISTS personas (
+            name TEXT PRIMARY KEY COLLATE NOCASE,
+            description TEXT NOT NULL,
+            personality TEXT NOT NULL,
+            scenario TEXT NOT NULL,
+            first_message TEXT NOT NULL,
+            system_prompt TEXT NOT NULL,
+            created_by TEXT NOT NULL,
+            created_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+
+        CREATE TABLE IF NOT EXISTS rp_sessions (
+            channel_id TEXT PRIMARY KEY,
+            scenario TEXT NOT NULL,
+            participants TEXT NOT NULL DEFAULT '[]',
+            turns INTEGER NOT NULL DEFAULT 0,
+            started_by TEXT NOT NULL,
+            started_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+
+        CREATE TABLE IF NOT EXISTS pending_requests (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            guild_id TEXT NOT NULL,
+            channel_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            payload TEXT NOT NULL,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            next_attempt INTEGER NOT NULL,
+            created_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+
+        CREATE TABLE IF NOT EXISTS confessions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id TEXT NOT NULL,
+            content TEXT NOT NULL,
+            created_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+
+        CREATE TABLE IF NOT EXISTS tickets (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            guild_id TEXT NOT NULL,
+            thread_id TEXT NOT NULL UNIQUE,
+            user_id TEXT NOT NULL,
+            subject TEXT NOT NULL,
+            transcript TEXT,
+            created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+            closed_at INTEGER
+        );
+
+        CREATE TABLE IF NOT EXISTS ticket_messages (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            ticket_id INTEGER NOT NULL,
+            author TEXT NOT NULL,
+            content TEXT NOT NULL,
+            created_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+
+        CREATE TABLE IF NOT EXISTS faq (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            guild_id TEXT NOT NULL,
+            question TEXT NOT NULL,
+            answer TEXT NOT NULL,
+            embedding BLOB NOT NULL,
+            created_by TEXT NOT NULL,
+            created_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+
+        CREATE TABLE IF NOT EXISTS knowledge_chunks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            guild_id TEXT NOT NULL,
+            source TEXT NOT NULL,
+            content TEXT NOT NULL,
+            embedding BLOB NOT NULL,
+            created_by TEXT NOT NULL,
+            created_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+        CREATE INDEX IF NOT EXISTS idx_knowledge_chunks_guild ON knowledge_chunks(guild_id, source);
+
+        CREATE TABLE IF NOT EXISTS persona_schedule (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            persona TEXT NOT NULL COLLATE NOCASE,
+            starts TEXT,
+            ends TEXT,
+            created_by TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS memories (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            channel_id TEXT NOT NULL,
+            prompt TEXT NOT NULL,
+            reply TEXT NOT NULL,
+            saved_by TEXT NOT NULL,
+            created_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+
+        CREATE TABLE IF NOT EXISTS known_guilds (
+            guild_id TEXT PRIMARY KEY,
+            joined_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+
+        -- A Discord user's identities in other games, e.g. ('123', 'wow', 'Pyuul')
+        CREATE TABLE IF NOT EXISTS linked_accounts (
+            user_id TEXT NOT NULL,
+            game TEXT NOT NULL,
+            account TEXT NOT NULL COLLATE NOCASE,
+            linked_at INTEGER NOT NULL DEFAULT (unixepoch()),
+            PRIMARY KEY (user_id, game, account)
+        );
+
+        -- League of Legends players tracked by !lol add; solo_rank is the
+        -- last solo queue tier and division seen, e.g. 'GOLD II'
+        CREATE TABLE IF NOT EXISTS riot_accounts (
+            puuid TEXT PRIMARY KEY,
+            riot_id TEXT NOT NULL COLLATE NOCASE,
+            added_by TEXT NOT NULL,
+            added_at INTEGER NOT NULL DEFAULT (unixepoch()),
+            solo_rank TEXT
+        );
+
+        -- Characters officers exempted from insults with !protect
+        CREATE TABLE IF NOT EXISTS protected_characters (
+            name TEXT PRIMARY KEY COLLATE NOCASE,
+            protected_by TEXT NOT NULL,
+            protected_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+
+        -- Named tallies kept with !counter, per guild
+        CREATE TABLE IF NOT EXISTS counters (
+            guild_id TEXT NOT NULL,
+            name TEXT NOT NULL COLLATE NOCASE,
+            count INTEGER NOT NULL DEFAULT 0,
+            created_by TEXT NOT NULL,
+            created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+            PRIMARY KEY (guild_id, name)
+        );
+
+        -- Saved text, links and images recalled with !tag <name>, per guild
+        CREATE TABLE IF NOT EXISTS tags (
+            guild_id TEXT NOT NULL,
+            name TEXT NOT NULL COLLATE NOCASE,
+            content TEXT NOT NULL,
+            owner TEXT NOT NULL,
+            created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+            PRIMARY KEY (guild_id, name)
+        );
+
+        -- !todo items, per channel and user; due_at and reminded_at are Unix seconds
+        CREATE TABLE IF NOT EXISTS todos (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            channel_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            text TEXT NOT NULL,
+            due_at INTEGER,
+            reminded_at INTEGER,
+            done_at INTEGER,
+            created_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+        CREATE INDEX IF NOT EXISTS idx_todos_channel ON todos(channel_id, user_id);
+
+        -- Named copies of a conversation history, saved and restored with !checkpoint
+        CREATE TABLE IF NOT EXISTS checkpoints (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            history_key TEXT NOT NULL,
+            name TEXT NOT NULL COLLATE NOCASE,
+            saved_by TEXT NOT NULL,
+            saved_at INTEGER NOT NULL DEFAULT (unixepoch()),
+            UNIQUE (history_key, name)
+        );
+
+        CREATE TABLE IF NOT EXISTS checkpoint_messages (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            checkpoint_id INTEGER NOT NULL REFERENCES checkpoints(id) ON DELETE CASCADE,
+            role TEXT NOT NULL,
+            author TEXT,
+            content TEXT NOT NULL,
+            timestamp INTEGER NOT NULL
+        );
+
+        -- Moderation history: the bot's own moderation commands and AutoMod executions
+        CREATE TABLE IF NOT EXISTS moderation_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            guild_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            action TEXT NOT NULL,
+            detail TEXT NOT NULL,
+            created_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+        CREATE INDEX IF NOT EXISTS idx_moderation_log_guild ON moderation_log(guild_id, created_at);
+
+        -- Voice channels made for someone who joined the join-to-create hub,
+        -- and who is in them, so empty ones are deleted even across restarts
+        CREATE TABLE IF NOT EXISTS temp_channels (
+            channel_id TEXT PRIMARY KEY,
+            guild_id TEXT NOT NULL,
+            owner_id TEXT NOT NULL,
+            created_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+
+        CREATE TABLE IF NOT EXISTS temp_channel_members (
+            channel_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            PRIMARY KEY (channel_id, user_id)
+        );
+
+        -- Daily windows a channel is locked or slowed, and while one is on,
+        -- what to put back: the slowmode and @everyone overwrite before it
+        CREATE TABLE IF NOT EXISTS quiet_hours (
+            channel_id TEXT PRIMARY KEY,
+            guild_id TEXT NOT NULL,
+            start_minute INTEGER NOT NULL,
+            end_minute INTEGER NOT NULL,
+            slowmode INTEGER,
+            set_by TEXT NOT NULL,
+            active INTEGER NOT NULL DEFAULT 0,
+            saved_slowmode INTEGER,
+            locked INTEGER NOT NULL DEFAULT 0,
+            saved_allow INTEGER,
+            saved_deny INTEGER
+        );
+
+        -- Each guild invite's use count when last seen, to tell which one a
+        -- new member used, and who joined through which invite
+        CREATE TABLE IF NOT EXISTS invite_uses (
+            guild_id TEXT NOT NULL,
+            code TEXT NOT NULL,
+            inviter_id TEXT,
+            uses INTEGER NOT NULL,
+            PRIMARY KEY (guild_id, code)
+        );
+
+        CREATE TABLE IF NOT EXISTS invite_joins (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            guild_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            code TEXT,
+            inviter_id TEXT,
+            joined_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+        CREATE INDEX IF NOT EXISTS idx_invite_joins_guild ON invite_joins(guild_id, inviter_id);
+
+        -- Old messages from listening channels, moved out to compressed files
+        CREATE TABLE IF NOT EXISTS message_archives (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            channel_id TEXT NOT NULL,
+            path TEXT NOT NULL,
+            first_timestamp INTEGER NOT NULL,
+            last_timestamp INTEGER NOT NULL,
+            message_count INTEGER NOT NULL,
+            created_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+        CREATE INDEX IF NOT EXISTS idx_message_archives_channel ON message_archives(channel_id);
+
+        CREATE TABLE IF NOT EXISTS attunement_steps (
+            character TEXT NOT NULL COLLATE NOCASE,
+            raid TEXT NOT NULL,
+            step INTEGER NOT NULL,
+            done_by TEXT NOT NULL,
+            done_at INTEGER NOT NULL DEFAULT (unixepoch()),
+            PRIMARY KEY (character, raid, step)
+        );
+
+        -- class is 'all' for items every character gets
+        CREATE TABLE IF NOT EXISTS checklist_items (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            class TEXT NOT NULL COLLATE NOCASE,
+            item TEXT NOT NULL COLLATE NOCASE,
+            UNIQUE (class, item)
+        );
+
+        CREATE TABLE IF NOT EXISTS checklist_ticks (
+            character TEXT NOT NULL COLLATE NOCASE,
+            item TEXT NOT NULL COLLATE NOCASE,
+            done_at INTEGER NOT NULL DEFAULT (unixepoch()),
+            PRIMARY KEY (character, item)
+        );
+
+        -- guild_id is '' for a flag's global setting
+        CREATE TABLE IF NOT EXISTS feature_flags (
+            name TEXT NOT NULL,
+            guild_id TEXT NOT NULL DEFAULT '',
+            enabled INTEGER NOT NULL DEFAULT 0,
+            rollout_percent INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (name, guild_id)
+        );
+
+        -- guild_id is '' for a script saved before scripts belonged to a
+        -- guild, until claim_scripts gives it one
+        CREATE TABLE IF NOT EXISTS scripts (
+            guild_id TEXT NOT NULL DEFAULT '',
+            name TEXT NOT NULL COLLATE NOCASE,
+            trigger TEXT NOT NULL,
+            pattern TEXT NOT NULL,
+            channel_id TEXT NOT NULL,
+            source TEXT NOT NULL,
+            created_by TEXT NOT NULL,
+            last_run INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (guild_id, name)
+        );
+
+        CREATE TABLE IF NOT EXISTS script_data (
+            guild_id TEXT NOT NULL DEFAULT '',
+            script TEXT NOT NULL COLLATE NOCASE,
+            key TEXT NOT NULL,
+            value TEXT NOT NULL,
+            PRIMARY KEY (guild_id, script, key)
         );",
     )?;
 
+    // Columns added after the first release
+    add_column_if_missing(conn, "tracked_characters", "game_version", "TEXT")?;
+    add_column_if_missing(conn, "messages", "author", "TEXT")?;
+    add_column_if_missing(conn, "messages", "message_id", "TEXT")?;
+    add_column_if_missing(conn, "feedback", "history_key", "TEXT")?;
+    add_column_if_missing(conn, "feedback", "user_id", "TEXT")?;
+    add_column_if_missing(conn, "feedback", "prompt_message_id", "TEXT")?;
+    add_column_if_missing(conn, "messages", "correlation_id", "TEXT")?;
+    add_column_if_missing(conn, "feedback", "correlation_id", "TEXT")?;
+    add_column_if_missing(conn, "messages", "author_id", "TEXT")?;
+    add_column_if_missing(conn, "memories", "asked_by", "TEXT")?;
+    add_column_if_missing(conn, "scripts", "guild_id", "TEXT")?;
+    migrate_guild_config(conn)?;
+    migrate_script_guilds(conn)?;
+    migrate_confession_channel(conn)?;
+    // A Discord message is stored at most once per history, however often the
+    // gateway delivers it
+    conn.execute_batch(
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_messages_discord_id
+            ON messages (channel_id, message_id);
+         CREATE INDEX IF NOT EXISTS idx_messages_message_id ON messages (message_id);",
+    )?;
+
     // Seed default system prompt if not present
     conn.execute(
         "INSERT OR IGNORE INTO config (key, value) VALUES ('system_prompt', ?1)",
         params![DEFAULT_SYSTEM_PROMPT],
     )?;
 
-    Ok(())
+    use_cipher(conn, None)
 }
 
-pub fn get_config(conn: &Connection, key: &str) -> Result<Option<String>> {
-    let mut stmt = conn.prepare("SELECT value FROM config WHERE key = ?1")?;
-    let mut rows = stmt.query(params![key])?;
-    match rows.next()? {
-        Some(row) => Ok(Some(row.get(0)?)),
-        None => Ok(None),
-    }
+/// Registers the `encrypt()` and `decrypt()` SQL functions that chat history,
+/// memories and rated exchanges are written and read through, encrypting with `cipher` or,
+/// without one, storing plaintext. Values written before a cipher was set
+/// decrypt to themselves.
+pub fn use_cipher(conn: &Connection, cipher: Option<Cipher>) -> Result<()> {
+    let cipher = cipher.map(Arc::new);
+    let encrypting = cipher.clone();
+    conn.create_scalar_function("encrypt", 1, FunctionFlags::SQLITE_UTF8, move |ctx| {
+        let value: Option<String> = ctx.get(0)?;
+        Ok(match (&encrypting, value) {
+            (Some(cipher), Some(value)) => Some(cipher.encrypt(&value)),
+            (_, value) => value,
+        })
+    })?;
+    conn.create_scalar_function(
+        "decrypt",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        move |ctx| {
+            let value: Option<String> = ctx.get(0)?;
+            match (&cipher, value) {
+                (Some(cipher), Some(value)) => cipher
+                    .decrypt(&value)
+                    .map(Some)
+                    .map_err(|e| rusqlite::Error::UserFunctionError(e.into())),
+                (_, value) => Ok(value),
+            }
+        },
+    )
 }
 
-pub fn set_config(conn: &Connection, key: &str, value: &str) -> Result<()> {
-    conn.execute(
-        "INSERT INTO config (key, value) VALUES (?1, ?2)
-         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
-        params![key, value],
+/// Encrypts chat history, checkpoints, memories and rated exchanges stored before encryption
+/// was turned on. Returns how many rows it changed.
+pub fn encrypt_plaintext(conn: &Connection) -> Result<usize> {
+    let like = format!("{}%", encryption::PREFIX);
+    let tx = conn.unchecked_transaction()?;
+    let mut changed = tx.execute("UPDATE messages SET content = encrypt(content) WHERE content NOT LIKE ?1", params![like])?;
+    changed += tx.execute(
+        "UPDATE checkpoint_messages SET content = encrypt(content) WHERE content NOT LIKE ?1",
+        params![like],
     )?;
-    Ok(())
+    changed += tx.execute(
+        "UPDATE memories SET prompt = encrypt(prompt), reply = encrypt(reply) WHERE prompt NOT LIKE ?1",
+        params![like],
+    )?;
+    changed += tx.execute(
+        "UPDATE feedback SET prompt = encrypt(prompt), reply = encrypt(reply) WHERE prompt NOT LIKE ?1",
+        params![like],
+    )?;
+    tx.commit()?;
+    Ok(changed)
 }
 
-pub fn get_context_mode(conn: &Connection, channel_id: &str) -> Result<String> {
-    let key = format!("context_mode:{}", channel_id);
-    Ok(get_config(conn, &key)?.unwrap_or_else(|| "channel".to_string()))
+/// Whether anything is stored encrypted, which can't be read without the key.
+pub fn has_encrypted_rows(conn: &Connection) -> Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM messages WHERE content LIKE ?1)
+             OR EXISTS(SELECT 1 FROM checkpoint_messages WHERE content LIKE ?1)
+             OR EXISTS(SELECT 1 FROM memories WHERE prompt LIKE ?1)
+             OR EXISTS(SELECT 1 FROM feedback WHERE prompt LIKE ?1)",
+        params![format!("{}%", encryption::PREFIX)],
+        |row| row.get(0),
+    )
 }
 
-pub fn set_context_mode(conn: &Connection, channel_id: &str, mode: &str) -> Result<()> {
-    let key = format!("context_mode:{}", channel_id);
-    set_config(conn, &key, mode)
+/// The database file's size in bytes.
+pub fn database_size(conn: &Connection) -> Result<i64> {
+    conn.query_row(
+        "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+        [],
+        |row| row.get(0),
+    )
 }
 
-pub fn clear_messages(conn: &Connection, channel_id: &str) -> Result<usize> {
-    conn.execute(
-        "DELETE FROM messages WHERE channel_id = ?1",
-        params![channel_id],
-    )
+/// Returns free pages to the filesystem and refreshes the query planner's
+/// statistics. A database created before incremental vacuum was turned on is
+/// converted with one full `VACUUM`.
+pub fn compact(conn: &Connection) -> Result<()> {
+    let mode: i64 = conn.query_row("PRAGMA auto_vacuum", [], |row| row.get(0))?;
+    // 2 is INCREMENTAL
+    if mode != 2 {
+        conn.execute_batch("PRAGMA auto_vacuum = INCREMENTAL; VACUUM;")?;
+    }
+    conn.execute_batch("PRAGMA incremental_vacuum; PRAGMA optimize;")
 }
 
-pub fn store_message(conn: &Connection, channel_id: &str, role: &str, content: &str) -> Result<()> {
-    conn.execute(
-        "INSERT INTO messages (channel_id, role, content) VALUES (?1, ?2, ?3)",
-        params![channel_id, role, content],
-    )?;
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, decl: &str) -> Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>>>()?
+        .iter()
+        .any(|name| name == column);
+    if !exists {
+        conn.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, decl))?;
+    }
     Ok(())
 }
 
-pub struct StoredMessage {
-    pub role: String,
-    pub content: String,
+/// Rebuilds a `config` table from before it had a guild column, moving
+/// `key:guild_id` rows for [`LEGACY_GUILD_KEYS`] under their guild.
+fn migrate_guild_config(conn: &Connection) -> Result<()> {
+    let mut stmt = conn.prepare("PRAGMA table_info(config)")?;
+    let has_guild = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>>>()?
+        .iter()
+        .any(|name| name == "guild_id");
+    if has_guild {
+        return Ok(());
+    }
+    let tx = conn.unchecked_transaction()?;
+    let rows = tx
+        .prepare("SELECT key, value FROM config")?
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<Vec<_>>>()?;
+    tx.execute_batch(
+        "DROP TABLE config;
+         CREATE TABLE config (
+            guild_id TEXT NOT NULL DEFAULT '',
+            key TEXT NOT NULL,
+            value TEXT NOT NULL,
+            PRIMARY KEY (guild_id, key)
+         );",
+    )?;
+    for (key, value) in rows {
+        let (guild_id, key) = match key.split_once(':') {
+            Some((name, guild_id)) if LEGACY_GUILD_KEYS.contains(&name) => (guild_id.to_string(), name.to_string()),
+            _ => (String::new(), key),
+        };
+        tx.execute(
+            "INSERT OR REPLACE INTO config (guild_id, key, value) VALUES (?1, ?2, ?3)",
+            params![guild_id, key, value],
+        )?;
+    }
+    tx.commit()
 }
 
-pub fn get_recent_messages(
-    conn: &Connection,
-    channel_id: &str,
-    limit: usize,
-) -> Result<Vec<StoredMessage>> {
-    let mut stmt = conn.prepare(
-        "SELECT role, content FROM messages
-         WHERE channel_id = ?1
-         ORDER BY timestamp DESC, id DESC
-         LIMIT ?2",
+/// Rebuilds the `scripts` and `script_data` tables from before they were keyed
+/// by guild, filing each script's data under the guild that has the script.
+fn migrate_script_guilds(conn: &Connection) -> Result<()> {
+    let mut stmt = conn.prepare("PRAGMA table_info(script_data)")?;
+    let has_guild = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>>>()?
+        .iter()
+        .any(|name| name == "guild_id");
+    if has_guild {
+        return Ok(());
+    }
+    let tx = conn.unchecked_transaction()?;
+    tx.execute_batch(
+        "ALTER TABLE scripts RENAME TO scripts_old;
+         ALTER TABLE script_data RENAME TO script_data_old;
+         CREATE TABLE scripts (
+            guild_id TEXT NOT NULL DEFAULT '',
+            name TEXT NOT NULL COLLATE NOCASE,
+            trigger TEXT NOT NULL,
+            pattern TEXT NOT NULL,
+            channel_id TEXT NOT NULL,
+            source TEXT NOT NULL,
+            created_by TEXT NOT NULL,
+            last_run INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (guild_id, name)
+         );
+         CREATE TABLE script_data (
+            guild_id TEXT NOT NULL DEFAULT '',
+            script TEXT NOT NULL COLLATE NOCASE,
+            key TEXT NOT NULL,
+            value TEXT NOT NULL,
+            PRIMARY KEY (guild_id, script, key)
+         );
+         INSERT INTO scripts (guild_id, name, trigger, pattern, channel_id, source, created_by, last_run)
+             SELECT COALESCE(guild_id, ''), name, trigger, pattern, channel_id, source, created_by, last_run
+             FROM scripts_old;
+         INSERT INTO script_data (guild_id, script, key, value)
+             SELECT COALESCE(s.guild_id, ''), d.script, d.key, d.value
+             FROM script_data_old d LEFT JOIN scripts_old s ON s.name = d.script;
+         DROP TABLE scripts_old;
+         DROP TABLE script_data_old;",
     )?;
-    let mut messages: Vec<StoredMessage> = stmt
-        .query_map(params![channel_id, limit as i64], |row| {
-            Ok(StoredMessage {
-                role: row.get(0)?,
-                content: row.get(1)?,
-            })
-        })?
+    tx.commit()
+}
+
+/// Moves the confession channel, once a single bot-wide setting naming its
+/// guild, to that guild's own settings.
+fn migrate_confession_channel(conn: &Connection) -> Result<()> {
+    let guild = get_config(conn, None, "confession_guild")?;
+    let channel = get_config(conn, None, "confession_channel")?;
+    let tx = conn.unchecked_transaction()?;
+    if let (Some(guild), Some(channel)) = (guild, channel) {
+        set_config(&tx, Some(&guild), "confession_channel", &channel)?;
+    }
+    delete_config(&tx, None, "confession_guild")?;
+    delete_config(&tx, None, "confession_channel")?;
+    tx.commit()
+}
+
+/// Every guild that has set `key` itself, with its value.
+pub fn get_guilds_with_config(conn: &Connection, key: &str) -> Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare("SELECT guild_id, value FROM config WHERE key = ?1 AND guild_id != '' ORDER BY guild_id")?;
+    let entries = stmt
+        .query_map(params![key], |row| Ok((row.get(0)?, row.get(1)?)))?
         .collect::<Result<Vec<_>>>()?;
+    Ok(entries)
+}
 
-    // Reverse so oldest is first (we fetched newest-first for LIMIT)
-    messages.reverse();
-    Ok(messages)
+/// `key` in `guild_id`, falling back to the bot-wide value when the guild
+/// hasn't set its own. `None` reads only the bot-wide value.
+pub fn get_config(conn: &Connection, guild_id: Option<&str>, key: &str) -> Result<Option<String>> {
+    conn.query_row(
+        "SELECT value FROM config WHERE key = ?2 AND guild_id IN ('', ?1)
+         ORDER BY guild_id = '' LIMIT 1",
+        params![guild_id.unwrap_or_default(), key],
+        |row| row.get(0),
+    )
+    .map(Some)
+    .or_else(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => Ok(None),
+        e => Err(e),
+    })
 }
 
-pub fn add_tracked_character(conn: &Connection, name: &str, added_by: &str) -> Result<bool> {
-    let rows = conn.execute(
-        "INSERT OR IGNORE INTO tracked_characters (name, added_by) VALUES (?1, ?2)",
-        params![name, added_by],
+/// Sets `key` for `guild_id` alone, or bot-wide with `None`.
+pub fn set_config(conn: &Connection, guild_id: Option<&str>, key: &str, value: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO config (guild_id, key, value) VALUES (?1, ?2, ?3)
+         ON CONFLICT(guild_id, key) DO UPDATE SET value = excluded.value",
+        params![guild_id.unwrap_or_default(), key, value],
     )?;
-    Ok(rows > 0)
+    Ok(())
 }
 
-pub fn remove_tracked_character(conn: &Connection, name: &str) -> Result<bool> {
+/// The bot-wide settings.
+pub fn get_all_config(conn: &Connection) -> Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare("SELECT key, value FROM config WHERE guild_id = '' ORDER BY key")?;
+    let entries = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(entries)
+}
+
+/// Removes `guild_id`'s own `key`, so it follows the bot-wide value again.
+pub fn delete_config(conn: &Connection, guild_id: Option<&str>, key: &str) -> Result<bool> {
     let rows = conn.execute(
-        "DELETE FROM tracked_characters WHERE name = ?1",
-        params![name],
+        "DELETE FROM config WHERE guild_id = ?1 AND key = ?2",
+        params![guild_id.unwrap_or_default(), key],
     )?;
     Ok(rows > 0)
 }
 
-pub fn get_tracked_characters(conn: &Connection) -> Result<Vec<String>> {
-    let mut stmt = conn.prepare("SELECT name FROM tracked_characters ORDER BY name")?;
-    let names = stmt
-        .query_map([], |row| row.get(0))?
-        .collect::<Result<Vec<String>>>()?;
-    Ok(names)
+/// [`get_config`] in a guild.
+pub fn get_guild_config(conn: &Connection, guild_id: &str, key: &str) -> Result<Option<String>> {
+    get_config(conn, Some(guild_id), key)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+pub fn set_guild_config(conn: &Connection, guild_id: &str, key: &str, value: &str) -> Result<()> {
+    set_config(conn, Some(guild_id), key, value)
+}
 
-    fn setup() -> Connection {
-        let conn = Connection::open_in_memory().unwrap();
-        init(&conn).unwrap();
-        conn
+pub fn delete_guild_config(conn: &Connection, guild_id: &str, key: &str) -> Result<bool> {
+    delete_config(conn, Some(guild_id), key)
+}
+
+/// Sets the system prompt in `guild_id`, or bot-wide with `None`. In a guild
+/// it replaces the persona picked for it, which would otherwise win.
+pub fn set_system_prompt(conn: &Connection, guild_id: Option<&str>, prompt: &str) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    set_config(&tx, guild_id, "system_prompt", prompt)?;
+    if let Some(guild_id) = guild_id {
+        delete_config(&tx, Some(guild_id), "persona")?;
     }
+    tx.commit()
+}
 
-    #[test]
-    fn test_init_creates_schema() {
-        let conn = setup();
-        // Verify tables exist by querying them
-        conn.prepare("SELECT * FROM config").unwrap();
-        conn.prepare("SELECT * FROM messages").unwrap();
+/// The response cap in `guild_id`: its own if it set one, else the bot-wide cap.
+pub fn get_response_cap(conn: &Connection, guild_id: Option<&str>) -> u32 {
+    get_config(conn, guild_id, "response_cap")
+        .ok()
+        .flatten()
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(DEFAULT_RESPONSE_CAP)
+}
+
+/// Records that the bot is in `guild_id`. Returns true the first time.
+pub fn mark_guild_known(conn: &Connection, guild_id: &str) -> Result<bool> {
+    let rows = conn.execute("INSERT OR IGNORE INTO known_guilds (guild_id) VALUES (?1)", params![guild_id])?;
+    Ok(rows > 0)
+}
+
+pub fn get_context_mode(conn: &Connection, channel_id: &str) -> Result<String> {
+    let key = format!("context_mode:{}", channel_id);
+    Ok(get_config(conn, None, &key)?.unwrap_or_else(|| "channel".to_string()))
+}
+
+pub fn set_context_mode(conn: &Connection, channel_id: &str, mode: &str) -> Result<()> {
+    let key = format!("context_mode:{}", channel_id);
+    set_config(conn, None, &key, mode)
+}
+
+/// Personality intensity (1-10) set for a channel with `!intensity`, if any.
+pub fn get_intensity(conn: &Connection, channel_id: &str) -> Result<Option<u8>> {
+    let key = format!("intensity:{}", channel_id);
+    Ok(get_config(conn, None, &key)?.and_then(|v| v.parse().ok()))
+}
+
+/// Sets a channel's personality intensity, or clears it with `None`.
+pub fn set_intensity(conn: &Connection, channel_id: &str, level: Option<u8>) -> Result<()> {
+    let key = format!("intensity:{}", channel_id);
+    match level {
+        Some(level) => set_config(conn, None, &key, &level.to_string()),
+        None => delete_config(conn, None, &key).map(|_| ()),
     }
+}
 
-    #[test]
-    fn test_default_system_prompt() {
-        let conn = setup();
-        let prompt = get_config(&conn, "system_prompt").unwrap().unwrap();
-        assert_eq!(prompt, DEFAULT_SYSTEM_PROMPT);
+/// The reply style `user_id` picked with `!style`, if any.
+pub fn get_reply_style(conn: &Connection, user_id: &str) -> Result<Option<String>> {
+    get_config(conn, None, &format!("reply_style:{}", user_id))
+}
+
+/// Sets a user's reply style, or clears it with `None`.
+pub fn set_reply_style(conn: &Connection, user_id: &str, style: Option<&str>) -> Result<()> {
+    let key = format!("reply_style:{}", user_id);
+    match style {
+        Some(style) => set_config(conn, None, &key, style),
+        None => delete_config(conn, None, &key).map(|_| ()),
     }
+}
 
-    #[test]
-    fn test_set_and_get_config() {
-        let conn = setup();
-        set_config(&conn, "test_key", "test_value").unwrap();
-        assert_eq!(
-            get_config(&conn, "test_key").unwrap(),
-            Some("test_value".to_string())
-        );
+/// Whether `!safemode` swaps the persona for a neutral one in a channel.
+pub fn is_safe_mode(conn: &Connection, channel_id: &str) -> Result<bool> {
+    let key = format!("safe_mode:{}", channel_id);
+    Ok(get_config(conn, None, &key)?.as_deref() == Some("true"))
+}
 
-        // Overwrite
-        set_config(&conn, "test_key", "new_value").unwrap();
-        assert_eq!(
-            get_config(&conn, "test_key").unwrap(),
-            Some("new_value".to_string())
-        );
+pub fn set_safe_mode(conn: &Connection, channel_id: &str, enabled: bool) -> Result<()> {
+    let key = format!("safe_mode:{}", channel_id);
+    if enabled {
+        set_config(conn, None, &key, "true")
+    } else {
+        delete_config(conn, None, &key).map(|_| ())
     }
+}
 
-    #[test]
-    fn test_store_and_retrieve_messages() {
-        let conn = setup();
-        store_message(&conn, "chan1", "user", "hello").unwrap();
-        store_message(&conn, "chan1", "assistant", "hi there").unwrap();
+/// Whether chat requests in a guild get the time and server preamble. On
+/// unless turned off with `!preamble off`.
+pub fn is_preamble_enabled(conn: &Connection, guild_id: &str) -> bool {
+    !matches!(get_config(conn, Some(guild_id), "preamble"), Ok(Some(v)) if v == "off")
+}
+
+pub fn set_preamble_enabled(conn: &Connection, guild_id: &str, enabled: bool) -> Result<()> {
+    set_config(conn, Some(guild_id), "preamble", if enabled { "on" } else { "off" })
+}
+
+/// How many days of `kind` (`snapshots` or `messages`) nightly maintenance
+/// keeps, or `None` to keep everything.
+pub fn get_retention_days(conn: &Connection, kind: &str) -> Result<Option<u32>> {
+    let key = format!("retention:{}", kind);
+    Ok(get_config(conn, None, &key)?.and_then(|v| v.parse().ok()))
+}
+
+pub fn set_retention_days(conn: &Connection, kind: &str, days: Option<u32>) -> Result<()> {
+    let key = format!("retention:{}", kind);
+    match days {
+        Some(days) => set_config(conn, None, &key, &days.to_string()),
+        None => delete_config(conn, None, &key).map(|_| ()),
+    }
+}
+
+/// Whether failed chat and level check requests in a guild are queued and
+/// retried (`!retry on`) instead of just failing.
+pub fn is_retry_enabled(conn: &Connection, guild_id: &str) -> bool {
+    matches!(get_config(conn, Some(guild_id), "retry"), Ok(Some(v)) if v == "true")
+}
+
+pub fn set_retry_enabled(conn: &Connection, guild_id: &str, enabled: bool) -> Result<()> {
+    set_config(conn, Some(guild_id), "retry", if enabled { "true" } else { "false" })
+}
+
+/// Whether LLM output may ping users in a guild. Off unless enabled with
+/// `!mentions allow`.
+pub fn allows_user_mentions(conn: &Connection, guild_id: &str) -> bool {
+    matches!(get_config(conn, Some(guild_id), "allow_mentions"), Ok(Some(v)) if v == "true")
+}
+
+pub fn set_allow_user_mentions(conn: &Connection, guild_id: &str, allow: bool) -> Result<()> {
+    set_config(conn, Some(guild_id), "allow_mentions", if allow { "true" } else { "false" })
+}
+
+/// Whether `!listen` has the bot remember every message in a channel, not just
+/// the ones addressed to it.
+pub fn is_listening(conn: &Connection, channel_id: &str) -> Result<bool> {
+    let key = format!("listen:{}", channel_id);
+    Ok(get_config(conn, None, &key)?.as_deref() == Some("true"))
+}
+
+pub fn set_listening(conn: &Connection, channel_id: &str, enabled: bool) -> Result<()> {
+    let key = format!("listen:{}", channel_id);
+    if enabled {
+        set_config(conn, None, &key, "true")
+    } else {
+        delete_config(conn, None, &key).map(|_| ())
+    }
+}
+
+/// Channels where `!listen` is on.
+pub fn get_listening_channels(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT substr(key, 8) FROM config WHERE key LIKE 'listen:%' AND value = 'true' ORDER BY key")?;
+    let channels = stmt.query_map([], |row| row.get(0))?.collect::<Result<Vec<_>>>()?;
+    Ok(channels)
+}
+
+/// Whether `!helpchannel` has the bot answer questions in a channel unprompted.
+pub fn is_help_channel(conn: &Connection, channel_id: &str) -> Result<bool> {
+    let key = format!("help_channel:{}", channel_id);
+    Ok(get_config(conn, None, &key)?.is_some())
+}
+
+pub fn set_help_channel(conn: &Connection, channel_id: &str, enabled: bool) -> Result<()> {
+    let key = format!("help_channel:{}", channel_id);
+    if enabled {
+        // Holds when the bot last answered, for the rate limit
+        set_config(conn, None, &key, "0")
+    } else {
+        delete_config(conn, None, &key).map(|_| ())
+    }
+}
+
+/// When the bot last answered a question unprompted in a help channel (unix seconds).
+pub fn help_channel_answered_at(conn: &Connection, channel_id: &str) -> Result<i64> {
+    let key = format!("help_channel:{}", channel_id);
+    Ok(get_config(conn, None, &key)?.and_then(|v| v.parse().ok()).unwrap_or(0))
+}
+
+pub fn set_help_channel_answered_at(conn: &Connection, channel_id: &str, at: i64) -> Result<()> {
+    let key = format!("help_channel:{}", channel_id);
+    set_config(conn, None, &key, &at.to_string())
+}
+
+/// Whether a user asked with `!listen optout` not to be recorded by listening channels.
+pub fn is_listen_opted_out(conn: &Connection, user_id: &str) -> Result<bool> {
+    let key = format!("listen_optout:{}", user_id);
+    Ok(get_config(conn, None, &key)?.as_deref() == Some("true"))
+}
+
+pub fn set_listen_opt_out(conn: &Connection, user_id: &str, opted_out: bool) -> Result<()> {
+    let key = format!("listen_optout:{}", user_id);
+    if opted_out {
+        set_config(conn, None, &key, "true")
+    } else {
+        delete_config(conn, None, &key).map(|_| ())
+    }
+}
+
+/// Whether a user asked with `!insults optout` not to have their characters roasted.
+pub fn is_insult_opted_out(conn: &Connection, user_id: &str) -> Result<bool> {
+    let key = format!("insult_optout:{}", user_id);
+    Ok(get_config(conn, None, &key)?.as_deref() == Some("true"))
+}
+
+pub fn set_insult_opt_out(conn: &Connection, user_id: &str, opted_out: bool) -> Result<()> {
+    let key = format!("insult_optout:{}", user_id);
+    if opted_out {
+        set_config(conn, None, &key, "true")
+    } else {
+        delete_config(conn, None, &key).map(|_| ())
+    }
+}
+
+/// Exempts a character from insults (`by` is the officer), or with `None`
+/// lifts the exemption. Returns whether anything changed.
+pub fn set_character_protected(conn: &Connection, name: &str, by: Option<&str>) -> Result<bool> {
+    let rows = match by {
+        Some(by) => conn.execute(
+            "INSERT OR IGNORE INTO protected_characters (name, protected_by) VALUES (?1, ?2)",
+            params![name, by],
+        )?,
+        None => conn.execute("DELETE FROM protected_characters WHERE name = ?1", params![name])?,
+    };
+    Ok(rows > 0)
+}
+
+pub fn is_character_protected(conn: &Connection, name: &str) -> Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM protected_characters WHERE name = ?1)",
+        params![name],
+        |row| row.get(0),
+    )
+}
+
+pub fn get_protected_characters(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT name FROM protected_characters ORDER BY name")?;
+    let names = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(names)
+}
+
+pub fn create_counter(conn: &Connection, guild_id: &str, name: &str, created_by: &str) -> Result<bool> {
+    let rows = conn.execute(
+        "INSERT OR IGNORE INTO counters (guild_id, name, created_by) VALUES (?1, ?2, ?3)",
+        params![guild_id, name, created_by],
+    )?;
+    Ok(rows > 0)
+}
+
+/// Adds `amount` (which may be negative) to a counter, returning the new
+/// count, or `None` if there's no such counter.
+pub fn increment_counter(conn: &Connection, guild_id: &str, name: &str, amount: i64) -> Result<Option<i64>> {
+    conn.query_row(
+        "UPDATE counters SET count = count + ?3 WHERE guild_id = ?1 AND name = ?2 RETURNING count",
+        params![guild_id, name, amount],
+        |row| row.get(0).map(Some),
+    )
+    .or_else(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => Ok(None),
+        e => Err(e),
+    })
+}
+
+pub fn reset_counter(conn: &Connection, guild_id: &str, name: &str) -> Result<bool> {
+    let rows = conn.execute(
+        "UPDATE counters SET count = 0 WHERE guild_id = ?1 AND name = ?2",
+        params![guild_id, name],
+    )?;
+    Ok(rows > 0)
+}
+
+pub fn delete_counter(conn: &Connection, guild_id: &str, name: &str) -> Result<bool> {
+    let rows = conn.execute(
+        "DELETE FROM counters WHERE guild_id = ?1 AND name = ?2",
+        params![guild_id, name],
+    )?;
+    Ok(rows > 0)
+}
+
+/// `(name, count)` for a guild's counters, by name.
+pub fn get_counters(conn: &Connection, guild_id: &str) -> Result<Vec<(String, i64)>> {
+    let mut stmt = conn.prepare("SELECT name, count FROM counters WHERE guild_id = ?1 ORDER BY name")?;
+    let counters = stmt
+        .query_map(params![guild_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(counters)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Tag {
+    pub name: String,
+    pub content: String,
+    pub owner: String,
+}
+
+/// Saves a new tag. Returns false if the guild already has one by that name.
+pub fn add_tag(conn: &Connection, guild_id: &str, name: &str, content: &str, owner: &str) -> Result<bool> {
+    let rows = conn.execute(
+        "INSERT OR IGNORE INTO tags (guild_id, name, content, owner) VALUES (?1, ?2, ?3, ?4)",
+        params![guild_id, name, content, owner],
+    )?;
+    Ok(rows > 0)
+}
+
+pub fn get_tag(conn: &Connection, guild_id: &str, name: &str) -> Result<Option<Tag>> {
+    let mut stmt = conn.prepare("SELECT name, content, owner FROM tags WHERE guild_id = ?1 AND name = ?2")?;
+    let mut rows = stmt.query(params![guild_id, name])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(Tag {
+            name: row.get(0)?,
+            content: row.get(1)?,
+            owner: row.get(2)?,
+        })),
+        None => Ok(None),
+    }
+}
+
+pub fn update_tag(conn: &Connection, guild_id: &str, name: &str, content: &str) -> Result<bool> {
+    let rows = conn.execute(
+        "UPDATE tags SET content = ?3 WHERE guild_id = ?1 AND name = ?2",
+        params![guild_id, name, content],
+    )?;
+    Ok(rows > 0)
+}
+
+pub fn remove_tag(conn: &Connection, guild_id: &str, name: &str) -> Result<bool> {
+    let rows = conn.execute("DELETE FROM tags WHERE guild_id = ?1 AND name = ?2", params![guild_id, name])?;
+    Ok(rows > 0)
+}
+
+pub fn get_tag_names(conn: &Connection, guild_id: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT name FROM tags WHERE guild_id = ?1 ORDER BY name")?;
+    let names = stmt
+        .query_map(params![guild_id], |row| row.get(0))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(names)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Todo {
+    pub id: i64,
+    pub channel_id: String,
+    pub user_id: String,
+    pub text: String,
+    pub due_at: Option<i64>,
+}
+
+fn todo_from_row(row: &rusqlite::Row) -> Result<Todo> {
+    Ok(Todo {
+        id: row.get(0)?,
+        channel_id: row.get(1)?,
+        user_id: row.get(2)?,
+        text: row.get(3)?,
+        due_at: row.get(4)?,
+    })
+}
+
+/// Adds a todo, returning its ID.
+pub fn add_todo(conn: &Connection, channel_id: &str, user_id: &str, text: &str, due_at: Option<i64>) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO todos (channel_id, user_id, text, due_at) VALUES (?1, ?2, ?3, ?4)",
+        params![channel_id, user_id, text, due_at],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Open todos in `channel_id`, only `user_id`'s if given, soonest due first
+/// and undated ones last.
+pub fn get_open_todos(conn: &Connection, channel_id: &str, user_id: Option<&str>) -> Result<Vec<Todo>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, channel_id, user_id, text, due_at FROM todos
+         WHERE channel_id = ?1 AND (?2 IS NULL OR user_id = ?2) AND done_at IS NULL
+         ORDER BY due_at IS NULL, due_at, id",
+    )?;
+    let todos = stmt
+        .query_map(params![channel_id, user_id], todo_from_row)?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(todos)
+}
+
+/// Marks one of `user_id`'s open todos in `channel_id` done. Returns false
+/// if there's no such todo.
+pub fn complete_todo(conn: &Connection, channel_id: &str, user_id: &str, id: i64) -> Result<bool> {
+    let rows = conn.execute(
+        "UPDATE todos SET done_at = unixepoch()
+         WHERE id = ?1 AND channel_id = ?2 AND user_id = ?3 AND done_at IS NULL",
+        params![id, channel_id, user_id],
+    )?;
+    Ok(rows > 0)
+}
+
+/// Open todos due by `now` that haven't had their reminder yet.
+pub fn due_todos(conn: &Connection, now: i64) -> Result<Vec<Todo>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, channel_id, user_id, text, due_at FROM todos
+         WHERE due_at <= ?1 AND reminded_at IS NULL AND done_at IS NULL ORDER BY due_at",
+    )?;
+    let todos = stmt
+        .query_map(params![now], todo_from_row)?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(todos)
+}
+
+pub fn mark_todo_reminded(conn: &Connection, id: i64, now: i64) -> Result<()> {
+    conn.execute("UPDATE todos SET reminded_at = ?2 WHERE id = ?1", params![id, now])?;
+    Ok(())
+}
+
+pub struct QuietHours {
+    pub channel_id: String,
+    pub guild_id: String,
+    pub start_minute: u32,
+    pub end_minute: u32,
+    /// Slowmode seconds during the window, or `None` to lock the channel.
+    pub slowmode: Option<u16>,
+    pub active: bool,
+    /// The channel's slowmode before the window, if it was changed.
+    pub saved_slowmode: Option<u16>,
+    /// Whether the window locked the channel, and the @everyone overwrite's
+    /// allow and deny bits before it (`None` if there was none).
+    pub locked: bool,
+    pub saved_overwrite: Option<(u64, u64)>,
+}
+
+const QUIET_HOURS_COLUMNS: &str = "channel_id, guild_id, start_minute, end_minute, slowmode, active, \
+     saved_slowmode, locked, saved_allow, saved_deny";
+
+fn quiet_hours_from_row(row: &rusqlite::Row) -> Result<QuietHours> {
+    let saved_allow: Option<i64> = row.get(8)?;
+    let saved_deny: Option<i64> = row.get(9)?;
+    Ok(QuietHours {
+        channel_id: row.get(0)?,
+        guild_id: row.get(1)?,
+        start_minute: row.get(2)?,
+        end_minute: row.get(3)?,
+        slowmode: row.get(4)?,
+        active: row.get(5)?,
+        saved_slowmode: row.get(6)?,
+        locked: row.get(7)?,
+        saved_overwrite: saved_allow.zip(saved_deny).map(|(allow, deny)| (allow as u64, deny as u64)),
+    })
+}
+
+/// Sets `channel_id`'s quiet hours, keeping what to restore if a window is
+/// already on.
+pub fn set_quiet_hours(
+    conn: &Connection,
+    channel_id: &str,
+    guild_id: &str,
+    start_minute: u32,
+    end_minute: u32,
+    slowmode: Option<u16>,
+    set_by: &str,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO quiet_hours (channel_id, guild_id, start_minute, end_minute, slowmode, set_by)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT(channel_id) DO UPDATE SET start_minute = ?3, end_minute = ?4, slowmode = ?5, set_by = ?6",
+        params![channel_id, guild_id, start_minute, end_minute, slowmode, set_by],
+    )?;
+    Ok(())
+}
+
+/// Removes `channel_id`'s quiet hours, returning them so an active window
+/// can be undone.
+pub fn remove_quiet_hours(conn: &Connection, channel_id: &str) -> Result<Option<QuietHours>> {
+    let quiet_hours = conn
+        .query_row(
+            &format!("SELECT {} FROM quiet_hours WHERE channel_id = ?1", QUIET_HOURS_COLUMNS),
+            params![channel_id],
+            quiet_hours_from_row,
+        )
+        .map(Some)
+        .or_else(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => Ok(None),
+            e => Err(e),
+        })?;
+    conn.execute("DELETE FROM quiet_hours WHERE channel_id = ?1", params![channel_id])?;
+    Ok(quiet_hours)
+}
+
+/// Quiet hours in `guild_id`, or in every guild for `None`.
+pub fn get_quiet_hours(conn: &Connection, guild_id: Option<&str>) -> Result<Vec<QuietHours>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM quiet_hours WHERE ?1 IS NULL OR guild_id = ?1 ORDER BY start_minute",
+        QUIET_HOURS_COLUMNS
+    ))?;
+    let quiet_hours = stmt
+        .query_map(params![guild_id], quiet_hours_from_row)?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(quiet_hours)
+}
+
+/// Records that a window started, with what it changed.
+pub fn start_quiet_hours(
+    conn: &Connection,
+    channel_id: &str,
+    saved_slowmode: Option<u16>,
+    locked: bool,
+    saved_overwrite: Option<(u64, u64)>,
+) -> Result<()> {
+    conn.execute(
+        "UPDATE quiet_hours SET active = 1, saved_slowmode = ?2, locked = ?3, saved_allow = ?4, saved_deny = ?5
+         WHERE channel_id = ?1",
+        params![
+            channel_id,
+            saved_slowmode,
+            locked,
+            saved_overwrite.map(|(allow, _)| allow as i64),
+            saved_overwrite.map(|(_, deny)| deny as i64)
+        ],
+    )?;
+    Ok(())
+}
+
+/// Records that a window ended and the channel was put back.
+pub fn end_quiet_hours(conn: &Connection, channel_id: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE quiet_hours SET active = 0, saved_slowmode = NULL, locked = 0, saved_allow = NULL, saved_deny = NULL
+         WHERE channel_id = ?1",
+        params![channel_id],
+    )?;
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct InviteUse {
+    pub code: String,
+    pub inviter_id: Option<String>,
+    pub uses: u64,
+}
+
+pub fn get_invite_uses(conn: &Connection, guild_id: &str) -> Result<Vec<InviteUse>> {
+    let mut stmt = conn.prepare("SELECT code, inviter_id, uses FROM invite_uses WHERE guild_id = ?1 ORDER BY code")?;
+    let invites = stmt
+        .query_map(params![guild_id], |row| {
+            Ok(InviteUse {
+                code: row.get(0)?,
+                inviter_id: row.get(1)?,
+                uses: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(invites)
+}
+
+/// Replaces `guild_id`'s invites with a fresh list from Discord.
+pub fn set_invite_uses(conn: &Connection, guild_id: &str, invites: &[InviteUse]) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("DELETE FROM invite_uses WHERE guild_id = ?1", params![guild_id])?;
+    for invite in invites {
+        tx.execute(
+            "INSERT INTO invite_uses (guild_id, code, inviter_id, uses) VALUES (?1, ?2, ?3, ?4)",
+            params![guild_id, invite.code, invite.inviter_id, invite.uses],
+        )?;
+    }
+    tx.commit()
+}
+
+/// Records that `user_id` joined, through `invite` if it's known.
+pub fn record_invite_join(conn: &Connection, guild_id: &str, user_id: &str, invite: Option<&InviteUse>) -> Result<()> {
+    conn.execute(
+        "INSERT INTO invite_joins (guild_id, user_id, code, inviter_id) VALUES (?1, ?2, ?3, ?4)",
+        params![guild_id, user_id, invite.map(|i| &i.code), invite.and_then(|i| i.inviter_id.as_ref())],
+    )?;
+    Ok(())
+}
+
+/// How many joins one invite (or, for `code: None`, unknown sources) brought in.
+pub struct JoinSource {
+    pub code: Option<String>,
+    pub inviter_id: Option<String>,
+    pub joins: i64,
+}
+
+/// Joins in `guild_id` per invite, most first, optionally only `inviter_id`'s.
+pub fn get_join_sources(conn: &Connection, guild_id: &str, inviter_id: Option<&str>) -> Result<Vec<JoinSource>> {
+    let mut stmt = conn.prepare(
+        "SELECT code, inviter_id, COUNT(*) AS joins FROM invite_joins
+         WHERE guild_id = ?1 AND (?2 IS NULL OR inviter_id = ?2)
+         GROUP BY code, inviter_id ORDER BY joins DESC, code",
+    )?;
+    let sources = stmt
+        .query_map(params![guild_id, inviter_id], |row| {
+            Ok(JoinSource {
+                code: row.get(0)?,
+                inviter_id: row.get(1)?,
+                joins: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(sources)
+}
+
+/// What [`purge_user`] deleted.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Purged {
+    pub messages: usize,
+    pub memories: usize,
+    pub feedback: usize,
+    pub preferences: usize,
+    pub links: usize,
+    /// Checkpoints, tickets and their logs, `!rp` sessions, todos,
+    /// confessions, queued requests and join records.
+    pub other: usize,
+}
+
+impl Purged {
+    pub fn total(&self) -> usize {
+        self.messages + self.memories + self.feedback + self.preferences + self.links + self.other
+    }
+}
+
+/// Deletes everything stored about `user_id`: their messages and their
+/// per-user histories (with the bot's replies), memories of their prompts,
+/// feedback, `!style`, linked accounts, tickets, todos and so on. Opt-outs are
+/// kept so the bot doesn't start recording them again, as is the moderation log.
+///
+/// Messages stored before author ids were, ticket logs and `!rp` turn orders
+/// only have a name, so they're matched on `names`, the names the user has
+/// been stored under.
+pub fn purge_user(conn: &Connection, user_id: &str, names: &[&str]) -> Result<Purged> {
+    // Per-user histories are keyed `channel:user`, after any `bot@` prefix
+    let own_history = format!("%:{}", user_id);
+    let names_json = serde_json::to_string(names).unwrap_or_default();
+    let tx = conn.unchecked_transaction()?;
+    // Memories from before `asked_by` are found through the message they quote
+    let old_memories = tx.execute(
+        "DELETE FROM memories WHERE asked_by IS NULL AND EXISTS (
+             SELECT 1 FROM messages m WHERE m.channel_id = memories.channel_id AND m.role = 'user'
+                 AND (m.author_id = ?1 OR (m.author_id IS NULL AND m.author IN (SELECT value FROM json_each(?2))))
+                 AND decrypt(m.content) = decrypt(memories.prompt))",
+        params![user_id, names_json],
+    )?;
+    let mut purged = Purged {
+        messages: tx.execute(
+            "DELETE FROM messages WHERE author_id = ?1 OR channel_id LIKE ?2
+                 OR (author_id IS NULL AND role = 'user' AND author IN (SELECT value FROM json_each(?3)))",
+            params![user_id, own_history, names_json],
+        )?,
+        memories: old_memories
+            + tx.execute(
+                "DELETE FROM memories WHERE asked_by = ?1 OR channel_id LIKE ?2",
+                params![user_id, own_history],
+            )?,
+        feedback: tx.execute(
+            "DELETE FROM feedback WHERE user_id = ?1 OR history_key LIKE ?2",
+            params![user_id, own_history],
+        )?,
+        preferences: tx.execute(
+            "DELETE FROM config WHERE key = 'reply_style:' || ?1",
+            params![user_id],
+        )?,
+        links: tx.execute("DELETE FROM linked_accounts WHERE user_id = ?1", params![user_id])?,
+        other: 0,
+    };
+    tx.execute(
+        "DELETE FROM checkpoint_messages WHERE checkpoint_id IN
+             (SELECT id FROM checkpoints WHERE saved_by = ?1 OR history_key LIKE ?2)",
+        params![user_id, own_history],
+    )?;
+    purged.other += tx.execute(
+        "DELETE FROM checkpoints WHERE saved_by = ?1 OR history_key LIKE ?2",
+        params![user_id, own_history],
+    )?;
+    for table in ["todos", "confessions", "pending_requests", "temp_channel_members", "invite_joins"] {
+        purged.other += tx.execute(&format!("DELETE FROM {} WHERE user_id = ?1", table), params![user_id])?;
+    }
+    tx.execute(
+        "UPDATE invite_joins SET inviter_id = NULL WHERE inviter_id = ?1",
+        params![user_id],
+    )?;
+
+    // Their tickets go whole; in other people's, only what they said
+    purged.other += tx.execute(
+        "DELETE FROM ticket_messages WHERE ticket_id IN (SELECT id FROM tickets WHERE user_id = ?1)
+             OR author IN (SELECT value FROM json_each(?2))",
+        params![user_id, names_json],
+    )?;
+    purged.other += tx.execute("DELETE FROM tickets WHERE user_id = ?1", params![user_id])?;
+    let transcripts = {
+        let mut stmt = tx.prepare("SELECT id, transcript FROM tickets WHERE transcript IS NOT NULL")?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<Result<Vec<_>>>()?;
+        rows
+    };
+    for (id, transcript) in transcripts {
+        let (kept, removed) = strip_transcript(&transcript, names);
+        if removed > 0 {
+            tx.execute("UPDATE tickets SET transcript = ?2 WHERE id = ?1", params![id, kept])?;
+            purged.other += removed;
+        }
+    }
+
+    purged.other += tx.execute("DELETE FROM rp_sessions WHERE started_by = ?1", params![user_id])?;
+    let sessions = {
+        let mut stmt = tx.prepare("SELECT channel_id, participants FROM rp_sessions")?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<Result<Vec<_>>>()?;
+        rows
+    };
+    for (channel_id, participants) in sessions {
+        let mut participants: Vec<String> = serde_json::from_str(&participants).unwrap_or_default();
+        let before = participants.len();
+        participants.retain(|p| !names.contains(&p.as_str()));
+        if participants.len() < before {
+            tx.execute(
+                "UPDATE rp_sessions SET participants = ?2 WHERE channel_id = ?1",
+                params![channel_id, serde_json::to_string(&participants).unwrap_or_default()],
+            )?;
+            purged.other += 1;
+        }
+    }
+    tx.commit()?;
+    Ok(purged)
+}
+
+/// Drops the messages by any of `names` from a ticket transcript written by
+/// [`close_ticket`], including the lines of multi-line ones. Returns the rest
+/// and how many messages were dropped.
+fn strip_transcript(transcript: &str, names: &[&str]) -> (String, usize) {
+    let mut kept = Vec::new();
+    let mut removed = 0;
+    let mut dropping = false;
+    for line in transcript.lines() {
+        // A new message starts `[YYYY-MM-DD HH:MM] author: `
+        let header = line
+            .strip_prefix('[')
+            .and_then(|rest| rest.get(16..))
+            .and_then(|rest| rest.strip_prefix("] "))
+            .and_then(|rest| rest.split_once(": "));
+        if let Some((author, _)) = header {
+            dropping = names.contains(&author);
+            removed += dropping as usize;
+        }
+        if !dropping {
+            kept.push(line);
+        }
+    }
+    (kept.join("\n"), removed)
+}
+
+pub struct ModerationEntry {
+    /// Who acted, or for AutoMod, whose message set it off.
+    pub user_id: String,
+    pub action: String,
+    pub detail: String,
+    pub created_at: i64,
+}
+
+pub fn log_moderation(conn: &Connection, guild_id: &str, user_id: &str, action: &str, detail: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO moderation_log (guild_id, user_id, action, detail) VALUES (?1, ?2, ?3, ?4)",
+        params![guild_id, user_id, action, detail],
+    )?;
+    Ok(())
+}
+
+/// A guild's latest moderation log entries, newest first.
+pub fn get_moderation_log(conn: &Connection, guild_id: &str, limit: usize) -> Result<Vec<ModerationEntry>> {
+    let mut stmt = conn.prepare(
+        "SELECT user_id, action, detail, created_at FROM moderation_log
+         WHERE guild_id = ?1 ORDER BY created_at DESC, id DESC LIMIT ?2",
+    )?;
+    let entries = stmt
+        .query_map(params![guild_id, limit as i64], |row| {
+            Ok(ModerationEntry {
+                user_id: row.get(0)?,
+                action: row.get(1)?,
+                detail: row.get(2)?,
+                created_at: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(entries)
+}
+
+pub fn add_temp_channel(conn: &Connection, channel_id: &str, guild_id: &str, owner_id: &str) -> Result<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO temp_channels (channel_id, guild_id, owner_id) VALUES (?1, ?2, ?3)",
+        params![channel_id, guild_id, owner_id],
+    )?;
+    Ok(())
+}
+
+pub fn remove_temp_channel(conn: &Connection, channel_id: &str) -> Result<bool> {
+    conn.execute("DELETE FROM temp_channel_members WHERE channel_id = ?1", params![channel_id])?;
+    let rows = conn.execute("DELETE FROM temp_channels WHERE channel_id = ?1", params![channel_id])?;
+    Ok(rows > 0)
+}
+
+pub fn get_temp_channels(conn: &Connection, guild_id: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT channel_id FROM temp_channels WHERE guild_id = ?1 ORDER BY created_at")?;
+    let channels = stmt
+        .query_map(params![guild_id], |row| row.get(0))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(channels)
+}
+
+/// Records that `user_id` is now in voice channel `channel_id` (`None` for
+/// none), and returns the temp channels their leaving emptied.
+pub fn set_temp_channel_member(conn: &Connection, user_id: &str, channel_id: Option<&str>) -> Result<Vec<String>> {
+    let tx = conn.unchecked_transaction()?;
+    let left: Vec<String> = {
+        let mut stmt = tx.prepare("SELECT channel_id FROM temp_channel_members WHERE user_id = ?1")?;
+        let left = stmt
+            .query_map(params![user_id], |row| row.get(0))?
+            .collect::<Result<Vec<String>>>()?;
+        left
+    };
+    tx.execute("DELETE FROM temp_channel_members WHERE user_id = ?1", params![user_id])?;
+    if let Some(channel_id) = channel_id {
+        tx.execute(
+            "INSERT INTO temp_channel_members (channel_id, user_id)
+             SELECT channel_id, ?2 FROM temp_channels WHERE channel_id = ?1",
+            params![channel_id, user_id],
+        )?;
+    }
+    let mut emptied = Vec::new();
+    for channel in left.into_iter().filter(|c| Some(c.as_str()) != channel_id) {
+        let occupied: bool = tx.query_row(
+            "SELECT EXISTS(SELECT 1 FROM temp_channel_members WHERE channel_id = ?1)",
+            params![channel],
+            |row| row.get(0),
+        )?;
+        if !occupied {
+            emptied.push(channel);
+        }
+    }
+    tx.commit()?;
+    Ok(emptied)
+}
+
+/// Replaces who is in a temp channel, from a fresh look at the guild.
+pub fn reset_temp_channel_members(conn: &Connection, channel_id: &str, user_ids: &[String]) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("DELETE FROM temp_channel_members WHERE channel_id = ?1", params![channel_id])?;
+    for user_id in user_ids {
+        tx.execute(
+            "INSERT OR IGNORE INTO temp_channel_members (channel_id, user_id) VALUES (?1, ?2)",
+            params![channel_id, user_id],
+        )?;
+    }
+    tx.commit()
+}
+
+/// Key under which conversation history is stored for a message from `user_id`
+/// in `channel_id`, depending on the channel's context mode.
+pub fn context_key(conn: &Connection, channel_id: &str, user_id: &str) -> String {
+    let mode = get_context_mode(conn, channel_id).unwrap_or_else(|_| "channel".to_string());
+    match mode.as_str() {
+        "user" => format!("{}:{}", channel_id, user_id),
+        _ => channel_id.to_string(),
+    }
+}
+
+pub fn clear_messages(conn: &Connection, channel_id: &str) -> Result<usize> {
+    conn.execute(
+        "DELETE FROM messages WHERE channel_id = ?1",
+        params![channel_id],
+    )
+}
+
+pub fn store_message(conn: &Connection, channel_id: &str, role: &str, content: &str) -> Result<()> {
+    store_message_from(conn, channel_id, role, None, None, None, content).map(|_| ())
+}
+
+/// [`store_message`], remembering who sent it (a display name and user ID)
+/// and the Discord message it came from. Returns false if that message was
+/// already stored. Rows are tagged with the event's [`trace`] correlation ID.
+pub fn store_message_from(
+    conn: &Connection,
+    channel_id: &str,
+    role: &str,
+    author: Option<&str>,
+    author_id: Option<&str>,
+    message_id: Option<&str>,
+    content: &str,
+) -> Result<bool> {
+    let rows = conn.execute(
+        "INSERT OR IGNORE INTO messages (channel_id, role, author, author_id, message_id, content, correlation_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, encrypt(?6), ?7)",
+        params![channel_id, role, author, author_id, message_id, content, trace::current()],
+    )?;
+    Ok(rows > 0)
+}
+
+/// Whether a Discord message is already in a history.
+pub fn has_message(conn: &Connection, channel_id: &str, message_id: &str) -> Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM messages WHERE channel_id = ?1 AND message_id = ?2)",
+        params![channel_id, message_id],
+        |row| row.get(0),
+    )
+}
+
+/// Removes a deleted Discord message from every history it was stored in.
+/// Returns how many rows went.
+pub fn delete_discord_message(conn: &Connection, message_id: &str) -> Result<usize> {
+    conn.execute("DELETE FROM messages WHERE message_id = ?1", params![message_id])
+}
+
+/// Deletes all but the newest `keep` messages under a history key.
+pub fn prune_messages(conn: &Connection, channel_id: &str, keep: usize) -> Result<usize> {
+    conn.execute(
+        "DELETE FROM messages WHERE channel_id = ?1 AND id NOT IN (
+             SELECT id FROM messages WHERE channel_id = ?1
+             ORDER BY timestamp DESC, id DESC
+             LIMIT ?2
+         )",
+        params![channel_id, keep as i64],
+    )
+}
+
+/// Copies one history's messages into another, keeping their timestamps, and
+/// with `remove` deletes the originals. Returns how many were copied.
+pub fn transfer_messages(conn: &Connection, from: &str, to: &str, remove: bool) -> Result<usize> {
+    let tx = conn.unchecked_transaction()?;
+    let copied = tx.execute(
+        "INSERT OR IGNORE INTO messages (channel_id, role, author, author_id, message_id, content, timestamp, correlation_id)
+         SELECT ?2, role, author, author_id, message_id, content, timestamp, correlation_id FROM messages
+         WHERE channel_id = ?1 ORDER BY id",
+        params![from, to],
+    )?;
+    if remove {
+        tx.execute("DELETE FROM messages WHERE channel_id = ?1", params![from])?;
+    }
+    tx.commit()?;
+    Ok(copied)
+}
+
+/// Saves `history_key`'s messages as checkpoint `name`, replacing an earlier
+/// one of that name. Returns how many messages were saved; an empty history
+/// isn't saved, since loading it would wipe the conversation.
+pub fn save_checkpoint(conn: &Connection, history_key: &str, name: &str, saved_by: &str) -> Result<usize> {
+    let tx = conn.unchecked_transaction()?;
+    let count: i64 = tx.query_row(
+        "SELECT COUNT(*) FROM messages WHERE channel_id = ?1",
+        params![history_key],
+        |row| row.get(0),
+    )?;
+    if count == 0 {
+        return Ok(0);
+    }
+    let old: Option<i64> = tx
+        .query_row(
+            "SELECT id FROM checkpoints WHERE history_key = ?1 AND name = ?2",
+            params![history_key, name],
+            |row| row.get(0),
+        )
+        .or_else(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => Ok(None),
+            e => Err(e),
+        })?;
+    if let Some(old) = old {
+        tx.execute("DELETE FROM checkpoint_messages WHERE checkpoint_id = ?1", params![old])?;
+        tx.execute("DELETE FROM checkpoints WHERE id = ?1", params![old])?;
+    }
+    tx.execute(
+        "INSERT INTO checkpoints (history_key, name, saved_by) VALUES (?1, ?2, ?3)",
+        params![history_key, name, saved_by],
+    )?;
+    let id = tx.last_insert_rowid();
+    let saved = tx.execute(
+        "INSERT INTO checkpoint_messages (checkpoint_id, role, author, content, timestamp)
+         SELECT ?1, role, author, content, timestamp FROM messages WHERE channel_id = ?2
+         ORDER BY timestamp, id",
+        params![id, history_key],
+    )?;
+    tx.commit()?;
+    Ok(saved)
+}
+
+/// Replaces `history_key`'s messages with checkpoint `name`. Returns how
+/// many messages were restored, or `None` if there's no such checkpoint.
+pub fn load_checkpoint(conn: &Connection, history_key: &str, name: &str) -> Result<Option<usize>> {
+    let tx = conn.unchecked_transaction()?;
+    let id: Option<i64> = tx
+        .query_row(
+            "SELECT id FROM checkpoints WHERE history_key = ?1 AND name = ?2",
+            params![history_key, name],
+            |row| row.get(0),
+        )
+        .or_else(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => Ok(None),
+            e => Err(e),
+        })?;
+    let Some(id) = id else {
+        return Ok(None);
+    };
+    tx.execute("DELETE FROM messages WHERE channel_id = ?1", params![history_key])?;
+    let restored = tx.execute(
+        "INSERT INTO messages (channel_id, role, author, content, timestamp)
+         SELECT ?1, role, author, content, timestamp FROM checkpoint_messages WHERE checkpoint_id = ?2
+         ORDER BY id",
+        params![history_key, id],
+    )?;
+    tx.commit()?;
+    Ok(Some(restored))
+}
+
+pub fn delete_checkpoint(conn: &Connection, history_key: &str, name: &str) -> Result<bool> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "DELETE FROM checkpoint_messages WHERE checkpoint_id IN
+             (SELECT id FROM checkpoints WHERE history_key = ?1 AND name = ?2)",
+        params![history_key, name],
+    )?;
+    let rows = tx.execute(
+        "DELETE FROM checkpoints WHERE history_key = ?1 AND name = ?2",
+        params![history_key, name],
+    )?;
+    tx.commit()?;
+    Ok(rows > 0)
+}
+
+/// `(name, saved_at)` for `history_key`'s checkpoints, newest first.
+pub fn get_checkpoints(conn: &Connection, history_key: &str) -> Result<Vec<(String, i64)>> {
+    let mut stmt = conn.prepare(
+        "SELECT name, saved_at FROM checkpoints WHERE history_key = ?1 ORDER BY saved_at DESC, id DESC",
+    )?;
+    let checkpoints = stmt
+        .query_map(params![history_key], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(checkpoints)
+}
+
+/// Deletes messages stored before `before` (unix seconds), from every history.
+pub fn prune_messages_before(conn: &Connection, before: i64) -> Result<usize> {
+    conn.execute("DELETE FROM messages WHERE timestamp < ?1", params![before])
+}
+
+/// A message on its way to an archive file, with everything needed to
+/// export it later. `content` is as stored, so encrypted when the database is.
+pub struct ArchivableMessage {
+    /// The history it belonged to: the channel, or `channel:user` for
+    /// per-user context.
+    pub history_key: String,
+    pub role: String,
+    pub author: Option<String>,
+    pub content: String,
+    pub timestamp: i64,
+    pub message_id: Option<String>,
+}
+
+/// Where `channel_id`'s histories (shared or per-user) are, as SQL on `messages`.
+const CHANNEL_HISTORIES: &str = "(channel_id = ?1 OR channel_id LIKE ?1 || ':%')";
+
+/// Messages in `channel_id`'s histories from before `before`, oldest first.
+pub fn get_messages_to_archive(conn: &Connection, channel_id: &str, before: i64) -> Result<Vec<ArchivableMessage>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT channel_id, role, author, content, timestamp, message_id FROM messages
+         WHERE {} AND timestamp < ?2 ORDER BY timestamp, id",
+        CHANNEL_HISTORIES
+    ))?;
+    let messages = stmt
+        .query_map(params![channel_id, before], |row| {
+            Ok(ArchivableMessage {
+                history_key: row.get(0)?,
+                role: row.get(1)?,
+                author: row.get(2)?,
+                content: row.get(3)?,
+                timestamp: row.get(4)?,
+                message_id: row.get(5)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(messages)
+}
+
+pub struct MessageArchive {
+    pub id: i64,
+    pub channel_id: String,
+    pub path: String,
+    pub first_timestamp: i64,
+    pub last_timestamp: i64,
+    pub message_count: i64,
+}
+
+/// Indexes the archive file at `path` and deletes the messages it holds,
+/// those [`get_messages_to_archive`] returned for the same arguments.
+pub fn record_message_archive(conn: &Connection, channel_id: &str, before: i64, path: &str, messages: &[ArchivableMessage]) -> Result<i64> {
+    let (Some(first), Some(last)) = (messages.first(), messages.last()) else {
+        return Ok(0);
+    };
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "INSERT INTO message_archives (channel_id, path, first_timestamp, last_timestamp, message_count)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![channel_id, path, first.timestamp, last.timestamp, messages.len() as i64],
+    )?;
+    let id = tx.last_insert_rowid();
+    tx.execute(
+        &format!("DELETE FROM messages WHERE {} AND timestamp < ?2", CHANNEL_HISTORIES),
+        params![channel_id, before],
+    )?;
+    tx.commit()?;
+    Ok(id)
+}
+
+pub fn get_message_archives(conn: &Connection, channel_id: &str) -> Result<Vec<MessageArchive>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, channel_id, path, first_timestamp, last_timestamp, message_count FROM message_archives
+         WHERE channel_id = ?1 ORDER BY first_timestamp",
+    )?;
+    let archives = stmt
+        .query_map(params![channel_id], |row| {
+            Ok(MessageArchive {
+                id: row.get(0)?,
+                channel_id: row.get(1)?,
+                path: row.get(2)?,
+                first_timestamp: row.get(3)?,
+                last_timestamp: row.get(4)?,
+                message_count: row.get(5)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(archives)
+}
+
+pub struct StoredMessage {
+    pub role: String,
+    /// Display name of the sender, for user messages stored with one.
+    pub author: Option<String>,
+    pub content: String,
+    pub timestamp: i64,
+}
+
+pub fn get_recent_messages(
+    conn: &Connection,
+    channel_id: &str,
+    limit: usize,
+) -> Result<Vec<StoredMessage>> {
+    let mut stmt = conn.prepare(
+        "SELECT role, author, decrypt(content), timestamp FROM messages
+         WHERE channel_id = ?1
+         ORDER BY timestamp DESC, id DESC
+         LIMIT ?2",
+    )?;
+    let mut messages: Vec<StoredMessage> = stmt
+        .query_map(params![channel_id, limit as i64], |row| {
+            Ok(StoredMessage {
+                role: row.get(0)?,
+                author: row.get(1)?,
+                content: row.get(2)?,
+                timestamp: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    // Reverse so oldest is first (we fetched newest-first for LIMIT)
+    messages.reverse();
+    Ok(messages)
+}
+
+pub fn add_tracked_character(conn: &Connection, name: &str, added_by: &str) -> Result<bool> {
+    let rows = conn.execute(
+        "INSERT OR IGNORE INTO tracked_characters (name, added_by) VALUES (?1, ?2)",
+        params![name, added_by],
+    )?;
+    Ok(rows > 0)
+}
+
+pub fn remove_tracked_character(conn: &Connection, name: &str) -> Result<bool> {
+    let rows = conn.execute(
+        "DELETE FROM tracked_characters WHERE name = ?1",
+        params![name],
+    )?;
+    Ok(rows > 0)
+}
+
+pub fn get_tracked_characters(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT name FROM tracked_characters ORDER BY name")?;
+    let names = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<String>>>()?;
+    Ok(names)
+}
+
+/// Row counts shown on the web dashboard.
+#[derive(Debug, Default, PartialEq)]
+pub struct UsageStats {
+    pub messages: u64,
+    pub contexts: u64,
+    pub tracked_characters: u64,
+    pub snapshots: u64,
+}
+
+pub fn usage_stats(conn: &Connection) -> Result<UsageStats> {
+    let count = |sql: &str| conn.query_row(sql, [], |row| row.get::<_, i64>(0)).map(|n| n as u64);
+    Ok(UsageStats {
+        messages: count("SELECT COUNT(*) FROM messages")?,
+        contexts: count("SELECT COUNT(DISTINCT channel_id) FROM messages")?,
+        tracked_characters: count("SELECT COUNT(*) FROM tracked_characters")?,
+        snapshots: count("SELECT COUNT(*) FROM character_snapshots")?,
+    })
+}
+
+pub fn set_feature_enabled(conn: &Connection, guild_id: &str, feature: &str, enabled: bool) -> Result<()> {
+    conn.execute(
+        "INSERT INTO guild_features (guild_id, feature, enabled) VALUES (?1, ?2, ?3)
+         ON CONFLICT(guild_id, feature) DO UPDATE SET enabled = excluded.enabled",
+        params![guild_id, feature, enabled],
+    )?;
+    Ok(())
+}
+
+/// Features are enabled unless a guild has explicitly disabled them.
+pub fn is_feature_enabled(conn: &Connection, guild_id: &str, feature: &str) -> Result<bool> {
+    let mut stmt = conn.prepare(
+        "SELECT enabled FROM guild_features WHERE guild_id = ?1 AND feature = ?2",
+    )?;
+    let mut rows = stmt.query(params![guild_id, feature])?;
+    match rows.next()? {
+        Some(row) => row.get(0),
+        None => Ok(true),
+    }
+}
+
+/// Returns false if the guild already blocks `entry`.
+pub fn add_blocklist_entry(conn: &Connection, guild_id: &str, entry: &str, added_by: &str) -> Result<bool> {
+    let rows = conn.execute(
+        "INSERT OR IGNORE INTO blocklist (guild_id, entry, added_by) VALUES (?1, ?2, ?3)",
+        params![guild_id, entry, added_by],
+    )?;
+    Ok(rows > 0)
+}
+
+pub fn remove_blocklist_entry(conn: &Connection, guild_id: &str, entry: &str) -> Result<bool> {
+    let rows = conn.execute(
+        "DELETE FROM blocklist WHERE guild_id = ?1 AND entry = ?2",
+        params![guild_id, entry],
+    )?;
+    Ok(rows > 0)
+}
+
+pub fn get_blocklist(conn: &Connection, guild_id: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT entry FROM blocklist WHERE guild_id = ?1 ORDER BY entry")?;
+    let entries = stmt
+        .query_map(params![guild_id], |row| row.get(0))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(entries)
+}
+
+pub fn get_blocklist_mode(conn: &Connection, guild_id: &str) -> Result<Option<String>> {
+    get_config(conn, Some(guild_id), "blocklist_mode")
+}
+
+pub fn set_blocklist_mode(conn: &Connection, guild_id: &str, mode: &str) -> Result<()> {
+    set_config(conn, Some(guild_id), "blocklist_mode", mode)
+}
+
+/// Adds a word that triggers chat in `guild_id`; false if it was already there.
+pub fn add_wake_word(conn: &Connection, guild_id: &str, word: &str, added_by: &str) -> Result<bool> {
+    let rows = conn.execute(
+        "INSERT OR IGNORE INTO wake_words (guild_id, word, added_by) VALUES (?1, ?2, ?3)",
+        params![guild_id, word, added_by],
+    )?;
+    Ok(rows > 0)
+}
+
+pub fn remove_wake_word(conn: &Connection, guild_id: &str, word: &str) -> Result<bool> {
+    let rows = conn.execute(
+        "DELETE FROM wake_words WHERE guild_id = ?1 AND word = ?2",
+        params![guild_id, word],
+    )?;
+    Ok(rows > 0)
+}
+
+/// Words that make the bot answer a message in a guild as if it were mentioned.
+pub fn get_wake_words(conn: &Connection, guild_id: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT word FROM wake_words WHERE guild_id = ?1 ORDER BY word")?;
+    let words = stmt
+        .query_map(params![guild_id], |row| row.get(0))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(words)
+}
+
+/// Stores the exchange a bot reply (`message_id`) carries, for reactions to rate.
+pub fn record_exchange(conn: &Connection, message_id: &str, persona: &str, prompt: &str, reply: &str) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO feedback (message_id, persona, prompt, reply, correlation_id) VALUES (?1, ?2, encrypt(?3), encrypt(?4), ?5)",
+        params![message_id, persona, prompt, reply, trace::current()],
+    )?;
+    Ok(())
+}
+
+/// Notes which history an exchange is in, who asked for it and in which
+/// message, for the reaction actions on its reply and for edits to the prompt.
+pub fn set_exchange_origin(
+    conn: &Connection,
+    message_id: &str,
+    history_key: &str,
+    user_id: Option<&str>,
+    prompt_message_id: Option<&str>,
+) -> Result<()> {
+    conn.execute(
+        "UPDATE feedback SET history_key = ?2, user_id = ?3, prompt_message_id = ?4 WHERE message_id = ?1",
+        params![message_id, history_key, user_id, prompt_message_id],
+    )?;
+    Ok(())
+}
+
+/// A recorded exchange, as [`get_exchange_record`] returns it.
+pub struct Exchange {
+    pub prompt: String,
+    pub reply: String,
+    pub history_key: Option<String>,
+    pub user_id: Option<String>,
+    pub prompt_message_id: Option<String>,
+}
+
+fn exchange_from_row(row: &rusqlite::Row) -> Result<Exchange> {
+    Ok(Exchange {
+        prompt: row.get("prompt")?,
+        reply: row.get("reply")?,
+        history_key: row.get("history_key")?,
+        user_id: row.get("user_id")?,
+        prompt_message_id: row.get("prompt_message_id")?,
+    })
+}
+
+pub fn get_exchange_record(conn: &Connection, message_id: &str) -> Result<Option<Exchange>> {
+    let mut stmt = conn.prepare(
+        "SELECT decrypt(prompt) AS prompt, decrypt(reply) AS reply, history_key, user_id, prompt_message_id
+         FROM feedback WHERE message_id = ?1",
+    )?;
+    let mut rows = stmt.query(params![message_id])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(exchange_from_row(row)?)),
+        None => Ok(None),
+    }
+}
+
+/// The exchange answering the user message `prompt_message_id`, with the
+/// first message its reply was sent in.
+pub fn get_exchange_for_prompt(conn: &Connection, prompt_message_id: &str) -> Result<Option<(String, Exchange)>> {
+    let mut stmt = conn.prepare(
+        "SELECT message_id, decrypt(prompt) AS prompt, decrypt(reply) AS reply, history_key, user_id, prompt_message_id
+         FROM feedback WHERE prompt_message_id = ?1 ORDER BY CAST(message_id AS INTEGER) LIMIT 1",
+    )?;
+    let mut rows = stmt.query(params![prompt_message_id])?;
+    match rows.next()? {
+        Some(row) => Ok(Some((row.get(0)?, exchange_from_row(row)?))),
+        None => Ok(None),
+    }
+}
+
+/// Forgets an exchange: the feedback records of its reply's messages, and its prompt and reply
+/// in `history_key`, found through the prompt's Discord message. Exchanges recorded before
+/// that was kept only lose their feedback records. Returns the prompt's stored author.
+pub fn forget_exchange(conn: &Connection, message_id: &str, history_key: &str, exchange: &Exchange) -> Result<Option<String>> {
+    let prompt = match &exchange.prompt_message_id {
+        Some(prompt_message_id) => conn
+            .query_row(
+                "SELECT id, author FROM messages WHERE channel_id = ?1 AND message_id = ?2 AND role = 'user'",
+                params![history_key, prompt_message_id],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Option<String>>(1)?)),
+            )
+            .map(Some)
+            .or_else(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => Ok(None),
+                e => Err(e),
+            })?,
+        None => None,
+    };
+    let author = match prompt {
+        Some((id, author)) => {
+            // The reply is stored after its prompt; only the rows since are decrypted
+            conn.execute(
+                "DELETE FROM messages WHERE id = (
+                     SELECT id FROM messages WHERE channel_id = ?1 AND role = 'assistant' AND id > ?2
+                         AND decrypt(content) = ?3
+                     ORDER BY id LIMIT 1
+                 )",
+                params![history_key, id, exchange.reply],
+            )?;
+            conn.execute("DELETE FROM messages WHERE id = ?1", params![id])?;
+            author
+        }
+        None => None,
+    };
+    conn.execute(
+        "DELETE FROM feedback
+         WHERE message_id = ?1 OR (history_key = ?2 AND decrypt(prompt) = ?3 AND decrypt(reply) = ?4)",
+        params![message_id, history_key, exchange.prompt, exchange.reply],
+    )?;
+    Ok(author)
+}
+
+/// Every sent message carrying the same exchange as `message_id`, in the order
+/// they were sent; long replies are split over several.
+pub fn exchange_message_ids(conn: &Connection, message_id: &str, exchange: &Exchange) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT message_id FROM feedback
+         WHERE message_id = ?1 OR (history_key = ?2 AND decrypt(prompt) = ?3 AND decrypt(reply) = ?4)
+         ORDER BY CAST(message_id AS INTEGER)",
+    )?;
+    let ids = stmt
+        .query_map(params![message_id, exchange.history_key, exchange.prompt, exchange.reply], |row| row.get(0))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(ids)
+}
+
+/// The prompt and reply of the exchange a sent bot message belongs to.
+pub fn get_exchange(conn: &Connection, message_id: &str) -> Result<Option<(String, String)>> {
+    let mut stmt = conn.prepare("SELECT decrypt(prompt), decrypt(reply) FROM feedback WHERE message_id = ?1")?;
+    let mut rows = stmt.query(params![message_id])?;
+    match rows.next()? {
+        Some(row) => Ok(Some((row.get(0)?, row.get(1)?))),
+        None => Ok(None),
+    }
+}
+
+/// Adds `delta` to a reply's score. Returns false if the message isn't a recorded reply.
+pub fn add_feedback_vote(conn: &Connection, message_id: &str, delta: i64) -> Result<bool> {
+    let rows = conn.execute(
+        "UPDATE feedback SET score = score + ?2 WHERE message_id = ?1",
+        params![message_id, delta],
+    )?;
+    Ok(rows > 0)
+}
+
+/// The best-rated of the latest upvoted `(prompt, reply)` exchanges for a
+/// persona, best first. A reply split over several messages counts once, with
+/// the votes on all its parts.
+pub fn top_feedback_examples(conn: &Connection, persona: &str, limit: usize) -> Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare(
+        "SELECT prompt, reply FROM (
+             SELECT decrypt(prompt) AS prompt, decrypt(reply) AS reply, SUM(score) AS score, MAX(created_at) AS created_at
+             FROM feedback
+             WHERE persona = ?1
+             GROUP BY 1, 2
+             HAVING SUM(score) > 0
+             ORDER BY created_at DESC
+             LIMIT ?2
+         )
+         ORDER BY score DESC, created_at DESC
+         LIMIT ?3",
+    )?;
+    let examples = stmt
+        .query_map(params![persona, FEEDBACK_RECENT_POOL as i64, limit as i64], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(examples)
+}
+
+pub struct TrackedCharacter {
+    pub name: String,
+    pub added_by: String,
+    pub added_at: i64,
+    /// Game version override; `None` follows the bot-wide default.
+    pub game_version: Option<String>,
+}
+
+fn tracked_character_from_row(row: &rusqlite::Row) -> Result<TrackedCharacter> {
+    Ok(TrackedCharacter {
+        name: row.get(0)?,
+        added_by: row.get(1)?,
+        added_at: row.get(2)?,
+        game_version: row.get(3)?,
+    })
+}
+
+pub fn get_tracked_character(conn: &Connection, name: &str) -> Result<Option<TrackedCharacter>> {
+    let mut stmt = conn.prepare(
+        "SELECT name, added_by, added_at, game_version FROM tracked_characters WHERE name = ?1",
+    )?;
+    let mut rows = stmt.query(params![name])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(tracked_character_from_row(row)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn get_tracked_character_details(conn: &Connection) -> Result<Vec<TrackedCharacter>> {
+    let mut stmt = conn.prepare(
+        "SELECT name, added_by, added_at, game_version FROM tracked_characters ORDER BY name",
+    )?;
+    let characters = stmt
+        .query_map([], tracked_character_from_row)?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(characters)
+}
+
+/// Sets (or with `None`, clears) a character's game version. Returns false if the
+/// character isn't tracked.
+pub fn set_character_game_version(conn: &Connection, name: &str, version: Option<&str>) -> Result<bool> {
+    let rows = conn.execute(
+        "UPDATE tracked_characters SET game_version = ?2 WHERE name = ?1",
+        params![name, version],
+    )?;
+    Ok(rows > 0)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CharacterSnapshot {
+    pub name: String,
+    pub level: u32,
+    pub experience: Option<u64>,
+    pub honorable_kills: Option<u32>,
+    pub honor_level: Option<u32>,
+    pub taken_at: i64,
+}
+
+impl CharacterSnapshot {
+    fn same_progress(&self, other: &CharacterSnapshot) -> bool {
+        self.level == other.level
+            && self.experience == other.experience
+            && self.honorable_kills == other.honorable_kills
+            && self.honor_level == other.honor_level
+    }
+}
+
+fn snapshot_from_row(row: &rusqlite::Row) -> Result<CharacterSnapshot> {
+    Ok(CharacterSnapshot {
+        name: row.get(0)?,
+        level: row.get(1)?,
+        experience: row.get::<_, Option<i64>>(2)?.map(|v| v as u64),
+        honorable_kills: row.get(3)?,
+        honor_level: row.get(4)?,
+        taken_at: row.get(5)?,
+    })
+}
+
+/// Stores `snapshot` only if it differs from the character's latest one, so each
+/// row marks a point where the character actually progressed. Returns whether a
+/// row was written.
+pub fn record_snapshot(conn: &Connection, snapshot: &CharacterSnapshot) -> Result<bool> {
+    if let Some(latest) = latest_snapshot(conn, &snapshot.name)? {
+        if latest.same_progress(snapshot) {
+            return Ok(false);
+        }
+    }
+    conn.execute(
+        "INSERT INTO character_snapshots (name, level, experience, honorable_kills, honor_level, taken_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            snapshot.name,
+            snapshot.level,
+            snapshot.experience.map(|v| v as i64),
+            snapshot.honorable_kills,
+            snapshot.honor_level,
+            snapshot.taken_at
+        ],
+    )?;
+    Ok(true)
+}
+
+pub fn latest_snapshot(conn: &Connection, name: &str) -> Result<Option<CharacterSnapshot>> {
+    snapshot_at_or_before(conn, name, i64::MAX)
+}
+
+/// The character's state as of `timestamp`: the newest snapshot taken at or before it.
+pub fn snapshot_at_or_before(conn: &Connection, name: &str, timestamp: i64) -> Result<Option<CharacterSnapshot>> {
+    let mut stmt = conn.prepare(
+        "SELECT name, level, experience, honorable_kills, honor_level, taken_at
+         FROM character_snapshots
+         WHERE name = ?1 AND taken_at <= ?2
+         ORDER BY taken_at DESC, id DESC
+         LIMIT 1",
+    )?;
+    let mut rows = stmt.query(params![name, timestamp])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(snapshot_from_row(row)?)),
+        None => Ok(None),
+    }
+}
+
+/// Every stored snapshot for the character, oldest first.
+pub fn get_snapshots(conn: &Connection, name: &str) -> Result<Vec<CharacterSnapshot>> {
+    let mut stmt = conn.prepare(
+        "SELECT name, level, experience, honorable_kills, honor_level, taken_at
+         FROM character_snapshots
+         WHERE name = ?1
+         ORDER BY taken_at ASC, id ASC",
+    )?;
+    let snapshots = stmt
+        .query_map(params![name], snapshot_from_row)?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(snapshots)
+}
+
+/// Deletes snapshots taken before `before` (unix seconds), keeping each
+/// character's latest so its current level is still known.
+pub fn prune_snapshots(conn: &Connection, before: i64) -> Result<usize> {
+    conn.execute(
+        "DELETE FROM character_snapshots WHERE taken_at < ?1 AND id != (
+             SELECT latest.id FROM character_snapshots latest
+             WHERE latest.name = character_snapshots.name
+             ORDER BY latest.taken_at DESC, latest.id DESC
+             LIMIT 1
+         )",
+        params![before],
+    )
+}
+
+pub fn earliest_snapshot(conn: &Connection, name: &str) -> Result<Option<CharacterSnapshot>> {
+    let mut stmt = conn.prepare(
+        "SELECT name, level, experience, honorable_kills, honor_level, taken_at
+         FROM character_snapshots
+         WHERE name = ?1
+         ORDER BY taken_at ASC, id ASC
+         LIMIT 1",
+    )?;
+    let mut rows = stmt.query(params![name])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(snapshot_from_row(row)?)),
+        None => Ok(None),
+    }
+}
+
+/// When the character last gained a level or experience: the first snapshot that
+/// already had its current level and XP. `None` if there are no snapshots.
+pub fn last_level_progress(conn: &Connection, name: &str) -> Result<Option<i64>> {
+    let Some(latest) = latest_snapshot(conn, name)? else {
+        return Ok(None);
+    };
+    conn.query_row(
+        "SELECT MIN(taken_at) FROM character_snapshots
+         WHERE name = ?1 AND level = ?2 AND experience IS ?3",
+        params![name, latest.level, latest.experience.map(|v| v as i64)],
+        |row| row.get(0),
+    )
+}
+
+/// Tracked character names containing `query` (case-insensitive), for autocomplete.
+pub fn search_tracked_characters(conn: &Connection, query: &str, limit: usize) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM tracked_characters
+         WHERE instr(lower(name), lower(?1)) > 0
+         ORDER BY name
+         LIMIT ?2",
+    )?;
+    let names = stmt
+        .query_map(params![query, limit as i64], |row| row.get(0))?
+        .collect::<Result<Vec<String>>>()?;
+    Ok(names)
+}
+
+/// A character imported with `!persona import`. `system_prompt` is built from
+/// the other fields when the card is imported.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Persona {
+    pub name: String,
+    pub description: String,
+    pub personality: String,
+    pub scenario: String,
+    pub first_message: String,
+    pub system_prompt: String,
+    pub created_by: String,
+}
+
+fn persona_from_row(row: &rusqlite::Row) -> Result<Persona> {
+    Ok(Persona {
+        name: row.get(0)?,
+        description: row.get(1)?,
+        personality: row.get(2)?,
+        scenario: row.get(3)?,
+        first_message: row.get(4)?,
+        system_prompt: row.get(5)?,
+        created_by: row.get(6)?,
+    })
+}
+
+/// Adds a persona, replacing any persona with the same name.
+pub fn save_persona(conn: &Connection, persona: &Persona) -> Result<()> {
+    conn.execute(
+        "INSERT INTO personas (name, description, personality, scenario, first_message, system_prompt, created_by)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT(name) DO UPDATE SET description = excluded.description,
+             personality = excluded.personality, scenario = excluded.scenario,
+             first_message = excluded.first_message, system_prompt = excluded.system_prompt,
+             created_by = excluded.created_by, created_at = unixepoch()",
+        params![
+            persona.name,
+            persona.description,
+            persona.personality,
+            persona.scenario,
+            persona.first_message,
+            persona.system_prompt,
+            persona.created_by
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn get_persona(conn: &Connection, name: &str) -> Result<Option<Persona>> {
+    let mut stmt = conn.prepare(
+        "SELECT name, description, personality, scenario, first_message, system_prompt, created_by
+         FROM personas WHERE name = ?1",
+    )?;
+    let mut rows = stmt.query(params![name])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(persona_from_row(row)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn get_personas(conn: &Connection) -> Result<Vec<Persona>> {
+    let mut stmt = conn.prepare(
+        "SELECT name, description, personality, scenario, first_message, system_prompt, created_by
+         FROM personas ORDER BY name",
+    )?;
+    let personas = stmt
+        .query_map([], persona_from_row)?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(personas)
+}
+
+pub fn remove_persona(conn: &Connection, name: &str) -> Result<bool> {
+    let rows = conn.execute("DELETE FROM personas WHERE name = ?1", params![name])?;
+    Ok(rows > 0)
+}
+
+/// A `!rp` session in a channel. `participants` are display names in the order
+/// they first spoke, which is also the turn order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpSession {
+    pub channel_id: String,
+    pub scenario: String,
+    pub participants: Vec<String>,
+    pub turns: i64,
+    pub started_by: String,
+    pub started_at: i64,
+}
+
+/// Starts a session, returning false if the channel already has one.
+pub fn start_rp_session(conn: &Connection, channel_id: &str, scenario: &str, started_by: &str) -> Result<bool> {
+    let rows = conn.execute(
+        "INSERT OR IGNORE INTO rp_sessions (channel_id, scenario, started_by) VALUES (?1, ?2, ?3)",
+        params![channel_id, scenario, started_by],
+    )?;
+    Ok(rows > 0)
+}
+
+pub fn get_rp_session(conn: &Connection, channel_id: &str) -> Result<Option<RpSession>> {
+    let mut stmt = conn.prepare(
+        "SELECT channel_id, scenario, participants, turns, started_by, started_at
+         FROM rp_sessions WHERE channel_id = ?1",
+    )?;
+    let mut rows = stmt.query(params![channel_id])?;
+    let Some(row) = rows.next()? else {
+        return Ok(None);
+    };
+    let participants: String = row.get(2)?;
+    Ok(Some(RpSession {
+        channel_id: row.get(0)?,
+        scenario: row.get(1)?,
+        participants: serde_json::from_str(&participants).unwrap_or_default(),
+        turns: row.get(3)?,
+        started_by: row.get(4)?,
+        started_at: row.get(5)?,
+    }))
+}
+
+/// Counts a turn by `speaker`, adding them to the turn order if they're new.
+/// Returns the updated session, or `None` if the channel has no session.
+pub fn record_rp_turn(conn: &Connection, channel_id: &str, speaker: &str) -> Result<Option<RpSession>> {
+    let Some(mut session) = get_rp_session(conn, channel_id)? else {
+        return Ok(None);
+    };
+    if !session.participants.iter().any(|p| p == speaker) {
+        session.participants.push(speaker.to_string());
+    }
+    session.turns += 1;
+    conn.execute(
+        "UPDATE rp_sessions SET participants = ?2, turns = ?3 WHERE channel_id = ?1",
+        params![
+            channel_id,
+            serde_json::to_string(&session.participants).unwrap_or_default(),
+            session.turns
+        ],
+    )?;
+    Ok(Some(session))
+}
+
+pub fn end_rp_session(conn: &Connection, channel_id: &str) -> Result<bool> {
+    let rows = conn.execute("DELETE FROM rp_sessions WHERE channel_id = ?1", params![channel_id])?;
+    Ok(rows > 0)
+}
+
+/// When a persona takes over: from `starts` to `ends` (`MM-DD`, inclusive)
+/// every year, or, with no dates, in turn with the other undated entries.
+pub struct PersonaScheduleEntry {
+    pub id: i64,
+    pub persona: String,
+    pub starts: Option<String>,
+    pub ends: Option<String>,
+}
+
+pub fn add_persona_schedule(conn: &Connection, persona: &str, starts: Option<&str>, ends: Option<&str>, created_by: &str) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO persona_schedule (persona, starts, ends, created_by) VALUES (?1, ?2, ?3, ?4)",
+        params![persona, starts, ends, created_by],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// The schedule in the order it was set up, which is the rotation order.
+pub fn get_persona_schedule(conn: &Connection) -> Result<Vec<PersonaScheduleEntry>> {
+    let mut stmt = conn.prepare("SELECT id, persona, starts, ends FROM persona_schedule ORDER BY id")?;
+    let entries = stmt
+        .query_map([], |row| {
+            Ok(PersonaScheduleEntry {
+                id: row.get(0)?,
+                persona: row.get(1)?,
+                starts: row.get(2)?,
+                ends: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(entries)
+}
+
+pub fn remove_persona_schedule(conn: &Connection, id: i64) -> Result<bool> {
+    let rows = conn.execute("DELETE FROM persona_schedule WHERE id = ?1", params![id])?;
+    Ok(rows > 0)
+}
+
+/// A failed request waiting in the retry queue; `payload` is a serialized
+/// [`crate::retry::Request`].
+pub struct PendingRequest {
+    pub id: i64,
+    pub guild_id: String,
+    pub channel_id: String,
+    pub user_id: String,
+    pub payload: String,
+    pub attempts: u32,
+}
+
+pub fn enqueue_request(
+    conn: &Connection,
+    guild_id: &str,
+    channel_id: &str,
+    user_id: &str,
+    payload: &str,
+    next_attempt: i64,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO pending_requests (guild_id, channel_id, user_id, payload, next_attempt)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![guild_id, channel_id, user_id, payload, next_attempt],
+    )?;
+    Ok(())
+}
+
+/// Queued requests whose next attempt is at or before `now`, oldest first.
+pub fn due_requests(conn: &Connection, now: i64) -> Result<Vec<PendingRequest>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, guild_id, channel_id, user_id, payload, attempts FROM pending_requests
+         WHERE next_attempt <= ?1 ORDER BY id",
+    )?;
+    let requests = stmt
+        .query_map(params![now], |row| {
+            Ok(PendingRequest {
+                id: row.get(0)?,
+                guild_id: row.get(1)?,
+                channel_id: row.get(2)?,
+                user_id: row.get(3)?,
+                payload: row.get(4)?,
+                attempts: row.get(5)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(requests)
+}
+
+/// Counts a failed attempt and sets when to try again.
+pub fn reschedule_request(conn: &Connection, id: i64, next_attempt: i64) -> Result<()> {
+    conn.execute(
+        "UPDATE pending_requests SET attempts = attempts + 1, next_attempt = ?2 WHERE id = ?1",
+        params![id, next_attempt],
+    )?;
+    Ok(())
+}
+
+pub fn remove_request(conn: &Connection, id: i64) -> Result<bool> {
+    let rows = conn.execute("DELETE FROM pending_requests WHERE id = ?1", params![id])?;
+    Ok(rows > 0)
+}
+
+/// An anonymous confession and who really sent it.
+pub struct Confession {
+    pub user_id: String,
+    pub content: String,
+    pub created_at: i64,
+}
+
+/// Saves a confession, returning its number.
+pub fn record_confession(conn: &Connection, user_id: &str, content: &str) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO confessions (user_id, content) VALUES (?1, ?2)",
+        params![user_id, content],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// When `user_id` last confessed, for the cooldown.
+pub fn last_confession_at(conn: &Connection, user_id: &str) -> Result<Option<i64>> {
+    conn.query_row(
+        "SELECT MAX(created_at) FROM confessions WHERE user_id = ?1",
+        params![user_id],
+        |row| row.get(0),
+    )
+}
+
+pub fn get_confession(conn: &Connection, id: i64) -> Result<Option<Confession>> {
+    let mut stmt = conn.prepare("SELECT user_id, content, created_at FROM confessions WHERE id = ?1")?;
+    let mut rows = stmt.query(params![id])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(Confession {
+            user_id: row.get(0)?,
+            content: row.get(1)?,
+            created_at: row.get(2)?,
+        })),
+        None => Ok(None),
+    }
+}
+
+/// A support ticket: a private thread between `user_id` and the server's
+/// support role. `transcript` is filled in when it's closed.
+#[derive(Debug)]
+pub struct Ticket {
+    pub id: i64,
+    pub thread_id: String,
+    pub user_id: String,
+    pub subject: String,
+    pub transcript: Option<String>,
+    pub created_at: i64,
+    pub closed_at: Option<i64>,
+}
+
+fn ticket_from_row(row: &rusqlite::Row) -> Result<Ticket> {
+    Ok(Ticket {
+        id: row.get(0)?,
+        thread_id: row.get(1)?,
+        user_id: row.get(2)?,
+        subject: row.get(3)?,
+        transcript: row.get(4)?,
+        created_at: row.get(5)?,
+        closed_at: row.get(6)?,
+    })
+}
+
+const TICKET_COLUMNS: &str = "id, thread_id, user_id, subject, transcript, created_at, closed_at";
+
+pub fn open_ticket(conn: &Connection, guild_id: &str, thread_id: &str, user_id: &str, subject: &str) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO tickets (guild_id, thread_id, user_id, subject) VALUES (?1, ?2, ?3, ?4)",
+        params![guild_id, thread_id, user_id, subject],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// The ticket whose thread is `thread_id`, open or closed.
+pub fn get_ticket_by_thread(conn: &Connection, thread_id: &str) -> Result<Option<Ticket>> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM tickets WHERE thread_id = ?1", TICKET_COLUMNS))?;
+    let mut rows = stmt.query(params![thread_id])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(ticket_from_row(row)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn get_ticket(conn: &Connection, guild_id: &str, id: i64) -> Result<Option<Ticket>> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM tickets WHERE guild_id = ?1 AND id = ?2", TICKET_COLUMNS))?;
+    let mut rows = stmt.query(params![guild_id, id])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(ticket_from_row(row)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn log_ticket_message(conn: &Connection, ticket_id: i64, author: &str, content: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO ticket_messages (ticket_id, author, content) VALUES (?1, ?2, ?3)",
+        params![ticket_id, author, content],
+    )?;
+    Ok(())
+}
+
+/// Closes a ticket, turning its logged messages into the stored transcript.
+/// Returns the transcript, or `None` if the ticket was already closed.
+pub fn close_ticket(conn: &Connection, ticket_id: i64, now: i64) -> Result<Option<String>> {
+    let mut stmt = conn.prepare("SELECT author, content, created_at FROM ticket_messages WHERE ticket_id = ?1 ORDER BY id")?;
+    let lines = stmt
+        .query_map(params![ticket_id], |row| {
+            let (author, content, at): (String, String, i64) = (row.get(0)?, row.get(1)?, row.get(2)?);
+            let at = chrono::DateTime::from_timestamp(at, 0)
+                .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default();
+            Ok(format!("[{}] {}: {}", at, author, content))
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    let transcript = lines.join("\n");
+    let rows = conn.execute(
+        "UPDATE tickets SET transcript = ?2, closed_at = ?3 WHERE id = ?1 AND closed_at IS NULL",
+        params![ticket_id, transcript, now],
+    )?;
+    if rows == 0 {
+        return Ok(None);
+    }
+    conn.execute("DELETE FROM ticket_messages WHERE ticket_id = ?1", params![ticket_id])?;
+    Ok(Some(transcript))
+}
+
+/// A canned answer to a frequently asked question, with the embedding of the
+/// question that incoming messages are compared against.
+pub struct FaqEntry {
+    pub id: i64,
+    pub question: String,
+    pub answer: String,
+    pub embedding: Vec<f32>,
+}
+
+/// Embeddings are stored as little-endian `f32`s.
+fn embedding_to_blob(embedding: &[f32]) -> Vec<u8> {
+    embedding.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+fn embedding_from_blob(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+pub fn add_faq(conn: &Connection, guild_id: &str, question: &str, answer: &str, embedding: &[f32], created_by: &str) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO faq (guild_id, question, answer, embedding, created_by) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![guild_id, question, answer, embedding_to_blob(embedding), created_by],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn remove_faq(conn: &Connection, guild_id: &str, id: i64) -> Result<bool> {
+    let rows = conn.execute("DELETE FROM faq WHERE guild_id = ?1 AND id = ?2", params![guild_id, id])?;
+    Ok(rows > 0)
+}
+
+pub fn get_faqs(conn: &Connection, guild_id: &str) -> Result<Vec<FaqEntry>> {
+    let mut stmt = conn.prepare("SELECT id, question, answer, embedding FROM faq WHERE guild_id = ?1 ORDER BY id")?;
+    let entries = stmt
+        .query_map(params![guild_id], |row| {
+            let blob: Vec<u8> = row.get(3)?;
+            Ok(FaqEntry {
+                id: row.get(0)?,
+                question: row.get(1)?,
+                answer: row.get(2)?,
+                embedding: embedding_from_blob(&blob),
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(entries)
+}
+
+/// A piece of a document uploaded with `!kb upload`, with its embedding.
+pub struct KnowledgeChunk {
+    pub source: String,
+    pub content: String,
+    pub embedding: Vec<f32>,
+}
+
+/// Stores a document's chunks, replacing any earlier upload with the same
+/// `source` name. `chunks` pairs each chunk's text with its embedding.
+pub fn save_knowledge(conn: &Connection, guild_id: &str, source: &str, chunks: &[(String, Vec<f32>)], created_by: &str) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "DELETE FROM knowledge_chunks WHERE guild_id = ?1 AND source = ?2",
+        params![guild_id, source],
+    )?;
+    for (content, embedding) in chunks {
+        tx.execute(
+            "INSERT INTO knowledge_chunks (guild_id, source, content, embedding, created_by) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![guild_id, source, content, embedding_to_blob(embedding), created_by],
+        )?;
+    }
+    tx.commit()
+}
+
+pub fn get_knowledge_chunks(conn: &Connection, guild_id: &str) -> Result<Vec<KnowledgeChunk>> {
+    let mut stmt = conn.prepare("SELECT source, content, embedding FROM knowledge_chunks WHERE guild_id = ?1 ORDER BY id")?;
+    let chunks = stmt
+        .query_map(params![guild_id], |row| {
+            let blob: Vec<u8> = row.get(2)?;
+            Ok(KnowledgeChunk {
+                source: row.get(0)?,
+                content: row.get(1)?,
+                embedding: embedding_from_blob(&blob),
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(chunks)
+}
+
+/// Each uploaded document's name and how many chunks it has.
+pub fn get_knowledge_sources(conn: &Connection, guild_id: &str) -> Result<Vec<(String, usize)>> {
+    let mut stmt = conn.prepare(
+        "SELECT source, COUNT(*) FROM knowledge_chunks WHERE guild_id = ?1 GROUP BY source ORDER BY source",
+    )?;
+    let sources = stmt
+        .query_map(params![guild_id], |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as usize)))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(sources)
+}
+
+/// Removes a document. Returns false if there was no such document.
+pub fn remove_knowledge_source(conn: &Connection, guild_id: &str, source: &str) -> Result<bool> {
+    let rows = conn.execute(
+        "DELETE FROM knowledge_chunks WHERE guild_id = ?1 AND source = ?2",
+        params![guild_id, source],
+    )?;
+    Ok(rows > 0)
+}
+
+/// An exchange someone pinned with 📌.
+pub struct Memory {
+    pub prompt: String,
+    pub reply: String,
+    pub saved_by: String,
+}
+
+/// Pins an exchange; `asked_by` is the user ID of whoever wrote the prompt.
+pub fn save_memory(conn: &Connection, channel_id: &str, prompt: &str, reply: &str, saved_by: &str, asked_by: Option<&str>) -> Result<()> {
+    conn.execute(
+        "INSERT INTO memories (channel_id, prompt, reply, saved_by, asked_by) VALUES (?1, encrypt(?2), encrypt(?3), ?4, ?5)",
+        params![channel_id, prompt, reply, saved_by, asked_by],
+    )?;
+    Ok(())
+}
+
+/// Records a character's current collection (`kind` is e.g. `mounts`) as
+/// `(id, name)` entries, noting when new ones first showed up and dropping
+/// ones that are gone. Returns how many are new since the last sync.
+pub fn sync_collection(conn: &Connection, character: &str, kind: &str, entries: &[(u64, String)], now: i64) -> Result<usize> {
+    let known: Vec<i64> = conn
+        .prepare("SELECT entry_id FROM collection_entries WHERE character = ?1 AND kind = ?2")?
+        .query_map(params![character, kind], |row| row.get(0))?
+        .collect::<Result<Vec<_>>>()?;
+    let first_sync = known.is_empty();
+    let first_seen = if first_sync { 0 } else { now };
+
+    let tx = conn.unchecked_transaction()?;
+    let mut added = 0;
+    for (id, name) in entries {
+        added += tx.execute(
+            "INSERT OR IGNORE INTO collection_entries (character, kind, entry_id, name, first_seen)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![character, kind, *id as i64, name, first_seen],
+        )?;
+    }
+    for id in known.iter().filter(|&&id| !entries.iter().any(|(e, _)| *e as i64 == id)) {
+        tx.execute(
+            "DELETE FROM collection_entries WHERE character = ?1 AND kind = ?2 AND entry_id = ?3",
+            params![character, kind, id],
+        )?;
+    }
+    tx.commit()?;
+    Ok(if first_sync { 0 } else { added })
+}
+
+/// How many entries of a collection a character had at the last sync, if ever synced.
+pub fn collection_count(conn: &Connection, character: &str, kind: &str) -> Result<Option<usize>> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM collection_entries WHERE character = ?1 AND kind = ?2",
+        params![character, kind],
+        |row| row.get(0),
+    )?;
+    Ok((count > 0).then_some(count as usize))
+}
+
+/// Names of the collection entries a character obtained most recently, newest first.
+/// Entries found on the first sync don't count, since when they came is unknown.
+pub fn recent_collection_entries(conn: &Connection, character: &str, kind: &str, limit: usize) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM collection_entries WHERE character = ?1 AND kind = ?2 AND first_seen > 0
+         ORDER BY first_seen DESC, entry_id DESC LIMIT ?3",
+    )?;
+    let names = stmt
+        .query_map(params![character, kind, limit as i64], |row| row.get(0))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(names)
+}
+
+pub struct LinkedAccount {
+    pub game: String,
+    pub account: String,
+}
+
+/// Links `account` in `game` to a Discord user. Returns false if it already was.
+pub fn link_account(conn: &Connection, user_id: &str, game: &str, account: &str) -> Result<bool> {
+    let rows = conn.execute(
+        "INSERT OR IGNORE INTO linked_accounts (user_id, game, account) VALUES (?1, ?2, ?3)",
+        params![user_id, game, account],
+    )?;
+    Ok(rows > 0)
+}
+
+pub fn unlink_account(conn: &Connection, user_id: &str, game: &str, account: &str) -> Result<bool> {
+    let rows = conn.execute(
+        "DELETE FROM linked_accounts WHERE user_id = ?1 AND game = ?2 AND account = ?3",
+        params![user_id, game, account],
+    )?;
+    Ok(rows > 0)
+}
+
+/// Unlinks all of a user's accounts in `game`, returning how many there were.
+pub fn unlink_game(conn: &Connection, user_id: &str, game: &str) -> Result<usize> {
+    conn.execute(
+        "DELETE FROM linked_accounts WHERE user_id = ?1 AND game = ?2",
+        params![user_id, game],
+    )
+}
+
+/// Every `(user ID, account)` linked in `game`.
+pub fn get_linked_accounts_for_game(conn: &Connection, game: &str) -> Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare("SELECT user_id, account FROM linked_accounts WHERE game = ?1 ORDER BY user_id")?;
+    let accounts = stmt
+        .query_map(params![game], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(accounts)
+}
+
+/// A user's linked accounts, by game then in the order they were linked.
+pub fn get_linked_accounts(conn: &Connection, user_id: &str) -> Result<Vec<LinkedAccount>> {
+    let mut stmt = conn.prepare(
+        "SELECT game, account FROM linked_accounts WHERE user_id = ?1
+         ORDER BY game, linked_at, account",
+    )?;
+    let accounts = stmt
+        .query_map(params![user_id], |row| {
+            Ok(LinkedAccount {
+                game: row.get(0)?,
+                account: row.get(1)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(accounts)
+}
+
+pub struct TrackedRiotAccount {
+    pub puuid: String,
+    pub riot_id: String,
+    pub added_by: String,
+    pub solo_rank: Option<String>,
+}
+
+/// Tracks a Riot account. Returns false if it already was, in which case its
+/// Riot ID is updated in case the player renamed.
+pub fn add_riot_account(conn: &Connection, puuid: &str, riot_id: &str, added_by: &str) -> Result<bool> {
+    let rows = conn.execute(
+        "INSERT OR IGNORE INTO riot_accounts (puuid, riot_id, added_by) VALUES (?1, ?2, ?3)",
+        params![puuid, riot_id, added_by],
+    )?;
+    if rows == 0 {
+        conn.execute(
+            "UPDATE riot_accounts SET riot_id = ?2 WHERE puuid = ?1",
+            params![puuid, riot_id],
+        )?;
+    }
+    Ok(rows > 0)
+}
+
+pub fn remove_riot_account(conn: &Connection, riot_id: &str) -> Result<bool> {
+    let rows = conn.execute("DELETE FROM riot_accounts WHERE riot_id = ?1", params![riot_id])?;
+    Ok(rows > 0)
+}
+
+pub fn get_riot_accounts(conn: &Connection) -> Result<Vec<TrackedRiotAccount>> {
+    let mut stmt = conn.prepare("SELECT puuid, riot_id, added_by, solo_rank FROM riot_accounts ORDER BY riot_id")?;
+    let accounts = stmt
+        .query_map([], |row| {
+            Ok(TrackedRiotAccount {
+                puuid: row.get(0)?,
+                riot_id: row.get(1)?,
+                added_by: row.get(2)?,
+                solo_rank: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(accounts)
+}
+
+pub fn set_riot_solo_rank(conn: &Connection, puuid: &str, rank: Option<&str>) -> Result<()> {
+    conn.execute(
+        "UPDATE riot_accounts SET solo_rank = ?2 WHERE puuid = ?1",
+        params![puuid, rank],
+    )?;
+    Ok(())
+}
+
+/// Stores a WoW token price (in copper). Returns false if that update was already stored.
+pub fn record_wow_token_price(conn: &Connection, updated_at: i64, price: u64) -> Result<bool> {
+    let rows = conn.execute(
+        "INSERT OR IGNORE INTO wow_token_prices (updated_at, price) VALUES (?1, ?2)",
+        params![updated_at, price as i64],
+    )?;
+    Ok(rows > 0)
+}
+
+/// The last `limit` stored WoW token prices as `(updated_at, copper)`, oldest first.
+pub fn get_wow_token_prices(conn: &Connection, limit: usize) -> Result<Vec<(i64, u64)>> {
+    let mut stmt = conn.prepare(
+        "SELECT updated_at, price FROM (
+             SELECT updated_at, price FROM wow_token_prices ORDER BY updated_at DESC LIMIT ?1
+         ) ORDER BY updated_at",
+    )?;
+    let prices = stmt
+        .query_map(params![limit as i64], |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as u64)))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(prices)
+}
+
+/// Ticks step `step` (1-based) of a character's attunement checklist for `raid`,
+/// or unticks it with `done_by` `None`.
+pub fn set_attunement_step(conn: &Connection, character: &str, raid: &str, step: u32, done_by: Option<&str>) -> Result<()> {
+    match done_by {
+        Some(done_by) => conn.execute(
+            "INSERT OR IGNORE INTO attunement_steps (character, raid, step, done_by) VALUES (?1, ?2, ?3, ?4)",
+            params![character, raid, step, done_by],
+        )?,
+        None => conn.execute(
+            "DELETE FROM attunement_steps WHERE character = ?1 AND raid = ?2 AND step = ?3",
+            params![character, raid, step],
+        )?,
+    };
+    Ok(())
+}
+
+/// The ticked steps of a character's attunement checklist for `raid`, in order.
+pub fn get_attunement_steps(conn: &Connection, character: &str, raid: &str) -> Result<Vec<u32>> {
+    let mut stmt = conn.prepare("SELECT step FROM attunement_steps WHERE character = ?1 AND raid = ?2 ORDER BY step")?;
+    let steps = stmt
+        .query_map(params![character, raid], |row| row.get(0))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(steps)
+}
+
+/// Adds an item to a class's checklist template. Returns false if it's already there.
+pub fn add_checklist_item(conn: &Connection, class: &str, item: &str) -> Result<bool> {
+    let rows = conn.execute(
+        "INSERT OR IGNORE INTO checklist_items (class, item) VALUES (?1, ?2)",
+        params![class, item],
+    )?;
+    Ok(rows > 0)
+}
+
+/// Removes an item from a class's checklist template. Returns false if it wasn't there.
+pub fn remove_checklist_item(conn: &Connection, class: &str, item: &str) -> Result<bool> {
+    let rows = conn.execute(
+        "DELETE FROM checklist_items WHERE class = ?1 AND item = ?2",
+        params![class, item],
+    )?;
+    Ok(rows > 0)
+}
+
+/// A class's checklist template, in the order items were added.
+pub fn get_checklist_items(conn: &Connection, class: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT item FROM checklist_items WHERE class = ?1 ORDER BY id")?;
+    let items = stmt.query_map(params![class], |row| row.get(0))?.collect::<Result<Vec<_>>>()?;
+    Ok(items)
+}
+
+/// Ticks or unticks a checklist item for a character.
+pub fn set_checklist_tick(conn: &Connection, character: &str, item: &str, done: bool) -> Result<()> {
+    if done {
+        conn.execute(
+            "INSERT OR IGNORE INTO checklist_ticks (character, item) VALUES (?1, ?2)",
+            params![character, item],
+        )?;
+    } else {
+        conn.execute(
+            "DELETE FROM checklist_ticks WHERE character = ?1 AND item = ?2",
+            params![character, item],
+        )?;
+    }
+    Ok(())
+}
+
+/// The checklist items a character has ticked.
+pub fn get_checklist_ticks(conn: &Connection, character: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT item FROM checklist_ticks WHERE character = ?1 ORDER BY done_at")?;
+    let items = stmt.query_map(params![character], |row| row.get(0))?.collect::<Result<Vec<_>>>()?;
+    Ok(items)
+}
+
+/// A feature flag's global setting or one guild's override.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlagSetting {
+    pub name: String,
+    /// `None` for the global setting.
+    pub guild_id: Option<String>,
+    pub enabled: bool,
+    /// Share of guilds (0-100) the flag is on for; only used globally.
+    pub rollout_percent: u8,
+}
+
+/// Turns a flag on or off everywhere (`guild_id` `None`) or in one guild.
+pub fn set_flag(conn: &Connection, name: &str, guild_id: Option<&str>, enabled: bool) -> Result<()> {
+    conn.execute(
+        "INSERT INTO feature_flags (name, guild_id, enabled) VALUES (?1, ?2, ?3)
+         ON CONFLICT(name, guild_id) DO UPDATE SET enabled = excluded.enabled",
+        params![name, guild_id.unwrap_or_default(), enabled],
+    )?;
+    Ok(())
+}
+
+/// Rolls a flag out to `percent` of guilds.
+pub fn set_flag_rollout(conn: &Connection, name: &str, percent: u8) -> Result<()> {
+    conn.execute(
+        "INSERT INTO feature_flags (name, guild_id, rollout_percent) VALUES (?1, '', ?2)
+         ON CONFLICT(name, guild_id) DO UPDATE SET rollout_percent = excluded.rollout_percent",
+        params![name, percent.min(100)],
+    )?;
+    Ok(())
+}
+
+/// Removes a flag's global setting or a guild's override. Returns false if there was none.
+pub fn clear_flag(conn: &Connection, name: &str, guild_id: Option<&str>) -> Result<bool> {
+    let rows = conn.execute(
+        "DELETE FROM feature_flags WHERE name = ?1 AND guild_id = ?2",
+        params![name, guild_id.unwrap_or_default()],
+    )?;
+    Ok(rows > 0)
+}
+
+fn flag_from_row(row: &rusqlite::Row) -> Result<FlagSetting> {
+    let guild_id: String = row.get(1)?;
+    Ok(FlagSetting {
+        name: row.get(0)?,
+        guild_id: Some(guild_id).filter(|g| !g.is_empty()),
+        enabled: row.get(2)?,
+        rollout_percent: row.get(3)?,
+    })
+}
+
+/// A flag's global setting (`guild_id` `None`) or a guild's override, if set.
+pub fn get_flag(conn: &Connection, name: &str, guild_id: Option<&str>) -> Result<Option<FlagSetting>> {
+    conn.query_row(
+        "SELECT name, guild_id, enabled, rollout_percent FROM feature_flags WHERE name = ?1 AND guild_id = ?2",
+        params![name, guild_id.unwrap_or_default()],
+        |row| flag_from_row(row).map(Some),
+    )
+    .or_else(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => Ok(None),
+        e => Err(e),
+    })
+}
+
+/// Every flag setting, global ones before their guild overrides.
+pub fn list_flags(conn: &Connection) -> Result<Vec<FlagSetting>> {
+    let mut stmt = conn.prepare(
+        "SELECT name, guild_id, enabled, rollout_percent FROM feature_flags ORDER BY name, guild_id",
+    )?;
+    let flags = stmt.query_map([], flag_from_row)?.collect::<Result<Vec<_>>>()?;
+    Ok(flags)
+}
+
+/// A channel's pinned exchanges, newest first.
+pub fn get_memories(conn: &Connection, channel_id: &str, limit: usize) -> Result<Vec<Memory>> {
+    let mut stmt = conn.prepare(
+        "SELECT decrypt(prompt), decrypt(reply), saved_by FROM memories WHERE channel_id = ?1 ORDER BY id DESC LIMIT ?2",
+    )?;
+    let memories = stmt
+        .query_map(params![channel_id, limit as i64], |row| {
+            Ok(Memory {
+                prompt: row.get(0)?,
+                reply: row.get(1)?,
+                saved_by: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(memories)
+}
+
+/// An admin-defined Rhai script. `trigger` is `message` (run when a message
+/// in its guild matches the `pattern` regex) or `schedule` (run every
+/// `pattern` minutes, replying in `channel_id`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Script {
+    /// Empty for a script saved before scripts belonged to a guild.
+    pub guild_id: String,
+    pub name: String,
+    pub trigger: String,
+    pub pattern: String,
+    pub channel_id: String,
+    pub source: String,
+    pub created_by: String,
+    pub last_run: i64,
+}
+
+const SCRIPT_COLUMNS: &str = "guild_id, name, trigger, pattern, channel_id, source, created_by, last_run";
+
+fn script_from_row(row: &rusqlite::Row) -> Result<Script> {
+    Ok(Script {
+        guild_id: row.get(0)?,
+        name: row.get(1)?,
+        trigger: row.get(2)?,
+        pattern: row.get(3)?,
+        channel_id: row.get(4)?,
+        source: row.get(5)?,
+        created_by: row.get(6)?,
+        last_run: row.get(7)?,
+    })
+}
+
+/// Adds a script, replacing the guild's script with the same name.
+pub fn save_script(conn: &Connection, script: &Script) -> Result<()> {
+    conn.execute(
+        "INSERT INTO scripts (guild_id, name, trigger, pattern, channel_id, source, created_by, last_run)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+         ON CONFLICT(guild_id, name) DO UPDATE SET trigger = excluded.trigger, pattern = excluded.pattern,
+             channel_id = excluded.channel_id, source = excluded.source,
+             created_by = excluded.created_by, last_run = excluded.last_run",
+        params![
+            script.guild_id,
+            script.name,
+            script.trigger,
+            script.pattern,
+            script.channel_id,
+            script.source,
+            script.created_by,
+            script.last_run
+        ],
+    )?;
+    Ok(())
+}
+
+/// Removes a guild's script and its stored data. Returns false if the guild
+/// has no such script.
+pub fn remove_script(conn: &Connection, guild_id: &str, name: &str) -> Result<bool> {
+    let tx = conn.unchecked_transaction()?;
+    let rows = tx.execute("DELETE FROM scripts WHERE guild_id = ?1 AND name = ?2", params![guild_id, name])?;
+    if rows > 0 {
+        tx.execute(
+            "DELETE FROM script_data WHERE guild_id = ?1 AND script = ?2",
+            params![guild_id, name],
+        )?;
+    }
+    tx.commit()?;
+    Ok(rows > 0)
+}
+
+pub fn get_script(conn: &Connection, guild_id: &str, name: &str) -> Result<Option<Script>> {
+    conn.query_row(
+        &format!("SELECT {} FROM scripts WHERE guild_id = ?1 AND name = ?2", SCRIPT_COLUMNS),
+        params![guild_id, name],
+        script_from_row,
+    )
+    .map(Some)
+    .or_else(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => Ok(None),
+        e => Err(e),
+    })
+}
+
+/// A guild's scripts, or with `None` every guild's, for the scheduler.
+pub fn get_scripts(conn: &Connection, guild_id: Option<&str>) -> Result<Vec<Script>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM scripts WHERE ?1 IS NULL OR guild_id = ?1 ORDER BY name",
+        SCRIPT_COLUMNS
+    ))?;
+    let scripts = stmt.query_map(params![guild_id], script_from_row)?.collect::<Result<Vec<_>>>()?;
+    Ok(scripts)
+}
+
+/// Gives scripts saved before scripts belonged to a guild, with their data, to
+/// the guild of the channel they were added in, once a message there says which
+/// guild that is. One whose name the guild already uses stays unclaimed.
+pub fn claim_scripts(conn: &Connection, guild_id: &str, channel_id: &str) -> Result<usize> {
+    let tx = conn.unchecked_transaction()?;
+    let names = tx
+        .prepare("SELECT name FROM scripts WHERE guild_id = '' AND channel_id = ?1")?
+        .query_map(params![channel_id], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>>>()?;
+    let mut claimed = 0;
+    for name in names {
+        let rows = tx.execute(
+            "UPDATE OR IGNORE scripts SET guild_id = ?1 WHERE guild_id = '' AND name = ?2",
+            params![guild_id, name],
+        )?;
+        if rows > 0 {
+            tx.execute(
+                "UPDATE OR IGNORE script_data SET guild_id = ?1 WHERE guild_id = '' AND script = ?2",
+                params![guild_id, name],
+            )?;
+            claimed += rows;
+        }
+    }
+    tx.commit()?;
+    Ok(claimed)
+}
+
+pub fn set_script_last_run(conn: &Connection, guild_id: &str, name: &str, at: i64) -> Result<()> {
+    conn.execute(
+        "UPDATE scripts SET last_run = ?3 WHERE guild_id = ?1 AND name = ?2",
+        params![guild_id, name, at],
+    )?;
+    Ok(())
+}
+
+/// A value a script stored with `set(key, value)`. Each script has its own keys.
+pub fn get_script_data(conn: &Connection, guild_id: &str, script: &str, key: &str) -> Result<Option<String>> {
+    let mut stmt = conn.prepare("SELECT value FROM script_data WHERE guild_id = ?1 AND script = ?2 AND key = ?3")?;
+    let mut rows = stmt.query(params![guild_id, script, key])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(row.get(0)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn set_script_data(conn: &Connection, guild_id: &str, script: &str, key: &str, value: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO script_data (guild_id, script, key, value) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(guild_id, script, key) DO UPDATE SET value = excluded.value",
+        params![guild_id, script, key, value],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        init(&conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_init_creates_schema() {
+        let conn = setup();
+        // Verify tables exist by querying them
+        conn.prepare("SELECT * FROM config").unwrap();
+        conn.prepare("SELECT * FROM messages").unwrap();
+    }
+
+    #[test]
+    fn test_default_system_prompt() {
+        let conn = setup();
+        let prompt = get_config(&conn, None, "system_prompt").unwrap().unwrap();
+        assert_eq!(prompt, DEFAULT_SYSTEM_PROMPT);
+    }
+
+    #[test]
+    fn test_set_and_get_config() {
+        let conn = setup();
+        set_config(&conn, None, "test_key", "test_value").unwrap();
+        assert_eq!(
+            get_config(&conn, None, "test_key").unwrap(),
+            Some("test_value".to_string())
+        );
+
+        // Overwrite
+        set_config(&conn, None, "test_key", "new_value").unwrap();
+        assert_eq!(
+            get_config(&conn, None, "test_key").unwrap(),
+            Some("new_value".to_string())
+        );
+    }
+
+    #[test]
+    fn test_guild_isolation() {
+        let conn = setup();
+        set_config(&conn, Some("1"), "system_prompt", "You are a pirate.").unwrap();
+        set_config(&conn, Some("2"), "response_cap", "40").unwrap();
+        assert_eq!(get_config(&conn, Some("1"), "system_prompt").unwrap().as_deref(), Some("You are a pirate."));
+        // Other guilds and DMs keep the bot-wide prompt
+        assert_eq!(get_config(&conn, Some("2"), "system_prompt").unwrap().as_deref(), Some(DEFAULT_SYSTEM_PROMPT));
+        assert_eq!(get_config(&conn, None, "system_prompt").unwrap().as_deref(), Some(DEFAULT_SYSTEM_PROMPT));
+        assert_eq!(get_response_cap(&conn, Some("2")), 40);
+        assert_eq!(get_response_cap(&conn, Some("1")), DEFAULT_RESPONSE_CAP);
+        set_config(&conn, None, "response_cap", "20").unwrap();
+        assert_eq!(get_response_cap(&conn, Some("1")), 20);
+        assert_eq!(get_response_cap(&conn, Some("2")), 40);
+
+        // Setting a prompt replaces the guild's persona; deleting falls back
+        set_config(&conn, Some("1"), "persona", "ghost").unwrap();
+        set_system_prompt(&conn, Some("1"), "You are a ninja.").unwrap();
+        assert_eq!(get_config(&conn, Some("1"), "persona").unwrap(), None);
+        assert!(delete_config(&conn, Some("1"), "system_prompt").unwrap());
+        assert_eq!(get_config(&conn, Some("1"), "system_prompt").unwrap().as_deref(), Some(DEFAULT_SYSTEM_PROMPT));
+        assert!(!delete_config(&conn, Some("1"), "system_prompt").unwrap());
+        // Guild rows aren't listed as bot-wide settings
+        assert_eq!(get_all_config(&conn).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_migrate_guild_config() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE config (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+             INSERT INTO config VALUES ('system_prompt', 'You are rude.'), ('response_cap:7', '25'),
+                 ('retention:archive', '30'), ('listen:99', 'true'), ('preamble:7', 'off'),
+                 ('blocklist_mode:7', 'regenerate');",
+        )
+        .unwrap();
+        init(&conn).unwrap();
+        assert_eq!(get_response_cap(&conn, Some("7")), 25);
+        assert_eq!(get_response_cap(&conn, None), DEFAULT_RESPONSE_CAP);
+        assert!(!is_preamble_enabled(&conn, "7"));
+        assert_eq!(get_blocklist_mode(&conn, "7").unwrap().as_deref(), Some("regenerate"));
+        assert_eq!(get_blocklist_mode(&conn, "8").unwrap(), None);
+        assert_eq!(get_retention_days(&conn, "archive").unwrap(), Some(30));
+        assert!(is_listening(&conn, "99").unwrap());
+        assert_eq!(get_config(&conn, None, "system_prompt").unwrap().as_deref(), Some("You are rude."));
+        // Only runs once
+        init(&conn).unwrap();
+        assert_eq!(get_response_cap(&conn, Some("7")), 25);
+    }
+
+    #[test]
+    fn test_get_all_config() {
+        let conn = setup();
+        set_config(&conn, None, "b_key", "2").unwrap();
+        set_config(&conn, None, "a_key", "1").unwrap();
+
+        let entries = get_all_config(&conn).unwrap();
+        let keys: Vec<_> = entries.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, ["a_key", "b_key", "system_prompt"]);
+        assert_eq!(entries[0].1, "1");
+    }
+
+    #[test]
+    fn test_delete_config() {
+        let conn = setup();
+        set_config(&conn, None, "test_key", "value").unwrap();
+        assert!(delete_config(&conn, None, "test_key").unwrap());
+        assert_eq!(get_config(&conn, None, "test_key").unwrap(), None);
+        assert!(!delete_config(&conn, None, "test_key").unwrap());
+    }
+
+    #[test]
+    fn test_response_cap_default_and_override() {
+        let conn = setup();
+        assert_eq!(get_response_cap(&conn, None), DEFAULT_RESPONSE_CAP);
+
+        set_config(&conn, None, "response_cap", "42").unwrap();
+        assert_eq!(get_response_cap(&conn, None), 42);
+
+        // Garbage falls back to the default
+        set_config(&conn, None, "response_cap", "lots").unwrap();
+        assert_eq!(get_response_cap(&conn, None), DEFAULT_RESPONSE_CAP);
+    }
+
+    #[test]
+    fn test_store_and_retrieve_messages() {
+        let conn = setup();
+        store_message(&conn, "chan1", "user", "hello").unwrap();
+        store_message(&conn, "chan1", "assistant", "hi there").unwrap();
 
         let msgs = get_recent_messages(&conn, "chan1", 10).unwrap();
         assert_eq!(msgs.len(), 2);
@@ -191,32 +3478,533 @@ mod tests {
     }
 
     #[test]
-    fn test_message_history_limit() {
+    fn test_message_authors() {
+        let conn = setup();
+        store_message_from(&conn, "chan1", "user", Some("Joe"), None, None, "hello").unwrap();
+        store_message(&conn, "chan1", "assistant", "hi there").unwrap();
+
+        let messages = get_recent_messages(&conn, "chan1", 10).unwrap();
+        assert_eq!(messages[0].author.as_deref(), Some("Joe"));
+        assert_eq!(messages[1].author, None);
+    }
+
+    #[test]
+    fn test_prune_messages() {
+        let conn = setup();
+        for i in 0..5 {
+            store_message(&conn, "chan1", "user", &format!("msg {}", i)).unwrap();
+        }
+        store_message(&conn, "chan2", "user", "elsewhere").unwrap();
+
+        assert_eq!(prune_messages(&conn, "chan1", 2).unwrap(), 3);
+        let msgs = get_recent_messages(&conn, "chan1", 10).unwrap();
+        assert_eq!(msgs.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(), ["msg 3", "msg 4"]);
+        assert_eq!(get_recent_messages(&conn, "chan2", 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_transfer_messages() {
+        let conn = setup();
+        store_message_from(&conn, "chan1", "user", Some("Joe"), None, Some("10"), "hello").unwrap();
+        store_message(&conn, "chan1", "assistant", "Go away.").unwrap();
+        store_message(&conn, "chan2", "user", "earlier").unwrap();
+
+        assert_eq!(transfer_messages(&conn, "chan1", "chan2", false).unwrap(), 2);
+        let contents = |key: &str| {
+            get_recent_messages(&conn, key, 10).unwrap().into_iter().map(|m| m.content).collect::<Vec<_>>()
+        };
+        assert_eq!(contents("chan2"), ["earlier", "hello", "Go away."]);
+        assert_eq!(contents("chan1"), ["hello", "Go away."]);
+        assert_eq!(get_recent_messages(&conn, "chan2", 10).unwrap()[1].author.as_deref(), Some("Joe"));
+
+        assert_eq!(transfer_messages(&conn, "chan1", "chan3", true).unwrap(), 2);
+        assert!(contents("chan1").is_empty());
+        assert_eq!(contents("chan3"), ["hello", "Go away."]);
+    }
+
+    #[test]
+    fn test_checkpoints() {
+        let conn = setup();
+        let contents = |key: &str| {
+            get_recent_messages(&conn, key, 10).unwrap().into_iter().map(|m| m.content).collect::<Vec<_>>()
+        };
+        store_message_from(&conn, "chan1", "user", Some("Joe"), None, Some("10"), "Once upon a time").unwrap();
+        store_message(&conn, "chan1", "assistant", "There was a dragon.").unwrap();
+        assert_eq!(save_checkpoint(&conn, "chan2", "Act 1", "user1").unwrap(), 0);
+        assert!(get_checkpoints(&conn, "chan2").unwrap().is_empty());
+        assert_eq!(save_checkpoint(&conn, "chan1", "Act 1", "user1").unwrap(), 2);
+
+        store_message(&conn, "chan1", "user", "The dragon dies").unwrap();
+        assert_eq!(load_checkpoint(&conn, "chan1", "act 1").unwrap(), Some(2));
+        assert_eq!(contents("chan1"), ["Once upon a time", "There was a dragon."]);
+        assert_eq!(get_recent_messages(&conn, "chan1", 10).unwrap()[0].author.as_deref(), Some("Joe"));
+        // Checkpoints belong to one history
+        assert_eq!(load_checkpoint(&conn, "chan2", "act 1").unwrap(), None);
+
+        // Saving again under the same name replaces it
+        clear_messages(&conn, "chan1").unwrap();
+        store_message(&conn, "chan1", "user", "A new story").unwrap();
+        assert_eq!(save_checkpoint(&conn, "chan1", "act 1", "user1").unwrap(), 1);
+        assert_eq!(get_checkpoints(&conn, "chan1").unwrap().len(), 1);
+        assert_eq!(load_checkpoint(&conn, "chan1", "Act 1").unwrap(), Some(1));
+
+        assert!(delete_checkpoint(&conn, "chan1", "act 1").unwrap());
+        assert!(!delete_checkpoint(&conn, "chan1", "act 1").unwrap());
+        assert!(get_checkpoints(&conn, "chan1").unwrap().is_empty());
+        let orphans: i64 = conn.query_row("SELECT COUNT(*) FROM checkpoint_messages", [], |row| row.get(0)).unwrap();
+        assert_eq!(orphans, 0);
+    }
+
+    #[test]
+    fn test_moderation_log() {
+        let conn = setup();
+        log_moderation(&conn, "g1", "mod1", "automod_addword", "darn").unwrap();
+        log_moderation(&conn, "g1", "user1", "automod", "AutoMod blocked a message").unwrap();
+        log_moderation(&conn, "g2", "mod2", "automod_addword", "heck").unwrap();
+
+        let log = get_moderation_log(&conn, "g1", 10).unwrap();
+        assert_eq!(log.iter().map(|e| e.action.as_str()).collect::<Vec<_>>(), ["automod", "automod_addword"]);
+        assert_eq!(log[1].user_id, "mod1");
+        assert_eq!(get_moderation_log(&conn, "g1", 1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_temp_channels() {
+        let conn = setup();
+        add_temp_channel(&conn, "v1", "g1", "user1").unwrap();
+        add_temp_channel(&conn, "v2", "g1", "user2").unwrap();
+        assert_eq!(get_temp_channels(&conn, "g1").unwrap(), ["v1", "v2"]);
+
+        assert!(set_temp_channel_member(&conn, "user1", Some("v1")).unwrap().is_empty());
+        assert!(set_temp_channel_member(&conn, "user2", Some("v1")).unwrap().is_empty());
+        // Joining a channel that isn't a temp one isn't tracked
+        assert!(set_temp_channel_member(&conn, "user3", Some("lobby")).unwrap().is_empty());
+
+        // The first to leave doesn't empty it, the last does
+        assert!(set_temp_channel_member(&conn, "user1", Some("lobby")).unwrap().is_empty());
+        assert_eq!(set_temp_channel_member(&conn, "user2", None).unwrap(), ["v1"]);
+
+        reset_temp_channel_members(&conn, "v2", &["user3".to_string()]).unwrap();
+        assert_eq!(set_temp_channel_member(&conn, "user3", None).unwrap(), ["v2"]);
+
+        assert!(remove_temp_channel(&conn, "v1").unwrap());
+        assert!(!remove_temp_channel(&conn, "v1").unwrap());
+        assert_eq!(get_temp_channels(&conn, "g1").unwrap(), ["v2"]);
+    }
+
+    #[test]
+    fn test_quiet_hours() {
+        let conn = setup();
+        set_quiet_hours(&conn, "c1", "g1", 1380, 420, None, "admin").unwrap();
+        set_quiet_hours(&conn, "c2", "g2", 60, 120, Some(30), "admin").unwrap();
+        assert_eq!(get_quiet_hours(&conn, None).unwrap().len(), 2);
+        let list = get_quiet_hours(&conn, Some("g1")).unwrap();
+        assert_eq!(list.len(), 1);
+        assert_eq!((list[0].start_minute, list[0].end_minute, list[0].slowmode), (1380, 420, None));
+        assert!(!list[0].active);
+
+        // Saved state survives the window being changed while it's on
+        start_quiet_hours(&conn, "c1", None, true, Some((1 << 40, 2048))).unwrap();
+        set_quiet_hours(&conn, "c1", "g1", 1320, 420, None, "admin").unwrap();
+        let on = &get_quiet_hours(&conn, Some("g1")).unwrap()[0];
+        assert!(on.active && on.locked);
+        assert_eq!(on.start_minute, 1320);
+        assert_eq!(on.saved_overwrite, Some((1 << 40, 2048)));
+
+        end_quiet_hours(&conn, "c1").unwrap();
+        let off = &get_quiet_hours(&conn, Some("g1")).unwrap()[0];
+        assert!(!off.active && !off.locked);
+        assert_eq!(off.saved_overwrite, None);
+
+        start_quiet_hours(&conn, "c2", Some(5), false, None).unwrap();
+        let removed = remove_quiet_hours(&conn, "c2").unwrap().unwrap();
+        assert_eq!(removed.saved_slowmode, Some(5));
+        assert!(remove_quiet_hours(&conn, "c2").unwrap().is_none());
+        assert!(get_quiet_hours(&conn, Some("g2")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_invites() {
+        let conn = setup();
+        let invite = |code: &str, inviter: Option<&str>, uses| InviteUse {
+            code: code.to_string(),
+            inviter_id: inviter.map(str::to_string),
+            uses,
+        };
+        set_invite_uses(&conn, "g1", &[invite("abc", Some("u1"), 3), invite("vanity", None, 9)]).unwrap();
+        set_invite_uses(&conn, "g1", &[invite("abc", Some("u1"), 4)]).unwrap();
+        assert_eq!(get_invite_uses(&conn, "g1").unwrap(), [invite("abc", Some("u1"), 4)]);
+        assert!(get_invite_uses(&conn, "g2").unwrap().is_empty());
+
+        record_invite_join(&conn, "g1", "new1", Some(&invite("abc", Some("u1"), 4))).unwrap();
+        record_invite_join(&conn, "g1", "new2", Some(&invite("abc", Some("u1"), 5))).unwrap();
+        record_invite_join(&conn, "g1", "new3", Some(&invite("xyz", Some("u2"), 1))).unwrap();
+        record_invite_join(&conn, "g1", "new4", None).unwrap();
+
+        let sources = get_join_sources(&conn, "g1", None).unwrap();
+        let summary: Vec<_> = sources.iter().map(|s| (s.code.as_deref(), s.joins)).collect();
+        assert_eq!(summary, [(Some("abc"), 2), (None, 1), (Some("xyz"), 1)]);
+        let by_u2 = get_join_sources(&conn, "g1", Some("u2")).unwrap();
+        assert_eq!(by_u2.len(), 1);
+        assert_eq!(by_u2[0].inviter_id.as_deref(), Some("u2"));
+    }
+
+    #[test]
+    fn test_message_archives() {
+        let conn = setup();
+        set_listening(&conn, "chan1", true).unwrap();
+        set_listening(&conn, "chan2", true).unwrap();
+        set_listening(&conn, "chan2", false).unwrap();
+        assert_eq!(get_listening_channels(&conn).unwrap(), ["chan1"]);
+
+        store_message_from(&conn, "chan1", "user", Some("Joe"), None, Some("10"), "old").unwrap();
+        store_message(&conn, "chan1:user1", "user", "old and per-user").unwrap();
+        store_message(&conn, "chan10", "user", "another channel").unwrap();
+        conn.execute("UPDATE messages SET timestamp = 5", []).unwrap();
+        store_message(&conn, "chan1", "user", "new").unwrap();
+
+        let old = get_messages_to_archive(&conn, "chan1", 100).unwrap();
+        let keys: Vec<_> = old.iter().map(|m| m.history_key.as_str()).collect();
+        assert_eq!(keys, ["chan1", "chan1:user1"]);
+        assert_eq!(old[0].message_id.as_deref(), Some("10"));
+
+        let id = record_message_archive(&conn, "chan1", 100, "/archive/chan1/5-5.jsonl.gz", &old).unwrap();
+        assert!(get_messages_to_archive(&conn, "chan1", 100).unwrap().is_empty());
+        assert_eq!(get_recent_messages(&conn, "chan1", 10).unwrap().len(), 1);
+        assert_eq!(get_recent_messages(&conn, "chan10", 10).unwrap().len(), 1);
+
+        let archives = get_message_archives(&conn, "chan1").unwrap();
+        assert_eq!(archives.len(), 1);
+        assert_eq!((archives[0].id, archives[0].message_count), (id, 2));
+        assert_eq!(record_message_archive(&conn, "chan1", 100, "unused", &[]).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_encryption() {
+        use base64::Engine;
+        let conn = setup();
+        store_message(&conn, "chan1", "user", "written before").unwrap();
+        save_checkpoint(&conn, "chan1", "start", "joe").unwrap();
+        assert!(!has_encrypted_rows(&conn).unwrap());
+
+        let key = base64::engine::general_purpose::STANDARD.encode([7u8; 32]);
+        use_cipher(&conn, Some(Cipher::from_base64(&key).unwrap())).unwrap();
+        // The message and its copy in the checkpoint
+        assert_eq!(encrypt_plaintext(&conn).unwrap(), 2);
+        store_message(&conn, "chan1", "user", "my secret").unwrap();
+        save_memory(&conn, "chan1", "my secret", "kept", "joe", None).unwrap();
+        record_exchange(&conn, "9", "default", "my secret", "a secret reply").unwrap();
+        record_exchange(&conn, "10", "default", "my secret", "a secret reply").unwrap();
+        assert!(has_encrypted_rows(&conn).unwrap());
+
+        let raw: Vec<String> = conn
+            .prepare(
+                "SELECT content FROM messages UNION ALL SELECT content FROM checkpoint_messages
+                 UNION ALL SELECT prompt FROM memories UNION ALL SELECT prompt FROM feedback
+                 UNION ALL SELECT reply FROM feedback",
+            )
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert!(raw.iter().all(|v| v.starts_with(encryption::PREFIX) && !v.contains("secret")));
+
+        let msgs = get_recent_messages(&conn, "chan1", 10).unwrap();
+        assert_eq!(msgs.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(), ["written before", "my secret"]);
+        assert_eq!(get_memories(&conn, "chan1", 10).unwrap()[0].prompt, "my secret");
+        assert_eq!(get_exchange(&conn, "9").unwrap(), Some(("my secret".to_string(), "a secret reply".to_string())));
+        // Both parts of a split reply count as one example, with their votes summed
+        add_feedback_vote(&conn, "9", 1).unwrap();
+        add_feedback_vote(&conn, "10", 1).unwrap();
+        assert_eq!(
+            top_feedback_examples(&conn, "default", 5).unwrap(),
+            [("my secret".to_string(), "a secret reply".to_string())]
+        );
+        // Archives get the stored ciphertext
+        let archivable = get_messages_to_archive(&conn, "chan1", i64::MAX).unwrap();
+        assert!(archivable.iter().all(|m| m.content.starts_with(encryption::PREFIX)));
+
+        // Without the key, encrypted rows can't be read
+        use_cipher(&conn, Some(Cipher::from_base64(&base64::engine::general_purpose::STANDARD.encode([8u8; 32])).unwrap())).unwrap();
+        assert!(get_recent_messages(&conn, "chan1", 10).is_err());
+    }
+
+    #[test]
+    fn test_purge_user() {
+        let conn = setup();
+        set_context_mode(&conn, "chan2", "user").unwrap();
+        store_message_from(&conn, "chan1", "user", Some("Joe"), Some("42"), Some("1"), "hi all").unwrap();
+        store_message_from(&conn, "chan1", "user", Some("Zara"), Some("7"), Some("2"), "hello").unwrap();
+        store_message_from(&conn, "chan2:42", "user", Some("Joe"), Some("42"), Some("3"), "just us").unwrap();
+        store_message(&conn, "chan2:42", "assistant", "go away").unwrap();
+        save_memory(&conn, "chan1", "hi all", "no", "Zara", Some("42")).unwrap();
+        set_reply_style(&conn, "42", Some("pirate")).unwrap();
+        set_listen_opt_out(&conn, "42", true).unwrap();
+        link_account(&conn, "42", "wow", "Pyuul").unwrap();
+        save_checkpoint(&conn, "chan2:42", "start", "42").unwrap();
+        // Stored before author ids, so only the name says whose they are
+        store_message_from(&conn, "chan1", "user", Some("Joe"), None, Some("4"), "old news").unwrap();
+        save_memory(&conn, "chan1", "old news", "noted", "Zara", None).unwrap();
+        let mine = open_ticket(&conn, "g1", "thread1", "42", "Help").unwrap();
+        log_ticket_message(&conn, mine, "Joe", "my ticket").unwrap();
+        let theirs = open_ticket(&conn, "g1", "thread2", "7", "Loot").unwrap();
+        log_ticket_message(&conn, theirs, "Joe", "I rolled 100\nhonest").unwrap();
+        log_ticket_message(&conn, theirs, "Zara", "Sure").unwrap();
+        close_ticket(&conn, theirs, 1000).unwrap();
+        start_rp_session(&conn, "chan3", "A tavern", "7").unwrap();
+        record_rp_turn(&conn, "chan3", "Joe").unwrap();
+        record_rp_turn(&conn, "chan3", "Zara").unwrap();
+
+        let purged = purge_user(&conn, "42", &["joe_1", "Joe"]).unwrap();
+        assert_eq!(
+            purged,
+            Purged {
+                messages: 4,
+                memories: 2,
+                feedback: 0,
+                preferences: 1,
+                links: 1,
+                // Checkpoint, ticket, its log line, a transcript line and a turn order
+                other: 5,
+            }
+        );
+        let left = get_recent_messages(&conn, "chan1", 10).unwrap();
+        assert_eq!(left.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(), ["hello"]);
+        assert!(get_recent_messages(&conn, "chan2:42", 10).unwrap().is_empty());
+        assert_eq!(get_reply_style(&conn, "42").unwrap(), None);
+        assert!(get_ticket(&conn, "g1", mine).unwrap().is_none());
+        let transcript = get_ticket(&conn, "g1", theirs).unwrap().unwrap().transcript.unwrap();
+        assert!(!transcript.contains("Joe") && !transcript.contains("honest"));
+        assert!(transcript.ends_with("Zara: Sure"));
+        assert_eq!(get_rp_session(&conn, "chan3").unwrap().unwrap().participants, ["Zara"]);
+        // The opt-out outlives the purge
+        assert!(is_listen_opted_out(&conn, "42").unwrap());
+        assert_eq!(purge_user(&conn, "42", &["Joe"]).unwrap().total(), 0);
+    }
+
+    #[test]
+    fn test_help_channel() {
+        let conn = setup();
+        assert!(!is_help_channel(&conn, "chan1").unwrap());
+        set_help_channel(&conn, "chan1", true).unwrap();
+        assert!(is_help_channel(&conn, "chan1").unwrap());
+        assert_eq!(help_channel_answered_at(&conn, "chan1").unwrap(), 0);
+        set_help_channel_answered_at(&conn, "chan1", 100).unwrap();
+        assert_eq!(help_channel_answered_at(&conn, "chan1").unwrap(), 100);
+        set_help_channel(&conn, "chan1", false).unwrap();
+        assert!(!is_help_channel(&conn, "chan1").unwrap());
+        assert!(!is_help_channel(&conn, "chan2").unwrap());
+    }
+
+    #[test]
+    fn test_listening() {
+        let conn = setup();
+        assert!(!is_listening(&conn, "chan1").unwrap());
+        set_listening(&conn, "chan1", true).unwrap();
+        assert!(is_listening(&conn, "chan1").unwrap());
+        assert!(!is_listening(&conn, "chan2").unwrap());
+        set_listening(&conn, "chan1", false).unwrap();
+        assert!(!is_listening(&conn, "chan1").unwrap());
+
+        set_listen_opt_out(&conn, "user1", true).unwrap();
+        assert!(is_listen_opted_out(&conn, "user1").unwrap());
+        assert!(!is_listen_opted_out(&conn, "user2").unwrap());
+    }
+
+    #[test]
+    fn test_messages_stored_once() {
+        let conn = setup();
+        assert!(store_message_from(&conn, "chan1", "user", Some("Joe"), None, Some("42"), "hello").unwrap());
+        assert!(!store_message_from(&conn, "chan1", "user", Some("Joe"), None, Some("42"), "hello").unwrap());
+        // Other histories, and messages with no Discord ID, aren't affected
+        assert!(store_message_from(&conn, "bot@chan1", "user", Some("Joe"), None, Some("42"), "hello").unwrap());
+        store_message(&conn, "chan1", "assistant", "hi").unwrap();
+        store_message(&conn, "chan1", "assistant", "hi").unwrap();
+
+        assert!(has_message(&conn, "chan1", "42").unwrap());
+        assert!(!has_message(&conn, "chan2", "42").unwrap());
+        assert_eq!(get_recent_messages(&conn, "chan1", 10).unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_messages_tagged_with_correlation_id() {
+        let conn = setup();
+        let id = trace::traced("test", async {
+            store_message(&conn, "chan1", "user", "hi").unwrap();
+            trace::current().unwrap()
+        })
+        .await;
+        store_message(&conn, "chan1", "user", "later").unwrap();
+        let tags: Vec<Option<String>> = conn
+            .prepare("SELECT correlation_id FROM messages ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(tags, [Some(id), None]);
+    }
+
+    #[test]
+    fn test_delete_discord_message() {
+        let conn = setup();
+        store_message_from(&conn, "chan1", "user", Some("Joe"), None, Some("42"), "secret").unwrap();
+        store_message_from(&conn, "bot@chan1", "user", Some("Joe"), None, Some("42"), "secret").unwrap();
+        store_message(&conn, "chan1", "assistant", "noted").unwrap();
+
+        assert_eq!(delete_discord_message(&conn, "42").unwrap(), 2);
+        assert!(!has_message(&conn, "chan1", "42").unwrap());
+        assert_eq!(get_recent_messages(&conn, "chan1", 10).unwrap().len(), 1);
+        assert_eq!(delete_discord_message(&conn, "42").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_message_history_limit() {
+        let conn = setup();
+        for i in 0..20 {
+            store_message(&conn, "chan1", "user", &format!("msg {}", i)).unwrap();
+        }
+
+        let msgs = get_recent_messages(&conn, "chan1", 5).unwrap();
+        assert_eq!(msgs.len(), 5);
+        // Should be the last 5 messages
+        assert_eq!(msgs[0].content, "msg 15");
+        assert_eq!(msgs[4].content, "msg 19");
+    }
+
+    #[test]
+    fn test_messages_scoped_to_channel() {
+        let conn = setup();
+        store_message(&conn, "chan_a", "user", "message in A").unwrap();
+        store_message(&conn, "chan_b", "user", "message in B").unwrap();
+
+        let msgs_a = get_recent_messages(&conn, "chan_a", 10).unwrap();
+        assert_eq!(msgs_a.len(), 1);
+        assert_eq!(msgs_a[0].content, "message in A");
+
+        let msgs_b = get_recent_messages(&conn, "chan_b", 10).unwrap();
+        assert_eq!(msgs_b.len(), 1);
+        assert_eq!(msgs_b[0].content, "message in B");
+    }
+
+    #[test]
+    fn test_context_key_follows_mode() {
+        let conn = setup();
+        assert_eq!(context_key(&conn, "chan1", "user1"), "chan1");
+
+        set_context_mode(&conn, "chan1", "user").unwrap();
+        assert_eq!(context_key(&conn, "chan1", "user1"), "chan1:user1");
+        // Other channels are unaffected
+        assert_eq!(context_key(&conn, "chan2", "user1"), "chan2");
+    }
+
+    #[test]
+    fn test_intensity() {
+        let conn = setup();
+        assert_eq!(get_intensity(&conn, "chan1").unwrap(), None);
+        set_intensity(&conn, "chan1", Some(7)).unwrap();
+        assert_eq!(get_intensity(&conn, "chan1").unwrap(), Some(7));
+        assert_eq!(get_intensity(&conn, "chan2").unwrap(), None);
+        set_intensity(&conn, "chan1", None).unwrap();
+        assert_eq!(get_intensity(&conn, "chan1").unwrap(), None);
+    }
+
+    #[test]
+    fn test_safe_mode() {
+        let conn = setup();
+        assert!(!is_safe_mode(&conn, "chan1").unwrap());
+        set_safe_mode(&conn, "chan1", true).unwrap();
+        assert!(is_safe_mode(&conn, "chan1").unwrap());
+        assert!(!is_safe_mode(&conn, "chan2").unwrap());
+        set_safe_mode(&conn, "chan1", false).unwrap();
+        assert!(!is_safe_mode(&conn, "chan1").unwrap());
+    }
+
+    #[test]
+    fn test_preamble_toggle() {
+        let conn = setup();
+        assert!(is_preamble_enabled(&conn, "guild1"));
+        set_preamble_enabled(&conn, "guild1", false).unwrap();
+        assert!(!is_preamble_enabled(&conn, "guild1"));
+        assert!(is_preamble_enabled(&conn, "guild2"));
+        set_preamble_enabled(&conn, "guild1", true).unwrap();
+        assert!(is_preamble_enabled(&conn, "guild1"));
+    }
+
+    #[test]
+    fn test_allow_user_mentions() {
+        let conn = setup();
+        assert!(!allows_user_mentions(&conn, "guild1"));
+
+        set_allow_user_mentions(&conn, "guild1", true).unwrap();
+        assert!(allows_user_mentions(&conn, "guild1"));
+        assert!(!allows_user_mentions(&conn, "guild2"));
+
+        set_allow_user_mentions(&conn, "guild1", false).unwrap();
+        assert!(!allows_user_mentions(&conn, "guild1"));
+    }
+
+    #[test]
+    fn test_feedback_examples() {
         let conn = setup();
-        for i in 0..20 {
-            store_message(&conn, "chan1", "user", &format!("msg {}", i)).unwrap();
-        }
+        record_exchange(&conn, "1", "rude", "hi", "go away").unwrap();
+        record_exchange(&conn, "2", "rude", "long question", "long answer").unwrap();
+        record_exchange(&conn, "3", "rude", "long question", "long answer").unwrap();
+        record_exchange(&conn, "4", "rude", "meh", "meh").unwrap();
+        record_exchange(&conn, "5", "nice", "hi", "hello!").unwrap();
 
-        let msgs = get_recent_messages(&conn, "chan1", 5).unwrap();
-        assert_eq!(msgs.len(), 5);
-        // Should be the last 5 messages
-        assert_eq!(msgs[0].content, "msg 15");
-        assert_eq!(msgs[4].content, "msg 19");
+        assert!(add_feedback_vote(&conn, "1", 1).unwrap());
+        // Votes on either part of a split reply count for the exchange
+        add_feedback_vote(&conn, "2", 1).unwrap();
+        add_feedback_vote(&conn, "3", 1).unwrap();
+        add_feedback_vote(&conn, "4", -1).unwrap();
+        add_feedback_vote(&conn, "5", 1).unwrap();
+        assert!(!add_feedback_vote(&conn, "99", 1).unwrap());
+
+        let examples = top_feedback_examples(&conn, "rude", 3).unwrap();
+        assert_eq!(
+            examples,
+            [
+                ("long question".to_string(), "long answer".to_string()),
+                ("hi".to_string(), "go away".to_string())
+            ]
+        );
+        assert_eq!(top_feedback_examples(&conn, "rude", 1).unwrap().len(), 1);
+
+        assert_eq!(get_exchange(&conn, "5").unwrap(), Some(("hi".to_string(), "hello!".to_string())));
+        assert_eq!(get_exchange(&conn, "99").unwrap(), None);
     }
 
     #[test]
-    fn test_messages_scoped_to_channel() {
+    fn test_blocklist() {
         let conn = setup();
-        store_message(&conn, "chan_a", "user", "message in A").unwrap();
-        store_message(&conn, "chan_b", "user", "message in B").unwrap();
+        assert!(add_blocklist_entry(&conn, "guild1", "darn", "user1").unwrap());
+        assert!(!add_blocklist_entry(&conn, "guild1", "DARN", "user1").unwrap());
+        assert!(add_blocklist_entry(&conn, "guild1", "/h[e]+ck/", "user1").unwrap());
+        assert_eq!(get_blocklist(&conn, "guild1").unwrap(), ["/h[e]+ck/", "darn"]);
+        assert!(get_blocklist(&conn, "guild2").unwrap().is_empty());
 
-        let msgs_a = get_recent_messages(&conn, "chan_a", 10).unwrap();
-        assert_eq!(msgs_a.len(), 1);
-        assert_eq!(msgs_a[0].content, "message in A");
+        assert!(remove_blocklist_entry(&conn, "guild1", "Darn").unwrap());
+        assert!(!remove_blocklist_entry(&conn, "guild1", "darn").unwrap());
 
-        let msgs_b = get_recent_messages(&conn, "chan_b", 10).unwrap();
-        assert_eq!(msgs_b.len(), 1);
-        assert_eq!(msgs_b[0].content, "message in B");
+        assert_eq!(get_blocklist_mode(&conn, "guild1").unwrap(), None);
+        set_blocklist_mode(&conn, "guild1", "regenerate").unwrap();
+        assert_eq!(get_blocklist_mode(&conn, "guild1").unwrap().as_deref(), Some("regenerate"));
+    }
+
+    #[test]
+    fn test_wake_words() {
+        let conn = setup();
+        assert!(add_wake_word(&conn, "g1", "christinith", "user1").unwrap());
+        assert!(!add_wake_word(&conn, "g1", "Christinith", "user2").unwrap());
+        add_wake_word(&conn, "g1", "bot", "user1").unwrap();
+        assert_eq!(get_wake_words(&conn, "g1").unwrap(), ["bot", "christinith"]);
+        assert!(get_wake_words(&conn, "g2").unwrap().is_empty());
+
+        assert!(remove_wake_word(&conn, "g1", "BOT").unwrap());
+        assert!(!remove_wake_word(&conn, "g1", "bot").unwrap());
+        assert_eq!(get_wake_words(&conn, "g1").unwrap(), ["christinith"]);
     }
 
     #[test]
@@ -229,6 +4017,23 @@ mod tests {
         assert!(!add_tracked_character(&conn, "pyuul", "user789").unwrap());
     }
 
+    #[test]
+    fn test_character_game_version() {
+        let conn = setup();
+        // Re-running init must not try to add the column twice
+        init(&conn).unwrap();
+        add_tracked_character(&conn, "Pyuul", "user123").unwrap();
+        assert_eq!(get_tracked_character(&conn, "Pyuul").unwrap().unwrap().game_version, None);
+
+        assert!(set_character_game_version(&conn, "pyuul", Some("retail")).unwrap());
+        let character = get_tracked_character(&conn, "Pyuul").unwrap().unwrap();
+        assert_eq!(character.game_version.as_deref(), Some("retail"));
+
+        assert!(set_character_game_version(&conn, "Pyuul", None).unwrap());
+        assert_eq!(get_tracked_character(&conn, "Pyuul").unwrap().unwrap().game_version, None);
+        assert!(!set_character_game_version(&conn, "Nobody", Some("era")).unwrap());
+    }
+
     #[test]
     fn test_remove_tracked_character() {
         let conn = setup();
@@ -248,4 +4053,717 @@ mod tests {
         let chars = get_tracked_characters(&conn).unwrap();
         assert_eq!(chars, vec!["Alpha", "Miko", "Zara"]);
     }
+
+    #[test]
+    fn test_search_tracked_characters() {
+        let conn = setup();
+        add_tracked_character(&conn, "Pyuul", "user1").unwrap();
+        add_tracked_character(&conn, "Puddle", "user2").unwrap();
+        add_tracked_character(&conn, "Zara", "user3").unwrap();
+
+        assert_eq!(search_tracked_characters(&conn, "p", 25).unwrap(), vec!["Puddle", "Pyuul"]);
+        assert_eq!(search_tracked_characters(&conn, "UUL", 25).unwrap(), vec!["Pyuul"]);
+        assert_eq!(search_tracked_characters(&conn, "", 2).unwrap(), vec!["Puddle", "Pyuul"]);
+        assert!(search_tracked_characters(&conn, "nobody", 25).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_feature_flags_default_enabled() {
+        let conn = setup();
+        assert!(is_feature_enabled(&conn, "guild1", "wow").unwrap());
+
+        set_feature_enabled(&conn, "guild1", "wow", false).unwrap();
+        assert!(!is_feature_enabled(&conn, "guild1", "wow").unwrap());
+        // Scoped to the guild
+        assert!(is_feature_enabled(&conn, "guild2", "wow").unwrap());
+
+        set_feature_enabled(&conn, "guild1", "wow", true).unwrap();
+        assert!(is_feature_enabled(&conn, "guild1", "wow").unwrap());
+    }
+
+    #[test]
+    fn test_get_tracked_character() {
+        let conn = setup();
+        add_tracked_character(&conn, "Pyuul", "user1").unwrap();
+        let c = get_tracked_character(&conn, "pyuul").unwrap().unwrap();
+        assert_eq!(c.name, "Pyuul");
+        assert_eq!(c.added_by, "user1");
+        assert!(get_tracked_character(&conn, "Nobody").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_get_tracked_character_details() {
+        let conn = setup();
+        add_tracked_character(&conn, "Zara", "user1").unwrap();
+        add_tracked_character(&conn, "Alpha", "user2").unwrap();
+
+        let chars = get_tracked_character_details(&conn).unwrap();
+        assert_eq!(chars.len(), 2);
+        assert_eq!(chars[0].name, "Alpha");
+        assert_eq!(chars[0].added_by, "user2");
+        assert!(chars[0].added_at > 0);
+        assert_eq!(chars[1].name, "Zara");
+    }
+
+    fn snapshot(name: &str, level: u32, kills: u32, taken_at: i64) -> CharacterSnapshot {
+        CharacterSnapshot {
+            name: name.to_string(),
+            level,
+            experience: Some(0),
+            honorable_kills: Some(kills),
+            honor_level: None,
+            taken_at,
+        }
+    }
+
+    #[test]
+    fn test_record_snapshot_skips_unchanged() {
+        let conn = setup();
+        assert!(record_snapshot(&conn, &snapshot("Pyuul", 10, 0, 100)).unwrap());
+        // Same progress later on is not stored again
+        assert!(!record_snapshot(&conn, &snapshot("Pyuul", 10, 0, 200)).unwrap());
+        assert!(record_snapshot(&conn, &snapshot("Pyuul", 11, 0, 300)).unwrap());
+        assert!(record_snapshot(&conn, &snapshot("Pyuul", 11, 5, 400)).unwrap());
+
+        let latest = latest_snapshot(&conn, "pyuul").unwrap().unwrap();
+        assert_eq!(latest.level, 11);
+        assert_eq!(latest.honorable_kills, Some(5));
+        assert_eq!(latest.taken_at, 400);
+    }
+
+    #[test]
+    fn test_snapshot_at_or_before() {
+        let conn = setup();
+        record_snapshot(&conn, &snapshot("Pyuul", 10, 0, 100)).unwrap();
+        record_snapshot(&conn, &snapshot("Pyuul", 12, 0, 300)).unwrap();
+
+        assert!(snapshot_at_or_before(&conn, "Pyuul", 50).unwrap().is_none());
+        assert_eq!(earliest_snapshot(&conn, "Pyuul").unwrap().unwrap().taken_at, 100);
+        assert_eq!(snapshot_at_or_before(&conn, "Pyuul", 299).unwrap().unwrap().level, 10);
+        assert_eq!(snapshot_at_or_before(&conn, "Pyuul", 300).unwrap().unwrap().level, 12);
+        assert!(latest_snapshot(&conn, "Nobody").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_usage_stats() {
+        let conn = setup();
+        assert_eq!(usage_stats(&conn).unwrap(), UsageStats::default());
+
+        store_message(&conn, "chan1", "user", "hi").unwrap();
+        store_message(&conn, "chan1", "assistant", "go away").unwrap();
+        store_message(&conn, "chan2:user1", "user", "hey").unwrap();
+        add_tracked_character(&conn, "Pyuul", "user1").unwrap();
+        record_snapshot(&conn, &snapshot("Pyuul", 10, 0, 100)).unwrap();
+
+        let stats = usage_stats(&conn).unwrap();
+        assert_eq!(stats.messages, 3);
+        assert_eq!(stats.contexts, 2);
+        assert_eq!(stats.tracked_characters, 1);
+        assert_eq!(stats.snapshots, 1);
+    }
+
+    #[test]
+    fn test_get_snapshots() {
+        let conn = setup();
+        record_snapshot(&conn, &snapshot("Pyuul", 12, 0, 300)).unwrap();
+        record_snapshot(&conn, &snapshot("Pyuul", 10, 0, 100)).unwrap();
+        record_snapshot(&conn, &snapshot("Zara", 30, 0, 200)).unwrap();
+
+        let levels: Vec<_> = get_snapshots(&conn, "pyuul")
+            .unwrap()
+            .iter()
+            .map(|s| (s.taken_at, s.level))
+            .collect();
+        assert_eq!(levels, [(100, 10), (300, 12)]);
+        assert!(get_snapshots(&conn, "Nobody").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_last_level_progress_ignores_pvp_changes() {
+        let conn = setup();
+        assert_eq!(last_level_progress(&conn, "Pyuul").unwrap(), None);
+
+        record_snapshot(&conn, &snapshot("Pyuul", 10, 0, 100)).unwrap();
+        record_snapshot(&conn, &snapshot("Pyuul", 11, 0, 200)).unwrap();
+        // Only honor changed after this point
+        record_snapshot(&conn, &snapshot("Pyuul", 11, 7, 300)).unwrap();
+        record_snapshot(&conn, &snapshot("Pyuul", 11, 9, 400)).unwrap();
+
+        assert_eq!(last_level_progress(&conn, "Pyuul").unwrap(), Some(200));
+    }
+
+    fn script(name: &str, trigger: &str, pattern: &str) -> Script {
+        Script {
+            guild_id: "g1".to_string(),
+            name: name.to_string(),
+            trigger: trigger.to_string(),
+            pattern: pattern.to_string(),
+            channel_id: "1".to_string(),
+            source: "reply(\"hi\")".to_string(),
+            created_by: "admin".to_string(),
+            last_run: 0,
+        }
+    }
+
+    #[test]
+    fn test_personas() {
+        let conn = setup();
+        let mut persona = Persona {
+            name: "Grumpy".to_string(),
+            description: "An old dwarf".to_string(),
+            personality: String::new(),
+            scenario: String::new(),
+            first_message: "What?".to_string(),
+            system_prompt: "You are Grumpy.".to_string(),
+            created_by: "user1".to_string(),
+        };
+        save_persona(&conn, &persona).unwrap();
+        assert_eq!(get_persona(&conn, "grumpy").unwrap(), Some(persona.clone()));
+
+        // Re-importing replaces the persona
+        persona.system_prompt = "You are very Grumpy.".to_string();
+        save_persona(&conn, &persona).unwrap();
+        assert_eq!(get_personas(&conn).unwrap(), [persona]);
+
+        assert!(remove_persona(&conn, "GRUMPY").unwrap());
+        assert!(!remove_persona(&conn, "Grumpy").unwrap());
+        assert_eq!(get_persona(&conn, "Grumpy").unwrap(), None);
+    }
+
+    #[test]
+    fn test_rp_sessions() {
+        let conn = setup();
+        assert!(start_rp_session(&conn, "chan1", "A tavern brawl", "user1").unwrap());
+        assert!(!start_rp_session(&conn, "chan1", "Another", "user2").unwrap());
+
+        record_rp_turn(&conn, "chan1", "Joe").unwrap();
+        record_rp_turn(&conn, "chan1", "Ann").unwrap();
+        let session = record_rp_turn(&conn, "chan1", "Joe").unwrap().unwrap();
+        assert_eq!(session.participants, ["Joe", "Ann"]);
+        assert_eq!(session.turns, 3);
+        assert_eq!(get_rp_session(&conn, "chan1").unwrap(), Some(session));
+        assert_eq!(record_rp_turn(&conn, "chan2", "Joe").unwrap(), None);
+
+        assert!(end_rp_session(&conn, "chan1").unwrap());
+        assert_eq!(get_rp_session(&conn, "chan1").unwrap(), None);
+    }
+
+    #[test]
+    fn test_pending_requests() {
+        let conn = setup();
+        enqueue_request(&conn, "g1", "chan1", "user1", "{}", 100).unwrap();
+        enqueue_request(&conn, "g1", "chan1", "user2", "{}", 200).unwrap();
+
+        let due = due_requests(&conn, 150).unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].user_id, "user1");
+        assert_eq!(due[0].attempts, 0);
+
+        reschedule_request(&conn, due[0].id, 300).unwrap();
+        assert_eq!(due_requests(&conn, 250).unwrap().len(), 1);
+        let due = due_requests(&conn, 300).unwrap();
+        assert_eq!(due[0].attempts, 1);
+
+        assert!(remove_request(&conn, due[0].id).unwrap());
+        assert!(!remove_request(&conn, due[0].id).unwrap());
+        assert_eq!(due_requests(&conn, 1000).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_confessions() {
+        let conn = setup();
+        assert_eq!(last_confession_at(&conn, "user1").unwrap(), None);
+        let first = record_confession(&conn, "user1", "I like pineapple pizza").unwrap();
+        let second = record_confession(&conn, "user2", "I never read the quest text").unwrap();
+        assert_eq!(second, first + 1);
+
+        assert!(last_confession_at(&conn, "user1").unwrap().is_some());
+        let confession = get_confession(&conn, second).unwrap().unwrap();
+        assert_eq!(confession.user_id, "user2");
+        assert_eq!(confession.content, "I never read the quest text");
+        assert!(get_confession(&conn, 99).unwrap().is_none());
+
+        // Each guild has its own confession channel
+        set_guild_config(&conn, "2", "confession_channel", "20").unwrap();
+        set_guild_config(&conn, "1", "confession_channel", "10").unwrap();
+        assert_eq!(
+            get_guilds_with_config(&conn, "confession_channel").unwrap(),
+            [("1".to_string(), "10".to_string()), ("2".to_string(), "20".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_migrate_confession_channel() {
+        let conn = setup();
+        set_config(&conn, None, "confession_guild", "7").unwrap();
+        set_config(&conn, None, "confession_channel", "70").unwrap();
+        migrate_confession_channel(&conn).unwrap();
+        assert_eq!(get_guild_config(&conn, "7", "confession_channel").unwrap().as_deref(), Some("70"));
+        // No other guild inherits it
+        assert_eq!(get_guild_config(&conn, "8", "confession_channel").unwrap(), None);
+        assert_eq!(get_config(&conn, None, "confession_guild").unwrap(), None);
+    }
+
+    #[test]
+    fn test_tickets() {
+        let conn = setup();
+        let id = open_ticket(&conn, "g1", "thread1", "user1", "Loot dispute").unwrap();
+        log_ticket_message(&conn, id, "Pyuul", "I rolled 100").unwrap();
+        log_ticket_message(&conn, id, "Zara", "Master looter said 99").unwrap();
+
+        let ticket = get_ticket_by_thread(&conn, "thread1").unwrap().unwrap();
+        assert_eq!(ticket.id, id);
+        assert_eq!(ticket.subject, "Loot dispute");
+        assert!(ticket.closed_at.is_none());
+
+        let transcript = close_ticket(&conn, id, 1000).unwrap().unwrap();
+        assert!(transcript.contains("Pyuul: I rolled 100\n"));
+        assert!(transcript.ends_with("Zara: Master looter said 99"));
+        // Closing twice does nothing
+        assert!(close_ticket(&conn, id, 2000).unwrap().is_none());
+
+        let ticket = get_ticket(&conn, "g1", id).unwrap().unwrap();
+        assert_eq!(ticket.transcript.as_deref(), Some(transcript.as_str()));
+        assert_eq!(ticket.closed_at, Some(1000));
+        assert!(get_ticket(&conn, "g2", id).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_faq() {
+        let conn = setup();
+        let id = add_faq(&conn, "g1", "When is raid?", "Thursdays at 8pm server time", &[0.5, -1.25], "admin").unwrap();
+        add_faq(&conn, "g2", "Other guild", "Not ours", &[1.0], "admin").unwrap();
+
+        let faqs = get_faqs(&conn, "g1").unwrap();
+        assert_eq!(faqs.len(), 1);
+        assert_eq!(faqs[0].question, "When is raid?");
+        assert_eq!(faqs[0].embedding, vec![0.5, -1.25]);
+
+        assert!(!remove_faq(&conn, "g2", id).unwrap());
+        assert!(remove_faq(&conn, "g1", id).unwrap());
+        assert!(get_faqs(&conn, "g1").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_knowledge() {
+        let conn = setup();
+        let chunks = vec![("Rule one".to_string(), vec![1.0, 0.0]), ("Rule two".to_string(), vec![0.0, 1.0])];
+        save_knowledge(&conn, "g1", "rules.md", &chunks, "admin").unwrap();
+        save_knowledge(&conn, "g1", "strats.pdf", &chunks[..1], "admin").unwrap();
+        assert_eq!(get_knowledge_chunks(&conn, "g1").unwrap().len(), 3);
+        assert!(get_knowledge_chunks(&conn, "g2").unwrap().is_empty());
+
+        // Uploading the same name again replaces it
+        save_knowledge(&conn, "g1", "rules.md", &chunks[1..], "admin").unwrap();
+        assert_eq!(
+            get_knowledge_sources(&conn, "g1").unwrap(),
+            vec![("rules.md".to_string(), 1), ("strats.pdf".to_string(), 1)]
+        );
+        let stored = get_knowledge_chunks(&conn, "g1").unwrap();
+        assert_eq!(stored[1].content, "Rule two");
+        assert_eq!(stored[1].embedding, vec![0.0, 1.0]);
+
+        assert!(remove_knowledge_source(&conn, "g1", "rules.md").unwrap());
+        assert!(!remove_knowledge_source(&conn, "g1", "rules.md").unwrap());
+    }
+
+    #[test]
+    fn test_persona_schedule() {
+        let conn = setup();
+        let id = add_persona_schedule(&conn, "Spooky", Some("10-01"), Some("10-31"), "admin").unwrap();
+        add_persona_schedule(&conn, "Calm", None, None, "admin").unwrap();
+
+        let entries = get_persona_schedule(&conn).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].starts.as_deref(), Some("10-01"));
+        assert_eq!(entries[1].persona, "Calm");
+        assert!(entries[1].ends.is_none());
+
+        assert!(remove_persona_schedule(&conn, id).unwrap());
+        assert!(!remove_persona_schedule(&conn, id).unwrap());
+        assert_eq!(get_persona_schedule(&conn).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_forget_exchange() {
+        let conn = setup();
+        store_message_from(&conn, "chan1", "user", Some("Pyuul"), None, Some("10"), "hi").unwrap();
+        store_message(&conn, "chan1", "assistant", "go away").unwrap();
+        store_message(&conn, "chan1", "user", "later").unwrap();
+        record_exchange(&conn, "11", "rude", "hi", "go away").unwrap();
+        set_exchange_origin(&conn, "11", "chan1", Some("user1"), Some("10")).unwrap();
+
+        record_exchange(&conn, "12", "rude", "hi", "go away").unwrap();
+        set_exchange_origin(&conn, "12", "chan1", Some("user1"), Some("10")).unwrap();
+
+        let exchange = get_exchange_record(&conn, "11").unwrap().unwrap();
+        assert_eq!(exchange.history_key.as_deref(), Some("chan1"));
+        assert_eq!(exchange_message_ids(&conn, "12", &exchange).unwrap(), ["11", "12"]);
+        let (reply, by_prompt) = get_exchange_for_prompt(&conn, "10").unwrap().unwrap();
+        assert_eq!((reply.as_str(), by_prompt.reply.as_str()), ("11", "go away"));
+        assert!(get_exchange_for_prompt(&conn, "11").unwrap().is_none());
+        assert_eq!(exchange.user_id.as_deref(), Some("user1"));
+
+        let author = forget_exchange(&conn, "11", "chan1", &exchange).unwrap();
+        assert_eq!(author.as_deref(), Some("Pyuul"));
+        let left: Vec<_> = get_recent_messages(&conn, "chan1", 10).unwrap().into_iter().map(|m| m.content).collect();
+        assert_eq!(left, ["later"]);
+        assert!(get_exchange_record(&conn, "11").unwrap().is_none());
+        assert!(get_exchange_record(&conn, "12").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_memories() {
+        let conn = setup();
+        save_memory(&conn, "chan1", "hi", "go away", "Pyuul", None).unwrap();
+        save_memory(&conn, "chan1", "why", "because", "Zara", None).unwrap();
+        let memories = get_memories(&conn, "chan1", 10).unwrap();
+        assert_eq!(memories.len(), 2);
+        assert_eq!(memories[0].saved_by, "Zara");
+        assert!(get_memories(&conn, "chan2", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_linked_accounts() {
+        let conn = setup();
+        assert!(link_account(&conn, "1", "wow", "Pyuul").unwrap());
+        assert!(!link_account(&conn, "1", "wow", "pyuul").unwrap());
+        assert!(link_account(&conn, "1", "osrs", "Zezima").unwrap());
+        assert!(link_account(&conn, "2", "wow", "Pyuul").unwrap());
+
+        let games: Vec<_> = get_linked_accounts(&conn, "1").unwrap().into_iter().map(|a| a.game).collect();
+        assert_eq!(games, ["osrs", "wow"]);
+        assert!(unlink_account(&conn, "1", "wow", "PYUUL").unwrap());
+        assert!(!unlink_account(&conn, "1", "wow", "Pyuul").unwrap());
+        assert_eq!(get_linked_accounts(&conn, "1").unwrap().len(), 1);
+        assert_eq!(get_linked_accounts(&conn, "2").unwrap().len(), 1);
+        assert_eq!(get_linked_accounts_for_game(&conn, "osrs").unwrap(), [("1".to_string(), "Zezima".to_string())]);
+        assert_eq!(unlink_game(&conn, "1", "osrs").unwrap(), 1);
+        assert!(get_linked_accounts(&conn, "1").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_riot_accounts() {
+        let conn = setup();
+        assert!(add_riot_account(&conn, "p1", "Faker#KR1", "1").unwrap());
+        assert!(!add_riot_account(&conn, "p1", "Hide on bush#KR1", "2").unwrap());
+        set_riot_solo_rank(&conn, "p1", Some("GOLD II")).unwrap();
+
+        let accounts = get_riot_accounts(&conn).unwrap();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].riot_id, "Hide on bush#KR1");
+        assert_eq!(accounts[0].added_by, "1");
+        assert_eq!(accounts[0].solo_rank.as_deref(), Some("GOLD II"));
+        assert!(remove_riot_account(&conn, "hide on bush#kr1").unwrap());
+        assert!(get_riot_accounts(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_insult_exemptions() {
+        let conn = setup();
+        assert!(!is_insult_opted_out(&conn, "user1").unwrap());
+        set_insult_opt_out(&conn, "user1", true).unwrap();
+        assert!(is_insult_opted_out(&conn, "user1").unwrap());
+        set_insult_opt_out(&conn, "user1", false).unwrap();
+        assert!(!is_insult_opted_out(&conn, "user1").unwrap());
+
+        assert!(set_character_protected(&conn, "Pyuul", Some("officer")).unwrap());
+        assert!(!set_character_protected(&conn, "pyuul", Some("officer")).unwrap());
+        assert!(is_character_protected(&conn, "PYUUL").unwrap());
+        assert_eq!(get_protected_characters(&conn).unwrap(), ["Pyuul"]);
+        assert!(set_character_protected(&conn, "Pyuul", None).unwrap());
+        assert!(!is_character_protected(&conn, "Pyuul").unwrap());
+    }
+
+    #[test]
+    fn test_counters() {
+        let conn = setup();
+        assert!(create_counter(&conn, "g1", "Deaths", "user1").unwrap());
+        assert!(!create_counter(&conn, "g1", "deaths", "user2").unwrap());
+        assert!(create_counter(&conn, "g2", "deaths", "user2").unwrap());
+
+        assert_eq!(increment_counter(&conn, "g1", "DEATHS", 1).unwrap(), Some(1));
+        assert_eq!(increment_counter(&conn, "g1", "deaths", 5).unwrap(), Some(6));
+        assert_eq!(increment_counter(&conn, "g1", "deaths", -2).unwrap(), Some(4));
+        assert_eq!(increment_counter(&conn, "g1", "wipes", 1).unwrap(), None);
+        assert_eq!(get_counters(&conn, "g1").unwrap(), [("Deaths".to_string(), 4)]);
+        assert_eq!(get_counters(&conn, "g2").unwrap(), [("deaths".to_string(), 0)]);
+
+        assert!(reset_counter(&conn, "g1", "deaths").unwrap());
+        assert_eq!(get_counters(&conn, "g1").unwrap(), [("Deaths".to_string(), 0)]);
+        assert!(delete_counter(&conn, "g1", "deaths").unwrap());
+        assert!(!delete_counter(&conn, "g1", "deaths").unwrap());
+        assert!(get_counters(&conn, "g1").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_tags() {
+        let conn = setup();
+        assert!(add_tag(&conn, "g1", "Rules", "Be nice", "user1").unwrap());
+        assert!(!add_tag(&conn, "g1", "rules", "Be mean", "user2").unwrap());
+        assert!(add_tag(&conn, "g2", "rules", "No rules", "user2").unwrap());
+
+        let tag = get_tag(&conn, "g1", "RULES").unwrap().unwrap();
+        assert_eq!(tag.name, "Rules");
+        assert_eq!(tag.content, "Be nice");
+        assert_eq!(tag.owner, "user1");
+        assert!(update_tag(&conn, "g1", "rules", "Be very nice").unwrap());
+        assert_eq!(get_tag(&conn, "g1", "rules").unwrap().unwrap().content, "Be very nice");
+        assert_eq!(get_tag(&conn, "g2", "rules").unwrap().unwrap().content, "No rules");
+        assert_eq!(get_tag(&conn, "g1", "missing").unwrap(), None);
+
+        assert!(add_tag(&conn, "g1", "boss", "https://example.com/boss.png", "user1").unwrap());
+        assert_eq!(get_tag_names(&conn, "g1").unwrap(), ["boss", "Rules"]);
+        assert!(remove_tag(&conn, "g1", "rules").unwrap());
+        assert!(!remove_tag(&conn, "g1", "rules").unwrap());
+        assert_eq!(get_tag_names(&conn, "g1").unwrap(), ["boss"]);
+    }
+
+    #[test]
+    fn test_todos() {
+        let conn = setup();
+        let pots = add_todo(&conn, "c1", "user1", "Farm 20 fire protection pots", Some(200)).unwrap();
+        let flasks = add_todo(&conn, "c1", "user1", "Buy flasks", None).unwrap();
+        let repair = add_todo(&conn, "c1", "user2", "Repair", Some(100)).unwrap();
+        add_todo(&conn, "c2", "user1", "Elsewhere", Some(50)).unwrap();
+
+        let ids = |todos: Vec<Todo>| todos.iter().map(|t| t.id).collect::<Vec<_>>();
+        assert_eq!(ids(get_open_todos(&conn, "c1", Some("user1")).unwrap()), [pots, flasks]);
+        assert_eq!(ids(get_open_todos(&conn, "c1", None).unwrap()), [repair, pots, flasks]);
+
+        // Only the owner can tick one off, in its own channel
+        assert!(!complete_todo(&conn, "c1", "user2", pots).unwrap());
+        assert!(!complete_todo(&conn, "c2", "user1", pots).unwrap());
+        assert!(complete_todo(&conn, "c1", "user1", pots).unwrap());
+        assert!(!complete_todo(&conn, "c1", "user1", pots).unwrap());
+        assert_eq!(ids(get_open_todos(&conn, "c1", Some("user1")).unwrap()), [flasks]);
+
+        let due = due_todos(&conn, 150).unwrap();
+        assert_eq!(due.iter().map(|t| t.text.as_str()).collect::<Vec<_>>(), ["Elsewhere", "Repair"]);
+        mark_todo_reminded(&conn, repair, 150).unwrap();
+        assert_eq!(due_todos(&conn, 300).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_collections() {
+        let conn = setup();
+        let entries = |ids: &[u64]| ids.iter().map(|&id| (id, format!("Mount {}", id))).collect::<Vec<_>>();
+        assert_eq!(collection_count(&conn, "Pyuul", "mounts").unwrap(), None);
+
+        // The first sync is a baseline: nothing counts as recently obtained
+        assert_eq!(sync_collection(&conn, "Pyuul", "mounts", &entries(&[1, 2]), 100).unwrap(), 0);
+        assert!(recent_collection_entries(&conn, "Pyuul", "mounts", 5).unwrap().is_empty());
+
+        assert_eq!(sync_collection(&conn, "Pyuul", "mounts", &entries(&[1, 2, 3]), 200).unwrap(), 1);
+        assert_eq!(sync_collection(&conn, "Pyuul", "mounts", &entries(&[2, 3, 4]), 300).unwrap(), 1);
+        assert_eq!(recent_collection_entries(&conn, "pyuul", "mounts", 5).unwrap(), ["Mount 4", "Mount 3"]);
+        assert_eq!(collection_count(&conn, "Pyuul", "mounts").unwrap(), Some(3));
+        assert_eq!(collection_count(&conn, "Pyuul", "pets").unwrap(), None);
+    }
+
+    #[test]
+    fn test_wow_token_prices() {
+        let conn = setup();
+        for (at, price) in [(100, 2_000_000_000), (200, 2_100_000_000), (300, 1_900_000_000)] {
+            assert!(record_wow_token_price(&conn, at, price).unwrap());
+        }
+        assert!(!record_wow_token_price(&conn, 300, 1).unwrap());
+        assert_eq!(get_wow_token_prices(&conn, 2).unwrap(), [(200, 2_100_000_000), (300, 1_900_000_000)]);
+    }
+
+    #[test]
+    fn test_attunement_steps() {
+        let conn = setup();
+        set_attunement_step(&conn, "Pyuul", "mc", 2, Some("user1")).unwrap();
+        set_attunement_step(&conn, "pyuul", "mc", 1, Some("user1")).unwrap();
+        set_attunement_step(&conn, "Pyuul", "mc", 1, Some("battle.net")).unwrap();
+        set_attunement_step(&conn, "Pyuul", "bwl", 1, Some("user1")).unwrap();
+        assert_eq!(get_attunement_steps(&conn, "PYUUL", "mc").unwrap(), [1, 2]);
+
+        set_attunement_step(&conn, "Pyuul", "mc", 2, None).unwrap();
+        assert_eq!(get_attunement_steps(&conn, "Pyuul", "mc").unwrap(), [1]);
+        assert!(get_attunement_steps(&conn, "Zara", "mc").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_checklists() {
+        let conn = setup();
+        assert!(add_checklist_item(&conn, "all", "Flasks").unwrap());
+        assert!(!add_checklist_item(&conn, "ALL", "flasks").unwrap());
+        add_checklist_item(&conn, "Rogue", "Poisons").unwrap();
+        add_checklist_item(&conn, "all", "Food buffs").unwrap();
+        assert_eq!(get_checklist_items(&conn, "all").unwrap(), ["Flasks", "Food buffs"]);
+        assert_eq!(get_checklist_items(&conn, "rogue").unwrap(), ["Poisons"]);
+        assert!(remove_checklist_item(&conn, "all", "FLASKS").unwrap());
+        assert_eq!(get_checklist_items(&conn, "all").unwrap(), ["Food buffs"]);
+
+        set_checklist_tick(&conn, "Pyuul", "Poisons", true).unwrap();
+        set_checklist_tick(&conn, "pyuul", "poisons", true).unwrap();
+        assert_eq!(get_checklist_ticks(&conn, "Pyuul").unwrap(), ["Poisons"]);
+        set_checklist_tick(&conn, "Pyuul", "Poisons", false).unwrap();
+        assert!(get_checklist_ticks(&conn, "Pyuul").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_feature_flags() {
+        let conn = setup();
+        assert_eq!(get_flag(&conn, "streaming", None).unwrap(), None);
+
+        set_flag_rollout(&conn, "streaming", 25).unwrap();
+        set_flag(&conn, "streaming", Some("g1"), true).unwrap();
+        set_flag(&conn, "streaming", None, false).unwrap();
+        let global = get_flag(&conn, "streaming", None).unwrap().unwrap();
+        assert!(!global.enabled);
+        assert_eq!(global.rollout_percent, 25);
+        assert!(get_flag(&conn, "streaming", Some("g1")).unwrap().unwrap().enabled);
+
+        let listed: Vec<_> = list_flags(&conn).unwrap().into_iter().map(|f| f.guild_id).collect();
+        assert_eq!(listed, [None, Some("g1".to_string())]);
+        assert!(clear_flag(&conn, "streaming", Some("g1")).unwrap());
+        assert!(!clear_flag(&conn, "streaming", Some("g1")).unwrap());
+        assert_eq!(list_flags(&conn).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_guild_config() {
+        let conn = setup();
+        set_config(&conn, None, "response_cap", "60").unwrap();
+        assert_eq!(get_response_cap(&conn, Some("g1")), 60);
+        set_guild_config(&conn, "g1", "response_cap", "25").unwrap();
+        assert_eq!(get_guild_config(&conn, "g1", "response_cap").unwrap().as_deref(), Some("25"));
+        assert_eq!(get_response_cap(&conn, Some("g1")), 25);
+        assert_eq!(get_response_cap(&conn, Some("g2")), 60);
+        assert_eq!(get_response_cap(&conn, None), 60);
+        assert!(delete_guild_config(&conn, "g1", "response_cap").unwrap());
+        assert_eq!(get_response_cap(&conn, Some("g1")), 60);
+
+        assert!(mark_guild_known(&conn, "g1").unwrap());
+        assert!(!mark_guild_known(&conn, "g1").unwrap());
+    }
+
+    #[test]
+    fn test_retention_pruning() {
+        let conn = setup();
+        for (name, level, taken_at) in [("Pyuul", 10, 100), ("Pyuul", 11, 200), ("Pyuul", 12, 900), ("Zara", 5, 100)] {
+            conn.execute(
+                "INSERT INTO character_snapshots (name, level, taken_at) VALUES (?1, ?2, ?3)",
+                params![name, level, taken_at],
+            )
+            .unwrap();
+        }
+        // Zara's only snapshot is kept, old as it is
+        assert_eq!(prune_snapshots(&conn, 500).unwrap(), 2);
+        assert_eq!(get_snapshots(&conn, "Pyuul").unwrap().len(), 1);
+        assert_eq!(latest_snapshot(&conn, "Zara").unwrap().unwrap().level, 5);
+
+        store_message(&conn, "chan1", "user", "old").unwrap();
+        conn.execute("UPDATE messages SET timestamp = 100", []).unwrap();
+        store_message(&conn, "chan1", "user", "new").unwrap();
+        assert_eq!(prune_messages_before(&conn, 500).unwrap(), 1);
+        assert_eq!(get_recent_messages(&conn, "chan1", 10).unwrap()[0].content, "new");
+
+        assert_eq!(get_retention_days(&conn, "messages").unwrap(), None);
+        set_retention_days(&conn, "messages", Some(30)).unwrap();
+        assert_eq!(get_retention_days(&conn, "messages").unwrap(), Some(30));
+        set_retention_days(&conn, "messages", None).unwrap();
+        assert_eq!(get_retention_days(&conn, "messages").unwrap(), None);
+    }
+
+    #[test]
+    fn test_compact() {
+        let conn = setup();
+        assert!(database_size(&conn).unwrap() > 0);
+        compact(&conn).unwrap();
+        let mode: i64 = conn.query_row("PRAGMA auto_vacuum", [], |row| row.get(0)).unwrap();
+        assert_eq!(mode, 2);
+    }
+
+    #[test]
+    fn test_scripts() {
+        let conn = setup();
+        save_script(&conn, &script("greet", "message", "^hi$")).unwrap();
+        save_script(&conn, &script("daily", "schedule", "1440")).unwrap();
+        // Saving again replaces
+        save_script(&conn, &script("Greet", "message", "^hello$")).unwrap();
+
+        let names: Vec<_> = get_scripts(&conn, Some("g1")).unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, ["daily", "greet"]);
+        assert_eq!(get_script(&conn, "g1", "greet").unwrap().unwrap().pattern, "^hello$");
+
+        // Another guild can have a script of the same name, with its own data and schedule
+        let other = Script {
+            guild_id: "g2".to_string(),
+            ..script("greet", "schedule", "60")
+        };
+        assert_eq!(get_script(&conn, "g2", "greet").unwrap(), None);
+        assert!(!remove_script(&conn, "g2", "greet").unwrap());
+        save_script(&conn, &other).unwrap();
+        assert_eq!(get_script(&conn, "g1", "greet").unwrap().unwrap().pattern, "^hello$");
+        assert_eq!(get_scripts(&conn, None).unwrap().len(), 3);
+
+        set_script_last_run(&conn, "g1", "daily", 500).unwrap();
+        set_script_last_run(&conn, "g2", "greet", 700).unwrap();
+        assert_eq!(get_script(&conn, "g1", "daily").unwrap().unwrap().last_run, 500);
+        assert_eq!(get_script(&conn, "g1", "greet").unwrap().unwrap().last_run, 0);
+
+        set_script_data(&conn, "g1", "greet", "count", "1").unwrap();
+        set_script_data(&conn, "g1", "greet", "count", "2").unwrap();
+        set_script_data(&conn, "g2", "greet", "count", "9").unwrap();
+        assert_eq!(get_script_data(&conn, "g1", "greet", "count").unwrap(), Some("2".to_string()));
+        assert_eq!(get_script_data(&conn, "g2", "greet", "count").unwrap(), Some("9".to_string()));
+        assert_eq!(get_script_data(&conn, "g1", "daily", "count").unwrap(), None);
+
+        assert!(remove_script(&conn, "g1", "GREET").unwrap());
+        assert!(!remove_script(&conn, "g1", "greet").unwrap());
+        assert_eq!(get_script_data(&conn, "g1", "greet", "count").unwrap(), None);
+        assert_eq!(get_script_data(&conn, "g2", "greet", "count").unwrap(), Some("9".to_string()));
+
+        // Scripts from before guilds are claimed, with their data, by the guild of their channel
+        let legacy = Script {
+            guild_id: String::new(),
+            ..script("old", "message", "^hi$")
+        };
+        save_script(&conn, &legacy).unwrap();
+        set_script_data(&conn, "", "old", "count", "3").unwrap();
+        assert_eq!(claim_scripts(&conn, "g2", "other channel").unwrap(), 0);
+        assert_eq!(claim_scripts(&conn, "g2", "1").unwrap(), 1);
+        assert!(get_script(&conn, "g2", "old").unwrap().is_some());
+        assert_eq!(get_script_data(&conn, "g2", "old", "count").unwrap(), Some("3".to_string()));
+    }
+
+    #[test]
+    fn test_migrate_script_guilds() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE scripts (
+                name TEXT PRIMARY KEY COLLATE NOCASE,
+                trigger TEXT NOT NULL,
+                pattern TEXT NOT NULL,
+                channel_id TEXT NOT NULL,
+                source TEXT NOT NULL,
+                created_by TEXT NOT NULL,
+                last_run INTEGER NOT NULL DEFAULT 0,
+                guild_id TEXT
+             );
+             CREATE TABLE script_data (
+                script TEXT NOT NULL COLLATE NOCASE,
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (script, key)
+             );
+             INSERT INTO scripts VALUES ('greet', 'message', '^hi$', '1', 'reply(\"hi\")', 'admin', 0, 'g1');
+             INSERT INTO scripts VALUES ('old', 'message', '^hi$', '2', 'reply(\"hi\")', 'admin', 0, NULL);
+             INSERT INTO script_data VALUES ('Greet', 'count', '2');
+             INSERT INTO script_data VALUES ('old', 'count', '5');",
+        )
+        .unwrap();
+        init(&conn).unwrap();
+
+        assert_eq!(get_script(&conn, "g1", "greet").unwrap().unwrap().channel_id, "1");
+        assert_eq!(get_script_data(&conn, "g1", "greet", "count").unwrap(), Some("2".to_string()));
+        assert_eq!(get_script_data(&conn, "", "old", "count").unwrap(), Some("5".to_string()));
+        assert_eq!(claim_scripts(&conn, "g2", "2").unwrap(), 1);
+        assert_eq!(get_script_data(&conn, "g2", "old", "count").unwrap(), Some("5".to_string()));
+    }
+
 }
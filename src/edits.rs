@@ -0,0 +1,75 @@
+use serenity::model::event::MessageUpdateEvent;
+use serenity::model::id::{ChannelId, MessageId};
+use serenity::prelude::Context;
+use tracing::{error, info};
+
+use crate::scheduler::unix_now;
+use crate::{db, mentions, Handler};
+
+/// How long after sending a message an edit to it still gets a new answer.
+const EDIT_WINDOW_SECS: i64 = 10 * 60;
+
+impl Handler {
+    /// Answers an edited message again when the bot already answered it, editing
+    /// the reply in place (see [`Handler::regenerate`]). Only edits within
+    /// [`EDIT_WINDOW_SECS`] of sending count, so fixing a typo in an old
+    /// message doesn't rewrite the conversation since.
+    pub(crate) async fn handle_message_edit(&self, ctx: &Context, event: &MessageUpdateEvent) {
+        // Updates without content are embeds being resolved, not edits
+        let Some(content) = &event.content else {
+            return;
+        };
+        if unix_now() - event.id.created_at().unix_timestamp() > EDIT_WINDOW_SECS {
+            return;
+        }
+        let found = {
+            let conn = self.db.lock().await;
+            db::get_exchange_for_prompt(&conn, &event.id.to_string()).unwrap_or_else(|e| {
+                error!("Failed to look up edited message: {}", e);
+                None
+            })
+        };
+        let Some((reply, exchange)) = found else {
+            return;
+        };
+        // With several bots only the one whose history has it answers again
+        let owner = match (&exchange.history_key, &exchange.user_id) {
+            (Some(key), Some(user_id)) => {
+                let conn = self.db.lock().await;
+                *key == self.history_key(&conn, &event.channel_id.to_string(), user_id)
+            }
+            _ => false,
+        };
+        if !owner || self.disabled_feature(event.guild_id, "chat").await.is_some() {
+            return;
+        }
+        let prompt = match ctx.http.get_current_user().await {
+            Ok(me) => mentions::strip_mention(content, me.id),
+            Err(e) => {
+                error!("Failed to get current user: {:?}", e);
+                return;
+            }
+        };
+        let Some(reply) = reply.parse().ok().map(MessageId::new) else {
+            return;
+        };
+        if prompt.is_empty() || prompt == exchange.prompt {
+            return;
+        }
+        info!("Message {} was edited; answering it again", event.id);
+        self.regenerate(&ctx.http, event.guild_id, event.channel_id, reply, &exchange, &prompt).await;
+    }
+
+    /// Removes deleted messages from the conversation histories, so what
+    /// someone took back stops shaping replies.
+    pub(crate) async fn handle_message_delete(&self, channel_id: ChannelId, message_ids: &[MessageId]) {
+        let conn = self.db.lock().await;
+        for message_id in message_ids {
+            match db::delete_discord_message(&conn, &message_id.to_string()) {
+                Ok(0) => {}
+                Ok(_) => info!("Forgot deleted message {} in {}", message_id, channel_id),
+                Err(e) => error!("Failed to forget deleted message: {}", e),
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+use rusqlite::Connection;
+
+use crate::{config, db};
+
+/// Marks a stored value as encrypted, so rows written before encryption was
+/// turned on still read as they are.
+pub const PREFIX: &str = "enc1:";
+/// AES-GCM's nonce length in bytes.
+const NONCE_LEN: usize = 12;
+
+/// Encrypts chat history and memories at rest with AES-256-GCM, each value
+/// with its own random nonce. The key is 32 bytes, base64-encoded, from
+/// `DATABASE_ENCRYPTION_KEY` (or a key file named by
+/// `DATABASE_ENCRYPTION_KEY_FILE`).
+pub struct Cipher(Aes256Gcm);
+
+impl Cipher {
+    pub fn from_base64(key: &str) -> Result<Cipher, String> {
+        let key = STANDARD
+            .decode(key.trim())
+            .map_err(|e| format!("DATABASE_ENCRYPTION_KEY isn't base64: {}", e))?;
+        Aes256Gcm::new_from_slice(&key)
+            .map(Cipher)
+            .map_err(|_| format!("DATABASE_ENCRYPTION_KEY must be 32 bytes (got {})", key.len()))
+    }
+
+    /// The cipher for the configured key, if there is one.
+    pub fn from_env() -> Result<Option<Cipher>, String> {
+        config::env_var("DATABASE_ENCRYPTION_KEY")?
+            .map(|key| Cipher::from_base64(&key))
+            .transpose()
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> String {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        // Encrypting into a Vec only fails if it can't allocate
+        let ciphertext = self.0.encrypt(&nonce, plaintext.as_bytes()).unwrap_or_default();
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        format!("{}{}", PREFIX, STANDARD.encode(sealed))
+    }
+
+    /// The plaintext of a stored value; values without [`PREFIX`] were stored
+    /// before encryption and come back unchanged.
+    pub fn decrypt(&self, stored: &str) -> Result<String, String> {
+        let Some(encoded) = stored.strip_prefix(PREFIX) else {
+            return Ok(stored.to_string());
+        };
+        let sealed = STANDARD.decode(encoded).map_err(|e| format!("Corrupt encrypted value: {}", e))?;
+        if sealed.len() < NONCE_LEN {
+            return Err("Corrupt encrypted value: too short".to_string());
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let plaintext = self
+            .0
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "Can't decrypt a stored value; is DATABASE_ENCRYPTION_KEY the one it was written with?".to_string())?;
+        String::from_utf8(plaintext).map_err(|e| format!("Corrupt encrypted value: {}", e))
+    }
+}
+
+/// Turns on encryption for `conn` if a key is configured, encrypting whatever
+/// was stored in plaintext before. Returns how many rows that changed. Fails
+/// without a key if anything is already encrypted, since it couldn't be read.
+pub fn enable(conn: &Connection) -> Result<usize, String> {
+    let Some(cipher) = Cipher::from_env()? else {
+        return match db::has_encrypted_rows(conn) {
+            Ok(true) => Err("The database holds encrypted messages but DATABASE_ENCRYPTION_KEY isn't set".to_string()),
+            Ok(false) => Ok(0),
+            Err(e) => Err(e.to_string()),
+        };
+    };
+    db::use_cipher(conn, Some(cipher)).map_err(|e| e.to_string())?;
+    db::encrypt_plaintext(conn).map_err(|e| format!("Failed to encrypt stored messages: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> String {
+        STANDARD.encode([7u8; 32])
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let cipher = Cipher::from_base64(&key()).unwrap();
+        let sealed = cipher.encrypt("my secret DM");
+        assert!(sealed.starts_with(PREFIX));
+        assert!(!sealed.contains("secret"));
+        // A fresh nonce every time
+        assert_ne!(sealed, cipher.encrypt("my secret DM"));
+        assert_eq!(cipher.decrypt(&sealed).unwrap(), "my secret DM");
+        assert_eq!(cipher.decrypt("written before").unwrap(), "written before");
+
+        let other = Cipher::from_base64(&STANDARD.encode([8u8; 32])).unwrap();
+        assert!(other.decrypt(&sealed).is_err());
+        assert!(cipher.decrypt("enc1:!!").is_err());
+    }
+
+    #[test]
+    fn test_bad_keys() {
+        assert!(Cipher::from_base64("not base64!").is_err());
+        assert!(Cipher::from_base64(&STANDARD.encode([1u8; 16])).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+//! Optional Sentry reporting, compiled in with the `sentry` feature and turned
+//! on by setting `SENTRY_DSN`. Panics and `error!` events are sent as Sentry
+//! events, with the `warn!`/`info!` lines before them as breadcrumbs, tagged
+//! with the command and guild being handled. Without the feature every
+//! function here is a no-op.
+
+use serenity::model::id::GuildId;
+use std::future::Future;
+use tracing::Subscriber;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Environment variable holding the Sentry DSN.
+pub const DSN_VAR: &str = "SENTRY_DSN";
+
+/// Keeps the Sentry client alive; queued events are flushed when it drops, so
+/// hold it until the process exits.
+pub struct Guard {
+    #[cfg(feature = "sentry")]
+    _client: sentry::ClientInitGuard,
+}
+
+/// Starts the Sentry client if `SENTRY_DSN` is set (and the feature is on).
+#[cfg(feature = "sentry")]
+pub fn init() -> Option<Guard> {
+    let dsn = std::env::var(DSN_VAR).ok().filter(|dsn| !dsn.trim().is_empty())?;
+    let mut options = sentry::ClientOptions::new();
+    options.release = sentry::release_name!();
+    options.attach_stacktrace = true;
+    let client = sentry::init((dsn, options));
+    client.is_enabled().then_some(Guard { _client: client })
+}
+
+#[cfg(not(feature = "sentry"))]
+pub fn init() -> Option<Guard> {
+    if std::env::var_os(DSN_VAR).is_some() {
+        eprintln!("{} is set but this build has no Sentry support (the `sentry` feature)", DSN_VAR);
+    }
+    None
+}
+
+/// The tracing layer feeding Sentry: errors become events, warnings and info
+/// become breadcrumbs. Does nothing until [`init`] has started a client.
+#[cfg(feature = "sentry")]
+pub fn layer<S>() -> Option<impl Layer<S>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    Some(sentry::integrations::tracing::layer())
+}
+
+#[cfg(not(feature = "sentry"))]
+pub fn layer<S>() -> Option<impl Layer<S>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    None::<tracing_subscriber::layer::Identity>
+}
+
+/// Runs `f` with its own Sentry scope, so tags set while handling one event
+/// don't leak into another handled concurrently.
+#[cfg(feature = "sentry")]
+pub fn scoped<F: Future>(correlation_id: &str, f: F) -> impl Future<Output = F::Output> {
+    use sentry::{Hub, SentryFutureExt};
+    let hub = Hub::new_from_top(Hub::current());
+    hub.configure_scope(|scope| scope.set_tag("correlation_id", correlation_id));
+    f.bind_hub(hub)
+}
+
+#[cfg(not(feature = "sentry"))]
+pub fn scoped<F: Future>(_correlation_id: &str, f: F) -> impl Future<Output = F::Output> {
+    f
+}
+
+/// Tags errors reported from here on in this event with the command being run
+/// and the guild it came from.
+#[cfg(feature = "sentry")]
+pub fn set_context(command: &str, guild_id: Option<GuildId>) {
+    sentry::configure_scope(|scope| {
+        scope.set_tag("command", command);
+        match guild_id {
+            Some(guild_id) => scope.set_tag("guild_id", guild_id),
+            None => scope.set_tag("guild_id", "dm"),
+        }
+    });
+}
+
+#[cfg(not(feature = "sentry"))]
+pub fn set_context(_command: &str, _guild_id: Option<GuildId>) {}
@@ -0,0 +1,77 @@
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::Handler;
+
+/// How many events a slow subscriber may fall behind before it starts missing them.
+pub const CHANNEL_CAPACITY: usize = 256;
+
+/// Something the bot did, broadcast to `/ws/events` subscribers.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BotEvent {
+    CommandInvoked {
+        command: String,
+        user_id: String,
+        channel_id: String,
+        guild_id: Option<String>,
+        slash: bool,
+    },
+    LlmReply {
+        context: String,
+        reply: String,
+    },
+    LevelUp {
+        name: String,
+        from: u32,
+        to: u32,
+    },
+}
+
+/// An event stamped with when it happened, as sent over the wire.
+#[derive(Serialize)]
+struct Envelope<'a> {
+    at: i64,
+    #[serde(flatten)]
+    event: &'a BotEvent,
+}
+
+impl BotEvent {
+    pub fn to_json(&self, at: i64) -> String {
+        serde_json::to_string(&Envelope { at, event: self }).unwrap_or_default()
+    }
+}
+
+pub fn channel() -> broadcast::Sender<BotEvent> {
+    broadcast::channel(CHANNEL_CAPACITY).0
+}
+
+impl Handler {
+    /// Broadcasts `event` to any listeners. Nobody listening is not an error.
+    pub(crate) fn emit(&self, event: BotEvent) {
+        let _ = self.events.send(event);
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<BotEvent> {
+        self.events.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_json() {
+        let event = BotEvent::LevelUp {
+            name: "Pyuul".to_string(),
+            from: 39,
+            to: 40,
+        };
+        let value: serde_json::Value = serde_json::from_str(&event.to_json(1_700_000_000)).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({ "type": "level_up", "at": 1_700_000_000, "name": "Pyuul", "from": 39, "to": 40 })
+        );
+    }
+}
@@ -0,0 +1,240 @@
+use chrono::DateTime;
+use serde_json::json;
+
+use crate::db::{CharacterSnapshot, StoredMessage, TrackedCharacter};
+
+/// Export file formats for `!character export`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name.to_lowercase().as_str() {
+            "csv" => Some(Format::Csv),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Json => "json",
+        }
+    }
+}
+
+/// A tracked character with its full snapshot history, oldest first.
+pub struct CharacterHistory {
+    pub character: TrackedCharacter,
+    pub snapshots: Vec<CharacterSnapshot>,
+}
+
+impl CharacterHistory {
+    fn current_level(&self) -> Option<u32> {
+        self.snapshots.last().map(|s| s.level)
+    }
+}
+
+/// RFC 3339 UTC time, which spreadsheets parse as a date.
+fn timestamp(unix: i64) -> String {
+    DateTime::from_timestamp(unix, 0)
+        .map(|t| t.to_rfc3339())
+        .unwrap_or_else(|| unix.to_string())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// One row per snapshot, with the character's current level repeated on each row
+/// so a pivot table needs no lookups. Characters without snapshots get a single
+/// row with the snapshot columns left blank.
+pub fn to_csv(histories: &[CharacterHistory]) -> String {
+    let mut out = String::from(
+        "name,current_level,added_by,added_at,taken_at,level,experience,honorable_kills,honor_level\n",
+    );
+    for history in histories {
+        let c = &history.character;
+        let prefix = format!(
+            "{},{},{},{}",
+            csv_field(&c.name),
+            optional(history.current_level()),
+            csv_field(&c.added_by),
+            timestamp(c.added_at)
+        );
+        if history.snapshots.is_empty() {
+            out.push_str(&format!("{},,,,,\n", prefix));
+            continue;
+        }
+        for s in &history.snapshots {
+            out.push_str(&format!(
+                "{},{},{},{},{},{}\n",
+                prefix,
+                timestamp(s.taken_at),
+                s.level,
+                optional(s.experience),
+                optional(s.honorable_kills),
+                optional(s.honor_level)
+            ));
+        }
+    }
+    out
+}
+
+pub fn to_json(histories: &[CharacterHistory]) -> String {
+    let characters: Vec<_> = histories
+        .iter()
+        .map(|history| {
+            let c = &history.character;
+            let snapshots: Vec<_> = history
+                .snapshots
+                .iter()
+                .map(|s| {
+                    json!({
+                        "taken_at": timestamp(s.taken_at),
+                        "level": s.level,
+                        "experience": s.experience,
+                        "honorable_kills": s.honorable_kills,
+                        "honor_level": s.honor_level,
+                    })
+                })
+                .collect();
+            json!({
+                "name": c.name,
+                "current_level": history.current_level(),
+                "added_by": c.added_by,
+                "added_at": timestamp(c.added_at),
+                "game_version": c.game_version,
+                "snapshots": snapshots,
+            })
+        })
+        .collect();
+    serde_json::to_string_pretty(&json!({ "characters": characters })).unwrap_or_default()
+}
+
+pub fn render(format: Format, histories: &[CharacterHistory]) -> String {
+    match format {
+        Format::Csv => to_csv(histories),
+        Format::Json => to_json(histories),
+    }
+}
+
+/// A conversation as a Markdown document for `!transcript`: each message
+/// under its speaker and time, quoted so its own formatting stays inside.
+pub fn transcript(messages: &[StoredMessage], bot_name: &str) -> String {
+    let mut doc = String::from("# Conversation transcript\n");
+    for message in messages {
+        let speaker = match message.role.as_str() {
+            "assistant" => bot_name,
+            _ => message.author.as_deref().unwrap_or("User"),
+        };
+        let time = DateTime::from_timestamp(message.timestamp, 0)
+            .map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+            .unwrap_or_default();
+        doc.push_str(&format!("\n**{}** · {}\n", speaker, time));
+        for line in message.content.lines() {
+            doc.push_str(&format!("> {}\n", line));
+        }
+    }
+    doc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(name: &str, levels: &[(i64, u32)]) -> CharacterHistory {
+        CharacterHistory {
+            character: TrackedCharacter {
+                name: name.to_string(),
+                added_by: "user1".to_string(),
+                added_at: 0,
+                game_version: None,
+            },
+            snapshots: levels
+                .iter()
+                .map(|&(taken_at, level)| CharacterSnapshot {
+                    name: name.to_string(),
+                    level,
+                    experience: None,
+                    honorable_kills: Some(3),
+                    honor_level: None,
+                    taken_at,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_csv() {
+        let csv = to_csv(&[history("Pyuul", &[(0, 10), (86_400, 12)]), history("Odd,\"Name\"", &[])]);
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[1],
+            "Pyuul,12,user1,1970-01-01T00:00:00+00:00,1970-01-01T00:00:00+00:00,10,,3,"
+        );
+        assert_eq!(
+            lines[2],
+            "Pyuul,12,user1,1970-01-01T00:00:00+00:00,1970-01-02T00:00:00+00:00,12,,3,"
+        );
+        assert_eq!(lines[3], "\"Odd,\"\"Name\"\"\",,user1,1970-01-01T00:00:00+00:00,,,,,");
+        // Every row has as many columns as the header
+        assert_eq!(lines[1].matches(',').count(), lines[0].matches(',').count());
+    }
+
+    #[test]
+    fn test_json() {
+        let value: serde_json::Value =
+            serde_json::from_str(&to_json(&[history("Pyuul", &[(0, 10), (86_400, 12)])])).unwrap();
+        let character = &value["characters"][0];
+        assert_eq!(character["name"], "Pyuul");
+        assert_eq!(character["current_level"], 12);
+        assert_eq!(character["snapshots"].as_array().unwrap().len(), 2);
+        assert_eq!(character["snapshots"][0]["honorable_kills"], 3);
+    }
+
+    #[test]
+    fn test_format_from_name() {
+        assert_eq!(Format::from_name("CSV"), Some(Format::Csv));
+        assert_eq!(Format::from_name("json"), Some(Format::Json));
+        assert_eq!(Format::from_name("xlsx"), None);
+    }
+
+    #[test]
+    fn test_transcript() {
+        let message = |role: &str, author: Option<&str>, content: &str, timestamp: i64| StoredMessage {
+            role: role.to_string(),
+            author: author.map(str::to_string),
+            content: content.to_string(),
+            timestamp,
+        };
+        let doc = transcript(
+            &[
+                message("user", Some("Pyuul"), "hi\n# not a heading", 0),
+                message("assistant", None, "go away", 60),
+                message("user", None, "please", 120),
+            ],
+            "Grumpy",
+        );
+        assert_eq!(
+            doc,
+            "# Conversation transcript\n\
+             \n**Pyuul** · 1970-01-01 00:00 UTC\n> hi\n> # not a heading\n\
+             \n**Grumpy** · 1970-01-01 00:01 UTC\n> go away\n\
+             \n**User** · 1970-01-01 00:02 UTC\n> please\n"
+        );
+    }
+}
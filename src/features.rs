@@ -0,0 +1,73 @@
+/// Subsystems that can be switched off per guild with `!feature disable <name>`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Feature {
+    Chat,
+    Wow,
+    LevelCheck,
+    Fun,
+    Scripts,
+    Lol,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 6] = [
+        Feature::Chat,
+        Feature::Wow,
+        Feature::LevelCheck,
+        Feature::Fun,
+        Feature::Scripts,
+        Feature::Lol,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::Chat => "chat",
+            Feature::Wow => "wow",
+            Feature::LevelCheck => "levelcheck",
+            Feature::Fun => "fun",
+            Feature::Scripts => "scripts",
+            Feature::Lol => "lol",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Feature::Chat => "LLM chat and conversation history",
+            Feature::Wow => "WoW character tracking and level checks",
+            Feature::LevelCheck => "Level checks only",
+            Feature::Fun => "`!ping`, `!hello` and other toys",
+            Feature::Scripts => "Admin-defined Rhai scripts",
+            Feature::Lol => "League of Legends tracking",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Feature> {
+        Feature::ALL
+            .into_iter()
+            .find(|f| f.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Features that must all be enabled for `command` (e.g. `levelcheck`) to run.
+/// Commands not listed here, like `help` and the config commands, are always available.
+pub fn required_for(command: &str) -> &'static [Feature] {
+    match command {
+        "clear" | "movecontext" | "checkpoint" | "checkpoint save" | "checkpoint load" | "checkpoint delete" | "checkpoint list" | "transcript" | "memories" | "helpchannel" | "contextchannel" | "contextuser" | "intensity" | "style" | "safemode" | "preamble" | "listen" | "ask" | "chat" | "Ask the bot" => &[Feature::Chat],
+        "rp" | "rp start" | "rp status" | "rp end" => &[Feature::Chat],
+        "faq" | "faq add" | "faq remove" | "faq list" => &[Feature::Chat],
+        "kb" | "kb upload" | "kb list" | "kb remove" => &[Feature::Chat],
+        "wakeword" | "wakeword add" | "wakeword remove" | "wakeword list" => &[Feature::Chat],
+        "persona" | "persona import" | "persona list" | "persona use" | "persona remove" | "persona schedule" => &[Feature::Chat],
+        "removecharacter" | "character" | "character add" | "character remove"
+        | "character list" | "character info" | "character version" | "character export" | "wowversion" | "professions" | "crafters" | "rep" | "pvp" | "pvpreport" | "slackers" | "milestones" | "race" | "chart" => &[Feature::Wow],
+        "attune" | "attunements" | "checklist" | "wowtoken" | "pets" | "mounts" => &[Feature::Wow],
+        "levelcheck" | "levelcheckraw" | "insultstyle" | "protect" | "unprotect" | "hype" | "hypemode" => &[Feature::Wow, Feature::LevelCheck],
+        "lol" | "lol add" | "lol remove" | "lol rank" | "lol matches" | "lol list" | "lol ladder" | "lol announce" => &[Feature::Lol],
+        "ping" | "hello" | "confess" | "confessions" => &[Feature::Fun],
+        "todo" | "todo add" | "todo list" | "todo done" => &[Feature::Fun],
+        "tag" | "tag add" | "tag edit" | "tag remove" | "tag info" | "tag list" => &[Feature::Fun],
+        "counter" | "counter create" | "counter inc" | "counter reset" | "counter delete" | "counter show" => &[Feature::Fun],
+        "script" | "script add" | "script remove" | "script list" | "script show" => &[Feature::Scripts],
+        _ => &[],
+    }
+}
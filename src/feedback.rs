@@ -0,0 +1,115 @@
+use rusqlite::Connection;
+use serenity::model::channel::{Reaction, ReactionType};
+use serenity::model::id::MessageId;
+use tracing::{error, info};
+
+use crate::bots::Conversation;
+use crate::clients::ChatMessage;
+use crate::{db, Handler};
+
+/// How many upvoted exchanges are shown to the LLM as examples.
+pub const FEW_SHOT_EXAMPLES: usize = 3;
+
+const UPVOTE: &str = "👍";
+const DOWNVOTE: &str = "👎";
+
+/// +1 for 👍, -1 for 👎, `None` for any other reaction.
+fn vote(emoji: &ReactionType) -> Option<i64> {
+    match emoji {
+        ReactionType::Unicode(e) if e == UPVOTE => Some(1),
+        ReactionType::Unicode(e) if e == DOWNVOTE => Some(-1),
+        _ => None,
+    }
+}
+
+/// Upvoted exchanges for `persona` as user/assistant message pairs, oldest
+/// first, to show the LLM what the server liked.
+pub fn few_shot_messages(conn: &Connection, persona: &str) -> rusqlite::Result<Vec<ChatMessage>> {
+    let mut examples = db::top_feedback_examples(conn, persona, FEW_SHOT_EXAMPLES)?;
+    examples.reverse();
+    Ok(examples
+        .into_iter()
+        .flat_map(|(prompt, reply)| [ChatMessage::new("user", prompt), ChatMessage::new("assistant", reply)])
+        .collect())
+}
+
+impl Handler {
+    /// Remembers which exchange the messages in `sent` carry, so 👍 and 👎
+    /// reactions on them can be credited to it and the [`crate::reactions`]
+    /// actions can find it.
+    pub(crate) async fn record_exchange(
+        &self,
+        conversation: &Conversation,
+        prompt: &str,
+        reply: &str,
+        sent: &[MessageId],
+    ) {
+        let conn = self.db.lock().await;
+        let persona = match self.persona(&conn, conversation.guild_id, conversation.channel_id) {
+            Ok(persona) => persona,
+            Err(e) => {
+                error!("Failed to record exchange: {}", e);
+                return;
+            }
+        };
+        let user_id = conversation.user_id.map(|id| id.to_string());
+        let prompt_message_id = conversation.message_id.map(|id| id.to_string());
+        for message_id in sent {
+            let message_id = message_id.to_string();
+            if let Err(e) = db::record_exchange(&conn, &message_id, &persona, prompt, reply)
+                .and_then(|_| db::set_exchange_origin(
+                    &conn,
+                    &message_id,
+                    &conversation.key,
+                    user_id.as_deref(),
+                    prompt_message_id.as_deref(),
+                ))
+            {
+                error!("Failed to record exchange: {}", e);
+            }
+        }
+    }
+
+    /// Counts a 👍 or 👎 on one of the bot's replies; `added` is false when the
+    /// reaction was removed.
+    pub(crate) async fn handle_reaction(&self, reaction: &Reaction, added: bool) {
+        let Some(mut delta) = vote(&reaction.emoji) else {
+            return;
+        };
+        if !added {
+            delta = -delta;
+        }
+        let conn = self.db.lock().await;
+        match db::add_feedback_vote(&conn, &reaction.message_id.to_string(), delta) {
+            Ok(true) => info!("Feedback {:+} on reply {}", delta, reaction.message_id),
+            Ok(false) => {}
+            Err(e) => error!("Failed to record feedback: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vote() {
+        assert_eq!(vote(&ReactionType::Unicode(UPVOTE.to_string())), Some(1));
+        assert_eq!(vote(&ReactionType::Unicode(DOWNVOTE.to_string())), Some(-1));
+        assert_eq!(vote(&ReactionType::Unicode("🎉".to_string())), None);
+    }
+
+    #[test]
+    fn test_few_shot_messages() {
+        let conn = Connection::open_in_memory().unwrap();
+        db::init(&conn).unwrap();
+        db::record_exchange(&conn, "1", "rude", "hi", "go away").unwrap();
+        db::record_exchange(&conn, "2", "rude", "help", "no").unwrap();
+        db::add_feedback_vote(&conn, "1", 1).unwrap();
+
+        let messages = few_shot_messages(&conn, "rude").unwrap();
+        let pairs: Vec<_> = messages.iter().map(|m| (m.role.as_str(), m.content.as_str())).collect();
+        assert_eq!(pairs, [("user", "hi"), ("assistant", "go away")]);
+        assert!(few_shot_messages(&conn, "nice").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,102 @@
+use rusqlite::Connection;
+use serenity::model::id::GuildId;
+use tracing::error;
+
+use crate::db;
+
+/// Whether flag `name` (e.g. `streaming`) is on for `guild_id`. A guild's own
+/// setting wins over the global one; a flag that's off globally can still be
+/// on for the share of guilds it's rolled out to. Unknown flags are off, and
+/// DMs only see the global setting.
+///
+/// Unlike [`crate::features`], which admins use to switch finished subsystems
+/// off, flags are for the bot's owner to try risky new ones on a test server
+/// first.
+pub fn enabled(conn: &Connection, name: &str, guild_id: Option<GuildId>) -> bool {
+    let guild = guild_id.map(|g| g.to_string());
+    let setting = |guild: Option<&str>| {
+        db::get_flag(conn, name, guild).unwrap_or_else(|e| {
+            error!("Failed to read flag {}: {}", name, e);
+            None
+        })
+    };
+
+    if let Some(own) = guild.as_deref().and_then(|g| setting(Some(g))) {
+        return own.enabled;
+    }
+    match (setting(None), guild_id) {
+        (Some(global), _) if global.enabled => true,
+        (Some(global), Some(guild_id)) => in_rollout(name, guild_id, global.rollout_percent),
+        _ => false,
+    }
+}
+
+/// Whether `guild_id` falls in the first `percent` of guilds for flag `name`.
+/// Each guild lands in a fixed bucket per flag, so raising the percentage only
+/// adds guilds, and different flags reach different guilds first.
+pub fn in_rollout(name: &str, guild_id: GuildId, percent: u8) -> bool {
+    bucket(name, guild_id) < u32::from(percent)
+}
+
+/// A stable 0-99 bucket for `guild_id` under flag `name` (FNV-1a, which unlike
+/// the standard library's hasher doesn't change between releases).
+fn bucket(name: &str, guild_id: GuildId) -> u32 {
+    let key = format!("{}:{}", name, guild_id);
+    let hash = key
+        .bytes()
+        .fold(0x811c_9dc5u32, |h, b| (h ^ u32::from(b)).wrapping_mul(0x0100_0193));
+    hash % 100
+}
+
+/// Flag names are lowercase words joined by `_` or `-`, like `tool_calling`.
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 32
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enabled() {
+        let conn = Connection::open_in_memory().unwrap();
+        db::init(&conn).unwrap();
+        let (test_server, other) = (GuildId::new(1), GuildId::new(2));
+        assert!(!enabled(&conn, "streaming", Some(test_server)));
+
+        db::set_flag(&conn, "streaming", Some("1"), true).unwrap();
+        assert!(enabled(&conn, "streaming", Some(test_server)));
+        assert!(!enabled(&conn, "streaming", Some(other)));
+        assert!(!enabled(&conn, "streaming", None));
+
+        db::set_flag(&conn, "streaming", None, true).unwrap();
+        db::set_flag(&conn, "streaming", Some("1"), false).unwrap();
+        assert!(!enabled(&conn, "streaming", Some(test_server)));
+        assert!(enabled(&conn, "streaming", Some(other)));
+        assert!(enabled(&conn, "streaming", None));
+    }
+
+    #[test]
+    fn test_rollout() {
+        let guilds: Vec<_> = (1..=1000).map(GuildId::new).collect();
+        let reached = |percent| guilds.iter().filter(|&&g| in_rollout("streaming", g, percent)).count();
+        assert_eq!(reached(0), 0);
+        assert_eq!(reached(100), 1000);
+        assert!((150..350).contains(&reached(25)));
+        // Raising the percentage keeps everyone already in
+        assert!(guilds
+            .iter()
+            .filter(|&&g| in_rollout("streaming", g, 10))
+            .all(|&g| in_rollout("streaming", g, 50)));
+    }
+
+    #[test]
+    fn test_valid_name() {
+        assert!(valid_name("tool_calling"));
+        assert!(!valid_name("Streaming"));
+        assert!(!valid_name("a b"));
+        assert!(!valid_name(""));
+    }
+}
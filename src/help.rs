@@ -0,0 +1,83 @@
+use serenity::builder::{CreateActionRow, CreateButton, CreateEmbed, CreateEmbedFooter};
+use serenity::model::application::ButtonStyle;
+use std::sync::Arc;
+
+use crate::commands;
+use crate::modules::BotModule;
+
+/// Prefix for the `custom_id` of every help category button.
+pub const CUSTOM_ID_PREFIX: &str = "help:";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Chat,
+    Wow,
+    Admin,
+    Fun,
+}
+
+impl Category {
+    pub const ALL: [Category; 4] = [Category::Chat, Category::Wow, Category::Admin, Category::Fun];
+
+    fn id(self) -> &'static str {
+        match self {
+            Category::Chat => "chat",
+            Category::Wow => "wow",
+            Category::Admin => "admin",
+            Category::Fun => "fun",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Category::Chat => "Chat",
+            Category::Wow => "WoW",
+            Category::Admin => "Admin",
+            Category::Fun => "Fun",
+        }
+    }
+
+    pub fn custom_id(self) -> String {
+        format!("{}{}", CUSTOM_ID_PREFIX, self.id())
+    }
+
+    pub fn from_custom_id(custom_id: &str) -> Option<Category> {
+        let id = custom_id.strip_prefix(CUSTOM_ID_PREFIX)?;
+        Category::ALL.into_iter().find(|c| c.id() == id)
+    }
+}
+
+/// The help page for `category`: commands from the [`commands`] registry
+/// first, then those the running modules list in [`BotModule::usage`].
+pub fn embed(category: Category, modules: &[Arc<dyn BotModule>], cap: u32) -> CreateEmbed {
+    let mut lines = commands::help_lines(category);
+    lines.extend(
+        modules
+            .iter()
+            .flat_map(|module| module.usage())
+            .filter(|usage| usage.category == category)
+            .map(|usage| usage.line()),
+    );
+    let embed = CreateEmbed::new()
+        .title(format!("Commands — {}", category.label()))
+        .description(lines.join("\n"));
+    match category {
+        Category::Admin => embed.footer(CreateEmbedFooter::new(format!("This server's response word cap: {}", cap))),
+        _ => embed,
+    }
+}
+
+pub fn buttons(selected: Category) -> Vec<CreateActionRow> {
+    let buttons = Category::ALL
+        .into_iter()
+        .map(|c| {
+            let style = if c == selected {
+                ButtonStyle::Primary
+            } else {
+                ButtonStyle::Secondary
+            };
+            CreateButton::new(c.custom_id()).label(c.label()).style(style)
+        })
+        .collect();
+    vec![CreateActionRow::Buttons(buttons)]
+}
@@ -0,0 +1,302 @@
+use serenity::model::id::GuildId;
+
+use crate::{db, Handler};
+
+/// How the one-shot insults and roasts are phrased, set per guild with
+/// `!insultstyle`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Style {
+    /// Whatever the system prompt's persona makes of it.
+    #[default]
+    Default,
+    Shakespearean,
+    DrillSergeant,
+    PassiveAggressive,
+}
+
+impl Style {
+    pub const ALL: [Style; 4] = [Style::Default, Style::Shakespearean, Style::DrillSergeant, Style::PassiveAggressive];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Style::Default => "default",
+            Style::Shakespearean => "shakespearean",
+            Style::DrillSergeant => "drill-sergeant",
+            Style::PassiveAggressive => "passive-aggressive",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Style> {
+        Style::ALL.into_iter().find(|s| s.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Added to the prompt to set the tone.
+    fn instruction(self) -> Option<&'static str> {
+        match self {
+            Style::Default => None,
+            Style::Shakespearean => Some("Write it as an Elizabethan insult, with thee, thou and archaic words."),
+            Style::DrillSergeant => Some("Write it like a drill sergeant screaming at a recruit, in capitals."),
+            Style::PassiveAggressive => Some("Write it passive-aggressively, as a polite remark that is really a dig."),
+        }
+    }
+
+    pub fn style_names() -> String {
+        let names: Vec<_> = Style::ALL.iter().map(|s| format!("`{}`", s.name())).collect();
+        names.join(", ")
+    }
+}
+
+/// Whether scheduled announcements roast or hype, set per guild with
+/// `!hypemode`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Mode {
+    #[default]
+    Roast,
+    Hype,
+}
+
+impl Mode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Roast => "roast",
+            Mode::Hype => "hype",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Mode> {
+        [Mode::Roast, Mode::Hype].into_iter().find(|m| m.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Who or what is being insulted (or, for [`Target::Hype`], praised).
+pub enum Target<'a> {
+    /// A level check line; `material` is anything else known about the character.
+    LevelCheck {
+        name: &'a str,
+        level: u32,
+        description: &'a str,
+        material: Option<&'a str>,
+    },
+    /// A level milestone announcement, which gets backhanded congratulations.
+    Milestone { name: &'a str, level: u32 },
+    /// The `!slackers` report's verdict on everyone idle, as `name (N days)` entries.
+    Slackers { list: &'a str },
+    /// Over-the-top praise for `name`; `about` is anything known about them.
+    Hype { name: &'a str, about: Option<&'a str> },
+}
+
+/// The one-shot prompt for `target` in `style`.
+pub fn prompt(style: Style, target: &Target) -> String {
+    let (task, reply) = match target {
+        Target::LevelCheck {
+            name,
+            level,
+            description,
+            material,
+        } => {
+            let material = material.map(|m| format!(" {}", m)).unwrap_or_default();
+            (
+                format!("Give a 1-5 word insult for a level {} {} named {}.{}", level, description, name, material),
+                "Reply with ONLY the insult, nothing else.",
+            )
+        }
+        Target::Milestone { name, level } => (
+            format!("{} just reached level {} in WoW. Congratulate them in one short sentence.", name, level),
+            "Reply with ONLY the sentence.",
+        ),
+        Target::Slackers { list } => (
+            format!("These WoW players haven't leveled in days: {}. Roast them in one short sentence.", list),
+            "Reply with ONLY the sentence.",
+        ),
+        Target::Hype { name, about } => {
+            let about = about.map(|a| format!(" ({})", a)).unwrap_or_default();
+            (
+                format!("Hype up {}{} with over-the-top praise in one or two sentences.", name, about),
+                "Reply with ONLY the praise.",
+            )
+        }
+    };
+    // The styles are all ways of insulting, so praise ignores them
+    let instruction = match target {
+        Target::Hype { .. } => None,
+        _ => style.instruction(),
+    };
+    match instruction {
+        Some(instruction) => format!("{} {} {}", task, instruction, reply),
+        None => format!("{} {}", task, reply),
+    }
+}
+
+impl Handler {
+    /// The insult style for `guild_id`; DMs get the default.
+    pub(crate) async fn insult_style(&self, guild_id: Option<GuildId>) -> Style {
+        let Some(guild_id) = guild_id else {
+            return Style::Default;
+        };
+        let conn = self.db.lock().await;
+        db::get_guild_config(&conn, &guild_id.to_string(), "insult_style")
+            .ok()
+            .flatten()
+            .and_then(|v| Style::from_name(&v))
+            .unwrap_or_default()
+    }
+
+    /// Roast or hype mode for `guild_id`; DMs get roasts.
+    pub(crate) async fn report_mode(&self, guild_id: Option<GuildId>) -> Mode {
+        let Some(guild_id) = guild_id else {
+            return Mode::Roast;
+        };
+        let conn = self.db.lock().await;
+        db::get_guild_config(&conn, &guild_id.to_string(), "report_mode")
+            .ok()
+            .flatten()
+            .and_then(|v| Mode::from_name(&v))
+            .unwrap_or_default()
+    }
+
+    /// `!hype [name]`: praise for `name`, or for `author` if no name was
+    /// given. A WoW character's level and class go into the prompt when
+    /// Battle.net can find it.
+    pub(crate) async fn hype_report(&self, name: Option<&str>, author: &str) -> String {
+        if self.llm.is_none() {
+            return "LLM not configured.".to_string();
+        }
+        let (name, about) = match name {
+            Some(name) if self.blizzard.is_some() => match self.fetch_wow_character(name).await {
+                Ok(c) => (
+                    c.name,
+                    Some(format!("a level {} {} {}", c.level, c.race.name, c.character_class.name)),
+                ),
+                Err(_) => (name.to_string(), None),
+            },
+            Some(name) => (name.to_string(), None),
+            None => (author.to_string(), None),
+        };
+        let target = Target::Hype {
+            name: &name,
+            about: about.as_deref(),
+        };
+        self.insult(Style::Default, target)
+            .await
+            .unwrap_or_else(|| format!("{} is so great even the LLM is speechless.", name))
+    }
+
+    /// Whether `character` may be insulted: not protected by an officer, and
+    /// not added by someone who opted out.
+    pub(crate) async fn insultable(&self, character: &str) -> bool {
+        let conn = self.db.lock().await;
+        if db::is_character_protected(&conn, character).unwrap_or(false) {
+            return false;
+        }
+        let owner = db::get_tracked_character(&conn, character).ok().flatten().map(|c| c.added_by);
+        !owner.is_some_and(|owner| db::is_insult_opted_out(&conn, &owner).unwrap_or(false))
+    }
+
+    /// An insult for `target` from the LLM in the bot's persona, or `None` if
+    /// there's no LLM or it failed.
+    pub(crate) async fn insult(&self, style: Style, target: Target<'_>) -> Option<String> {
+        self.llm.as_ref()?;
+        let system_prompt = {
+            let conn = self.db.lock().await;
+            db::get_config(&conn, None, "system_prompt").ok().flatten().unwrap_or_default()
+        };
+        self.query_llm_oneshot(system_prompt, prompt(style, &target))
+            .await
+            .ok()
+            .map(|insult| insult.trim().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::mock::{self, MockLlm};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(Style::from_name("Drill-Sergeant"), Some(Style::DrillSergeant));
+        assert_eq!(Style::from_name("rude"), None);
+    }
+
+    #[test]
+    fn test_prompt() {
+        let target = Target::LevelCheck {
+            name: "Pyuul",
+            level: 42,
+            description: "Night Elf Druid",
+            material: None,
+        };
+        assert_eq!(
+            prompt(Style::Default, &target),
+            "Give a 1-5 word insult for a level 42 Night Elf Druid named Pyuul. Reply with ONLY the insult, nothing else."
+        );
+        let shakespearean = prompt(Style::Shakespearean, &Target::Milestone { name: "Pyuul", level: 60 });
+        assert!(shakespearean.starts_with("Pyuul just reached level 60 in WoW."));
+        assert!(shakespearean.contains("Elizabethan"));
+        assert!(shakespearean.ends_with("Reply with ONLY the sentence."));
+
+        let hype = prompt(
+            Style::DrillSergeant,
+            &Target::Hype {
+                name: "Pyuul",
+                about: Some("a level 42 Night Elf Druid"),
+            },
+        );
+        assert_eq!(
+            hype,
+            "Hype up Pyuul (a level 42 Night Elf Druid) with over-the-top praise in one or two sentences. Reply with ONLY the praise."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hype() {
+        let llm = Arc::new(MockLlm::replying(&["Legendary!"]));
+        let handler = mock::handler(Some(llm.clone()), None);
+        assert_eq!(handler.report_mode(Some(GuildId::new(7))).await, Mode::Roast);
+        {
+            let conn = handler.db.lock().await;
+            db::set_guild_config(&conn, "7", "report_mode", "hype").unwrap();
+        }
+        assert_eq!(handler.report_mode(Some(GuildId::new(7))).await, Mode::Hype);
+
+        assert_eq!(handler.hype_report(None, "jowi").await, "Legendary!");
+        assert!(llm.requests.lock().unwrap()[0][1].content.starts_with("Hype up jowi with"));
+        assert_eq!(mock::handler(None, None).hype_report(Some("Pyuul"), "jowi").await, "LLM not configured.");
+    }
+
+    #[tokio::test]
+    async fn test_guild_style() {
+        let llm = Arc::new(MockLlm::replying(&[" Thou lumpish laggard \n"]));
+        let handler = mock::handler(Some(llm.clone()), None);
+        let guild = GuildId::new(7);
+        assert_eq!(handler.insult_style(Some(guild)).await, Style::Default);
+        {
+            let conn = handler.db.lock().await;
+            db::set_guild_config(&conn, "7", "insult_style", "shakespearean").unwrap();
+        }
+        assert_eq!(handler.insult_style(Some(guild)).await, Style::Shakespearean);
+        assert_eq!(handler.insult_style(None).await, Style::Default);
+
+        let insult = handler.insult(Style::Shakespearean, Target::Slackers { list: "Pyuul (9 days)" }).await;
+        assert_eq!(insult.as_deref(), Some("Thou lumpish laggard"));
+        let requests = llm.requests.lock().unwrap();
+        assert!(requests[0][1].content.contains("Pyuul (9 days)"));
+        assert!(requests[0][1].content.contains("Elizabethan"));
+    }
+
+    #[tokio::test]
+    async fn test_insultable() {
+        let handler = mock::handler(None, None);
+        {
+            let conn = handler.db.lock().await;
+            db::add_tracked_character(&conn, "Pyuul", "user1").unwrap();
+            db::add_tracked_character(&conn, "Zara", "user2").unwrap();
+            db::set_insult_opt_out(&conn, "user1", true).unwrap();
+            db::set_character_protected(&conn, "Zara", Some("officer")).unwrap();
+        }
+        assert!(!handler.insultable("Pyuul").await);
+        assert!(!handler.insultable("Zara").await);
+        assert!(handler.insultable("Untracked").await);
+    }
+}
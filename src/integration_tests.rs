@@ -0,0 +1,346 @@
+//! End-to-end tests of the handler against a simulated Blizzard API and LLM
+//! (served by wiremock) and an in-memory database.
+
+use serde_json::{json, Value};
+use std::sync::Arc;
+use wiremock::matchers::{header, method, path, path_regex, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use crate::chat_template::ChatTemplate;
+use crate::clients::{mock, BattleNet, BlizzardClient, ChatMessage, LlamaCpp, LlmClient, RiotApi, RiotClient, RiotRoute, SteamClient, SteamWebApi};
+use crate::{db, trace, Handler, HISTORY_LIMIT};
+
+const CHARACTER_PATH: &str = "/profile/wow/character/nightslayer";
+
+fn oauth_token(expires_in: u64) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "access_token": "test-token",
+        "token_type": "bearer",
+        "expires_in": expires_in,
+    }))
+}
+
+fn character(name: &str, level: u32, race: &str, class: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "name": name,
+        "level": level,
+        "race": { "id": 1, "name": race },
+        "character_class": { "id": 11, "name": class },
+        "experience": 1234,
+    }))
+}
+
+/// A Battle.net client talking to `server`, issuing tokens that last `expires_in` seconds.
+async fn battlenet(server: &MockServer, expires_in: u64) -> BattleNet {
+    Mock::given(method("POST"))
+        .and(path("/oauth/token"))
+        .respond_with(oauth_token(expires_in))
+        .mount(server)
+        .await;
+    BattleNet::new(reqwest::Client::new(), crate::wow::Region::Us, "id".into(), "secret".into())
+        .with_base_urls(&server.uri(), &format!("{}/oauth/token", server.uri()))
+}
+
+async fn oauth_requests(server: &MockServer) -> usize {
+    server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| r.url.path() == "/oauth/token")
+        .count()
+}
+
+async fn track(handler: &Handler, names: &[&str]) {
+    let conn = handler.db.lock().await;
+    for name in names {
+        db::add_tracked_character(&conn, name, "user1").unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_token_is_cached_until_it_expires() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/pyuul", CHARACTER_PATH)))
+        .and(header("authorization", "Bearer test-token"))
+        .respond_with(character("Pyuul", 42, "Night Elf", "Druid"))
+        .mount(&server)
+        .await;
+
+    let client = battlenet(&server, 3600).await;
+    for _ in 0..3 {
+        let path = format!("{}/pyuul?namespace=profile-classic1x-us", CHARACTER_PATH);
+        assert!(client.get_json(&path).await.unwrap().is_some());
+    }
+    assert_eq!(oauth_requests(&server).await, 1);
+}
+
+#[tokio::test]
+async fn test_expired_token_is_refreshed() {
+    let server = MockServer::start().await;
+    // Tokens are treated as expiring a minute early, so this one is stale at once
+    let client = battlenet(&server, 60).await;
+    client.token().await.unwrap();
+    client.token().await.unwrap();
+    assert_eq!(oauth_requests(&server).await, 2);
+}
+
+#[tokio::test]
+async fn test_oauth_failure_is_reported() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/oauth/token"))
+        .respond_with(ResponseTemplate::new(401))
+        .mount(&server)
+        .await;
+    let client = BattleNet::new(reqwest::Client::new(), crate::wow::Region::Us, "id".into(), "bad".into())
+        .with_base_urls(&server.uri(), &format!("{}/oauth/token", server.uri()));
+    assert_eq!(client.token().await.unwrap_err(), "OAuth returned status 401 Unauthorized");
+}
+
+#[tokio::test]
+async fn test_missing_character() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path_regex(format!("^{}/.*", CHARACTER_PATH)))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+    let handler = mock::handler(None, Some(Arc::new(battlenet(&server, 3600).await)));
+
+    let err = handler.fetch_wow_character("Ghost").await.err().unwrap();
+    assert_eq!(err, "Character **Ghost** not found on Nightslayer (Anniversary).");
+}
+
+#[tokio::test]
+async fn test_steam_web_api() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/ISteamUser/ResolveVanityURL/v0001/"))
+        .and(query_param("key", "steam-key"))
+        .and(query_param("vanityurl", "gaben"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "response": { "success": 1, "steamid": "76561197960287930" }
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/ISteamUser/ResolveVanityURL/v0001/"))
+        .and(query_param("vanityurl", "nobody"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "response": { "success": 42, "message": "No match" }
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/ISteamUser/GetPlayerSummaries/v0002/"))
+        .and(query_param("steamids", "76561197960287930"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "response": { "players": [{
+                "steamid": "76561197960287930",
+                "personaname": "Rabscuttle",
+                "gameid": "570",
+                "gameextrainfo": "Dota 2",
+            }]}
+        })))
+        .mount(&server)
+        .await;
+
+    let steam = SteamWebApi::new(reqwest::Client::new(), "steam-key".into()).with_base_url(&server.uri());
+    let id = steam.resolve_vanity_url("gaben").await.unwrap().unwrap();
+    assert_eq!(steam.resolve_vanity_url("nobody").await.unwrap(), None);
+    let players = steam.player_summaries(&[id]).await.unwrap();
+    assert_eq!(players[0].personaname, "Rabscuttle");
+    assert_eq!(players[0].gameextrainfo.as_deref(), Some("Dota 2"));
+}
+
+#[tokio::test]
+async fn test_riot_rank_end_to_end() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/riot/account/v1/accounts/by-riot-id/Hide%20on%20bush/KR1"))
+        .and(header("x-riot-token", "riot-key"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "puuid": "p1", "gameName": "Hide on bush", "tagLine": "KR1"
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/lol/league/v4/entries/by-puuid/p1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([{
+            "queueType": "RANKED_SOLO_5x5", "tier": "CHALLENGER", "rank": "I",
+            "leaguePoints": 1402, "wins": 300, "losses": 210,
+        }])))
+        .mount(&server)
+        .await;
+
+    let riot = RiotApi::new(reqwest::Client::new(), crate::riot::Platform::Kr, "riot-key".into())
+        .with_base_url(&server.uri());
+    assert_eq!(riot.get_json(RiotRoute::Regional, "/riot/account/v1/accounts/by-riot-id/x/y").await.unwrap(), None);
+    let mut handler = mock::handler(None, None);
+    handler.riot = Some(Arc::new(riot));
+
+    let report = handler.riot_rank_report("Hide on bush#KR1").await;
+    assert_eq!(report.lines().nth(1), Some("  Solo/Duo: Challenger 1402 LP (300W/210L)"));
+    assert_eq!(handler.riot_rank_report("Nobody#KR1").await, "No Riot account **Nobody#KR1**.");
+}
+
+#[tokio::test]
+async fn test_level_check_end_to_end() {
+    let server = MockServer::start().await;
+    for (name, level, race, class) in [("pyuul", 42, "Night Elf", "Druid"), ("zara", 58, "Orc", "Warrior")] {
+        let display = format!("{}{}", name[..1].to_uppercase(), &name[1..]);
+        Mock::given(method("GET"))
+            .and(path(format!("{}/{}", CHARACTER_PATH, name)))
+            .respond_with(character(&display, level, race, class))
+            .mount(&server)
+            .await;
+    }
+    Mock::given(method("GET"))
+        .and(path(format!("{}/ghost", CHARACTER_PATH)))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/broken", CHARACTER_PATH)))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&server)
+        .await;
+
+    let handler = mock::handler(None, Some(Arc::new(battlenet(&server, 3600).await)));
+    track(&handler, &["Pyuul", "Zara", "Ghost", "Broken"]).await;
+
+    let report = handler.level_check(None, None, false).await;
+    let lines: Vec<_> = report.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "**Level Check — Nightslayer**",
+            "  Zara — Level 58 Orc Warrior",
+            "  Pyuul — Level 42 Night Elf Druid",
+            "  ⚠ Broken: Blizzard API returned status 503 Service Unavailable",
+            "  ⚠ Ghost: Character **Ghost** not found on Nightslayer (Anniversary).",
+        ]
+    );
+    // Every character shared one token
+    assert_eq!(oauth_requests(&server).await, 1);
+}
+
+#[tokio::test]
+async fn test_chat_history_is_windowed() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "choices": [{ "message": { "role": "assistant", "content": "ok" } }],
+        })))
+        .mount(&server)
+        .await;
+    let llm = LlamaCpp::new(reqwest::Client::new(), server.uri());
+    let handler = mock::handler(Some(Arc::new(llm)), None);
+
+    let turns = HISTORY_LIMIT;
+    for i in 0..turns {
+        handler.ask_llama(&mock::conversation("chan"), &format!("message {}", i)).await.unwrap();
+    }
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), turns);
+    let last: Value = serde_json::from_slice(&requests[turns - 1].body).unwrap();
+    let messages = last["messages"].as_array().unwrap();
+    // The system prompt plus the newest HISTORY_LIMIT stored messages, oldest first
+    assert_eq!(messages.len(), HISTORY_LIMIT + 1);
+    assert_eq!(messages[0]["role"], "system");
+    let mut stored = Vec::new();
+    for i in 0..turns {
+        stored.push(format!("message {}", i));
+        stored.push("ok".to_string());
+    }
+    // The last reply hadn't arrived when the last request was sent
+    stored.pop();
+    let sent: Vec<_> = messages[1..]
+        .iter()
+        // Drop the word cap reminder on the newest message
+        .map(|m| m["content"].as_str().unwrap().lines().next().unwrap().to_string())
+        .collect();
+    assert_eq!(sent, stored[stored.len() - HISTORY_LIMIT..]);
+
+    // Other contexts don't see this conversation
+    handler.ask_llama(&mock::conversation("other"), "hi").await.unwrap();
+    let requests = server.received_requests().await.unwrap();
+    let other: Value = serde_json::from_slice(&requests.last().unwrap().body).unwrap();
+    assert_eq!(other["messages"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_chat_template_stop_tokens() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/props"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chat_template": "{{ '<|start_header_id|>' + message['role'] + '<|end_header_id|>' }}",
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "choices": [{ "message": { "role": "assistant", "content": "ok" } }],
+        })))
+        .mount(&server)
+        .await;
+
+    let llm = LlamaCpp::new(reqwest::Client::new(), server.uri());
+    let template = llm.detect_template().await.unwrap().unwrap();
+    assert_eq!(template, ChatTemplate::Llama3);
+    let llm = llm.with_template(template, false);
+    llm.complete(vec![ChatMessage::new("user", "hi")]).await.unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    let body: Value = serde_json::from_slice(&requests.last().unwrap().body).unwrap();
+    assert_eq!(body["stop"], json!(["<|eot_id|>", "<|start_header_id|>"]));
+}
+
+#[tokio::test]
+async fn test_forced_chat_template_formats_locally() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/completion"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "content": " go away " })))
+        .mount(&server)
+        .await;
+
+    let llm = LlamaCpp::new(reqwest::Client::new(), server.uri()).with_template(ChatTemplate::Mistral, true);
+    let reply = llm.complete(vec![ChatMessage::new("user", "hi")]).await.unwrap();
+    assert_eq!(reply, "go away");
+
+    let requests = server.received_requests().await.unwrap();
+    let body: Value = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(body["prompt"], "<s>[INST] hi [/INST]");
+    assert_eq!(body["stop"], json!(["</s>", "[INST]"]));
+}
+
+#[tokio::test]
+async fn test_llm_requests_carry_correlation_id() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "choices": [{ "message": { "role": "assistant", "content": "ok" } }],
+        })))
+        .mount(&server)
+        .await;
+    let llm = LlamaCpp::new(reqwest::Client::new(), server.uri());
+
+    let id = trace::traced("test", async {
+        llm.complete(vec![ChatMessage::new("user", "hi")]).await.unwrap();
+        trace::current().unwrap()
+    })
+    .await;
+    llm.complete(vec![ChatMessage::new("user", "hi")]).await.unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests[0].headers.get(trace::HEADER).unwrap().to_str().unwrap(), id);
+    assert!(requests[1].headers.get(trace::HEADER).is_none());
+}
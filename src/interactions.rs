@@ -0,0 +1,519 @@
+use serenity::builder::{
+    CreateActionRow, CreateAutocompleteResponse, CreateCommand, CreateCommandOption, CreateInputText,
+    CreateInteractionResponse, CreateInteractionResponseFollowup, CreateInteractionResponseMessage,
+    CreateModal, EditInteractionResponse,
+};
+use serenity::model::application::{
+    ActionRowComponent, CommandDataOption, CommandDataOptionValue, CommandInteraction,
+    CommandOptionType, CommandType,
+    ComponentInteraction, ComponentInteractionDataKind, InputTextStyle, Interaction,
+    ModalInteraction, ResolvedTarget,
+};
+use serenity::model::id::MessageId;
+use serenity::prelude::*;
+use tracing::{error, info, warn};
+
+use crate::events::BotEvent;
+use crate::{checklist, db, error_tracking, help, markdown, mentions, onboarding, purge, trace, wow, Handler, SELECT_MENU_MAX_OPTIONS};
+
+pub const REMOVE_CHARACTER_SELECT_ID: &str = "removecharacter";
+const ASK_THE_BOT_COMMAND: &str = "Ask the bot";
+const SYSTEM_PROMPT_MODAL_ID: &str = "systemprompt_edit";
+const SYSTEM_PROMPT_INPUT_ID: &str = "system_prompt";
+
+// Discord rejects text inputs longer than this
+const TEXT_INPUT_MAX_LENGTH: usize = 4000;
+
+/// Slash and context-menu commands registered with Discord on `ready`.
+pub fn application_commands() -> Vec<CreateCommand> {
+    vec![
+        CreateCommand::new("systemprompt")
+            .description("Manage the system prompt")
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "edit",
+                "Edit the system prompt in a form",
+            ))
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "show",
+                    "Show the current system prompt",
+                )
+                .add_sub_option(public_option()),
+            ),
+        CreateCommand::new("cap")
+            .description("View or set the response word cap")
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::Integer, "words", "New word cap")
+                    .min_int_value(1)
+                    .max_int_value(db::MAX_RESPONSE_CAP as u64),
+            )
+            .add_option(public_option()),
+        CreateCommand::new("levelcheck")
+            .description("Check levels of tracked characters")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "character",
+                    "Only check this character",
+                )
+                .set_autocomplete(true),
+            )
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::Boolean,
+                "raw",
+                "Skip the insults",
+            )),
+        CreateCommand::new("removecharacter")
+            .description("Stop tracking a character")
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::String, "name", "Character name")
+                    .required(true)
+                    .set_autocomplete(true),
+            ),
+        CreateCommand::new("chat")
+            .description("Talk to the bot")
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::String, "message", "What to say")
+                    .required(true),
+            ),
+        CreateCommand::new(ASK_THE_BOT_COMMAND).kind(CommandType::Message),
+    ]
+}
+
+/// Admin/config responses are only shown to the invoker unless they ask otherwise.
+fn public_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::Boolean,
+        "public",
+        "Show the response to everyone in the channel",
+    )
+}
+
+/// Options of the invoked (sub)command, skipping over the subcommand wrapper.
+fn options(command: &CommandInteraction) -> &[CommandDataOption] {
+    match command.data.options.first().map(|o| &o.value) {
+        Some(CommandDataOptionValue::SubCommand(opts)) => opts,
+        _ => &command.data.options,
+    }
+}
+
+fn string_option<'a>(command: &'a CommandInteraction, name: &str) -> Option<&'a str> {
+    options(command).iter().find_map(|o| match &o.value {
+        CommandDataOptionValue::String(text) if o.name == name => Some(text.trim()),
+        _ => None,
+    })
+}
+
+fn is_ephemeral(command: &CommandInteraction) -> bool {
+    !options(command)
+        .iter()
+        .any(|o| o.name == "public" && matches!(o.value, CommandDataOptionValue::Boolean(true)))
+}
+
+async fn reply(ctx: &Context, command: &CommandInteraction, content: String, ephemeral: bool) {
+    let response = CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(content)
+            .ephemeral(ephemeral),
+    );
+    if let Err(why) = command.create_response(&ctx.http, response).await {
+        error!("Error responding to interaction: {:?}", why);
+    }
+}
+
+/// Acknowledges the interaction so slow work can run past Discord's 3s deadline.
+/// Returns false if the acknowledgement failed and no follow-up is possible.
+async fn defer(ctx: &Context, command: &CommandInteraction) -> bool {
+    if let Err(why) = command.defer(&ctx.http).await {
+        error!("Error deferring interaction: {:?}", why);
+        return false;
+    }
+    true
+}
+
+/// Replaces the "thinking..." placeholder left by [`defer`] with the final content.
+/// Fills in a deferred response; anything past one message's worth goes in
+/// follow-up messages. The content may come from the LLM, so mentions are
+/// escaped unless `allow_users` is set, and `@everyone` never pings. Returns the
+/// IDs of the messages that made it out.
+async fn edit_reply(ctx: &Context, command: &CommandInteraction, content: String, allow_users: bool) -> Vec<MessageId> {
+    let content = mentions::sanitize(&content, allow_users);
+    let mut parts = markdown::split(&content, markdown::DISCORD_MESSAGE_MAX).into_iter();
+    let first = parts.next().unwrap_or(content);
+
+    let edit = EditInteractionResponse::new()
+        .content(first)
+        .allowed_mentions(mentions::allowed(allow_users));
+    let mut sent = match command.edit_response(&ctx.http, edit).await {
+        Ok(message) => vec![message.id],
+        Err(why) => {
+            error!("Error editing interaction response: {:?}", why);
+            return Vec::new();
+        }
+    };
+    for part in parts {
+        let followup = CreateInteractionResponseFollowup::new()
+            .content(part)
+            .allowed_mentions(mentions::allowed(allow_users));
+        match command.create_followup(&ctx.http, followup).await {
+            Ok(message) => sent.push(message.id),
+            Err(why) => {
+                error!("Error sending follow-up message: {:?}", why);
+                break;
+            }
+        }
+    }
+    sent
+}
+
+impl Handler {
+    pub(crate) async fn handle_interaction(&self, ctx: Context, interaction: Interaction) {
+        match interaction {
+            Interaction::Command(command) => self.handle_command(&ctx, &command).await,
+            Interaction::Autocomplete(command) => self.handle_autocomplete(&ctx, &command).await,
+            Interaction::Component(component) => self.handle_component(&ctx, &component).await,
+            Interaction::Modal(modal) => self.handle_modal(&ctx, &modal).await,
+            _ => {}
+        }
+    }
+
+    async fn handle_command(&self, ctx: &Context, command: &CommandInteraction) {
+        if let Some(feature) = self.disabled_feature(command.guild_id, &command.data.name).await {
+            let response = format!("The **{}** feature is disabled in this server.", feature.name());
+            reply(ctx, command, response, true).await;
+            return;
+        }
+
+        error_tracking::set_context(&command.data.name, command.guild_id);
+        self.emit(BotEvent::CommandInvoked {
+            command: command.data.name.clone(),
+            user_id: command.user.id.to_string(),
+            channel_id: command.channel_id.to_string(),
+            guild_id: command.guild_id.map(|g| g.to_string()),
+            slash: true,
+        });
+
+        let subcommand = command.data.options.first().map(|o| o.name.as_str());
+
+        match (command.data.name.as_str(), subcommand) {
+            ("systemprompt", Some("edit")) => self.open_system_prompt_modal(ctx, command).await,
+            ("systemprompt", Some("show")) => {
+                let current = {
+                    let conn = self.db.lock().await;
+                    self.guild_system_prompt(&conn, command.guild_id).unwrap_or_default()
+                };
+                let response = format!("**Current system prompt:**\n{}", current);
+                reply(ctx, command, response, is_ephemeral(command)).await;
+            }
+            ("cap", _) => self.set_cap_command(ctx, command).await,
+            ("levelcheck", _) => {
+                let raw = options(command)
+                    .iter()
+                    .any(|o| o.name == "raw" && matches!(o.value, CommandDataOptionValue::Boolean(true)));
+                let character = string_option(command, "character");
+                if !defer(ctx, command).await {
+                    return;
+                }
+                let embed = self.level_check_embed(command.guild_id, character, !raw).await;
+                let edit = EditInteractionResponse::new().embed(embed);
+                if let Err(why) = command.edit_response(&ctx.http, edit).await {
+                    error!("Error editing interaction response: {:?}", why);
+                }
+            }
+            ("removecharacter", _) => {
+                let Some(name) = string_option(command, "name") else {
+                    return;
+                };
+                let response = self.remove_character(name, &command.user.name).await;
+                reply(ctx, command, response, false).await;
+            }
+            ("chat", _) => self.chat_command(ctx, command).await,
+            (ASK_THE_BOT_COMMAND, _) => self.ask_about_message(ctx, command).await,
+            _ => warn!("Unhandled application command: {}", command.data.name),
+        }
+    }
+
+    /// Suggests tracked character names for any autocompleted character argument.
+    async fn handle_autocomplete(&self, ctx: &Context, command: &CommandInteraction) {
+        let Some(focused) = command.data.autocomplete() else {
+            return;
+        };
+
+        let is_character_arg = matches!(
+            (command.data.name.as_str(), focused.name),
+            ("levelcheck", "character") | ("removecharacter", "name")
+        );
+        if !is_character_arg {
+            return;
+        }
+
+        let names = {
+            let conn = self.db.lock().await;
+            db::search_tracked_characters(&conn, focused.value, SELECT_MENU_MAX_OPTIONS)
+                .unwrap_or_default()
+        };
+
+        let mut choices = CreateAutocompleteResponse::new();
+        for name in names {
+            choices = choices.add_string_choice(name.clone(), name);
+        }
+        if let Err(why) = command
+            .create_response(&ctx.http, CreateInteractionResponse::Autocomplete(choices))
+            .await
+        {
+            error!("Error responding to autocomplete: {:?}", why);
+        }
+    }
+
+    async fn open_system_prompt_modal(&self, ctx: &Context, command: &CommandInteraction) {
+        let current = {
+            let conn = self.db.lock().await;
+            self.guild_system_prompt(&conn, command.guild_id).unwrap_or_default()
+        };
+
+        let mut input = CreateInputText::new(
+            InputTextStyle::Paragraph,
+            "System prompt",
+            SYSTEM_PROMPT_INPUT_ID,
+        )
+        .max_length(TEXT_INPUT_MAX_LENGTH as u16);
+        // Pre-filling with an over-long value would make Discord reject the modal
+        if !current.is_empty() && current.chars().count() <= TEXT_INPUT_MAX_LENGTH {
+            input = input.value(current);
+        }
+
+        let modal = CreateModal::new(SYSTEM_PROMPT_MODAL_ID, "Edit system prompt")
+            .components(vec![CreateActionRow::InputText(input)]);
+        if let Err(why) = command
+            .create_response(&ctx.http, CreateInteractionResponse::Modal(modal))
+            .await
+        {
+            error!("Error responding to interaction: {:?}", why);
+        }
+    }
+
+    async fn set_cap_command(&self, ctx: &Context, command: &CommandInteraction) {
+        let words = options(command).iter().find_map(|o| match o.value {
+            CommandDataOptionValue::Integer(n) if o.name == "words" => Some(n),
+            _ => None,
+        });
+
+        let guild = command.guild_id.map(|g| g.to_string());
+        let conn = self.db.lock().await;
+        let response = match words {
+            None => format!(
+                "Response word cap is currently **{}**.",
+                db::get_response_cap(&conn, guild.as_deref())
+            ),
+            Some(n) => match db::set_config(&conn, guild.as_deref(), "response_cap", &n.to_string()) {
+                Ok(_) => {
+                    info!("{} set response cap to {}", command.user.name, n);
+                    format!("Response word cap set to **{}**.", n)
+                }
+                Err(e) => {
+                    error!("Failed to set response cap: {}", e);
+                    "Failed to save cap.".to_string()
+                }
+            },
+        };
+        drop(conn);
+
+        reply(ctx, command, response, is_ephemeral(command)).await;
+    }
+
+    /// Feeds the targeted message to the LLM in the invoker's current context.
+    async fn ask_about_message(&self, ctx: &Context, command: &CommandInteraction) {
+        let Some(ResolvedTarget::Message(target)) = command.data.target() else {
+            return;
+        };
+
+        if target.content.trim().is_empty() {
+            reply(ctx, command, "That message has no text for me to read.".to_string(), true).await;
+            return;
+        }
+
+        info!(
+            "{} asked about message from {}: {}",
+            command.user.name, target.author.name, target.content
+        );
+
+        if !defer(ctx, command).await {
+            return;
+        }
+
+        let mut conversation = {
+            let conn = self.db.lock().await;
+            let nick = command.member.as_ref().and_then(|m| m.nick.as_deref());
+            self.conversation(&conn, command.guild_id, command.channel_id, &command.user, nick)
+        };
+        self.add_preamble(&ctx.http, &mut conversation).await;
+        self.add_channel_topic(&ctx.http, &mut conversation).await;
+        let question = format!("{} said: \"{}\"", target.author.name, target.content);
+        let result = self.ask_llama(&conversation, &question).await;
+        let response = match &result {
+            Ok(reply) => reply.clone(),
+            Err(e) => {
+                error!("LLM error: {}", e);
+                format!("Sorry, I couldn't get a response: {}{}", e, trace::error_ref())
+            }
+        };
+
+        let allow_users = self.allows_user_mentions(command.guild_id).await;
+        let sent = edit_reply(ctx, command, response, allow_users).await;
+        if let Ok(reply) = result {
+            self.record_exchange(&conversation, &question, &reply, &sent).await;
+        }
+    }
+
+    async fn chat_command(&self, ctx: &Context, command: &CommandInteraction) {
+        let Some(message) = string_option(command, "message") else {
+            return;
+        };
+
+        info!("Received /chat from {}: {}", command.user.name, message);
+
+        if !defer(ctx, command).await {
+            return;
+        }
+
+        let mut conversation = {
+            let conn = self.db.lock().await;
+            let nick = command.member.as_ref().and_then(|m| m.nick.as_deref());
+            self.conversation(&conn, command.guild_id, command.channel_id, &command.user, nick)
+        };
+        self.add_preamble(&ctx.http, &mut conversation).await;
+        self.add_channel_topic(&ctx.http, &mut conversation).await;
+        let result = self.ask_llama(&conversation, message).await;
+        let response = match &result {
+            Ok(reply) => format!("> {}\n{}", message, reply),
+            Err(e) => {
+                error!("LLM error: {}", e);
+                format!("Sorry, I couldn't get a response: {}{}", e, trace::error_ref())
+            }
+        };
+
+        let allow_users = self.allows_user_mentions(command.guild_id).await;
+        let sent = edit_reply(ctx, command, response, allow_users).await;
+        if let Ok(reply) = result {
+            self.record_exchange(&conversation, message, &reply, &sent).await;
+        }
+    }
+
+    async fn handle_component(&self, ctx: &Context, component: &ComponentInteraction) {
+        if let Some(category) = help::Category::from_custom_id(&component.data.custom_id) {
+            let cap = {
+                let conn = self.db.lock().await;
+                db::get_response_cap(&conn, component.guild_id.map(|g| g.to_string()).as_deref())
+            };
+            let response = CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .embed(help::embed(category, &self.modules, cap))
+                    .components(help::buttons(category)),
+            );
+            if let Err(why) = component.create_response(&ctx.http, response).await {
+                error!("Error responding to interaction: {:?}", why);
+            }
+            return;
+        }
+
+        if let Some((step, guild_id)) = onboarding::Step::from_custom_id(&component.data.custom_id) {
+            self.handle_onboarding(ctx, component, step, guild_id).await;
+            return;
+        }
+
+        if let Some((name, class, index)) = checklist::from_custom_id(&component.data.custom_id) {
+            self.handle_checklist_button(ctx, component, &name, &class, index).await;
+            return;
+        }
+
+        if let Some((confirm, user_id)) = purge::from_custom_id(&component.data.custom_id) {
+            self.handle_purge_button(ctx, component, confirm, user_id).await;
+            return;
+        }
+
+        if let Some(page) = component.data.custom_id.strip_prefix(wow::LIST_PAGE_PREFIX) {
+            let page = page.parse::<usize>().unwrap_or(0);
+            let characters = {
+                let conn = self.db.lock().await;
+                db::get_tracked_character_details(&conn).unwrap_or_default()
+            };
+            let (embed, components) = wow::list_page(&characters, page);
+            let response = CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .embed(embed)
+                    .components(components),
+            );
+            if let Err(why) = component.create_response(&ctx.http, response).await {
+                error!("Error responding to interaction: {:?}", why);
+            }
+            return;
+        }
+
+        if component.data.custom_id == REMOVE_CHARACTER_SELECT_ID {
+            let ComponentInteractionDataKind::StringSelect { values } = &component.data.kind else {
+                return;
+            };
+            let Some(name) = values.first() else {
+                return;
+            };
+
+            let content = self.remove_character(name, &component.user.name).await;
+            let response = CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .components(vec![]),
+            );
+            if let Err(why) = component.create_response(&ctx.http, response).await {
+                error!("Error responding to interaction: {:?}", why);
+            }
+        }
+    }
+
+    async fn handle_modal(&self, ctx: &Context, modal: &ModalInteraction) {
+        if modal.data.custom_id != SYSTEM_PROMPT_MODAL_ID {
+            return;
+        }
+
+        let new_prompt = modal
+            .data
+            .components
+            .iter()
+            .flat_map(|row| row.components.iter())
+            .find_map(|c| match c {
+                ActionRowComponent::InputText(input) if input.custom_id == SYSTEM_PROMPT_INPUT_ID => {
+                    input.value.clone()
+                }
+                _ => None,
+            })
+            .unwrap_or_default();
+        let new_prompt = new_prompt.trim();
+
+        let content = if new_prompt.is_empty() {
+            "System prompt can't be empty.".to_string()
+        } else {
+            let conn = self.db.lock().await;
+            let guild = modal.guild_id.map(|g| g.to_string());
+            match db::set_system_prompt(&conn, guild.as_deref(), new_prompt) {
+                Ok(_) => {
+                    info!("{} updated system prompt to: {}", modal.user.name, new_prompt);
+                    "System prompt updated!".to_string()
+                }
+                Err(e) => {
+                    error!("Failed to update system prompt: {}", e);
+                    "Failed to update system prompt.".to_string()
+                }
+            }
+        };
+
+        let response = CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content(content)
+                .ephemeral(true),
+        );
+        if let Err(why) = modal.create_response(&ctx.http, response).await {
+            error!("Error responding to interaction: {:?}", why);
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use serenity::http::Http;
+use serenity::model::id::{GuildId, UserId};
+use tracing::{error, info, warn};
+
+use crate::db::{self, InviteUse, JoinSource};
+use crate::Handler;
+
+/// Invites `!invites` lists.
+const SOURCES_SHOWN: usize = 10;
+
+/// The invite a new member used, from the use counts before and after they
+/// joined: the one whose count went up, or one that vanished because it hit
+/// its max uses. `None` if it's ambiguous (two joins at once) or there was
+/// no invite, like a vanity URL or server discovery.
+pub fn used_invite(before: &[InviteUse], after: &[InviteUse]) -> Option<InviteUse> {
+    let uses_before = |code: &str| before.iter().find(|i| i.code == code).map_or(0, |i| i.uses);
+    let increased: Vec<&InviteUse> = after.iter().filter(|i| i.uses > uses_before(&i.code)).collect();
+    if let [invite] = increased[..] {
+        return Some(invite.clone());
+    }
+    if !increased.is_empty() {
+        return None;
+    }
+    let vanished: Vec<&InviteUse> = before.iter().filter(|b| !after.iter().any(|a| a.code == b.code)).collect();
+    match vanished[..] {
+        [invite] => Some(InviteUse {
+            uses: invite.uses + 1,
+            ..invite.clone()
+        }),
+        _ => None,
+    }
+}
+
+/// One line of `!invites`.
+pub fn source_line(source: &JoinSource) -> String {
+    let joins = if source.joins == 1 { "1 join".to_string() } else { format!("{} joins", source.joins) };
+    match (&source.code, &source.inviter_id) {
+        (Some(code), Some(inviter)) => format!("  `{}` by <@{}> — {}", code, inviter, joins),
+        (Some(code), None) => format!("  `{}` — {}", code, joins),
+        (None, _) => format!("  Unknown (vanity URL, discovery or simultaneous joins) — {}", joins),
+    }
+}
+
+impl Handler {
+    /// The guild's invites from Discord, or `None` without Manage Server.
+    async fn fetch_invites(&self, http: &Http, guild_id: GuildId) -> Option<Vec<InviteUse>> {
+        match guild_id.invites(http).await {
+            Ok(invites) => Some(
+                invites
+                    .into_iter()
+                    .map(|i| InviteUse {
+                        code: i.code,
+                        inviter_id: i.inviter.map(|u| u.id.to_string()),
+                        uses: i.uses,
+                    })
+                    .collect(),
+            ),
+            Err(e) => {
+                warn!("Can't list invites in guild {} (needs Manage Server): {:?}", guild_id, e);
+                None
+            }
+        }
+    }
+
+    /// Caches `guild_id`'s invite use counts, for telling which one the next
+    /// member joins with.
+    pub(crate) async fn refresh_invites(&self, http: &Http, guild_id: GuildId) {
+        let Some(invites) = self.fetch_invites(http, guild_id).await else { return };
+        let conn = self.db.lock().await;
+        if let Err(e) = db::set_invite_uses(&conn, &guild_id.to_string(), &invites) {
+            error!("Failed to cache invites: {}", e);
+        }
+    }
+
+    /// Works out which invite `user_id` joined with and records it.
+    pub(crate) async fn record_member_join(&self, http: &Http, guild_id: GuildId, user_id: UserId) {
+        let Some(after) = self.fetch_invites(http, guild_id).await else { return };
+        let guild = guild_id.to_string();
+        let conn = self.db.lock().await;
+        let before = match db::get_invite_uses(&conn, &guild) {
+            Ok(before) => before,
+            Err(e) => {
+                error!("Failed to load cached invites: {}", e);
+                return;
+            }
+        };
+        let invite = used_invite(&before, &after);
+        match &invite {
+            Some(invite) => info!("{} joined guild {} with invite {}", user_id, guild_id, invite.code),
+            None => info!("{} joined guild {} with an unknown invite", user_id, guild_id),
+        }
+        if let Err(e) = db::record_invite_join(&conn, &guild, &user_id.to_string(), invite.as_ref()) {
+            error!("Failed to record invite join: {}", e);
+        }
+        if let Err(e) = db::set_invite_uses(&conn, &guild, &after) {
+            error!("Failed to cache invites: {}", e);
+        }
+    }
+
+    /// `!invites [@user]`: where members came from, or who `inviter` brought in.
+    pub(crate) async fn invites_report(&self, guild_id: GuildId, inviter: Option<UserId>) -> String {
+        let inviter = inviter.map(|u| u.to_string());
+        let sources = {
+            let conn = self.db.lock().await;
+            db::get_join_sources(&conn, &guild_id.to_string(), inviter.as_deref())
+        };
+        let sources = match sources {
+            Ok(sources) => sources,
+            Err(e) => {
+                error!("Failed to load join sources: {}", e);
+                return "Failed to load invite stats.".to_string();
+            }
+        };
+        let total: i64 = sources.iter().map(|s| s.joins).sum();
+        let lines: Vec<String> = sources.iter().take(SOURCES_SHOWN).map(source_line).collect();
+        match inviter {
+            Some(inviter) if total == 0 => format!("Nobody has joined through <@{}>'s invites yet.", inviter),
+            Some(inviter) => format!("<@{}> has brought in **{}**:\n{}", inviter, total, lines.join("\n")),
+            None if total == 0 => "No joins recorded yet. Invite tracking needs `TRACK_INVITES` and the Manage Server permission.".to_string(),
+            None => format!("**Where {} members came from**\n{}", total, lines.join("\n")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invite(code: &str, uses: u64) -> InviteUse {
+        InviteUse {
+            code: code.to_string(),
+            inviter_id: Some("u1".to_string()),
+            uses,
+        }
+    }
+
+    #[test]
+    fn test_used_invite() {
+        let before = [invite("abc", 3), invite("once", 0)];
+        assert_eq!(used_invite(&before, &[invite("abc", 4), invite("once", 0)]), Some(invite("abc", 4)));
+        // Made since the cache was filled
+        assert_eq!(used_invite(&before, &[invite("abc", 3), invite("once", 0), invite("new", 1)]), Some(invite("new", 1)));
+        // A single-use invite is deleted once used
+        assert_eq!(used_invite(&before, &[invite("abc", 3)]), Some(invite("once", 1)));
+        // Two joins at once, or nothing to go on
+        assert_eq!(used_invite(&before, &[invite("abc", 4), invite("once", 1)]), None);
+        assert_eq!(used_invite(&before, &before), None);
+        assert_eq!(used_invite(&before, &[]), None);
+    }
+
+    #[test]
+    fn test_source_line() {
+        let source = JoinSource {
+            code: Some("abc".to_string()),
+            inviter_id: Some("42".to_string()),
+            joins: 3,
+        };
+        assert_eq!(source_line(&source), "  `abc` by <@42> — 3 joins");
+        let unknown = JoinSource {
+            code: None,
+            inviter_id: None,
+            joins: 1,
+        };
+        assert!(source_line(&unknown).ends_with("— 1 join"));
+    }
+}
@@ -0,0 +1,166 @@
+use tracing::warn;
+
+use crate::bots::Conversation;
+use crate::{db, Handler};
+
+/// Largest document `!kb upload` accepts.
+pub const MAX_DOCUMENT_BYTES: u32 = 10 * 1024 * 1024;
+/// Target size of a chunk; paragraphs are packed up to this, longer ones split.
+const CHUNK_CHARS: usize = 1000;
+/// How many chunks go into a prompt at most.
+const TOP_CHUNKS: usize = 3;
+/// How similar (cosine) a chunk must be to the question to be worth including.
+const MIN_RELEVANCE: f32 = 0.5;
+
+/// The text of an uploaded document: PDFs are extracted, text and markdown
+/// are taken as they are.
+pub fn extract_text(filename: &str, bytes: &[u8]) -> Result<String, String> {
+    let extension = filename.rsplit_once('.').map(|(_, ext)| ext.to_lowercase()).unwrap_or_default();
+    match extension.as_str() {
+        "pdf" => pdf_extract::extract_text_from_mem(bytes).map_err(|e| format!("I couldn't read that PDF: {}", e)),
+        "txt" | "md" | "markdown" => String::from_utf8(bytes.to_vec()).map_err(|_| "That file isn't UTF-8 text.".to_string()),
+        _ => Err("I can read `.txt`, `.md` and `.pdf` files.".to_string()),
+    }
+}
+
+/// Splits `text` into chunks of about [`CHUNK_CHARS`], keeping paragraphs
+/// together where they fit.
+pub fn chunk(text: &str) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let paragraphs = text.split("\n\n").map(str::trim).filter(|p| !p.is_empty());
+    for paragraph in paragraphs {
+        if !current.is_empty() && current.chars().count() + paragraph.chars().count() > CHUNK_CHARS {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if paragraph.chars().count() > CHUNK_CHARS {
+            let chars: Vec<char> = paragraph.chars().collect();
+            chunks.extend(chars.chunks(CHUNK_CHARS).map(|c| c.iter().collect::<String>()));
+            continue;
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(paragraph);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+pub fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+/// The chunks most relevant to `embedding`, best first.
+fn top_chunks<'a>(embedding: &[f32], chunks: &'a [db::KnowledgeChunk]) -> Vec<&'a db::KnowledgeChunk> {
+    let mut scored: Vec<(f32, &db::KnowledgeChunk)> = chunks
+        .iter()
+        .map(|chunk| (cosine(embedding, &chunk.embedding), chunk))
+        .filter(|(score, _)| *score >= MIN_RELEVANCE)
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.into_iter().take(TOP_CHUNKS).map(|(_, chunk)| chunk).collect()
+}
+
+impl Handler {
+    /// Adds the parts of the guild's `!kb` documents relevant to `question` to
+    /// `conversation`. Nothing is added without documents or when embedding fails.
+    pub(crate) async fn add_knowledge(&self, conversation: &mut Conversation, question: &str) {
+        let Some(guild_id) = conversation.guild_id else {
+            return;
+        };
+        let chunks = {
+            let conn = self.db.lock().await;
+            db::get_knowledge_chunks(&conn, &guild_id.to_string()).unwrap_or_default()
+        };
+        if chunks.is_empty() {
+            return;
+        }
+        let Some(llm) = &self.llm else {
+            return;
+        };
+        let embedding = match llm.embed(question).await {
+            Ok(embedding) => embedding,
+            Err(e) => {
+                warn!("Failed to embed question for the knowledge base: {}", e);
+                return;
+            }
+        };
+        let relevant = top_chunks(&embedding, &chunks);
+        if relevant.is_empty() {
+            return;
+        }
+        let excerpts: Vec<String> = relevant
+            .iter()
+            .map(|chunk| format!("From \"{}\":\n{}", chunk.source, chunk.content))
+            .collect();
+        conversation.notes.push(format!(
+            "Excerpts from this server's documents that may help answer; say so if they don't cover it:\n\n{}",
+            excerpts.join("\n\n")
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_llm::hashed_embedding;
+
+    #[test]
+    fn test_chunk() {
+        assert!(chunk("\n\n  \n").is_empty());
+        assert_eq!(chunk("Rule one.\n\nRule two."), ["Rule one.\n\nRule two."]);
+
+        let long = "a".repeat(CHUNK_CHARS);
+        let chunks = chunk(&format!("intro\n\n{}\n\n{}b", long, long));
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks[0], "intro");
+        assert_eq!(chunks[3], "b");
+        assert!(chunks.iter().all(|c| c.chars().count() <= CHUNK_CHARS));
+    }
+
+    #[test]
+    fn test_extract_text() {
+        assert_eq!(extract_text("rules.MD", b"# Rules").unwrap(), "# Rules");
+        assert!(extract_text("rules.txt", &[0xff, 0xfe]).is_err());
+        assert!(extract_text("rules.docx", b"").is_err());
+        assert!(extract_text("broken.pdf", b"not a pdf").is_err());
+    }
+
+    #[test]
+    fn test_cosine() {
+        assert!((cosine(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert_eq!(cosine(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+        assert_eq!(cosine(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+        assert_eq!(cosine(&[1.0], &[1.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn test_top_chunks() {
+        let chunk = |content: &str| db::KnowledgeChunk {
+            source: "rules.md".to_string(),
+            content: content.to_string(),
+            embedding: hashed_embedding(content),
+        };
+        let chunks = [
+            chunk("Loot is distributed by council vote"),
+            chunk("Raid starts at eight on Thursday"),
+            chunk("Be nice in guild chat"),
+        ];
+        let found = top_chunks(&hashed_embedding("does raid start at eight on thursday"), &chunks);
+        assert_eq!(found[0].content, "Raid starts at eight on Thursday");
+        assert!(top_chunks(&hashed_embedding("zebra"), &chunks).is_empty());
+    }
+}
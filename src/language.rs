@@ -0,0 +1,44 @@
+use whatlang::Lang;
+
+/// Shorter messages don't say enough to tell languages apart.
+const MIN_DETECT_CHARS: usize = 12;
+
+/// The language `text` is written in, when it can be told reliably.
+pub fn detect(text: &str) -> Option<Lang> {
+    if text.chars().filter(|c| c.is_alphabetic()).count() < MIN_DETECT_CHARS {
+        return None;
+    }
+    whatlang::detect(text).filter(|info| info.is_reliable()).map(|info| info.lang())
+}
+
+/// The instruction to answer in the language of `text`. Personas and prompts
+/// are written in English, so English needs none.
+pub fn reply_note(text: &str) -> Option<String> {
+    match detect(text)? {
+        Lang::Eng => None,
+        lang => Some(format!(
+            "The message you are answering is in {}. Reply in {} too, whatever language these instructions are in.",
+            lang.eng_name(),
+            lang.eng_name()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect("Hola, ¿cómo estás? ¿Vamos a la banda esta noche?"), Some(Lang::Spa));
+        assert_eq!(detect("Wann fängt der Schlachtzug heute Abend an?"), Some(Lang::Deu));
+        assert_eq!(detect("lol ok"), None);
+    }
+
+    #[test]
+    fn test_reply_note() {
+        assert_eq!(reply_note("When does the raid start tonight, do you know?"), None);
+        let note = reply_note("Quand est-ce que le raid commence ce soir ? Je dois préparer mes potions.").unwrap();
+        assert!(note.contains("Reply in French"));
+    }
+}
@@ -0,0 +1,174 @@
+use std::env;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{Builder, Rotation};
+use tracing_subscriber::filter::EnvFilter;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::{reload, Layer, Registry};
+
+use crate::config;
+
+/// What's logged unless `LOG_FILTER` says otherwise.
+pub const DEFAULT_FILTER: &str = "info";
+/// Rotated log files kept unless `LOG_KEEP_FILES` says otherwise.
+const DEFAULT_KEEP_FILES: usize = 14;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    Text,
+    /// One JSON object per line, for log collectors.
+    Json,
+}
+
+/// Where logs go and what's logged, from the environment. Logging starts
+/// before [`config::Config`] is loaded, so these are read on their own.
+#[derive(Debug)]
+pub struct Settings {
+    pub format: Format,
+    /// Directory for rotating log files, written besides stdout.
+    pub dir: Option<PathBuf>,
+    pub rotation: Rotation,
+    pub keep_files: usize,
+    /// `tracing` filter directives, as in `info,discord_bot::wow=debug`.
+    pub filter: String,
+}
+
+impl Settings {
+    pub fn from_env() -> Result<Settings, String> {
+        Settings::from_lookup(|name| env::var(name).ok())
+    }
+
+    pub(crate) fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Settings, String> {
+        let var = |name: &str| config::var_from(&lookup, name);
+        let format = match var("LOG_FORMAT")?.map(|v| v.to_lowercase()).as_deref() {
+            None | Some("text") => Format::Text,
+            Some("json") => Format::Json,
+            Some(other) => return Err(format!("LOG_FORMAT must be text or json, not {}", other)),
+        };
+        let rotation = match var("LOG_ROTATION")?.map(|v| v.to_lowercase()).as_deref() {
+            None | Some("daily") => Rotation::DAILY,
+            Some("hourly") => Rotation::HOURLY,
+            Some("never") => Rotation::NEVER,
+            Some(other) => return Err(format!("LOG_ROTATION must be hourly, daily or never, not {}", other)),
+        };
+        let keep_files = match var("LOG_KEEP_FILES")? {
+            Some(n) => n
+                .parse()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| format!("LOG_KEEP_FILES must be a positive number, not {}", n))?,
+            None => DEFAULT_KEEP_FILES,
+        };
+        let filter = var("LOG_FILTER")?.unwrap_or_else(|| DEFAULT_FILTER.to_string());
+        EnvFilter::try_new(&filter).map_err(|e| format!("LOG_FILTER: {}", e))?;
+        Ok(Settings {
+            format,
+            dir: var("LOG_DIR")?.map(PathBuf::from),
+            rotation,
+            keep_files,
+            filter,
+        })
+    }
+}
+
+/// The filter on the stdout and file logs, for `!loglevel`.
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+/// The filter logging started with, for `!loglevel reset`.
+static STARTUP_FILTER: OnceLock<String> = OnceLock::new();
+
+type Sink = Box<dyn Layer<Registry> + Send + Sync>;
+
+fn sink<W>(format: Format, writer: W, ansi: bool) -> Sink
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer).with_ansi(ansi);
+    match format {
+        Format::Text => layer.boxed(),
+        Format::Json => layer.json().boxed(),
+    }
+}
+
+/// Stdout and, with `LOG_DIR`, rotating files, under a filter that can be
+/// changed while running. File writes happen on a background thread; the
+/// guard flushes it when dropped, so keep it until exit.
+pub fn sinks(settings: &Settings) -> Result<(impl Layer<Registry>, Option<WorkerGuard>), String> {
+    let (file, guard) = match &settings.dir {
+        Some(dir) => {
+            let appender = Builder::new()
+                .rotation(settings.rotation.clone())
+                .filename_prefix("discord-bot")
+                .filename_suffix("log")
+                .max_log_files(settings.keep_files)
+                .build(dir)
+                .map_err(|e| format!("Can't write logs to {}: {}", dir.display(), e))?;
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            (Some(sink(settings.format, writer, false)), Some(guard))
+        }
+        None => (None, None),
+    };
+    let filter = EnvFilter::try_new(&settings.filter).map_err(|e| format!("LOG_FILTER: {}", e))?;
+    let (filter, handle) = reload::Layer::new(filter);
+    // Only set once; a second call's filter just can't be changed
+    let _ = FILTER.set(handle);
+    let _ = STARTUP_FILTER.set(settings.filter.clone());
+    let sinks = sink(settings.format, std::io::stdout, true).and_then(file).with_filter(filter);
+    Ok((sinks, guard))
+}
+
+/// The filter directives in effect.
+pub fn current_filter() -> Option<String> {
+    FILTER.get()?.with_current(|filter| filter.to_string()).ok()
+}
+
+/// Replaces the filter on the stdout and file logs; `None` goes back to the
+/// one logging started with.
+pub fn set_filter(directives: Option<&str>) -> Result<String, String> {
+    let handle = FILTER.get().ok_or("Logging isn't set up")?;
+    let directives = directives.or(STARTUP_FILTER.get().map(String::as_str)).unwrap_or(DEFAULT_FILTER);
+    let filter = EnvFilter::try_new(directives).map_err(|e| e.to_string())?;
+    handle.reload(filter).map_err(|e| e.to_string())?;
+    Ok(directives.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(vars: &[(&str, &str)]) -> Result<Settings, String> {
+        Settings::from_lookup(|name| vars.iter().find(|(k, _)| *k == name).map(|(_, v)| v.to_string()))
+    }
+
+    #[test]
+    fn test_defaults() {
+        let settings = load(&[]).unwrap();
+        assert_eq!(settings.format, Format::Text);
+        assert_eq!(settings.dir, None);
+        assert_eq!(settings.rotation, Rotation::DAILY);
+        assert_eq!(settings.keep_files, DEFAULT_KEEP_FILES);
+        assert_eq!(settings.filter, DEFAULT_FILTER);
+    }
+
+    #[test]
+    fn test_settings() {
+        let settings = load(&[
+            ("LOG_FORMAT", "JSON"),
+            ("LOG_DIR", "/var/log/discord-bot"),
+            ("LOG_ROTATION", "hourly"),
+            ("LOG_KEEP_FILES", "48"),
+            ("LOG_FILTER", "warn,discord_bot::wow=debug"),
+        ])
+        .unwrap();
+        assert_eq!(settings.format, Format::Json);
+        assert_eq!(settings.dir, Some(PathBuf::from("/var/log/discord-bot")));
+        assert_eq!(settings.rotation, Rotation::HOURLY);
+        assert_eq!(settings.keep_files, 48);
+        assert_eq!(settings.filter, "warn,discord_bot::wow=debug");
+
+        assert!(load(&[("LOG_FORMAT", "xml")]).is_err());
+        assert!(load(&[("LOG_ROTATION", "weekly")]).is_err());
+        assert!(load(&[("LOG_KEEP_FILES", "0")]).is_err());
+        assert!(load(&[("LOG_FILTER", "discord_bot=loud")]).is_err());
+    }
+}
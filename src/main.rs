@@ -1,207 +1,185 @@
+mod alerts;
+mod archive;
+mod args;
+mod attunement;
+mod automod;
+mod blocklist;
+mod bots;
+mod chat_template;
+mod checklist;
+mod cli;
+mod clients;
+mod commands;
+mod config;
 mod db;
-
+mod edits;
+mod encryption;
+mod error_tracking;
+mod events;
+mod export;
+mod features;
+mod feedback;
+mod flags;
+mod help;
+mod insults;
+mod interactions;
+mod invites;
+#[cfg(test)]
+mod integration_tests;
+mod knowledge;
+mod language;
+mod logging;
+mod maintenance;
+mod markdown;
+mod mentions;
+mod middleware;
+mod mock_llm;
+mod modules;
+mod onboarding;
+mod persona;
+mod preamble;
+mod profile;
+mod purge;
+mod quiet_hours;
+mod reactions;
+mod readonly;
+mod render;
+mod retry;
+mod riot;
+mod scheduler;
+mod scripting;
+mod steam;
+mod systemd;
+mod todos;
+mod trace;
+mod validate;
+mod web;
+mod wow;
+
+use clap::Parser;
 use futures::future::join_all;
 use reqwest::Client as HttpClient;
 use rusqlite::Connection;
-use serde::{Deserialize, Serialize};
 use serenity::async_trait;
-use serenity::model::channel::Message;
+use serenity::builder::CreateEmbed;
+use serenity::client::ClientBuilder;
+use serenity::model::application::{Command, Interaction};
+use serenity::model::channel::{Message, Reaction};
+use serenity::gateway::GatewayError;
+use serenity::http::HttpBuilder;
+use serenity::model::event::MessageUpdateEvent;
 use serenity::model::gateway::Ready;
+use serenity::model::guild::automod::ActionExecution;
+use serenity::model::guild::{Guild, Member};
+use serenity::model::id::{ChannelId, GuildId, MessageId};
+use serenity::model::voice::VoiceState;
 use serenity::prelude::*;
-use std::env;
 use std::sync::Arc;
-use std::time::Instant;
+use tracing_subscriber::prelude::*;
+use std::process::ExitCode;
 use tokio::sync::Mutex;
 use tracing::{error, info, warn};
 
-const HISTORY_LIMIT: usize = 10;
-
-struct BattleNetAuth {
-    client_id: String,
-    client_secret: String,
-    token: Option<String>,
-    expires_at: Option<Instant>,
-}
-
-impl BattleNetAuth {
-    fn new(client_id: String, client_secret: String) -> Self {
-        Self {
-            client_id,
-            client_secret,
-            token: None,
-            expires_at: None,
-        }
-    }
-
-    fn is_expired(&self) -> bool {
-        match self.expires_at {
-            Some(exp) => Instant::now() >= exp,
-            None => true,
-        }
-    }
-}
+use args::Args;
+use features::Feature;
+use clients::ChatMessage;
 
-#[derive(Deserialize)]
-struct OAuthTokenResponse {
-    access_token: String,
-    expires_in: u64,
-}
-
-#[derive(Deserialize)]
-struct WowCharacter {
-    name: String,
-    level: u32,
-    race: WowEnum,
-    character_class: WowEnum,
-}
-
-#[derive(Deserialize)]
-struct WowEnum {
-    name: String,
-}
+const HISTORY_LIMIT: usize = 10;
+/// llama.cpp and Battle.net calls in flight at once, across all bot
+/// identities; see [`clients::Bounded`].
+const MAX_BACKEND_CALLS: usize = 16;
+const SELECT_MENU_MAX_OPTIONS: usize = 25;
 
 struct Handler {
-    http_client: HttpClient,
-    llama_api_url: Option<String>,
-    battlenet_auth: Option<Arc<Mutex<BattleNetAuth>>>,
-    db: Arc<Mutex<Connection>>,
-}
-
-#[derive(Serialize)]
-struct ChatRequest {
-    messages: Vec<ChatMessage>,
-    temperature: f32,
-    stop: Vec<String>,
-}
-
-#[derive(Serialize, Deserialize)]
-struct ChatMessage {
-    role: String,
-    content: String,
-}
-
-#[derive(Deserialize)]
-struct ChatResponse {
-    choices: Vec<Choice>,
-}
-
-#[derive(Deserialize)]
-struct Choice {
-    message: ChatMessage,
+    llm: Option<Arc<dyn clients::LlmClient>>,
+    blizzard: Option<Arc<dyn clients::BlizzardClient>>,
+    steam: Option<Arc<dyn clients::SteamClient>>,
+    riot: Option<Arc<dyn clients::RiotClient>>,
+    wow_region: wow::Region,
+    /// Game version used when neither the character nor the `wow_version` config sets one.
+    wow_version: wow::GameVersion,
+    db: Arc<dyn clients::Store>,
+    events: tokio::sync::broadcast::Sender<events::BotEvent>,
+    identity: bots::Identity,
+    modules: Vec<Arc<dyn modules::BotModule>>,
+    middleware: Vec<Arc<dyn middleware::Middleware>>,
+    topics: Arc<preamble::TopicCache>,
 }
 
 impl Handler {
-    async fn get_battlenet_token(&self) -> Result<String, String> {
-        let auth_lock = self
-            .battlenet_auth
-            .as_ref()
-            .ok_or("Battle.net not configured")?;
-        let mut auth = auth_lock.lock().await;
-
-        if !auth.is_expired() {
-            return Ok(auth.token.clone().unwrap());
-        }
-
-        let resp = self
-            .http_client
-            .post("https://oauth.battle.net/token")
-            .basic_auth(&auth.client_id, Some(&auth.client_secret))
-            .form(&[("grant_type", "client_credentials")])
-            .send()
-            .await
-            .map_err(|e| format!("OAuth request failed: {}", e))?;
-
-        if !resp.status().is_success() {
-            return Err(format!("OAuth returned status {}", resp.status()));
-        }
-
-        let token_resp: OAuthTokenResponse = resp
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse OAuth response: {}", e))?;
-
-        // Expire 60s early to avoid edge cases
-        let expires_at = Instant::now()
-            + std::time::Duration::from_secs(token_resp.expires_in.saturating_sub(60));
-        auth.token = Some(token_resp.access_token.clone());
-        auth.expires_at = Some(expires_at);
-
-        Ok(token_resp.access_token)
-    }
-
-    async fn fetch_wow_character(&self, name: &str) -> Result<WowCharacter, String> {
-        let token = self.get_battlenet_token().await?;
-        let url = format!(
-            "https://us.api.blizzard.com/profile/wow/character/nightslayer/{}?namespace=profile-classicann-us&locale=en_US",
-            name.to_lowercase()
-        );
-
-        let resp = self
-            .http_client
-            .get(&url)
-            .bearer_auth(&token)
-            .send()
-            .await
-            .map_err(|e| format!("API request failed: {}", e))?;
-
-        if resp.status() == reqwest::StatusCode::NOT_FOUND {
-            return Err(format!("Character **{}** not found on Nightslayer.", name));
-        }
-
-        if !resp.status().is_success() {
-            return Err(format!("Blizzard API returned status {}", resp.status()));
-        }
-
-        resp.json::<WowCharacter>()
-            .await
-            .map_err(|e| format!("Failed to parse character data: {}", e))
-    }
-
-    async fn ask_llama(&self, context_key: &str, user_message: &str) -> Result<String, String> {
-        let api_url = self
-            .llama_api_url
-            .as_ref()
-            .ok_or("LLAMA_API_URL not configured")?;
+    async fn ask_llama(&self, conversation: &bots::Conversation, user_message: &str) -> Result<String, String> {
+        let context_key = conversation.key.as_str();
+        let llm = self.llm.as_ref().ok_or("LLAMA_API_URL not configured")?;
 
         // Build messages array with system prompt and history
         let messages = {
             let conn = self.db.lock().await;
 
             // Store the user message
-            db::store_message(&conn, context_key, "user", user_message)
-                .map_err(|e| format!("DB error storing user message: {}", e))?;
-
-            let system_prompt = db::get_config(&conn, "system_prompt")
-                .map_err(|e| format!("DB error: {}", e))?
-                .unwrap_or_default();
+            let message_id = conversation.message_id.map(|id| id.to_string());
+            let user_id = conversation.user_id.map(|id| id.to_string());
+            db::store_message_from(
+                &conn,
+                context_key,
+                "user",
+                conversation.speaker.as_deref(),
+                user_id.as_deref(),
+                message_id.as_deref(),
+                user_message,
+            )
+            .map_err(|e| format!("DB error storing user message: {}", e))?;
+
+            let mut system_prompt = self
+                .channel_system_prompt(&conn, conversation.guild_id, conversation.channel_id)
+                .map_err(|e| format!("DB error: {}", e))?;
+            // The asker's own `!style` and language go last, on top of the persona
+            let style = conversation
+                .user_id
+                .and_then(|user_id| db::get_reply_style(&conn, &user_id.to_string()).ok().flatten())
+                .and_then(|name| persona::ReplyStyle::from_name(&name))
+                .map(|style| style.instruction());
+            let language = language::reply_note(user_message);
+            for note in conversation.notes.iter().map(String::as_str).chain(style).chain(language.as_deref()) {
+                if !system_prompt.is_empty() {
+                    system_prompt.push_str("\n\n");
+                }
+                system_prompt.push_str(note);
+            }
+            let persona = self
+                .persona(&conn, conversation.guild_id, conversation.channel_id)
+                .map_err(|e| format!("DB error: {}", e))?;
+            let examples = feedback::few_shot_messages(&conn, &persona)
+                .map_err(|e| format!("DB error: {}", e))?;
 
             let history = db::get_recent_messages(&conn, context_key, HISTORY_LIMIT)
                 .map_err(|e| format!("DB error: {}", e))?;
 
-            let mut msgs = Vec::with_capacity(history.len() + 1);
+            let mut msgs = Vec::with_capacity(history.len() + examples.len() + 1);
 
             if !system_prompt.is_empty() {
-                msgs.push(ChatMessage {
-                    role: "system".to_string(),
-                    content: system_prompt,
-                });
+                msgs.push(ChatMessage::new("system", system_prompt));
             }
 
+            // Upvoted exchanges first, so the style drifts toward what people liked
+            msgs.extend(examples);
+
+            // Where everyone shares a history, say who said what
+            let shared = db::get_context_mode(&conn, &conversation.channel_id.to_string())
+                .map(|mode| mode == "channel")
+                .unwrap_or(true);
             for m in history {
-                msgs.push(ChatMessage {
-                    role: m.role,
-                    content: m.content,
-                });
+                let content = match (&m.author, m.role.as_str()) {
+                    (Some(author), "user") if shared => format!("{}: {}", author, m.content),
+                    _ => m.content,
+                };
+                msgs.push(ChatMessage::new(&m.role, content));
             }
 
             // Append a reminder suffix to the last user message
             if let Some(last) = msgs.last_mut() {
                 if last.role == "user" {
-                    let cap = db::get_config(&conn, "response_cap")
-                        .ok()
-                        .flatten()
-                        .and_then(|v| v.parse::<u32>().ok())
-                        .unwrap_or(10);
+                    let guild_id = conversation.guild_id.map(|id| id.to_string());
+                    let cap = db::get_response_cap(&conn, guild_id.as_deref());
                     last.content.push_str(&format!(
                         "\n(Reply in {} words or less. Stay in character.)",
                         cap
@@ -212,39 +190,18 @@ impl Handler {
             msgs
         };
 
-        let request = ChatRequest {
-            messages,
-            temperature: 0.4,
-            stop: vec![
-                "<|im_end|>".to_string(),
-                "<|im_start|>".to_string(),
-                "</s>".to_string(),
-                "[INST]".to_string(),
-            ],
-        };
-
-        let response = self
-            .http_client
-            .post(format!("{}/v1/chat/completions", api_url))
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| format!("Failed to reach llama.cpp: {}", e))?;
-
-        if !response.status().is_success() {
-            return Err(format!("llama.cpp returned status {}", response.status()));
+        let blocklist = self.blocklist(conversation.guild_id).await;
+        let mut reply = llm.complete(messages.clone()).await?;
+        if blocklist.mode == blocklist::Mode::Regenerate {
+            for _ in 0..blocklist::Mode::MAX_REGENERATIONS {
+                if !blocklist.is_match(&reply) {
+                    break;
+                }
+                info!("Reply in {} hit the blocklist; regenerating", context_key);
+                reply = llm.complete(messages.clone()).await?;
+            }
         }
-
-        let chat_response: ChatResponse = response
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse response: {}", e))?;
-
-        let reply = chat_response
-            .choices
-            .first()
-            .map(|c| c.message.content.clone())
-            .ok_or_else(|| "No response from model".to_string())?;
+        let reply = blocklist.mask(&reply);
 
         // Store the assistant response
         {
@@ -254,565 +211,872 @@ impl Handler {
             }
         }
 
+        self.emit(events::BotEvent::LlmReply {
+            context: context_key.to_string(),
+            reply: reply.clone(),
+        });
+
         Ok(reply)
     }
 
-    async fn query_llm_oneshot(
-        &self,
-        system_prompt: String,
-        user_message: String,
-    ) -> Result<String, String> {
-        let api_url = self
-            .llama_api_url
-            .as_ref()
-            .ok_or("LLAMA_API_URL not configured")?;
-
-        let messages = vec![
-            ChatMessage {
-                role: "system".to_string(),
-                content: system_prompt,
-            },
-            ChatMessage {
-                role: "user".to_string(),
-                content: user_message,
-            },
-        ];
-
-        let request = ChatRequest {
-            messages,
-            temperature: 0.4,
-            stop: vec![
-                "<|im_end|>".to_string(),
-                "<|im_start|>".to_string(),
-                "</s>".to_string(),
-                "[INST]".to_string(),
-            ],
-        };
-
-        let response = self
-            .http_client
-            .post(format!("{}/v1/chat/completions", api_url))
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| format!("Failed to reach llama.cpp: {}", e))?;
+    /// First feature required by `command` that is disabled in `guild_id`, if any.
+    /// Commands outside a guild (DMs) are never gated.
+    async fn disabled_feature(&self, guild_id: Option<GuildId>, command: &str) -> Option<Feature> {
+        let guild_id = guild_id?.to_string();
+        let conn = self.db.lock().await;
+        features::required_for(command)
+            .iter()
+            .copied()
+            .find(|f| !db::is_feature_enabled(&conn, &guild_id, f.name()).unwrap_or(true))
+    }
 
-        if !response.status().is_success() {
-            return Err(format!("llama.cpp returned status {}", response.status()));
+    async fn remove_character(&self, name: &str, removed_by: &str) -> String {
+        let conn = self.db.lock().await;
+        match db::remove_tracked_character(&conn, name) {
+            Ok(true) => {
+                info!("{} removed tracked character {}", removed_by, name);
+                format!("Removed **{}** from tracking.", name)
+            }
+            Ok(false) => format!("**{}** is not being tracked.", name),
+            Err(e) => {
+                error!("DB error removing character: {}", e);
+                "Failed to remove character.".to_string()
+            }
         }
+    }
 
-        let chat_response: ChatResponse = response
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse response: {}", e))?;
+    /// Builds the level check report for all tracked characters (or just `only`), optionally
+    /// decorated with LLM-generated insults in `guild_id`'s style.
+    async fn level_check(&self, guild_id: Option<GuildId>, only: Option<&str>, use_insults: bool) -> String {
+        self.level_check_report(guild_id, only, use_insults).await.0
+    }
 
-        chat_response
-            .choices
-            .first()
-            .map(|c| c.message.content.clone())
-            .ok_or_else(|| "No response from model".to_string())
+    /// The level check as an embed, with the leading character's avatar as the thumbnail.
+    async fn level_check_embed(&self, guild_id: Option<GuildId>, only: Option<&str>, use_insults: bool) -> CreateEmbed {
+        let (report, leader) = self.level_check_report(guild_id, only, use_insults).await;
+        let embed = match report.split_once('\n') {
+            Some((title, body)) => CreateEmbed::new().title(title.trim_matches('*')).description(body),
+            None => CreateEmbed::new().description(report),
+        };
+        match leader {
+            Some(name) => match self.character_avatar(&name).await {
+                Some(url) => embed.thumbnail(url),
+                None => embed,
+            },
+            None => embed,
+        }
     }
-}
 
-#[async_trait]
-impl EventHandler for Handler {
-    async fn message(&self, ctx: Context, msg: Message) {
-        // Ignore messages from bots (including ourselves)
-        if msg.author.bot {
-            return;
+    /// [`Handler::level_check`]'s report and the highest-level character in it.
+    async fn level_check_report(
+        &self,
+        guild_id: Option<GuildId>,
+        only: Option<&str>,
+        use_insults: bool,
+    ) -> (String, Option<String>) {
+        if self.blizzard.is_none() {
+            return ("Battle.net API not configured.".to_string(), None);
         }
 
-        // Respond to direct commands
-        if msg.content.starts_with("!help") {
-            let cap = {
+        let names = match only {
+            Some(name) => vec![name.to_string()],
+            None => {
                 let conn = self.db.lock().await;
-                db::get_config(&conn, "response_cap")
-                    .ok()
-                    .flatten()
-                    .and_then(|v| v.parse::<u32>().ok())
-                    .unwrap_or(10)
-            };
-            let response = format!(
-                "**Commands:**\n\
-                 `!help` — Show this message\n\
-                 `!ping` — Pong!\n\
-                 `!hello` — Greet the bot\n\
-                 `!systemprompt [text]` — View or set the system prompt\n\
-                 `!cap <1-500>` — Set response word cap (currently **{}**)\n\
-                 `!clear` — Clear conversation history\n\
-                 `!contextchannel` — Shared history per channel\n\
-                 `!contextuser` — Separate history per user\n\
-                 `!addcharacter <name>` — Track a WoW character\n\
-                 `!removecharacter <name>` — Stop tracking a character\n\
-                 `!levelcheck` — Check levels of tracked characters (with insults)\n\
-                 `!levelcheckraw` — Check levels without insults\n\
-                 \n\
-                 Mention me to chat!",
-                cap
-            );
-            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
-                error!("Error sending message: {:?}", why);
+                db::get_tracked_characters(&conn).unwrap_or_default()
             }
-            return;
-        }
+        };
 
-        if msg.content.starts_with("!ping") {
-            if let Err(why) = msg.channel_id.say(&ctx.http, "Pong! 🏓").await {
-                error!("Error sending message: {:?}", why);
-            }
-            return;
+        if names.is_empty() {
+            return ("No characters tracked. Use `!character add <name>` to add one.".to_string(), None);
         }
 
-        if msg.content.starts_with("!hello") {
-            let response = "IT'S CHRISTINITH! ARE YOU STUPID OR ARE YOU DEAF?!";
-            if let Err(why) = msg.channel_id.say(&ctx.http, response).await {
-                error!("Error sending message: {:?}", why);
+        let futures: Vec<_> = names
+            .iter()
+            .map(|name| self.fetch_wow_character(name))
+            .collect();
+        let results = join_all(futures).await;
+
+        let mut entries: Vec<(String, u32, String)> = Vec::new();
+        let mut errors: Vec<String> = Vec::new();
+
+        for (name, result) in names.iter().zip(results) {
+            match result {
+                Ok(c) => entries.push((
+                    c.name,
+                    c.level,
+                    format!("{} {}", c.race.name, c.character_class.name),
+                )),
+                Err(e) => errors.push(format!("{}: {}", name, e)),
             }
-            return;
         }
 
-        if msg.content.starts_with("!systemprompt") {
-            let new_prompt = msg.content.trim_start_matches("!systemprompt").trim();
-            if new_prompt.is_empty() {
-                // Show current prompt
-                let conn = self.db.lock().await;
-                let current = db::get_config(&conn, "system_prompt")
-                    .ok()
-                    .flatten()
-                    .unwrap_or_default();
-                let response = format!("**Current system prompt:**\n{}", current);
-                if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
-                    error!("Error sending message: {:?}", why);
-                }
-            } else {
-                let conn = self.db.lock().await;
-                match db::set_config(&conn, "system_prompt", new_prompt) {
-                    Ok(_) => {
-                        info!("{} updated system prompt to: {}", msg.author.name, new_prompt);
-                        if let Err(why) = msg.channel_id.say(&ctx.http, "System prompt updated!").await {
-                            error!("Error sending message: {:?}", why);
-                        }
-                    }
-                    Err(e) => {
-                        error!("Failed to update system prompt: {}", e);
-                        if let Err(why) = msg.channel_id.say(&ctx.http, "Failed to update system prompt.").await {
-                            error!("Error sending message: {:?}", why);
-                        }
-                    }
-                }
-            }
-            return;
-        }
+        entries.sort_by_key(|e| std::cmp::Reverse(e.1));
 
-        if msg.content.starts_with("!cap") {
-            let arg = msg.content.trim_start_matches("!cap").trim();
-            if arg.is_empty() {
-                let cap = {
-                    let conn = self.db.lock().await;
-                    db::get_config(&conn, "response_cap")
-                        .ok()
-                        .flatten()
-                        .and_then(|v| v.parse::<u32>().ok())
-                        .unwrap_or(10)
-                };
-                let response = format!("Response word cap is currently **{}**. Usage: `!cap <1-500>`", cap);
-                if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
-                    error!("Error sending message: {:?}", why);
-                }
-            } else {
-                match arg.parse::<u32>() {
-                    Ok(n) if (1..=500).contains(&n) => {
-                        let conn = self.db.lock().await;
-                        match db::set_config(&conn, "response_cap", &n.to_string()) {
-                            Ok(_) => {
-                                info!("{} set response cap to {}", msg.author.name, n);
-                                let response = format!("Response word cap set to **{}**.", n);
-                                if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
-                                    error!("Error sending message: {:?}", why);
-                                }
-                            }
-                            Err(e) => {
-                                error!("Failed to set response cap: {}", e);
-                                if let Err(why) = msg.channel_id.say(&ctx.http, "Failed to save cap.").await {
-                                    error!("Error sending message: {:?}", why);
-                                }
-                            }
-                        }
-                    }
-                    _ => {
-                        if let Err(why) = msg.channel_id.say(&ctx.http, "Cap must be a number between 1 and 500.").await {
-                            error!("Error sending message: {:?}", why);
-                        }
+        // Fetch insults in parallel if LLM is configured and this isn't !levelcheckraw
+        let insults: Vec<Option<String>> = if use_insults && self.llm.is_some() {
+            let style = self.insult_style(guild_id).await;
+            let insult_futures: Vec<_> = entries
+                .iter()
+                .map(|(name, level, desc)| async move {
+                    // Protected and opted-out characters get the raw line
+                    if !self.insultable(name).await {
+                        return None;
                     }
-                }
-            }
-            return;
-        }
+                    // Collections are only known for characters someone has looked up
+                    let material = self.collection_material(name).await;
+                    let target = insults::Target::LevelCheck {
+                        name,
+                        level: *level,
+                        description: desc,
+                        material: material.as_deref(),
+                    };
+                    self.insult(style, target).await
+                })
+                .collect();
+            join_all(insult_futures).await
+        } else {
+            entries.iter().map(|_| None).collect()
+        };
 
-        if msg.content.starts_with("!clear") {
-            let conn = self.db.lock().await;
-            let channel_id = msg.channel_id.to_string();
-            let mode = db::get_context_mode(&conn, &channel_id).unwrap_or_else(|_| "channel".to_string());
-            let context_key = match mode.as_str() {
-                "user" => format!("{}:{}", channel_id, msg.author.id),
-                _ => channel_id,
-            };
-            match db::clear_messages(&conn, &context_key) {
-                Ok(n) => {
-                    let response = format!("Cleared {} messages.", n);
-                    if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
-                        error!("Error sending message: {:?}", why);
-                    }
-                }
-                Err(e) => {
-                    error!("Failed to clear messages: {}", e);
-                }
+        let mut response = String::from("**Level Check — Nightslayer**\n");
+        for ((name, level, desc), insult) in entries.iter().zip(insults.iter()) {
+            match insult {
+                Some(text) => response.push_str(&format!(
+                    "  {} — Level {} {} — *{}*\n", name, level, desc, text
+                )),
+                None => response.push_str(&format!(
+                    "  {} — Level {} {}\n", name, level, desc
+                )),
             }
-            return;
+        }
+        for err in &errors {
+            response.push_str(&format!("  ⚠ {}\n", err));
         }
 
-        if msg.content.starts_with("!contextchannel") {
-            let conn = self.db.lock().await;
-            let channel_id = msg.channel_id.to_string();
-            match db::set_context_mode(&conn, &channel_id, "channel") {
-                Ok(_) => {
-                    if let Err(why) = msg.channel_id.say(&ctx.http, "Context mode set to **channel** — everyone shares history here.").await {
-                        error!("Error sending message: {:?}", why);
-                    }
-                }
-                Err(e) => {
-                    error!("Failed to set context mode: {}", e);
-                }
-            }
+        (response, entries.into_iter().next().map(|(name, _, _)| name))
+    }
+
+    /// Runs a message through the middleware, the [`commands`] registry and
+    /// the modules.
+    async fn dispatch_message(&self, ctx: Context, msg: Message) {
+        // Ignore messages from bots (including ourselves)
+        if msg.author.bot {
             return;
         }
 
-        if msg.content.starts_with("!contextuser") {
-            let conn = self.db.lock().await;
-            let channel_id = msg.channel_id.to_string();
-            match db::set_context_mode(&conn, &channel_id, "user") {
-                Ok(_) => {
-                    if let Err(why) = msg.channel_id.say(&ctx.http, "Context mode set to **user** — everyone gets their own history here.").await {
-                        error!("Error sending message: {:?}", why);
-                    }
-                }
-                Err(e) => {
-                    error!("Failed to set context mode: {}", e);
-                }
-            }
+        if !self.identity.serves(msg.channel_id) {
             return;
         }
 
-        if msg.content.starts_with("!addcharacter") {
-            let name = msg.content.trim_start_matches("!addcharacter").trim();
-            if name.is_empty() {
-                if let Err(why) = msg.channel_id.say(&ctx.http, "Usage: `!addcharacter <name>`").await {
-                    error!("Error sending message: {:?}", why);
-                }
-                return;
-            }
+        // Extra bots only chat; commands are left to the primary bot so they
+        // aren't answered twice
+        let (command, args) = if self.identity.is_primary() {
+            args::parse_command(&msg.content).unwrap_or_default()
+        } else {
+            Default::default()
+        };
+        let (command, args) = resolve_command(command, args);
+        let command = command.as_str();
 
-            if self.battlenet_auth.is_none() {
-                if let Err(why) = msg.channel_id.say(&ctx.http, "Battle.net API not configured.").await {
-                    error!("Error sending message: {:?}", why);
-                }
+        for step in &self.middleware {
+            if step.handle(self, &ctx, &msg, command, &args).await == middleware::Flow::Stop {
                 return;
             }
-
-            let typing = msg.channel_id.start_typing(&ctx.http);
-            match self.fetch_wow_character(name).await {
-                Ok(character) => {
-                    let conn = self.db.lock().await;
-                    let added_by = msg.author.id.to_string();
-                    match db::add_tracked_character(&conn, &character.name, &added_by) {
-                        Ok(true) => {
-                            let response = format!(
-                                "Now tracking **{}** — Level {} {} {}",
-                                character.name, character.level, character.race.name, character.character_class.name
-                            );
-                            drop(typing);
-                            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
-                                error!("Error sending message: {:?}", why);
-                            }
-                        }
-                        Ok(false) => {
-                            let response = format!(
-                                "**{}** is already tracked — Level {} {} {}",
-                                character.name, character.level, character.race.name, character.character_class.name
-                            );
-                            drop(typing);
-                            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
-                                error!("Error sending message: {:?}", why);
-                            }
-                        }
-                        Err(e) => {
-                            error!("DB error adding character: {}", e);
-                            drop(typing);
-                            if let Err(why) = msg.channel_id.say(&ctx.http, "Failed to save character.").await {
-                                error!("Error sending message: {:?}", why);
-                            }
-                        }
-                    }
-                }
-                Err(e) => {
-                    drop(typing);
-                    if let Err(why) = msg.channel_id.say(&ctx.http, &e).await {
-                        error!("Error sending message: {:?}", why);
-                    }
-                }
-            }
-            return;
         }
 
-        if msg.content.starts_with("!removecharacter") {
-            let name = msg.content.trim_start_matches("!removecharacter").trim();
-            if name.is_empty() {
-                if let Err(why) = msg.channel_id.say(&ctx.http, "Usage: `!removecharacter <name>`").await {
-                    error!("Error sending message: {:?}", why);
-                }
-                return;
-            }
+        if !command.is_empty() {
+            error_tracking::set_context(command, msg.guild_id);
+            self.emit(events::BotEvent::CommandInvoked {
+                command: command.to_string(),
+                user_id: msg.author.id.to_string(),
+                channel_id: msg.channel_id.to_string(),
+                guild_id: msg.guild_id.map(|g| g.to_string()),
+                slash: false,
+            });
+        }
 
-            let conn = self.db.lock().await;
-            match db::remove_tracked_character(&conn, name) {
-                Ok(true) => {
-                    if let Err(why) = msg.channel_id.say(&ctx.http, &format!("Removed **{}** from tracking.", name)).await {
-                        error!("Error sending message: {:?}", why);
-                    }
-                }
-                Ok(false) => {
-                    if let Err(why) = msg.channel_id.say(&ctx.http, &format!("**{}** is not being tracked.", name)).await {
-                        error!("Error sending message: {:?}", why);
-                    }
-                }
-                Err(e) => {
-                    error!("DB error removing character: {}", e);
-                    if let Err(why) = msg.channel_id.say(&ctx.http, "Failed to remove character.").await {
-                        error!("Error sending message: {:?}", why);
-                    }
-                }
-            }
+        if let Some(registered) = commands::find(command) {
+            registered.execute(self, &ctx, &msg, &args).await;
             return;
         }
 
-        if msg.content.starts_with("!levelcheck") {
-            let use_insults = !msg.content.starts_with("!levelcheckraw");
-
-            if self.battlenet_auth.is_none() {
-                if let Err(why) = msg.channel_id.say(&ctx.http, "Battle.net API not configured.").await {
-                    error!("Error sending message: {:?}", why);
-                }
+        for module in &self.modules {
+            if module.on_message(self, &ctx, &msg, command, &args).await {
                 return;
             }
+        }
+    }
 
-            let names = {
-                let conn = self.db.lock().await;
-                db::get_tracked_characters(&conn).unwrap_or_default()
-            };
-
-            if names.is_empty() {
-                if let Err(why) = msg.channel_id.say(&ctx.http, "No characters tracked. Use `!addcharacter <name>` to add one.").await {
-                    error!("Error sending message: {:?}", why);
-                }
-                return;
-            }
+    async fn query_llm_oneshot(
+        &self,
+        system_prompt: String,
+        user_message: String,
+    ) -> Result<String, String> {
+        let llm = self.llm.as_ref().ok_or("LLAMA_API_URL not configured")?;
+        llm.complete(vec![
+            ChatMessage::new("system", system_prompt),
+            ChatMessage::new("user", user_message),
+        ])
+        .await
+    }
+}
 
-            let typing = msg.channel_id.start_typing(&ctx.http);
-            let futures: Vec<_> = names
-                .iter()
-                .map(|name| self.fetch_wow_character(name))
-                .collect();
-            let results = join_all(futures).await;
-
-            let mut entries: Vec<(String, u32, String)> = Vec::new();
-            let mut errors: Vec<String> = Vec::new();
-
-            for (name, result) in names.iter().zip(results) {
-                match result {
-                    Ok(c) => entries.push((
-                        c.name,
-                        c.level,
-                        format!("{} {}", c.race.name, c.character_class.name),
-                    )),
-                    Err(e) => errors.push(format!("{}: {}", name, e)),
-                }
-            }
+/// Older flat command names and the grouped command they now map to.
+const COMMAND_ALIASES: &[(&str, &str)] = &[
+    ("addcharacter", "character add"),
+    ("removecharacter", "character remove"),
+];
 
-            entries.sort_by(|a, b| b.1.cmp(&a.1));
+/// Commands whose first argument selects a subcommand.
+const COMMAND_GROUPS: &[&str] = &["automod", "blocklist", "character", "checkpoint", "counter", "faq", "flag", "kb", "lol", "persona", "rp", "script", "steam", "tag", "tempvoice", "todo", "wakeword"];
 
-            // Fetch insults in parallel if LLM is configured and this isn't !levelcheckraw
-            let insults: Vec<Option<String>> = if use_insults && self.llama_api_url.is_some() {
-                let system_prompt = {
-                    let conn = self.db.lock().await;
-                    db::get_config(&conn, "system_prompt")
-                        .ok()
-                        .flatten()
-                        .unwrap_or_default()
-                };
+/// Folds `!group sub ...` into a single `"group sub"` command name and maps legacy
+/// aliases onto it, so `!addcharacter x` and `!character add x` dispatch identically.
+fn resolve_command(command: String, args: Args) -> (String, Args) {
+    if let Some((_, canonical)) = COMMAND_ALIASES.iter().find(|(alias, _)| *alias == command) {
+        return (canonical.to_string(), args);
+    }
+    if COMMAND_GROUPS.contains(&command.as_str()) {
+        if let Some((sub, rest)) = args.subcommand() {
+            return (format!("{} {}", command, sub), rest);
+        }
+    }
+    (command, args)
+}
 
-                let insult_futures: Vec<_> = entries
-                    .iter()
-                    .map(|(name, level, desc)| {
-                        let sys = system_prompt.clone();
-                        let prompt = format!(
-                            "Give a 1-5 word insult for a level {} {} named {}. Reply with ONLY the insult, nothing else.",
-                            level, desc, name
-                        );
-                        self.query_llm_oneshot(sys, prompt)
-                    })
-                    .collect();
-
-                join_all(insult_futures)
-                    .await
-                    .into_iter()
-                    .map(|r| r.ok())
-                    .collect()
-            } else {
-                entries.iter().map(|_| None).collect()
-            };
+#[async_trait]
+impl EventHandler for Handler {
+    async fn message(&self, ctx: Context, msg: Message) {
+        trace::traced("message", self.dispatch_message(ctx, msg)).await;
+    }
 
-            let mut response = String::from("**Level Check — Nightslayer**\n");
-            for ((name, level, desc), insult) in entries.iter().zip(insults.iter()) {
-                match insult {
-                    Some(text) => response.push_str(&format!(
-                        "  {} — Level {} {} — *{}*\n", name, level, desc, text.trim()
-                    )),
-                    None => response.push_str(&format!(
-                        "  {} — Level {} {}\n", name, level, desc
-                    )),
-                }
-            }
-            for err in &errors {
-                response.push_str(&format!("  ⚠ {}\n", err));
-            }
+    async fn message_update(&self, ctx: Context, _old: Option<Message>, _new: Option<Message>, event: MessageUpdateEvent) {
+        trace::traced("message_update", self.handle_message_edit(&ctx, &event)).await;
+    }
 
-            drop(typing);
-            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
-                error!("Error sending message: {:?}", why);
-            }
-            return;
-        }
+    async fn message_delete(&self, _ctx: Context, channel_id: ChannelId, deleted: MessageId, _guild_id: Option<GuildId>) {
+        trace::traced("message_delete", self.handle_message_delete(channel_id, &[deleted])).await;
+    }
 
-        // When mentioned, send the message to llama.cpp
-        if msg.mentions_me(&ctx.http).await.unwrap_or(false) {
-            info!("Received message from {}: {}", msg.author.name, msg.content);
+    async fn message_delete_bulk(&self, _ctx: Context, channel_id: ChannelId, deleted: Vec<MessageId>, _guild_id: Option<GuildId>) {
+        trace::traced("message_delete_bulk", self.handle_message_delete(channel_id, &deleted)).await;
+    }
 
-            // Show typing indicator while waiting for LLM
-            let typing = msg.channel_id.start_typing(&ctx.http);
+    async fn reaction_add(&self, ctx: Context, reaction: Reaction) {
+        trace::traced("reaction_add", async {
+            self.handle_reaction(&reaction, true).await;
+            self.handle_reaction_action(&ctx, &reaction).await;
+        })
+        .await;
+    }
 
-            // Strip the bot mention from the message to get the actual question
-            let content = msg
-                .content
-                .split_once('>')
-                .map(|(_, rest)| rest.trim())
-                .unwrap_or(&msg.content);
+    async fn reaction_remove(&self, _ctx: Context, reaction: Reaction) {
+        trace::traced("reaction_remove", self.handle_reaction(&reaction, false)).await;
+    }
 
-            if content.is_empty() {
-                if let Err(why) = msg
-                    .channel_id
-                    .say(&ctx.http, "You mentioned me but didn't say anything!")
-                    .await
-                {
-                    error!("Error sending message: {:?}", why);
-                }
-                return;
-            }
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        trace::traced("interaction", self.handle_interaction(ctx, interaction)).await;
+    }
 
-            let channel_id = msg.channel_id.to_string();
-            let context_key = {
-                let conn = self.db.lock().await;
-                let mode = db::get_context_mode(&conn, &channel_id).unwrap_or_else(|_| "channel".to_string());
-                match mode.as_str() {
-                    "user" => format!("{}:{}", channel_id, msg.author.id),
-                    _ => channel_id.clone(),
-                }
-            };
-            let response = match self.ask_llama(&context_key, content).await {
-                Ok(reply) => reply,
-                Err(e) => {
-                    error!("LLM error: {}", e);
-                    format!("Sorry, I couldn't get a response: {}", e)
-                }
-            };
+    async fn auto_moderation_action_execution(&self, _ctx: Context, execution: ActionExecution) {
+        trace::traced("automod", self.record_automod_execution(&execution)).await;
+    }
 
-            drop(typing);
+    async fn guild_create(&self, ctx: Context, guild: Guild, _is_new: Option<bool>) {
+        trace::traced("guild_create", async {
+            self.start_onboarding(&ctx, &guild).await;
+            for module in &self.modules {
+                module.on_guild_create(self, &ctx, &guild).await;
+            }
+        })
+        .await;
+    }
 
-            // Discord has a 2000 char limit - truncate if needed
-            let response = if response.len() > 1990 {
-                format!("{}...", &response[..1990])
-            } else {
-                response
-            };
+    async fn guild_member_addition(&self, ctx: Context, new_member: Member) {
+        trace::traced("member_join", async {
+            for module in &self.modules {
+                module.on_member_join(self, &ctx, &new_member).await;
+            }
+        })
+        .await;
+    }
 
-            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
-                error!("Error sending message: {:?}", why);
+    async fn voice_state_update(&self, ctx: Context, _old: Option<VoiceState>, new: VoiceState) {
+        trace::traced("voice_state", async {
+            for module in &self.modules {
+                module.on_voice_state(self, &ctx, &new).await;
             }
-        }
+        })
+        .await;
     }
 
-    async fn ready(&self, _: Context, ready: Ready) {
+    async fn ready(&self, ctx: Context, ready: Ready) {
         info!("{} is connected and ready!", ready.user.name);
+        systemd::notify(&format!("READY=1\nSTATUS=Connected as {}", ready.user.name));
+
+        // Slash commands belong to the primary bot; extra bots are chat-only
+        let commands = if self.identity.is_primary() {
+            interactions::application_commands()
+        } else {
+            Vec::new()
+        };
+        if let Err(why) = Command::set_global_commands(&ctx.http, commands).await {
+            error!("Failed to register application commands: {:?}", why);
+        }
+
+        for module in &self.modules {
+            module.on_ready(self, &ctx, &ready).await;
+        }
     }
 }
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
+    // Initialize error reporting before logging, so the Sentry layer has a client
+    let _sentry = error_tracking::init();
+
     // Initialize logging
-    tracing_subscriber::fmt::init();
+    let sinks = logging::Settings::from_env().and_then(|settings| logging::sinks(&settings));
+    let (sinks, _log_guard) = match sinks {
+        Ok(sinks) => sinks,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(systemd::exit::CONFIG);
+        }
+    };
+    tracing_subscriber::registry()
+        .with(sinks)
+        .with(alerts::AlertLayer)
+        .with(error_tracking::layer())
+        .init();
+
+    let cli = cli::Cli::parse();
+    match cli.command {
+        None => run(cli.database, false, false, false).await,
+        Some(cli::Command::Run { strict, mock_llm, read_only }) => run(cli.database, strict, mock_llm, read_only).await,
+        Some(command) => match cli::run(&cli.database, command) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("{}", e);
+                ExitCode::FAILURE
+            }
+        },
+    }
+}
 
-    // Get Discord token from environment
-    let token = env::var("DISCORD_TOKEN").expect("Expected DISCORD_TOKEN in environment");
+/// Connects to Discord and serves until the gateway connection ends or the
+/// process is asked to stop. The exit code says which way it failed; see
+/// [`systemd::exit`]. With `strict`, a broken integration stops startup; with
+/// `mock_llm`, chat goes to a built-in echo server instead of llama.cpp. With
+/// `read_only`, nothing is sent to Discord; see [`readonly`].
+async fn run(db_path: String, strict: bool, mock_llm: bool, read_only: bool) -> ExitCode {
+    let mut config = match config::Config::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("{}", e);
+            return ExitCode::from(systemd::exit::CONFIG);
+        }
+    };
 
-    // Get llama.cpp API URL (optional - bot works without it but can't answer LLM questions)
-    let llama_api_url = env::var("LLAMA_API_URL").ok();
-    if llama_api_url.is_some() {
-        info!("LLAMA_API_URL configured: {}", llama_api_url.as_ref().unwrap());
-    } else {
-        warn!("LLAMA_API_URL not set - LLM features disabled");
+    if mock_llm {
+        match mock_llm::spawn().await {
+            Ok(url) => {
+                warn!("Using the mock LLM at {} instead of llama.cpp", url);
+                config.llama_api_url = Some(url);
+            }
+            Err(e) => {
+                error!("Failed to start mock LLM: {}", e);
+                return ExitCode::from(systemd::exit::SOFTWARE);
+            }
+        }
     }
 
-    // Get Battle.net credentials (optional)
-    let battlenet_auth = match (
-        env::var("BATTLENET_CLIENT_ID"),
-        env::var("BATTLENET_CLIENT_SECRET"),
-    ) {
-        (Ok(id), Ok(secret)) => {
+    let modules = modules::registered(&config);
+    let http_client = HttpClient::new();
+    // Shared by llama.cpp and Battle.net, so slow calls to either wait their turn
+    let backend_slots = Arc::new(tokio::sync::Semaphore::new(MAX_BACKEND_CALLS));
+
+    // llama.cpp is optional - the bot works without it but can't answer LLM questions
+    let llm: Option<Arc<dyn clients::LlmClient>> = match config.llama_api_url {
+        Some(url) => {
+            info!("LLAMA_API_URL configured: {}", url);
+            let llama = clients::LlamaCpp::new(http_client.clone(), url);
+            let llama = match config.llama_chat_template {
+                Some(template) => {
+                    info!("Using the {} chat template (LLAMA_CHAT_TEMPLATE)", template.name());
+                    llama.with_template(template, true)
+                }
+                None => match llama.detect_template().await {
+                    Ok(Some(template)) => {
+                        info!("Detected the {} chat template", template.name());
+                        llama.with_template(template, false)
+                    }
+                    Ok(None) => {
+                        warn!("Unrecognized chat template; using generic stop tokens");
+                        llama
+                    }
+                    Err(e) => {
+                        warn!("Couldn't detect the chat template ({}); using generic stop tokens", e);
+                        llama
+                    }
+                },
+            };
+            Some(Arc::new(clients::Bounded::new(backend_slots.clone(), Arc::new(llama))))
+        }
+        None => {
+            warn!("LLAMA_API_URL not set - LLM features disabled");
+            None
+        }
+    };
+
+    // Battle.net is optional too
+    let blizzard: Option<Arc<dyn clients::BlizzardClient>> = match config.battlenet_credentials {
+        Some((id, secret)) => {
             info!("Battle.net API configured");
-            Some(Arc::new(Mutex::new(BattleNetAuth::new(id, secret))))
+            let battle_net = clients::BattleNet::new(http_client.clone(), config.wow_region, id, secret);
+            Some(Arc::new(clients::Bounded::new(backend_slots.clone(), Arc::new(battle_net))))
         }
-        _ => {
+        None => {
             warn!("BATTLENET_CLIENT_ID/SECRET not set — WoW features disabled");
             None
         }
     };
+    let steam: Option<Arc<dyn clients::SteamClient>> = config.steam_api_key.map(|key| {
+        info!("Steam Web API configured");
+        Arc::new(clients::SteamWebApi::new(http_client.clone(), key)) as Arc<dyn clients::SteamClient>
+    });
+    let riot: Option<Arc<dyn clients::RiotClient>> = config.riot_api_key.map(|key| {
+        info!("Riot API configured for {}", config.riot_platform.slug());
+        Arc::new(clients::RiotApi::new(http_client.clone(), config.riot_platform, key)) as Arc<dyn clients::RiotClient>
+    });
+    info!(
+        "WoW region {}, default game version {}",
+        config.wow_region.slug(),
+        config.wow_version.label()
+    );
 
     // Initialize database
-    let db_path = env::var("DATABASE_PATH").unwrap_or_else(|_| "./discord-bot.db".to_string());
     info!("Opening database at {}", db_path);
-    let conn = Connection::open(&db_path).expect("Failed to open database");
-    db::init(&conn).expect("Failed to initialize database schema");
+    let conn = match Connection::open(&db_path) {
+        Ok(conn) => conn,
+        Err(e) => {
+            error!("Failed to open database: {}", e);
+            return ExitCode::from(systemd::exit::IO);
+        }
+    };
+    if let Err(e) = db::init(&conn) {
+        error!("Failed to initialize database schema: {}", e);
+        return ExitCode::from(systemd::exit::IO);
+    }
+    match encryption::enable(&conn) {
+        Ok(0) => {}
+        Ok(encrypted) => info!("Encrypted {} stored rows", encrypted),
+        Err(e) => {
+            error!("{}", e);
+            return ExitCode::from(systemd::exit::CONFIG);
+        }
+    }
+    let read_only = read_only || db::get_config(&conn, None, "read_only").ok().flatten().as_deref() == Some("on");
     let db = Arc::new(Mutex::new(conn));
 
+    // In read-only mode Discord API requests go through a local relay, which withholds them
+    let discord_relay = if read_only {
+        match readonly::spawn(http_client.clone()).await {
+            Ok(url) => Some(url),
+            Err(e) => {
+                error!("Failed to start the Discord API relay: {}", e);
+                return ExitCode::from(systemd::exit::SOFTWARE);
+            }
+        }
+    } else {
+        None
+    };
+    readonly::set(read_only);
+    if read_only {
+        warn!("Read-only mode: processing everything but sending nothing to Discord");
+    }
+    let discord_http = |token: &str| match &discord_relay {
+        Some(relay) => HttpBuilder::new(token).proxy(relay.as_str()).build(),
+        None => HttpBuilder::new(token).build(),
+    };
+
     // Set gateway intents
-    let intents = GatewayIntents::GUILD_MESSAGES
+    let intents = GatewayIntents::GUILDS
+        | GatewayIntents::GUILD_MESSAGES
         | GatewayIntents::DIRECT_MESSAGES
-        | GatewayIntents::MESSAGE_CONTENT;
+        | GatewayIntents::GUILD_MESSAGE_REACTIONS
+        | GatewayIntents::DIRECT_MESSAGE_REACTIONS
+        | GatewayIntents::MESSAGE_CONTENT
+        | GatewayIntents::AUTO_MODERATION_EXECUTION
+        | GatewayIntents::GUILD_VOICE_STATES;
+    // Privileged, so only asked for when invite tracking is on
+    let intents = if config.track_invites {
+        intents | GatewayIntents::GUILD_MEMBERS
+    } else {
+        intents
+    };
+
+    let handler = Arc::new(Handler {
+        llm,
+        blizzard,
+        steam,
+        riot,
+        wow_region: config.wow_region,
+        wow_version: config.wow_version,
+        db,
+        events: events::channel(),
+        identity: bots::Identity::primary(),
+        modules,
+        middleware: middleware::chain(),
+        topics: Arc::default(),
+    });
+
+    for module in &handler.modules {
+        if let Err(e) = module.init(&handler).await {
+            error!("Failed to initialize {} module: {}", module.name(), e);
+            return ExitCode::from(systemd::exit::CONFIG);
+        }
+    }
 
     // Create client
-    let mut client = Client::builder(&token, intents)
-        .event_handler(Handler {
-            http_client: HttpClient::new(),
-            llama_api_url,
-            battlenet_auth,
-            db,
-        })
+    let mut client = match ClientBuilder::new_with_http(discord_http(&config.discord_token), intents)
+        .event_handler_arc(handler.clone())
         .await
-        .expect("Error creating client");
+    {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Error creating client: {:?}", e);
+            return ExitCode::from(systemd::exit::SOFTWARE);
+        }
+    };
+
+    let checks = handler.validate_integrations(&client.http).await;
+    for line in validate::summary_table(&checks) {
+        info!("{}", line);
+    }
+    if validate::has_failures(&checks) {
+        if strict {
+            error!("Refusing to start with broken integrations (--strict)");
+            return ExitCode::from(systemd::exit::CONFIG);
+        }
+        warn!("Some integrations are broken; their features will fail until fixed");
+    }
+
+    scheduler::spawn(handler.clone(), client.http.clone(), config.poll_interval);
+    retry::spawn(handler.clone(), client.http.clone());
+    alerts::spawn(handler.clone(), client.http.clone());
+    maintenance::spawn(handler.clone(), archive::dir_for(&db_path));
+
+    // Optional admin dashboard and API
+    if let Some((addr, web_token)) = config.web {
+        web::spawn(handler.clone(), client.http.clone(), addr, web_token);
+    }
+
+    // Extra bot identities share the handler's database and clients
+    let mut shard_managers = vec![client.shard_manager.clone()];
+    for bot in config.extra_bots {
+        let identity = bots::Identity {
+            name: Some(bot.name.clone()),
+            system_prompt: bot.system_prompt,
+            channels: bot.channels.into_iter().map(ChannelId::new).collect(),
+        };
+        // Extra bots only chat, so they don't need the privileged members intent
+        let mut extra = match ClientBuilder::new_with_http(discord_http(&bot.token), intents - GatewayIntents::GUILD_MEMBERS)
+            .event_handler(handler.for_identity(identity))
+            .await
+        {
+            Ok(client) => client,
+            Err(e) => {
+                error!("Error creating client for bot {}: {:?}", bot.name, e);
+                return ExitCode::from(systemd::exit::SOFTWARE);
+            }
+        };
+        shard_managers.push(extra.shard_manager.clone());
+        info!("Starting extra bot {}", bot.name);
+        tokio::spawn(async move {
+            if let Err(why) = extra.start().await {
+                error!("Bot {} stopped: {:?}", bot.name, why);
+            }
+        });
+    }
+
+    systemd::spawn_watchdog();
+
+    // Disconnect cleanly on SIGTERM/Ctrl-C so `client.start()` returns
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("Shutting down...");
+        systemd::notify("STOPPING=1");
+        for shard_manager in shard_managers {
+            shard_manager.shutdown_all().await;
+        }
+    });
 
     info!("Starting Discord bot...");
 
     // Start the client
-    if let Err(why) = client.start().await {
-        error!("Client error: {:?}", why);
+    match client.start().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(serenity::Error::Gateway(
+            why @ (GatewayError::InvalidAuthentication
+            | GatewayError::InvalidGatewayIntents
+            | GatewayError::DisallowedGatewayIntents),
+        )) => {
+            error!("Discord refused the connection: {}", why);
+            ExitCode::from(systemd::exit::CONFIG)
+        }
+        Err(why) => {
+            error!("Client error: {:?}", why);
+            ExitCode::from(systemd::exit::UNAVAILABLE)
+        }
+    }
+}
+
+async fn shutdown_signal() {
+    let mut terminate = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+        Ok(signal) => signal,
+        Err(e) => {
+            warn!("Can't listen for SIGTERM: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clients::mock::{self, MockBlizzard, MockLlm};
+
+    #[tokio::test]
+    async fn test_cheap_commands_skip_busy_backends() {
+        let slots = Arc::new(tokio::sync::Semaphore::new(MAX_BACKEND_CALLS));
+        let llm = Arc::new(clients::Bounded::new(slots.clone(), Arc::new(MockLlm::replying(&["Pong."]))));
+        let handler = mock::handler(Some(llm), None);
+        let held = slots.acquire_many(MAX_BACKEND_CALLS as u32).await.unwrap();
+
+        let conversation = mock::conversation("chan");
+        let reply = handler.ask_llama(&conversation, "hello");
+        tokio::pin!(reply);
+        let wait = std::time::Duration::from_millis(50);
+        assert!(tokio::time::timeout(wait, &mut reply).await.is_err());
+        // Meanwhile a command that doesn't call the LLM goes through the feature check untouched
+        let feature = tokio::time::timeout(wait, handler.disabled_feature(Some(GuildId::new(1)), "ping")).await;
+        assert_eq!(feature.unwrap(), None);
+
+        drop(held);
+        assert_eq!(reply.await.unwrap(), "Pong.");
+        assert_eq!(slots.available_permits(), MAX_BACKEND_CALLS);
+    }
+
+    #[tokio::test]
+    async fn test_ask_llama_stores_history() {
+        let llm = Arc::new(MockLlm::replying(&["Go away.", "Still no."]));
+        let handler = mock::handler(Some(llm.clone()), None);
+
+        assert_eq!(handler.ask_llama(&mock::conversation("chan"), "hello").await.unwrap(), "Go away.");
+        assert_eq!(handler.ask_llama(&mock::conversation("chan"), "please").await.unwrap(), "Still no.");
+
+        // The second request carries the system prompt, the first exchange and the
+        // word cap reminder on the new message
+        let second = llm.requests.lock().unwrap()[1].clone();
+        let roles: Vec<_> = second.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["system", "user", "assistant", "user"]);
+        assert!(second[3].content.starts_with("please\n(Reply in 10 words or less."));
+
+        let conn = handler.db.lock().await;
+        let stored: Vec<_> = db::get_recent_messages(&conn, "chan", 10)
+            .unwrap()
+            .into_iter()
+            .map(|m| m.content)
+            .collect();
+        assert_eq!(stored, ["hello", "Go away.", "please", "Still no."]);
+    }
+
+    #[tokio::test]
+    async fn test_ask_llama_attributes_speakers() {
+        let llm = Arc::new(MockLlm::replying(&["Hi Joe.", "Hi Ann."]));
+        let handler = mock::handler(Some(llm.clone()), None);
+        let as_speaker = |name: &str| bots::Conversation {
+            speaker: Some(name.to_string()),
+            ..mock::conversation("chan")
+        };
+
+        handler.ask_llama(&as_speaker("Joe"), "hello").await.unwrap();
+        handler.ask_llama(&as_speaker("Ann"), "hey").await.unwrap();
+        let second = llm.requests.lock().unwrap()[1].clone();
+        let contents: Vec<_> = second.iter().skip(1).map(|m| m.content.as_str()).collect();
+        assert_eq!(contents[..2], ["Joe: hello", "Hi Joe."]);
+        assert!(contents[2].starts_with("Ann: hey\n"));
+
+        // Per-user histories have one speaker, so names are left out
+        {
+            let conn = handler.db.lock().await;
+            db::set_context_mode(&conn, "1", "user").unwrap();
+        }
+        handler.ask_llama(&as_speaker("Joe"), "again").await.unwrap();
+        let third = llm.requests.lock().unwrap()[2].clone();
+        assert_eq!(third[1].content, "hello");
+    }
+
+    #[tokio::test]
+    async fn test_ask_llama_applies_reply_style() {
+        let llm = Arc::new(MockLlm::replying(&["Fine.", "Fine."]));
+        let handler = mock::handler(Some(llm.clone()), None);
+        {
+            let conn = handler.db.lock().await;
+            db::set_reply_style(&conn, "5", Some("concise")).unwrap();
+        }
+        let from = |user: u64| bots::Conversation {
+            user_id: Some(serenity::model::id::UserId::new(user)),
+            ..mock::conversation("chan")
+        };
+
+        handler.ask_llama(&from(5), "hello").await.unwrap();
+        handler.ask_llama(&from(6), "hello").await.unwrap();
+        let requests = llm.requests.lock().unwrap();
+        assert!(requests[0][0].content.ends_with(persona::ReplyStyle::Concise.instruction()));
+        assert!(!requests[1][0].content.contains("concise"));
+    }
+
+    #[tokio::test]
+    async fn test_ask_llama_replies_in_kind() {
+        let llm = Arc::new(MockLlm::replying(&["Ni idea.", "No idea."]));
+        let handler = mock::handler(Some(llm.clone()), None);
+
+        handler.ask_llama(&mock::conversation("chan"), "Hola, ¿cómo estás? ¿Vamos a la banda esta noche?").await.unwrap();
+        handler.ask_llama(&mock::conversation("chan"), "What time does the raid start tonight?").await.unwrap();
+        let requests = llm.requests.lock().unwrap();
+        assert!(requests[0][0].content.contains("Reply in Spanish"));
+        assert!(!requests[1][0].content.contains("Reply in"));
+    }
+
+    #[tokio::test]
+    async fn test_ask_llama_includes_upvoted_examples() {
+        let llm = Arc::new(MockLlm::replying(&["Fine."]));
+        let handler = mock::handler(Some(llm.clone()), None);
+        let conversation = mock::conversation("chan");
+        handler
+            .record_exchange(&conversation, "hi", "Go away.", &[serenity::model::id::MessageId::new(7)])
+            .await;
+        {
+            let conn = handler.db.lock().await;
+            db::add_feedback_vote(&conn, "7", 1).unwrap();
+        }
+
+        handler.ask_llama(&conversation, "hello").await.unwrap();
+        let request = llm.requests.lock().unwrap()[0].clone();
+        let contents: Vec<_> = request.iter().skip(1).map(|m| m.content.as_str()).collect();
+        assert_eq!(contents[..2], ["hi", "Go away."]);
+        assert!(contents[2].starts_with("hello\n"));
+    }
+
+    #[tokio::test]
+    async fn test_ask_llama_applies_blocklist() {
+        let llm = Arc::new(MockLlm::replying(&["Darn it.", "Darn.", "Fine.", "Darn again."]));
+        let handler = mock::handler(Some(llm.clone()), None);
+        let in_guild = |key| bots::Conversation {
+            guild_id: Some(GuildId::new(1)),
+            ..mock::conversation(key)
+        };
+        {
+            let conn = handler.db.lock().await;
+            db::add_blocklist_entry(&conn, "1", "darn", "admin").unwrap();
+        }
+
+        // Masked by default
+        assert_eq!(handler.ask_llama(&in_guild("chan"), "hi").await.unwrap(), "████ it.");
+
+        // Regenerated until clean
+        {
+            let conn = handler.db.lock().await;
+            db::set_blocklist_mode(&conn, "1", "regenerate").unwrap();
+        }
+        assert_eq!(handler.ask_llama(&in_guild("chan"), "hi").await.unwrap(), "Fine.");
+        assert_eq!(llm.requests.lock().unwrap().len(), 3);
+
+        // DMs have no blocklist
+        assert_eq!(handler.ask_llama(&mock::conversation("dm"), "hi").await.unwrap(), "Darn again.");
+    }
+
+    #[tokio::test]
+    async fn test_ask_llama_without_llm() {
+        let handler = mock::handler(None, None);
+        assert!(handler.ask_llama(&mock::conversation("chan"), "hello").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_level_check_aggregates() {
+        let blizzard = MockBlizzard::default()
+            .with_character("Pyuul", 42, "Night Elf", "Druid")
+            .with_character("Zara", 58, "Orc", "Warrior");
+        let handler = mock::handler(None, Some(Arc::new(blizzard)));
+        {
+            let conn = handler.db.lock().await;
+            for name in ["Pyuul", "Zara", "Ghost"] {
+                db::add_tracked_character(&conn, name, "user1").unwrap();
+            }
+        }
+
+        let report = handler.level_check(None, None, true).await;
+        let lines: Vec<_> = report.lines().collect();
+        assert_eq!(lines[1], "  Zara — Level 58 Orc Warrior");
+        assert_eq!(lines[2], "  Pyuul — Level 42 Night Elf Druid");
+        assert!(lines[3].starts_with("  ⚠ Ghost: Character **Ghost** not found"));
+
+        let llm = Arc::new(MockLlm::replying(&["slowpoke"]));
+        let handler = mock::handler(
+            Some(llm),
+            Some(Arc::new(MockBlizzard::default().with_character("Pyuul", 42, "Night Elf", "Druid"))),
+        );
+        let report = handler.level_check(None, Some("Pyuul"), true).await;
+        assert!(report.contains("Level 42 Night Elf Druid — *slowpoke*"));
+    }
+
+    #[tokio::test]
+    async fn test_level_check_embed_shows_leader_avatar() {
+        let blizzard = MockBlizzard::default()
+            .with_character("Pyuul", 42, "Night Elf", "Druid")
+            .with_character("Zara", 58, "Orc", "Warrior")
+            .with_avatar("Zara", "https://render.example/zara-avatar.jpg");
+        let handler = mock::handler(None, Some(Arc::new(blizzard)));
+        {
+            let conn = handler.db.lock().await;
+            for name in ["Pyuul", "Zara"] {
+                db::add_tracked_character(&conn, name, "user1").unwrap();
+            }
+        }
+
+        let embed = serde_json::to_value(handler.level_check_embed(None, None, false).await).unwrap();
+        assert_eq!(embed["title"], "Level Check — Nightslayer");
+        assert!(embed["description"].as_str().unwrap().starts_with("  Zara — Level 58"));
+        assert_eq!(embed["thumbnail"]["url"], "https://render.example/zara-avatar.jpg");
+
+        // No media, no thumbnail
+        let embed = serde_json::to_value(handler.level_check_embed(None, Some("Pyuul"), false).await).unwrap();
+        assert!(embed.get("thumbnail").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_level_check_without_battlenet() {
+        let handler = mock::handler(None, None);
+        assert_eq!(handler.level_check(None, None, false).await, "Battle.net API not configured.");
+    }
+
+    #[test]
+    fn test_resolve_command() {
+        let resolve = |content: &str| {
+            let (command, args) = args::parse_command(content).unwrap();
+            let (command, args) = resolve_command(command, args);
+            (command, args.positional().to_vec())
+        };
+        assert_eq!(resolve("!character add Pyuul"), ("character add".to_string(), vec!["Pyuul".to_string()]));
+        assert_eq!(resolve("!addcharacter Pyuul"), ("character add".to_string(), vec!["Pyuul".to_string()]));
+        assert_eq!(resolve("!Script LIST"), ("script list".to_string(), vec![]));
+        assert_eq!(resolve("!ping"), ("ping".to_string(), vec![]));
     }
 }
@@ -0,0 +1,113 @@
+use chrono::{DateTime, Duration as ChronoDuration, Local, NaiveTime, TimeZone};
+use rusqlite::Connection;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info};
+
+use crate::scheduler::unix_now;
+use crate::{archive, db, Handler};
+
+/// Local time the nightly maintenance runs at, when the bot is quietest.
+const MAINTENANCE_HOUR: u32 = 4;
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+/// What a maintenance run did.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub size_before: i64,
+    pub size_after: i64,
+    pub snapshots_pruned: usize,
+    pub messages_pruned: usize,
+}
+
+impl Report {
+    pub fn summary(&self) -> String {
+        format!(
+            "Pruned {} snapshots and {} messages; database {} KiB -> {} KiB",
+            self.snapshots_pruned,
+            self.messages_pruned,
+            self.size_before / 1024,
+            self.size_after / 1024
+        )
+    }
+}
+
+/// Prunes snapshots and messages past their retention (`!retention`), then
+/// compacts and optimizes the database.
+pub fn run(conn: &Connection, now: i64) -> rusqlite::Result<Report> {
+    let mut report = Report {
+        size_before: db::database_size(conn)?,
+        ..Report::default()
+    };
+    if let Some(days) = db::get_retention_days(conn, "snapshots")? {
+        report.snapshots_pruned = db::prune_snapshots(conn, now - days as i64 * DAY_SECS)?;
+    }
+    if let Some(days) = db::get_retention_days(conn, "messages")? {
+        report.messages_pruned = db::prune_messages_before(conn, now - days as i64 * DAY_SECS)?;
+    }
+    db::compact(conn)?;
+    report.size_after = db::database_size(conn)?;
+    Ok(report)
+}
+
+/// How long from `now` until the next [`MAINTENANCE_HOUR`].
+fn until_next_run<Tz: TimeZone>(now: DateTime<Tz>) -> Duration {
+    let at = NaiveTime::from_hms_opt(MAINTENANCE_HOUR, 0, 0).unwrap_or_default();
+    let mut next = now.date_naive().and_time(at);
+    if next <= now.naive_local() {
+        next += ChronoDuration::days(1);
+    }
+    (next - now.naive_local()).to_std().unwrap_or_default()
+}
+
+/// Starts the nightly maintenance loop, which first moves old listened-to
+/// messages out to `archive_dir`.
+pub fn spawn(handler: Arc<Handler>, archive_dir: PathBuf) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(until_next_run(Local::now())).await;
+            let conn = handler.db.lock().await;
+            match archive::run(&conn, &archive_dir, unix_now()) {
+                Ok(0) => {}
+                Ok(archived) => info!("Archived {} messages to {}", archived, archive_dir.display()),
+                Err(e) => error!("Message archiving failed: {}", e),
+            }
+            match run(&conn, unix_now()) {
+                Ok(report) => info!("Database maintenance: {}", report.summary()),
+                Err(e) => error!("Database maintenance failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_until_next_run() {
+        let before = Utc.with_ymd_and_hms(2024, 3, 1, 1, 30, 0).unwrap();
+        assert_eq!(until_next_run(before), Duration::from_secs(150 * 60));
+        let after = Utc.with_ymd_and_hms(2024, 3, 1, 4, 0, 0).unwrap();
+        assert_eq!(until_next_run(after), Duration::from_secs(24 * 60 * 60));
+    }
+
+    #[test]
+    fn test_run() {
+        let conn = Connection::open_in_memory().unwrap();
+        db::init(&conn).unwrap();
+        db::store_message(&conn, "chan1", "user", "old").unwrap();
+        conn.execute("UPDATE messages SET timestamp = 0", []).unwrap();
+
+        // Nothing is pruned without a retention setting
+        let report = run(&conn, 100 * DAY_SECS).unwrap();
+        assert_eq!(report.messages_pruned, 0);
+
+        db::set_retention_days(&conn, "messages", Some(30)).unwrap();
+        let report = run(&conn, 100 * DAY_SECS).unwrap();
+        assert_eq!(report.messages_pruned, 1);
+        assert!(report.size_after > 0);
+    }
+}
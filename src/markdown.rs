@@ -0,0 +1,326 @@
+/// Longest message Discord accepts, in characters.
+pub const DISCORD_MESSAGE_MAX: usize = 2000;
+
+/// Marks the end of truncated text.
+const ELLIPSIS: &str = "…";
+
+/// Discord markdown constructs that need a closing token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Marker {
+    Fence,
+    Code,
+    Bold,
+    Underline,
+    Strike,
+    Spoiler,
+    Italic,
+}
+
+/// Inline markers, longest first so `**` wins over `*`.
+const INLINE: [(Marker, &str); 5] = [
+    (Marker::Bold, "**"),
+    (Marker::Underline, "__"),
+    (Marker::Strike, "~~"),
+    (Marker::Spoiler, "||"),
+    (Marker::Italic, "*"),
+];
+
+impl Marker {
+    fn token(self) -> &'static str {
+        match self {
+            Marker::Fence => "```",
+            Marker::Code => "`",
+            Marker::Bold => "**",
+            Marker::Underline => "__",
+            Marker::Strike => "~~",
+            Marker::Spoiler => "||",
+            Marker::Italic => "*",
+        }
+    }
+}
+
+fn toggle(open: &mut Vec<Marker>, marker: Marker) {
+    match open.iter().rposition(|m| *m == marker) {
+        Some(i) => {
+            open.remove(i);
+        }
+        None => open.push(marker),
+    }
+}
+
+/// Markers opened in `text` and not yet closed, outermost first. Nothing is
+/// formatting inside code, and a backslash escapes the next character.
+fn open_markers(text: &str) -> Vec<Marker> {
+    scan(text).0
+}
+
+/// [`open_markers`], plus the language tag of the code block left open, if any.
+fn scan(text: &str) -> (Vec<Marker>, &str) {
+    let mut open = Vec::new();
+    let mut fence_lang = "";
+    let mut rest = text;
+    'scan: while let Some(c) = rest.chars().next() {
+        let in_fence = open.last() == Some(&Marker::Fence);
+        let in_code = open.last() == Some(&Marker::Code);
+
+        if rest.starts_with("```") && !in_code {
+            toggle(&mut open, Marker::Fence);
+            rest = &rest[3..];
+            if !in_fence {
+                let line = rest.split('\n').next().unwrap_or_default();
+                fence_lang = if line.chars().all(|c| c.is_ascii_alphanumeric() || "+-#_".contains(c)) {
+                    line
+                } else {
+                    ""
+                };
+            }
+            continue;
+        }
+        if in_fence {
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+        if c == '`' {
+            toggle(&mut open, Marker::Code);
+            rest = &rest[1..];
+            continue;
+        }
+        if in_code {
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+        if c == '\\' {
+            let mut chars = rest.chars();
+            chars.next();
+            chars.next();
+            rest = chars.as_str();
+            continue;
+        }
+        for (marker, token) in INLINE {
+            if rest.starts_with(token) {
+                toggle(&mut open, marker);
+                rest = &rest[token.len()..];
+                continue 'scan;
+            }
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    (open, fence_lang)
+}
+
+/// The tokens that close everything left open in `text`, innermost first.
+fn closing(text: &str) -> String {
+    let mut out = String::new();
+    for marker in open_markers(text).into_iter().rev() {
+        if marker == Marker::Fence && !text.ends_with('\n') {
+            out.push('\n');
+        }
+        out.push_str(marker.token());
+    }
+    out
+}
+
+/// The tokens that reopen everything [`closing`] closed, outermost first, so a
+/// continuation renders the same. Code blocks keep their language tag.
+fn reopening(text: &str) -> String {
+    let (open, fence_lang) = scan(text);
+    let mut out = String::new();
+    for marker in open {
+        out.push_str(marker.token());
+        if marker == Marker::Fence {
+            out.push_str(fence_lang);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// The first `n` characters of `text`.
+fn char_prefix(text: &str, n: usize) -> &str {
+    match text.char_indices().nth(n) {
+        Some((i, _)) => &text[..i],
+        None => text,
+    }
+}
+
+/// Shortens `text` to at most `max` characters, cutting on a character
+/// boundary (at a word break when there's one near the end), adding an
+/// ellipsis and closing any code block or formatting left open.
+pub fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut budget = max.saturating_sub(ELLIPSIS.chars().count());
+    loop {
+        let mut cut = char_prefix(text, budget);
+        if let Some(i) = cut.rfind(char::is_whitespace) {
+            if i >= cut.len() * 4 / 5 {
+                cut = cut[..i].trim_end();
+            }
+        }
+        let out = format!("{}{}{}", cut, ELLIPSIS, closing(cut));
+        let len = out.chars().count();
+        if len <= max || budget == 0 {
+            return if len <= max { out } else { String::new() };
+        }
+        budget = budget.saturating_sub(len - max);
+    }
+}
+
+/// Splits `text` into messages of at most `max` characters, preferring line
+/// breaks, then word breaks. Formatting open at a split is closed at the end of
+/// one message and reopened at the start of the next; a code block reopens with
+/// its language tag so highlighting carries on.
+pub fn split(text: &str, max: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut prefix = String::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let mut budget = max.saturating_sub(prefix.chars().count());
+        let chunk = loop {
+            if budget == 0 {
+                // Formatting alone doesn't fit; fall back to plain cuts
+                prefix.clear();
+                let take = char_prefix(rest, max.max(1));
+                rest = &rest[take.len()..];
+                break take.to_string();
+            }
+            let mut take = char_prefix(rest, budget);
+            let mut next = &rest[take.len()..];
+            if !next.is_empty() {
+                let boundary = take
+                    .rfind('\n')
+                    .filter(|&i| i >= take.len() / 2)
+                    .or_else(|| take.rfind(' ').filter(|&i| i >= take.len() / 2));
+                if let Some(i) = boundary {
+                    // The separator itself is dropped
+                    next = &rest[i + 1..];
+                    take = &take[..i];
+                }
+            }
+            let body = format!("{}{}", prefix, take);
+            let close = if next.is_empty() { String::new() } else { closing(&body) };
+            let chunk = format!("{}{}", body, close);
+            let len = chunk.chars().count();
+            // A closing token can run into a marker at the cut (`*` + `**`), so
+            // check the result really is balanced
+            if len <= max && (next.is_empty() || open_markers(&chunk).is_empty()) {
+                prefix = reopening(&body);
+                rest = next;
+                break chunk;
+            }
+            budget = budget.saturating_sub(len.saturating_sub(max).max(1));
+        };
+        if !chunk.trim().is_empty() {
+            chunks.push(chunk);
+        }
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_short_text_is_unchanged() {
+        assert_eq!(truncate("hello **world**", 20), "hello **world**");
+    }
+
+    #[test]
+    fn test_truncate_respects_char_boundaries() {
+        let text = "é".repeat(3000);
+        let out = truncate(&text, DISCORD_MESSAGE_MAX);
+        assert_eq!(out.chars().count(), DISCORD_MESSAGE_MAX);
+        assert!(out.ends_with(ELLIPSIS));
+    }
+
+    #[test]
+    fn test_truncate_closes_markdown() {
+        assert_eq!(truncate("**bold text that goes on and on", 16), "**bold text…**");
+        assert_eq!(truncate("```rust\nfn main() {}\nfn other() {}\n```", 30), "```rust\nfn main() {}\nfn…\n```");
+        assert_eq!(truncate("`code` then ||a spoiler that runs long||", 30), "`code` then ||a spoiler…||");
+        // Nothing inside code counts as formatting
+        assert_eq!(truncate("`a ** b` and more words here", 14), "`a ** b` and…");
+        // Escaped markers aren't formatting either
+        assert_eq!(truncate("\\*not italic\\* and more words here", 22), "\\*not italic\\* and…");
+    }
+
+    #[test]
+    fn test_open_markers() {
+        assert_eq!(open_markers("***both"), [Marker::Bold, Marker::Italic]);
+        assert_eq!(open_markers("**done** ~~open"), [Marker::Strike]);
+        assert_eq!(open_markers("```\n**not bold"), [Marker::Fence]);
+        assert!(open_markers("__a__ `b` ||c||").is_empty());
+    }
+
+    #[test]
+    fn test_split_reopens_code_blocks() {
+        let code: String = (0..40).map(|i| format!("let x{} = {};\n", i, i)).collect();
+        let text = format!("Here you go:\n```rust\n{}```\nDone.", code);
+        let chunks = split(&text, 200);
+        assert!(chunks.len() > 2);
+        assert!(chunks[0].starts_with("Here you go:\n```rust\n"));
+        for chunk in &chunks[..chunks.len() - 1] {
+            assert!(chunk.ends_with("\n```"), "{:?}", chunk);
+        }
+        for chunk in &chunks[1..] {
+            assert!(chunk.starts_with("```rust\n"), "{:?}", chunk);
+        }
+        assert!(chunks.last().unwrap().ends_with("```\nDone."));
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(split("short", 2000), ["short"]);
+        assert!(split("", 2000).is_empty());
+        assert_eq!(split("one two three four", 9), ["one two", "three", "four"]);
+        assert_eq!(split("**bold words here**", 12), ["**bold**", "**words**", "**here**"]);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_split_fits_and_balances(text in "[a-z *_~|`\n]{0,600}", max in 20usize..200) {
+            let chunks = split(&text, max);
+            for chunk in &chunks {
+                prop_assert!(chunk.chars().count() <= max);
+            }
+            // Only the last chunk may leave open what the text itself left open
+            for chunk in chunks.iter().rev().skip(1) {
+                prop_assert!(open_markers(chunk).is_empty(), "unbalanced: {:?}", chunk);
+            }
+        }
+
+        #[test]
+        fn prop_split_keeps_short_text(text in "[a-zé *_~|`\\\\\n]{1,200}") {
+            prop_assume!(!text.trim().is_empty());
+            prop_assert_eq!(split(&text, DISCORD_MESSAGE_MAX), vec![text]);
+        }
+
+        #[test]
+        fn prop_truncate_fits(text in "[a-zé *_~|`\\\\\n]{0,300}", max in 20usize..200) {
+            let out = truncate(&text, max);
+            prop_assert!(out.chars().count() <= max);
+        }
+
+        #[test]
+        fn prop_truncate_keeps_a_prefix(text in "[a-zé *_~|`\\\\\n]{0,300}", max in 20usize..200) {
+            let out = truncate(&text, max);
+            if text.chars().count() <= max {
+                prop_assert_eq!(&out, &text);
+            } else {
+                let (kept, _) = out.split_once(ELLIPSIS).unwrap();
+                prop_assert!(text.starts_with(kept));
+            }
+        }
+
+        #[test]
+        fn prop_truncate_balances_markdown(text in "[a-z *_~|`\n]{0,300}", max in 20usize..200) {
+            let out = truncate(&text, max);
+            if text.chars().count() > max {
+                prop_assert!(open_markers(&out).is_empty(), "unbalanced: {:?}", out);
+            }
+        }
+    }
+}
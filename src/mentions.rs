@@ -0,0 +1,90 @@
+use regex::Regex;
+use serenity::builder::CreateAllowedMentions;
+use serenity::model::id::{GuildId, UserId};
+use std::sync::OnceLock;
+
+use crate::{db, Handler};
+
+/// Breaks mention syntax without visibly changing it.
+const ZERO_WIDTH_SPACE: &str = "\u{200B}";
+
+fn everyone_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"@(everyone|here)").unwrap())
+}
+
+fn mention_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"<@([!&]?)(\d+)>").unwrap())
+}
+
+/// Escapes `@everyone`, `@here` and role mentions in text the bot didn't write
+/// itself, plus user mentions unless `allow_users` is set.
+pub fn sanitize(text: &str, allow_users: bool) -> String {
+    let text = everyone_pattern().replace_all(text, format!("@{}$1", ZERO_WIDTH_SPACE));
+    mention_pattern()
+        .replace_all(&text, |caps: &regex::Captures| {
+            if allow_users && &caps[1] != "&" {
+                caps[0].to_string()
+            } else {
+                format!("<@{}{}{}>", ZERO_WIDTH_SPACE, &caps[1], &caps[2])
+            }
+        })
+        .into_owned()
+}
+
+/// Who a message may ping: nobody, or users only. Escaping alone isn't enough,
+/// since Discord also pings for mentions spelled in ways we don't catch.
+pub fn allowed(allow_users: bool) -> CreateAllowedMentions {
+    CreateAllowedMentions::new().all_users(allow_users)
+}
+
+/// `content` without mentions of the bot, `<@id>` or `<@!id>`.
+pub fn strip_mention(content: &str, bot_id: UserId) -> String {
+    content
+        .replace(&format!("<@{}>", bot_id), "")
+        .replace(&format!("<@!{}>", bot_id), "")
+        .trim()
+        .to_string()
+}
+
+impl Handler {
+    /// Whether LLM output may ping users in `guild_id` (`!mentions allow`).
+    /// Never in DMs, where there's nobody else to ping.
+    pub(crate) async fn allows_user_mentions(&self, guild_id: Option<GuildId>) -> bool {
+        let Some(guild_id) = guild_id else {
+            return false;
+        };
+        let conn = self.db.lock().await;
+        db::allows_user_mentions(&conn, &guild_id.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_mention() {
+        let me = UserId::new(42);
+        assert_eq!(strip_mention("<@42> hi there", me), "hi there");
+        assert_eq!(strip_mention("hi <@!42>, 2 > 1?", me), "hi , 2 > 1?");
+        assert_eq!(strip_mention("<@7> hi", me), "<@7> hi");
+    }
+
+    #[test]
+    fn test_sanitize_everyone() {
+        let out = sanitize("hey @everyone and @here", true);
+        assert_eq!(out, "hey @\u{200B}everyone and @\u{200B}here");
+        assert!(!everyone_pattern().is_match(&out));
+    }
+
+    #[test]
+    fn test_sanitize_mentions() {
+        assert_eq!(sanitize("hi <@123> and <@!456>", false), "hi <@\u{200B}123> and <@\u{200B}!456>");
+        assert_eq!(sanitize("hi <@123> and <@!456>", true), "hi <@123> and <@!456>");
+        // Roles are never allowed
+        assert_eq!(sanitize("<@&789>", true), "<@\u{200B}&789>");
+        assert_eq!(sanitize("email me@example.com", false), "email me@example.com");
+    }
+}
@@ -0,0 +1,242 @@
+use serenity::async_trait;
+use serenity::model::channel::Message;
+use serenity::model::id::{MessageId, UserId};
+use serenity::prelude::Context;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+
+use crate::args::Args;
+use crate::Handler;
+
+/// How many recent message IDs each bot remembers to spot redeliveries.
+const DEDUP_CAPACITY: usize = 1000;
+/// The same message this many times in a row within [`SPAM_WINDOW`] is spam.
+const SPAM_REPEATS: u32 = 3;
+const SPAM_WINDOW: Duration = Duration::from_secs(30);
+/// Commands a user may run per [`RATE_LIMIT_WINDOW`].
+const RATE_LIMIT_COMMANDS: usize = 5;
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(10);
+
+/// Whether a message carries on down the chain.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Stop,
+}
+
+/// A step every message passes through before the modules see it. Middleware
+/// runs in order and any step can stop the message, replying itself if the
+/// user should hear why.
+#[async_trait]
+pub trait Middleware: Send + Sync {
+    async fn handle(&self, handler: &Handler, ctx: &Context, msg: &Message, command: &str, args: &Args) -> Flow;
+}
+
+/// Dedup → spam check → feature gate → rate limit. Command dispatch and
+/// the LLM fallback come after, as the modules.
+pub fn chain() -> Vec<Arc<dyn Middleware>> {
+    vec![
+        Arc::new(Dedup::default()),
+        Arc::new(SpamCheck::default()),
+        Arc::new(FeatureGate),
+        Arc::new(RateLimit::default()),
+    ]
+}
+
+/// Drops messages the gateway delivers again after a reconnect, so they
+/// aren't answered twice. The chain is shared by every bot identity, so each
+/// bot's deliveries are tracked apart.
+#[derive(Default)]
+pub struct Dedup {
+    /// Recently seen messages, and the same messages oldest first for eviction.
+    seen: Mutex<(HashSet<Delivery>, VecDeque<Delivery>)>,
+}
+
+/// A message as delivered to one bot: the bot's name (`None` for the primary)
+/// and the message ID.
+type Delivery = (Option<String>, MessageId);
+
+impl Dedup {
+    /// Records `message` as seen by `bot`; false if it already was.
+    fn first_sighting(&self, bot: Option<&str>, message: MessageId) -> bool {
+        let mut seen = self.seen.lock().unwrap();
+        let (ids, order) = &mut *seen;
+        let id = (bot.map(str::to_string), message);
+        if !ids.insert(id.clone()) {
+            return false;
+        }
+        order.push_back(id);
+        if order.len() > DEDUP_CAPACITY {
+            if let Some(oldest) = order.pop_front() {
+                ids.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+#[async_trait]
+impl Middleware for Dedup {
+    async fn handle(&self, handler: &Handler, _ctx: &Context, msg: &Message, _command: &str, _args: &Args) -> Flow {
+        if self.first_sighting(handler.identity.name.as_deref(), msg.id) {
+            return Flow::Continue;
+        }
+        info!("Ignoring redelivered message {}", msg.id);
+        Flow::Stop
+    }
+}
+
+/// Ignores a user repeating the same message over and over.
+#[derive(Default)]
+pub struct SpamCheck {
+    /// Each user's last message, how many times in a row they've sent it, and when.
+    last: Mutex<HashMap<UserId, (String, u32, Instant)>>,
+}
+
+impl SpamCheck {
+    fn is_spam(&self, user: UserId, content: &str, now: Instant) -> bool {
+        let mut last = self.last.lock().unwrap();
+        let entry = last.entry(user).or_insert_with(|| (String::new(), 0, now));
+        if entry.0 == content && now.duration_since(entry.2) < SPAM_WINDOW {
+            entry.1 += 1;
+        } else {
+            *entry = (content.to_string(), 1, now);
+        }
+        entry.2 = now;
+        entry.1 > SPAM_REPEATS
+    }
+}
+
+#[async_trait]
+impl Middleware for SpamCheck {
+    async fn handle(&self, _handler: &Handler, _ctx: &Context, msg: &Message, _command: &str, _args: &Args) -> Flow {
+        if self.is_spam(msg.author.id, &msg.content, Instant::now()) {
+            info!("Ignoring repeated message from {}", msg.author.name);
+            return Flow::Stop;
+        }
+        Flow::Continue
+    }
+}
+
+/// Refuses commands whose feature has been switched off in this guild.
+pub struct FeatureGate;
+
+#[async_trait]
+impl Middleware for FeatureGate {
+    async fn handle(&self, handler: &Handler, ctx: &Context, msg: &Message, command: &str, _args: &Args) -> Flow {
+        let Some(feature) = handler.disabled_feature(msg.guild_id, command).await else {
+            return Flow::Continue;
+        };
+        let response = format!("The **{}** feature is disabled in this server.", feature.name());
+        if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+            error!("Error sending message: {:?}", why);
+        }
+        Flow::Stop
+    }
+}
+
+/// Limits how fast one user can run commands. The first refused command gets
+/// a reply; the rest of the burst is dropped quietly.
+#[derive(Default)]
+pub struct RateLimit {
+    /// Each user's recent command times, oldest first, and whether they've been told to slow down.
+    recent: Mutex<HashMap<UserId, (VecDeque<Instant>, bool)>>,
+}
+
+enum Limit {
+    Allowed,
+    /// Refused; `true` the first time in a burst.
+    Refused(bool),
+}
+
+impl RateLimit {
+    fn check(&self, user: UserId, now: Instant) -> Limit {
+        let mut recent = self.recent.lock().unwrap();
+        let (times, warned) = recent.entry(user).or_default();
+        while times.front().is_some_and(|t| now.duration_since(*t) >= RATE_LIMIT_WINDOW) {
+            times.pop_front();
+        }
+        if times.len() < RATE_LIMIT_COMMANDS {
+            times.push_back(now);
+            *warned = false;
+            return Limit::Allowed;
+        }
+        Limit::Refused(!std::mem::replace(warned, true))
+    }
+}
+
+#[async_trait]
+impl Middleware for RateLimit {
+    async fn handle(&self, _handler: &Handler, ctx: &Context, msg: &Message, command: &str, _args: &Args) -> Flow {
+        if command.is_empty() {
+            return Flow::Continue;
+        }
+        match self.check(msg.author.id, Instant::now()) {
+            Limit::Allowed => Flow::Continue,
+            Limit::Refused(first) => {
+                if first {
+                    let response = "Slow down — too many commands. Try again in a few seconds.";
+                    if let Err(why) = msg.channel_id.say(&ctx.http, response).await {
+                        error!("Error sending message: {:?}", why);
+                    }
+                }
+                Flow::Stop
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedup() {
+        let dedup = Dedup::default();
+        assert!(dedup.first_sighting(None, MessageId::new(1)));
+        assert!(!dedup.first_sighting(None, MessageId::new(1)));
+        // Another bot sees the same message separately
+        assert!(dedup.first_sighting(Some("helpful"), MessageId::new(1)));
+
+        // The oldest IDs are forgotten once full
+        for id in 2..=DEDUP_CAPACITY as u64 + 1 {
+            dedup.first_sighting(None, MessageId::new(id));
+        }
+        assert!(dedup.first_sighting(None, MessageId::new(1)));
+    }
+
+    #[test]
+    fn test_spam_check() {
+        let check = SpamCheck::default();
+        let user = UserId::new(1);
+        let start = Instant::now();
+        for i in 0..SPAM_REPEATS {
+            assert!(!check.is_spam(user, "hi", start + Duration::from_secs(i as u64)));
+        }
+        assert!(check.is_spam(user, "hi", start + Duration::from_secs(5)));
+        // Other users and other messages aren't affected
+        assert!(!check.is_spam(UserId::new(2), "hi", start + Duration::from_secs(5)));
+        assert!(!check.is_spam(user, "something else", start + Duration::from_secs(6)));
+        // Repeats spread out over time are fine
+        let later = start + SPAM_WINDOW * 2;
+        for i in 0..SPAM_REPEATS * 2 {
+            assert!(!check.is_spam(user, "gm", later + SPAM_WINDOW * i));
+        }
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let limit = RateLimit::default();
+        let user = UserId::new(1);
+        let start = Instant::now();
+        for _ in 0..RATE_LIMIT_COMMANDS {
+            assert!(matches!(limit.check(user, start), Limit::Allowed));
+        }
+        assert!(matches!(limit.check(user, start), Limit::Refused(true)));
+        assert!(matches!(limit.check(user, start), Limit::Refused(false)));
+        assert!(matches!(limit.check(UserId::new(2), start), Limit::Allowed));
+        assert!(matches!(limit.check(user, start + RATE_LIMIT_WINDOW), Limit::Allowed));
+    }
+}
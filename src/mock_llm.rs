@@ -0,0 +1,124 @@
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use tracing::error;
+
+use crate::clients::ChatMessage;
+
+/// Dimensions of [`hashed_embedding`] vectors.
+const EMBEDDING_DIMS: usize = 64;
+
+#[derive(serde::Deserialize)]
+struct ChatRequest {
+    messages: Vec<ChatMessage>,
+}
+
+#[derive(serde::Deserialize)]
+struct EmbeddingRequest {
+    input: String,
+}
+
+/// Starts a stand-in for llama.cpp on a free local port and returns its base URL.
+/// It answers `/v1/chat/completions` by echoing the last user message, so chat,
+/// history and truncation can be exercised without a model, and `/v1/embeddings`
+/// with [`hashed_embedding`].
+pub async fn spawn() -> std::io::Result<String> {
+    let listener = tokio::net::TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).await?;
+    let url = format!("http://{}", listener.local_addr()?);
+    let app = Router::new()
+        .route("/v1/models", get(models))
+        .route("/props", get(props))
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/v1/embeddings", post(embeddings));
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            error!("Mock LLM stopped: {}", e);
+        }
+    });
+    Ok(url)
+}
+
+async fn models() -> Json<Value> {
+    Json(json!({ "object": "list", "data": [{ "id": "mock", "object": "model" }] }))
+}
+
+async fn props() -> Json<Value> {
+    Json(json!({ "chat_template": "{% for message in messages %}<|im_start|>{{ message.role }}\n{{ message.content }}<|im_end|>\n{% endfor %}" }))
+}
+
+/// The reply to a conversation: the last user message without the word-cap
+/// reminder the bot appends, plus how much history came with it.
+fn echo(messages: &[ChatMessage]) -> String {
+    let last = messages
+        .iter()
+        .rev()
+        .find(|m| m.role == "user")
+        .map(|m| m.content.as_str())
+        .unwrap_or_default();
+    let last = last.split_once("\n(Reply in").map(|(text, _)| text).unwrap_or(last);
+    let history = messages.iter().filter(|m| m.role != "system").count().saturating_sub(1);
+    format!("[mock, {} earlier messages] {}", history, last)
+}
+
+async fn chat_completions(Json(request): Json<ChatRequest>) -> Json<Value> {
+    Json(json!({
+        "object": "chat.completion",
+        "model": "mock",
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": echo(&request.messages) },
+            "finish_reason": "stop",
+        }],
+    }))
+}
+
+/// A bag-of-words stand-in for a real embedding: each word counts towards a
+/// hashed dimension, so texts sharing words come out similar.
+pub fn hashed_embedding(text: &str) -> Vec<f32> {
+    let mut vector = vec![0.0; EMBEDDING_DIMS];
+    for word in text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+        let hash = word
+            .to_lowercase()
+            .bytes()
+            .fold(0u32, |h, b| h.wrapping_mul(31).wrapping_add(b as u32));
+        vector[hash as usize % EMBEDDING_DIMS] += 1.0;
+    }
+    vector
+}
+
+async fn embeddings(Json(request): Json<EmbeddingRequest>) -> Json<Value> {
+    Json(json!({
+        "object": "list",
+        "model": "mock",
+        "data": [{ "object": "embedding", "index": 0, "embedding": hashed_embedding(&request.input) }],
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat_template::ChatTemplate;
+    use crate::clients::{LlamaCpp, LlmClient};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_mock_server_echoes() {
+        let url = spawn().await.unwrap();
+        let llm = LlamaCpp::new(reqwest::Client::new(), url.clone());
+
+        assert_eq!(llm.probe(Duration::from_secs(5)).await, Ok(url));
+        assert_eq!(llm.detect_template().await, Ok(Some(ChatTemplate::ChatMl)));
+        let reply = llm
+            .complete(vec![
+                ChatMessage::new("system", "Be rude."),
+                ChatMessage::new("user", "hi"),
+                ChatMessage::new("assistant", "go away"),
+                ChatMessage::new("user", "please\n(Reply in 10 words or less. Stay in character.)"),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(reply, "[mock, 2 earlier messages] please");
+        assert_eq!(llm.embed("raid tonight").await, Ok(hashed_embedding("Raid tonight?")));
+    }
+}
@@ -0,0 +1,164 @@
+use serenity::async_trait;
+use serenity::http::Http;
+use serenity::model::channel::Message;
+use serenity::model::gateway::Ready;
+use serenity::model::guild::{Guild, Member};
+use serenity::model::voice::VoiceState;
+use serenity::prelude::Context;
+use std::sync::Arc;
+
+use crate::args::Args;
+use crate::commands::Usage;
+use crate::config::Config;
+use crate::Handler;
+
+mod confessions;
+mod counters;
+mod faq;
+mod invites;
+mod knowledge;
+mod llm_chat;
+mod moderation;
+mod profiles;
+mod quiet_hours;
+mod riot_tracker;
+mod roleplay;
+mod scripts;
+mod tags;
+mod temp_voice;
+mod tickets;
+mod todos;
+mod wow_tracker;
+
+pub use confessions::Confessions;
+pub use counters::Counters;
+pub use faq::Faq;
+pub use invites::InviteTracker;
+pub use knowledge::Knowledge;
+pub use llm_chat::LlmChat;
+pub use moderation::Moderation;
+pub use profiles::Profiles;
+pub use quiet_hours::QuietHours;
+pub use riot_tracker::RiotTracker;
+pub use roleplay::Roleplay;
+pub use scripts::Scripting;
+pub use tags::Tags;
+pub use temp_voice::TempVoice;
+pub use tickets::Tickets;
+pub use todos::Todos;
+pub use wow_tracker::WowTracker;
+
+/// A feature of the bot. Modules get every message in registration order and see
+/// the shared [`Handler`] for its database and API clients; the handler itself
+/// only parses commands, runs the [`crate::middleware`] chain and dispatches the
+/// standalone [`crate::commands`].
+#[async_trait]
+pub trait BotModule: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// How its commands are listed in `!help`, in order.
+    fn usage(&self) -> Vec<Usage> {
+        Vec::new()
+    }
+
+    /// Runs once at startup, before connecting to Discord. An error aborts startup.
+    async fn init(&self, _handler: &Handler) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn on_ready(&self, _handler: &Handler, _ctx: &Context, _ready: &Ready) {}
+
+    /// Handles a message, with `command` empty when it isn't a `!command`.
+    /// Returns whether it was handled, which stops later modules seeing it.
+    async fn on_message(&self, _handler: &Handler, _ctx: &Context, _msg: &Message, _command: &str, _args: &Args) -> bool {
+        false
+    }
+
+    /// Runs when someone joins, leaves or moves between voice channels.
+    async fn on_voice_state(&self, _handler: &Handler, _ctx: &Context, _state: &VoiceState) {}
+
+    /// Runs for each guild on connect, with who is in voice at the time, and
+    /// when the bot joins a new one.
+    async fn on_guild_create(&self, _handler: &Handler, _ctx: &Context, _guild: &Guild) {}
+
+    /// Runs when someone joins a guild. Only called with `TRACK_INVITES` set,
+    /// since it needs the privileged Server Members intent.
+    async fn on_member_join(&self, _handler: &Handler, _ctx: &Context, _member: &Member) {}
+
+    /// Runs on every scheduler tick (`POLL_INTERVAL_SECS`).
+    async fn on_tick(&self, _handler: &Handler, _http: &Arc<Http>) {}
+}
+
+/// The modules to run for `config`. Chat is last so commands that mention the
+/// bot still run as commands.
+pub fn registered(config: &Config) -> Vec<Arc<dyn BotModule>> {
+    let mut modules: Vec<Arc<dyn BotModule>> = vec![
+        Arc::new(Moderation),
+        Arc::new(Counters),
+        Arc::new(Tags),
+        Arc::new(Todos),
+        Arc::new(TempVoice),
+        Arc::new(QuietHours),
+        Arc::new(Confessions),
+        Arc::new(Tickets),
+        Arc::new(WowTracker),
+        Arc::new(RiotTracker),
+        Arc::new(Profiles),
+        Arc::new(Scripting),
+    ];
+    if config.track_invites {
+        modules.push(Arc::new(InviteTracker));
+    }
+    if config.llama_api_url.is_some() {
+        modules.push(Arc::new(Faq));
+        modules.push(Arc::new(Knowledge));
+        modules.push(Arc::new(Roleplay));
+        modules.push(Arc::new(LlmChat));
+    }
+    modules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::help::Category;
+
+    fn names(vars: &[(&str, &str)]) -> Vec<&'static str> {
+        let config = Config::from_lookup(|name| {
+            vars.iter().find(|(k, _)| *k == name).map(|(_, v)| v.to_string())
+        })
+        .unwrap();
+        registered(&config).iter().map(|m| m.name()).collect()
+    }
+
+    #[test]
+    fn test_registered_modules() {
+        assert_eq!(names(&[("DISCORD_TOKEN", "abc")]), ["moderation", "counters", "tags", "todos", "tempvoice", "quiethours", "confessions", "tickets", "wow", "lol", "profiles", "scripts"]);
+        assert_eq!(
+            names(&[("DISCORD_TOKEN", "abc"), ("LLAMA_API_URL", "http://localhost:8080")]),
+            ["moderation", "counters", "tags", "todos", "tempvoice", "quiethours", "confessions", "tickets", "wow", "lol", "profiles", "scripts", "faq", "knowledge", "roleplay", "chat"]
+        );
+        assert_eq!(names(&[("DISCORD_TOKEN", "abc"), ("TRACK_INVITES", "1")]).last(), Some(&"invites"));
+    }
+
+    #[test]
+    fn test_module_usage() {
+        let config = Config::from_lookup(|name| match name {
+            "DISCORD_TOKEN" => Some("abc".to_string()),
+            "LLAMA_API_URL" => Some("http://localhost:8080".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        let usages: Vec<Usage> = registered(&config).iter().flat_map(|m| m.usage()).collect();
+        for category in Category::ALL {
+            let lines: Vec<String> = usages.iter().filter(|u| u.category == category).map(|u| u.line()).collect();
+            assert!(!lines.is_empty());
+            // Discord's limit on an embed description
+            assert!(lines.join("\n").chars().count() < 4096);
+        }
+        // Registered commands are listed from the registry, not again here
+        for command in crate::commands::REGISTRY {
+            assert!(usages.iter().all(|u| u.syntax.split(' ').next() != Some(command.name())), "{}", command.name());
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use chrono::DateTime;
+use rusqlite::Connection;
+use serenity::async_trait;
+use serenity::builder::{CreateAllowedMentions, CreateMessage};
+use serenity::model::channel::Message;
+use serenity::model::id::{ChannelId, GuildId};
+use serenity::prelude::Context;
+use tracing::{error, info, warn};
+
+use super::BotModule;
+use crate::args::Args;
+use crate::commands::Usage;
+use crate::help::Category;
+use crate::scheduler::unix_now;
+use crate::{bots, db, mentions, Handler};
+
+/// How long a user waits between confessions.
+const CONFESS_COOLDOWN_SECS: i64 = 10 * 60;
+/// Longest confession accepted, leaving room for the header.
+const MAX_CONFESSION_CHARS: usize = 1500;
+
+const MODERATION_PROMPT: &str = "You screen anonymous messages before they are posted to a Discord server. \
+     Reply UNSAFE if the message harasses or threatens a real person, reveals someone's private information, \
+     or is hateful. Otherwise reply SAFE. Reply with one word only.";
+
+/// Anonymous confessions: `!confess <text>` in a DM is reposted without a name
+/// in the channel a server set with `!confessions here`. Who sent what is kept
+/// so the bot's owner can look it up with `!confession <number>` if one is abused.
+pub struct Confessions;
+
+/// Every server's confession channel.
+fn confession_channels(conn: &Connection) -> Vec<(GuildId, ChannelId)> {
+    let channels = match db::get_guilds_with_config(conn, "confession_channel") {
+        Ok(channels) => channels,
+        Err(e) => {
+            error!("Failed to load confession channels: {}", e);
+            return Vec::new();
+        }
+    };
+    channels
+        .into_iter()
+        .filter_map(|(guild, channel)| Some((GuildId::new(guild.parse().ok()?), ChannelId::new(channel.parse().ok()?))))
+        .collect()
+}
+
+impl Confessions {
+    async fn confess(&self, handler: &Handler, ctx: &Context, msg: &Message, text: &str) -> String {
+        if msg.guild_id.is_some() {
+            // Don't leave the would-be anonymous text sitting under their name
+            let _ = msg.delete(&ctx.http).await;
+            return "Confessions are anonymous, so send `!confess <text>` to me in a DM.".to_string();
+        }
+        if text.is_empty() {
+            return "Usage: `!confess <text>`".to_string();
+        }
+        if text.chars().count() > MAX_CONFESSION_CHARS {
+            return format!("That's too long; confessions can be up to {} characters.", MAX_CONFESSION_CHARS);
+        }
+
+        let user_id = msg.author.id.to_string();
+        let channels = {
+            let conn = handler.db.lock().await;
+            if let Ok(Some(last)) = db::last_confession_at(&conn, &user_id) {
+                let wait = last + CONFESS_COOLDOWN_SECS - unix_now();
+                if wait > 0 {
+                    return format!("One confession at a time. Try again in {} minutes.", wait / 60 + 1);
+                }
+            }
+            confession_channels(&conn)
+        };
+        let (guild_id, channel_id, text) = match self.pick_server(ctx, msg, channels, text).await {
+            Ok(target) => target,
+            Err(response) => return response,
+        };
+        if handler.disabled_feature(Some(guild_id), "confess").await.is_some() {
+            return "Confessions are disabled on that server.".to_string();
+        }
+        if let Some(reason) = self.screen(handler, guild_id, text).await {
+            info!("Refused a confession from {}: {}", msg.author.name, reason);
+            return format!("I can't post that: {}.", reason);
+        }
+
+        let number = {
+            let conn = handler.db.lock().await;
+            match db::record_confession(&conn, &user_id, text) {
+                Ok(number) => number,
+                Err(e) => {
+                    error!("Failed to record confession: {}", e);
+                    return "Failed to save your confession.".to_string();
+                }
+            }
+        };
+        let message = CreateMessage::new()
+            .content(format!("📮 **Confession #{}**\n{}", number, mentions::sanitize(text, false)))
+            .allowed_mentions(CreateAllowedMentions::new());
+        match channel_id.send_message(&ctx.http, message).await {
+            Ok(_) => format!("Posted anonymously as confession #{}.", number),
+            Err(why) => {
+                error!("Error sending message: {:?}", why);
+                "I couldn't post your confession.".to_string()
+            }
+        }
+    }
+
+    /// Which of the servers the author is in gets the confession. With several,
+    /// the confession starts with the server's number from the list sent back.
+    async fn pick_server<'a>(
+        &self,
+        ctx: &Context,
+        msg: &Message,
+        channels: Vec<(GuildId, ChannelId)>,
+        text: &'a str,
+    ) -> Result<(GuildId, ChannelId, &'a str), String> {
+        let mut servers = Vec::new();
+        for (guild_id, channel_id) in channels {
+            if guild_id.member(&ctx.http, msg.author.id).await.is_ok() {
+                servers.push((guild_id, channel_id));
+            }
+        }
+        match servers.as_slice() {
+            [] => Err("Confessions aren't set up in any server we share. An admin can run `!confessions here` in a channel.".to_string()),
+            [(guild_id, channel_id)] => Ok((*guild_id, *channel_id, text)),
+            _ => {
+                let picked = text
+                    .split_once(char::is_whitespace)
+                    .and_then(|(n, rest)| Some((n.parse::<usize>().ok()?.checked_sub(1)?, rest.trim())))
+                    .and_then(|(i, rest)| servers.get(i).map(|&(guild_id, channel_id)| (guild_id, channel_id, rest)));
+                if let Some(target) = picked {
+                    return Ok(target);
+                }
+                let mut response = String::from("We share several servers with confessions; start with the number of the one you mean:\n");
+                for (i, (guild_id, _)) in servers.iter().enumerate() {
+                    let name = guild_id.to_partial_guild(&ctx.http).await.map(|g| g.name).unwrap_or_else(|_| guild_id.to_string());
+                    response.push_str(&format!("  {}. {}\n", i + 1, name));
+                }
+                response.push_str("For example: `!confess 1 <text>`");
+                Err(response)
+            }
+        }
+    }
+
+    /// Why `text` can't be posted, if it can't: it hits the server's blocklist,
+    /// or the LLM, when there is one, flags it.
+    async fn screen(&self, handler: &Handler, guild_id: GuildId, text: &str) -> Option<&'static str> {
+        if handler.blocklist(Some(guild_id)).await.is_match(text) {
+            return Some("it has a blocked word in it");
+        }
+        handler.llm.as_ref()?;
+        match handler.query_llm_oneshot(MODERATION_PROMPT.to_string(), text.to_string()).await {
+            Ok(verdict) if verdict.trim().to_uppercase().starts_with("UNSAFE") => Some("it looks like it targets someone"),
+            Ok(_) => None,
+            Err(e) => {
+                // The blocklist has already passed it
+                warn!("Couldn't screen a confession with the LLM: {}", e);
+                None
+            }
+        }
+    }
+
+    async fn set_channel(&self, handler: &Handler, ctx: &Context, msg: &Message, args: &Args) -> String {
+        let Some(guild_id) = msg.guild_id else {
+            return "Set the confession channel from inside a server.".to_string();
+        };
+        if !bots::can_manage_guild(&ctx.http, guild_id, msg.author.id).await && !bots::is_owner(&ctx.http, msg.author.id).await {
+            return "You need the Manage Server permission to set the confession channel.".to_string();
+        }
+        let conn = handler.db.lock().await;
+        let guild = guild_id.to_string();
+        let saved = match args.get(0) {
+            Some("here") => db::set_guild_config(&conn, &guild, "confession_channel", &msg.channel_id.to_string()),
+            Some("off") => db::delete_guild_config(&conn, &guild, "confession_channel").map(|_| ()),
+            _ => return "Usage: `!confessions here|off`".to_string(),
+        };
+        match saved {
+            Ok(_) => {
+                info!("{} set confessions {} in {}", msg.author.name, args.raw(), msg.channel_id);
+                if args.get(0) == Some("here") {
+                    "Anonymous confessions sent to me with `!confess` in a DM will be posted here.".to_string()
+                } else {
+                    "Confessions turned off.".to_string()
+                }
+            }
+            Err(e) => {
+                error!("Failed to save confession channel: {}", e);
+                "Failed to save confession channel.".to_string()
+            }
+        }
+    }
+
+    /// Who sent confession `args[0]`, for the bot's owner only.
+    async fn audit(&self, handler: &Handler, ctx: &Context, msg: &Message, args: &Args) -> String {
+        if !bots::is_owner(&ctx.http, msg.author.id).await {
+            return "Only the bot's owner can look up confessions.".to_string();
+        }
+        if msg.guild_id.is_some() {
+            return "Ask me in a DM so nobody else sees the answer.".to_string();
+        }
+        let Some(Ok(number)) = args.parsed::<i64>(0) else {
+            return "Usage: `!confession <number>`".to_string();
+        };
+        let conn = handler.db.lock().await;
+        match db::get_confession(&conn, number) {
+            Ok(Some(confession)) => {
+                info!("{} looked up the author of confession #{}", msg.author.name, number);
+                let when = DateTime::from_timestamp(confession.created_at, 0)
+                    .map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+                    .unwrap_or_default();
+                format!(
+                    "Confession #{} was sent by <@{}> ({}) at {}:\n{}",
+                    number,
+                    confession.user_id,
+                    confession.user_id,
+                    when,
+                    mentions::sanitize(&confession.content, false)
+                )
+            }
+            Ok(None) => format!("There's no confession #{}.", number),
+            Err(e) => {
+                error!("Failed to look up confession: {}", e);
+                "Failed to look up that confession.".to_string()
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl BotModule for Confessions {
+    fn name(&self) -> &'static str {
+        "confessions"
+    }
+
+    fn usage(&self) -> Vec<Usage> {
+        vec![
+            Usage::new(Category::Admin, "confessions here|off", "Post anonymous `!confess` messages in this channel (Manage Server)"),
+            Usage::new(Category::Admin, "confession <number>", "Who sent a confession (bot owner only, in a DM)"),
+            Usage::new(Category::Fun, "confess [server number] <text>", "DM me to post an anonymous confession"),
+        ]
+    }
+
+    async fn on_message(&self, handler: &Handler, ctx: &Context, msg: &Message, command: &str, args: &Args) -> bool {
+        let response = match command {
+            "confess" => self.confess(handler, ctx, msg, args.raw().trim()).await,
+            "confessions" => self.set_channel(handler, ctx, msg, args).await,
+            "confession" => self.audit(handler, ctx, msg, args).await,
+            _ => return false,
+        };
+        let message = CreateMessage::new()
+            .content(response)
+            .allowed_mentions(CreateAllowedMentions::new());
+        if let Err(why) = msg.channel_id.send_message(&ctx.http, message).await {
+            error!("Error sending message: {:?}", why);
+        }
+        true
+    }
+}
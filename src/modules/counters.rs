@@ -0,0 +1,133 @@
+use serenity::async_trait;
+use serenity::model::channel::Message;
+use serenity::prelude::Context;
+use tracing::{error, info};
+
+use super::BotModule;
+use crate::args::Args;
+use crate::commands::Usage;
+use crate::help::Category;
+use crate::{db, Handler};
+
+const USAGE: &str = "Usage: `!counter create|inc|reset|delete <name>`, `!counter inc <name> [amount]` or `!counter show [name]`";
+
+/// Longest counter name, so `!counter show` stays readable.
+const MAX_NAME_CHARS: usize = 32;
+
+/// Named per-server tallies: wipes, bad pulls, times someone stood in fire.
+pub struct Counters;
+
+#[async_trait]
+impl BotModule for Counters {
+    fn name(&self) -> &'static str {
+        "counters"
+    }
+
+    fn usage(&self) -> Vec<Usage> {
+        vec![
+            Usage::new(Category::Fun, "counter create|inc|reset|delete <name>", "Keep a tally of wipes, deaths or bad pulls (`!counter inc <name> <amount>` to add more)"),
+            Usage::new(Category::Fun, "counter show [name]", "This server's counters"),
+        ]
+    }
+
+    async fn on_message(&self, handler: &Handler, ctx: &Context, msg: &Message, command: &str, args: &Args) -> bool {
+        if command != "counter" && !command.starts_with("counter ") {
+            return false;
+        }
+        let response = counter_command(handler, msg, command, args).await;
+        if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+            error!("Error sending message: {:?}", why);
+        }
+        true
+    }
+}
+
+async fn counter_command(handler: &Handler, msg: &Message, command: &str, args: &Args) -> String {
+    let Some(guild_id) = msg.guild_id else {
+        return "Counters can only be used in a server.".to_string();
+    };
+    let guild = guild_id.to_string();
+    let name = args.get(0).unwrap_or_default();
+    match command {
+        "counter create" if !name.is_empty() => {
+            if name.chars().count() > MAX_NAME_CHARS {
+                return format!("Counter names can be up to {} characters.", MAX_NAME_CHARS);
+            }
+            let conn = handler.db.lock().await;
+            match db::create_counter(&conn, &guild, name, &msg.author.id.to_string()) {
+                Ok(true) => {
+                    info!("{} created counter {:?} in guild {}", msg.author.name, name, guild_id);
+                    format!("Created counter **{}**. Bump it with `!counter inc {}`.", name, name)
+                }
+                Ok(false) => format!("There's already a counter called **{}**.", name),
+                Err(e) => {
+                    error!("Failed to create counter: {}", e);
+                    "Failed to create counter.".to_string()
+                }
+            }
+        }
+        "counter inc" if !name.is_empty() => {
+            let amount = match args.parsed::<i64>(1) {
+                Some(Ok(amount)) => amount,
+                Some(Err(e)) => return e,
+                None => 1,
+            };
+            let conn = handler.db.lock().await;
+            match db::increment_counter(&conn, &guild, name, amount) {
+                Ok(Some(count)) => format!("**{}**: {}", name, count),
+                Ok(None) => format!("No counter called **{}**. Create it with `!counter create {}`.", name, name),
+                Err(e) => {
+                    error!("Failed to increment counter: {}", e);
+                    "Failed to update counter.".to_string()
+                }
+            }
+        }
+        "counter reset" if !name.is_empty() => {
+            let conn = handler.db.lock().await;
+            match db::reset_counter(&conn, &guild, name) {
+                Ok(true) => {
+                    info!("{} reset counter {:?} in guild {}", msg.author.name, name, guild_id);
+                    format!("**{}** is back to 0.", name)
+                }
+                Ok(false) => format!("No counter called **{}**.", name),
+                Err(e) => {
+                    error!("Failed to reset counter: {}", e);
+                    "Failed to reset counter.".to_string()
+                }
+            }
+        }
+        "counter delete" if !name.is_empty() => {
+            let conn = handler.db.lock().await;
+            match db::delete_counter(&conn, &guild, name) {
+                Ok(true) => {
+                    info!("{} deleted counter {:?} in guild {}", msg.author.name, name, guild_id);
+                    format!("Deleted counter **{}**.", name)
+                }
+                Ok(false) => format!("No counter called **{}**.", name),
+                Err(e) => {
+                    error!("Failed to delete counter: {}", e);
+                    "Failed to delete counter.".to_string()
+                }
+            }
+        }
+        "counter" | "counter show" => {
+            let conn = handler.db.lock().await;
+            match db::get_counters(&conn, &guild) {
+                Ok(counters) if counters.is_empty() => format!("No counters yet. {}", USAGE),
+                Ok(counters) if !name.is_empty() => match counters.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)) {
+                    Some((name, count)) => format!("**{}**: {}", name, count),
+                    None => format!("No counter called **{}**.", name),
+                },
+                Ok(counters) => {
+                    let lines: Vec<String> = counters.iter().map(|(name, count)| format!("  **{}**: {}", name, count)).collect();
+                    format!("**Counters**\n{}", lines.join("\n"))
+                }
+                Err(e) => {
+                    error!("Failed to load counters: {}", e);
+                    "Failed to load counters.".to_string()
+                }
+            }
+        }
+        _ => USAGE.to_string(),
+    }
+}
@@ -0,0 +1,198 @@
+use serenity::async_trait;
+use serenity::builder::{CreateAllowedMentions, CreateMessage};
+use serenity::model::channel::Message;
+use serenity::model::id::GuildId;
+use serenity::prelude::Context;
+use tracing::{error, info, warn};
+
+use super::BotModule;
+use crate::args::Args;
+use crate::commands::Usage;
+use crate::help::Category;
+use crate::knowledge::cosine;
+use crate::{db, markdown, mentions, Handler};
+
+/// How similar (cosine) a message must be to a FAQ question to get its answer.
+const FAQ_THRESHOLD: f32 = 0.85;
+const ADD_USAGE: &str = "Usage: `!faq add <question> | <answer>`";
+
+/// A knowledge base of canned answers: `!faq add` stores a question with its
+/// embedding, and questions asked in the server that mean the same thing get
+/// the stored answer without an LLM call.
+pub struct Faq;
+
+/// The entry whose question is closest to `embedding`, if it's over the threshold.
+fn best_match<'a>(embedding: &[f32], entries: &'a [db::FaqEntry]) -> Option<&'a db::FaqEntry> {
+    entries
+        .iter()
+        .map(|entry| (cosine(embedding, &entry.embedding), entry))
+        .filter(|(score, _)| *score >= FAQ_THRESHOLD)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, entry)| entry)
+}
+
+impl Faq {
+    async fn command(&self, handler: &Handler, msg: &Message, guild_id: GuildId, command: &str, args: &Args) -> String {
+        let guild = guild_id.to_string();
+        match command {
+            "faq add" => {
+                let Some((question, answer)) = args.raw().split_once('|') else {
+                    return ADD_USAGE.to_string();
+                };
+                let (question, answer) = (question.trim(), answer.trim());
+                if question.is_empty() || answer.is_empty() {
+                    return ADD_USAGE.to_string();
+                }
+                let Some(llm) = &handler.llm else {
+                    return "The FAQ needs an LLM for embeddings.".to_string();
+                };
+                let embedding = match llm.embed(question).await {
+                    Ok(embedding) => embedding,
+                    Err(e) => {
+                        error!("Failed to embed FAQ question: {}", e);
+                        return format!("I couldn't embed that question: {}", e);
+                    }
+                };
+                let conn = handler.db.lock().await;
+                match db::add_faq(&conn, &guild, question, answer, &embedding, &msg.author.name) {
+                    Ok(id) => {
+                        info!("{} added FAQ #{} in guild {}: {}", msg.author.name, id, guild_id, question);
+                        format!("Added FAQ #{}. I'll answer questions like that one for you.", id)
+                    }
+                    Err(e) => {
+                        error!("Failed to save FAQ: {}", e);
+                        "Failed to save FAQ.".to_string()
+                    }
+                }
+            }
+            "faq remove" => {
+                let Some(Ok(id)) = args.parsed::<i64>(0) else {
+                    return "Usage: `!faq remove <number>`".to_string();
+                };
+                let conn = handler.db.lock().await;
+                match db::remove_faq(&conn, &guild, id) {
+                    Ok(true) => {
+                        info!("{} removed FAQ #{} in guild {}", msg.author.name, id, guild_id);
+                        format!("Removed FAQ #{}.", id)
+                    }
+                    Ok(false) => format!("There's no FAQ #{}.", id),
+                    Err(e) => {
+                        error!("Failed to remove FAQ: {}", e);
+                        "Failed to remove FAQ.".to_string()
+                    }
+                }
+            }
+            "faq" | "faq list" => {
+                let conn = handler.db.lock().await;
+                match db::get_faqs(&conn, &guild) {
+                    Ok(entries) if entries.is_empty() => "No FAQs yet. Add one with `!faq add <question> | <answer>`.".to_string(),
+                    Ok(entries) => {
+                        let lines: Vec<String> = entries
+                            .iter()
+                            .map(|e| format!("**#{}** {} — {}", e.id, e.question, e.answer))
+                            .collect();
+                        markdown::truncate(&format!("**FAQ:**\n{}", lines.join("\n")), markdown::DISCORD_MESSAGE_MAX)
+                    }
+                    Err(e) => {
+                        error!("Failed to load FAQs: {}", e);
+                        "Failed to load FAQs.".to_string()
+                    }
+                }
+            }
+            _ => "Usage: `!faq add <question> | <answer>`, `!faq remove <number>` or `!faq list`".to_string(),
+        }
+    }
+
+    /// Answers `msg` from the FAQ if it closely matches a stored question.
+    async fn auto_answer(&self, handler: &Handler, ctx: &Context, msg: &Message, guild_id: GuildId) -> bool {
+        if !handler.identity.is_primary() || !msg.content.contains('?') {
+            return false;
+        }
+        if handler.disabled_feature(Some(guild_id), "faq").await.is_some() {
+            return false;
+        }
+        let entries = {
+            let conn = handler.db.lock().await;
+            match db::get_faqs(&conn, &guild_id.to_string()) {
+                Ok(entries) if !entries.is_empty() => entries,
+                _ => return false,
+            }
+        };
+        let Some(llm) = &handler.llm else {
+            return false;
+        };
+        let embedding = match llm.embed(&msg.content).await {
+            Ok(embedding) => embedding,
+            Err(e) => {
+                warn!("Failed to embed message for FAQ: {}", e);
+                return false;
+            }
+        };
+        let Some(entry) = best_match(&embedding, &entries) else {
+            return false;
+        };
+
+        info!("Answering {} from FAQ #{}", msg.author.name, entry.id);
+        let message = CreateMessage::new()
+            .content(mentions::sanitize(&entry.answer, false))
+            .reference_message(msg)
+            .allowed_mentions(CreateAllowedMentions::new());
+        if let Err(why) = msg.channel_id.send_message(&ctx.http, message).await {
+            error!("Error sending message: {:?}", why);
+        }
+        true
+    }
+}
+
+#[async_trait]
+impl BotModule for Faq {
+    fn name(&self) -> &'static str {
+        "faq"
+    }
+
+    fn usage(&self) -> Vec<Usage> {
+        vec![
+            Usage::new(Category::Chat, "faq add <question> | <answer>", "Answer questions like this one with a canned reply (`!faq list|remove <number>`)"),
+        ]
+    }
+
+    async fn on_message(&self, handler: &Handler, ctx: &Context, msg: &Message, command: &str, args: &Args) -> bool {
+        let Some(guild_id) = msg.guild_id else {
+            return false;
+        };
+        if command.is_empty() {
+            return self.auto_answer(handler, ctx, msg, guild_id).await;
+        }
+        if command != "faq" && !command.starts_with("faq ") {
+            return false;
+        }
+        let response = self.command(handler, msg, guild_id, command, args).await;
+        if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+            error!("Error sending message: {:?}", why);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_llm::hashed_embedding;
+
+    fn entry(id: i64, question: &str) -> db::FaqEntry {
+        db::FaqEntry {
+            id,
+            question: question.to_string(),
+            answer: String::new(),
+            embedding: hashed_embedding(question),
+        }
+    }
+
+    #[test]
+    fn test_best_match() {
+        let entries = [entry(1, "When is raid night?"), entry(2, "How do I apply to the guild?")];
+        let found = best_match(&hashed_embedding("when is raid night"), &entries);
+        assert_eq!(found.map(|e| e.id), Some(1));
+        assert!(best_match(&hashed_embedding("what's for dinner?"), &entries).is_none());
+    }
+}
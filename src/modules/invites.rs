@@ -0,0 +1,35 @@
+use serenity::async_trait;
+use serenity::model::gateway::Ready;
+use serenity::model::guild::Member;
+use serenity::prelude::Context;
+
+use super::BotModule;
+use crate::Handler;
+
+/// Invite tracking: remembers each invite's use count so a new member can be
+/// attributed to the invite (and inviter) they joined with. `!invites`
+/// reports on it.
+pub struct InviteTracker;
+
+#[async_trait]
+impl BotModule for InviteTracker {
+    fn name(&self) -> &'static str {
+        "invites"
+    }
+
+    async fn on_ready(&self, handler: &Handler, ctx: &Context, ready: &Ready) {
+        if !handler.identity.is_primary() {
+            return;
+        }
+        for guild in &ready.guilds {
+            handler.refresh_invites(&ctx.http, guild.id).await;
+        }
+    }
+
+    async fn on_member_join(&self, handler: &Handler, ctx: &Context, member: &Member) {
+        if !handler.identity.is_primary() || member.user.bot {
+            return;
+        }
+        handler.record_member_join(&ctx.http, member.guild_id, member.user.id).await;
+    }
+}
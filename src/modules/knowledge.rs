@@ -0,0 +1,145 @@
+use serenity::async_trait;
+use serenity::model::channel::{Attachment, Message};
+use serenity::model::id::GuildId;
+use serenity::prelude::Context;
+use tracing::{error, info};
+
+use super::BotModule;
+use crate::args::Args;
+use crate::commands::Usage;
+use crate::help::Category;
+use crate::{db, knowledge, markdown, Handler};
+
+/// Most chunks one document may have, so a huge upload can't tie up the LLM.
+const MAX_CHUNKS: usize = 500;
+
+/// Documents for retrieval-augmented answers: `!kb upload` chunks and embeds
+/// attached files, and chat pulls in the relevant parts (see
+/// [`Handler::add_knowledge`]).
+pub struct Knowledge;
+
+impl Knowledge {
+    /// Reads, chunks and embeds one attachment, returning how many chunks it made.
+    async fn ingest(&self, handler: &Handler, guild_id: GuildId, attachment: &Attachment, uploader: &str) -> Result<usize, String> {
+        if attachment.size > knowledge::MAX_DOCUMENT_BYTES {
+            return Err(format!(
+                "**{}** is too big; documents can be up to {} MB.",
+                attachment.filename,
+                knowledge::MAX_DOCUMENT_BYTES / 1024 / 1024
+            ));
+        }
+        let bytes = attachment.download().await.map_err(|e| {
+            error!("Failed to download document: {:?}", e);
+            format!("Failed to download **{}**.", attachment.filename)
+        })?;
+        let text = knowledge::extract_text(&attachment.filename, &bytes).map_err(|e| format!("**{}**: {}", attachment.filename, e))?;
+        let chunks = knowledge::chunk(&text);
+        if chunks.is_empty() {
+            return Err(format!("**{}** has no text in it.", attachment.filename));
+        }
+        if chunks.len() > MAX_CHUNKS {
+            return Err(format!("**{}** is too long to index.", attachment.filename));
+        }
+
+        let Some(llm) = &handler.llm else {
+            return Err("The knowledge base needs an LLM for embeddings.".to_string());
+        };
+        let mut embedded = Vec::with_capacity(chunks.len());
+        for chunk in chunks {
+            let embedding = llm
+                .embed(&chunk)
+                .await
+                .map_err(|e| format!("I couldn't embed **{}**: {}", attachment.filename, e))?;
+            embedded.push((chunk, embedding));
+        }
+        let conn = handler.db.lock().await;
+        db::save_knowledge(&conn, &guild_id.to_string(), &attachment.filename, &embedded, uploader).map_err(|e| {
+            error!("Failed to save document: {}", e);
+            format!("Failed to save **{}**.", attachment.filename)
+        })?;
+        Ok(embedded.len())
+    }
+
+    async fn command(&self, handler: &Handler, ctx: &Context, msg: &Message, guild_id: GuildId, command: &str, args: &Args) -> String {
+        let guild = guild_id.to_string();
+        match command {
+            "kb upload" => {
+                if msg.attachments.is_empty() {
+                    return "Attach `.txt`, `.md` or `.pdf` files to `!kb upload`.".to_string();
+                }
+                let typing = msg.channel_id.start_typing(&ctx.http);
+                let mut lines = Vec::new();
+                for attachment in &msg.attachments {
+                    match self.ingest(handler, guild_id, attachment, &msg.author.name).await {
+                        Ok(chunks) => {
+                            info!("{} added {} to the knowledge base of guild {}", msg.author.name, attachment.filename, guild_id);
+                            lines.push(format!("Learned **{}** ({} sections).", attachment.filename, chunks));
+                        }
+                        Err(e) => lines.push(e),
+                    }
+                }
+                drop(typing);
+                lines.join("\n")
+            }
+            "kb remove" if !args.is_empty() => {
+                let conn = handler.db.lock().await;
+                match db::remove_knowledge_source(&conn, &guild, args.raw()) {
+                    Ok(true) => {
+                        info!("{} removed {} from the knowledge base of guild {}", msg.author.name, args.raw(), guild_id);
+                        format!("Forgot **{}**.", args.raw())
+                    }
+                    Ok(false) => format!("There's no document called **{}**.", args.raw()),
+                    Err(e) => {
+                        error!("Failed to remove document: {}", e);
+                        "Failed to remove the document.".to_string()
+                    }
+                }
+            }
+            "kb" | "kb list" => {
+                let conn = handler.db.lock().await;
+                match db::get_knowledge_sources(&conn, &guild) {
+                    Ok(sources) if sources.is_empty() => "No documents yet. Attach some to `!kb upload`.".to_string(),
+                    Ok(sources) => {
+                        let lines: Vec<String> = sources
+                            .iter()
+                            .map(|(source, chunks)| format!("• **{}** ({} sections)", source, chunks))
+                            .collect();
+                        markdown::truncate(&format!("**Documents:**\n{}", lines.join("\n")), markdown::DISCORD_MESSAGE_MAX)
+                    }
+                    Err(e) => {
+                        error!("Failed to load documents: {}", e);
+                        "Failed to load documents.".to_string()
+                    }
+                }
+            }
+            _ => "Usage: `!kb upload` with files attached, `!kb list` or `!kb remove <filename>`".to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl BotModule for Knowledge {
+    fn name(&self) -> &'static str {
+        "knowledge"
+    }
+
+    fn usage(&self) -> Vec<Usage> {
+        vec![
+            Usage::new(Category::Chat, "kb upload", "With `.txt`/`.md`/`.pdf` files attached: documents I read before answering (`!kb list|remove <filename>`)"),
+        ]
+    }
+
+    async fn on_message(&self, handler: &Handler, ctx: &Context, msg: &Message, command: &str, args: &Args) -> bool {
+        if command != "kb" && !command.starts_with("kb ") {
+            return false;
+        }
+        let response = match msg.guild_id {
+            Some(guild_id) => self.command(handler, ctx, msg, guild_id, command, args).await,
+            None => "Documents can only be added in a server.".to_string(),
+        };
+        if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+            error!("Error sending message: {:?}", why);
+        }
+        true
+    }
+}
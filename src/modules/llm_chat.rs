@@ -0,0 +1,1291 @@
+use chrono::Local;
+use serenity::async_trait;
+use serenity::builder::{CreateAttachment, CreateMessage};
+use serenity::http::Http;
+use serenity::model::channel::Message;
+use serenity::prelude::Context;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+use super::BotModule;
+use crate::args::Args;
+use crate::commands::Usage;
+use crate::help::Category;
+use crate::persona::{self, schedule, MAX_INTENSITY, MIN_INTENSITY};
+use crate::scheduler::unix_now;
+use crate::{bots, db, export, markdown, mentions, retry, trace, Handler};
+
+/// Newest messages kept per history in `!listen` channels, so overheard chatter
+/// doesn't pile up forever.
+const LISTEN_RETENTION: usize = 200;
+
+const LISTEN_USAGE: &str = "Usage: `!listen on|off`, or `!listen optout|optin` to choose whether I record you";
+
+const WAKEWORD_USAGE: &str = "Usage: `!wakeword add|remove <word>` or `!wakeword list`";
+
+const PERSONA_USAGE: &str = "Usage: `!persona import` with a character card attached, `!persona list`, \
+     `!persona use <name>`, `!persona remove <name>` or `!persona schedule`";
+const HELP_CHANNEL_USAGE: &str = "Usage: `!helpchannel on|off`";
+/// Least time between two unprompted answers in a `!helpchannel`.
+const HELP_CHANNEL_COOLDOWN_SECS: i64 = 60;
+/// What the LLM says instead of answering a help-channel question it isn't sure of.
+const UNSURE: &str = "UNSURE";
+const HELP_CHANNEL_NOTE: &str = "Someone asked this in the server's help channel without mentioning you. \
+     Answer helpfully and accurately. If you aren't confident you know the answer, reply with only the word UNSURE.";
+/// Exchanges `!transcript` exports by default, and at most.
+const DEFAULT_TRANSCRIPT_EXCHANGES: usize = 10;
+const MAX_TRANSCRIPT_EXCHANGES: usize = 100;
+/// How many saved exchanges `!memories` lists.
+const MEMORIES_SHOWN: usize = 10;
+const SCHEDULE_USAGE: &str = "Usage: `!persona schedule add <name> <MM-DD> <MM-DD>`, `!persona schedule rotate <name>`, \
+     `!persona schedule every <days>`, `!persona schedule remove <number>`, `!persona schedule announce` or `!persona schedule list`";
+
+/// Whether `content` has any of `words` as a whole word, ignoring case.
+fn contains_wake_word(content: &str, words: &[String]) -> bool {
+    content
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|token| words.iter().any(|word| word.eq_ignore_ascii_case(token)))
+}
+
+/// Whether `content` asks something, i.e. ends in a question mark.
+fn is_question(content: &str) -> bool {
+    content.trim_end().ends_with('?')
+}
+
+/// Whether the LLM declined a help-channel question with [`UNSURE`].
+fn is_unsure(reply: &str) -> bool {
+    reply.trim_start().to_uppercase().starts_with(UNSURE)
+}
+
+/// Tells the LLM which of its messages a Discord reply answers: the whole
+/// exchange when it was recorded, else just the message.
+fn reply_note(replied: &str, exchange: Option<(String, String)>) -> String {
+    match exchange {
+        Some((prompt, reply)) => format!(
+            "This message is a reply to your earlier answer. They had said: \"{}\" and you answered: \"{}\"",
+            prompt, reply
+        ),
+        None => format!("This message is a reply to your earlier message: \"{}\"", replied),
+    }
+}
+
+const MOVE_CONTEXT_USAGE: &str = "Usage: `!movecontext #channel [--copy]`";
+
+/// `!movecontext #channel`: carries the conversation here over to another
+/// channel in the same server, leaving a copy behind with `--copy`. The
+/// histories are whichever the author would talk in, given each channel's
+/// context mode.
+async fn move_context(handler: &Handler, ctx: &Context, msg: &Message, args: &Args) -> String {
+    let Some(target) = args.get(0).and_then(serenity::utils::parse_channel_mention) else {
+        return MOVE_CONTEXT_USAGE.to_string();
+    };
+    if target == msg.channel_id {
+        return "That's this channel.".to_string();
+    }
+    let same_server = match target.to_channel(&ctx.http).await {
+        Ok(channel) => msg.guild_id.is_some() && channel.guild().map(|c| c.guild_id) == msg.guild_id,
+        Err(_) => false,
+    };
+    if !same_server {
+        return "Conversations can only move to another channel in this server.".to_string();
+    }
+
+    let copy = args.flag("copy");
+    let user_id = msg.author.id.to_string();
+    let conn = handler.db.lock().await;
+    let from = handler.history_key(&conn, &msg.channel_id.to_string(), &user_id);
+    let to = handler.history_key(&conn, &target.to_string(), &user_id);
+    match db::transfer_messages(&conn, &from, &to, !copy) {
+        Ok(0) => "There's no conversation here to move.".to_string(),
+        Ok(n) => {
+            info!("{} {} {} messages from {} to {}", msg.author.name, if copy { "copied" } else { "moved" }, n, from, to);
+            format!("{} {} messages to <#{}>; carry on there.", if copy { "Copied" } else { "Moved" }, n, target)
+        }
+        Err(e) => {
+            error!("Failed to move context: {}", e);
+            "Failed to move the conversation.".to_string()
+        }
+    }
+}
+
+const CHECKPOINT_USAGE: &str = "Usage: `!checkpoint save|load|delete <name>` or `!checkpoint list`";
+
+/// `!checkpoint`: named save points of the conversation here, to restore
+/// later, e.g. before a roleplay takes a turn.
+async fn checkpoint_command(handler: &Handler, msg: &Message, command: &str, args: &Args) -> String {
+    let name = args.raw();
+    let conn = handler.db.lock().await;
+    let key = handler.history_key(&conn, &msg.channel_id.to_string(), &msg.author.id.to_string());
+    match command {
+        "checkpoint save" if !name.is_empty() => match db::save_checkpoint(&conn, &key, name, &msg.author.id.to_string()) {
+            Ok(0) => "There's no conversation here to save.".to_string(),
+            Ok(n) => {
+                info!("{} saved checkpoint {:?} of {}", msg.author.name, name, key);
+                format!("Saved {} messages as **{}**. `!checkpoint load {}` to come back to it.", n, name, name)
+            }
+            Err(e) => {
+                error!("Failed to save checkpoint: {}", e);
+                "Failed to save the checkpoint.".to_string()
+            }
+        },
+        "checkpoint load" if !name.is_empty() => match db::load_checkpoint(&conn, &key, name) {
+            Ok(Some(n)) => {
+                info!("{} loaded checkpoint {:?} into {}", msg.author.name, name, key);
+                format!("Restored **{}** ({} messages). Picking up from there.", name, n)
+            }
+            Ok(None) => format!("No checkpoint called **{}** here. See `!checkpoint list`.", name),
+            Err(e) => {
+                error!("Failed to load checkpoint: {}", e);
+                "Failed to load the checkpoint.".to_string()
+            }
+        },
+        "checkpoint delete" if !name.is_empty() => match db::delete_checkpoint(&conn, &key, name) {
+            Ok(true) => format!("Deleted checkpoint **{}**.", name),
+            Ok(false) => format!("No checkpoint called **{}** here.", name),
+            Err(e) => {
+                error!("Failed to delete checkpoint: {}", e);
+                "Failed to delete the checkpoint.".to_string()
+            }
+        },
+        "checkpoint" | "checkpoint list" => match db::get_checkpoints(&conn, &key) {
+            Ok(checkpoints) if checkpoints.is_empty() => format!("No checkpoints here. {}", CHECKPOINT_USAGE),
+            Ok(checkpoints) => {
+                let lines: Vec<String> = checkpoints
+                    .iter()
+                    .map(|(name, saved_at)| format!("  **{}** — saved <t:{}:R>", name, saved_at))
+                    .collect();
+                format!("**Checkpoints**\n{}", lines.join("\n"))
+            }
+            Err(e) => {
+                error!("Failed to list checkpoints: {}", e);
+                "Failed to load checkpoints.".to_string()
+            }
+        },
+        _ => CHECKPOINT_USAGE.to_string(),
+    }
+}
+
+/// Chat with the LLM when mentioned, plus the prompt and history commands.
+pub struct LlmChat;
+
+impl LlmChat {
+    /// Answers `content` from `msg` with the LLM, as for a mention. `replied_to`
+    /// is the bot's own message when `msg` is a reply to it.
+    async fn chat(&self, handler: &Handler, ctx: &Context, msg: &Message, content: &str, replied_to: Option<&Message>) {
+        let mut conversation = {
+            let conn = handler.db.lock().await;
+            let nick = msg.member.as_ref().and_then(|m| m.nick.as_deref());
+            let mut conversation = handler.conversation(&conn, msg.guild_id, msg.channel_id, &msg.author, nick);
+            conversation.message_id = Some(msg.id);
+            // Already answered before a reconnect
+            if db::has_message(&conn, &conversation.key, &msg.id.to_string()).unwrap_or(false) {
+                info!("Ignoring already answered message {}", msg.id);
+                return;
+            }
+            if let Some(replied) = replied_to {
+                let exchange = db::get_exchange(&conn, &replied.id.to_string()).unwrap_or_default();
+                conversation.notes.push(reply_note(&replied.content, exchange));
+            }
+            conversation
+        };
+        info!("Received message from {}: {}", msg.author.name, msg.content);
+
+        // Show typing indicator while waiting for LLM
+        let typing = msg.channel_id.start_typing(&ctx.http);
+
+        handler.add_preamble(&ctx.http, &mut conversation).await;
+        handler.add_channel_topic(&ctx.http, &mut conversation).await;
+        handler.add_knowledge(&mut conversation, content).await;
+        let result = handler.ask_llama(&conversation, content).await;
+        let response = match &result {
+            Ok(reply) => reply.clone(),
+            Err(e) => {
+                error!("LLM error: {}", e);
+                let request = retry::Request::chat(&conversation, content);
+                if handler.defer(msg.guild_id, msg.channel_id, msg.author.id, &request).await {
+                    request.queued_reply().to_string()
+                } else {
+                    format!("Sorry, I couldn't get a response: {}{}", e, trace::error_ref())
+                }
+            }
+        };
+
+        drop(typing);
+
+        let allow_users = handler.allows_user_mentions(msg.guild_id).await;
+        let response = mentions::sanitize(&response, allow_users);
+        let mut sent = Vec::new();
+        for part in markdown::split(&response, markdown::DISCORD_MESSAGE_MAX) {
+            let message = CreateMessage::new()
+                .content(part)
+                .allowed_mentions(mentions::allowed(allow_users));
+            match msg.channel_id.send_message(&ctx.http, message).await {
+                Ok(message) => sent.push(message.id),
+                Err(why) => {
+                    error!("Error sending message: {:?}", why);
+                    break;
+                }
+            }
+        }
+        if let Ok(reply) = result {
+            handler.record_exchange(&conversation, content, &reply, &sent).await;
+        }
+    }
+
+    /// Whether `msg` contains one of its guild's wake words or was sent in the
+    /// channel picked as its default during onboarding. Only the primary bot
+    /// answers to them, so a channel with several bots gets one reply.
+    async fn wakes(&self, handler: &Handler, msg: &Message) -> bool {
+        let Some(guild_id) = msg.guild_id else {
+            return false;
+        };
+        if !handler.identity.is_primary() {
+            return false;
+        }
+        let conn = handler.db.lock().await;
+        let default_channel = db::get_guild_config(&conn, &guild_id.to_string(), "default_channel").unwrap_or_default();
+        if default_channel == Some(msg.channel_id.to_string()) {
+            return true;
+        }
+        let words = db::get_wake_words(&conn, &guild_id.to_string()).unwrap_or_default();
+        contains_wake_word(&msg.content, &words)
+    }
+
+    /// Answers a question in a `!helpchannel` without being mentioned, at most
+    /// once per [`HELP_CHANNEL_COOLDOWN_SECS`] and only when the LLM is
+    /// confident. Returns whether it answered.
+    async fn answer_question(&self, handler: &Handler, ctx: &Context, msg: &Message) -> bool {
+        if msg.guild_id.is_none() || !handler.identity.is_primary() || !is_question(&msg.content) {
+            return false;
+        }
+        let channel_id = msg.channel_id.to_string();
+        let mut conversation = {
+            let conn = handler.db.lock().await;
+            if !db::is_help_channel(&conn, &channel_id).unwrap_or(false) {
+                return false;
+            }
+            let answered_at = db::help_channel_answered_at(&conn, &channel_id).unwrap_or(0);
+            if unix_now() - answered_at < HELP_CHANNEL_COOLDOWN_SECS {
+                return false;
+            }
+            let nick = msg.member.as_ref().and_then(|m| m.nick.as_deref());
+            let mut conversation = handler.conversation(&conn, msg.guild_id, msg.channel_id, &msg.author, nick);
+            conversation.message_id = Some(msg.id);
+            conversation
+        };
+        if handler.disabled_feature(msg.guild_id, "chat").await.is_some() {
+            return false;
+        }
+
+        let question = msg.content.trim();
+        handler.add_preamble(&ctx.http, &mut conversation).await;
+        handler.add_channel_topic(&ctx.http, &mut conversation).await;
+        handler.add_knowledge(&mut conversation, question).await;
+        let mut system_prompt = {
+            let conn = handler.db.lock().await;
+            handler
+                .channel_system_prompt(&conn, msg.guild_id, msg.channel_id)
+                .unwrap_or_default()
+        };
+        for note in conversation.notes.iter().map(String::as_str).chain([HELP_CHANNEL_NOTE]) {
+            if !system_prompt.is_empty() {
+                system_prompt.push_str("\n\n");
+            }
+            system_prompt.push_str(note);
+        }
+        let reply = match handler.query_llm_oneshot(system_prompt, question.to_string()).await {
+            Ok(reply) => reply,
+            Err(e) => {
+                warn!("LLM error answering help-channel question: {}", e);
+                return false;
+            }
+        };
+        if is_unsure(&reply) {
+            info!("Not confident enough to answer {} in {}", msg.id, channel_id);
+            return false;
+        }
+        let reply = handler.blocklist(msg.guild_id).await.mask(&reply);
+        {
+            let conn = handler.db.lock().await;
+            if let Err(e) = db::set_help_channel_answered_at(&conn, &channel_id, unix_now()) {
+                error!("Failed to save help-channel answer time: {}", e);
+            }
+        }
+
+        let allow_users = handler.allows_user_mentions(msg.guild_id).await;
+        let message = CreateMessage::new()
+            .content(markdown::truncate(&mentions::sanitize(&reply, allow_users), markdown::DISCORD_MESSAGE_MAX))
+            .reference_message(msg)
+            .allowed_mentions(mentions::allowed(allow_users));
+        match msg.channel_id.send_message(&ctx.http, message).await {
+            Ok(sent) => handler.record_exchange(&conversation, question, &reply, &[sent.id]).await,
+            Err(why) => error!("Error sending message: {:?}", why),
+        }
+        true
+    }
+
+    /// Stores a message not addressed to the bot in a `!listen` channel, so
+    /// it's there as context the next time someone does talk to the bot.
+    async fn overhear(&self, handler: &Handler, msg: &Message) {
+        if msg.content.trim().is_empty() || handler.disabled_feature(msg.guild_id, "chat").await.is_some() {
+            return;
+        }
+        let channel_id = msg.channel_id.to_string();
+        let user_id = msg.author.id.to_string();
+        let conn = handler.db.lock().await;
+        let listening = db::is_listening(&conn, &channel_id).unwrap_or(false)
+            && !db::is_listen_opted_out(&conn, &user_id).unwrap_or(true);
+        if !listening {
+            return;
+        }
+        let key = handler.history_key(&conn, &channel_id, &user_id);
+        let speaker = bots::speaker_name(&msg.author, msg.member.as_ref().and_then(|m| m.nick.as_deref()));
+        let message_id = msg.id.to_string();
+        if let Err(e) = db::store_message_from(&conn, &key, "user", Some(&speaker), Some(&user_id), Some(&message_id), &msg.content)
+            .and_then(|_| db::prune_messages(&conn, &key, LISTEN_RETENTION))
+        {
+            error!("Failed to store overheard message: {}", e);
+        }
+    }
+
+    async fn wakeword_command(&self, handler: &Handler, msg: &Message, command: &str, args: &Args) -> String {
+        let Some(guild_id) = msg.guild_id else {
+            return "Wake words can only be set in a server.".to_string();
+        };
+        let guild = guild_id.to_string();
+        let word = args.raw();
+        match command {
+            "wakeword add" if !word.is_empty() => {
+                if !word.chars().all(char::is_alphanumeric) {
+                    return "A wake word is a single word of letters and numbers.".to_string();
+                }
+                let conn = handler.db.lock().await;
+                match db::add_wake_word(&conn, &guild, word, &msg.author.id.to_string()) {
+                    Ok(true) => {
+                        info!("{} added wake word {:?} in guild {}", msg.author.name, word, guild_id);
+                        format!("I'll answer messages that say **{}**.", word)
+                    }
+                    Ok(false) => format!("**{}** is already a wake word.", word),
+                    Err(e) => {
+                        error!("Failed to add wake word: {}", e);
+                        "Failed to save wake word.".to_string()
+                    }
+                }
+            }
+            "wakeword remove" if !word.is_empty() => {
+                let conn = handler.db.lock().await;
+                match db::remove_wake_word(&conn, &guild, word) {
+                    Ok(true) => {
+                        info!("{} removed wake word {:?} in guild {}", msg.author.name, word, guild_id);
+                        format!("**{}** is no longer a wake word.", word)
+                    }
+                    Ok(false) => format!("**{}** isn't a wake word.", word),
+                    Err(e) => {
+                        error!("Failed to remove wake word: {}", e);
+                        "Failed to remove wake word.".to_string()
+                    }
+                }
+            }
+            "wakeword" | "wakeword list" => {
+                let conn = handler.db.lock().await;
+                match db::get_wake_words(&conn, &guild) {
+                    Ok(words) if words.is_empty() => format!("No wake words; mention me to chat. {}", WAKEWORD_USAGE),
+                    Ok(words) => format!("**Wake words:** {}", words.join(", ")),
+                    Err(e) => {
+                        error!("Failed to load wake words: {}", e);
+                        "Failed to load wake words.".to_string()
+                    }
+                }
+            }
+            _ => WAKEWORD_USAGE.to_string(),
+        }
+    }
+
+    async fn persona_command(&self, handler: &Handler, ctx: &Context, msg: &Message, command: &str, args: &Args) -> String {
+        let name = args.raw();
+        match command {
+            "persona import" => {
+                let Some(attachment) = msg.attachments.first() else {
+                    return "Attach a character card (`.json` or `.png`) to `!persona import`.".to_string();
+                };
+                if attachment.size > persona::MAX_CARD_BYTES {
+                    return format!("That file is too big; cards can be up to {} MB.", persona::MAX_CARD_BYTES / 1024 / 1024);
+                }
+                let bytes = match attachment.download().await {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        error!("Failed to download character card: {:?}", e);
+                        return "Failed to download the card.".to_string();
+                    }
+                };
+                let card = match persona::card::parse(&bytes) {
+                    Ok(card) => card,
+                    Err(e) => return e,
+                };
+                let persona = persona::from_card(&card, &msg.author.id.to_string());
+                let conn = handler.db.lock().await;
+                match db::save_persona(&conn, &persona) {
+                    Ok(_) => {
+                        info!("{} imported persona {}", msg.author.name, persona.name);
+                        format!("Imported **{}**. Use `!persona use {}` to switch to it.", persona.name, persona.name)
+                    }
+                    Err(e) => {
+                        error!("Failed to save persona: {}", e);
+                        "Failed to save the persona.".to_string()
+                    }
+                }
+            }
+            "persona" | "persona list" => {
+                let conn = handler.db.lock().await;
+                let current = handler.guild_system_prompt(&conn, msg.guild_id).ok();
+                match db::get_personas(&conn) {
+                    Ok(personas) if personas.is_empty() => {
+                        "No personas yet. Import one with `!persona import`.".to_string()
+                    }
+                    Ok(personas) => {
+                        let mut response = String::from("**Personas:**\n");
+                        for p in personas {
+                            let in_use = current.as_deref() == Some(p.system_prompt.as_str());
+                            let marker = if in_use { " (in use)" } else { "" };
+                            response.push_str(&format!("- **{}**{}\n", p.name, marker));
+                        }
+                        response
+                    }
+                    Err(e) => {
+                        error!("Failed to list personas: {}", e);
+                        "Failed to list personas.".to_string()
+                    }
+                }
+            }
+            "persona use" if !name.is_empty() => {
+                let conn = handler.db.lock().await;
+                let persona = match db::get_persona(&conn, name) {
+                    Ok(Some(persona)) => persona,
+                    Ok(None) => return format!("No persona named **{}**. See `!persona list`.", name),
+                    Err(e) => {
+                        error!("Failed to load persona: {}", e);
+                        return "Failed to load the persona.".to_string();
+                    }
+                };
+                // In a server this picks its persona, as onboarding does
+                let switched = match msg.guild_id {
+                    Some(guild_id) => db::set_guild_config(&conn, &guild_id.to_string(), "persona", &persona.name),
+                    None => db::set_config(&conn, None, "system_prompt", &persona.system_prompt),
+                };
+                match switched {
+                    Ok(_) => {
+                        info!("{} switched persona to {}", msg.author.name, persona.name);
+                        match persona.first_message.as_str() {
+                            "" => format!("I'm **{}** now.", persona.name),
+                            greeting => greeting.to_string(),
+                        }
+                    }
+                    Err(e) => {
+                        error!("Failed to switch persona: {}", e);
+                        "Failed to switch persona.".to_string()
+                    }
+                }
+            }
+            "persona remove" if !name.is_empty() => {
+                let conn = handler.db.lock().await;
+                match db::remove_persona(&conn, name) {
+                    Ok(true) => {
+                        info!("{} removed persona {}", msg.author.name, name);
+                        format!("Removed persona **{}**. The current system prompt is unchanged.", name)
+                    }
+                    Ok(false) => format!("No persona named **{}**.", name),
+                    Err(e) => {
+                        error!("Failed to remove persona: {}", e);
+                        "Failed to remove the persona.".to_string()
+                    }
+                }
+            }
+            "persona schedule" => self.schedule_command(handler, ctx, msg, args).await,
+            _ => PERSONA_USAGE.to_string(),
+        }
+    }
+
+    async fn schedule_command(&self, handler: &Handler, ctx: &Context, msg: &Message, args: &Args) -> String {
+        // The schedule switches the bot-wide persona, so only the owner changes it
+        let listing = matches!(args.positional().first().map(String::as_str), None | Some("list"));
+        if !listing && !bots::is_owner(&ctx.http, msg.author.id).await {
+            return "Only the bot's owner can change the persona schedule.".to_string();
+        }
+        let conn = handler.db.lock().await;
+        let author = msg.author.name.as_str();
+        let scheduled = |name: &str| match db::get_persona(&conn, name) {
+            Ok(Some(persona)) => Ok(persona.name),
+            Ok(None) => Err(format!("No persona named **{}**. See `!persona list`.", name)),
+            Err(e) => {
+                error!("Failed to load persona: {}", e);
+                Err("Failed to load the persona.".to_string())
+            }
+        };
+        let saved = |result: rusqlite::Result<String>| {
+            result.unwrap_or_else(|e| {
+                error!("Failed to update persona schedule: {}", e);
+                "Failed to update the persona schedule.".to_string()
+            })
+        };
+        match args.positional() {
+            [sub, name, starts, ends] if sub == "add" => {
+                let name = match scheduled(name) {
+                    Ok(name) => name,
+                    Err(e) => return e,
+                };
+                let (Some(starts), Some(ends)) = (schedule::parse_month_day(starts), schedule::parse_month_day(ends)) else {
+                    return "Dates are `MM-DD`, like `10-01 10-31`.".to_string();
+                };
+                saved(db::add_persona_schedule(&conn, &name, Some(&starts), Some(&ends), author).map(|id| {
+                    info!("{} scheduled persona {} from {} to {}", author, name, starts, ends);
+                    format!("Scheduled **{}** from {} to {} every year (#{}).", name, starts, ends, id)
+                }))
+            }
+            [sub, name] if sub == "rotate" => {
+                let name = match scheduled(name) {
+                    Ok(name) => name,
+                    Err(e) => return e,
+                };
+                saved(db::add_persona_schedule(&conn, &name, None, None, author).map(|id| {
+                    info!("{} added persona {} to the rotation", author, name);
+                    format!("Added **{}** to the rotation (#{}).", name, id)
+                }))
+            }
+            [sub, days] if sub == "every" => match days.parse::<u32>() {
+                Ok(days) if days > 0 => saved(db::set_config(&conn, None, "persona_rotation_days", &days.to_string()).map(|_| {
+                    info!("{} set the persona rotation to {} days", author, days);
+                    format!("The rotation now moves on every {} days.", days)
+                })),
+                _ => "Give the rotation length as a number of days.".to_string(),
+            },
+            [sub, id] if sub == "remove" => match id.parse::<i64>() {
+                Ok(id) => match db::remove_persona_schedule(&conn, id) {
+                    Ok(true) => {
+                        info!("{} removed persona schedule entry {}", author, id);
+                        format!("Removed schedule entry #{}.", id)
+                    }
+                    Ok(false) => format!("There's no schedule entry #{}.", id),
+                    Err(e) => saved(Err(e)),
+                },
+                Err(_) => SCHEDULE_USAGE.to_string(),
+            },
+            [sub] if sub == "announce" => saved(db::set_config(&conn, None, "persona_announce_channel", &msg.channel_id.to_string()).map(|_| {
+                info!("{} set the persona announcement channel to {}", author, msg.channel_id);
+                "I'll announce scheduled persona changes here.".to_string()
+            })),
+            [] => self.describe_schedule(&conn),
+            [sub] if sub == "list" => self.describe_schedule(&conn),
+            _ => SCHEDULE_USAGE.to_string(),
+        }
+    }
+
+    fn describe_schedule(&self, conn: &rusqlite::Connection) -> String {
+        let entries = match db::get_persona_schedule(conn) {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("Failed to load persona schedule: {}", e);
+                return "Failed to load the persona schedule.".to_string();
+            }
+        };
+        if entries.is_empty() {
+            return format!("Nothing scheduled. {}", SCHEDULE_USAGE);
+        }
+        let days = db::get_config(conn, None, "persona_rotation_days")
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| schedule::DEFAULT_ROTATION_DAYS.to_string());
+        let mut response = String::from("**Persona schedule:**\n");
+        for entry in &entries {
+            match (&entry.starts, &entry.ends) {
+                (Some(starts), Some(ends)) => response.push_str(&format!("#{} **{}** from {} to {}\n", entry.id, entry.persona, starts, ends)),
+                _ => response.push_str(&format!("#{} **{}** in the {}-day rotation\n", entry.id, entry.persona, days)),
+            }
+        }
+        response
+    }
+}
+
+#[async_trait]
+impl BotModule for LlmChat {
+    fn name(&self) -> &'static str {
+        "chat"
+    }
+
+    fn usage(&self) -> Vec<Usage> {
+        vec![
+            Usage::new(Category::Chat, "", "Mention me (or use `/chat`) to chat, and reply to my messages to keep going!"),
+            Usage::new(Category::Chat, "wakeword add|remove <word>", "Answer messages with a word in them as if I was mentioned (`!wakeword list`)"),
+            Usage::new(Category::Chat, "", "Right-click a message → Apps → **Ask the bot** to ask about it"),
+            Usage::new(Category::Chat, "ask <question>", "One-off answer that ignores and skips the conversation history"),
+            Usage::new(Category::Chat, "clear", "Clear conversation history"),
+            Usage::new(Category::Chat, "movecontext #channel [--copy]", "Continue this conversation in another channel"),
+            Usage::new(Category::Chat, "checkpoint save|load <name>", "Save the conversation here and restore it later (`!checkpoint list`, `!checkpoint delete <name>`)"),
+            Usage::new(Category::Chat, "transcript [n]", "Download our last n exchanges as a Markdown file"),
+            Usage::new(Category::Chat, "", "React to my replies: 🔁 redo, 📌 save to `!memories`, 🗑️ delete, ❓ explain"),
+            Usage::new(Category::Chat, "", "Edit a message I answered in the last 10 minutes and I'll redo my reply"),
+            Usage::new(Category::Chat, "contextchannel", "Shared history per channel"),
+            Usage::new(Category::Chat, "contextuser", "Separate history per user"),
+            Usage::new(Category::Chat, "helpchannel on|off", "Answer questions here without being mentioned, when I'm sure (Manage Server)"),
+            Usage::new(Category::Chat, "listen on|off", "Remember the whole conversation here, not just messages to me (Manage Server; `!listen optout` to be left out)"),
+            Usage::new(Category::Chat, "intensity <1-10|off>", "How unhinged I am in this channel"),
+            Usage::new(Category::Chat, "style concise|verbose|emoji-heavy|off", "How I format replies to you, whatever the persona"),
+            Usage::new(Category::Admin, "systemprompt [text]", "View or set the system prompt for this server"),
+            Usage::new(Category::Admin, "/systemprompt edit", "Edit the system prompt in a form"),
+            Usage::new(Category::Admin, "persona import", "With a SillyTavern character card attached (JSON or PNG): import it"),
+            Usage::new(Category::Admin, "persona list|use <name>|remove <name>", "Manage imported personas"),
+            Usage::new(Category::Admin, "persona schedule add <name> <MM-DD> <MM-DD>|rotate <name>", "Switch personas on dates or in a rotation (`!persona schedule` for more; bot owner only)"),
+            Usage::new(Category::Admin, "safemode on|off", "Use a polite, neutral persona in this channel"),
+            Usage::new(Category::Admin, "cap <1-500>", "Set this server's response word cap"),
+            Usage::new(Category::Admin, "", "`/cap` and `/systemprompt show` reply privately unless `public` is set"),
+            Usage::new(Category::Admin, "preamble on|off", "Tell me the time and which server and channel I'm in"),
+            Usage::new(Category::Admin, "mentions [allow|block]", "Whether my replies can ping users (never `@everyone`)"),
+        ]
+    }
+
+    async fn on_tick(&self, handler: &Handler, http: &Arc<Http>) {
+        handler.apply_persona_schedule(http, Local::now().date_naive()).await;
+    }
+
+    async fn on_message(&self, handler: &Handler, ctx: &Context, msg: &Message, command: &str, args: &Args) -> bool {
+        if command == "systemprompt" {
+            let new_prompt = args.raw();
+            let guild = msg.guild_id.map(|g| g.to_string());
+            if new_prompt.is_empty() {
+                // Show current prompt
+                let conn = handler.db.lock().await;
+                let current = handler.guild_system_prompt(&conn, msg.guild_id).unwrap_or_default();
+                let response = format!("**Current system prompt:**\n{}", current);
+                if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                    error!("Error sending message: {:?}", why);
+                }
+            } else {
+                let conn = handler.db.lock().await;
+                match db::set_system_prompt(&conn, guild.as_deref(), new_prompt) {
+                    Ok(_) => {
+                        info!("{} updated system prompt to: {}", msg.author.name, new_prompt);
+                        if let Err(why) = msg.channel_id.say(&ctx.http, "System prompt updated!").await {
+                            error!("Error sending message: {:?}", why);
+                        }
+                    }
+                    Err(e) => {
+                        error!("Failed to update system prompt: {}", e);
+                        if let Err(why) = msg.channel_id.say(&ctx.http, "Failed to update system prompt.").await {
+                            error!("Error sending message: {:?}", why);
+                        }
+                    }
+                }
+            }
+            return true;
+        }
+
+        if command == "cap" {
+            let guild = msg.guild_id.map(|g| g.to_string());
+            if args.is_empty() {
+                let cap = {
+                    let conn = handler.db.lock().await;
+                    db::get_response_cap(&conn, guild.as_deref())
+                };
+                let response = format!("Response word cap is currently **{}**. Usage: `!cap <1-500>`", cap);
+                if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                    error!("Error sending message: {:?}", why);
+                }
+            } else {
+                match args.parsed::<u32>(0) {
+                    Some(Ok(n)) if (1..=db::MAX_RESPONSE_CAP).contains(&n) => {
+                        let conn = handler.db.lock().await;
+                        match db::set_config(&conn, guild.as_deref(), "response_cap", &n.to_string()) {
+                            Ok(_) => {
+                                info!("{} set response cap to {}", msg.author.name, n);
+                                let response = format!("Response word cap set to **{}**.", n);
+                                if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                                    error!("Error sending message: {:?}", why);
+                                }
+                            }
+                            Err(e) => {
+                                error!("Failed to set response cap: {}", e);
+                                if let Err(why) = msg.channel_id.say(&ctx.http, "Failed to save cap.").await {
+                                    error!("Error sending message: {:?}", why);
+                                }
+                            }
+                        }
+                    }
+                    _ => {
+                        if let Err(why) = msg.channel_id.say(&ctx.http, "Cap must be a number between 1 and 500.").await {
+                            error!("Error sending message: {:?}", why);
+                        }
+                    }
+                }
+            }
+            return true;
+        }
+
+        if command == "persona" || command.starts_with("persona ") {
+            let response = self.persona_command(handler, ctx, msg, command, args).await;
+            let response = markdown::truncate(&response, markdown::DISCORD_MESSAGE_MAX);
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "wakeword" || command.starts_with("wakeword ") {
+            let response = self.wakeword_command(handler, msg, command, args).await;
+            let response = markdown::truncate(&response, markdown::DISCORD_MESSAGE_MAX);
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "ask" {
+            let question = args.raw();
+            if question.is_empty() {
+                if let Err(why) = msg.channel_id.say(&ctx.http, "Usage: `!ask <question>`").await {
+                    error!("Error sending message: {:?}", why);
+                }
+                return true;
+            }
+
+            let typing = msg.channel_id.start_typing(&ctx.http);
+            // The channel's persona, but none of its history, and nothing stored
+            let system_prompt = {
+                let conn = handler.db.lock().await;
+                handler.channel_system_prompt(&conn, msg.guild_id, msg.channel_id)
+            };
+            let result = match system_prompt {
+                Ok(system_prompt) => handler.query_llm_oneshot(system_prompt, question.to_string()).await,
+                Err(e) => Err(format!("DB error: {}", e)),
+            };
+            let response = match result {
+                Ok(reply) => handler.blocklist(msg.guild_id).await.mask(&reply),
+                Err(e) => {
+                    error!("LLM error: {}", e);
+                    format!("Sorry, I couldn't get a response: {}{}", e, trace::error_ref())
+                }
+            };
+            drop(typing);
+
+            let allow_users = handler.allows_user_mentions(msg.guild_id).await;
+            let response = mentions::sanitize(&response, allow_users);
+            for part in markdown::split(&response, markdown::DISCORD_MESSAGE_MAX) {
+                let message = CreateMessage::new()
+                    .content(part)
+                    .allowed_mentions(mentions::allowed(allow_users));
+                if let Err(why) = msg.channel_id.send_message(&ctx.http, message).await {
+                    error!("Error sending message: {:?}", why);
+                    break;
+                }
+            }
+            return true;
+        }
+
+        if command == "intensity" {
+            let channel_id = msg.channel_id.to_string();
+            let conn = handler.db.lock().await;
+            let usage = format!("Usage: `!intensity <{}-{}|off>`", MIN_INTENSITY, MAX_INTENSITY);
+            let response = match args.get(0) {
+                None => match db::get_intensity(&conn, &channel_id) {
+                    Ok(Some(level)) => format!("Intensity here is **{}/{}**. {}", level, MAX_INTENSITY, usage),
+                    Ok(None) => format!("No intensity set here; the system prompt applies as is. {}", usage),
+                    Err(e) => {
+                        error!("Failed to read intensity: {}", e);
+                        "Failed to read intensity.".to_string()
+                    }
+                },
+                Some("off") => match db::set_intensity(&conn, &channel_id, None) {
+                    Ok(_) => "Intensity cleared for this channel.".to_string(),
+                    Err(e) => {
+                        error!("Failed to clear intensity: {}", e);
+                        "Failed to clear intensity.".to_string()
+                    }
+                },
+                Some(_) => match args.parsed::<u8>(0) {
+                    Some(Ok(level)) if (MIN_INTENSITY..=MAX_INTENSITY).contains(&level) => {
+                        match db::set_intensity(&conn, &channel_id, Some(level)) {
+                            Ok(_) => {
+                                info!("{} set intensity to {} in {}", msg.author.name, level, channel_id);
+                                format!(
+                                    "Intensity set to **{}/{}**: {}",
+                                    level,
+                                    MAX_INTENSITY,
+                                    persona::intensity_instruction(level)
+                                )
+                            }
+                            Err(e) => {
+                                error!("Failed to set intensity: {}", e);
+                                "Failed to save intensity.".to_string()
+                            }
+                        }
+                    }
+                    _ => usage,
+                },
+            };
+            drop(conn);
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "style" {
+            let user_id = msg.author.id.to_string();
+            let conn = handler.db.lock().await;
+            let names: Vec<_> = persona::ReplyStyle::ALL.iter().map(|s| s.name()).collect();
+            let usage = format!("Usage: `!style {}|off`", names.join("|"));
+            let response = match args.get(0) {
+                None => match db::get_reply_style(&conn, &user_id) {
+                    Ok(Some(style)) => format!("Your reply style is **{}**. {}", style, usage),
+                    Ok(None) => format!("You haven't picked a reply style. {}", usage),
+                    Err(e) => {
+                        error!("Failed to read reply style: {}", e);
+                        "Failed to read your reply style.".to_string()
+                    }
+                },
+                Some("off") => match db::set_reply_style(&conn, &user_id, None) {
+                    Ok(_) => "Reply style cleared; you'll get the usual replies.".to_string(),
+                    Err(e) => {
+                        error!("Failed to clear reply style: {}", e);
+                        "Failed to clear your reply style.".to_string()
+                    }
+                },
+                Some(name) => match persona::ReplyStyle::from_name(name) {
+                    Some(style) => match db::set_reply_style(&conn, &user_id, Some(style.name())) {
+                        Ok(_) => {
+                            info!("{} set their reply style to {}", msg.author.name, style.name());
+                            format!("Your replies will be **{}** from now on.", style.name())
+                        }
+                        Err(e) => {
+                            error!("Failed to set reply style: {}", e);
+                            "Failed to save your reply style.".to_string()
+                        }
+                    },
+                    None => usage,
+                },
+            };
+            drop(conn);
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "safemode" {
+            let channel_id = msg.channel_id.to_string();
+            let conn = handler.db.lock().await;
+            let response = match args.get(0) {
+                None => match db::is_safe_mode(&conn, &channel_id) {
+                    Ok(on) => format!(
+                        "Safe mode is **{}** here. Usage: `!safemode on|off`",
+                        if on { "on" } else { "off" }
+                    ),
+                    Err(e) => {
+                        error!("Failed to read safe mode: {}", e);
+                        "Failed to read safe mode.".to_string()
+                    }
+                },
+                Some(state @ ("on" | "off")) => match db::set_safe_mode(&conn, &channel_id, state == "on") {
+                    Ok(_) => {
+                        info!("{} turned safe mode {} in {}", msg.author.name, state, channel_id);
+                        if state == "on" {
+                            "Safe mode **on**: I'll be polite and helpful in this channel.".to_string()
+                        } else {
+                            "Safe mode **off**: back to my usual self.".to_string()
+                        }
+                    }
+                    Err(e) => {
+                        error!("Failed to set safe mode: {}", e);
+                        "Failed to save safe mode.".to_string()
+                    }
+                },
+                Some(_) => "Usage: `!safemode on|off`".to_string(),
+            };
+            drop(conn);
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "preamble" {
+            let Some(guild_id) = msg.guild_id else {
+                if let Err(why) = msg.channel_id.say(&ctx.http, "The preamble can only be configured in a server.").await {
+                    error!("Error sending message: {:?}", why);
+                }
+                return true;
+            };
+
+            let conn = handler.db.lock().await;
+            let response = match args.get(0) {
+                None => format!(
+                    "I'm {}told the time and which server and channel I'm in. Usage: `!preamble on|off`",
+                    if db::is_preamble_enabled(&conn, &guild_id.to_string()) { "" } else { "not " }
+                ),
+                Some(state @ ("on" | "off")) => {
+                    match db::set_preamble_enabled(&conn, &guild_id.to_string(), state == "on") {
+                        Ok(_) => {
+                            info!("{} turned the preamble {} in guild {}", msg.author.name, state, guild_id);
+                            format!("Time and server preamble turned **{}**.", state)
+                        }
+                        Err(e) => {
+                            error!("Failed to set preamble: {}", e);
+                            "Failed to save preamble setting.".to_string()
+                        }
+                    }
+                }
+                Some(_) => "Usage: `!preamble on|off`".to_string(),
+            };
+            drop(conn);
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "mentions" {
+            let Some(guild_id) = msg.guild_id else {
+                if let Err(why) = msg.channel_id.say(&ctx.http, "Mentions can only be configured in a server.").await {
+                    error!("Error sending message: {:?}", why);
+                }
+                return true;
+            };
+
+            let response = match args.positional() {
+                [] => {
+                    let allowed = handler.allows_user_mentions(Some(guild_id)).await;
+                    format!(
+                        "My replies {} ping users. `@everyone`, `@here` and roles are never pinged. \
+                         Usage: `!mentions allow|block`",
+                        if allowed { "can" } else { "can't" }
+                    )
+                }
+                [action] if action == "allow" || action == "block" => {
+                    let allow = action == "allow";
+                    let conn = handler.db.lock().await;
+                    match db::set_allow_user_mentions(&conn, &guild_id.to_string(), allow) {
+                        Ok(_) => {
+                            info!("{} set user mentions to {} in guild {}", msg.author.name, action, guild_id);
+                            if allow {
+                                "My replies can now ping users.".to_string()
+                            } else {
+                                "My replies will no longer ping anyone.".to_string()
+                            }
+                        }
+                        Err(e) => {
+                            error!("Failed to set mention setting: {}", e);
+                            "Failed to save mention setting.".to_string()
+                        }
+                    }
+                }
+                _ => "Usage: `!mentions [allow|block]`".to_string(),
+            };
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "listen" {
+            let channel_id = msg.channel_id.to_string();
+            let user_id = msg.author.id.to_string();
+            // Listening records everyone in the channel, so servers leave it to managers
+            let may_toggle = match (args.get(0), msg.guild_id) {
+                (Some("on" | "off"), Some(guild_id)) => bots::can_manage_guild(&ctx.http, guild_id, msg.author.id).await,
+                _ => true,
+            };
+            let conn = handler.db.lock().await;
+            let response = match args.get(0) {
+                None => match db::is_listening(&conn, &channel_id) {
+                    Ok(on) => format!(
+                        "Listening is **{}** here: I remember {}. {}",
+                        if on { "on" } else { "off" },
+                        if on { "the whole conversation" } else { "only messages to me" },
+                        LISTEN_USAGE
+                    ),
+                    Err(e) => {
+                        error!("Failed to read listen setting: {}", e);
+                        "Failed to read listen setting.".to_string()
+                    }
+                },
+                Some("on" | "off") if !may_toggle => "You need the Manage Server permission to turn listening on or off.".to_string(),
+                Some(state @ ("on" | "off")) => match db::set_listening(&conn, &channel_id, state == "on") {
+                    Ok(_) => {
+                        info!("{} turned listening {} in {}", msg.author.name, state, channel_id);
+                        if state == "on" {
+                            "Listening **on**: I'll remember the conversation here for context, \
+                             except from people who `!listen optout`."
+                                .to_string()
+                        } else {
+                            "Listening **off**: I'll only remember messages to me.".to_string()
+                        }
+                    }
+                    Err(e) => {
+                        error!("Failed to set listen setting: {}", e);
+                        "Failed to save listen setting.".to_string()
+                    }
+                },
+                Some(choice @ ("optout" | "optin")) => match db::set_listen_opt_out(&conn, &user_id, choice == "optout") {
+                    Ok(_) => {
+                        info!("{} chose {} for listening", msg.author.name, choice);
+                        if choice == "optout" {
+                            "Got it, I won't record your messages unless you talk to me.".to_string()
+                        } else {
+                            "Got it, listening channels will include your messages again.".to_string()
+                        }
+                    }
+                    Err(e) => {
+                        error!("Failed to set listen opt-out: {}", e);
+                        "Failed to save your choice.".to_string()
+                    }
+                },
+                Some(_) => LISTEN_USAGE.to_string(),
+            };
+            drop(conn);
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "helpchannel" {
+            let channel_id = msg.channel_id.to_string();
+            let may_toggle = match (args.get(0), msg.guild_id) {
+                (Some("on" | "off"), Some(guild_id)) => bots::can_manage_guild(&ctx.http, guild_id, msg.author.id).await,
+                _ => true,
+            };
+            let conn = handler.db.lock().await;
+            let response = match args.get(0) {
+                None => match db::is_help_channel(&conn, &channel_id) {
+                    Ok(on) => format!(
+                        "Help channel mode is **{}** here. {}",
+                        if on { "on" } else { "off" },
+                        HELP_CHANNEL_USAGE
+                    ),
+                    Err(e) => {
+                        error!("Failed to read help channel setting: {}", e);
+                        "Failed to read help channel setting.".to_string()
+                    }
+                },
+                Some("on" | "off") if !may_toggle => "You need the Manage Server permission to change help channel mode.".to_string(),
+                Some(state @ ("on" | "off")) => match db::set_help_channel(&conn, &channel_id, state == "on") {
+                    Ok(_) => {
+                        info!("{} turned help channel mode {} in {}", msg.author.name, state, channel_id);
+                        if state == "on" {
+                            "Help channel mode **on**: I'll answer questions here without being mentioned, \
+                             when I'm confident I know the answer."
+                                .to_string()
+                        } else {
+                            "Help channel mode **off**.".to_string()
+                        }
+                    }
+                    Err(e) => {
+                        error!("Failed to set help channel setting: {}", e);
+                        "Failed to save help channel setting.".to_string()
+                    }
+                },
+                Some(_) => HELP_CHANNEL_USAGE.to_string(),
+            };
+            drop(conn);
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "transcript" {
+            let exchanges = match args.parsed::<usize>(0) {
+                None => DEFAULT_TRANSCRIPT_EXCHANGES,
+                Some(Ok(n)) if (1..=MAX_TRANSCRIPT_EXCHANGES).contains(&n) => n,
+                Some(_) => {
+                    let response = format!("Usage: `!transcript [1-{}]`", MAX_TRANSCRIPT_EXCHANGES);
+                    if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                        error!("Error sending message: {:?}", why);
+                    }
+                    return true;
+                }
+            };
+            let messages = {
+                let conn = handler.db.lock().await;
+                let context_key = handler.history_key(&conn, &msg.channel_id.to_string(), &msg.author.id.to_string());
+                db::get_recent_messages(&conn, &context_key, exchanges * 2).unwrap_or_default()
+            };
+            let message = if messages.is_empty() {
+                CreateMessage::new().content("There's no conversation here to export.")
+            } else {
+                let bot_name = match ctx.http.get_current_user().await {
+                    Ok(user) => user.name.clone(),
+                    Err(_) => "Bot".to_string(),
+                };
+                let doc = export::transcript(&messages, &bot_name);
+                CreateMessage::new()
+                    .content(format!("The last {} messages of our conversation.", messages.len()))
+                    .add_file(CreateAttachment::bytes(doc.into_bytes(), "transcript.md"))
+            };
+            if let Err(why) = msg.channel_id.send_message(&ctx.http, message).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "memories" {
+            let memories = {
+                let conn = handler.db.lock().await;
+                db::get_memories(&conn, &msg.channel_id.to_string(), MEMORIES_SHOWN).unwrap_or_default()
+            };
+            let response = if memories.is_empty() {
+                "Nothing saved here yet. React 📌 to one of my replies to keep it.".to_string()
+            } else {
+                let lines: Vec<String> = memories
+                    .iter()
+                    .map(|m| format!("• {}\n  → {} *(saved by {})*", m.prompt, m.reply, m.saved_by))
+                    .collect();
+                markdown::truncate(&format!("**Saved exchanges:**\n{}", lines.join("\n")), markdown::DISCORD_MESSAGE_MAX)
+            };
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "clear" {
+            let conn = handler.db.lock().await;
+            let context_key = handler.history_key(&conn, &msg.channel_id.to_string(), &msg.author.id.to_string());
+            match db::clear_messages(&conn, &context_key) {
+                Ok(n) => {
+                    let response = format!("Cleared {} messages.", n);
+                    if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                        error!("Error sending message: {:?}", why);
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to clear messages: {}", e);
+                }
+            }
+            return true;
+        }
+
+        if command == "checkpoint" || command.starts_with("checkpoint ") {
+            let response = checkpoint_command(handler, msg, command, args).await;
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "movecontext" {
+            let response = move_context(handler, ctx, msg, args).await;
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "contextchannel" {
+            let conn = handler.db.lock().await;
+            let channel_id = msg.channel_id.to_string();
+            match db::set_context_mode(&conn, &channel_id, "channel") {
+                Ok(_) => {
+                    if let Err(why) = msg.channel_id.say(&ctx.http, "Context mode set to **channel** — everyone shares history here.").await {
+                        error!("Error sending message: {:?}", why);
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to set context mode: {}", e);
+                }
+            }
+            return true;
+        }
+
+        if command == "contextuser" {
+            let conn = handler.db.lock().await;
+            let channel_id = msg.channel_id.to_string();
+            match db::set_context_mode(&conn, &channel_id, "user") {
+                Ok(_) => {
+                    if let Err(why) = msg.channel_id.say(&ctx.http, "Context mode set to **user** — everyone gets their own history here.").await {
+                        error!("Error sending message: {:?}", why);
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to set context mode: {}", e);
+                }
+            }
+            return true;
+        }
+
+        // When mentioned or replied to, send the message to llama.cpp
+        let me = ctx.http.get_current_user().await.ok().map(|user| user.id);
+        let replied_to = msg
+            .referenced_message
+            .as_deref()
+            .filter(|replied| Some(replied.author.id) == me);
+        if let Some(me) = me.filter(|me| msg.mentions_user_id(*me) || replied_to.is_some()) {
+            if handler.disabled_feature(msg.guild_id, "chat").await.is_some() {
+                return true;
+            }
+
+            // Strip the bot mention from the message to get the actual question
+            let content = mentions::strip_mention(&msg.content, me);
+
+            if content.is_empty() {
+                if let Err(why) = msg
+                    .channel_id
+                    .say(&ctx.http, "You mentioned me but didn't say anything!")
+                    .await
+                {
+                    error!("Error sending message: {:?}", why);
+                }
+                return true;
+            }
+
+            self.chat(handler, ctx, msg, &content, replied_to).await;
+            return true;
+        }
+
+        if command.is_empty() && self.answer_question(handler, ctx, msg).await {
+            return true;
+        }
+
+        if command.is_empty() && self.wakes(handler, msg).await {
+            if handler.disabled_feature(msg.guild_id, "chat").await.is_some() {
+                return true;
+            }
+            self.chat(handler, ctx, msg, msg.content.trim(), None).await;
+            return true;
+        }
+
+        if command.is_empty() {
+            self.overhear(handler, msg).await;
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contains_wake_word() {
+        let words = vec!["christinith".to_string()];
+        assert!(contains_wake_word("christinith what time is it", &words));
+        assert!(contains_wake_word("hey, Christinith!", &words));
+        assert!(!contains_wake_word("christinithe is not her name", &words));
+        assert!(!contains_wake_word("hello", &[]));
+    }
+
+    #[test]
+    fn test_help_channel_checks() {
+        assert!(is_question("how do I join the raid? "));
+        assert!(!is_question("? is a question mark"));
+        assert!(is_unsure("UNSURE"));
+        assert!(is_unsure(" unsure."));
+        assert!(!is_unsure("Raid starts at eight. Unsure about the loot."));
+    }
+
+    #[test]
+    fn test_reply_note() {
+        assert!(reply_note("go away", None).ends_with("earlier message: \"go away\""));
+        let note = reply_note("go away", Some(("hi".to_string(), "go away".to_string())));
+        assert!(note.contains("They had said: \"hi\" and you answered: \"go away\""));
+    }
+}
@@ -0,0 +1,562 @@
+use serenity::async_trait;
+use serenity::builder::CreateMessage;
+use serenity::model::channel::Message;
+use serenity::model::id::GuildId;
+use serenity::prelude::Context;
+use tracing::{error, info};
+
+use super::BotModule;
+use crate::args::Args;
+use crate::blocklist::{self, Mode};
+use crate::commands::Usage;
+use crate::features::Feature;
+use crate::help::Category;
+use crate::{automod, bots, db, flags, mentions, readonly, Handler};
+
+const FLAG_USAGE: &str = "Usage: `!flag list`, `!flag on|off|clear <name> [here]` or `!flag rollout <name> <percent>`";
+
+const BLOCKLIST_USAGE: &str = "Usage: `!blocklist add|remove <word or /regex/>`, `!blocklist list` \
+     or `!blocklist mode mask|regenerate`";
+
+const AUTOMOD_USAGE: &str = "Usage: `!automod addword|removeword <word>`, `!automod list` or `!automod log`";
+
+/// Server administration: `!feature`, `!blocklist`, `!automod`, `!retry`,
+/// `!retention`, `!errorchannel`, `!readonly` and `!flag`.
+pub struct Moderation;
+
+impl Moderation {
+    async fn flag_command(&self, handler: &Handler, msg: &Message, command: &str, args: &Args) -> String {
+        let conn = handler.db.lock().await;
+        if command == "flag list" {
+            let settings = match db::list_flags(&conn) {
+                Ok(settings) => settings,
+                Err(e) => {
+                    error!("Failed to list flags: {}", e);
+                    return "Failed to load flags.".to_string();
+                }
+            };
+            if settings.is_empty() {
+                return "No flags set.".to_string();
+            }
+            let mut response = String::from("**Flags:**\n");
+            for setting in settings {
+                let state = if setting.enabled { "on" } else { "off" };
+                let line = match setting.guild_id {
+                    Some(guild) => format!("  `{}` {} in {}\n", setting.name, state, guild),
+                    None if setting.rollout_percent > 0 && !setting.enabled => {
+                        format!("  `{}` on for {}% of servers\n", setting.name, setting.rollout_percent)
+                    }
+                    None => format!("  `{}` {} everywhere\n", setting.name, state),
+                };
+                response.push_str(&line);
+            }
+            return response;
+        }
+
+        let Some(name) = args.get(0).filter(|name| flags::valid_name(name)) else {
+            return FLAG_USAGE.to_string();
+        };
+        let here = args.get(1) == Some("here");
+        let guild = match (here, msg.guild_id) {
+            (true, Some(guild_id)) => Some(guild_id.to_string()),
+            (true, None) => return "`here` only works in a server.".to_string(),
+            (false, _) => None,
+        };
+        let scope = if here { "in this server" } else { "everywhere" };
+        let result = match command {
+            "flag on" | "flag off" => {
+                let on = command == "flag on";
+                db::set_flag(&conn, name, guild.as_deref(), on)
+                    .map(|_| format!("`{}` is {} {}.", name, if on { "on" } else { "off" }, scope))
+            }
+            "flag rollout" => match args.get(1).map(|p| p.trim_end_matches('%').parse::<u8>()) {
+                Some(Ok(percent)) if percent <= 100 => db::set_flag_rollout(&conn, name, percent)
+                    .map(|_| format!("`{}` is rolling out to {}% of servers.", name, percent)),
+                _ => return FLAG_USAGE.to_string(),
+            },
+            "flag clear" => db::clear_flag(&conn, name, guild.as_deref()).map(|cleared| match cleared {
+                true => format!("Cleared `{}` {}.", name, scope),
+                false => format!("`{}` isn't set {}.", name, scope),
+            }),
+            _ => return FLAG_USAGE.to_string(),
+        };
+        match result {
+            Ok(mut response) => {
+                info!("{} ran !{} {}", msg.author.name, command, args.raw());
+                if msg.guild_id.is_some() && command != "flag on" && command != "flag off" {
+                    let here = if flags::enabled(&conn, name, msg.guild_id) { "on" } else { "off" };
+                    response.push_str(&format!(" It's {} in this server.", here));
+                }
+                response
+            }
+            Err(e) => {
+                error!("Failed to update flag {}: {}", name, e);
+                "Failed to save the flag.".to_string()
+            }
+        }
+    }
+
+    async fn blocklist_command(
+        &self,
+        handler: &Handler,
+        msg: &Message,
+        guild_id: GuildId,
+        command: &str,
+        args: &Args,
+    ) -> String {
+        let guild = guild_id.to_string();
+        let entry = args.raw();
+        match command {
+            "blocklist add" if !entry.is_empty() => {
+                if let Err(e) = blocklist::compile_entry(entry) {
+                    return e;
+                }
+                let conn = handler.db.lock().await;
+                match db::add_blocklist_entry(&conn, &guild, entry, &msg.author.id.to_string()) {
+                    Ok(true) => {
+                        info!("{} blocked {:?} in guild {}", msg.author.name, entry, guild_id);
+                        format!("Blocked `{}`.", entry)
+                    }
+                    Ok(false) => format!("`{}` is already blocked.", entry),
+                    Err(e) => {
+                        error!("Failed to add blocklist entry: {}", e);
+                        "Failed to save blocklist entry.".to_string()
+                    }
+                }
+            }
+            "blocklist remove" if !entry.is_empty() => {
+                let conn = handler.db.lock().await;
+                match db::remove_blocklist_entry(&conn, &guild, entry) {
+                    Ok(true) => {
+                        info!("{} unblocked {:?} in guild {}", msg.author.name, entry, guild_id);
+                        format!("Unblocked `{}`.", entry)
+                    }
+                    Ok(false) => format!("`{}` isn't blocked.", entry),
+                    Err(e) => {
+                        error!("Failed to remove blocklist entry: {}", e);
+                        "Failed to remove blocklist entry.".to_string()
+                    }
+                }
+            }
+            "blocklist" | "blocklist list" => {
+                let conn = handler.db.lock().await;
+                let mode = db::get_blocklist_mode(&conn, &guild)
+                    .ok()
+                    .flatten()
+                    .unwrap_or_else(|| Mode::Mask.name().to_string());
+                match db::get_blocklist(&conn, &guild) {
+                    Ok(entries) if entries.is_empty() => "Nothing is blocked.".to_string(),
+                    Ok(entries) => {
+                        let entries: Vec<String> = entries.iter().map(|e| format!("`{}`", e)).collect();
+                        format!("**Blocked ({}):** {}", mode, entries.join(", "))
+                    }
+                    Err(e) => {
+                        error!("Failed to load blocklist: {}", e);
+                        "Failed to load the blocklist.".to_string()
+                    }
+                }
+            }
+            "blocklist mode" => match args.get(0).and_then(Mode::from_name) {
+                Some(mode) => {
+                    let conn = handler.db.lock().await;
+                    match db::set_blocklist_mode(&conn, &guild, mode.name()) {
+                        Ok(_) => match mode {
+                            Mode::Mask => "Blocked words in replies will be masked.".to_string(),
+                            Mode::Regenerate => format!(
+                                "Replies with blocked words will be regenerated (up to {} times, then masked).",
+                                Mode::MAX_REGENERATIONS
+                            ),
+                        },
+                        Err(e) => {
+                            error!("Failed to set blocklist mode: {}", e);
+                            "Failed to save blocklist mode.".to_string()
+                        }
+                    }
+                }
+                None => "Usage: `!blocklist mode mask|regenerate`".to_string(),
+            },
+            _ => BLOCKLIST_USAGE.to_string(),
+        }
+    }
+
+    /// `!automod`: Discord's own AutoMod word filter, run from the bot, and
+    /// the moderation log its executions go to. Server managers only.
+    async fn automod_command(
+        &self,
+        handler: &Handler,
+        ctx: &Context,
+        msg: &Message,
+        guild_id: GuildId,
+        command: &str,
+        args: &Args,
+    ) -> String {
+        if !bots::can_manage_guild(&ctx.http, guild_id, msg.author.id).await {
+            return "Only server managers can use `!automod`.".to_string();
+        }
+        let word = args.raw();
+        match command {
+            "automod addword" | "automod removeword" if !word.is_empty() => {
+                let add = command == "automod addword";
+                handler.automod_edit_word(&ctx.http, guild_id, word, add, msg.author.id).await
+            }
+            "automod list" => handler.automod_rules_report(&ctx.http, guild_id).await,
+            "automod log" => {
+                let conn = handler.db.lock().await;
+                match db::get_moderation_log(&conn, &guild_id.to_string(), automod::LOG_SHOWN) {
+                    Ok(entries) if entries.is_empty() => "The moderation log is empty.".to_string(),
+                    Ok(entries) => {
+                        let lines: Vec<String> = entries
+                            .iter()
+                            .map(|e| format!("  <t:{}:R> <@{}> — {}: {}", e.created_at, e.user_id, e.action, e.detail))
+                            .collect();
+                        format!("**Moderation log**\n{}", lines.join("\n"))
+                    }
+                    Err(e) => {
+                        error!("Failed to load moderation log: {}", e);
+                        "Failed to load the moderation log.".to_string()
+                    }
+                }
+            }
+            _ => AUTOMOD_USAGE.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl BotModule for Moderation {
+    fn name(&self) -> &'static str {
+        "moderation"
+    }
+
+    fn usage(&self) -> Vec<Usage> {
+        vec![
+            Usage::new(Category::Admin, "feature [enable|disable <name>]", "Toggle features for this server (Manage Server)"),
+            Usage::new(Category::Admin, "blocklist add|remove <word or /regex/>", "Words to keep out of my replies"),
+            Usage::new(Category::Admin, "blocklist list|mode <mask|regenerate>", "Show the blocklist, or mask vs. retry on a match"),
+            Usage::new(Category::Admin, "automod addword|removeword <word>", "Have Discord's AutoMod block a word (`!automod list` for all rules)"),
+            Usage::new(Category::Admin, "automod log", "Recent AutoMod blocks and filter changes"),
+            Usage::new(Category::Admin, "retry on|off", "When llama.cpp or Battle.net is down, queue chats and level checks and reply later"),
+            Usage::new(Category::Admin, "retention snapshots|messages <days|off>", "How long nightly maintenance keeps old data (bot owner only)"),
+            Usage::new(Category::Admin, "retention archive <days|off>", "Move listening channels' old messages to compressed files instead (bot owner only)"),
+            Usage::new(Category::Admin, "errorchannel here|off", "Post unexpected errors in this channel (bot owner only)"),
+            Usage::new(Category::Admin, "flag on|off|clear <name> [here]", "Try new subsystems on one server first (bot owner only; `!flag rollout <name> <percent>`, `!flag list`)"),
+            Usage::new(Category::Admin, "readonly on|off", "Process everything but send nothing, for dry runs (bot owner only)"),
+        ]
+    }
+
+    async fn on_message(&self, handler: &Handler, ctx: &Context, msg: &Message, command: &str, args: &Args) -> bool {
+        if command == "blocklist" || command.starts_with("blocklist ") {
+            let response = match msg.guild_id {
+                Some(guild_id) => self.blocklist_command(handler, msg, guild_id, command, args).await,
+                None => "The blocklist can only be managed in a server.".to_string(),
+            };
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "automod" || command.starts_with("automod ") {
+            let response = match msg.guild_id {
+                Some(guild_id) => self.automod_command(handler, ctx, msg, guild_id, command, args).await,
+                None => "AutoMod can only be managed in a server.".to_string(),
+            };
+            // The log names offenders; listing them shouldn't ping them
+            let message = CreateMessage::new().content(response).allowed_mentions(mentions::allowed(false));
+            if let Err(why) = msg.channel_id.send_message(&ctx.http, message).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "retry" {
+            let Some(guild_id) = msg.guild_id else {
+                if let Err(why) = msg.channel_id.say(&ctx.http, "Retries can only be configured in a server.").await {
+                    error!("Error sending message: {:?}", why);
+                }
+                return true;
+            };
+
+            let conn = handler.db.lock().await;
+            let response = match args.get(0) {
+                None => format!(
+                    "When llama.cpp or Battle.net is down, I {}. Usage: `!retry on|off`",
+                    if db::is_retry_enabled(&conn, &guild_id.to_string()) {
+                        "queue the request and get back to you"
+                    } else {
+                        "just say it failed"
+                    }
+                ),
+                Some(state @ ("on" | "off")) => match db::set_retry_enabled(&conn, &guild_id.to_string(), state == "on") {
+                    Ok(_) => {
+                        info!("{} turned retries {} in guild {}", msg.author.name, state, guild_id);
+                        format!("Retrying failed requests turned **{}**.", state)
+                    }
+                    Err(e) => {
+                        error!("Failed to set retry setting: {}", e);
+                        "Failed to save retry setting.".to_string()
+                    }
+                },
+                Some(_) => "Usage: `!retry on|off`".to_string(),
+            };
+            drop(conn);
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "retention" {
+            // Retention is bot-wide and deletes data from every server
+            if !bots::is_owner(&ctx.http, msg.author.id).await {
+                if let Err(why) = msg.channel_id.say(&ctx.http, "Only the bot's owner can change retention.").await {
+                    error!("Error sending message: {:?}", why);
+                }
+                return true;
+            }
+            let usage = "Usage: `!retention snapshots|messages|archive <days|off>`";
+            let conn = handler.db.lock().await;
+            let response = match args.positional() {
+                [] => {
+                    let describe = |kind: &str| match db::get_retention_days(&conn, kind) {
+                        Ok(Some(days)) => format!("{} days", days),
+                        Ok(None) => "forever".to_string(),
+                        Err(e) => {
+                            error!("Failed to read retention: {}", e);
+                            "?".to_string()
+                        }
+                    };
+                    let archive = match db::get_retention_days(&conn, "archive") {
+                        Ok(Some(days)) => format!(" Listening channels' history is archived to files after **{} days**.", days),
+                        _ => String::new(),
+                    };
+                    format!(
+                        "Nightly maintenance keeps snapshots for **{}** and chat history for **{}**.{} {}",
+                        describe("snapshots"),
+                        describe("messages"),
+                        archive,
+                        usage
+                    )
+                }
+                [kind, days] if kind == "archive" => {
+                    let days = match days.as_str() {
+                        "off" => Ok(None),
+                        days => days.parse::<u32>().ok().filter(|d| *d > 0).map(Some).ok_or(()),
+                    };
+                    match days.map(|days| (days, db::set_retention_days(&conn, kind, days))) {
+                        Ok((days, Ok(_))) => {
+                            info!("{} set archiving to {:?} days", msg.author.name, days);
+                            match days {
+                                Some(days) => format!("Listening channels' messages will be moved to archive files after **{}** days (`!archives` to get them).", days),
+                                None => "Messages stay in the database until retention removes them.".to_string(),
+                            }
+                        }
+                        Ok((_, Err(e))) => {
+                            error!("Failed to set archiving: {}", e);
+                            "Failed to save retention.".to_string()
+                        }
+                        Err(_) => usage.to_string(),
+                    }
+                }
+                [kind, days] if kind == "snapshots" || kind == "messages" => {
+                    let days = match days.as_str() {
+                        "off" => Ok(None),
+                        days => days.parse::<u32>().ok().filter(|d| *d > 0).map(Some).ok_or(()),
+                    };
+                    match days {
+                        Ok(days) => match db::set_retention_days(&conn, kind, days) {
+                            Ok(_) => {
+                                info!("{} set {} retention to {:?} days", msg.author.name, kind, days);
+                                match days {
+                                    Some(days) => format!("Keeping {} for **{}** days.", kind, days),
+                                    None => format!("Keeping {} forever.", kind),
+                                }
+                            }
+                            Err(e) => {
+                                error!("Failed to set retention: {}", e);
+                                "Failed to save retention.".to_string()
+                            }
+                        },
+                        Err(_) => usage.to_string(),
+                    }
+                }
+                _ => usage.to_string(),
+            };
+            drop(conn);
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "errorchannel" {
+            let response = if !bots::is_owner(&ctx.http, msg.author.id).await {
+                "Only the bot's owner can choose where errors go.".to_string()
+            } else {
+                let conn = handler.db.lock().await;
+                let saved = match args.get(0) {
+                    Some("here") => db::set_config(&conn, None, "error_channel", &msg.channel_id.to_string()).map(|_| true),
+                    Some("off") => db::delete_config(&conn, None, "error_channel").map(|_| false),
+                    _ => {
+                        drop(conn);
+                        if let Err(why) = msg.channel_id.say(&ctx.http, "Usage: `!errorchannel here|off`").await {
+                            error!("Error sending message: {:?}", why);
+                        }
+                        return true;
+                    }
+                };
+                match saved {
+                    Ok(true) => {
+                        info!("{} set the error channel to {}", msg.author.name, msg.channel_id);
+                        "I'll post unexpected errors here, with repeats grouped.".to_string()
+                    }
+                    Ok(false) => {
+                        info!("{} turned off the error channel", msg.author.name);
+                        "I'll stop posting errors.".to_string()
+                    }
+                    Err(e) => {
+                        error!("Failed to set error channel: {}", e);
+                        "Failed to save the error channel.".to_string()
+                    }
+                }
+            };
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "flag" || command.starts_with("flag ") {
+            let response = if !bots::is_owner(&ctx.http, msg.author.id).await {
+                "Only the bot's owner can change flags.".to_string()
+            } else {
+                self.flag_command(handler, msg, command, args).await
+            };
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "readonly" {
+            let on = match args.get(0) {
+                Some("on") => true,
+                Some("off") => false,
+                _ => {
+                    let usage = format!(
+                        "Usage: `!readonly on|off` (currently {})",
+                        if readonly::enabled() { "on" } else { "off" }
+                    );
+                    if let Err(why) = msg.channel_id.say(&ctx.http, usage).await {
+                        error!("Error sending message: {:?}", why);
+                    }
+                    return true;
+                }
+            };
+            let mut switch_on = false;
+            let response = if !bots::is_owner(&ctx.http, msg.author.id).await {
+                "Only the bot's owner can switch read-only mode.".to_string()
+            } else {
+                let conn = handler.db.lock().await;
+                let saved = if on {
+                    db::set_config(&conn, None, "read_only", "on")
+                } else {
+                    db::delete_config(&conn, None, "read_only").map(|_| ())
+                };
+                match saved {
+                    // Without the relay there's nothing to withhold sends until a restart sets it up
+                    Ok(()) if on && !readonly::relayed() => {
+                        info!("{} saved read-only mode for the next start", msg.author.name);
+                        "Read-only mode saved: it starts with the next restart, and from then I'll process \
+                         and log everything but send nothing until `!readonly off`."
+                            .to_string()
+                    }
+                    Ok(()) if on => {
+                        info!("{} turned on read-only mode", msg.author.name);
+                        switch_on = true;
+                        "Read-only mode on: I'll keep processing and logging everything, but send nothing \
+                         until `!readonly off`."
+                            .to_string()
+                    }
+                    Ok(()) => {
+                        info!("{} turned off read-only mode", msg.author.name);
+                        readonly::set(false);
+                        "Read-only mode off: I'm talking again.".to_string()
+                    }
+                    Err(e) => {
+                        error!("Failed to save read-only mode: {}", e);
+                        "Failed to save read-only mode.".to_string()
+                    }
+                }
+            };
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            // Switched on only now, so the confirmation above still goes out
+            if switch_on {
+                readonly::set(true);
+            }
+            return true;
+        }
+
+        if command == "feature" {
+            let Some(guild_id) = msg.guild_id else {
+                if let Err(why) = msg.channel_id.say(&ctx.http, "Features can only be toggled in a server.").await {
+                    error!("Error sending message: {:?}", why);
+                }
+                return true;
+            };
+
+            // Anyone may list them; switching one off affects the whole server
+            let may_toggle = match args.positional() {
+                [_, _] => bots::can_manage_guild(&ctx.http, guild_id, msg.author.id).await,
+                _ => true,
+            };
+            let response = match args.positional() {
+                [] => {
+                    let conn = handler.db.lock().await;
+                    let mut response = String::from("**Features:**\n");
+                    for feature in Feature::ALL {
+                        let enabled = db::is_feature_enabled(&conn, &guild_id.to_string(), feature.name())
+                            .unwrap_or(true);
+                        response.push_str(&format!(
+                            "  {} `{}` — {}\n",
+                            if enabled { "✅" } else { "❌" },
+                            feature.name(),
+                            feature.description()
+                        ));
+                    }
+                    response.push_str("Usage: `!feature enable|disable <name>`");
+                    response
+                }
+                [action, _] if (action == "enable" || action == "disable") && !may_toggle => {
+                    "You need the Manage Server permission to toggle features.".to_string()
+                }
+                [action, name] if action == "enable" || action == "disable" => match Feature::from_name(name) {
+                    Some(feature) => {
+                        let enabled = action == "enable";
+                        let conn = handler.db.lock().await;
+                        match db::set_feature_enabled(&conn, &guild_id.to_string(), feature.name(), enabled) {
+                            Ok(_) => {
+                                info!("{} {}d feature {} in guild {}", msg.author.name, action, feature.name(), guild_id);
+                                format!("Feature **{}** {}d.", feature.name(), action)
+                            }
+                            Err(e) => {
+                                error!("Failed to set feature flag: {}", e);
+                                "Failed to save feature setting.".to_string()
+                            }
+                        }
+                    }
+                    None => format!("Unknown feature `{}`. Use `!feature` to list them.", name),
+                },
+                _ => "Usage: `!feature [enable|disable <name>]`".to_string(),
+            };
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        false
+    }
+}
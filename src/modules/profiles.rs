@@ -0,0 +1,215 @@
+use serenity::async_trait;
+use serenity::builder::CreateMessage;
+use serenity::http::Http;
+use serenity::model::channel::Message;
+use serenity::model::id::ChannelId;
+use serenity::prelude::Context;
+use std::sync::Arc;
+use tracing::{error, info};
+
+use super::BotModule;
+use crate::args::Args;
+use crate::commands::Usage;
+use crate::help::Category;
+use crate::profile::{self, Game};
+use crate::scheduler::unix_now;
+use crate::steam::{self, RaidNight};
+use crate::{db, Handler};
+
+const LINK_USAGE: &str = "Usage: `!link <game> <name>` (or `!unlink <game> <name>`)";
+const STEAM_USAGE: &str = "Usage: `!steam link <profile>|unlink|nag here <day> <HH:MM>|nag off`";
+
+/// Game accounts linked to Discord users: `!link`, `!unlink`, `!profile`, and
+/// Steam's `!steam` and `!playing` with the raid night nag.
+pub struct Profiles;
+
+#[async_trait]
+impl BotModule for Profiles {
+    fn name(&self) -> &'static str {
+        "profiles"
+    }
+
+    fn usage(&self) -> Vec<Usage> {
+        vec![
+            Usage::new(Category::Fun, "link <wow|osrs|lol|steam> <name>", "Add a game account to your profile (`!unlink` to remove it)"),
+            Usage::new(Category::Fun, "profile [@user]", "Everyone's linked accounts, levels and this week's progress"),
+            Usage::new(Category::Fun, "steam link <profile>|unlink", "Link your Steam profile (ID, URL or custom URL name)"),
+            Usage::new(Category::Fun, "playing [@user]", "What someone is playing on Steam right now"),
+            Usage::new(Category::Fun, "steam nag here <day> <HH:MM>|off", "On raid night (UTC), tell anyone still in a Steam game to log in"),
+        ]
+    }
+
+    async fn on_tick(&self, handler: &Handler, http: &Arc<Http>) {
+        nag_raid_night(handler, http).await;
+    }
+
+    async fn on_message(&self, handler: &Handler, ctx: &Context, msg: &Message, command: &str, args: &Args) -> bool {
+        if command == "link" || command == "unlink" {
+            let response = match (args.get(0), args.positional().get(1..).filter(|rest| !rest.is_empty())) {
+                (Some(game), Some(rest)) => match Game::from_name(game) {
+                    // Steam IDs are checked against the API and only one is kept
+                    Some(Game::Steam) if command == "link" => {
+                        handler.link_steam(&msg.author.id.to_string(), &rest.join(" ")).await
+                    }
+                    Some(game) => {
+                        let account = rest.join(" ");
+                        let user_id = msg.author.id.to_string();
+                        let conn = handler.db.lock().await;
+                        let result = if command == "link" {
+                            db::link_account(&conn, &user_id, game.slug(), &account)
+                        } else {
+                            db::unlink_account(&conn, &user_id, game.slug(), &account)
+                        };
+                        match (command, result) {
+                            ("link", Ok(true)) => {
+                                info!("{} linked {} account {}", msg.author.name, game.slug(), account);
+                                format!("Linked **{}** ({}) to your profile.", account, game.name())
+                            }
+                            ("link", Ok(false)) => format!("**{}** is already on your profile.", account),
+                            (_, Ok(true)) => {
+                                info!("{} unlinked {} account {}", msg.author.name, game.slug(), account);
+                                format!("Unlinked **{}** ({}).", account, game.name())
+                            }
+                            (_, Ok(false)) => format!("**{}** isn't on your profile.", account),
+                            (_, Err(e)) => {
+                                error!("Failed to {} account: {}", command, e);
+                                "Failed to update your profile.".to_string()
+                            }
+                        }
+                    }
+                    None => profile::unknown_game(game),
+                },
+                _ => LINK_USAGE.to_string(),
+            };
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "steam link" || command == "steam unlink" || command == "steam nag" {
+            let response = match command {
+                "steam link" => match args.get(0) {
+                    Some(profile) => handler.link_steam(&msg.author.id.to_string(), profile).await,
+                    None => STEAM_USAGE.to_string(),
+                },
+                "steam unlink" => {
+                    let conn = handler.db.lock().await;
+                    match db::unlink_game(&conn, &msg.author.id.to_string(), steam::GAME) {
+                        Ok(0) => "You haven't linked Steam.".to_string(),
+                        Ok(_) => "Unlinked your Steam profile.".to_string(),
+                        Err(e) => {
+                            error!("Failed to unlink Steam: {}", e);
+                            "Failed to update your profile.".to_string()
+                        }
+                    }
+                }
+                _ => set_raid_night(handler, msg, args).await,
+            };
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "steam" || command.starts_with("steam ") {
+            if let Err(why) = msg.channel_id.say(&ctx.http, STEAM_USAGE).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "playing" {
+            let user = msg.mentions.first().unwrap_or(&msg.author);
+            let response = handler.playing_report(user).await;
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "profile" {
+            let user = msg.mentions.first().unwrap_or(&msg.author);
+            let embed = handler.profile_embed(user).await;
+            if let Err(why) = msg.channel_id.send_message(&ctx.http, CreateMessage::new().embed(embed)).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        false
+    }
+}
+
+/// `!steam nag here <day> <HH:MM>` / `!steam nag off`.
+async fn set_raid_night(handler: &Handler, msg: &Message, args: &Args) -> String {
+    let conn = handler.db.lock().await;
+    match (args.get(0), args.get(1), args.get(2)) {
+        (Some("here"), Some(day), Some(time)) => {
+            let Some(night) = RaidNight::parse(day, time) else {
+                return format!("`{} {}` isn't a day and `HH:MM` time.", day, time);
+            };
+            let result = db::set_config(&conn, None, "raid_night", &night.to_config())
+                .and_then(|_| db::set_config(&conn, None, "raid_night_channel", &msg.channel_id.to_string()));
+            match result {
+                Ok(()) => {
+                    info!("{} set raid night to {} in {}", msg.author.name, night, msg.channel_id);
+                    format!(
+                        "Raid night is {}. Anyone still in a Steam game then gets told to log in here.",
+                        night
+                    )
+                }
+                Err(e) => {
+                    error!("Failed to set raid night: {}", e);
+                    "Failed to save raid night.".to_string()
+                }
+            }
+        }
+        (Some("off"), None, None) => match db::delete_config(&conn, None, "raid_night_channel") {
+            Ok(_) => "Raid night nagging disabled.".to_string(),
+            Err(e) => {
+                error!("Failed to clear raid night channel: {}", e);
+                "Failed to disable raid night nagging.".to_string()
+            }
+        },
+        _ => STEAM_USAGE.to_string(),
+    }
+}
+
+/// Once per raid night, tells everyone who linked Steam and is still in a
+/// game to log in.
+async fn nag_raid_night(handler: &Handler, http: &Http) {
+    let now = unix_now();
+    let (channel_id, start) = {
+        let conn = handler.db.lock().await;
+        let config = |key: &str| db::get_config(&conn, None, key).ok().flatten();
+        let channel = config("raid_night_channel").and_then(|v| v.parse::<u64>().ok());
+        let start = config("raid_night")
+            .and_then(|v| RaidNight::from_config(&v))
+            .and_then(|night| night.current_start(now));
+        let last_nagged = config("raid_night_nagged").and_then(|v| v.parse::<i64>().ok());
+        match (channel, start) {
+            (Some(channel), Some(start)) if last_nagged != Some(start) => (ChannelId::new(channel), start),
+            _ => return,
+        }
+    };
+
+    let playing = match handler.steam_players_in_game().await {
+        Ok(playing) => playing,
+        Err(e) => {
+            error!("Failed to check Steam for the raid night nag: {}", e);
+            return;
+        }
+    };
+    for (user_id, game) in &playing {
+        if let Err(why) = channel_id.say(http, steam::nag(user_id, game)).await {
+            error!("Failed to post raid night nag: {:?}", why);
+        }
+    }
+    info!("Nagged {} players in Steam games for raid night", playing.len());
+
+    let conn = handler.db.lock().await;
+    if let Err(e) = db::set_config(&conn, None, "raid_night_nagged", &start.to_string()) {
+        error!("Failed to record raid night nag: {}", e);
+    }
+}
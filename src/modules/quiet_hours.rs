@@ -0,0 +1,284 @@
+use chrono::Local;
+use serenity::async_trait;
+use serenity::builder::EditChannel;
+use serenity::http::Http;
+use serenity::model::channel::{Channel, ChannelType, Message, PermissionOverwrite, PermissionOverwriteType};
+use serenity::model::id::{ChannelId, GuildId, RoleId};
+use serenity::model::permissions::Permissions;
+use serenity::prelude::Context;
+use serenity::utils::parse_channel_mention;
+use std::sync::Arc;
+use tracing::{error, info};
+
+use super::BotModule;
+use crate::args::Args;
+use crate::commands::Usage;
+use crate::help::Category;
+use crate::quiet_hours::{clock, Action, Window, MAX_SLOWMODE_SECS};
+use crate::{bots, db, Handler};
+
+const USAGE: &str = "Usage: `!quiethours 23:00-07:00 [#channel] [--slowmode=<seconds>]` or `!quiethours off [#channel]`";
+
+/// What a lock takes away from @everyone.
+const LOCKED: Permissions = Permissions::SEND_MESSAGES.union(Permissions::SEND_MESSAGES_IN_THREADS);
+
+/// Scheduled quiet hours: `!quiethours` locks a channel or puts it in
+/// slowmode every night (or any daily window), then puts it back.
+pub struct QuietHours;
+
+#[async_trait]
+impl BotModule for QuietHours {
+    fn name(&self) -> &'static str {
+        "quiethours"
+    }
+
+    fn usage(&self) -> Vec<Usage> {
+        vec![
+            Usage::new(Category::Admin, "quiethours 23:00-07:00 [#channel] [--slowmode=<seconds>]", "Lock (or slow) a channel every night; `!quiethours off` to stop"),
+        ]
+    }
+
+    /// Starts windows that have begun and ends those that are over.
+    async fn on_tick(&self, handler: &Handler, http: &Arc<Http>) {
+        let all = {
+            let conn = handler.db.lock().await;
+            match db::get_quiet_hours(&conn, None) {
+                Ok(all) => all,
+                Err(e) => {
+                    error!("Failed to load quiet hours: {}", e);
+                    return;
+                }
+            }
+        };
+        let now = Local::now().time();
+        for quiet_hours in &all {
+            let window = Window {
+                start: quiet_hours.start_minute,
+                end: quiet_hours.end_minute,
+            };
+            match (window.contains(now), quiet_hours.active) {
+                (true, false) => start(handler, http, quiet_hours, window).await,
+                (false, true) => end(handler, http, quiet_hours).await,
+                _ => {}
+            }
+        }
+    }
+
+    async fn on_message(&self, handler: &Handler, ctx: &Context, msg: &Message, command: &str, args: &Args) -> bool {
+        if command != "quiethours" {
+            return false;
+        }
+        let response = quiethours_command(handler, ctx, msg, args).await;
+        if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+            error!("Error sending message: {:?}", why);
+        }
+        true
+    }
+}
+
+fn everyone(guild_id: &str) -> Option<PermissionOverwriteType> {
+    let guild_id = guild_id.parse::<u64>().ok()?;
+    Some(PermissionOverwriteType::Role(RoleId::new(guild_id)))
+}
+
+/// Locks or slows the channel, remembering how it was.
+async fn start(handler: &Handler, http: &Http, quiet_hours: &db::QuietHours, window: Window) {
+    let Ok(channel_id) = quiet_hours.channel_id.parse::<u64>().map(ChannelId::new) else { return };
+    let Some(everyone) = everyone(&quiet_hours.guild_id) else { return };
+    let channel = match channel_id.to_channel(http).await {
+        Ok(Channel::Guild(channel)) => channel,
+        other => {
+            error!("Can't start quiet hours in channel {}: {:?}", channel_id, other.err());
+            return;
+        }
+    };
+    let action = Action::from_slowmode_secs(quiet_hours.slowmode);
+    let result = match action {
+        Action::Slowmode(secs) => channel_id
+            .edit(http, EditChannel::new().rate_limit_per_user(secs))
+            .await
+            .map(|_| (channel.rate_limit_per_user.or(Some(0)), None)),
+        Action::Lock => {
+            let current = channel.permission_overwrites.iter().find(|o| o.kind == everyone);
+            let (allow, deny) = current.map_or((Permissions::empty(), Permissions::empty()), |o| (o.allow, o.deny));
+            let locked = PermissionOverwrite {
+                allow: allow - LOCKED,
+                deny: deny | LOCKED,
+                kind: everyone,
+            };
+            channel_id
+                .create_permission(http, locked)
+                .await
+                .map(|_| (None, current.map(|o| (o.allow.bits(), o.deny.bits()))))
+        }
+    };
+    let (saved_slowmode, saved_overwrite) = match result {
+        Ok(saved) => saved,
+        Err(why) => {
+            error!("Failed to start quiet hours in channel {}: {:?}", channel_id, why);
+            return;
+        }
+    };
+    {
+        let conn = handler.db.lock().await;
+        if let Err(e) = db::start_quiet_hours(&conn, &quiet_hours.channel_id, saved_slowmode, action == Action::Lock, saved_overwrite) {
+            error!("Failed to record quiet hours starting: {}", e);
+        }
+    }
+    info!("Quiet hours started in channel {}", channel_id);
+    let notice = format!("🌙 Quiet hours: this channel is {} until {}.", action.describe(), clock(window.end));
+    if let Err(why) = channel_id.say(http, notice).await {
+        error!("Failed to post quiet hours notice: {:?}", why);
+    }
+}
+
+/// Puts the channel back how it was before the window. The window counts as
+/// over even if Discord refuses, so a deleted channel isn't retried forever.
+async fn end(handler: &Handler, http: &Http, quiet_hours: &db::QuietHours) {
+    let Ok(channel_id) = quiet_hours.channel_id.parse::<u64>().map(ChannelId::new) else { return };
+    if let Some(secs) = quiet_hours.saved_slowmode {
+        if let Err(why) = channel_id.edit(http, EditChannel::new().rate_limit_per_user(secs)).await {
+            error!("Failed to restore slowmode in channel {}: {:?}", channel_id, why);
+        }
+    }
+    if let (true, Some(everyone)) = (quiet_hours.locked, everyone(&quiet_hours.guild_id)) {
+        let restored = match quiet_hours.saved_overwrite {
+            Some((allow, deny)) => {
+                let overwrite = PermissionOverwrite {
+                    allow: Permissions::from_bits_truncate(allow),
+                    deny: Permissions::from_bits_truncate(deny),
+                    kind: everyone,
+                };
+                channel_id.create_permission(http, overwrite).await
+            }
+            None => channel_id.delete_permission(http, everyone).await,
+        };
+        if let Err(why) = restored {
+            error!("Failed to unlock channel {}: {:?}", channel_id, why);
+        }
+    }
+    {
+        let conn = handler.db.lock().await;
+        if let Err(e) = db::end_quiet_hours(&conn, &quiet_hours.channel_id) {
+            error!("Failed to record quiet hours ending: {}", e);
+        }
+    }
+    info!("Quiet hours ended in channel {}", channel_id);
+    if let Err(why) = channel_id.say(http, "☀️ Quiet hours are over.").await {
+        error!("Failed to post quiet hours notice: {:?}", why);
+    }
+}
+
+/// The channel named in the arguments (or this one), if it's a text channel
+/// in `guild_id`.
+async fn target_channel(ctx: &Context, msg: &Message, guild_id: GuildId, mention: Option<&str>) -> Option<ChannelId> {
+    let channel_id = match mention {
+        Some(mention) => parse_channel_mention(mention)?,
+        None => msg.channel_id,
+    };
+    match channel_id.to_channel(&ctx.http).await {
+        Ok(Channel::Guild(channel)) if channel.guild_id == guild_id && channel.kind == ChannelType::Text => Some(channel_id),
+        _ => None,
+    }
+}
+
+async fn quiethours_command(handler: &Handler, ctx: &Context, msg: &Message, args: &Args) -> String {
+    let Some(guild_id) = msg.guild_id else {
+        return "Quiet hours can only be set in a server.".to_string();
+    };
+    let guild = guild_id.to_string();
+    if args.is_empty() {
+        let conn = handler.db.lock().await;
+        return match db::get_quiet_hours(&conn, Some(&guild)) {
+            Ok(list) if list.is_empty() => format!("No quiet hours set. {}", USAGE),
+            Ok(list) => {
+                let lines: Vec<String> = list
+                    .iter()
+                    .map(|q| {
+                        let window = Window {
+                            start: q.start_minute,
+                            end: q.end_minute,
+                        };
+                        let action = Action::from_slowmode_secs(q.slowmode).describe();
+                        let on = if q.active { " (now)" } else { "" };
+                        format!("  <#{}> {} `{}`{}", q.channel_id, action, window, on)
+                    })
+                    .collect();
+                format!(
+                    "**Quiet hours** (bot time, now {})\n{}",
+                    Local::now().format("%H:%M"),
+                    lines.join("\n")
+                )
+            }
+            Err(e) => {
+                error!("Failed to load quiet hours: {}", e);
+                "Failed to load quiet hours.".to_string()
+            }
+        };
+    }
+    if !bots::can_manage_guild(&ctx.http, guild_id, msg.author.id).await {
+        return "You need the Manage Server permission to set quiet hours.".to_string();
+    }
+
+    if args.get(0) == Some("off") {
+        let Some(channel_id) = target_channel(ctx, msg, guild_id, args.get(1)).await else {
+            return "That isn't a text channel in this server.".to_string();
+        };
+        let removed = {
+            let conn = handler.db.lock().await;
+            db::remove_quiet_hours(&conn, &channel_id.to_string())
+        };
+        return match removed {
+            Ok(Some(quiet_hours)) => {
+                if quiet_hours.active {
+                    end(handler, &ctx.http, &quiet_hours).await;
+                }
+                info!("{} removed quiet hours in channel {}", msg.author.name, channel_id);
+                format!("Removed quiet hours for <#{}>.", channel_id)
+            }
+            Ok(None) => format!("<#{}> has no quiet hours.", channel_id),
+            Err(e) => {
+                error!("Failed to remove quiet hours: {}", e);
+                "Failed to remove quiet hours.".to_string()
+            }
+        };
+    }
+
+    let Some(window) = args.get(0).and_then(Window::parse) else {
+        return USAGE.to_string();
+    };
+    let action = match args.flag_value("slowmode").map(|s| s.trim_end_matches('s').parse::<u16>()) {
+        Some(Ok(secs)) if (1..=MAX_SLOWMODE_SECS).contains(&secs) => Action::Slowmode(secs),
+        Some(_) => return format!("Slowmode has to be 1 to {} seconds.", MAX_SLOWMODE_SECS),
+        None => Action::Lock,
+    };
+    let Some(channel_id) = target_channel(ctx, msg, guild_id, args.get(1)).await else {
+        return "That isn't a text channel in this server.".to_string();
+    };
+    let conn = handler.db.lock().await;
+    match db::set_quiet_hours(
+        &conn,
+        &channel_id.to_string(),
+        &guild,
+        window.start,
+        window.end,
+        action.slowmode_secs(),
+        &msg.author.id.to_string(),
+    ) {
+        Ok(_) => {
+            info!("{} set quiet hours {} in channel {}", msg.author.name, window, channel_id);
+            format!(
+                "<#{}> will be {} every day from {} to {} (bot time, now {}).",
+                channel_id,
+                action.describe(),
+                clock(window.start),
+                clock(window.end),
+                Local::now().format("%H:%M")
+            )
+        }
+        Err(e) => {
+            error!("Failed to save quiet hours: {}", e);
+            "Failed to save quiet hours.".to_string()
+        }
+    }
+}
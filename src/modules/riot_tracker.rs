@@ -0,0 +1,160 @@
+use serenity::async_trait;
+use serenity::http::Http;
+use serenity::model::channel::Message;
+use serenity::model::id::ChannelId;
+use serenity::prelude::Context;
+use std::sync::Arc;
+use tracing::{error, info};
+
+use super::BotModule;
+use crate::args::Args;
+use crate::commands::Usage;
+use crate::help::Category;
+use crate::{db, Handler};
+
+const USAGE: &str = "Usage: `!lol add|remove|rank|matches <Name#TAG>`, `!lol list`, `!lol ladder` or `!lol announce here|off`";
+
+/// League of Legends tracking over the Riot API: the `!lol` commands and
+/// solo queue promotion announcements.
+pub struct RiotTracker;
+
+#[async_trait]
+impl BotModule for RiotTracker {
+    fn name(&self) -> &'static str {
+        "lol"
+    }
+
+    fn usage(&self) -> Vec<Usage> {
+        vec![
+            Usage::new(Category::Wow, "lol add|remove <Name#TAG>", "Track a League of Legends player (`!lol list`)"),
+            Usage::new(Category::Wow, "lol rank|matches <Name#TAG>", "Ranked standing or the last few games"),
+            Usage::new(Category::Wow, "lol ladder", "Tracked players by Solo/Duo rank (`!lol announce here|off` posts promotions)"),
+        ]
+    }
+
+    /// Posts tracked players' rank changes to the announcement channel.
+    async fn on_tick(&self, handler: &Handler, http: &Arc<Http>) {
+        if handler.riot.is_none() {
+            return;
+        }
+        let announcements = handler.poll_riot_ranks().await;
+        let channel = {
+            let conn = handler.db.lock().await;
+            db::get_config(&conn, None, "lol_announce_channel")
+                .ok()
+                .flatten()
+                .and_then(|v| v.parse::<u64>().ok())
+        };
+        let Some(channel) = channel.map(ChannelId::new) else {
+            return;
+        };
+        for announcement in &announcements {
+            if let Err(why) = channel.say(http, announcement).await {
+                error!("Failed to post rank change: {:?}", why);
+            }
+        }
+    }
+
+    async fn on_message(&self, handler: &Handler, ctx: &Context, msg: &Message, command: &str, args: &Args) -> bool {
+        if command != "lol" && !command.starts_with("lol ") {
+            return false;
+        }
+        // Riot IDs can have spaces, so the whole argument is the ID
+        let riot_id = args.raw();
+        let response = match command {
+            "lol add" | "lol remove" | "lol rank" | "lol matches" if riot_id.is_empty() => USAGE.to_string(),
+            "lol add" | "lol remove" | "lol rank" | "lol matches" | "lol list" | "lol ladder" if handler.riot.is_none() => {
+                "Riot API not configured.".to_string()
+            }
+            "lol add" => {
+                let typing = msg.channel_id.start_typing(&ctx.http);
+                let response = match handler.fetch_riot_account(riot_id).await {
+                    Ok(account) => {
+                        let conn = handler.db.lock().await;
+                        match db::add_riot_account(&conn, &account.puuid, &account.riot_id(), &msg.author.id.to_string()) {
+                            Ok(true) => {
+                                info!("{} started tracking {}", msg.author.name, account.riot_id());
+                                format!("Now tracking **{}**.", account.riot_id())
+                            }
+                            Ok(false) => format!("**{}** is already tracked.", account.riot_id()),
+                            Err(e) => {
+                                error!("DB error adding Riot account: {}", e);
+                                "Failed to save player.".to_string()
+                            }
+                        }
+                    }
+                    Err(e) => e,
+                };
+                drop(typing);
+                response
+            }
+            "lol remove" => {
+                let conn = handler.db.lock().await;
+                match db::remove_riot_account(&conn, riot_id) {
+                    Ok(true) => {
+                        info!("{} stopped tracking {}", msg.author.name, riot_id);
+                        format!("Stopped tracking **{}**.", riot_id)
+                    }
+                    Ok(false) => format!("**{}** isn't tracked.", riot_id),
+                    Err(e) => {
+                        error!("DB error removing Riot account: {}", e);
+                        "Failed to remove player.".to_string()
+                    }
+                }
+            }
+            "lol rank" => handler.riot_rank_report(riot_id).await,
+            "lol matches" => {
+                let typing = msg.channel_id.start_typing(&ctx.http);
+                let response = handler.riot_matches_report(riot_id).await;
+                drop(typing);
+                response
+            }
+            "lol list" => {
+                let conn = handler.db.lock().await;
+                match db::get_riot_accounts(&conn) {
+                    Ok(accounts) if accounts.is_empty() => {
+                        "No players tracked. Use `!lol add <Name#TAG>` to add one.".to_string()
+                    }
+                    Ok(accounts) => {
+                        let lines: Vec<String> = accounts
+                            .iter()
+                            .map(|a| format!("  **{}** (added by <@{}>)", a.riot_id, a.added_by))
+                            .collect();
+                        format!("**Tracked players**\n{}", lines.join("\n"))
+                    }
+                    Err(e) => {
+                        error!("DB error listing Riot accounts: {}", e);
+                        "Failed to load players.".to_string()
+                    }
+                }
+            }
+            "lol ladder" => {
+                let typing = msg.channel_id.start_typing(&ctx.http);
+                let response = handler.riot_ladder().await;
+                drop(typing);
+                response
+            }
+            "lol announce" => {
+                let conn = handler.db.lock().await;
+                let result = match args.get(0) {
+                    Some("here") => db::set_config(&conn, None, "lol_announce_channel", &msg.channel_id.to_string())
+                        .map(|_| "Solo queue promotions and demotions will be posted in this channel."),
+                    Some("off") => db::delete_config(&conn, None, "lol_announce_channel").map(|_| "Rank announcements disabled."),
+                    _ => Ok(USAGE),
+                };
+                match result {
+                    Ok(response) => response.to_string(),
+                    Err(e) => {
+                        error!("Failed to set rank announcement channel: {}", e);
+                        "Failed to save announcement channel.".to_string()
+                    }
+                }
+            }
+            _ => USAGE.to_string(),
+        };
+        if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+            error!("Error sending message: {:?}", why);
+        }
+        true
+    }
+}
@@ -0,0 +1,284 @@
+use serenity::async_trait;
+use serenity::builder::CreateMessage;
+use serenity::model::channel::Message;
+use serenity::prelude::Context;
+use tracing::{error, info};
+
+use super::BotModule;
+use crate::args::Args;
+use crate::bots::{self, Conversation};
+use crate::commands::Usage;
+use crate::help::Category;
+use crate::{db, markdown, mentions, trace, Handler};
+
+/// Most messages fed into the end-of-session summary.
+const TRANSCRIPT_LIMIT: usize = 200;
+
+const SUMMARY_PROMPT: &str = "You are summarizing a finished roleplay for the people who played it. \
+     Write a short recap of what happened, naming who did what. Use past tense.";
+
+/// Multi-participant roleplay: `!rp start <scenario>` makes every message in the
+/// channel a turn, labelled with the speaker's name, until `!rp end`.
+pub struct Roleplay;
+
+/// History key for a channel's session, apart from its normal chat history.
+fn history_key(channel_id: &str) -> String {
+    format!("rp:{}", channel_id)
+}
+
+/// Who the bot should hand over to after `speaker`, going round in turn order.
+fn next_speaker<'a>(session: &'a db::RpSession, speaker: &str) -> Option<&'a str> {
+    let position = session.participants.iter().position(|p| p == speaker)?;
+    let next = &session.participants[(position + 1) % session.participants.len()];
+    (next != speaker).then_some(next.as_str())
+}
+
+/// The system prompt addition describing the session to the LLM.
+fn session_notes(session: &db::RpSession, speaker: &str) -> String {
+    let mut notes = format!(
+        "You are running a roleplay with several people. Scenario: {}\n\
+         Each message is prefixed with the speaker's name, like `[Name]: ...`. \
+         Reply in character as the narrator and any side characters; never write lines for the participants.\n\
+         Participants in turn order: {}.",
+        session.scenario,
+        session.participants.join(", ")
+    );
+    if let Some(next) = next_speaker(session, speaker) {
+        notes.push_str(&format!(" End your reply by handing the scene to {}.", next));
+    }
+    notes
+}
+
+impl Roleplay {
+    async fn command(&self, handler: &Handler, msg: &Message, command: &str, args: &Args) -> String {
+        let channel_id = msg.channel_id.to_string();
+        match command {
+            "rp start" if !args.is_empty() => {
+                let conn = handler.db.lock().await;
+                match db::start_rp_session(&conn, &channel_id, args.raw(), &msg.author.id.to_string()) {
+                    Ok(true) => {
+                        // A fresh session starts from a clean transcript
+                        if let Err(e) = db::clear_messages(&conn, &history_key(&channel_id)) {
+                            error!("Failed to clear roleplay history: {}", e);
+                        }
+                        info!("{} started a roleplay in {}", msg.author.name, channel_id);
+                        format!(
+                            "🎭 **Roleplay started:** {}\nEveryone who speaks here joins in, in order. `!rp end` to finish.",
+                            args.raw()
+                        )
+                    }
+                    Ok(false) => "There's already a roleplay running here. `!rp end` it first.".to_string(),
+                    Err(e) => {
+                        error!("Failed to start roleplay: {}", e);
+                        "Failed to start the roleplay.".to_string()
+                    }
+                }
+            }
+            "rp end" => self.end(handler, &channel_id).await,
+            "rp" | "rp status" => {
+                let conn = handler.db.lock().await;
+                match db::get_rp_session(&conn, &channel_id) {
+                    Ok(Some(session)) => format!(
+                        "🎭 **Scenario:** {}\n**Turn order:** {}\n**Turns so far:** {}",
+                        session.scenario,
+                        if session.participants.is_empty() {
+                            "nobody yet".to_string()
+                        } else {
+                            session.participants.join(" → ")
+                        },
+                        session.turns
+                    ),
+                    Ok(None) => "No roleplay running here. Usage: `!rp start <scenario>`".to_string(),
+                    Err(e) => {
+                        error!("Failed to load roleplay: {}", e);
+                        "Failed to load the roleplay.".to_string()
+                    }
+                }
+            }
+            _ => "Usage: `!rp start <scenario>`, `!rp status` or `!rp end`".to_string(),
+        }
+    }
+
+    /// Ends the channel's session with a summary of its transcript.
+    async fn end(&self, handler: &Handler, channel_id: &str) -> String {
+        let key = history_key(channel_id);
+        let (session, transcript) = {
+            let conn = handler.db.lock().await;
+            let session = match db::get_rp_session(&conn, channel_id) {
+                Ok(Some(session)) => session,
+                Ok(None) => return "No roleplay running here.".to_string(),
+                Err(e) => {
+                    error!("Failed to load roleplay: {}", e);
+                    return "Failed to load the roleplay.".to_string();
+                }
+            };
+            let transcript: Vec<String> = db::get_recent_messages(&conn, &key, TRANSCRIPT_LIMIT)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|m| match m.role.as_str() {
+                    "assistant" => format!("[Narrator]: {}", m.content),
+                    _ => m.content,
+                })
+                .collect();
+            (session, transcript)
+        };
+
+        let summary = if transcript.is_empty() {
+            "Nobody got a word in.".to_string()
+        } else {
+            let request = format!("Scenario: {}\n\n{}", session.scenario, transcript.join("\n"));
+            match handler.query_llm_oneshot(SUMMARY_PROMPT.to_string(), request).await {
+                Ok(summary) => summary,
+                Err(e) => {
+                    error!("Failed to summarize roleplay: {}", e);
+                    "I couldn't write a summary this time.".to_string()
+                }
+            }
+        };
+
+        let conn = handler.db.lock().await;
+        if let Err(e) = db::end_rp_session(&conn, channel_id).and_then(|_| db::clear_messages(&conn, &key)) {
+            error!("Failed to end roleplay: {}", e);
+            return "Failed to end the roleplay.".to_string();
+        }
+        format!(
+            "🎭 **Roleplay over** after {} turns with {}.\n\n{}",
+            session.turns,
+            if session.participants.is_empty() {
+                "nobody".to_string()
+            } else {
+                session.participants.join(", ")
+            },
+            summary
+        )
+    }
+
+    /// Plays one turn: records the speaker and replies in the scene.
+    async fn turn(&self, handler: &Handler, ctx: &Context, msg: &Message, session: db::RpSession) {
+        let speaker = bots::speaker_name(&msg.author, msg.member.as_ref().and_then(|m| m.nick.as_deref()));
+        let key = history_key(&session.channel_id);
+        let session = {
+            let conn = handler.db.lock().await;
+            // Already played before a reconnect
+            if db::has_message(&conn, &key, &msg.id.to_string()).unwrap_or(false) {
+                info!("Ignoring already played roleplay turn {}", msg.id);
+                return;
+            }
+            match db::record_rp_turn(&conn, &session.channel_id, &speaker) {
+                Ok(Some(session)) => session,
+                Ok(None) => return,
+                Err(e) => {
+                    error!("Failed to record roleplay turn: {}", e);
+                    return;
+                }
+            }
+        };
+
+        let typing = msg.channel_id.start_typing(&ctx.http);
+        let mut conversation = Conversation {
+            guild_id: msg.guild_id,
+            channel_id: msg.channel_id,
+            key,
+            message_id: Some(msg.id),
+            user_id: Some(msg.author.id),
+            // Turns already carry the speaker's name
+            speaker: None,
+            notes: vec![session_notes(&session, &speaker)],
+        };
+        handler.add_preamble(&ctx.http, &mut conversation).await;
+        let line = format!("[{}]: {}", speaker, msg.content);
+        let response = match handler.ask_llama(&conversation, &line).await {
+            Ok(reply) => reply,
+            Err(e) => {
+                error!("LLM error: {}", e);
+                format!("Sorry, I couldn't get a response: {}{}", e, trace::error_ref())
+            }
+        };
+        drop(typing);
+
+        let allow_users = handler.allows_user_mentions(msg.guild_id).await;
+        let response = mentions::sanitize(&response, allow_users);
+        for part in markdown::split(&response, markdown::DISCORD_MESSAGE_MAX) {
+            let message = CreateMessage::new()
+                .content(part)
+                .allowed_mentions(mentions::allowed(allow_users));
+            if let Err(why) = msg.channel_id.send_message(&ctx.http, message).await {
+                error!("Error sending message: {:?}", why);
+                break;
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl BotModule for Roleplay {
+    fn name(&self) -> &'static str {
+        "roleplay"
+    }
+
+    fn usage(&self) -> Vec<Usage> {
+        vec![
+            Usage::new(Category::Chat, "rp start <scenario>", "Roleplay with everyone in the channel (`!rp status`, `!rp end` for a recap)"),
+        ]
+    }
+
+    async fn on_message(&self, handler: &Handler, ctx: &Context, msg: &Message, command: &str, args: &Args) -> bool {
+        if command == "rp" || command.starts_with("rp ") {
+            let response = self.command(handler, msg, command, args).await;
+            let response = markdown::truncate(&response, markdown::DISCORD_MESSAGE_MAX);
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        // Only the primary bot runs sessions, and commands are never turns
+        if !command.is_empty() || !handler.identity.is_primary() || msg.content.trim().is_empty() {
+            return false;
+        }
+        let session = {
+            let conn = handler.db.lock().await;
+            db::get_rp_session(&conn, &msg.channel_id.to_string()).ok().flatten()
+        };
+        let Some(session) = session else {
+            return false;
+        };
+        if handler.disabled_feature(msg.guild_id, "chat").await.is_some() {
+            return false;
+        }
+        self.turn(handler, ctx, msg, session).await;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(participants: &[&str]) -> db::RpSession {
+        db::RpSession {
+            channel_id: "1".to_string(),
+            scenario: "A heist".to_string(),
+            participants: participants.iter().map(|p| p.to_string()).collect(),
+            turns: 0,
+            started_by: "user1".to_string(),
+            started_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_next_speaker() {
+        let s = session(&["Joe", "Ann", "Bo"]);
+        assert_eq!(next_speaker(&s, "Joe"), Some("Ann"));
+        assert_eq!(next_speaker(&s, "Bo"), Some("Joe"));
+        assert_eq!(next_speaker(&session(&["Joe"]), "Joe"), None);
+    }
+
+    #[test]
+    fn test_session_notes() {
+        let notes = session_notes(&session(&["Joe", "Ann"]), "Joe");
+        assert!(notes.contains("Scenario: A heist"));
+        assert!(notes.contains("Participants in turn order: Joe, Ann."));
+        assert!(notes.ends_with("handing the scene to Ann."));
+    }
+}
@@ -0,0 +1,321 @@
+use serenity::async_trait;
+use serenity::builder::CreateMessage;
+use serenity::http::Http;
+use serenity::model::channel::Message;
+use serenity::model::id::{ChannelId, GuildId};
+use serenity::prelude::Context;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+use super::BotModule;
+use crate::args::Args;
+use crate::commands::Usage;
+use crate::help::Category;
+use crate::scheduler::unix_now;
+use crate::scripting::{self, Invocation, Trigger};
+use crate::{bots, db, markdown, mentions, Handler};
+
+const MAX_SCRIPT_NAME_LEN: usize = 32;
+const ADD_USAGE: &str = "Usage: `!script add <name> message <regex>` or `!script add <name> every <minutes>`, \
+     followed by the script in a code block";
+
+/// Admin-defined Rhai scripts: the `!script` commands, plus running message
+/// scripts on matching messages and scheduled scripts on the scheduler tick.
+pub struct Scripting;
+
+/// Splits `head ```rhai\ncode``` ` into the text before the code block and the
+/// code, dropping the language tag if there is one.
+fn split_code_block(raw: &str) -> Option<(&str, &str)> {
+    let (head, rest) = raw.split_once("```")?;
+    let (body, _) = rest.rsplit_once("```")?;
+    let code = match body.split_once('\n') {
+        Some((tag, code)) if tag.trim().chars().all(|c| c.is_ascii_alphanumeric()) => code,
+        _ => body,
+    };
+    Some((head.trim(), code.trim()))
+}
+
+fn describe_trigger(script: &db::Script) -> String {
+    match script.trigger.as_str() {
+        "schedule" => format!("every {} min in <#{}>", script.pattern, script.channel_id),
+        _ => format!("on messages matching `{}`", script.pattern),
+    }
+}
+
+/// Sends each script reply as one message; scripts have a reply budget, so
+/// overlong replies are cut rather than split. Replies can echo what users
+/// wrote, so they may ping users but never everyone or roles.
+async fn send_replies(http: &Http, channel_id: ChannelId, replies: &[String]) {
+    for reply in replies.iter().filter(|r| !r.trim().is_empty()) {
+        let reply = markdown::truncate(reply, markdown::DISCORD_MESSAGE_MAX);
+        let message = CreateMessage::new().content(reply).allowed_mentions(mentions::allowed(true));
+        if let Err(why) = channel_id.send_message(http, message).await {
+            error!("Error sending message: {:?}", why);
+        }
+    }
+}
+
+impl Scripting {
+    async fn add(&self, handler: &Handler, msg: &Message, guild_id: GuildId, args: &Args) -> String {
+        let Some((head, source)) = split_code_block(args.raw()) else {
+            return ADD_USAGE.to_string();
+        };
+        let head = Args::parse(head);
+        let (name, trigger, pattern) = match head.positional() {
+            [name, kind, pattern] if kind == "message" => (name, "message", pattern),
+            [name, kind, pattern] if kind == "every" => (name, "schedule", pattern),
+            _ => return ADD_USAGE.to_string(),
+        };
+        if name.len() > MAX_SCRIPT_NAME_LEN {
+            return format!("Script names can be at most {} characters.", MAX_SCRIPT_NAME_LEN);
+        }
+        if let Err(e) = Trigger::parse(trigger, pattern).and_then(|_| scripting::compile(source)) {
+            return e;
+        }
+
+        let script = db::Script {
+            guild_id: guild_id.to_string(),
+            name: name.to_string(),
+            trigger: trigger.to_string(),
+            pattern: pattern.to_string(),
+            channel_id: msg.channel_id.to_string(),
+            source: source.to_string(),
+            created_by: msg.author.name.clone(),
+            last_run: unix_now(),
+        };
+        let conn = handler.db.lock().await;
+        match db::save_script(&conn, &script) {
+            Ok(_) => {
+                info!("{} saved script {}", msg.author.name, name);
+                format!("Script **{}** saved — runs {}.", name, describe_trigger(&script))
+            }
+            Err(e) => {
+                error!("Failed to save script: {}", e);
+                "Failed to save script.".to_string()
+            }
+        }
+    }
+
+    /// Runs every script of the message's guild whose regex matches. Returns
+    /// whether any did.
+    async fn run_message_scripts(&self, handler: &Handler, ctx: &Context, msg: &Message, guild_id: GuildId) -> bool {
+        let scripts = {
+            let conn = handler.db.lock().await;
+            match scripts_of(&conn, guild_id, msg.channel_id) {
+                Ok(scripts) => scripts,
+                Err(e) => {
+                    error!("Failed to load scripts: {}", e);
+                    return false;
+                }
+            }
+        };
+
+        let mut matched = false;
+        for script in scripts.iter().filter(|s| s.trigger == "message") {
+            let Ok(Trigger::Message(regex)) = Trigger::parse(&script.trigger, &script.pattern) else {
+                continue;
+            };
+            let Some(captures) = regex.captures(&msg.content) else {
+                continue;
+            };
+            matched = true;
+
+            let invocation = Invocation {
+                message: msg.content.clone(),
+                author: msg.author.name.clone(),
+                channel: msg.channel_id.to_string(),
+                captures: captures
+                    .iter()
+                    .map(|c| c.map(|c| c.as_str().to_string()).unwrap_or_default())
+                    .collect(),
+            };
+            match handler.run_script(script, invocation).await {
+                Ok(replies) => send_replies(&ctx.http, msg.channel_id, &replies).await,
+                Err(e) => {
+                    warn!("Script {} failed: {}", script.name, e);
+                    let response = format!("Script **{}** failed: {}", script.name, e);
+                    if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                        error!("Error sending message: {:?}", why);
+                    }
+                }
+            }
+        }
+        matched
+    }
+}
+
+#[async_trait]
+impl BotModule for Scripting {
+    fn name(&self) -> &'static str {
+        "scripts"
+    }
+
+    fn usage(&self) -> Vec<Usage> {
+        vec![
+            Usage::new(Category::Admin, "script add <name> message <regex>|every <minutes>", "Add a Rhai script, with the code in a code block (Manage Server)"),
+            Usage::new(Category::Admin, "script list|show <name>|remove <name>", "Manage this server's scripts"),
+        ]
+    }
+
+    async fn on_message(&self, handler: &Handler, ctx: &Context, msg: &Message, command: &str, args: &Args) -> bool {
+        let is_script_command = command == "script" || command.starts_with("script ");
+        let Some(guild_id) = msg.guild_id else {
+            if is_script_command {
+                return reply(ctx, msg, "Scripts only work in servers.").await;
+            }
+            return false;
+        };
+        if matches!(command, "script add" | "script remove")
+            && !bots::can_manage_guild(&ctx.http, guild_id, msg.author.id).await
+        {
+            return reply(ctx, msg, "You need the Manage Server permission to change scripts.").await;
+        }
+
+        let response = match command {
+            "script add" => self.add(handler, msg, guild_id, args).await,
+            "script remove" => {
+                let Some(name) = args.get(0) else {
+                    return reply(ctx, msg, "Usage: `!script remove <name>`").await;
+                };
+                let conn = handler.db.lock().await;
+                match db::remove_script(&conn, &guild_id.to_string(), name) {
+                    Ok(true) => {
+                        info!("{} removed script {}", msg.author.name, name);
+                        format!("Script **{}** removed.", name)
+                    }
+                    Ok(false) => format!("No script named **{}**.", name),
+                    Err(e) => {
+                        error!("Failed to remove script: {}", e);
+                        "Failed to remove script.".to_string()
+                    }
+                }
+            }
+            "script list" => {
+                let conn = handler.db.lock().await;
+                match scripts_of(&conn, guild_id, msg.channel_id) {
+                    Ok(scripts) if scripts.is_empty() => "No scripts yet. Add one with `!script add`.".to_string(),
+                    Ok(scripts) => {
+                        let mut response = String::from("**Scripts:**\n");
+                        for script in &scripts {
+                            response.push_str(&format!(
+                                "  `{}` — {} (by {})\n",
+                                script.name,
+                                describe_trigger(script),
+                                script.created_by
+                            ));
+                        }
+                        response
+                    }
+                    Err(e) => {
+                        error!("Failed to list scripts: {}", e);
+                        "Failed to list scripts.".to_string()
+                    }
+                }
+            }
+            "script show" => {
+                let Some(name) = args.get(0) else {
+                    return reply(ctx, msg, "Usage: `!script show <name>`").await;
+                };
+                let conn = handler.db.lock().await;
+                match db::get_script(&conn, &guild_id.to_string(), name) {
+                    Ok(Some(script)) => format!(
+                        "**{}** runs {}:\n```rust\n{}\n```",
+                        script.name,
+                        describe_trigger(&script),
+                        script.source
+                    ),
+                    Ok(None) => format!("No script named **{}**.", name),
+                    Err(e) => {
+                        error!("Failed to load script: {}", e);
+                        "Failed to load script.".to_string()
+                    }
+                }
+            }
+            _ if is_script_command => {
+                "Usage: `!script add|remove|list|show [name]`".to_string()
+            }
+            // Extra bots don't run scripts, or every script would reply once per bot
+            _ if !handler.identity.is_primary() => return false,
+            _ => {
+                if handler.disabled_feature(msg.guild_id, "script").await.is_some() {
+                    return false;
+                }
+                return self.run_message_scripts(handler, ctx, msg, guild_id).await;
+            }
+        };
+        reply(ctx, msg, &response).await
+    }
+
+    async fn on_tick(&self, handler: &Handler, http: &Arc<Http>) {
+        let now = unix_now();
+        let due: Vec<db::Script> = {
+            let conn = handler.db.lock().await;
+            match db::get_scripts(&conn, None) {
+                Ok(scripts) => scripts
+                    .into_iter()
+                    .filter(|s| match Trigger::parse(&s.trigger, &s.pattern) {
+                        Ok(Trigger::Schedule(minutes)) => now - s.last_run >= minutes as i64 * 60,
+                        _ => false,
+                    })
+                    .collect(),
+                Err(e) => {
+                    error!("Failed to load scripts: {}", e);
+                    return;
+                }
+            }
+        };
+
+        for script in due {
+            {
+                let conn = handler.db.lock().await;
+                if let Err(e) = db::set_script_last_run(&conn, &script.guild_id, &script.name, now) {
+                    error!("Failed to record script run: {}", e);
+                }
+            }
+            let Ok(channel_id) = script.channel_id.parse::<u64>().map(ChannelId::new) else {
+                continue;
+            };
+            let invocation = Invocation {
+                channel: script.channel_id.clone(),
+                ..Default::default()
+            };
+            match handler.run_script(&script, invocation).await {
+                Ok(replies) => send_replies(http, channel_id, &replies).await,
+                Err(e) => warn!("Scheduled script {} failed: {}", script.name, e),
+            }
+        }
+    }
+}
+
+/// The guild's scripts, first claiming any from before scripts belonged to a
+/// guild that were added in this channel.
+fn scripts_of(conn: &rusqlite::Connection, guild_id: GuildId, channel_id: ChannelId) -> rusqlite::Result<Vec<db::Script>> {
+    let guild_id = guild_id.to_string();
+    db::claim_scripts(conn, &guild_id, &channel_id.to_string())?;
+    db::get_scripts(conn, Some(&guild_id))
+}
+
+async fn reply(ctx: &Context, msg: &Message, response: &str) -> bool {
+    if let Err(why) = msg.channel_id.say(&ctx.http, response).await {
+        error!("Error sending message: {:?}", why);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_code_block() {
+        assert_eq!(
+            split_code_block("greet message \"^hi$\"\n```rhai\nreply(\"hello\");\n```"),
+            Some(("greet message \"^hi$\"", "reply(\"hello\");"))
+        );
+        assert_eq!(
+            split_code_block("daily every 60 ```reply(\"tick\")```"),
+            Some(("daily every 60", "reply(\"tick\")"))
+        );
+        assert_eq!(split_code_block("greet message hi"), None);
+    }
+}
@@ -0,0 +1,191 @@
+use serenity::async_trait;
+use serenity::builder::CreateMessage;
+use serenity::model::channel::Message;
+use serenity::prelude::Context;
+use tracing::{error, info};
+
+use super::BotModule;
+use crate::args::Args;
+use crate::commands::Usage;
+use crate::help::Category;
+use crate::{bots, db, mentions, Handler};
+
+const USAGE: &str = "Usage: `!tag <name>`, `!tag add|edit <name> <content>`, `!tag remove|info <name>` or `!tag list`";
+
+/// Longest tag name, so `!tag list` stays readable.
+const MAX_NAME_CHARS: usize = 32;
+/// Longest tag, leaving room under Discord's 2000-character message limit.
+const MAX_CONTENT_CHARS: usize = 1900;
+
+/// Subcommands, which can't be tag names.
+const RESERVED: &[&str] = &["add", "edit", "remove", "list", "info"];
+
+/// Saved text, links and images per server: `!tag add <name> <content>` and
+/// `!tag <name>` to post it. Only the tag's owner or someone who can manage
+/// the server can change it.
+pub struct Tags;
+
+#[async_trait]
+impl BotModule for Tags {
+    fn name(&self) -> &'static str {
+        "tags"
+    }
+
+    fn usage(&self) -> Vec<Usage> {
+        vec![
+            Usage::new(Category::Fun, "tag add <name> <text or image>", "Save something to post later with `!tag <name>` (`!tag list` for all)"),
+            Usage::new(Category::Fun, "tag edit|remove <name>", "Change a tag (its owner or a server manager)"),
+        ]
+    }
+
+    async fn on_message(&self, handler: &Handler, ctx: &Context, msg: &Message, command: &str, args: &Args) -> bool {
+        if command != "tag" && !command.starts_with("tag ") {
+            return false;
+        }
+        let response = tag_command(handler, ctx, msg, command, args).await;
+        // Tags are written by anyone, so they never ping
+        let message = CreateMessage::new().content(response).allowed_mentions(mentions::allowed(false));
+        if let Err(why) = msg.channel_id.send_message(&ctx.http, message).await {
+            error!("Error sending message: {:?}", why);
+        }
+        true
+    }
+}
+
+/// The tag text after its name, plus the links of any attached images.
+fn tag_content(msg: &Message, rest: &str) -> String {
+    let mut parts: Vec<&str> = vec![rest.trim()];
+    parts.extend(msg.attachments.iter().map(|a| a.url.as_str()));
+    parts.retain(|p| !p.is_empty());
+    parts.join("\n")
+}
+
+async fn tag_command(handler: &Handler, ctx: &Context, msg: &Message, command: &str, args: &Args) -> String {
+    let Some(guild_id) = msg.guild_id else {
+        return "Tags can only be used in a server.".to_string();
+    };
+    let guild = guild_id.to_string();
+    // Tag names are one word; everything after is the content, as typed
+    let (name, rest) = args.raw().split_once(char::is_whitespace).unwrap_or((args.raw(), ""));
+
+    match command {
+        "tag add" if !name.is_empty() => {
+            if RESERVED.iter().any(|r| r.eq_ignore_ascii_case(name)) {
+                return format!("`{}` is a subcommand, so it can't be a tag name.", name);
+            }
+            if name.chars().count() > MAX_NAME_CHARS {
+                return format!("Tag names can be up to {} characters.", MAX_NAME_CHARS);
+            }
+            let content = tag_content(msg, rest);
+            if content.is_empty() {
+                return "Give the tag some text, a link or an attached image.".to_string();
+            }
+            if content.chars().count() > MAX_CONTENT_CHARS {
+                return format!("That's too long; tags can be up to {} characters.", MAX_CONTENT_CHARS);
+            }
+            let conn = handler.db.lock().await;
+            match db::add_tag(&conn, &guild, name, &content, &msg.author.id.to_string()) {
+                Ok(true) => {
+                    info!("{} added tag {:?} in guild {}", msg.author.name, name, guild_id);
+                    format!("Saved tag **{}**. Post it with `!tag {}`.", name, name)
+                }
+                Ok(false) => format!("There's already a tag called **{}**.", name),
+                Err(e) => {
+                    error!("Failed to add tag: {}", e);
+                    "Failed to save tag.".to_string()
+                }
+            }
+        }
+        "tag edit" | "tag remove" if !name.is_empty() => {
+            let tag = {
+                let conn = handler.db.lock().await;
+                db::get_tag(&conn, &guild, name)
+            };
+            let tag = match tag {
+                Ok(Some(tag)) => tag,
+                Ok(None) => return format!("No tag called **{}**.", name),
+                Err(e) => {
+                    error!("Failed to load tag: {}", e);
+                    return "Failed to load tag.".to_string();
+                }
+            };
+            if tag.owner != msg.author.id.to_string()
+                && !bots::can_manage_guild(&ctx.http, guild_id, msg.author.id).await
+                && !bots::is_owner(&ctx.http, msg.author.id).await
+            {
+                return format!("Only <@{}> or a server manager can change **{}**.", tag.owner, tag.name);
+            }
+
+            if command == "tag remove" {
+                let conn = handler.db.lock().await;
+                return match db::remove_tag(&conn, &guild, &tag.name) {
+                    Ok(_) => {
+                        info!("{} removed tag {:?} in guild {}", msg.author.name, tag.name, guild_id);
+                        format!("Removed tag **{}**.", tag.name)
+                    }
+                    Err(e) => {
+                        error!("Failed to remove tag: {}", e);
+                        "Failed to remove tag.".to_string()
+                    }
+                };
+            }
+            let content = tag_content(msg, rest);
+            if content.is_empty() {
+                return "Usage: `!tag edit <name> <content>`".to_string();
+            }
+            if content.chars().count() > MAX_CONTENT_CHARS {
+                return format!("That's too long; tags can be up to {} characters.", MAX_CONTENT_CHARS);
+            }
+            let conn = handler.db.lock().await;
+            match db::update_tag(&conn, &guild, &tag.name, &content) {
+                Ok(_) => {
+                    info!("{} edited tag {:?} in guild {}", msg.author.name, tag.name, guild_id);
+                    format!("Updated tag **{}**.", tag.name)
+                }
+                Err(e) => {
+                    error!("Failed to edit tag: {}", e);
+                    "Failed to save tag.".to_string()
+                }
+            }
+        }
+        "tag info" if !name.is_empty() => {
+            let conn = handler.db.lock().await;
+            match db::get_tag(&conn, &guild, name) {
+                Ok(Some(tag)) => format!("**{}** belongs to <@{}>.", tag.name, tag.owner),
+                Ok(None) => format!("No tag called **{}**.", name),
+                Err(e) => {
+                    error!("Failed to load tag: {}", e);
+                    "Failed to load tag.".to_string()
+                }
+            }
+        }
+        "tag list" => {
+            let conn = handler.db.lock().await;
+            match db::get_tag_names(&conn, &guild) {
+                Ok(names) if names.is_empty() => format!("No tags yet. {}", USAGE),
+                Ok(names) => format!("**Tags:** {}", names.join(", ")),
+                Err(e) => {
+                    error!("Failed to load tags: {}", e);
+                    "Failed to load tags.".to_string()
+                }
+            }
+        }
+        "tag" => USAGE.to_string(),
+        _ => {
+            // `!tag <name>` arrives as the group command "tag <name>"
+            let name = command.trim_start_matches("tag ");
+            if RESERVED.contains(&name) {
+                return USAGE.to_string();
+            }
+            let conn = handler.db.lock().await;
+            match db::get_tag(&conn, &guild, name) {
+                Ok(Some(tag)) => tag.content,
+                Ok(None) => format!("No tag called **{}**. See `!tag list`.", name),
+                Err(e) => {
+                    error!("Failed to load tag: {}", e);
+                    "Failed to load tag.".to_string()
+                }
+            }
+        }
+    }
+}
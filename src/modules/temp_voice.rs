@@ -0,0 +1,213 @@
+use serenity::async_trait;
+use serenity::builder::CreateChannel;
+use serenity::http::Http;
+use serenity::model::channel::{Channel, ChannelType, Message, PermissionOverwrite, PermissionOverwriteType};
+use serenity::model::guild::Guild;
+use serenity::model::id::{ChannelId, GuildId, UserId};
+use serenity::model::permissions::Permissions;
+use serenity::model::voice::VoiceState;
+use serenity::prelude::Context;
+use serenity::utils::parse_channel_mention;
+use tracing::{error, info};
+
+use super::BotModule;
+use crate::args::Args;
+use crate::commands::Usage;
+use crate::help::Category;
+use crate::{bots, db, Handler};
+
+const USAGE: &str = "Usage: `!tempvoice hub <#voice-channel>` or `!tempvoice off`";
+
+/// Guild config key for the "join to create" voice channel.
+const HUB_KEY: &str = "voice_hub";
+
+/// "Join to create" voice channels: joining the hub channel makes a personal
+/// one next to it, which its owner can manage and which is deleted once empty.
+pub struct TempVoice;
+
+#[async_trait]
+impl BotModule for TempVoice {
+    fn name(&self) -> &'static str {
+        "tempvoice"
+    }
+
+    fn usage(&self) -> Vec<Usage> {
+        vec![
+            Usage::new(Category::Admin, "tempvoice hub <#voice-channel>|off", "Joining the hub makes a personal voice channel, deleted once empty"),
+        ]
+    }
+
+    async fn on_voice_state(&self, handler: &Handler, ctx: &Context, state: &VoiceState) {
+        let Some(guild_id) = state.guild_id else { return };
+        if !handler.identity.is_primary() {
+            return;
+        }
+        let (emptied, hub) = {
+            let conn = handler.db.lock().await;
+            let emptied = db::set_temp_channel_member(&conn, &state.user_id.to_string(), state.channel_id.map(|c| c.to_string()).as_deref());
+            let hub = db::get_guild_config(&conn, &guild_id.to_string(), HUB_KEY).ok().flatten();
+            (emptied, hub)
+        };
+        match emptied {
+            Ok(emptied) => {
+                for channel in emptied {
+                    delete_temp_channel(handler, &ctx.http, &channel).await;
+                }
+            }
+            Err(e) => error!("Failed to update temp channel members: {}", e),
+        }
+        let joined_hub = state.channel_id.is_some_and(|c| hub.as_deref() == Some(c.to_string().as_str()));
+        if let (true, Some(hub)) = (joined_hub, state.channel_id) {
+            let name = state.member.as_ref().map(|m| m.display_name().to_string());
+            create_temp_channel(handler, &ctx.http, guild_id, hub, state.user_id, name.as_deref()).await;
+        }
+    }
+
+    /// Catches up on what happened while the bot was offline: temp channels
+    /// that emptied are deleted, and the others' members are refreshed.
+    async fn on_guild_create(&self, handler: &Handler, ctx: &Context, guild: &Guild) {
+        if !handler.identity.is_primary() {
+            return;
+        }
+        let channels = {
+            let conn = handler.db.lock().await;
+            match db::get_temp_channels(&conn, &guild.id.to_string()) {
+                Ok(channels) => channels,
+                Err(e) => {
+                    error!("Failed to load temp channels: {}", e);
+                    return;
+                }
+            }
+        };
+        for channel in channels {
+            let occupants: Vec<String> = guild
+                .voice_states
+                .values()
+                .filter(|s| s.channel_id.is_some_and(|c| c.to_string() == channel))
+                .map(|s| s.user_id.to_string())
+                .collect();
+            if occupants.is_empty() {
+                delete_temp_channel(handler, &ctx.http, &channel).await;
+                continue;
+            }
+            let conn = handler.db.lock().await;
+            if let Err(e) = db::reset_temp_channel_members(&conn, &channel, &occupants) {
+                error!("Failed to refresh temp channel members: {}", e);
+            }
+        }
+    }
+
+    async fn on_message(&self, handler: &Handler, ctx: &Context, msg: &Message, command: &str, args: &Args) -> bool {
+        if command != "tempvoice" && !command.starts_with("tempvoice ") {
+            return false;
+        }
+        let response = tempvoice_command(handler, ctx, msg, command, args).await;
+        if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+            error!("Error sending message: {:?}", why);
+        }
+        true
+    }
+}
+
+/// Makes `owner` a voice channel in the hub's category and moves them into it.
+async fn create_temp_channel(handler: &Handler, http: &Http, guild_id: GuildId, hub: ChannelId, owner: UserId, name: Option<&str>) {
+    let category = match hub.to_channel(http).await {
+        Ok(Channel::Guild(channel)) => channel.parent_id,
+        _ => None,
+    };
+    let owner_permissions = PermissionOverwrite {
+        allow: Permissions::MANAGE_CHANNELS | Permissions::MOVE_MEMBERS,
+        deny: Permissions::empty(),
+        kind: PermissionOverwriteType::Member(owner),
+    };
+    let mut builder = CreateChannel::new(format!("{}'s channel", name.unwrap_or("Someone")))
+        .kind(ChannelType::Voice)
+        .permissions(vec![owner_permissions]);
+    if let Some(category) = category {
+        builder = builder.category(category);
+    }
+    let channel = match guild_id.create_channel(http, builder).await {
+        Ok(channel) => channel,
+        Err(why) => {
+            error!("Failed to create temp voice channel in guild {}: {:?}", guild_id, why);
+            return;
+        }
+    };
+    {
+        let conn = handler.db.lock().await;
+        if let Err(e) = db::add_temp_channel(&conn, &channel.id.to_string(), &guild_id.to_string(), &owner.to_string()) {
+            error!("Failed to record temp channel: {}", e);
+        }
+    }
+    if let Err(why) = guild_id.move_member(http, owner, channel.id).await {
+        // They left the hub before the move, so nobody will ever empty it
+        error!("Failed to move {} into their temp channel: {:?}", owner, why);
+        delete_temp_channel(handler, http, &channel.id.to_string()).await;
+        return;
+    }
+    info!("Created temp voice channel {} for {} in guild {}", channel.id, owner, guild_id);
+}
+
+/// Deletes a temp channel from Discord and stops tracking it. It's forgotten
+/// even if Discord refuses, since someone may have deleted it by hand.
+async fn delete_temp_channel(handler: &Handler, http: &Http, channel: &str) {
+    if let Ok(id) = channel.parse::<u64>() {
+        if let Err(why) = ChannelId::new(id).delete(http).await {
+            error!("Failed to delete temp voice channel {}: {:?}", channel, why);
+        }
+    }
+    let conn = handler.db.lock().await;
+    if let Err(e) = db::remove_temp_channel(&conn, channel) {
+        error!("Failed to forget temp channel: {}", e);
+    }
+}
+
+async fn tempvoice_command(handler: &Handler, ctx: &Context, msg: &Message, command: &str, args: &Args) -> String {
+    let Some(guild_id) = msg.guild_id else {
+        return "Temp voice channels can only be set up in a server.".to_string();
+    };
+    let guild = guild_id.to_string();
+    if command != "tempvoice" && !bots::can_manage_guild(&ctx.http, guild_id, msg.author.id).await {
+        return "You need the Manage Server permission to set up temp voice channels.".to_string();
+    }
+    match command {
+        "tempvoice hub" => {
+            let Some(hub) = args.get(0).and_then(parse_channel_mention) else {
+                return USAGE.to_string();
+            };
+            match hub.to_channel(&ctx.http).await {
+                Ok(Channel::Guild(channel)) if channel.guild_id == guild_id && channel.kind == ChannelType::Voice => {}
+                _ => return "The hub has to be a voice channel in this server.".to_string(),
+            }
+            let conn = handler.db.lock().await;
+            match db::set_guild_config(&conn, &guild, HUB_KEY, &hub.to_string()) {
+                Ok(_) => {
+                    info!("{} set the temp voice hub to {} in guild {}", msg.author.name, hub, guild_id);
+                    format!("Joining <#{}> now creates a temp voice channel, deleted once it's empty.", hub)
+                }
+                Err(e) => {
+                    error!("Failed to set temp voice hub: {}", e);
+                    "Failed to save hub channel.".to_string()
+                }
+            }
+        }
+        "tempvoice off" => {
+            let conn = handler.db.lock().await;
+            match db::delete_guild_config(&conn, &guild, HUB_KEY) {
+                Ok(_) => "Temp voice channels disabled.".to_string(),
+                Err(e) => {
+                    error!("Failed to clear temp voice hub: {}", e);
+                    "Failed to disable temp voice channels.".to_string()
+                }
+            }
+        }
+        "tempvoice" => {
+            let conn = handler.db.lock().await;
+            match db::get_guild_config(&conn, &guild, HUB_KEY).ok().flatten() {
+                Some(hub) => format!("Joining <#{}> creates a temp voice channel. {}", hub, USAGE),
+                None => format!("Temp voice channels are off. {}", USAGE),
+            }
+        }
+        _ => USAGE.to_string(),
+    }
+}
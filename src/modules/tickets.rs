@@ -0,0 +1,229 @@
+use serenity::async_trait;
+use serenity::builder::{CreateAllowedMentions, CreateAttachment, CreateMessage, CreateThread, EditThread};
+use serenity::model::channel::{ChannelType, Message};
+use serenity::model::id::{GuildId, RoleId};
+use serenity::model::mention::Mentionable;
+use serenity::prelude::Context;
+use tracing::{error, info};
+
+use super::BotModule;
+use crate::args::Args;
+use crate::commands::Usage;
+use crate::help::Category;
+use crate::scheduler::unix_now;
+use crate::{bots, db, Handler};
+
+/// Discord's limit on thread names.
+const MAX_THREAD_NAME_LEN: usize = 100;
+const USAGE: &str = "Usage: `!ticket <subject>`, `!ticket close`, `!ticket transcript <number>` or `!ticket role <@role>`";
+
+/// Support tickets: `!ticket <subject>` opens a private thread with the
+/// server's support role, everything said in it is logged, and `!ticket close`
+/// archives the thread and keeps the transcript.
+pub struct Tickets;
+
+fn support_role(conn: &rusqlite::Connection, guild_id: GuildId) -> Option<RoleId> {
+    let role = db::get_guild_config(conn, &guild_id.to_string(), "ticket_role").ok().flatten()?;
+    role.parse().ok().map(RoleId::new)
+}
+
+/// Whether the author of `msg` has the support role.
+fn is_support(msg: &Message, role: Option<RoleId>) -> bool {
+    match (role, &msg.member) {
+        (Some(role), Some(member)) => member.roles.contains(&role),
+        _ => false,
+    }
+}
+
+impl Tickets {
+    async fn open(&self, handler: &Handler, ctx: &Context, msg: &Message, guild_id: GuildId, subject: &str) -> Result<(), String> {
+        let role = {
+            let conn = handler.db.lock().await;
+            support_role(&conn, guild_id)
+        };
+        let Some(role) = role else {
+            return Err("Tickets aren't set up. An admin can run `!ticket role <@role>` first.".to_string());
+        };
+
+        let name: String = format!("Ticket: {}", subject).chars().take(MAX_THREAD_NAME_LEN).collect();
+        let builder = CreateThread::new(name).kind(ChannelType::PrivateThread).invitable(false);
+        let thread = msg.channel_id.create_thread(&ctx.http, builder).await.map_err(|e| {
+            error!("Failed to create ticket thread: {:?}", e);
+            "I couldn't open a private thread here. Do I have permission to create them?".to_string()
+        })?;
+        if let Err(e) = thread.id.add_thread_member(&ctx.http, msg.author.id).await {
+            error!("Failed to add {} to ticket thread: {:?}", msg.author.name, e);
+        }
+
+        let number = {
+            let conn = handler.db.lock().await;
+            db::open_ticket(&conn, &guild_id.to_string(), &thread.id.to_string(), &msg.author.id.to_string(), subject)
+                .map_err(|e| {
+                    error!("Failed to save ticket: {}", e);
+                    "Failed to save the ticket.".to_string()
+                })?
+        };
+        info!("{} opened ticket #{}: {}", msg.author.name, number, subject);
+
+        // Mentioning the role adds its members to the private thread
+        let greeting = CreateMessage::new()
+            .content(format!(
+                "🎫 **Ticket #{}** from {}: {}\n{}, please take a look. Everything said here is kept for the record; \
+                 run `!ticket close` when it's sorted.",
+                number,
+                msg.author.mention(),
+                subject,
+                role.mention()
+            ))
+            .allowed_mentions(CreateAllowedMentions::new().users([msg.author.id]).roles([role]));
+        if let Err(why) = thread.id.send_message(&ctx.http, greeting).await {
+            error!("Error sending message: {:?}", why);
+        }
+        Ok(())
+    }
+
+    async fn close(&self, handler: &Handler, ctx: &Context, msg: &Message, guild_id: GuildId) -> String {
+        let conn = handler.db.lock().await;
+        let ticket = match db::get_ticket_by_thread(&conn, &msg.channel_id.to_string()) {
+            Ok(Some(ticket)) if ticket.closed_at.is_none() => ticket,
+            Ok(_) => return "Run `!ticket close` inside an open ticket's thread.".to_string(),
+            Err(e) => {
+                error!("Failed to load ticket: {}", e);
+                return "Failed to load the ticket.".to_string();
+            }
+        };
+        if ticket.user_id != msg.author.id.to_string() && !is_support(msg, support_role(&conn, guild_id)) {
+            return "Only whoever opened the ticket or the support role can close it.".to_string();
+        }
+        if let Err(e) = db::close_ticket(&conn, ticket.id, unix_now()) {
+            error!("Failed to close ticket: {}", e);
+            return "Failed to close the ticket.".to_string();
+        }
+        drop(conn);
+        info!("{} closed ticket #{}", msg.author.name, ticket.id);
+
+        let response = format!("Ticket #{} closed. The transcript has been saved.", ticket.id);
+        if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+            error!("Error sending message: {:?}", why);
+        }
+        if let Err(e) = msg.channel_id.edit_thread(&ctx.http, EditThread::new().archived(true).locked(true)).await {
+            error!("Failed to archive ticket thread: {:?}", e);
+        }
+        String::new()
+    }
+
+    /// Sends a closed ticket's transcript as a file, to the support role only.
+    async fn transcript(&self, handler: &Handler, ctx: &Context, msg: &Message, guild_id: GuildId, args: &Args) -> String {
+        let Some(Ok(number)) = args.parsed::<i64>(1) else {
+            return "Usage: `!ticket transcript <number>`".to_string();
+        };
+        let ticket = {
+            let conn = handler.db.lock().await;
+            if !is_support(msg, support_role(&conn, guild_id)) {
+                return "Only the support role can read ticket transcripts.".to_string();
+            }
+            match db::get_ticket(&conn, &guild_id.to_string(), number) {
+                Ok(Some(ticket)) => ticket,
+                Ok(None) => return format!("There's no ticket #{}.", number),
+                Err(e) => {
+                    error!("Failed to load ticket: {}", e);
+                    return "Failed to load the ticket.".to_string();
+                }
+            }
+        };
+        let Some(transcript) = ticket.transcript else {
+            return format!("Ticket #{} is still open in <#{}>.", number, ticket.thread_id);
+        };
+        let message = CreateMessage::new()
+            .content(format!(
+                "Transcript of ticket #{} from <@{}>, opened <t:{}:f>: {}",
+                number, ticket.user_id, ticket.created_at, ticket.subject
+            ))
+            .add_file(CreateAttachment::bytes(transcript.into_bytes(), format!("ticket-{}.txt", number)))
+            .allowed_mentions(CreateAllowedMentions::new());
+        if let Err(why) = msg.channel_id.send_message(&ctx.http, message).await {
+            error!("Error sending message: {:?}", why);
+        }
+        String::new()
+    }
+
+    /// Logs a message said in an open ticket's thread.
+    async fn log(&self, handler: &Handler, msg: &Message) {
+        let conn = handler.db.lock().await;
+        let Ok(Some(ticket)) = db::get_ticket_by_thread(&conn, &msg.channel_id.to_string()) else {
+            return;
+        };
+        if ticket.closed_at.is_some() {
+            return;
+        }
+        let author = bots::speaker_name(&msg.author, msg.member.as_ref().and_then(|m| m.nick.as_deref()));
+        if let Err(e) = db::log_ticket_message(&conn, ticket.id, &author, &msg.content) {
+            error!("Failed to log ticket message: {}", e);
+        }
+    }
+}
+
+#[async_trait]
+impl BotModule for Tickets {
+    fn name(&self) -> &'static str {
+        "tickets"
+    }
+
+    fn usage(&self) -> Vec<Usage> {
+        vec![
+            Usage::new(Category::Admin, "ticket role <@role>", "Who handles `!ticket` threads (Manage Server)"),
+            Usage::new(Category::Admin, "ticket transcript <number>", "Transcript of a closed ticket (support role)"),
+            Usage::new(Category::Fun, "ticket <subject>", "Open a private support thread (`!ticket close` when done)"),
+        ]
+    }
+
+    async fn on_message(&self, handler: &Handler, ctx: &Context, msg: &Message, command: &str, args: &Args) -> bool {
+        let Some(guild_id) = msg.guild_id else {
+            return false;
+        };
+        self.log(handler, msg).await;
+        if command != "ticket" {
+            return false;
+        }
+
+        let response = match args.get(0) {
+            None => USAGE.to_string(),
+            Some("close") => self.close(handler, ctx, msg, guild_id).await,
+            Some("transcript") => self.transcript(handler, ctx, msg, guild_id, args).await,
+            // The support role reads every ticket, so only managers pick it
+            Some("role") if !bots::can_manage_guild(&ctx.http, guild_id, msg.author.id).await => {
+                "You need the Manage Server permission to set the support role.".to_string()
+            }
+            Some("role") => match args.get(1).and_then(|r| r.trim_start_matches("<@&").trim_end_matches('>').parse::<u64>().ok()) {
+                Some(role) => {
+                    let conn = handler.db.lock().await;
+                    match db::set_guild_config(&conn, &guild_id.to_string(), "ticket_role", &role.to_string()) {
+                        Ok(_) => {
+                            info!("{} set the ticket support role to {}", msg.author.name, role);
+                            format!("Tickets will go to {}.", RoleId::new(role).mention())
+                        }
+                        Err(e) => {
+                            error!("Failed to set ticket role: {}", e);
+                            "Failed to save the support role.".to_string()
+                        }
+                    }
+                }
+                None => "Usage: `!ticket role <@role>`".to_string(),
+            },
+            Some(_) => match self.open(handler, ctx, msg, guild_id, args.raw().trim()).await {
+                Ok(()) => String::new(),
+                Err(e) => e,
+            },
+        };
+        if response.is_empty() {
+            return true;
+        }
+        let message = CreateMessage::new()
+            .content(response)
+            .allowed_mentions(CreateAllowedMentions::new());
+        if let Err(why) = msg.channel_id.send_message(&ctx.http, message).await {
+            error!("Error sending message: {:?}", why);
+        }
+        true
+    }
+}
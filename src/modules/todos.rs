@@ -0,0 +1,150 @@
+use serenity::async_trait;
+use serenity::builder::CreateMessage;
+use serenity::http::Http;
+use serenity::model::channel::Message;
+use serenity::model::id::ChannelId;
+use serenity::prelude::Context;
+use std::sync::Arc;
+use tracing::{error, info};
+
+use super::BotModule;
+use crate::args::Args;
+use crate::commands::Usage;
+use crate::help::Category;
+use crate::scheduler::unix_now;
+use crate::{db, mentions, todos, Handler};
+
+const USAGE: &str = "Usage: `!todo add <text> [--due=2d|2024-06-01|2024-06-01T19:30]`, `!todo list [all]` or `!todo done <number>`";
+
+/// Longest todo, so lists stay readable.
+const MAX_TODO_CHARS: usize = 200;
+
+/// Todo lists kept in the channel where the planning happens: `!todo add`,
+/// `!todo list` and `!todo done`, with a reminder when a due date passes.
+pub struct Todos;
+
+#[async_trait]
+impl BotModule for Todos {
+    fn name(&self) -> &'static str {
+        "todos"
+    }
+
+    fn usage(&self) -> Vec<Usage> {
+        vec![
+            Usage::new(Category::Fun, "todo add <text> [--due=2d|2024-06-01]", "Add a todo in this channel; I'll remind you here when it's due"),
+            Usage::new(Category::Fun, "todo list [all]", "Your todos here (or everyone's)"),
+            Usage::new(Category::Fun, "todo done <number>", "Tick off one of your todos"),
+        ]
+    }
+
+    /// Reminds people of todos that have fallen due, in the todo's channel.
+    async fn on_tick(&self, handler: &Handler, http: &Arc<Http>) {
+        let now = unix_now();
+        let due = {
+            let conn = handler.db.lock().await;
+            match db::due_todos(&conn, now) {
+                Ok(due) => due,
+                Err(e) => {
+                    error!("Failed to load due todos: {}", e);
+                    return;
+                }
+            }
+        };
+        for todo in &due {
+            let Ok(channel) = todo.channel_id.parse::<u64>() else { continue };
+            let message = CreateMessage::new()
+                .content(todos::reminder(todo))
+                .allowed_mentions(mentions::allowed(true));
+            if let Err(why) = ChannelId::new(channel).send_message(http, message).await {
+                error!("Failed to post todo reminder: {:?}", why);
+            }
+            // Marked either way, so a deleted channel doesn't retry forever
+            let conn = handler.db.lock().await;
+            if let Err(e) = db::mark_todo_reminded(&conn, todo.id, now) {
+                error!("Failed to mark todo reminded: {}", e);
+            }
+        }
+    }
+
+    async fn on_message(&self, handler: &Handler, ctx: &Context, msg: &Message, command: &str, args: &Args) -> bool {
+        if command != "todo" && !command.starts_with("todo ") {
+            return false;
+        }
+        let response = todo_command(handler, msg, command, args).await;
+        // Todo text is written by anyone, so it never pings
+        let message = CreateMessage::new().content(response).allowed_mentions(mentions::allowed(false));
+        if let Err(why) = msg.channel_id.send_message(&ctx.http, message).await {
+            error!("Error sending message: {:?}", why);
+        }
+        true
+    }
+}
+
+async fn todo_command(handler: &Handler, msg: &Message, command: &str, args: &Args) -> String {
+    let channel_id = msg.channel_id.to_string();
+    let user_id = msg.author.id.to_string();
+    match command {
+        "todo add" => {
+            let text = args.positional().join(" ");
+            if text.is_empty() {
+                return USAGE.to_string();
+            }
+            if text.chars().count() > MAX_TODO_CHARS {
+                return format!("That's too long; todos can be up to {} characters.", MAX_TODO_CHARS);
+            }
+            let due_at = match args.flag_value("due") {
+                Some(due) => match todos::parse_due(due, unix_now()) {
+                    Some(at) => Some(at),
+                    None => return format!("Couldn't read the due date `{}`. {}", due, USAGE),
+                },
+                None => None,
+            };
+            let conn = handler.db.lock().await;
+            match db::add_todo(&conn, &channel_id, &user_id, &text, due_at) {
+                Ok(id) => {
+                    info!("{} added todo #{} in channel {}", msg.author.name, id, channel_id);
+                    match due_at {
+                        Some(at) => format!("Added todo `#{}`, due <t:{}:R>. I'll remind you here.", id, at),
+                        None => format!("Added todo `#{}`.", id),
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to add todo: {}", e);
+                    "Failed to save todo.".to_string()
+                }
+            }
+        }
+        "todo" | "todo list" => {
+            let everyone = args.get(0) == Some("all");
+            let conn = handler.db.lock().await;
+            match db::get_open_todos(&conn, &channel_id, (!everyone).then_some(user_id.as_str())) {
+                Ok(list) if list.is_empty() => format!("Nothing to do here. {}", USAGE),
+                Ok(list) => {
+                    let lines: Vec<String> = list.iter().map(|todo| todos::todo_line(todo, everyone)).collect();
+                    let title = if everyone { "Todos in this channel" } else { "Your todos here" };
+                    format!("**{}**\n{}", title, lines.join("\n"))
+                }
+                Err(e) => {
+                    error!("Failed to load todos: {}", e);
+                    "Failed to load todos.".to_string()
+                }
+            }
+        }
+        "todo done" => {
+            let id = match args.get(0).map(|id| id.trim_start_matches('#').parse::<i64>()) {
+                Some(Ok(id)) => id,
+                _ => return "Usage: `!todo done <number>` (the number from `!todo list`)".to_string(),
+            };
+            let conn = handler.db.lock().await;
+            match db::complete_todo(&conn, &channel_id, &user_id, id) {
+                Ok(true) => format!("Done with `#{}`. ✅", id),
+                Ok(false) => format!("You have no open todo `#{}` in this channel.", id),
+                Err(e) => {
+                    error!("Failed to complete todo: {}", e);
+                    "Failed to update todo.".to_string()
+                }
+            }
+        }
+        _ => USAGE.to_string(),
+    }
+}
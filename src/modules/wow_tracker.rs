@@ -0,0 +1,1058 @@
+use serenity::async_trait;
+use serenity::builder::{
+    CreateActionRow, CreateAttachment, CreateMessage, CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption,
+};
+use serenity::http::Http;
+use serenity::model::channel::Message;
+use serenity::model::id::ChannelId;
+use serenity::prelude::Context;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+use super::BotModule;
+use crate::args::Args;
+use crate::commands::Usage;
+use crate::events::BotEvent;
+use crate::help::Category;
+use crate::scheduler::{unix_now, WEEK_SECS};
+use crate::{attunement, bots, checklist, db, export, insults, interactions, retry, wow, Handler, SELECT_MENU_MAX_OPTIONS};
+
+fn unknown_version(value: &str) -> String {
+    let names: Vec<_> = wow::GameVersion::ALL.iter().map(|v| format!("`{}`", v.name())).collect();
+    format!("Unknown game version `{}`. Try {}.", value, names.join(", "))
+}
+
+/// Reads the optional `--version=<game version>` flag.
+fn version_flag(args: &Args) -> Result<Option<wow::GameVersion>, String> {
+    match args.flag_value("version") {
+        Some(value) => wow::GameVersion::from_name(value)
+            .map(Some)
+            .ok_or_else(|| unknown_version(value)),
+        None => Ok(None),
+    }
+}
+
+const CHECKLIST_USAGE: &str =
+    "Usage: `!checklist <name>`, `!checklist summary` or `!checklist template <class|all> [add|remove <item>]`";
+
+/// `!checklist template <class|all> [add|remove <item>]`: shows or edits a class's checklist.
+async fn checklist_template(handler: &Handler, msg: &Message, class: &str, args: &Args) -> String {
+    let item = args.positional().get(3..).unwrap_or_default().join(" ");
+    let conn = handler.db.lock().await;
+    let result = match (args.get(2), item.is_empty()) {
+        (None, _) => {
+            return match db::get_checklist_items(&conn, class) {
+                Ok(items) if items.is_empty() => format!("The {} checklist is empty.", class),
+                Ok(items) => {
+                    let lines: Vec<_> = items.iter().enumerate().map(|(i, item)| format!("  {}. {}", i + 1, item)).collect();
+                    format!("**Checklist — {}**\n{}", class, lines.join("\n"))
+                }
+                Err(e) => {
+                    error!("Failed to load {} checklist: {}", class, e);
+                    "Failed to load the checklist.".to_string()
+                }
+            };
+        }
+        (Some("add"), false) => match db::get_checklist_items(&conn, class) {
+            Ok(items) if items.len() >= checklist::MAX_ITEMS => {
+                return format!("A checklist holds at most {} items.", checklist::MAX_ITEMS);
+            }
+            _ => db::add_checklist_item(&conn, class, &item).map(|added| match added {
+                true => format!("Added **{}** to the {} checklist.", item, class),
+                false => format!("**{}** is already on the {} checklist.", item, class),
+            }),
+        },
+        (Some("remove"), false) => db::remove_checklist_item(&conn, class, &item).map(|removed| match removed {
+            true => format!("Removed **{}** from the {} checklist.", item, class),
+            false => format!("**{}** isn't on the {} checklist.", item, class),
+        }),
+        _ => return CHECKLIST_USAGE.to_string(),
+    };
+    match result {
+        Ok(response) => {
+            info!("{} edited the {} checklist: {}", msg.author.name, class, args.raw());
+            response
+        }
+        Err(e) => {
+            error!("Failed to edit {} checklist: {}", class, e);
+            "Failed to save the checklist.".to_string()
+        }
+    }
+}
+
+/// WoW character tracking: the `!character` commands, level checks, reports
+/// and charts.
+pub struct WowTracker;
+
+#[async_trait]
+impl BotModule for WowTracker {
+    fn name(&self) -> &'static str {
+        "wow"
+    }
+
+    fn usage(&self) -> Vec<Usage> {
+        vec![
+            Usage::new(Category::Wow, "character add <name> [--version=<v>]", "Track a WoW character"),
+            Usage::new(Category::Wow, "character remove [name]", "Stop tracking a character (pick from a list if no name)"),
+            Usage::new(Category::Wow, "character list [page]", "Show tracked characters and who added them"),
+            Usage::new(Category::Wow, "character info <name> [--version=<v>]", "Look up a character (with a character card)"),
+            Usage::new(Category::Wow, "character version <name> <era|anniversary|cata|retail|default>", "Set a character's game version"),
+            Usage::new(Category::Wow, "character export [csv|json]", "Download levels and snapshot history"),
+            Usage::new(Category::Wow, "professions <name>", "Show a character's professions"),
+            Usage::new(Category::Wow, "crafters <profession>", "Who in the roster has a profession"),
+            Usage::new(Category::Wow, "rep <name> [faction]", "Reputation with key factions (or one faction)"),
+            Usage::new(Category::Wow, "pvp <name>", "Honorable kills and honor level"),
+            Usage::new(Category::Wow, "wowtoken", "Current retail WoW token price, with recent history"),
+            Usage::new(Category::Wow, "pets|mounts <name>", "How many a character has collected, and the newest"),
+            Usage::new(Category::Wow, "attune <name> <mc|ony|bwl> [step...|all] [--undo]", "A character's attunement checklist; tick steps off"),
+            Usage::new(Category::Wow, "attunements [raid]", "Who in the roster still needs attuning"),
+            Usage::new(Category::Wow, "checklist <name>", "A character's raid checklist (consumables, pre-raid BiS), ticked off with buttons"),
+            Usage::new(Category::Wow, "checklist summary", "Raid readiness across the roster"),
+            Usage::new(Category::Wow, "checklist template <class|all> [add|remove <item>]", "Show or edit the checklist for a class"),
+            Usage::new(Category::Wow, "pvpreport here|off|now", "Weekly PvP report channel"),
+            Usage::new(Category::Wow, "milestones here|off|levels <level>...", "Level milestone announcements"),
+            Usage::new(Category::Wow, "race [pin|unpin]", "Race-to-60 leaderboard with ETAs (pin to keep it updated)"),
+            Usage::new(Category::Wow, "chart <name|all>", "Level-over-time chart for a character or the whole roster"),
+            Usage::new(Category::Wow, "slackers [days]", "Who hasn't leveled lately (`!slackers window <days>` sets the default)"),
+            Usage::new(Category::Wow, "levelcheck [name] [--raw]", "Check levels of tracked characters (with insults)"),
+            Usage::new(Category::Wow, "levelcheckraw", "Check levels without insults"),
+            Usage::new(Category::Wow, "insultstyle [shakespearean|drill-sergeant|passive-aggressive|default]", "How level checks, milestones and slackers get roasted here"),
+            Usage::new(Category::Wow, "hype [name]", "Over-the-top praise for a character or person (you, by default)"),
+            Usage::new(Category::Wow, "hypemode [roast|hype]", "Whether milestone announcements roast or hype"),
+            Usage::new(Category::Wow, "insults optout|optin", "Keep your characters out of the roasts (they get the plain line)"),
+            Usage::new(Category::Wow, "protect [character]", "Exempt a character from insults (officers; no name lists them)"),
+            Usage::new(Category::Wow, "unprotect <character>", "Let the roasts back at a character (officers)"),
+            Usage::new(Category::Wow, "/levelcheck [character] [raw]", "Slash version, with name suggestions"),
+            Usage::new(Category::Wow, "/removecharacter <name>", "Slash version, with name suggestions"),
+            Usage::new(Category::Admin, "wowversion [era|anniversary|cata|retail|default]", "Default WoW game version"),
+        ]
+    }
+
+    /// Snapshots tracked characters, announces level milestones, refreshes the
+    /// pinned race leaderboard, records the token price, and posts the weekly
+    /// PvP report when it is due.
+    async fn on_tick(&self, handler: &Handler, http: &Arc<Http>) {
+        if handler.blizzard.is_none() {
+            return;
+        }
+
+        let level_ups = handler.snapshot_characters().await;
+        for level_up in &level_ups {
+            handler.emit(BotEvent::LevelUp {
+                name: level_up.name.clone(),
+                from: level_up.from,
+                to: level_up.to,
+            });
+        }
+        handler.announce_milestones(http, &level_ups).await;
+        handler.update_race_message(http).await;
+        handler.poll_wow_token().await;
+        post_weekly_pvp_report(handler, http).await;
+    }
+
+    async fn on_message(&self, handler: &Handler, ctx: &Context, msg: &Message, command: &str, args: &Args) -> bool {
+        if command == "character add" {
+            let Some(name) = args.get(0) else {
+                if let Err(why) = msg.channel_id.say(&ctx.http, "Usage: `!character add <name>`").await {
+                    error!("Error sending message: {:?}", why);
+                }
+                return true;
+            };
+
+            if handler.blizzard.is_none() {
+                if let Err(why) = msg.channel_id.say(&ctx.http, "Battle.net API not configured.").await {
+                    error!("Error sending message: {:?}", why);
+                }
+                return true;
+            }
+
+            let version = match version_flag(args) {
+                Ok(version) => version,
+                Err(e) => {
+                    if let Err(why) = msg.channel_id.say(&ctx.http, &e).await {
+                        error!("Error sending message: {:?}", why);
+                    }
+                    return true;
+                }
+            };
+
+            let typing = msg.channel_id.start_typing(&ctx.http);
+            let fetched = match version {
+                Some(version) => handler.fetch_wow_character_in(name, version).await,
+                None => handler.fetch_wow_character(name).await,
+            };
+            match fetched {
+                Ok(character) => {
+                    let conn = handler.db.lock().await;
+                    let added_by = msg.author.id.to_string();
+                    let added = db::add_tracked_character(&conn, &character.name, &added_by);
+                    if let (Ok(_), Some(version)) = (&added, version) {
+                        if let Err(e) = db::set_character_game_version(&conn, &character.name, Some(version.name())) {
+                            error!("DB error setting game version: {}", e);
+                        }
+                    }
+                    match added {
+                        Ok(true) => {
+                            let response = format!(
+                                "Now tracking **{}** — Level {} {} {}",
+                                character.name, character.level, character.race.name, character.character_class.name
+                            );
+                            drop(typing);
+                            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                                error!("Error sending message: {:?}", why);
+                            }
+                        }
+                        Ok(false) => {
+                            let response = format!(
+                                "**{}** is already tracked — Level {} {} {}",
+                                character.name, character.level, character.race.name, character.character_class.name
+                            );
+                            drop(typing);
+                            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                                error!("Error sending message: {:?}", why);
+                            }
+                        }
+                        Err(e) => {
+                            error!("DB error adding character: {}", e);
+                            drop(typing);
+                            if let Err(why) = msg.channel_id.say(&ctx.http, "Failed to save character.").await {
+                                error!("Error sending message: {:?}", why);
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    drop(typing);
+                    if let Err(why) = msg.channel_id.say(&ctx.http, &e).await {
+                        error!("Error sending message: {:?}", why);
+                    }
+                }
+            }
+            return true;
+        }
+
+        if command == "character remove" {
+            let Some(name) = args.get(0) else {
+                let names = {
+                    let conn = handler.db.lock().await;
+                    db::get_tracked_characters(&conn).unwrap_or_default()
+                };
+
+                if names.is_empty() {
+                    if let Err(why) = msg.channel_id.say(&ctx.http, "No characters tracked.").await {
+                        error!("Error sending message: {:?}", why);
+                    }
+                    return true;
+                }
+
+                // Discord caps select menus at 25 options
+                let options: Vec<_> = names
+                    .iter()
+                    .take(SELECT_MENU_MAX_OPTIONS)
+                    .map(|n| CreateSelectMenuOption::new(n, n))
+                    .collect();
+                let menu = CreateSelectMenu::new(
+                    interactions::REMOVE_CHARACTER_SELECT_ID,
+                    CreateSelectMenuKind::String { options },
+                )
+                .placeholder("Pick a character to stop tracking");
+                let content = if names.len() > SELECT_MENU_MAX_OPTIONS {
+                    format!(
+                        "Showing the first {} of {} tracked characters. Use `!character remove <name>` for the rest.",
+                        SELECT_MENU_MAX_OPTIONS,
+                        names.len()
+                    )
+                } else {
+                    "Which character should I stop tracking?".to_string()
+                };
+                let message = CreateMessage::new()
+                    .content(content)
+                    .components(vec![CreateActionRow::SelectMenu(menu)]);
+                if let Err(why) = msg.channel_id.send_message(&ctx.http, message).await {
+                    error!("Error sending message: {:?}", why);
+                }
+                return true;
+            };
+
+            let response = handler.remove_character(name, &msg.author.name).await;
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "character list" {
+            let characters = {
+                let conn = handler.db.lock().await;
+                db::get_tracked_character_details(&conn).unwrap_or_default()
+            };
+            // Pages are 1-based for users
+            let page = args
+                .parsed::<usize>(0)
+                .and_then(|p| p.ok())
+                .unwrap_or(1)
+                .saturating_sub(1);
+            let (embed, components) = wow::list_page(&characters, page);
+            let message = CreateMessage::new().embed(embed).components(components);
+            if let Err(why) = msg.channel_id.send_message(&ctx.http, message).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "character info" {
+            let Some(name) = args.get(0) else {
+                if let Err(why) = msg.channel_id.say(&ctx.http, "Usage: `!character info <name>`").await {
+                    error!("Error sending message: {:?}", why);
+                }
+                return true;
+            };
+
+            if handler.blizzard.is_none() {
+                if let Err(why) = msg.channel_id.say(&ctx.http, "Battle.net API not configured.").await {
+                    error!("Error sending message: {:?}", why);
+                }
+                return true;
+            }
+
+            let version = match version_flag(args) {
+                Ok(version) => version,
+                Err(e) => {
+                    if let Err(why) = msg.channel_id.say(&ctx.http, &e).await {
+                        error!("Error sending message: {:?}", why);
+                    }
+                    return true;
+                }
+            };
+
+            let typing = msg.channel_id.start_typing(&ctx.http);
+            let message = match handler.character_info(name, version).await {
+                Ok(message) => message,
+                Err(e) => CreateMessage::new().content(e),
+            };
+            drop(typing);
+            if let Err(why) = msg.channel_id.send_message(&ctx.http, message).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "character export" {
+            let format = match args.get(0) {
+                None => export::Format::Csv,
+                Some(name) => match export::Format::from_name(name) {
+                    Some(format) => format,
+                    None => {
+                        if let Err(why) = msg.channel_id.say(&ctx.http, "Usage: `!character export [csv|json]`").await {
+                            error!("Error sending message: {:?}", why);
+                        }
+                        return true;
+                    }
+                },
+            };
+
+            let histories: Vec<_> = {
+                let conn = handler.db.lock().await;
+                db::get_tracked_character_details(&conn)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|character| export::CharacterHistory {
+                        snapshots: db::get_snapshots(&conn, &character.name).unwrap_or_default(),
+                        character,
+                    })
+                    .collect()
+            };
+            if histories.is_empty() {
+                if let Err(why) = msg.channel_id.say(&ctx.http, "No characters tracked.").await {
+                    error!("Error sending message: {:?}", why);
+                }
+                return true;
+            }
+
+            let data = export::render(format, &histories);
+            let filename = format!("characters.{}", format.extension());
+            let message = CreateMessage::new()
+                .content(format!("Exported {} characters.", histories.len()))
+                .add_file(CreateAttachment::bytes(data.into_bytes(), filename));
+            if let Err(why) = msg.channel_id.send_message(&ctx.http, message).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "character version" {
+            let (Some(name), Some(value)) = (args.get(0), args.get(1)) else {
+                if let Err(why) = msg
+                    .channel_id
+                    .say(&ctx.http, "Usage: `!character version <name> <era|anniversary|cata|retail|default>`")
+                    .await
+                {
+                    error!("Error sending message: {:?}", why);
+                }
+                return true;
+            };
+
+            let version = if value.eq_ignore_ascii_case("default") {
+                None
+            } else {
+                match wow::GameVersion::from_name(value) {
+                    Some(version) => Some(version),
+                    None => {
+                        let response = unknown_version(value);
+                        if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                            error!("Error sending message: {:?}", why);
+                        }
+                        return true;
+                    }
+                }
+            };
+
+            let conn = handler.db.lock().await;
+            let response = match db::set_character_game_version(&conn, name, version.map(|v| v.name())) {
+                Ok(true) => match version {
+                    Some(version) => format!("**{}** will be looked up on {}.", name, version.label()),
+                    None => format!("**{}** now follows the default game version.", name),
+                },
+                Ok(false) => format!("**{}** is not being tracked.", name),
+                Err(e) => {
+                    error!("DB error setting game version: {}", e);
+                    "Failed to save game version.".to_string()
+                }
+            };
+            drop(conn);
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "wowversion" {
+            let conn = handler.db.lock().await;
+            let response = match args.get(0) {
+                None => {
+                    let current = db::get_config(&conn, None, "wow_version")
+                        .ok()
+                        .flatten()
+                        .and_then(|v| wow::GameVersion::from_name(&v))
+                        .unwrap_or(handler.wow_version);
+                    format!("Default game version: **{}**", current.label())
+                }
+                Some(value) if value.eq_ignore_ascii_case("default") => {
+                    match db::delete_config(&conn, None, "wow_version") {
+                        Ok(_) => format!("Default game version reset to **{}**.", handler.wow_version.label()),
+                        Err(e) => {
+                            error!("DB error resetting game version: {}", e);
+                            "Failed to reset game version.".to_string()
+                        }
+                    }
+                }
+                Some(value) => match wow::GameVersion::from_name(value) {
+                    Some(version) => match db::set_config(&conn, None, "wow_version", version.name()) {
+                        Ok(()) => format!("Default game version set to **{}**.", version.label()),
+                        Err(e) => {
+                            error!("DB error setting game version: {}", e);
+                            "Failed to save game version.".to_string()
+                        }
+                    },
+                    None => unknown_version(value),
+                },
+            };
+            drop(conn);
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "attune" {
+            let response = match (args.get(0), args.get(1)) {
+                (Some(name), Some(raid)) => match attunement::Raid::from_name(raid) {
+                    Some(raid) => {
+                        let total = raid.steps().len() as u32;
+                        let steps: Option<Vec<u32>> = match &args.positional()[2..] {
+                            [] => Some(Vec::new()),
+                            [all] if all == "all" => Some((1..=total).collect()),
+                            steps => steps
+                                .iter()
+                                .map(|s| s.parse().ok().filter(|n| (1..=total).contains(n)))
+                                .collect(),
+                        };
+                        match steps {
+                            None => format!("Steps are numbered 1 to {}.", total),
+                            Some(steps) if steps.is_empty() => handler.attunement_checklist(name, raid).await,
+                            Some(steps) => {
+                                let done = !args.flag("undo");
+                                info!("{} updated {}'s {} attunement: {:?} done={}", msg.author.name, name, raid.slug(), steps, done);
+                                handler
+                                    .update_attunement(name, raid, &steps, done, &msg.author.id.to_string())
+                                    .await
+                            }
+                        }
+                    }
+                    None => attunement::unknown_raid(raid),
+                },
+                _ => "Usage: `!attune <name> <mc|ony|bwl> [step...|all] [--undo]`".to_string(),
+            };
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "attunements" {
+            let response = match args.get(0) {
+                None => handler.attunements_report(None).await,
+                Some(raid) => match attunement::Raid::from_name(raid) {
+                    Some(raid) => handler.attunements_report(Some(raid)).await,
+                    None => attunement::unknown_raid(raid),
+                },
+            };
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "pets" || command == "mounts" {
+            let collection = if command == "pets" { wow::Collection::Pets } else { wow::Collection::Mounts };
+            let response = match args.get(0) {
+                None => format!("Usage: `!{} <name>`", command),
+                Some(_) if handler.blizzard.is_none() => "Battle.net API not configured.".to_string(),
+                Some(name) => handler.collection_report(name, collection).await,
+            };
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "insultstyle" {
+            let response = match (msg.guild_id, args.get(0)) {
+                (None, _) => "Insult styles are set per server.".to_string(),
+                (Some(guild_id), None) => format!(
+                    "Insult style here is **{}**. Usage: `!insultstyle <style>`, one of {}",
+                    handler.insult_style(Some(guild_id)).await.name(),
+                    insults::Style::style_names()
+                ),
+                (Some(guild_id), Some(name)) => match insults::Style::from_name(name) {
+                    Some(style) => {
+                        let conn = handler.db.lock().await;
+                        match db::set_guild_config(&conn, &guild_id.to_string(), "insult_style", style.name()) {
+                            Ok(()) => {
+                                info!("{} set insult style to {} in {}", msg.author.name, style.name(), guild_id);
+                                format!("Insult style set to **{}**.", style.name())
+                            }
+                            Err(e) => {
+                                error!("Failed to set insult style: {}", e);
+                                "Failed to save insult style.".to_string()
+                            }
+                        }
+                    }
+                    None => format!("Unknown style `{}`. Try {}.", name, insults::Style::style_names()),
+                },
+            };
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "hypemode" {
+            let response = match (msg.guild_id, args.get(0)) {
+                (None, _) => "Hype mode is set per server.".to_string(),
+                (Some(guild_id), None) => format!(
+                    "Milestone announcements here are in **{}** mode. Usage: `!hypemode roast|hype`",
+                    handler.report_mode(Some(guild_id)).await.name()
+                ),
+                (Some(guild_id), Some(name)) => match insults::Mode::from_name(name) {
+                    Some(mode) => {
+                        let conn = handler.db.lock().await;
+                        match db::set_guild_config(&conn, &guild_id.to_string(), "report_mode", mode.name()) {
+                            Ok(()) => {
+                                info!("{} set report mode to {} in {}", msg.author.name, mode.name(), guild_id);
+                                format!("Milestone announcements will now **{}**.", mode.name())
+                            }
+                            Err(e) => {
+                                error!("Failed to set report mode: {}", e);
+                                "Failed to save hype mode.".to_string()
+                            }
+                        }
+                    }
+                    None => "Usage: `!hypemode roast|hype`".to_string(),
+                },
+            };
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "hype" {
+            let typing = msg.channel_id.start_typing(&ctx.http);
+            let response = handler.hype_report(args.get(0), &msg.author.name).await;
+            drop(typing);
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "insults" {
+            let user_id = msg.author.id.to_string();
+            let conn = handler.db.lock().await;
+            let response = match args.get(0) {
+                Some(choice @ ("optout" | "optin")) => {
+                    match db::set_insult_opt_out(&conn, &user_id, choice == "optout") {
+                        Ok(()) if choice == "optout" => {
+                            "Got it — your characters won't be roasted any more.".to_string()
+                        }
+                        Ok(()) => "Your characters are fair game again.".to_string(),
+                        Err(e) => {
+                            error!("Failed to set insult opt-out: {}", e);
+                            "Failed to save your choice.".to_string()
+                        }
+                    }
+                }
+                _ => {
+                    let opted_out = db::is_insult_opted_out(&conn, &user_id).unwrap_or(false);
+                    format!(
+                        "Your characters {} roasted. Usage: `!insults optout|optin`",
+                        if opted_out { "are not" } else { "can be" }
+                    )
+                }
+            };
+            drop(conn);
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "protect" || command == "unprotect" {
+            let response = match (msg.guild_id, args.get(0)) {
+                (_, None) if command == "protect" => {
+                    let conn = handler.db.lock().await;
+                    match db::get_protected_characters(&conn) {
+                        Ok(names) if names.is_empty() => {
+                            "No protected characters. Usage: `!protect <character>`".to_string()
+                        }
+                        Ok(names) => format!("**Protected from insults:** {}", names.join(", ")),
+                        Err(e) => {
+                            error!("Failed to load protected characters: {}", e);
+                            "Failed to load protected characters.".to_string()
+                        }
+                    }
+                }
+                (_, None) => "Usage: `!unprotect <character>`".to_string(),
+                (None, Some(_)) => "Protect characters from a server, where officers can be told apart.".to_string(),
+                (Some(guild_id), Some(name)) => {
+                    if !bots::can_manage_guild(&ctx.http, guild_id, msg.author.id).await
+                        && !bots::is_owner(&ctx.http, msg.author.id).await
+                    {
+                        "Only officers (Manage Server) can protect characters.".to_string()
+                    } else {
+                        let protect = command == "protect";
+                        let by = msg.author.id.to_string();
+                        let conn = handler.db.lock().await;
+                        match db::set_character_protected(&conn, name, protect.then_some(by.as_str())) {
+                            Ok(changed) => {
+                                if changed {
+                                    info!("{} {}ed {} from insults", msg.author.name, command, name);
+                                }
+                                match (protect, changed) {
+                                    (true, true) => format!("**{}** is now protected from insults.", name),
+                                    (true, false) => format!("**{}** is already protected.", name),
+                                    (false, true) => format!("**{}** can be insulted again.", name),
+                                    (false, false) => format!("**{}** isn't protected.", name),
+                                }
+                            }
+                            Err(e) => {
+                                error!("Failed to {} {}: {}", command, name, e);
+                                "Failed to save protection.".to_string()
+                            }
+                        }
+                    }
+                }
+            };
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "wowtoken" {
+            let response = if handler.blizzard.is_none() {
+                "Battle.net API not configured.".to_string()
+            } else {
+                handler.wow_token_report().await
+            };
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "checklist" {
+            let message = match (args.get(0), args.get(1)) {
+                (Some("summary"), None) => CreateMessage::new().content(handler.checklist_summary().await),
+                (Some("template"), Some(class)) => CreateMessage::new().content(checklist_template(handler, msg, class, args).await),
+                (Some(name), None) => match handler.checklist_view(name).await {
+                    Ok((embed, components)) => CreateMessage::new().embed(embed).components(components),
+                    Err(e) => CreateMessage::new().content(e),
+                },
+                _ => CreateMessage::new().content(CHECKLIST_USAGE),
+            };
+            if let Err(why) = msg.channel_id.send_message(&ctx.http, message).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "professions" || command == "crafters" {
+            // Profession names can be multiple words ("First Aid")
+            let arg = args.positional().join(" ");
+            if arg.is_empty() {
+                let usage = if command == "professions" {
+                    "Usage: `!professions <name>`"
+                } else {
+                    "Usage: `!crafters <profession>`"
+                };
+                if let Err(why) = msg.channel_id.say(&ctx.http, usage).await {
+                    error!("Error sending message: {:?}", why);
+                }
+                return true;
+            }
+
+            if handler.blizzard.is_none() {
+                if let Err(why) = msg.channel_id.say(&ctx.http, "Battle.net API not configured.").await {
+                    error!("Error sending message: {:?}", why);
+                }
+                return true;
+            }
+
+            let typing = msg.channel_id.start_typing(&ctx.http);
+            let response = if command == "professions" {
+                handler.professions_report(&arg).await
+            } else {
+                handler.crafters_report(&arg).await
+            };
+            drop(typing);
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "rep" {
+            let Some(name) = args.get(0) else {
+                if let Err(why) = msg.channel_id.say(&ctx.http, "Usage: `!rep <name> [faction]`").await {
+                    error!("Error sending message: {:?}", why);
+                }
+                return true;
+            };
+            let faction = args.positional()[1..].join(" ");
+            let faction = (!faction.is_empty()).then_some(faction.as_str());
+
+            if handler.blizzard.is_none() {
+                if let Err(why) = msg.channel_id.say(&ctx.http, "Battle.net API not configured.").await {
+                    error!("Error sending message: {:?}", why);
+                }
+                return true;
+            }
+
+            let typing = msg.channel_id.start_typing(&ctx.http);
+            let message = match handler.reputation_embed(name, faction).await {
+                Ok(embed) => CreateMessage::new().embed(embed),
+                Err(e) => CreateMessage::new().content(e),
+            };
+            drop(typing);
+            if let Err(why) = msg.channel_id.send_message(&ctx.http, message).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "pvp" {
+            let Some(name) = args.get(0) else {
+                if let Err(why) = msg.channel_id.say(&ctx.http, "Usage: `!pvp <name>`").await {
+                    error!("Error sending message: {:?}", why);
+                }
+                return true;
+            };
+
+            if handler.blizzard.is_none() {
+                if let Err(why) = msg.channel_id.say(&ctx.http, "Battle.net API not configured.").await {
+                    error!("Error sending message: {:?}", why);
+                }
+                return true;
+            }
+
+            let typing = msg.channel_id.start_typing(&ctx.http);
+            let response = handler.pvp_report(name).await;
+            drop(typing);
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "slackers" && args.get(0) == Some("window") {
+            let response = match args.parsed::<u32>(1) {
+                Some(Ok(days)) if days > 0 => {
+                    let conn = handler.db.lock().await;
+                    match db::set_config(&conn, None, "slacker_days", &days.to_string()) {
+                        Ok(_) => format!("Slacker window set to **{}** days.", days),
+                        Err(e) => {
+                            error!("Failed to set slacker window: {}", e);
+                            "Failed to save slacker window.".to_string()
+                        }
+                    }
+                }
+                _ => "Usage: `!slackers window <days>`".to_string(),
+            };
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "slackers" {
+            let window_days = match args.parsed::<u32>(0) {
+                Some(Ok(days)) => days,
+                Some(Err(_)) => {
+                    if let Err(why) = msg.channel_id.say(&ctx.http, "Usage: `!slackers [days]`").await {
+                        error!("Error sending message: {:?}", why);
+                    }
+                    return true;
+                }
+                None => {
+                    let conn = handler.db.lock().await;
+                    db::get_config(&conn, None, "slacker_days")
+                        .ok()
+                        .flatten()
+                        .and_then(|v| v.parse::<u32>().ok())
+                        .unwrap_or(wow::DEFAULT_SLACKER_DAYS)
+                }
+            };
+
+            let typing = msg.channel_id.start_typing(&ctx.http);
+            let response = handler.slackers_report(msg.guild_id, window_days).await;
+            drop(typing);
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "chart" {
+            let target = match args.get(0) {
+                Some(name) if name.eq_ignore_ascii_case("all") => None,
+                Some(name) => Some(name),
+                None => {
+                    if let Err(why) = msg.channel_id.say(&ctx.http, "Usage: `!chart <name|all>`").await {
+                        error!("Error sending message: {:?}", why);
+                    }
+                    return true;
+                }
+            };
+
+            let message = match handler.level_chart(target).await {
+                Ok(chart) => CreateMessage::new().add_file(chart),
+                Err(e) => CreateMessage::new().content(e),
+            };
+            if let Err(why) = msg.channel_id.send_message(&ctx.http, message).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "race" {
+            let leaderboard = handler.race_leaderboard().await;
+            match args.get(0) {
+                Some("pin") => {
+                    let sent = match msg.channel_id.say(&ctx.http, &leaderboard).await {
+                        Ok(sent) => sent,
+                        Err(why) => {
+                            error!("Error sending message: {:?}", why);
+                            return true;
+                        }
+                    };
+                    if let Err(why) = sent.pin(&ctx.http).await {
+                        warn!("Failed to pin race leaderboard: {:?}", why);
+                    }
+                    let conn = handler.db.lock().await;
+                    let target = format!("{}:{}", sent.channel_id, sent.id);
+                    if let Err(e) = db::set_config(&conn, None, "race_message", &target) {
+                        error!("Failed to save race leaderboard message: {}", e);
+                    }
+                }
+                Some("unpin") => {
+                    let conn = handler.db.lock().await;
+                    let response = match db::delete_config(&conn, None, "race_message") {
+                        Ok(true) => "Stopped updating the race leaderboard.",
+                        Ok(false) => "No race leaderboard is being updated.",
+                        Err(e) => {
+                            error!("Failed to clear race leaderboard message: {}", e);
+                            "Failed to stop updates."
+                        }
+                    };
+                    if let Err(why) = msg.channel_id.say(&ctx.http, response).await {
+                        error!("Error sending message: {:?}", why);
+                    }
+                }
+                _ => {
+                    if let Err(why) = msg.channel_id.say(&ctx.http, &leaderboard).await {
+                        error!("Error sending message: {:?}", why);
+                    }
+                }
+            }
+            return true;
+        }
+
+        if command == "milestones" {
+            let response = match args.get(0) {
+                Some("here") => {
+                    let conn = handler.db.lock().await;
+                    match db::set_config(&conn, None, "milestone_channel", &msg.channel_id.to_string()) {
+                        Ok(_) => "Level milestones will be announced in this channel.".to_string(),
+                        Err(e) => {
+                            error!("Failed to set milestone channel: {}", e);
+                            "Failed to save milestone channel.".to_string()
+                        }
+                    }
+                }
+                Some("off") => {
+                    let conn = handler.db.lock().await;
+                    match db::delete_config(&conn, None, "milestone_channel") {
+                        Ok(_) => "Milestone announcements disabled.".to_string(),
+                        Err(e) => {
+                            error!("Failed to clear milestone channel: {}", e);
+                            "Failed to disable announcements.".to_string()
+                        }
+                    }
+                }
+                Some("levels") => {
+                    let levels = wow::parse_milestones(&args.positional()[1..].join(","));
+                    if levels.is_empty() {
+                        "Usage: `!milestones levels 40 60`".to_string()
+                    } else {
+                        let value = levels.iter().map(|l| l.to_string()).collect::<Vec<_>>().join(",");
+                        let conn = handler.db.lock().await;
+                        match db::set_config(&conn, None, "milestone_levels", &value) {
+                            Ok(_) => format!("Milestone levels set to **{}**.", value),
+                            Err(e) => {
+                                error!("Failed to set milestone levels: {}", e);
+                                "Failed to save milestone levels.".to_string()
+                            }
+                        }
+                    }
+                }
+                _ => "Usage: `!milestones here|off` or `!milestones levels <level>...`".to_string(),
+            };
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "pvpreport" {
+            let response = match args.get(0) {
+                Some("here") => {
+                    let conn = handler.db.lock().await;
+                    match db::set_config(&conn, None, "pvp_report_channel", &msg.channel_id.to_string()) {
+                        Ok(_) => {
+                            info!("{} set PvP report channel to {}", msg.author.name, msg.channel_id);
+                            "Weekly PvP report will be posted in this channel.".to_string()
+                        }
+                        Err(e) => {
+                            error!("Failed to set PvP report channel: {}", e);
+                            "Failed to save report channel.".to_string()
+                        }
+                    }
+                }
+                Some("off") => {
+                    let conn = handler.db.lock().await;
+                    match db::delete_config(&conn, None, "pvp_report_channel") {
+                        Ok(_) => "Weekly PvP report disabled.".to_string(),
+                        Err(e) => {
+                            error!("Failed to clear PvP report channel: {}", e);
+                            "Failed to disable report.".to_string()
+                        }
+                    }
+                }
+                Some("now") => handler.weekly_pvp_report().await,
+                _ => "Usage: `!pvpreport here|off|now`".to_string(),
+            };
+            if let Err(why) = msg.channel_id.say(&ctx.http, &response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "character" || command.starts_with("character ") {
+            let response = "Usage: `!character add|remove|list|info|version|export [name]`";
+            if let Err(why) = msg.channel_id.say(&ctx.http, response).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        if command == "levelcheck" || command == "levelcheckraw" {
+            let use_insults = command == "levelcheck" && !args.flag("raw");
+
+            let typing = msg.channel_id.start_typing(&ctx.http);
+            let request = retry::Request::LevelCheck {
+                only: args.get(0).map(str::to_string),
+                insults: use_insults,
+            };
+            let message = if handler.blizzard.is_some()
+                && !handler.battlenet_reachable().await
+                && handler.defer(msg.guild_id, msg.channel_id, msg.author.id, &request).await
+            {
+                CreateMessage::new().content(request.queued_reply())
+            } else {
+                CreateMessage::new().embed(handler.level_check_embed(msg.guild_id, args.get(0), use_insults).await)
+            };
+            drop(typing);
+
+            if let Err(why) = msg.channel_id.send_message(&ctx.http, message).await {
+                error!("Error sending message: {:?}", why);
+            }
+            return true;
+        }
+
+        false
+    }
+}
+
+async fn post_weekly_pvp_report(handler: &Handler, http: &Http) {
+    let now = unix_now();
+    let channel_id = {
+        let conn = handler.db.lock().await;
+        let channel = db::get_config(&conn, None, "pvp_report_channel")
+            .ok()
+            .flatten()
+            .and_then(|v| v.parse::<u64>().ok());
+        let last_sent = db::get_config(&conn, None, "pvp_report_last")
+            .ok()
+            .flatten()
+            .and_then(|v| v.parse::<i64>().ok())
+            .unwrap_or(0);
+        match channel {
+            Some(id) if now - last_sent >= WEEK_SECS => ChannelId::new(id),
+            _ => return,
+        }
+    };
+
+    let report = handler.weekly_pvp_report().await;
+    if let Err(why) = channel_id.say(http, &report).await {
+        error!("Failed to post weekly PvP report: {:?}", why);
+        return;
+    }
+    info!("Posted weekly PvP report to {}", channel_id);
+
+    let conn = handler.db.lock().await;
+    if let Err(e) = db::set_config(&conn, None, "pvp_report_last", &now.to_string()) {
+        error!("Failed to record PvP report time: {}", e);
+    }
+}
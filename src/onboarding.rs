@@ -0,0 +1,315 @@
+use chrono::Utc;
+use serenity::builder::{
+    CreateActionRow, CreateButton, CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage,
+    CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption,
+};
+use serenity::model::application::{ButtonStyle, ComponentInteraction, ComponentInteractionDataKind};
+use serenity::model::channel::ChannelType;
+use serenity::model::guild::audit_log::{Action, MemberAction};
+use serenity::model::guild::Guild;
+use serenity::model::id::{ChannelId, GuildId, UserId};
+use serenity::prelude::Context;
+use tracing::{error, info, warn};
+
+use crate::features::Feature;
+use crate::{db, Handler, SELECT_MENU_MAX_OPTIONS};
+
+/// Prefix for the `custom_id` of every setup component; the step and the
+/// guild follow, since the wizard usually runs in a DM.
+pub const CUSTOM_ID_PREFIX: &str = "onboard:";
+
+/// Response caps offered in setup, in words.
+const CAP_CHOICES: [u32; 5] = [25, 50, 100, 200, 400];
+
+/// How recently the bot must have joined a guild for it to get the wizard.
+const NEW_GUILD_SECS: i64 = 10 * 60;
+
+/// Option value for keeping the global persona or having no default channel.
+const NONE_VALUE: &str = "none";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Step {
+    Persona,
+    Channel,
+    Cap,
+    Features,
+    Done,
+}
+
+impl Step {
+    const ALL: [Step; 5] = [Step::Persona, Step::Channel, Step::Cap, Step::Features, Step::Done];
+
+    fn id(self) -> &'static str {
+        match self {
+            Step::Persona => "persona",
+            Step::Channel => "channel",
+            Step::Cap => "cap",
+            Step::Features => "features",
+            Step::Done => "done",
+        }
+    }
+
+    fn custom_id(self, guild_id: GuildId) -> String {
+        format!("{}{}:{}", CUSTOM_ID_PREFIX, self.id(), guild_id)
+    }
+
+    pub fn from_custom_id(custom_id: &str) -> Option<(Step, GuildId)> {
+        let (id, guild) = custom_id.strip_prefix(CUSTOM_ID_PREFIX)?.split_once(':')?;
+        let step = Step::ALL.into_iter().find(|s| s.id() == id)?;
+        let guild_id = guild.parse().ok().filter(|&id| id != 0).map(GuildId::new)?;
+        Some((step, guild_id))
+    }
+}
+
+/// The setup menus: persona, default channel, response cap and features, then
+/// a button to finish. `channels` are the guild's text channels by name.
+fn components(guild_id: GuildId, personas: &[db::Persona], channels: &[(ChannelId, String)]) -> Vec<CreateActionRow> {
+    // Discord caps select menus at 25 options, one of which is "none"
+    let mut persona_options = vec![CreateSelectMenuOption::new("Keep the default persona", NONE_VALUE)];
+    persona_options.extend(
+        personas
+            .iter()
+            .take(SELECT_MENU_MAX_OPTIONS - 1)
+            .map(|p| CreateSelectMenuOption::new(&p.name, format!("p:{}", p.name))),
+    );
+    let mut channel_options = vec![CreateSelectMenuOption::new("Only when mentioned", NONE_VALUE)];
+    channel_options.extend(
+        channels
+            .iter()
+            .take(SELECT_MENU_MAX_OPTIONS - 1)
+            .map(|(id, name)| CreateSelectMenuOption::new(format!("#{}", name), id.to_string())),
+    );
+    let cap_options = CAP_CHOICES
+        .iter()
+        .map(|cap| CreateSelectMenuOption::new(format!("{} words", cap), cap.to_string()))
+        .collect();
+    let feature_options = Feature::ALL
+        .into_iter()
+        .map(|f| {
+            CreateSelectMenuOption::new(f.name(), f.name())
+                .description(f.description().replace('`', ""))
+                .default_selection(true)
+        })
+        .collect();
+
+    vec![
+        CreateActionRow::SelectMenu(
+            CreateSelectMenu::new(Step::Persona.custom_id(guild_id), CreateSelectMenuKind::String { options: persona_options })
+                .placeholder("Persona"),
+        ),
+        CreateActionRow::SelectMenu(
+            CreateSelectMenu::new(Step::Channel.custom_id(guild_id), CreateSelectMenuKind::String { options: channel_options })
+                .placeholder("Channel where I answer everything"),
+        ),
+        CreateActionRow::SelectMenu(
+            CreateSelectMenu::new(Step::Cap.custom_id(guild_id), CreateSelectMenuKind::String { options: cap_options })
+                .placeholder("Response length"),
+        ),
+        CreateActionRow::SelectMenu(
+            CreateSelectMenu::new(Step::Features.custom_id(guild_id), CreateSelectMenuKind::String { options: feature_options })
+                .placeholder("Enabled features")
+                .min_values(0)
+                .max_values(Feature::ALL.len() as u8),
+        ),
+        CreateActionRow::Buttons(vec![CreateButton::new(Step::Done.custom_id(guild_id))
+            .label("Done")
+            .style(ButtonStyle::Success)]),
+    ]
+}
+
+/// The guild's setup so far, as shown above the menus.
+fn summary(conn: &rusqlite::Connection, guild_id: GuildId) -> rusqlite::Result<String> {
+    let guild = guild_id.to_string();
+    let persona = db::get_guild_config(conn, &guild, "persona")?.unwrap_or_else(|| "default".to_string());
+    let channel = match db::get_guild_config(conn, &guild, "default_channel")? {
+        Some(id) => format!("<#{}>", id),
+        None => "none, only when mentioned".to_string(),
+    };
+    let cap = db::get_response_cap(conn, Some(&guild));
+    let mut enabled = Vec::new();
+    for feature in Feature::ALL {
+        if db::is_feature_enabled(conn, &guild, feature.name())? {
+            enabled.push(feature.name());
+        }
+    }
+    let features = if enabled.is_empty() { "none".to_string() } else { enabled.join(", ") };
+    Ok(format!(
+        "**Persona:** {}\n**Default channel:** {}\n**Response cap:** {} words\n**Features:** {}",
+        persona, channel, cap, features
+    ))
+}
+
+impl Handler {
+    /// Sends the setup wizard the first time the bot joins `guild`: to whoever
+    /// invited it when the audit log says, else to the server's system channel.
+    /// Guilds the bot was already in are sent on every connect, so only one
+    /// joined in the last [`NEW_GUILD_SECS`] counts.
+    pub(crate) async fn start_onboarding(&self, ctx: &Context, guild: &Guild) {
+        let age = Utc::now().timestamp() - guild.joined_at.unix_timestamp();
+        if !self.identity.is_primary() || age > NEW_GUILD_SECS {
+            return;
+        }
+        let (personas, intro) = {
+            let conn = self.db.lock().await;
+            match db::mark_guild_known(&conn, &guild.id.to_string()) {
+                Ok(true) => {}
+                Ok(false) => return,
+                Err(e) => {
+                    error!("Failed to record guild: {}", e);
+                    return;
+                }
+            }
+            let personas = db::get_personas(&conn).unwrap_or_default();
+            let intro = summary(&conn, guild.id).unwrap_or_default();
+            (personas, intro)
+        };
+        info!("Joined guild {} ({}), starting setup", guild.name, guild.id);
+
+        let mut channels: Vec<_> = guild.channels.values().filter(|c| c.kind == ChannelType::Text).collect();
+        channels.sort_by_key(|c| c.position);
+        let channels: Vec<(ChannelId, String)> = channels.into_iter().map(|c| (c.id, c.name.clone())).collect();
+        let message = CreateMessage::new()
+            .content(format!(
+                "👋 Thanks for adding me to **{}**! Let's get set up. Pick what you like below; everything \
+                 can be changed later with commands.\n\n{}",
+                guild.name, intro
+            ))
+            .components(components(guild.id, &personas, &channels));
+
+        if let Some(inviter) = self.inviter(ctx, guild.id).await {
+            match inviter.direct_message(&ctx.http, message.clone()).await {
+                Ok(_) => return,
+                Err(why) => warn!("Couldn't DM setup to the inviter of {}: {:?}", guild.id, why),
+            }
+        }
+        match guild.system_channel_id {
+            Some(channel_id) => {
+                if let Err(why) = channel_id.send_message(&ctx.http, message).await {
+                    error!("Error sending message: {:?}", why);
+                }
+            }
+            None => warn!("Nowhere to send setup for guild {}", guild.id),
+        }
+    }
+
+    /// Who added the bot to `guild_id`, from the audit log. Needs the View
+    /// Audit Log permission.
+    async fn inviter(&self, ctx: &Context, guild_id: GuildId) -> Option<UserId> {
+        let me = ctx.http.get_current_user().await.ok()?.id;
+        let logs = guild_id
+            .audit_logs(&ctx.http, Some(Action::Member(MemberAction::BotAdd)), None, None, Some(10))
+            .await
+            .map_err(|e| warn!("Couldn't read the audit log of {}: {:?}", guild_id, e))
+            .ok()?;
+        logs.entries
+            .iter()
+            .find(|entry| entry.target_id.map(|id| id.get()) == Some(me.get()))
+            .map(|entry| entry.user_id)
+    }
+
+    /// Saves a choice from the setup wizard and shows the updated summary.
+    /// In a server only members with Manage Server may use it; a DM only ever
+    /// goes to the inviter.
+    pub(crate) async fn handle_onboarding(&self, ctx: &Context, component: &ComponentInteraction, step: Step, guild_id: GuildId) {
+        if component.guild_id.is_some() {
+            let allowed = component
+                .member
+                .as_ref()
+                .and_then(|m| m.permissions)
+                .is_some_and(|p| p.manage_guild());
+            if !allowed {
+                let response = CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content("Only members who can manage the server can set me up.")
+                        .ephemeral(true),
+                );
+                if let Err(why) = component.create_response(&ctx.http, response).await {
+                    error!("Error responding to interaction: {:?}", why);
+                }
+                return;
+            }
+        }
+
+        let values: &[String] = match &component.data.kind {
+            ComponentInteractionDataKind::StringSelect { values } => values,
+            _ => &[],
+        };
+        let guild = guild_id.to_string();
+        let content = {
+            let conn = self.db.lock().await;
+            let saved = match step {
+                Step::Persona => match values.first().and_then(|v| v.strip_prefix("p:")) {
+                    Some(name) => db::set_guild_config(&conn, &guild, "persona", name),
+                    None => db::delete_guild_config(&conn, &guild, "persona").map(|_| ()),
+                },
+                Step::Channel => match values.first().filter(|v| *v != NONE_VALUE) {
+                    Some(channel) => db::set_guild_config(&conn, &guild, "default_channel", channel),
+                    None => db::delete_guild_config(&conn, &guild, "default_channel").map(|_| ()),
+                },
+                Step::Cap => match values.first() {
+                    Some(cap) => db::set_guild_config(&conn, &guild, "response_cap", cap),
+                    None => Ok(()),
+                },
+                Step::Features => Feature::ALL.into_iter().try_for_each(|f| {
+                    let enabled = values.iter().any(|v| v == f.name());
+                    db::set_feature_enabled(&conn, &guild, f.name(), enabled)
+                }),
+                Step::Done => Ok(()),
+            };
+            if let Err(e) = saved {
+                error!("Failed to save setup: {}", e);
+            } else if step != Step::Done {
+                info!("{} set up {} for guild {}: {:?}", component.user.name, step.id(), guild_id, values);
+            }
+            summary(&conn, guild_id).unwrap_or_else(|e| {
+                error!("Failed to load setup: {}", e);
+                "Failed to load the settings.".to_string()
+            })
+        };
+
+        let message = if step == Step::Done {
+            CreateInteractionResponseMessage::new()
+                .content(format!("✅ All set! `!help` lists everything I can do.\n\n{}", content))
+                .components(vec![])
+        } else {
+            CreateInteractionResponseMessage::new().content(format!("Setup so far:\n\n{}", content))
+        };
+        if let Err(why) = component
+            .create_response(&ctx.http, CreateInteractionResponse::UpdateMessage(message))
+            .await
+        {
+            error!("Error responding to interaction: {:?}", why);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_custom_id_round_trip() {
+        let guild_id = GuildId::new(42);
+        for step in Step::ALL {
+            assert_eq!(Step::from_custom_id(&step.custom_id(guild_id)), Some((step, guild_id)));
+        }
+        assert_eq!(Step::from_custom_id("onboard:persona:0"), None);
+        assert_eq!(Step::from_custom_id("onboard:colour:42"), None);
+        assert_eq!(Step::from_custom_id("help:chat"), None);
+    }
+
+    #[test]
+    fn test_summary() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        db::init(&conn).unwrap();
+        let guild_id = GuildId::new(1);
+        db::set_guild_config(&conn, "1", "default_channel", "5").unwrap();
+        db::set_guild_config(&conn, "1", "response_cap", "25").unwrap();
+        db::set_feature_enabled(&conn, "1", "scripts", false).unwrap();
+        let summary = summary(&conn, guild_id).unwrap();
+        assert!(summary.contains("**Persona:** default"));
+        assert!(summary.contains("<#5>"));
+        assert!(summary.contains("25 words"));
+        assert!(summary.ends_with("**Features:** chat, wow, levelcheck, fun, lol"));
+    }
+}
@@ -0,0 +1,153 @@
+pub mod card;
+pub mod schedule;
+
+use rusqlite::Connection;
+use serenity::model::id::{ChannelId, GuildId};
+
+use crate::{db, Handler};
+
+/// Largest character card `!persona import` accepts; card PNGs are portraits,
+/// so they're bigger than the JSON alone.
+pub const MAX_CARD_BYTES: u32 = 8 * 1024 * 1024;
+
+/// Used instead of the configured persona in `!safemode` channels.
+pub const SAFE_MODE_PROMPT: &str = "You are a friendly, helpful assistant in a Discord server. \
+     Be polite, neutral and concise. Don't insult, tease or swear at anyone.";
+
+pub const MIN_INTENSITY: u8 = 1;
+pub const MAX_INTENSITY: u8 = 10;
+
+/// How hard the persona leans in at each `!intensity` level, mildest first.
+const INTENSITY_STYLES: [&str; MAX_INTENSITY as usize] = [
+    "mildly sarcastic at most, and friendly underneath",
+    "lightly teasing, never mean",
+    "dry and a little snarky",
+    "openly sarcastic",
+    "blunt and sarcastic, with the odd jab",
+    "rude, with jabs in most replies",
+    "rude and theatrical, roasting freely",
+    "savage, roasting everyone and everything",
+    "unhinged, with over-the-top insults",
+    "scorched earth: hold absolutely nothing back",
+];
+
+/// The instruction appended to the system prompt for an intensity level.
+pub fn intensity_instruction(level: u8) -> String {
+    let level = level.clamp(MIN_INTENSITY, MAX_INTENSITY);
+    format!(
+        "Personality intensity in this channel: {}/{}. Be {}.",
+        level,
+        MAX_INTENSITY,
+        INTENSITY_STYLES[(level - MIN_INTENSITY) as usize]
+    )
+}
+
+/// How someone wants replies formatted, set per user with `!style`. It
+/// shapes the format, not the persona.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReplyStyle {
+    Concise,
+    Verbose,
+    EmojiHeavy,
+}
+
+impl ReplyStyle {
+    pub const ALL: [ReplyStyle; 3] = [ReplyStyle::Concise, ReplyStyle::Verbose, ReplyStyle::EmojiHeavy];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ReplyStyle::Concise => "concise",
+            ReplyStyle::Verbose => "verbose",
+            ReplyStyle::EmojiHeavy => "emoji-heavy",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ReplyStyle> {
+        ReplyStyle::ALL.into_iter().find(|s| s.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The instruction appended to the system prompt when this user is talking.
+    pub fn instruction(self) -> &'static str {
+        match self {
+            ReplyStyle::Concise => "The person talking to you prefers concise replies: get to the point, no filler.",
+            ReplyStyle::Verbose => "The person talking to you prefers detailed replies: explain fully and give examples.",
+            ReplyStyle::EmojiHeavy => "The person talking to you loves emoji: use plenty of them in your reply.",
+        }
+    }
+}
+
+/// A stored persona for an imported character card.
+pub fn from_card(card: &card::Card, created_by: &str) -> db::Persona {
+    db::Persona {
+        name: card.name.trim().to_string(),
+        description: card.description.clone(),
+        personality: card.personality.clone(),
+        scenario: card.scenario.clone(),
+        first_message: card.greeting().unwrap_or_default(),
+        system_prompt: card.system_prompt(),
+        created_by: created_by.to_string(),
+    }
+}
+
+impl Handler {
+    /// The persona in effect in `channel_id`: the guild's system prompt, or
+    /// the neutral one in safe mode.
+    pub(crate) fn persona(&self, conn: &Connection, guild_id: Option<GuildId>, channel_id: ChannelId) -> rusqlite::Result<String> {
+        if db::is_safe_mode(conn, &channel_id.to_string())? {
+            return Ok(SAFE_MODE_PROMPT.to_string());
+        }
+        self.guild_system_prompt(conn, guild_id)
+    }
+
+    /// The bot's system prompt adjusted for `channel_id`'s settings. Safe mode
+    /// replaces the persona outright, ignoring intensity too.
+    pub(crate) fn channel_system_prompt(
+        &self,
+        conn: &Connection,
+        guild_id: Option<GuildId>,
+        channel_id: ChannelId,
+    ) -> rusqlite::Result<String> {
+        if db::is_safe_mode(conn, &channel_id.to_string())? {
+            return Ok(SAFE_MODE_PROMPT.to_string());
+        }
+        let mut prompt = self.guild_system_prompt(conn, guild_id)?;
+        if let Some(level) = db::get_intensity(conn, &channel_id.to_string())? {
+            if !prompt.is_empty() {
+                prompt.push_str("\n\n");
+            }
+            prompt.push_str(&intensity_instruction(level));
+        }
+        Ok(prompt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::mock;
+
+    #[test]
+    fn test_intensity_instruction() {
+        assert!(intensity_instruction(1).contains("1/10. Be mildly sarcastic"));
+        assert!(intensity_instruction(10).contains("scorched earth"));
+        // Out-of-range levels are clamped
+        assert_eq!(intensity_instruction(0), intensity_instruction(1));
+        assert_eq!(intensity_instruction(42), intensity_instruction(10));
+    }
+
+    #[tokio::test]
+    async fn test_channel_system_prompt() {
+        let handler = mock::handler(None, None);
+        let conn = handler.db.lock().await;
+        db::set_config(&conn, None, "system_prompt", "You are rude.").unwrap();
+        db::set_intensity(&conn, "1", Some(9)).unwrap();
+
+        let prompt = handler.channel_system_prompt(&conn, None, ChannelId::new(1)).unwrap();
+        assert_eq!(prompt, format!("You are rude.\n\n{}", intensity_instruction(9)));
+        // Other channels get the plain prompt
+        assert_eq!(handler.channel_system_prompt(&conn, None, ChannelId::new(2)).unwrap(), "You are rude.");
+
+        db::set_safe_mode(&conn, "1", true).unwrap();
+        assert_eq!(handler.channel_system_prompt(&conn, None, ChannelId::new(1)).unwrap(), SAFE_MODE_PROMPT);
+    }
+}
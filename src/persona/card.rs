@@ -0,0 +1,184 @@
+//! Character cards in the Tavern format used by SillyTavern and friends: JSON,
+//! or a PNG with the JSON base64-encoded in a `chara` text chunk.
+
+use base64::Engine;
+use serde::Deserialize;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+/// The text chunk keyword Tavern cards are stored under.
+const CARD_KEYWORD: &[u8] = b"chara";
+
+/// The parts of a card the bot uses. V1 cards have these at the top level, V2
+/// (`"spec": "chara_card_v2"`) under `data`.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+pub struct Card {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub personality: String,
+    #[serde(default)]
+    pub scenario: String,
+    #[serde(default, rename = "first_mes")]
+    pub first_message: String,
+    /// V2 only: replaces the bot's own system prompt when set.
+    #[serde(default)]
+    pub system_prompt: String,
+}
+
+#[derive(Deserialize)]
+struct CardV2 {
+    data: Card,
+}
+
+impl Card {
+    /// The system prompt for chatting as this character. `{{char}}` and
+    /// `{{user}}` placeholders are filled in, as Tavern frontends do.
+    pub fn system_prompt(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.system_prompt.trim().is_empty() {
+            parts.push(self.system_prompt.trim().to_string());
+        }
+        parts.push(format!("You are {}. Stay in character.", self.name));
+        for (label, text) in [
+            ("Description", &self.description),
+            ("Personality", &self.personality),
+            ("Scenario", &self.scenario),
+        ] {
+            if !text.trim().is_empty() {
+                parts.push(format!("{}: {}", label, text.trim()));
+            }
+        }
+        self.fill_placeholders(&parts.join("\n\n"))
+    }
+
+    /// The greeting the character opens with, if the card has one.
+    pub fn greeting(&self) -> Option<String> {
+        let greeting = self.first_message.trim();
+        (!greeting.is_empty()).then(|| self.fill_placeholders(greeting))
+    }
+
+    fn fill_placeholders(&self, text: &str) -> String {
+        text.replace("{{char}}", &self.name)
+            .replace("{{user}}", "the user")
+    }
+}
+
+/// Parses a card from a `.json` file or a PNG card image.
+pub fn parse(bytes: &[u8]) -> Result<Card, String> {
+    let json = if bytes.starts_with(PNG_SIGNATURE) {
+        let encoded = png_text_chunk(bytes, CARD_KEYWORD).ok_or("That PNG has no character card in it")?;
+        base64::engine::general_purpose::STANDARD
+            .decode(encoded.trim_ascii())
+            .map_err(|e| format!("The card data isn't valid base64: {}", e))?
+    } else {
+        bytes.to_vec()
+    };
+
+    let value: serde_json::Value =
+        serde_json::from_slice(&json).map_err(|e| format!("The card isn't valid JSON: {}", e))?;
+    let card = if value.get("data").is_some() {
+        serde_json::from_value::<CardV2>(value).map(|v2| v2.data)
+    } else {
+        serde_json::from_value::<Card>(value)
+    }
+    .map_err(|e| format!("The card is missing fields: {}", e))?;
+
+    if card.name.trim().is_empty() {
+        return Err("The card has no name".to_string());
+    }
+    Ok(card)
+}
+
+/// The text of the first `tEXt` chunk with the given keyword.
+fn png_text_chunk<'a>(png: &'a [u8], keyword: &[u8]) -> Option<&'a [u8]> {
+    let mut rest = png.get(PNG_SIGNATURE.len()..)?;
+    // Each chunk: 4-byte big-endian length, 4-byte type, data, 4-byte CRC
+    while rest.len() >= 12 {
+        let len = u32::from_be_bytes(rest[..4].try_into().ok()?) as usize;
+        let kind = &rest[4..8];
+        let data = rest.get(8..8 + len)?;
+        if kind == b"tEXt" {
+            if let Some((key, text)) = data.split_at_checked(keyword.len()) {
+                if key == keyword && text.first() == Some(&0) {
+                    return Some(&text[1..]);
+                }
+            }
+        }
+        if kind == b"IEND" {
+            return None;
+        }
+        rest = rest.get(12 + len..)?;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V2_CARD: &str = r#"{
+        "spec": "chara_card_v2",
+        "spec_version": "2.0",
+        "data": {
+            "name": "Grumpy",
+            "description": "{{char}} is an old dwarf who hates {{user}}.",
+            "personality": "Grumpy",
+            "scenario": "",
+            "first_mes": "What do YOU want?",
+            "mes_example": "",
+            "tags": ["dwarf"]
+        }
+    }"#;
+
+    fn png_with_text(keyword: &str, text: &str) -> Vec<u8> {
+        let mut png = PNG_SIGNATURE.to_vec();
+        for (kind, data) in [
+            (&b"IHDR"[..], vec![0; 13]),
+            (&b"tEXt"[..], [keyword.as_bytes(), &[0], text.as_bytes()].concat()),
+            (&b"IEND"[..], Vec::new()),
+        ] {
+            png.extend((data.len() as u32).to_be_bytes());
+            png.extend(kind);
+            png.extend(&data);
+            png.extend([0; 4]);
+        }
+        png
+    }
+
+    #[test]
+    fn test_parse_v2_json() {
+        let card = parse(V2_CARD.as_bytes()).unwrap();
+        assert_eq!(card.name, "Grumpy");
+        assert_eq!(card.greeting().as_deref(), Some("What do YOU want?"));
+        assert_eq!(
+            card.system_prompt(),
+            "You are Grumpy. Stay in character.\n\n\
+             Description: Grumpy is an old dwarf who hates the user.\n\n\
+             Personality: Grumpy"
+        );
+    }
+
+    #[test]
+    fn test_parse_v1_json() {
+        let card = parse(br#"{"name": "Bob", "description": "A bob.", "first_mes": ""}"#).unwrap();
+        assert_eq!(card.name, "Bob");
+        assert_eq!(card.greeting(), None);
+    }
+
+    #[test]
+    fn test_parse_png() {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(V2_CARD);
+        let card = parse(&png_with_text("chara", &encoded)).unwrap();
+        assert_eq!(card.name, "Grumpy");
+
+        assert!(parse(&png_with_text("Comment", "hello")).is_err());
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_cards() {
+        assert!(parse(b"not json").is_err());
+        assert!(parse(br#"{"description": "no name"}"#).is_err());
+        assert!(parse(br#"{"name": " "}"#).is_err());
+    }
+}
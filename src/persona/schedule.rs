@@ -0,0 +1,188 @@
+use chrono::{Datelike, NaiveDate};
+use serenity::http::Http;
+use serenity::model::id::ChannelId;
+use tracing::{error, info};
+
+use crate::{db, Handler};
+
+/// How long each persona lasts in a rotation unless `!persona schedule every`
+/// says otherwise.
+pub const DEFAULT_ROTATION_DAYS: u32 = 7;
+
+/// Parses a `MM-DD` date, normalized to two digits each so dates compare as strings.
+pub fn parse_month_day(text: &str) -> Option<String> {
+    let (month, day) = text.split_once('-')?;
+    let (month, day): (u32, u32) = (month.parse().ok()?, day.parse().ok()?);
+    // 2024 is a leap year, so Feb 29 is allowed
+    NaiveDate::from_ymd_opt(2024, month, day)?;
+    Some(format!("{:02}-{:02}", month, day))
+}
+
+/// Whether `date` falls in `starts..=ends`, which wraps over New Year when
+/// `ends` comes before `starts` (e.g. `12-20` to `01-05`).
+fn in_range(date: &str, starts: &str, ends: &str) -> bool {
+    if starts <= ends {
+        starts <= date && date <= ends
+    } else {
+        date >= starts || date <= ends
+    }
+}
+
+/// The persona the schedule wants on `date`: a dated entry covering it, else
+/// the rotation's turn, else none. The first matching dated entry wins.
+pub fn scheduled(entries: &[db::PersonaScheduleEntry], date: NaiveDate, rotation_days: u32) -> Option<&str> {
+    let today = format!("{:02}-{:02}", date.month(), date.day());
+    let dated = entries.iter().find(|e| match (&e.starts, &e.ends) {
+        (Some(starts), Some(ends)) => in_range(&today, starts, ends),
+        _ => false,
+    });
+    if let Some(entry) = dated {
+        return Some(&entry.persona);
+    }
+    let rotation: Vec<&db::PersonaScheduleEntry> = entries.iter().filter(|e| e.starts.is_none()).collect();
+    if rotation.is_empty() {
+        return None;
+    }
+    let day = date.num_days_from_ce() as u32 / rotation_days.max(1);
+    Some(&rotation[day as usize % rotation.len()].persona)
+}
+
+impl Handler {
+    /// Switches the bot's persona to whatever the schedule wants on `date`,
+    /// announcing changes in the `!persona schedule announce` channel. When
+    /// the schedule stops wanting one, the prompt from before it is restored.
+    /// A persona picked with `!persona use` in between stays until the
+    /// schedule next changes.
+    pub(crate) async fn apply_persona_schedule(&self, http: &Http, date: NaiveDate) {
+        let announcement = {
+            let conn = self.db.lock().await;
+            match self.switch_scheduled_persona(&conn, date) {
+                Ok(Some(announcement)) => announcement,
+                Ok(None) => return,
+                Err(e) => {
+                    error!("Failed to apply persona schedule: {}", e);
+                    return;
+                }
+            }
+        };
+        let conn = self.db.lock().await;
+        let channel = db::get_config(&conn, None, "persona_announce_channel").ok().flatten();
+        drop(conn);
+        if let Some(channel_id) = channel.and_then(|c| c.parse().ok()).map(ChannelId::new) {
+            if let Err(why) = channel_id.say(http, &announcement).await {
+                error!("Error sending message: {:?}", why);
+            }
+        }
+    }
+
+    /// Makes the switch, returning what to announce if anything changed.
+    fn switch_scheduled_persona(&self, conn: &rusqlite::Connection, date: NaiveDate) -> rusqlite::Result<Option<String>> {
+        let entries = db::get_persona_schedule(conn)?;
+        let rotation_days = db::get_config(conn, None, "persona_rotation_days")?
+            .and_then(|d| d.parse().ok())
+            .unwrap_or(DEFAULT_ROTATION_DAYS);
+        let wanted = scheduled(&entries, date, rotation_days);
+        let applied = db::get_config(conn, None, "scheduled_persona")?;
+        if wanted == applied.as_deref() {
+            return Ok(None);
+        }
+
+        let Some(name) = wanted else {
+            let base = db::get_config(conn, None, "persona_base_prompt")?.unwrap_or_default();
+            db::set_config(conn, None, "system_prompt", &base)?;
+            db::delete_config(conn, None, "persona_base_prompt")?;
+            db::delete_config(conn, None, "scheduled_persona")?;
+            info!("Persona schedule ended, restored the previous persona");
+            return Ok(Some("🎭 Back to my usual self.".to_string()));
+        };
+        let Some(persona) = db::get_persona(conn, name)? else {
+            // Removed since it was scheduled; try again next tick
+            return Ok(None);
+        };
+        if applied.is_none() {
+            let current = db::get_config(conn, None, "system_prompt")?.unwrap_or_default();
+            db::set_config(conn, None, "persona_base_prompt", &current)?;
+        }
+        db::set_config(conn, None, "system_prompt", &persona.system_prompt)?;
+        db::set_config(conn, None, "scheduled_persona", &persona.name)?;
+        info!("Persona schedule switched to {}", persona.name);
+        Ok(Some(match persona.first_message.as_str() {
+            "" => format!("🎭 I'm **{}** now.", persona.name),
+            greeting => format!("🎭 {}", greeting),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::mock;
+
+    fn entry(persona: &str, dates: Option<(&str, &str)>) -> db::PersonaScheduleEntry {
+        db::PersonaScheduleEntry {
+            id: 0,
+            persona: persona.to_string(),
+            starts: dates.map(|(s, _)| s.to_string()),
+            ends: dates.map(|(_, e)| e.to_string()),
+        }
+    }
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_parse_month_day() {
+        assert_eq!(parse_month_day("10-1").as_deref(), Some("10-01"));
+        assert_eq!(parse_month_day("02-29").as_deref(), Some("02-29"));
+        assert_eq!(parse_month_day("02-30"), None);
+        assert_eq!(parse_month_day("13-01"), None);
+        assert_eq!(parse_month_day("halloween"), None);
+    }
+
+    #[test]
+    fn test_scheduled() {
+        let entries = [
+            entry("Spooky", Some(("10-01", "10-31"))),
+            entry("Festive", Some(("12-01", "01-06"))),
+        ];
+        assert_eq!(scheduled(&entries, date(10, 31), 7), Some("Spooky"));
+        assert_eq!(scheduled(&entries, date(1, 2), 7), Some("Festive"));
+        assert_eq!(scheduled(&entries, date(11, 1), 7), None);
+
+        let rotation = [entry("A", None), entry("B", None), entry("Spooky", Some(("10-31", "10-31")))];
+        let first = scheduled(&rotation, date(3, 4), 1).unwrap();
+        let second = scheduled(&rotation, date(3, 5), 1).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(scheduled(&rotation, date(3, 6), 1), Some(first));
+        // Dated entries win over the rotation
+        assert_eq!(scheduled(&rotation, date(10, 31), 1), Some("Spooky"));
+    }
+
+    #[tokio::test]
+    async fn test_switch_and_restore() {
+        let handler = mock::handler(None, None);
+        let conn = handler.db.lock().await;
+        db::set_config(&conn, None, "system_prompt", "You are rude.").unwrap();
+        let spooky = db::Persona {
+            name: "Spooky".to_string(),
+            description: String::new(),
+            personality: String::new(),
+            scenario: String::new(),
+            first_message: String::new(),
+            system_prompt: "You are a ghost.".to_string(),
+            created_by: "admin".to_string(),
+        };
+        db::save_persona(&conn, &spooky).unwrap();
+        db::add_persona_schedule(&conn, "Spooky", Some("10-01"), Some("10-31"), "admin").unwrap();
+
+        let announcement = handler.switch_scheduled_persona(&conn, date(10, 2)).unwrap();
+        assert_eq!(announcement.as_deref(), Some("🎭 I'm **Spooky** now."));
+        assert_eq!(db::get_config(&conn, None, "system_prompt").unwrap().as_deref(), Some("You are a ghost."));
+        assert_eq!(handler.switch_scheduled_persona(&conn, date(10, 3)).unwrap(), None);
+
+        assert!(handler.switch_scheduled_persona(&conn, date(11, 1)).unwrap().is_some());
+        assert_eq!(db::get_config(&conn, None, "system_prompt").unwrap().as_deref(), Some("You are rude."));
+        assert_eq!(handler.switch_scheduled_persona(&conn, date(11, 2)).unwrap(), None);
+    }
+}
@@ -0,0 +1,153 @@
+use chrono::{DateTime, Local, Utc};
+use serenity::http::Http;
+use serenity::model::id::{ChannelId, GuildId};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::bots::Conversation;
+use crate::{db, Handler};
+
+/// How long a channel's topic is remembered before asking Discord again.
+const TOPIC_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Where a message was sent, as far as Discord would tell us.
+#[derive(Default)]
+pub struct Place {
+    pub guild: Option<String>,
+    pub channel: Option<String>,
+}
+
+/// Channel topics fetched from Discord, so each chat message doesn't cost an
+/// API call. `None` is cached too, for channels without a topic.
+#[derive(Default)]
+pub struct TopicCache {
+    topics: Mutex<HashMap<ChannelId, (Instant, Option<String>)>>,
+}
+
+impl TopicCache {
+    fn get(&self, channel_id: ChannelId, now: Instant) -> Option<Option<String>> {
+        let topics = self.topics.lock().unwrap();
+        let (fetched, topic) = topics.get(&channel_id)?;
+        (now.duration_since(*fetched) < TOPIC_TTL).then(|| topic.clone())
+    }
+
+    fn insert(&self, channel_id: ChannelId, topic: Option<String>, now: Instant) {
+        self.topics.lock().unwrap().insert(channel_id, (now, topic));
+    }
+}
+
+/// The note keeping the LLM on the channel's subject.
+pub fn topic_note(topic: &str) -> Option<String> {
+    let topic = topic.trim();
+    (!topic.is_empty()).then(|| format!("This channel is for: {}", topic))
+}
+
+/// The note telling the LLM what time it is and where it's talking.
+pub fn render(now: DateTime<Utc>, local: DateTime<Local>, place: &Place) -> String {
+    let mut lines = vec![format!(
+        "Current time: {} UTC ({} local time, {}).",
+        now.format("%Y-%m-%d %H:%M"),
+        local.format("%H:%M"),
+        local.format("%A")
+    )];
+    match (&place.guild, &place.channel) {
+        (Some(guild), Some(channel)) => lines.push(format!("You are in #{} on the Discord server \"{}\".", channel, guild)),
+        (Some(guild), None) => lines.push(format!("You are on the Discord server \"{}\".", guild)),
+        (None, Some(channel)) => lines.push(format!("You are in #{}.", channel)),
+        (None, None) => lines.push("You are in a direct message.".to_string()),
+    }
+    lines.join("\n")
+}
+
+impl Handler {
+    /// Adds the time and server preamble to `conversation`, unless the guild
+    /// turned it off with `!preamble off`. Lookups that fail are left out.
+    pub(crate) async fn add_preamble(&self, http: &Http, conversation: &mut Conversation) {
+        if let Some(guild_id) = conversation.guild_id {
+            let conn = self.db.lock().await;
+            if !db::is_preamble_enabled(&conn, &guild_id.to_string()) {
+                return;
+            }
+        }
+        let place = lookup_place(http, conversation.guild_id, conversation.channel_id).await;
+        conversation.notes.push(render(Utc::now(), Local::now(), &place));
+    }
+
+    /// Adds the channel's topic to `conversation`, so replies stay on the
+    /// channel's subject. Topics are cached for [`TOPIC_TTL`].
+    pub(crate) async fn add_channel_topic(&self, http: &Http, conversation: &mut Conversation) {
+        if conversation.guild_id.is_none() {
+            return;
+        }
+        let channel_id = conversation.channel_id;
+        let topic = match self.topics.get(channel_id, Instant::now()) {
+            Some(topic) => topic,
+            None => match channel_id.to_channel(http).await {
+                Ok(channel) => {
+                    let topic = channel.guild().and_then(|c| c.topic);
+                    self.topics.insert(channel_id, topic.clone(), Instant::now());
+                    topic
+                }
+                // Not cached, so the next message tries again
+                Err(_) => None,
+            },
+        };
+        if let Some(note) = topic.as_deref().and_then(topic_note) {
+            conversation.notes.push(note);
+        }
+    }
+}
+
+async fn lookup_place(http: &Http, guild_id: Option<GuildId>, channel_id: ChannelId) -> Place {
+    let Some(guild_id) = guild_id else {
+        return Place::default();
+    };
+    let mut place = Place {
+        guild: guild_id.to_partial_guild(http).await.ok().map(|g| g.name),
+        ..Place::default()
+    };
+    if let Some(channel) = channel_id.to_channel(http).await.ok().and_then(|c| c.guild()) {
+        place.channel = Some(channel.name);
+    }
+    place
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_render() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 18, 30, 0).unwrap();
+        let local = now.with_timezone(&Local);
+        let place = Place {
+            guild: Some("Azeroth".to_string()),
+            channel: Some("general".to_string()),
+        };
+        let text = render(now, local, &place);
+        assert!(text.starts_with("Current time: 2024-03-01 18:30 UTC"));
+        assert!(text.ends_with("You are in #general on the Discord server \"Azeroth\"."));
+
+        assert!(render(now, local, &Place::default()).ends_with("You are in a direct message."));
+    }
+
+    #[test]
+    fn test_topic_note() {
+        assert_eq!(topic_note(" Raid talk only "), Some("This channel is for: Raid talk only".to_string()));
+        assert_eq!(topic_note("  "), None);
+    }
+
+    #[test]
+    fn test_topic_cache() {
+        let cache = TopicCache::default();
+        let (channel, start) = (ChannelId::new(1), Instant::now());
+        assert_eq!(cache.get(channel, start), None);
+        cache.insert(channel, Some("Raids".to_string()), start);
+        assert_eq!(cache.get(channel, start + Duration::from_secs(60)), Some(Some("Raids".to_string())));
+        assert_eq!(cache.get(channel, start + TOPIC_TTL), None);
+        cache.insert(channel, None, start);
+        assert_eq!(cache.get(channel, start), Some(None));
+    }
+}
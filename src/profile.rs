@@ -0,0 +1,171 @@
+use futures::future::join_all;
+use serenity::builder::{CreateEmbed, CreateEmbedFooter};
+use serenity::model::user::User;
+use tracing::error;
+
+use crate::{db, scheduler, steam, Handler};
+
+/// Linked accounts shown per game, so a profile stays within Discord's
+/// 25-field embed limit.
+const MAX_ACCOUNTS_PER_GAME: usize = 5;
+
+/// A game whose accounts can be linked to a Discord user.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Game {
+    Wow,
+    Osrs,
+    Lol,
+    Steam,
+}
+
+impl Game {
+    pub const ALL: [Game; 4] = [Game::Wow, Game::Osrs, Game::Lol, Game::Steam];
+
+    /// Short name used in commands and the database.
+    pub fn slug(self) -> &'static str {
+        match self {
+            Game::Wow => "wow",
+            Game::Osrs => "osrs",
+            Game::Lol => "lol",
+            Game::Steam => steam::GAME,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Game::Wow => "World of Warcraft",
+            Game::Osrs => "Old School RuneScape",
+            Game::Lol => "League of Legends",
+            Game::Steam => "Steam",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Game> {
+        Game::ALL.into_iter().find(|g| g.slug().eq_ignore_ascii_case(name))
+    }
+
+    fn game_names() -> String {
+        let names: Vec<_> = Game::ALL.iter().map(|g| format!("`{}`", g.slug())).collect();
+        names.join(", ")
+    }
+}
+
+pub fn unknown_game(name: &str) -> String {
+    format!("Unknown game `{}`. Try {}.", name, Game::game_names())
+}
+
+/// OSRS hiscores page for `account`; there's no OSRS API client, so the
+/// profile links to it rather than showing levels.
+fn osrs_hiscores_url(account: &str) -> String {
+    format!(
+        "https://secure.runescape.com/m=hiscore_oldschool/hiscorepersonal?user1={}",
+        account.replace(' ', "+")
+    )
+}
+
+/// One line of the WoW field: level and class, plus levels gained this week
+/// when the character is tracked.
+pub fn wow_line(name: &str, level: u32, class: &str, week_ago: Option<u32>) -> String {
+    let progress = match week_ago {
+        Some(before) if level > before => format!(" (+{} this week)", level - before),
+        _ => String::new(),
+    };
+    format!("**{}** — level {} {}{}", name, level, class, progress)
+}
+
+impl Handler {
+    async fn wow_profile_line(&self, name: &str) -> String {
+        if self.blizzard.is_none() {
+            return format!("**{}**", name);
+        }
+        match self.fetch_wow_character(name).await {
+            Ok(character) => {
+                let week_ago = {
+                    let conn = self.db.lock().await;
+                    db::snapshot_at_or_before(&conn, name, scheduler::unix_now() - scheduler::WEEK_SECS)
+                        .ok()
+                        .flatten()
+                        .map(|s| s.level)
+                };
+                wow_line(&character.name, character.level, &character.character_class.name, week_ago)
+            }
+            Err(e) => format!("**{}** — {}", name, e),
+        }
+    }
+
+    /// Steam IDs as persona names with what they're playing, or as-is if
+    /// Steam can't be asked.
+    async fn steam_profile_lines(&self, steam_ids: &[&str]) -> Vec<String> {
+        let ids: Vec<String> = steam_ids.iter().map(|id| id.to_string()).collect();
+        let players = match &self.steam {
+            Some(steam) => steam.player_summaries(&ids).await.unwrap_or_default(),
+            None => Vec::new(),
+        };
+        ids.iter()
+            .map(|id| match players.iter().find(|p| &p.steamid == id) {
+                Some(player) => steam::describe_playing(player),
+                None => format!("`{}`", id),
+            })
+            .collect()
+    }
+
+    /// `!profile @user`: every account `user` has linked, one field per game.
+    pub(crate) async fn profile_embed(&self, user: &User) -> CreateEmbed {
+        let accounts = {
+            let conn = self.db.lock().await;
+            db::get_linked_accounts(&conn, &user.id.to_string()).unwrap_or_else(|e| {
+                error!("Failed to load linked accounts for {}: {}", user.name, e);
+                Vec::new()
+            })
+        };
+        let mut embed = CreateEmbed::new()
+            .title(format!("{}'s profile", user.name))
+            .thumbnail(user.face());
+        if accounts.is_empty() {
+            return embed.description("No linked accounts. Link one with `!link <game> <name>`.");
+        }
+
+        for game in Game::ALL {
+            let names: Vec<&str> = accounts
+                .iter()
+                .filter(|a| a.game == game.slug())
+                .map(|a| a.account.as_str())
+                .take(MAX_ACCOUNTS_PER_GAME)
+                .collect();
+            if names.is_empty() {
+                continue;
+            }
+            let lines = match game {
+                Game::Wow => join_all(names.iter().map(|name| self.wow_profile_line(name))).await,
+                Game::Osrs => names
+                    .iter()
+                    .map(|name| format!("[{}]({})", name, osrs_hiscores_url(name)))
+                    .collect(),
+                Game::Lol => join_all(names.iter().map(|riot_id| self.riot_profile_line(riot_id))).await,
+                Game::Steam => self.steam_profile_lines(&names).await,
+            };
+            embed = embed.field(game.name(), lines.join("\n"), false);
+        }
+        embed.footer(CreateEmbedFooter::new("Link more with !link <game> <name>"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(Game::from_name("WoW"), Some(Game::Wow));
+        assert_eq!(Game::from_name("osrs"), Some(Game::Osrs));
+        assert_eq!(Game::from_name("eve"), None);
+        assert_eq!(unknown_game("eve"), "Unknown game `eve`. Try `wow`, `osrs`, `lol`, `steam`.");
+    }
+
+    #[test]
+    fn test_wow_line() {
+        assert_eq!(wow_line("Pyuul", 42, "Druid", Some(38)), "**Pyuul** — level 42 Druid (+4 this week)");
+        assert_eq!(wow_line("Pyuul", 42, "Druid", Some(42)), "**Pyuul** — level 42 Druid");
+        assert_eq!(wow_line("Pyuul", 42, "Druid", None), "**Pyuul** — level 42 Druid");
+    }
+}
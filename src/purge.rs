@@ -0,0 +1,141 @@
+use serenity::builder::{CreateActionRow, CreateButton, CreateInteractionResponse, CreateInteractionResponseMessage};
+use serenity::http::Http;
+use serenity::model::application::{ButtonStyle, ComponentInteraction};
+use serenity::model::id::UserId;
+use serenity::model::user::User;
+use serenity::prelude::Context;
+use tracing::{error, info, warn};
+
+use crate::db::{self, Purged};
+use crate::Handler;
+
+/// Prefix for the `custom_id` of the `!purgeme` buttons; whether it's the
+/// confirm button and the user whose data goes follow, so only they can press it.
+pub const CUSTOM_ID_PREFIX: &str = "purge:";
+
+pub fn custom_id(confirm: bool, user_id: UserId) -> String {
+    format!("{}{}:{}", CUSTOM_ID_PREFIX, if confirm { "confirm" } else { "cancel" }, user_id)
+}
+
+/// Whether a `!purgeme` button confirms, and whose data it's for.
+pub fn from_custom_id(custom_id: &str) -> Option<(bool, UserId)> {
+    let (action, user) = custom_id.strip_prefix(CUSTOM_ID_PREFIX)?.split_once(':')?;
+    let confirm = match action {
+        "confirm" => true,
+        "cancel" => false,
+        _ => return None,
+    };
+    Some((confirm, UserId::new(user.parse().ok().filter(|&id| id != 0)?)))
+}
+
+/// Confirm and cancel buttons for `!purgeme`.
+pub fn buttons(user_id: UserId) -> Vec<CreateActionRow> {
+    vec![CreateActionRow::Buttons(vec![
+        CreateButton::new(custom_id(true, user_id))
+            .label("Delete my data")
+            .style(ButtonStyle::Danger),
+        CreateButton::new(custom_id(false, user_id))
+            .label("Cancel")
+            .style(ButtonStyle::Secondary),
+    ])]
+}
+
+/// What a purge leaves behind, said whenever one is asked for or done.
+pub const KEPT: &str = "Kept: your `!listen` opt-out, so you stay unrecorded, the moderation log, \
+     and archive files already written.";
+
+/// The names `user`'s older messages, ticket logs and `!rp` turns were stored
+/// under. Server nicknames aren't known here, so rows under one are missed.
+pub fn stored_names(user: &User) -> Vec<String> {
+    let mut names = vec![user.name.clone()];
+    if let Some(global) = user.global_name.as_ref().filter(|g| **g != user.name) {
+        names.push(global.clone());
+    }
+    names
+}
+
+/// What a purge deleted, for the person who asked.
+pub fn summary(purged: &Purged) -> String {
+    if purged.total() == 0 {
+        return format!("There was nothing stored to delete. {}", KEPT);
+    }
+    format!(
+        "Deleted {} messages, {} memories, {} feedback records, {} preferences, {} linked accounts and {} other records. {}",
+        purged.messages, purged.memories, purged.feedback, purged.preferences, purged.links, purged.other, KEPT
+    )
+}
+
+impl Handler {
+    /// Deletes everything stored about `user_id`, returning what to say.
+    pub(crate) async fn purge_user_data(&self, http: &Http, user_id: UserId, by: &str) -> String {
+        let names = match user_id.to_user(http).await {
+            Ok(user) => stored_names(&user),
+            Err(e) => {
+                warn!("Couldn't look up {} to purge rows stored by name: {}", user_id, e);
+                Vec::new()
+            }
+        };
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let purged = {
+            let conn = self.db.lock().await;
+            db::purge_user(&conn, &user_id.to_string(), &names)
+        };
+        match purged {
+            Ok(purged) => {
+                info!("{} purged the stored data of {} ({} rows)", by, user_id, purged.total());
+                summary(&purged)
+            }
+            Err(e) => {
+                error!("Failed to purge user data: {}", e);
+                "Failed to delete the data; nothing was removed.".to_string()
+            }
+        }
+    }
+
+    pub(crate) async fn handle_purge_button(&self, ctx: &Context, component: &ComponentInteraction, confirm: bool, user_id: UserId) {
+        let response = if component.user.id != user_id {
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content("Only the person who asked can confirm deleting their data. Use `!purgeme` for your own.")
+                    .ephemeral(true),
+            )
+        } else {
+            let content = if confirm {
+                self.purge_user_data(&ctx.http, user_id, &component.user.name).await
+            } else {
+                "Cancelled; nothing was deleted.".to_string()
+            };
+            CreateInteractionResponse::UpdateMessage(CreateInteractionResponseMessage::new().content(content).components(vec![]))
+        };
+        if let Err(why) = component.create_response(&ctx.http, response).await {
+            error!("Error responding to interaction: {:?}", why);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_custom_id_round_trip() {
+        let user = UserId::new(42);
+        assert_eq!(from_custom_id(&custom_id(true, user)), Some((true, user)));
+        assert_eq!(from_custom_id(&custom_id(false, user)), Some((false, user)));
+        assert_eq!(from_custom_id("purge:confirm:0"), None);
+        assert_eq!(from_custom_id("purge:maybe:42"), None);
+        assert_eq!(from_custom_id("help:chat"), None);
+    }
+
+    #[test]
+    fn test_summary() {
+        assert!(summary(&Purged::default()).starts_with("There was nothing stored to delete. Kept:"));
+        let purged = Purged {
+            messages: 3,
+            links: 1,
+            ..Purged::default()
+        };
+        assert!(summary(&purged).starts_with("Deleted 3 messages, 0 memories"));
+        assert!(summary(&purged).ends_with(KEPT));
+    }
+}
@@ -0,0 +1,113 @@
+use chrono::{NaiveTime, Timelike};
+
+/// Longest slowmode Discord allows, six hours.
+pub const MAX_SLOWMODE_SECS: u16 = 6 * 60 * 60;
+
+/// A daily window as minutes after midnight, bot local time. `start > end`
+/// wraps past midnight, as in `23:00-07:00`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Window {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl Window {
+    /// Parses `HH:MM-HH:MM`. A window that starts and ends at the same time
+    /// would be empty, so it's rejected.
+    pub fn parse(input: &str) -> Option<Window> {
+        let (start, end) = input.split_once('-')?;
+        let minute = |s: &str| {
+            let time = NaiveTime::parse_from_str(s.trim(), "%H:%M").ok()?;
+            Some(time.hour() * 60 + time.minute())
+        };
+        let window = Window {
+            start: minute(start)?,
+            end: minute(end)?,
+        };
+        (window.start != window.end).then_some(window)
+    }
+
+    /// Whether `time` falls in the window, counting its start but not its end.
+    pub fn contains(self, time: NaiveTime) -> bool {
+        let minute = time.hour() * 60 + time.minute();
+        if self.start < self.end {
+            (self.start..self.end).contains(&minute)
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+}
+
+/// `minute` after midnight as `HH:MM`.
+pub fn clock(minute: u32) -> String {
+    format!("{:02}:{:02}", minute / 60, minute % 60)
+}
+
+impl std::fmt::Display for Window {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", clock(self.start), clock(self.end))
+    }
+}
+
+/// What happens to the channel during quiet hours.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    /// Nobody but moderators can send messages.
+    Lock,
+    /// Everyone waits this many seconds between messages.
+    Slowmode(u16),
+}
+
+impl Action {
+    /// The action as stored: `None` for a lock, else the slowmode seconds.
+    pub fn slowmode_secs(self) -> Option<u16> {
+        match self {
+            Action::Lock => None,
+            Action::Slowmode(secs) => Some(secs),
+        }
+    }
+
+    pub fn from_slowmode_secs(secs: Option<u16>) -> Action {
+        secs.map_or(Action::Lock, Action::Slowmode)
+    }
+
+    pub fn describe(self) -> String {
+        match self {
+            Action::Lock => "locked".to_string(),
+            Action::Slowmode(secs) => format!("in {}s slowmode", secs),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Window::parse("23:00-07:00"), Some(Window { start: 1380, end: 420 }));
+        assert_eq!(Window::parse("9:30 - 17:00"), Some(Window { start: 570, end: 1020 }));
+        assert_eq!(Window::parse("23:00-07:00").unwrap().to_string(), "23:00-07:00");
+        assert_eq!(Window::parse("07:00-07:00"), None);
+        assert_eq!(Window::parse("24:00-07:00"), None);
+        assert_eq!(Window::parse("23:00"), None);
+    }
+
+    #[test]
+    fn test_contains() {
+        let overnight = Window::parse("23:00-07:00").unwrap();
+        assert!(overnight.contains(at(23, 0)));
+        assert!(overnight.contains(at(3, 15)));
+        assert!(!overnight.contains(at(7, 0)));
+        assert!(!overnight.contains(at(12, 0)));
+
+        let daytime = Window::parse("09:00-17:00").unwrap();
+        assert!(daytime.contains(at(9, 0)));
+        assert!(!daytime.contains(at(17, 0)));
+        assert!(!daytime.contains(at(23, 0)));
+    }
+}
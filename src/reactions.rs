@@ -0,0 +1,229 @@
+use serenity::builder::{CreateMessage, EditMessage};
+use serenity::model::channel::{Reaction, ReactionType};
+use serenity::http::Http;
+use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+use serenity::prelude::Context;
+use std::sync::Arc;
+use tracing::{error, info};
+
+use crate::bots::Conversation;
+use crate::{db, markdown, mentions, trace, Handler};
+
+/// What a reaction on one of the bot's replies asks for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    /// 🔁 — answer the same prompt again, in place.
+    Regenerate,
+    /// 📌 — keep the exchange in the memories table.
+    Pin,
+    /// 🗑️ — delete the reply and forget the exchange.
+    Delete,
+    /// ❓ — explain the reply in more detail.
+    Explain,
+}
+
+impl Action {
+    /// Only whoever asked may redo or delete a reply; anyone may pin or ask.
+    fn requester_only(self) -> bool {
+        matches!(self, Action::Regenerate | Action::Delete)
+    }
+}
+
+fn action(emoji: &ReactionType) -> Option<Action> {
+    let ReactionType::Unicode(e) = emoji else {
+        return None;
+    };
+    // Clients send 🗑️ with or without the variation selector
+    match e.trim_end_matches('\u{fe0f}') {
+        "🔁" => Some(Action::Regenerate),
+        "📌" => Some(Action::Pin),
+        "🗑" => Some(Action::Delete),
+        "❓" => Some(Action::Explain),
+        _ => None,
+    }
+}
+
+impl Handler {
+    /// Carries out the 🔁 📌 🗑️ ❓ reactions on the bot's own replies.
+    pub(crate) async fn handle_reaction_action(&self, ctx: &Context, reaction: &Reaction) {
+        let Some(action) = action(&reaction.emoji) else {
+            return;
+        };
+        let Some(user_id) = reaction.user_id else {
+            return;
+        };
+        let me = match ctx.http.get_current_user().await {
+            Ok(user) => user.id,
+            Err(e) => {
+                error!("Failed to get current user: {:?}", e);
+                return;
+            }
+        };
+        // Only replies this bot sent, and never its own reactions
+        if user_id == me || reaction.message_author_id.is_some_and(|author| author != me) {
+            return;
+        }
+        let message_id = reaction.message_id.to_string();
+        let exchange = {
+            let conn = self.db.lock().await;
+            match db::get_exchange_record(&conn, &message_id) {
+                Ok(Some(exchange)) => exchange,
+                Ok(None) => return,
+                Err(e) => {
+                    error!("Failed to load exchange: {}", e);
+                    return;
+                }
+            }
+        };
+        if action.requester_only() && exchange.user_id.as_deref() != Some(user_id.to_string().as_str()) {
+            return;
+        }
+
+        match action {
+            Action::Regenerate => {
+                self.regenerate(&ctx.http, reaction.guild_id, reaction.channel_id, reaction.message_id, &exchange, &exchange.prompt)
+                    .await
+            }
+            Action::Pin => {
+                let name = match user_id.to_user(&ctx.http).await {
+                    Ok(user) => user.name,
+                    Err(_) => user_id.to_string(),
+                };
+                let conn = self.db.lock().await;
+                match db::save_memory(&conn, &reaction.channel_id.to_string(), &exchange.prompt, &exchange.reply, &name, exchange.user_id.as_deref()) {
+                    Ok(()) => info!("{} saved reply {} to memories", name, reaction.message_id),
+                    Err(e) => error!("Failed to save memory: {}", e),
+                }
+            }
+            Action::Delete => {
+                let (sent, _) = self.forget(reaction.message_id, &exchange).await;
+                for id in sent {
+                    if let Err(why) = reaction.channel_id.delete_message(&ctx.http, id).await {
+                        error!("Error deleting message: {:?}", why);
+                    }
+                }
+                info!("{} deleted reply {}", user_id, reaction.message_id);
+            }
+            Action::Explain => {
+                let system_prompt = {
+                    let conn = self.db.lock().await;
+                    self.channel_system_prompt(&conn, reaction.guild_id, reaction.channel_id).unwrap_or_default()
+                };
+                let question = format!(
+                    "Earlier you were asked:\n{}\n\nYou replied:\n{}\n\nExplain that reply in more detail: what you meant and why.",
+                    exchange.prompt, exchange.reply
+                );
+                let typing = reaction.channel_id.start_typing(&ctx.http);
+                let response = match self.query_llm_oneshot(system_prompt, question).await {
+                    Ok(explanation) => explanation,
+                    Err(e) => {
+                        error!("LLM error: {}", e);
+                        format!("Sorry, I couldn't explain that: {}{}", e, trace::error_ref())
+                    }
+                };
+                drop(typing);
+                let allow_users = self.allows_user_mentions(reaction.guild_id).await;
+                let response = mentions::sanitize(&response, allow_users);
+                let message = CreateMessage::new()
+                    .content(markdown::truncate(&response, markdown::DISCORD_MESSAGE_MAX))
+                    .reference_message((reaction.channel_id, reaction.message_id))
+                    .allowed_mentions(mentions::allowed(allow_users));
+                if let Err(why) = reaction.channel_id.send_message(&ctx.http, message).await {
+                    error!("Error sending message: {:?}", why);
+                }
+            }
+        }
+    }
+
+    /// Drops an exchange from its history and the feedback records, returning
+    /// the messages its reply was sent in and who asked.
+    pub(crate) async fn forget(&self, message_id: MessageId, exchange: &db::Exchange) -> (Vec<MessageId>, Option<String>) {
+        let conn = self.db.lock().await;
+        let message_id = message_id.to_string();
+        let sent = db::exchange_message_ids(&conn, &message_id, exchange).unwrap_or_default();
+        let speaker = match &exchange.history_key {
+            Some(key) => db::forget_exchange(&conn, &message_id, key, exchange).unwrap_or_else(|e| {
+                error!("Failed to forget exchange: {}", e);
+                None
+            }),
+            None => None,
+        };
+        let sent = sent.iter().filter_map(|id| id.parse().ok()).map(MessageId::new).collect();
+        (sent, speaker)
+    }
+
+    /// Answers `prompt` again in place of `exchange` and puts the new reply in
+    /// the message `reply`; the old exchange leaves the history and the rest of
+    /// a reply that was split over several messages is deleted. Used for 🔁
+    /// with the same prompt, and for edited prompts.
+    pub(crate) async fn regenerate(
+        &self,
+        http: &Arc<Http>,
+        guild_id: Option<GuildId>,
+        channel_id: ChannelId,
+        reply: MessageId,
+        exchange: &db::Exchange,
+        prompt: &str,
+    ) {
+        let Some(key) = exchange.history_key.clone() else {
+            return;
+        };
+        let (sent, speaker) = self.forget(reply, exchange).await;
+        for id in sent.into_iter().filter(|id| *id != reply) {
+            if let Err(why) = channel_id.delete_message(http, id).await {
+                error!("Error deleting message: {:?}", why);
+            }
+        }
+        let parse_id = |id: &Option<String>| id.as_deref().and_then(|id| id.parse().ok());
+        let mut conversation = Conversation {
+            guild_id,
+            channel_id,
+            key,
+            message_id: parse_id(&exchange.prompt_message_id).map(MessageId::new),
+            user_id: parse_id(&exchange.user_id).map(UserId::new),
+            speaker,
+            notes: Vec::new(),
+        };
+        let typing = channel_id.start_typing(http);
+        self.add_preamble(http, &mut conversation).await;
+        self.add_channel_topic(http, &mut conversation).await;
+        self.add_knowledge(&mut conversation, prompt).await;
+        let result = self.ask_llama(&conversation, prompt).await;
+        drop(typing);
+        let new_reply = match result {
+            Ok(reply) => reply,
+            Err(e) => {
+                error!("LLM error: {}", e);
+                return;
+            }
+        };
+
+        let allow_users = self.allows_user_mentions(guild_id).await;
+        let content = markdown::truncate(&mentions::sanitize(&new_reply, allow_users), markdown::DISCORD_MESSAGE_MAX);
+        let edit = EditMessage::new().content(content).allowed_mentions(mentions::allowed(allow_users));
+        if let Err(why) = channel_id.edit_message(http, reply, edit).await {
+            error!("Error editing message: {:?}", why);
+            return;
+        }
+        self.record_exchange(&conversation, prompt, &new_reply, &[reply]).await;
+        info!("Regenerated reply {}", reply);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_action() {
+        let unicode = |e: &str| ReactionType::Unicode(e.to_string());
+        assert_eq!(action(&unicode("🔁")), Some(Action::Regenerate));
+        assert_eq!(action(&unicode("📌")), Some(Action::Pin));
+        assert_eq!(action(&unicode("🗑️")), Some(Action::Delete));
+        assert_eq!(action(&unicode("🗑")), Some(Action::Delete));
+        assert_eq!(action(&unicode("❓")), Some(Action::Explain));
+        assert_eq!(action(&unicode("👍")), None);
+        assert!(Action::Delete.requester_only());
+        assert!(!Action::Pin.requester_only());
+    }
+}
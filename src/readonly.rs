@@ -0,0 +1,221 @@
+use axum::body::{to_bytes, Body};
+use axum::extract::{Request, State};
+use axum::http::{HeaderName, HeaderValue, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use tracing::{error, info, warn};
+
+use crate::markdown;
+
+/// Where requests go when they aren't withheld.
+const DISCORD: &str = "https://discord.com";
+/// Largest request relayed; Discord's own upload limit is well below this.
+const MAX_BODY_BYTES: usize = 100 * 1024 * 1024;
+/// How much of a withheld request's body is logged.
+const LOGGED_BODY_CHARS: usize = 500;
+/// Discord's epoch (2015-01-01) in Unix milliseconds, for made-up snowflakes.
+const DISCORD_EPOCH_MS: i64 = 1_420_070_400_000;
+/// Headers that describe one connection to or from the relay, not the
+/// request or response passed along it.
+const HOP_BY_HOP: [&str; 3] = ["connection", "content-length", "transfer-encoding"];
+
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+/// Whether Discord requests go through the relay, which is only set up when
+/// the bot starts in read-only mode.
+static RELAYED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the bot is in read-only mode: everything is processed and logged,
+/// but nothing is sent to Discord.
+pub fn enabled() -> bool {
+    READ_ONLY.load(Ordering::Relaxed)
+}
+
+pub fn set(on: bool) {
+    READ_ONLY.store(on, Ordering::Relaxed);
+}
+
+/// Whether read-only mode can be switched on without a restart.
+pub fn relayed() -> bool {
+    RELAYED.load(Ordering::Relaxed)
+}
+
+/// Starts a relay for the Discord API on a free local port and returns its base
+/// URL, for [`serenity::http::HttpBuilder::proxy`]. Requests are passed on to
+/// Discord unchanged, except that in read-only mode everything but `GET` is
+/// logged and answered locally instead.
+pub async fn spawn(client: reqwest::Client) -> std::io::Result<String> {
+    let listener = tokio::net::TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).await?;
+    let url = format!("http://{}", listener.local_addr()?);
+    RELAYED.store(true, Ordering::Relaxed);
+    let app = Router::new().fallback(relay).with_state(client);
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            error!("Discord API relay stopped: {}", e);
+        }
+    });
+    Ok(url)
+}
+
+async fn relay(State(client): State<reqwest::Client>, request: Request) -> Response {
+    let (parts, body) = request.into_parts();
+    let Ok(body) = to_bytes(body, MAX_BODY_BYTES).await else {
+        return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+    };
+    let path = parts.uri.path_and_query().map_or("/", |p| p.as_str());
+
+    if enabled() && parts.method != Method::GET {
+        let logged = markdown::truncate(&String::from_utf8_lossy(&body), LOGGED_BODY_CHARS);
+        info!("Read-only: not sending {} {} {}", parts.method, path, logged);
+        return withheld(&parts.method, parts.uri.path(), &body);
+    }
+
+    let Ok(method) = reqwest::Method::from_bytes(parts.method.as_str().as_bytes()) else {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    };
+    let mut forward = client.request(method, format!("{}{}", DISCORD, path)).body(body.to_vec());
+    for (name, value) in &parts.headers {
+        if name != "host" && !HOP_BY_HOP.contains(&name.as_str()) {
+            forward = forward.header(name.as_str(), value.as_bytes());
+        }
+    }
+    let response = match forward.send().await {
+        Ok(response) => response,
+        Err(e) => {
+            warn!("Discord API relay failed for {} {}: {}", parts.method, path, e);
+            return StatusCode::BAD_GATEWAY.into_response();
+        }
+    };
+
+    let status = StatusCode::from_u16(response.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+    let headers: Vec<_> = response
+        .headers()
+        .iter()
+        .filter(|(name, _)| !HOP_BY_HOP.contains(&name.as_str()))
+        .filter_map(|(name, value)| {
+            Some((HeaderName::from_bytes(name.as_str().as_bytes()).ok()?, HeaderValue::from_bytes(value.as_bytes()).ok()?))
+        })
+        .collect();
+    let body = match response.bytes().await {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("Discord API relay failed reading {} {}: {}", parts.method, path, e);
+            return StatusCode::BAD_GATEWAY.into_response();
+        }
+    };
+    let mut relayed = Response::new(Body::from(body));
+    *relayed.status_mut() = status;
+    relayed.headers_mut().extend(headers);
+    relayed
+}
+
+/// What Discord would have answered, near enough: the message for sends and
+/// edits and the channel for new threads, channels and DMs (so the bot can
+/// carry on as if it went out), otherwise nothing.
+fn withheld(method: &Method, path: &str, body: &[u8]) -> Response {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    // Paths look like /api/v10/channels/<id>/messages[/<id>] or /api/v10/webhooks/<id>/<token>
+    let resource = segments.get(2..).unwrap_or_default();
+    let body = serde_json::from_slice::<Value>(body).unwrap_or_default();
+    let text = |field: &str| body[field].as_str().unwrap_or_default().to_string();
+    let fake = match (method, resource) {
+        (&Method::POST, ["channels", id, "messages"]) | (&Method::PATCH, ["channels", id, "messages", _]) => {
+            fake_message(id, &text("content"))
+        }
+        (&Method::POST, ["webhooks", _, _]) | (&Method::PATCH, ["webhooks", _, _, "messages", _]) => {
+            fake_message("1", &text("content"))
+        }
+        // Threads default to public, as Discord's do when started from a message
+        (&Method::POST, ["channels", parent, "threads"] | ["channels", parent, "messages", _, "threads"]) => json!({
+            "id": snowflake(),
+            "type": body["type"].as_u64().unwrap_or(11),
+            "name": text("name"),
+            "parent_id": parent,
+        }),
+        (&Method::POST, ["guilds", guild, "channels"]) => json!({
+            "id": snowflake(),
+            "type": body["type"].as_u64().unwrap_or(0),
+            "name": text("name"),
+            "guild_id": guild,
+            "parent_id": body["parent_id"],
+        }),
+        (&Method::POST, ["users", "@me", "channels"]) => json!({
+            "id": snowflake(),
+            "type": 1,
+            "recipients": [{ "id": body["recipient_id"], "username": "read-only", "discriminator": "0000", "avatar": null }],
+        }),
+        _ => return StatusCode::NO_CONTENT.into_response(),
+    };
+    axum::Json(fake).into_response()
+}
+
+/// A fresh ID, as Discord gives anything it creates.
+fn snowflake() -> String {
+    static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+    let now = chrono::Utc::now().timestamp_millis();
+    (((now - DISCORD_EPOCH_MS) as u64) << 22 | (SEQUENCE.fetch_add(1, Ordering::Relaxed) & 0xfff)).to_string()
+}
+
+/// A message with a fresh ID, as Discord returns for a send.
+fn fake_message(channel_id: &str, content: &str) -> Value {
+    let now = chrono::Utc::now();
+    json!({
+        "id": snowflake(),
+        "channel_id": channel_id,
+        "author": { "id": "1", "username": "read-only", "discriminator": "0000", "avatar": null },
+        "content": content,
+        "timestamp": now.to_rfc3339(),
+        "edited_timestamp": null,
+        "tts": false,
+        "mention_everyone": false,
+        "mentions": [],
+        "mention_roles": [],
+        "attachments": [],
+        "embeds": [],
+        "pinned": false,
+        "type": 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serenity::model::channel::{ChannelType, GuildChannel, Message, PrivateChannel};
+
+    async fn body_of(response: Response) -> Vec<u8> {
+        to_bytes(response.into_body(), MAX_BODY_BYTES).await.unwrap().to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_withheld_send_looks_sent() {
+        let response = withheld(&Method::POST, "/api/v10/channels/42/messages", br#"{"content":"hi"}"#);
+        assert_eq!(response.status(), StatusCode::OK);
+        let message: Message = serde_json::from_slice(&body_of(response).await).unwrap();
+        assert_eq!(message.channel_id.get(), 42);
+        assert_eq!(message.content, "hi");
+
+        let edit = withheld(&Method::PATCH, "/api/v10/webhooks/1/token/messages/@original", br#"{"content":"x"}"#);
+        assert!(serde_json::from_slice::<Message>(&body_of(edit).await).is_ok());
+
+        let typing = withheld(&Method::POST, "/api/v10/channels/42/typing", b"");
+        assert_eq!(typing.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_withheld_channels_parse() {
+        let thread = withheld(&Method::POST, "/api/v10/channels/42/threads", br#"{"name":"Ticket #1","type":12}"#);
+        let thread: GuildChannel = serde_json::from_slice(&body_of(thread).await).unwrap();
+        assert_eq!((thread.parent_id.map(|id| id.get()), thread.kind), (Some(42), ChannelType::PrivateThread));
+        assert_eq!(thread.name, "Ticket #1");
+
+        let voice = withheld(&Method::POST, "/api/v10/guilds/7/channels", br#"{"name":"Squad","type":2,"parent_id":"9"}"#);
+        let voice: GuildChannel = serde_json::from_slice(&body_of(voice).await).unwrap();
+        assert_eq!((voice.guild_id.get(), voice.kind), (7, ChannelType::Voice));
+
+        let dm = withheld(&Method::POST, "/api/v10/users/@me/channels", br#"{"recipient_id":"5"}"#);
+        let dm: PrivateChannel = serde_json::from_slice(&body_of(dm).await).unwrap();
+        assert_eq!(dm.recipient.id.get(), 5);
+    }
+}
@@ -0,0 +1,264 @@
+use base64::Engine;
+use plotters::prelude::*;
+use resvg::tiny_skia::{Pixmap, Transform};
+use resvg::usvg::{self, fontdb};
+use std::sync::{Arc, OnceLock};
+
+const CARD_WIDTH: u32 = 600;
+const CARD_HEIGHT: u32 = 200;
+const FALLBACK_CLASS_COLOR: &str = "#7F7F7F";
+const CHART_WIDTH: u32 = 800;
+const CHART_HEIGHT: u32 = 450;
+const DAY_SECS: f64 = 24.0 * 60.0 * 60.0;
+
+/// What goes on a character card.
+pub struct CardInfo<'a> {
+    pub name: &'a str,
+    pub level: u32,
+    pub race: &'a str,
+    pub class: &'a str,
+    pub realm: &'a str,
+    pub version: &'a str,
+}
+
+/// Blizzard's class colours, as used on the armory and in raid frames.
+pub fn class_color(class: &str) -> &'static str {
+    match class.to_lowercase().as_str() {
+        "death knight" => "#C41E3A",
+        "demon hunter" => "#A330C9",
+        "druid" => "#FF7C0A",
+        "evoker" => "#33937F",
+        "hunter" => "#AAD372",
+        "mage" => "#3FC7EB",
+        "monk" => "#00FF98",
+        "paladin" => "#F48CBA",
+        "priest" => "#FFFFFF",
+        "rogue" => "#FFF468",
+        "shaman" => "#0070DD",
+        "warlock" => "#8788EE",
+        "warrior" => "#C69B6D",
+        _ => FALLBACK_CLASS_COLOR,
+    }
+}
+
+/// Sans-serif families to prefer, most common on Linux hosts first.
+const PREFERRED_FONTS: &[&str] = &["DejaVu Sans", "Liberation Sans", "Noto Sans", "Arial"];
+
+/// System fonts and the family to use for `sans-serif`, loaded once; scanning the
+/// font directories is slow. fontdb assumes Arial is installed, which it rarely is.
+fn fonts() -> &'static (Arc<fontdb::Database>, String) {
+    static FONTS: OnceLock<(Arc<fontdb::Database>, String)> = OnceLock::new();
+    FONTS.get_or_init(|| {
+        let mut db = fontdb::Database::new();
+        db.load_system_fonts();
+        let installed: Vec<String> = db
+            .faces()
+            .flat_map(|face| face.families.iter().map(|(family, _)| family.clone()))
+            .collect();
+        let family = PREFERRED_FONTS
+            .iter()
+            .find(|f| installed.iter().any(|i| i == *f))
+            .map(|f| f.to_string())
+            .or_else(|| installed.first().cloned())
+            .unwrap_or_else(|| PREFERRED_FONTS[0].to_string());
+        db.set_sans_serif_family(family.clone());
+        (Arc::new(db), family)
+    })
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Rasterizes an SVG document to PNG bytes.
+pub fn svg_to_png(svg: &str) -> Result<Vec<u8>, String> {
+    let (fontdb, family) = fonts();
+    let options = usvg::Options {
+        fontdb: fontdb.clone(),
+        font_family: family.clone(),
+        ..Default::default()
+    };
+    let tree = usvg::Tree::from_str(svg, &options).map_err(|e| format!("Invalid SVG: {}", e))?;
+    let size = tree.size().to_int_size();
+    let mut pixmap = Pixmap::new(size.width(), size.height()).ok_or("Image has no area")?;
+    resvg::render(&tree, Transform::default(), &mut pixmap.as_mut());
+    pixmap
+        .encode_png()
+        .map_err(|e| format!("Failed to encode PNG: {}", e))
+}
+
+/// Armory-style card: class-coloured background, class icon, name and level.
+/// `icon` is the class icon image (JPEG or PNG); without it the class initial is
+/// drawn in its place.
+pub fn character_card(info: &CardInfo, icon: Option<&[u8]>) -> Result<Vec<u8>, String> {
+    let color = class_color(info.class);
+    let icon_svg = match icon {
+        Some(bytes) => format!(
+            r#"<image x="32" y="52" width="96" height="96" clip-path="url(#icon)" href="data:image/jpeg;base64,{}"/>"#,
+            base64::engine::general_purpose::STANDARD.encode(bytes)
+        ),
+        None => format!(
+            r##"<circle cx="80" cy="100" r="48" fill="{color}"/>
+               <text x="80" y="118" font-size="52" font-weight="bold" fill="#111111" text-anchor="middle">{}</text>"##,
+            escape(&info.class.chars().next().unwrap_or('?').to_string())
+        ),
+    };
+
+    let svg = format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">
+  <defs>
+    <linearGradient id="bg" x1="0" y1="0" x2="1" y2="1">
+      <stop offset="0" stop-color="{color}"/>
+      <stop offset="0.65" stop-color="#1A1A1F"/>
+    </linearGradient>
+    <clipPath id="icon"><circle cx="80" cy="100" r="48"/></clipPath>
+  </defs>
+  <rect width="{w}" height="{h}" rx="16" fill="url(#bg)"/>
+  <circle cx="80" cy="100" r="52" fill="#111111" stroke="{color}" stroke-width="4"/>
+  {icon_svg}
+  <text x="156" y="84" font-size="40" font-weight="bold" fill="#FFFFFF">{name}</text>
+  <text x="156" y="122" font-size="22" fill="{color}">Level {level} {race} {class}</text>
+  <text x="156" y="154" font-size="16" fill="#AAAAAA">{realm} · {version}</text>
+</svg>"##,
+        w = CARD_WIDTH,
+        h = CARD_HEIGHT,
+        name = escape(info.name),
+        level = info.level,
+        race = escape(info.race),
+        class = escape(info.class),
+        realm = escape(info.realm),
+        version = escape(info.version),
+    );
+
+    svg_to_png(&svg)
+}
+
+/// One character's line on a level chart: `(unix time, level)` points, oldest first.
+pub struct LevelSeries {
+    pub name: String,
+    pub points: Vec<(i64, u32)>,
+}
+
+fn plot_err<E: std::fmt::Display>(e: E) -> String {
+    format!("Failed to draw chart: {}", e)
+}
+
+/// Level-over-time line chart with one line per series. The x axis counts days
+/// back from `now`; each line is extended flat to `now`, since snapshots are only
+/// stored when something changes.
+pub fn level_chart(title: &str, series: &[LevelSeries], now: i64) -> Result<Vec<u8>, String> {
+    let start = series
+        .iter()
+        .filter_map(|s| s.points.first())
+        .map(|(t, _)| *t)
+        .min()
+        .ok_or("No snapshots to chart")?;
+    let max_level = series
+        .iter()
+        .flat_map(|s| s.points.iter().map(|(_, level)| *level))
+        .max()
+        .unwrap_or(1);
+    let days_ago = |t: i64| (t - now) as f64 / DAY_SECS;
+    // At least a day wide so a single snapshot still gets an axis
+    let x_min = days_ago(start).min(-1.0);
+
+    let mut svg = String::new();
+    {
+        let root = SVGBackend::with_string(&mut svg, (CHART_WIDTH, CHART_HEIGHT)).into_drawing_area();
+        root.fill(&WHITE).map_err(plot_err)?;
+        let mut chart = ChartBuilder::on(&root)
+            .caption(title, ("sans-serif", 24))
+            .margin(16)
+            .x_label_area_size(40)
+            .y_label_area_size(48)
+            .build_cartesian_2d(x_min..0.0, 0u32..max_level + 2)
+            .map_err(plot_err)?;
+        chart
+            .configure_mesh()
+            .x_desc("Days ago")
+            .y_desc("Level")
+            // Adding 0.0 turns -0 into 0
+            .x_label_formatter(&|d| format!("{}", (-d * 10.0).round() / 10.0 + 0.0))
+            .draw()
+            .map_err(plot_err)?;
+
+        for (i, s) in series.iter().enumerate() {
+            let Some(&(_, last_level)) = s.points.last() else {
+                continue;
+            };
+            let color = Palette99::pick(i).to_rgba();
+            let points = s
+                .points
+                .iter()
+                .map(|&(t, level)| (days_ago(t), level))
+                .chain(std::iter::once((0.0, last_level)));
+            chart
+                .draw_series(LineSeries::new(points, color.stroke_width(2)))
+                .map_err(plot_err)?
+                .label(s.name.as_str())
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2)));
+        }
+
+        if series.len() > 1 {
+            chart
+                .configure_series_labels()
+                .position(SeriesLabelPosition::UpperLeft)
+                .border_style(BLACK)
+                .background_style(WHITE.mix(0.8))
+                .draw()
+                .map_err(plot_err)?;
+        }
+        root.present().map_err(plot_err)?;
+    }
+
+    svg_to_png(&svg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    #[test]
+    fn test_character_card_renders_png() {
+        let info = CardInfo {
+            name: "Pyuul <&>",
+            level: 42,
+            race: "Night Elf",
+            class: "Druid",
+            realm: "Nightslayer",
+            version: "Anniversary",
+        };
+        let png = character_card(&info, None).unwrap();
+        assert!(png.starts_with(PNG_SIGNATURE));
+    }
+
+    #[test]
+    fn test_level_chart_renders_png() {
+        let series = [
+            LevelSeries {
+                name: "Pyuul".to_string(),
+                points: vec![(0, 10), (86_400, 14), (3 * 86_400, 20)],
+            },
+            LevelSeries {
+                name: "Zara".to_string(),
+                points: vec![(86_400, 30)],
+            },
+        ];
+        let png = level_chart("Race to 60", &series, 4 * 86_400).unwrap();
+        assert!(png.starts_with(PNG_SIGNATURE));
+
+        assert!(level_chart("Empty", &[], 0).is_err());
+    }
+
+    #[test]
+    fn test_class_color() {
+        assert_eq!(class_color("Death Knight"), "#C41E3A");
+        assert_eq!(class_color("mage"), "#3FC7EB");
+        assert_eq!(class_color("Bard"), FALLBACK_CLASS_COLOR);
+    }
+}
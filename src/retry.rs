@@ -0,0 +1,266 @@
+use serde::{Deserialize, Serialize};
+use serenity::builder::{CreateAllowedMentions, CreateMessage};
+use serenity::http::Http;
+use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+use serenity::model::mention::Mentionable;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info};
+
+use crate::bots::Conversation;
+use crate::scheduler::unix_now;
+use crate::{db, markdown, mentions, Handler};
+
+/// How long to wait before each retry; a request still failing after the last
+/// one is given up on.
+const RETRY_DELAYS_SECS: [i64; 5] = [60, 5 * 60, 15 * 60, 60 * 60, 3 * 60 * 60];
+/// How often the queue is checked for requests that are due.
+const RETRY_POLL: Duration = Duration::from_secs(30);
+
+/// A request that failed because llama.cpp or Battle.net was down, saved to
+/// run again later.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Request {
+    Chat {
+        key: String,
+        message_id: Option<u64>,
+        speaker: Option<String>,
+        notes: Vec<String>,
+        prompt: String,
+    },
+    LevelCheck {
+        only: Option<String>,
+        insults: bool,
+    },
+}
+
+impl Request {
+    pub fn chat(conversation: &Conversation, prompt: &str) -> Request {
+        Request::Chat {
+            key: conversation.key.clone(),
+            message_id: conversation.message_id.map(|id| id.get()),
+            speaker: conversation.speaker.clone(),
+            notes: conversation.notes.clone(),
+            prompt: prompt.to_string(),
+        }
+    }
+
+    /// What to tell the requester while they wait.
+    pub fn queued_reply(&self) -> &'static str {
+        match self {
+            Request::Chat { .. } => "I can't reach my brain right now. I'll get back to you when it's back.",
+            Request::LevelCheck { .. } => "Battle.net isn't answering right now. I'll post the level check when it's back.",
+        }
+    }
+}
+
+/// When to try again after `attempts` failed tries, or `None` to give up.
+fn next_attempt(attempts: u32, now: i64) -> Option<i64> {
+    RETRY_DELAYS_SECS.get(attempts as usize).map(|delay| now + delay)
+}
+
+/// Starts the loop that retries queued requests as they come due.
+pub fn spawn(handler: Arc<Handler>, http: Arc<Http>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(RETRY_POLL);
+        loop {
+            ticker.tick().await;
+            handler.retry_due(&http).await;
+        }
+    });
+}
+
+impl Handler {
+    /// Queues `request` to be retried if `guild_id` turned on `!retry`. Only
+    /// the primary bot runs the queue, so requests to other bots just fail.
+    pub(crate) async fn defer(
+        &self,
+        guild_id: Option<GuildId>,
+        channel_id: ChannelId,
+        user_id: UserId,
+        request: &Request,
+    ) -> bool {
+        let Some(guild_id) = guild_id else {
+            return false;
+        };
+        if !self.identity.is_primary() {
+            return false;
+        }
+        let conn = self.db.lock().await;
+        if !db::is_retry_enabled(&conn, &guild_id.to_string()) {
+            return false;
+        }
+        let Ok(payload) = serde_json::to_string(request) else {
+            return false;
+        };
+        let next = next_attempt(0, unix_now()).unwrap_or_default();
+        match db::enqueue_request(
+            &conn,
+            &guild_id.to_string(),
+            &channel_id.to_string(),
+            &user_id.to_string(),
+            &payload,
+            next,
+        ) {
+            Ok(_) => {
+                info!("Queued a failed request from {} for retry", user_id);
+                true
+            }
+            Err(e) => {
+                error!("Failed to queue request: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Whether Battle.net can be reached, judged by fetching a token.
+    pub(crate) async fn battlenet_reachable(&self) -> bool {
+        match &self.blizzard {
+            Some(blizzard) => blizzard.token().await.is_ok(),
+            None => false,
+        }
+    }
+
+    async fn run_request(
+        &self,
+        guild_id: Option<GuildId>,
+        channel_id: ChannelId,
+        user_id: UserId,
+        request: &Request,
+    ) -> Result<String, String> {
+        match request {
+            Request::Chat {
+                key,
+                message_id,
+                speaker,
+                notes,
+                prompt,
+            } => {
+                let conversation = Conversation {
+                    guild_id,
+                    channel_id,
+                    key: key.clone(),
+                    message_id: message_id.map(MessageId::new),
+                    user_id: Some(user_id),
+                    speaker: speaker.clone(),
+                    notes: notes.clone(),
+                };
+                self.ask_llama(&conversation, prompt).await
+            }
+            Request::LevelCheck { only, insults } => {
+                if !self.battlenet_reachable().await {
+                    return Err("Battle.net is still unreachable".to_string());
+                }
+                Ok(self.level_check(guild_id, only.as_deref(), *insults).await)
+            }
+        }
+    }
+
+    /// Runs every queued request that's due, replying to whoever asked once it
+    /// works or runs out of retries.
+    pub(crate) async fn retry_due(&self, http: &Http) {
+        let due = {
+            let conn = self.db.lock().await;
+            match db::due_requests(&conn, unix_now()) {
+                Ok(due) => due,
+                Err(e) => {
+                    error!("Failed to load queued requests: {}", e);
+                    return;
+                }
+            }
+        };
+
+        for pending in due {
+            let (Ok(channel), Ok(user)) = (pending.channel_id.parse::<u64>(), pending.user_id.parse::<u64>()) else {
+                continue;
+            };
+            let (channel_id, user_id) = (ChannelId::new(channel), UserId::new(user));
+            let guild_id = pending.guild_id.parse::<u64>().ok().map(GuildId::new);
+            let request: Request = match serde_json::from_str(&pending.payload) {
+                Ok(request) => request,
+                Err(e) => {
+                    error!("Dropping unreadable queued request {}: {}", pending.id, e);
+                    let conn = self.db.lock().await;
+                    let _ = db::remove_request(&conn, pending.id);
+                    continue;
+                }
+            };
+
+            let reply = match self.run_request(guild_id, channel_id, user_id, &request).await {
+                Ok(reply) => reply,
+                Err(e) => {
+                    let attempts = pending.attempts + 1;
+                    let conn = self.db.lock().await;
+                    if let Some(next) = next_attempt(attempts, unix_now()) {
+                        info!("Queued request {} failed again ({}), retrying later", pending.id, e);
+                        if let Err(e) = db::reschedule_request(&conn, pending.id, next) {
+                            error!("Failed to reschedule request: {}", e);
+                        }
+                        continue;
+                    }
+                    info!("Giving up on queued request {}: {}", pending.id, e);
+                    format!("Sorry, I gave up on your earlier request: {}", e)
+                }
+            };
+
+            let allow_users = self.allows_user_mentions(guild_id).await;
+            let content = format!("{} {}", user_id.mention(), mentions::sanitize(&reply, allow_users));
+            let message = CreateMessage::new()
+                .content(markdown::truncate(&content, markdown::DISCORD_MESSAGE_MAX))
+                .allowed_mentions(CreateAllowedMentions::new().all_users(allow_users).users([user_id]));
+            if let Err(why) = channel_id.send_message(http, message).await {
+                error!("Error sending message: {:?}", why);
+            }
+            let conn = self.db.lock().await;
+            if let Err(e) = db::remove_request(&conn, pending.id) {
+                error!("Failed to remove request: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::mock;
+
+    #[test]
+    fn test_next_attempt() {
+        assert_eq!(next_attempt(0, 1000), Some(1060));
+        assert_eq!(next_attempt(4, 1000), Some(1000 + 3 * 60 * 60));
+        assert_eq!(next_attempt(5, 1000), None);
+    }
+
+    #[test]
+    fn test_request_round_trip() {
+        let request = Request::LevelCheck {
+            only: Some("Pyuul".to_string()),
+            insults: true,
+        };
+        let json = serde_json::to_string(&request).unwrap();
+        assert!(json.contains("\"kind\":\"level_check\""));
+        assert_eq!(serde_json::from_str::<Request>(&json).unwrap(), request);
+    }
+
+    #[tokio::test]
+    async fn test_defer_needs_opt_in() {
+        let handler = mock::handler(None, None);
+        let request = Request::chat(&mock::conversation("chan"), "hi");
+        let (guild, channel, user) = (Some(GuildId::new(1)), ChannelId::new(1), UserId::new(2));
+
+        assert!(!handler.defer(guild, channel, user, &request).await);
+        {
+            let conn = handler.db.lock().await;
+            db::set_retry_enabled(&conn, "1", true).unwrap();
+        }
+        assert!(handler.defer(guild, channel, user, &request).await);
+        // DMs never queue
+        assert!(!handler.defer(None, channel, user, &request).await);
+
+        let conn = handler.db.lock().await;
+        let due = db::due_requests(&conn, unix_now() + 60).unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(serde_json::from_str::<Request>(&due[0].payload).unwrap(), request);
+    }
+}
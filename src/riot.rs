@@ -0,0 +1,433 @@
+use futures::future::join_all;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tracing::error;
+
+use crate::clients::RiotRoute;
+use crate::{db, Handler};
+
+/// Matches listed by `!lol matches`.
+const RECENT_MATCHES: usize = 5;
+
+/// The ranked queue the ladder and promotion announcements follow.
+pub const SOLO_QUEUE: &str = "RANKED_SOLO_5x5";
+
+/// Tiers from lowest to highest.
+const TIERS: [&str; 10] = [
+    "IRON", "BRONZE", "SILVER", "GOLD", "PLATINUM", "EMERALD", "DIAMOND", "MASTER", "GRANDMASTER", "CHALLENGER",
+];
+
+/// A League of Legends server. Account and match data are served per
+/// continent, summoner and ranked data per server.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Platform {
+    Na,
+    Br,
+    Euw,
+    Eune,
+    Kr,
+    Jp,
+}
+
+impl Platform {
+    pub const ALL: [Platform; 6] = [Platform::Na, Platform::Br, Platform::Euw, Platform::Eune, Platform::Kr, Platform::Jp];
+
+    /// The platform routing value, as in `na1.api.riotgames.com`.
+    pub fn slug(self) -> &'static str {
+        match self {
+            Platform::Na => "na1",
+            Platform::Br => "br1",
+            Platform::Euw => "euw1",
+            Platform::Eune => "eun1",
+            Platform::Kr => "kr",
+            Platform::Jp => "jp1",
+        }
+    }
+
+    /// The regional routing value for account and match data.
+    pub fn region(self) -> &'static str {
+        match self {
+            Platform::Na | Platform::Br => "americas",
+            Platform::Euw | Platform::Eune => "europe",
+            Platform::Kr | Platform::Jp => "asia",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Platform> {
+        Platform::ALL.into_iter().find(|p| p.slug().eq_ignore_ascii_case(name))
+    }
+
+    pub fn api_base(self) -> String {
+        format!("https://{}.api.riotgames.com", self.slug())
+    }
+
+    pub fn regional_api_base(self) -> String {
+        format!("https://{}.api.riotgames.com", self.region())
+    }
+}
+
+/// Splits a Riot ID like `Faker#KR1` into game name and tag line.
+pub fn parse_riot_id(riot_id: &str) -> Option<(&str, &str)> {
+    let (name, tag) = riot_id.trim().rsplit_once('#')?;
+    (!name.is_empty() && !tag.is_empty()).then_some((name, tag))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RiotAccount {
+    pub puuid: String,
+    pub game_name: String,
+    pub tag_line: String,
+}
+
+impl RiotAccount {
+    pub fn riot_id(&self) -> String {
+        format!("{}#{}", self.game_name, self.tag_line)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeagueEntry {
+    pub queue_type: String,
+    pub tier: String,
+    /// Division, `I` to `IV`.
+    pub rank: String,
+    pub league_points: u32,
+    pub wins: u32,
+    pub losses: u32,
+}
+
+impl LeagueEntry {
+    /// Tier and division, e.g. `Gold II`; Master and up have no divisions.
+    pub fn rank_name(&self) -> String {
+        rank_name(&self.tier, &self.rank)
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "{} {} LP ({}W/{}L)",
+            self.rank_name(),
+            self.league_points,
+            self.wins,
+            self.losses
+        )
+    }
+
+    /// A number that orders entries by rank, then LP.
+    pub fn score(&self) -> u32 {
+        rank_score(&self.tier, &self.rank) + self.league_points
+    }
+}
+
+fn queue_name(queue_type: &str) -> &str {
+    match queue_type {
+        SOLO_QUEUE => "Solo/Duo",
+        "RANKED_FLEX_SR" => "Flex",
+        other => other,
+    }
+}
+
+fn rank_name(tier: &str, division: &str) -> String {
+    let mut chars = tier.chars();
+    let tier = match chars.next() {
+        Some(first) => format!("{}{}", first, chars.as_str().to_lowercase()),
+        None => String::new(),
+    };
+    match TIERS.iter().position(|t| t.eq_ignore_ascii_case(&tier)) {
+        Some(i) if i < 7 => format!("{} {}", tier, division),
+        _ => tier,
+    }
+}
+
+/// Orders tiers and divisions, 100 points per division so LP can be added.
+fn rank_score(tier: &str, division: &str) -> u32 {
+    let tier = TIERS.iter().position(|t| t.eq_ignore_ascii_case(tier)).unwrap_or(0) as u32;
+    let division = match division {
+        "IV" => 0,
+        "III" => 1,
+        "II" => 2,
+        "I" => 3,
+        _ => 0,
+    };
+    tier * 400 + division * 100
+}
+
+/// A stored rank like `GOLD II` as `(tier, division)`.
+fn split_rank(rank: &str) -> (&str, &str) {
+    rank.split_once(' ').unwrap_or((rank, ""))
+}
+
+/// The announcement for a solo queue rank change from `from` to `to`
+/// (both like `GOLD II`), or `None` if it's the same rank.
+pub fn rank_change(riot_id: &str, from: &str, to: &str) -> Option<String> {
+    let (from_tier, from_division) = split_rank(from);
+    let (to_tier, to_division) = split_rank(to);
+    let (before, after) = (rank_score(from_tier, from_division), rank_score(to_tier, to_division));
+    let to_name = rank_name(to_tier, to_division);
+    match after.cmp(&before) {
+        std::cmp::Ordering::Greater => Some(format!("📈 **{}** climbed to **{}**!", riot_id, to_name)),
+        std::cmp::Ordering::Less => Some(format!("📉 **{}** dropped to **{}**.", riot_id, to_name)),
+        std::cmp::Ordering::Equal => None,
+    }
+}
+
+/// One player's side of a finished match.
+#[derive(Debug, PartialEq)]
+pub struct MatchSummary {
+    pub champion: String,
+    pub kills: u32,
+    pub deaths: u32,
+    pub assists: u32,
+    pub win: bool,
+    pub mode: String,
+}
+
+impl MatchSummary {
+    /// `puuid`'s results from a match-v5 match, if they played in it.
+    pub fn from_match(value: &Value, puuid: &str) -> Option<MatchSummary> {
+        let info = &value["info"];
+        let player = info["participants"]
+            .as_array()?
+            .iter()
+            .find(|p| p["puuid"].as_str() == Some(puuid))?;
+        let stat = |key: &str| player[key].as_u64().unwrap_or(0) as u32;
+        let mode = match info["gameMode"].as_str().unwrap_or_default() {
+            "CLASSIC" => "Summoner's Rift".to_string(),
+            mode => mode.to_string(),
+        };
+        Some(MatchSummary {
+            champion: player["championName"].as_str()?.to_string(),
+            kills: stat("kills"),
+            deaths: stat("deaths"),
+            assists: stat("assists"),
+            win: player["win"].as_bool().unwrap_or(false),
+            mode,
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "{} {} {}/{}/{} — {}",
+            if self.win { "✅" } else { "❌" },
+            self.champion,
+            self.kills,
+            self.deaths,
+            self.assists,
+            self.mode
+        )
+    }
+}
+
+impl Handler {
+    async fn fetch_riot<T: DeserializeOwned>(&self, route: RiotRoute, path: &str, missing: &str) -> Result<T, String> {
+        let riot = self.riot.as_ref().ok_or("Riot API not configured")?;
+        let Some(value) = riot.get_json(route, path).await? else {
+            return Err(missing.to_string());
+        };
+        serde_json::from_value(value).map_err(|e| format!("Failed to parse Riot data: {}", e))
+    }
+
+    pub(crate) async fn fetch_riot_account(&self, riot_id: &str) -> Result<RiotAccount, String> {
+        let (name, tag) = parse_riot_id(riot_id).ok_or_else(|| format!("`{}` isn't a Riot ID like `Name#TAG`.", riot_id))?;
+        let path = format!("/riot/account/v1/accounts/by-riot-id/{}/{}", name.replace(' ', "%20"), tag);
+        self.fetch_riot(RiotRoute::Regional, &path, &format!("No Riot account **{}**.", riot_id))
+            .await
+    }
+
+    async fn fetch_league_entries(&self, puuid: &str) -> Result<Vec<LeagueEntry>, String> {
+        let path = format!("/lol/league/v4/entries/by-puuid/{}", puuid);
+        self.fetch_riot(RiotRoute::Platform, &path, "No ranked data.").await
+    }
+
+    async fn fetch_recent_matches(&self, puuid: &str) -> Result<Vec<MatchSummary>, String> {
+        let path = format!("/lol/match/v5/matches/by-puuid/{}/ids?count={}", puuid, RECENT_MATCHES);
+        let ids: Vec<String> = self.fetch_riot(RiotRoute::Regional, &path, "No matches.").await?;
+        let matches = join_all(ids.iter().map(|id| {
+            let path = format!("/lol/match/v5/matches/{}", id);
+            async move { self.fetch_riot::<Value>(RiotRoute::Regional, &path, "Match not found.").await }
+        }))
+        .await;
+        Ok(matches
+            .into_iter()
+            .filter_map(|m| m.map_err(|e| error!("Failed to fetch match: {}", e)).ok())
+            .filter_map(|m| MatchSummary::from_match(&m, puuid))
+            .collect())
+    }
+
+    /// The player's solo queue rank, if placed.
+    async fn solo_queue_entry(&self, puuid: &str) -> Result<Option<LeagueEntry>, String> {
+        let entries = self.fetch_league_entries(puuid).await?;
+        Ok(entries.into_iter().find(|e| e.queue_type == SOLO_QUEUE))
+    }
+
+    /// `!lol rank <Name#TAG>`: ranked standing in every queue.
+    pub(crate) async fn riot_rank_report(&self, riot_id: &str) -> String {
+        let account = match self.fetch_riot_account(riot_id).await {
+            Ok(account) => account,
+            Err(e) => return e,
+        };
+        let entries = match self.fetch_league_entries(&account.puuid).await {
+            Ok(entries) => entries,
+            Err(e) => return e,
+        };
+        let mut response = format!("**{}** — League of Legends\n", account.riot_id());
+        if entries.is_empty() {
+            response.push_str("Unranked.");
+        }
+        for entry in &entries {
+            response.push_str(&format!("  {}: {}\n", queue_name(&entry.queue_type), entry.describe()));
+        }
+        response
+    }
+
+    /// `!lol matches <Name#TAG>`: the last few games.
+    pub(crate) async fn riot_matches_report(&self, riot_id: &str) -> String {
+        let account = match self.fetch_riot_account(riot_id).await {
+            Ok(account) => account,
+            Err(e) => return e,
+        };
+        match self.fetch_recent_matches(&account.puuid).await {
+            Ok(matches) if matches.is_empty() => format!("No recent matches for **{}**.", account.riot_id()),
+            Ok(matches) => {
+                let lines: Vec<String> = matches.iter().map(|m| format!("  {}", m.describe())).collect();
+                format!("**Recent matches — {}**\n{}", account.riot_id(), lines.join("\n"))
+            }
+            Err(e) => e,
+        }
+    }
+
+    /// A `!profile` line for a linked Riot ID: solo queue rank.
+    pub(crate) async fn riot_profile_line(&self, riot_id: &str) -> String {
+        if self.riot.is_none() {
+            return format!("**{}**", riot_id);
+        }
+        let account = match self.fetch_riot_account(riot_id).await {
+            Ok(account) => account,
+            Err(e) => return format!("**{}** — {}", riot_id, e),
+        };
+        match self.solo_queue_entry(&account.puuid).await {
+            Ok(Some(entry)) => format!("**{}** — {}", account.riot_id(), entry.describe()),
+            Ok(None) => format!("**{}** — unranked", account.riot_id()),
+            Err(e) => format!("**{}** — {}", account.riot_id(), e),
+        }
+    }
+
+    /// `!lol ladder`: tracked players by solo queue rank.
+    pub(crate) async fn riot_ladder(&self) -> String {
+        let accounts = {
+            let conn = self.db.lock().await;
+            db::get_riot_accounts(&conn).unwrap_or_default()
+        };
+        if accounts.is_empty() {
+            return "No players tracked. Use `!lol add <Name#TAG>` to add one.".to_string();
+        }
+        let entries = join_all(accounts.iter().map(|a| self.solo_queue_entry(&a.puuid))).await;
+        let mut ranked: Vec<(&str, LeagueEntry)> = Vec::new();
+        let mut unranked = Vec::new();
+        for (account, entry) in accounts.iter().zip(entries) {
+            match entry {
+                Ok(Some(entry)) => ranked.push((&account.riot_id, entry)),
+                Ok(None) => unranked.push(account.riot_id.as_str()),
+                Err(e) => error!("Failed to fetch rank for {}: {}", account.riot_id, e),
+            }
+        }
+        ranked.sort_by_key(|(_, entry)| std::cmp::Reverse(entry.score()));
+
+        let mut response = String::from("**Solo/Duo ladder**\n");
+        for (i, (riot_id, entry)) in ranked.iter().enumerate() {
+            response.push_str(&format!("  {}. **{}** — {}\n", i + 1, riot_id, entry.describe()));
+        }
+        if !unranked.is_empty() {
+            response.push_str(&format!("Unranked: {}", unranked.join(", ")));
+        }
+        response
+    }
+
+    /// Checks tracked players' solo queue ranks, returning an announcement for
+    /// each that changed since the last poll. A player's first rank is just
+    /// recorded.
+    pub(crate) async fn poll_riot_ranks(&self) -> Vec<String> {
+        let accounts = {
+            let conn = self.db.lock().await;
+            db::get_riot_accounts(&conn).unwrap_or_default()
+        };
+        let mut announcements = Vec::new();
+        for account in accounts {
+            let rank = match self.solo_queue_entry(&account.puuid).await {
+                Ok(entry) => entry.map(|e| format!("{} {}", e.tier, e.rank)),
+                Err(e) => {
+                    error!("Failed to poll rank for {}: {}", account.riot_id, e);
+                    continue;
+                }
+            };
+            if rank == account.solo_rank {
+                continue;
+            }
+            if let (Some(from), Some(to)) = (&account.solo_rank, &rank) {
+                announcements.extend(rank_change(&account.riot_id, from, to));
+            }
+            let conn = self.db.lock().await;
+            if let Err(e) = db::set_riot_solo_rank(&conn, &account.puuid, rank.as_deref()) {
+                error!("Failed to save rank for {}: {}", account.riot_id, e);
+            }
+        }
+        announcements
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::mock::{self, MockRiot};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_parse_riot_id() {
+        assert_eq!(parse_riot_id("Faker#KR1"), Some(("Faker", "KR1")));
+        assert_eq!(parse_riot_id("Big Name#1234"), Some(("Big Name", "1234")));
+        assert_eq!(parse_riot_id("Faker"), None);
+        assert_eq!(parse_riot_id("#KR1"), None);
+    }
+
+    #[test]
+    fn test_rank_names() {
+        assert_eq!(rank_name("GOLD", "II"), "Gold II");
+        assert_eq!(rank_name("MASTER", "I"), "Master");
+        assert!(rank_score("PLATINUM", "IV") > rank_score("GOLD", "I"));
+        assert_eq!(rank_change("Faker#KR1", "GOLD I", "PLATINUM IV").unwrap(), "📈 **Faker#KR1** climbed to **Platinum IV**!");
+        assert_eq!(rank_change("Faker#KR1", "GOLD I", "GOLD II").unwrap(), "📉 **Faker#KR1** dropped to **Gold II**.");
+        assert_eq!(rank_change("Faker#KR1", "GOLD I", "GOLD I"), None);
+    }
+
+    #[test]
+    fn test_match_summary() {
+        let game = serde_json::json!({ "info": { "gameMode": "CLASSIC", "participants": [
+            { "puuid": "other", "championName": "Zed", "kills": 1, "deaths": 8, "assists": 0, "win": false },
+            { "puuid": "me", "championName": "Ahri", "kills": 8, "deaths": 2, "assists": 11, "win": true },
+        ]}});
+        let summary = MatchSummary::from_match(&game, "me").unwrap();
+        assert_eq!(summary.describe(), "✅ Ahri 8/2/11 — Summoner's Rift");
+        assert_eq!(MatchSummary::from_match(&game, "nobody"), None);
+    }
+
+    #[tokio::test]
+    async fn test_poll_riot_ranks() {
+        let mut handler = mock::handler(None, None);
+        let riot = Arc::new(MockRiot::default().with_rank("p1", "GOLD", "I"));
+        handler.riot = Some(riot.clone());
+        {
+            let conn = handler.db.lock().await;
+            db::add_riot_account(&conn, "p1", "Faker#KR1", "user1").unwrap();
+        }
+
+        // The first rank seen is only recorded
+        assert!(handler.poll_riot_ranks().await.is_empty());
+        assert!(handler.poll_riot_ranks().await.is_empty());
+
+        riot.set_rank("p1", "PLATINUM", "IV");
+        assert_eq!(handler.poll_riot_ranks().await, ["📈 **Faker#KR1** climbed to **Platinum IV**!"]);
+        assert!(handler.riot_ladder().await.contains("1. **Faker#KR1** — Platinum IV 50 LP"));
+    }
+}
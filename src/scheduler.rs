@@ -0,0 +1,28 @@
+use serenity::http::Http;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::Handler;
+
+pub const WEEK_SECS: i64 = 7 * 24 * 60 * 60;
+
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Starts the background loop that runs every module's scheduled work each
+/// `poll_interval`.
+pub fn spawn(handler: Arc<Handler>, http: Arc<Http>, poll_interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(poll_interval);
+        loop {
+            ticker.tick().await;
+            for module in &handler.modules {
+                module.on_tick(&handler, &http).await;
+            }
+        }
+    });
+}
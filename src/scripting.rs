@@ -0,0 +1,253 @@
+use regex::{Regex, RegexBuilder};
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Scope};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Handle;
+use tracing::info;
+
+use crate::{db, Handler};
+
+/// Rhai operations a script may run before it is stopped, which keeps an
+/// accidental infinite loop from tying up a thread.
+const MAX_OPERATIONS: u64 = 100_000;
+const MAX_STRING_SIZE: usize = 10_000;
+const MAX_COLLECTION_SIZE: usize = 1_000;
+const MAX_CALL_LEVELS: usize = 32;
+/// Replies per run, so a script can't flood a channel.
+const MAX_REPLIES: usize = 5;
+const MAX_LLM_CALLS: usize = 3;
+/// Compiled size limit for trigger regexes.
+const REGEX_SIZE_LIMIT: usize = 1 << 16;
+
+/// When a script runs.
+pub enum Trigger {
+    /// On every message matching the regex.
+    Message(Regex),
+    /// Every this many minutes, on the scheduler tick.
+    Schedule(u64),
+}
+
+impl Trigger {
+    /// Parses a trigger as stored in the database: `message` with a regex, or
+    /// `schedule` with a number of minutes.
+    pub fn parse(kind: &str, pattern: &str) -> Result<Trigger, String> {
+        match kind {
+            "message" => RegexBuilder::new(pattern)
+                .size_limit(REGEX_SIZE_LIMIT)
+                .build()
+                .map(Trigger::Message)
+                .map_err(|e| format!("Invalid regex: {}", e)),
+            "schedule" => match pattern.parse::<u64>() {
+                Ok(minutes) if minutes > 0 => Ok(Trigger::Schedule(minutes)),
+                _ => Err("Schedule must be a number of minutes".to_string()),
+            },
+            _ => Err(format!("Unknown trigger `{}`", kind)),
+        }
+    }
+}
+
+/// What a script sees of the message (or schedule tick) that ran it, as the
+/// `message`, `author`, `channel` and `captures` variables.
+#[derive(Default)]
+pub struct Invocation {
+    pub message: String,
+    pub author: String,
+    pub channel: String,
+    /// Regex capture groups, with the whole match first.
+    pub captures: Vec<String>,
+}
+
+/// Everything a script can do besides replying: its own key/value storage and
+/// one-shot LLM calls. Calls block, so scripts run on a blocking thread.
+pub trait ScriptHost: Send + Sync + 'static {
+    fn get(&self, key: &str) -> Result<Option<String>, String>;
+    fn set(&self, key: &str, value: &str) -> Result<(), String>;
+    fn llm(&self, prompt: &str) -> Result<String, String>;
+}
+
+fn sandboxed_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    engine.set_max_string_size(MAX_STRING_SIZE);
+    engine.set_max_array_size(MAX_COLLECTION_SIZE);
+    engine.set_max_map_size(MAX_COLLECTION_SIZE);
+    // No `import` from the filesystem and no `eval`
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    engine.on_print(|text| info!("Script printed: {}", text));
+    engine.on_debug(|text, _, _| info!("Script debug: {}", text));
+    engine
+}
+
+/// Checks that `source` parses, so mistakes show up when a script is added
+/// rather than when it first triggers.
+pub fn compile(source: &str) -> Result<(), String> {
+    sandboxed_engine()
+        .compile(source)
+        .map(|_| ())
+        .map_err(|e| format!("Syntax error: {}", e))
+}
+
+/// Runs `source` to completion, returning the messages it passed to `reply`.
+pub fn execute<H: ScriptHost>(source: &str, invocation: &Invocation, host: H) -> Result<Vec<String>, String> {
+    let mut engine = sandboxed_engine();
+    let host = Arc::new(host);
+    let replies = Arc::new(Mutex::new(Vec::new()));
+
+    let sink = replies.clone();
+    engine.register_fn("reply", move |text: &str| -> Result<(), Box<EvalAltResult>> {
+        let mut sink = sink.lock().unwrap();
+        if sink.len() >= MAX_REPLIES {
+            return Err(format!("A script can reply at most {} times", MAX_REPLIES).into());
+        }
+        sink.push(text.to_string());
+        Ok(())
+    });
+
+    let store = host.clone();
+    engine.register_fn("get", move |key: &str| -> Result<Dynamic, Box<EvalAltResult>> {
+        Ok(store.get(key)?.map(Dynamic::from).unwrap_or(Dynamic::UNIT))
+    });
+    let store = host.clone();
+    engine.register_fn("set", move |key: &str, value: Dynamic| -> Result<(), Box<EvalAltResult>> {
+        Ok(store.set(key, &value.to_string())?)
+    });
+
+    let llm_calls = AtomicUsize::new(0);
+    engine.register_fn("llm", move |prompt: &str| -> Result<String, Box<EvalAltResult>> {
+        if llm_calls.fetch_add(1, Ordering::Relaxed) >= MAX_LLM_CALLS {
+            return Err(format!("A script can call llm() at most {} times", MAX_LLM_CALLS).into());
+        }
+        Ok(host.llm(prompt)?)
+    });
+
+    let mut scope = Scope::new();
+    scope.push("message", invocation.message.clone());
+    scope.push("author", invocation.author.clone());
+    scope.push("channel", invocation.channel.clone());
+    let captures: Array = invocation.captures.iter().cloned().map(Dynamic::from).collect();
+    scope.push("captures", captures);
+
+    engine
+        .run_with_scope(&mut scope, source)
+        .map_err(|e| e.to_string())?;
+    let replies = replies.lock().unwrap().clone();
+    Ok(replies)
+}
+
+/// Host backed by the bot's database and LLM, for one script.
+struct HandlerHost {
+    handler: Arc<Handler>,
+    runtime: Handle,
+    guild_id: String,
+    script: String,
+}
+
+impl ScriptHost for HandlerHost {
+    fn get(&self, key: &str) -> Result<Option<String>, String> {
+        let conn = self.runtime.block_on(self.handler.db.lock());
+        db::get_script_data(&conn, &self.guild_id, &self.script, key).map_err(|e| format!("DB error: {}", e))
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), String> {
+        let conn = self.runtime.block_on(self.handler.db.lock());
+        db::set_script_data(&conn, &self.guild_id, &self.script, key, value).map_err(|e| format!("DB error: {}", e))
+    }
+
+    fn llm(&self, prompt: &str) -> Result<String, String> {
+        let system_prompt = {
+            let conn = self.runtime.block_on(self.handler.db.lock());
+            self.handler.system_prompt(&conn).map_err(|e| format!("DB error: {}", e))?
+        };
+        self.runtime
+            .block_on(self.handler.query_llm_oneshot(system_prompt, prompt.to_string()))
+    }
+}
+
+impl Handler {
+    /// Runs a stored script on a blocking thread, returning its replies.
+    pub(crate) async fn run_script(&self, script: &db::Script, invocation: Invocation) -> Result<Vec<String>, String> {
+        let host = HandlerHost {
+            handler: Arc::new(self.for_identity(self.identity.clone())),
+            runtime: Handle::current(),
+            guild_id: script.guild_id.clone(),
+            script: script.name.clone(),
+        };
+        let source = script.source.clone();
+        tokio::task::spawn_blocking(move || execute(&source, &invocation, host))
+            .await
+            .map_err(|e| format!("Script crashed: {}", e))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct FakeHost {
+        data: Mutex<HashMap<String, String>>,
+    }
+
+    impl ScriptHost for FakeHost {
+        fn get(&self, key: &str) -> Result<Option<String>, String> {
+            Ok(self.data.lock().unwrap().get(key).cloned())
+        }
+
+        fn set(&self, key: &str, value: &str) -> Result<(), String> {
+            self.data.lock().unwrap().insert(key.to_string(), value.to_string());
+            Ok(())
+        }
+
+        fn llm(&self, prompt: &str) -> Result<String, String> {
+            Ok(prompt.to_uppercase())
+        }
+    }
+
+    fn invocation(message: &str, captures: &[&str]) -> Invocation {
+        Invocation {
+            message: message.to_string(),
+            author: "pyuul".to_string(),
+            channel: "1".to_string(),
+            captures: captures.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_execute_replies() {
+        let source = r#"
+            let count = parse_int(get("count") ?? "0") + 1;
+            set("count", count);
+            reply(`${author} rolled ${captures[1]} (roll #${count})`);
+            reply(llm("hi"));
+        "#;
+        let host = FakeHost::default();
+        host.set("count", "4").unwrap();
+        let replies = execute(source, &invocation("!roll d20", &["!roll d20", "d20"]), host).unwrap();
+        assert_eq!(replies, ["pyuul rolled d20 (roll #5)", "HI"]);
+    }
+
+    #[test]
+    fn test_execute_is_sandboxed() {
+        let run = |source: &str| execute(source, &Invocation::default(), FakeHost::default());
+        assert!(run("loop { }").is_err());
+        assert!(run(r#"import "secrets" as s;"#).is_err());
+        assert!(run(r#"eval("reply(1)")"#).is_err());
+        assert!(run(r#"for i in 0..10 { reply("spam") }"#).is_err());
+        assert!(run(r#"for i in 0..10 { llm("hi") }"#).is_err());
+    }
+
+    #[test]
+    fn test_trigger_parse() {
+        assert!(matches!(Trigger::parse("message", "^!roll (d\\d+)$"), Ok(Trigger::Message(_))));
+        assert!(matches!(Trigger::parse("schedule", "60"), Ok(Trigger::Schedule(60))));
+        assert!(Trigger::parse("message", "(unclosed").is_err());
+        assert!(Trigger::parse("schedule", "0").is_err());
+        assert!(Trigger::parse("reaction", "x").is_err());
+        assert!(compile("reply(").is_err());
+        assert!(compile(r#"reply("ok")"#).is_ok());
+    }
+}
@@ -0,0 +1,265 @@
+use chrono::{DateTime, Datelike, Days, Weekday};
+use serenity::model::user::User;
+use std::fmt;
+use tracing::{error, info};
+
+use crate::clients::SteamPlayer;
+use crate::{db, Handler};
+
+/// The `game` of Steam IDs in `linked_accounts`.
+pub const GAME: &str = "steam";
+
+/// How long after raid night starts people still in a Steam game get nagged.
+pub const NAG_WINDOW_SECS: i64 = 2 * 60 * 60;
+
+/// What someone typed to identify their Steam profile.
+#[derive(Debug, PartialEq)]
+pub enum ProfileRef {
+    /// A 64-bit Steam ID, e.g. from `steamcommunity.com/profiles/<id>`.
+    Id(String),
+    /// A custom URL name, e.g. from `steamcommunity.com/id/<name>`.
+    Vanity(String),
+}
+
+pub fn parse_profile(input: &str) -> ProfileRef {
+    let input = input.trim().trim_end_matches('/');
+    if let Some((_, id)) = input.split_once("/profiles/") {
+        return ProfileRef::Id(id.to_string());
+    }
+    if let Some((_, name)) = input.split_once("/id/") {
+        return ProfileRef::Vanity(name.to_string());
+    }
+    // Individual account IDs all start 7656119 and are 17 digits
+    if input.len() == 17 && input.starts_with("7656") && input.chars().all(|c| c.is_ascii_digit()) {
+        return ProfileRef::Id(input.to_string());
+    }
+    ProfileRef::Vanity(input.to_string())
+}
+
+/// A weekly raid start time, in UTC.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RaidNight {
+    pub weekday: Weekday,
+    /// Minutes after midnight.
+    pub minute: u32,
+}
+
+impl RaidNight {
+    /// Parses a day (`wed`, `Wednesday`) and a `HH:MM` time.
+    pub fn parse(day: &str, time: &str) -> Option<RaidNight> {
+        let weekday = day.parse::<Weekday>().ok()?;
+        let (hours, minutes) = time.split_once(':')?;
+        let (hours, minutes) = (hours.parse::<u32>().ok()?, minutes.parse::<u32>().ok()?);
+        (hours < 24 && minutes < 60).then_some(RaidNight {
+            weekday,
+            minute: hours * 60 + minutes,
+        })
+    }
+
+    /// The `raid_night` config value, e.g. `Wed 19:30`.
+    pub fn to_config(self) -> String {
+        format!("{} {:02}:{:02}", self.weekday, self.minute / 60, self.minute % 60)
+    }
+
+    pub fn from_config(value: &str) -> Option<RaidNight> {
+        let (day, time) = value.split_once(' ')?;
+        RaidNight::parse(day, time)
+    }
+
+    /// When the raid night under way at `now` started, if it started less
+    /// than [`NAG_WINDOW_SECS`] ago.
+    pub fn current_start(self, now: i64) -> Option<i64> {
+        let today = DateTime::from_timestamp(now, 0)?.date_naive();
+        // A late raid can run past midnight, so yesterday's may still be on
+        [Some(today), today.checked_sub_days(Days::new(1))]
+            .into_iter()
+            .flatten()
+            .filter(|day| day.weekday() == self.weekday)
+            .filter_map(|day| day.and_hms_opt(self.minute / 60, self.minute % 60, 0))
+            .map(|start| start.and_utc().timestamp())
+            .find(|&start| start <= now && now < start + NAG_WINDOW_SECS)
+    }
+}
+
+impl fmt::Display for RaidNight {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let day = match self.weekday {
+            Weekday::Mon => "Monday",
+            Weekday::Tue => "Tuesday",
+            Weekday::Wed => "Wednesday",
+            Weekday::Thu => "Thursday",
+            Weekday::Fri => "Friday",
+            Weekday::Sat => "Saturday",
+            Weekday::Sun => "Sunday",
+        };
+        write!(f, "{}s at {:02}:{:02} UTC", day, self.minute / 60, self.minute % 60)
+    }
+}
+
+/// The nag for someone still in a game when raid night starts.
+pub fn nag(user_id: &str, game: &str) -> String {
+    format!("<@{}> stop playing **{}** and log in, raid night has started!", user_id, game)
+}
+
+/// `!playing` for one player.
+pub fn describe_playing(player: &SteamPlayer) -> String {
+    match &player.gameextrainfo {
+        Some(game) => format!("**{}** is playing **{}**.", player.personaname, game),
+        None => format!("**{}** isn't playing anything on Steam (or their profile is private).", player.personaname),
+    }
+}
+
+impl Handler {
+    /// The Steam ID `user_id` linked, if any.
+    pub(crate) async fn linked_steam_id(&self, user_id: &str) -> Option<String> {
+        let conn = self.db.lock().await;
+        db::get_linked_accounts(&conn, user_id)
+            .ok()?
+            .into_iter()
+            .find(|a| a.game == GAME)
+            .map(|a| a.account)
+    }
+
+    /// `!steam link <profile>`: replaces `user_id`'s Steam ID with the one
+    /// `input` names, checking the profile exists.
+    pub(crate) async fn link_steam(&self, user_id: &str, input: &str) -> String {
+        let Some(steam) = &self.steam else {
+            return "Steam Web API not configured.".to_string();
+        };
+        let steam_id = match parse_profile(input) {
+            ProfileRef::Id(id) => id,
+            ProfileRef::Vanity(name) => match steam.resolve_vanity_url(&name).await {
+                Ok(Some(id)) => id,
+                Ok(None) => return format!("No Steam profile called `{}`.", name),
+                Err(e) => return e,
+            },
+        };
+        let player = match steam.player_summaries(std::slice::from_ref(&steam_id)).await {
+            Ok(players) => players.into_iter().next(),
+            Err(e) => return e,
+        };
+        let Some(player) = player else {
+            return format!("No Steam profile with ID `{}`.", steam_id);
+        };
+
+        let conn = self.db.lock().await;
+        let result = db::unlink_game(&conn, user_id, GAME)
+            .and_then(|_| db::link_account(&conn, user_id, GAME, &player.steamid));
+        match result {
+            Ok(_) => {
+                info!("User {} linked Steam ID {}", user_id, player.steamid);
+                format!("Linked Steam profile **{}** to your profile.", player.personaname)
+            }
+            Err(e) => {
+                error!("Failed to link Steam ID: {}", e);
+                "Failed to update your profile.".to_string()
+            }
+        }
+    }
+
+    /// `!playing @user`: what `user` is playing on Steam right now.
+    pub(crate) async fn playing_report(&self, user: &User) -> String {
+        let Some(steam) = &self.steam else {
+            return "Steam Web API not configured.".to_string();
+        };
+        let Some(steam_id) = self.linked_steam_id(&user.id.to_string()).await else {
+            return format!("{} hasn't linked Steam. Use `!steam link <profile>`.", user.name);
+        };
+        match steam.player_summaries(&[steam_id]).await {
+            Ok(players) => match players.first() {
+                Some(player) => describe_playing(player),
+                None => format!("{}'s Steam profile has gone missing.", user.name),
+            },
+            Err(e) => e,
+        }
+    }
+
+    /// `(user ID, game)` for everyone with a linked Steam ID who is in a game.
+    pub(crate) async fn steam_players_in_game(&self) -> Result<Vec<(String, String)>, String> {
+        let Some(steam) = &self.steam else {
+            return Ok(Vec::new());
+        };
+        let linked = {
+            let conn = self.db.lock().await;
+            db::get_linked_accounts_for_game(&conn, GAME).map_err(|e| e.to_string())?
+        };
+        let mut playing = Vec::new();
+        // The API takes at most 100 IDs at a time
+        for chunk in linked.chunks(100) {
+            let ids: Vec<String> = chunk.iter().map(|(_, id)| id.clone()).collect();
+            for player in steam.player_summaries(&ids).await? {
+                let Some(game) = player.gameextrainfo else { continue };
+                for (user_id, _) in chunk.iter().filter(|(_, id)| *id == player.steamid) {
+                    playing.push((user_id.clone(), game.clone()));
+                }
+            }
+        }
+        Ok(playing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::mock::{self, MockSteam};
+    use serenity::model::id::UserId;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_parse_profile() {
+        let id = "76561197960287930";
+        assert_eq!(parse_profile(id), ProfileRef::Id(id.to_string()));
+        assert_eq!(
+            parse_profile("https://steamcommunity.com/profiles/76561197960287930/"),
+            ProfileRef::Id(id.to_string())
+        );
+        assert_eq!(parse_profile("https://steamcommunity.com/id/gabelogannewell"), ProfileRef::Vanity("gabelogannewell".to_string()));
+        assert_eq!(parse_profile("gabelogannewell"), ProfileRef::Vanity("gabelogannewell".to_string()));
+    }
+
+    #[test]
+    fn test_raid_night() {
+        let night = RaidNight::parse("wednesday", "23:30").unwrap();
+        assert_eq!(night.to_config(), "Wed 23:30");
+        assert_eq!(RaidNight::from_config("Wed 23:30"), Some(night));
+        assert_eq!(night.to_string(), "Wednesdays at 23:30 UTC");
+        assert_eq!(RaidNight::parse("wed", "24:00"), None);
+        assert_eq!(RaidNight::parse("someday", "19:00"), None);
+
+        // Wednesday 2024-01-03 23:30 UTC
+        let start = 1_704_324_600;
+        assert_eq!(night.current_start(start - 60), None);
+        assert_eq!(night.current_start(start), Some(start));
+        // Past midnight, into Thursday
+        assert_eq!(night.current_start(start + 90 * 60), Some(start));
+        assert_eq!(night.current_start(start + NAG_WINDOW_SECS), None);
+        assert_eq!(night.current_start(start + 7 * 24 * 60 * 60 + 60), Some(start + 7 * 24 * 60 * 60));
+    }
+
+    #[tokio::test]
+    async fn test_link_and_playing() {
+        let mut handler = mock::handler(None, None);
+        handler.steam = Some(Arc::new(
+            MockSteam::default()
+                .with_player("76561197960287930", "Gaben", Some("Dota 2"))
+                .with_player("76561197960287931", "Idle", None),
+        ));
+
+        assert_eq!(handler.link_steam("1", "nobody").await, "No Steam profile called `nobody`.");
+        assert_eq!(handler.link_steam("1", "gaben").await, "Linked Steam profile **Gaben** to your profile.");
+        assert!(handler.link_steam("2", "76561197960287931").await.contains("**Idle**"));
+        assert_eq!(handler.linked_steam_id("1").await.as_deref(), Some("76561197960287930"));
+
+        let mut user = User::default();
+        user.id = UserId::new(1);
+        assert_eq!(handler.playing_report(&user).await, "**Gaben** is playing **Dota 2**.");
+        assert_eq!(
+            handler.steam_players_in_game().await.unwrap(),
+            [("1".to_string(), "Dota 2".to_string())]
+        );
+
+        // Linking again replaces the old ID
+        handler.link_steam("1", "idle").await;
+        assert!(handler.steam_players_in_game().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,102 @@
+//! systemd service integration: readiness and watchdog notifications, and exit
+//! codes that tell a supervisor whether restarting could help.
+
+use std::env;
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Exit codes from sysexits.h, so `RestartPreventExitStatus=78` can stop systemd
+/// from restart-looping on a bad config while transient failures still restart.
+pub mod exit {
+    /// A setting is missing or invalid, or Discord rejected the token or intents.
+    pub const CONFIG: u8 = 78;
+    /// The database couldn't be opened or migrated.
+    pub const IO: u8 = 74;
+    /// Lost the gateway connection for a reason that may go away on its own.
+    pub const UNAVAILABLE: u8 = 69;
+    /// The Discord client couldn't be built.
+    pub const SOFTWARE: u8 = 70;
+}
+
+/// Sends a state string (`READY=1`, `WATCHDOG=1`, ...) to the service manager.
+/// Does nothing when not started by systemd with `Type=notify`.
+pub fn notify(state: &str) {
+    let Ok(path) = env::var("NOTIFY_SOCKET") else {
+        return;
+    };
+    if let Err(e) = send(&path, state) {
+        warn!("sd_notify to {} failed: {}", path, e);
+    }
+}
+
+fn send(path: &str, state: &str) -> std::io::Result<()> {
+    let socket = UnixDatagram::unbound()?;
+    // A leading '@' names a socket in Linux's abstract namespace
+    if let Some(name) = path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+            return Ok(());
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = name;
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "abstract sockets are Linux-only",
+            ));
+        }
+    }
+    socket.send_to(state.as_bytes(), path)?;
+    Ok(())
+}
+
+/// How often to ping the watchdog: half the configured timeout, or `None` if the
+/// unit has no `WatchdogSec=` (or the setting is meant for another process).
+fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+    let usec = env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
+
+/// Pings the watchdog from the async runtime, so a wedged runtime stops the pings
+/// and systemd restarts the bot.
+pub fn spawn_watchdog() {
+    let Some(interval) = watchdog_interval() else {
+        return;
+    };
+    debug!("Pinging systemd watchdog every {:?}", interval);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            notify("WATCHDOG=1");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notify_sends_to_socket() {
+        let dir = std::env::temp_dir().join(format!("sd-notify-test-{}", std::process::id()));
+        let _ = std::fs::remove_file(&dir);
+        let listener = UnixDatagram::bind(&dir).unwrap();
+
+        send(dir.to_str().unwrap(), "READY=1").unwrap();
+        let mut buf = [0u8; 64];
+        let n = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1");
+
+        std::fs::remove_file(&dir).unwrap();
+    }
+}
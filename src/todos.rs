@@ -0,0 +1,71 @@
+use chrono::{NaiveDate, NaiveDateTime};
+
+use crate::db::Todo;
+
+/// Parses a `--due=` value into Unix seconds: a delay from `now` (`30m`,
+/// `6h`, `2d`, `1w`), a date (`2024-06-01`, at midnight UTC) or a date and
+/// time (`2024-06-01T19:30`, UTC).
+pub fn parse_due(input: &str, now: i64) -> Option<i64> {
+    if let Some(unit) = input.chars().last().filter(|c| c.is_ascii_alphabetic()) {
+        let amount = input[..input.len() - 1].parse::<i64>().ok().filter(|&n| n > 0)?;
+        let secs = match unit.to_ascii_lowercase() {
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return None,
+        };
+        return now.checked_add(amount.checked_mul(secs)?);
+    }
+    if let Ok(at) = NaiveDateTime::parse_from_str(input, "%Y-%m-%dT%H:%M") {
+        return Some(at.and_utc().timestamp());
+    }
+    let date = NaiveDate::parse_from_str(input, "%Y-%m-%d").ok()?;
+    Some(date.and_hms_opt(0, 0, 0)?.and_utc().timestamp())
+}
+
+/// One `!todo list` line; `show_owner` is for lists with everyone's todos.
+pub fn todo_line(todo: &Todo, show_owner: bool) -> String {
+    let owner = if show_owner { format!(" — <@{}>", todo.user_id) } else { String::new() };
+    let due = todo.due_at.map(|at| format!(" (due <t:{}:R>)", at)).unwrap_or_default();
+    format!("  `#{}` {}{}{}", todo.id, todo.text, due, owner)
+}
+
+/// The reminder posted when a todo falls due.
+pub fn reminder(todo: &Todo) -> String {
+    format!(
+        "⏰ <@{}> your todo `#{}` is due: **{}** (`!todo done {}` when it's done)",
+        todo.user_id, todo.id, todo.text, todo.id
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_due() {
+        assert_eq!(parse_due("30m", 1000), Some(1000 + 30 * 60));
+        assert_eq!(parse_due("2D", 1000), Some(1000 + 2 * 24 * 60 * 60));
+        assert_eq!(parse_due("1w", 0), Some(7 * 24 * 60 * 60));
+        assert_eq!(parse_due("2024-01-03", 0), Some(1_704_240_000));
+        assert_eq!(parse_due("2024-01-03T23:30", 0), Some(1_704_324_600));
+        assert_eq!(parse_due("0d", 0), None);
+        assert_eq!(parse_due("3y", 0), None);
+        assert_eq!(parse_due("tomorrow", 0), None);
+        assert_eq!(parse_due("2024-13-01", 0), None);
+    }
+
+    #[test]
+    fn test_todo_line() {
+        let todo = Todo {
+            id: 3,
+            channel_id: "c1".to_string(),
+            user_id: "42".to_string(),
+            text: "Farm pots".to_string(),
+            due_at: Some(1_704_240_000),
+        };
+        assert_eq!(todo_line(&todo, false), "  `#3` Farm pots (due <t:1704240000:R>)");
+        assert_eq!(todo_line(&todo, true), "  `#3` Farm pots (due <t:1704240000:R>) — <@42>");
+    }
+}
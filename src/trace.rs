@@ -0,0 +1,74 @@
+use reqwest::RequestBuilder;
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::Instrument;
+
+use crate::error_tracking;
+
+/// Header carrying the correlation ID on requests to the LLM and Battle.net.
+pub const HEADER: &str = "X-Correlation-ID";
+
+tokio::task_local! {
+    static CORRELATION_ID: String;
+}
+
+/// A short random ID, 8 hex digits, to quote in a bug report.
+pub fn new_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let hash = RandomState::new().hash_one(COUNTER.fetch_add(1, Ordering::Relaxed));
+    format!("{:08x}", hash as u32)
+}
+
+/// Runs the handling of one incoming `event` under a fresh correlation ID,
+/// which every log line inside carries (as the span's `cid`) and [`current`]
+/// returns.
+pub async fn traced<F: Future>(event: &'static str, f: F) -> F::Output {
+    let id = new_id();
+    let span = tracing::info_span!("event", kind = event, cid = %id);
+    let f = error_tracking::scoped(&id, f.instrument(span));
+    CORRELATION_ID.scope(id, f).await
+}
+
+/// The correlation ID of the event being handled, if any.
+pub fn current() -> Option<String> {
+    CORRELATION_ID.try_with(String::clone).ok()
+}
+
+/// `" (error ref: …)"` to append to error replies, so users can quote it to an
+/// admin who then finds the logs; empty outside an event.
+pub fn error_ref() -> String {
+    current().map(|id| format!(" (error ref: {})", id)).unwrap_or_default()
+}
+
+/// Tags an outbound request with the current correlation ID.
+pub fn tag(request: RequestBuilder) -> RequestBuilder {
+    match current() {
+        Some(id) => request.header(HEADER, id),
+        None => request,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_id() {
+        let (a, b) = (new_id(), new_id());
+        assert_eq!(a.len(), 8);
+        assert!(a.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(a, b);
+    }
+
+    #[tokio::test]
+    async fn test_traced() {
+        assert_eq!(current(), None);
+        assert_eq!(error_ref(), "");
+        let (id, reference) = traced("test", async { (current(), error_ref()) }).await;
+        let id = id.unwrap();
+        assert_eq!(reference, format!(" (error ref: {})", id));
+        assert_eq!(current(), None);
+    }
+}
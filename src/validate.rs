@@ -0,0 +1,145 @@
+use serenity::http::Http;
+use std::time::Duration;
+
+use crate::Handler;
+
+/// How long each integration gets to answer its startup probe.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, PartialEq)]
+pub enum Status {
+    Enabled(String),
+    Disabled(String),
+    /// Configured but not working; `--strict` refuses to start on these.
+    Broken(String),
+}
+
+/// The startup probe result for one integration.
+#[derive(Debug)]
+pub struct Check {
+    pub integration: &'static str,
+    /// Bot features that need this integration.
+    pub features: &'static str,
+    pub status: Status,
+}
+
+pub fn has_failures(checks: &[Check]) -> bool {
+    checks.iter().any(|c| matches!(c.status, Status::Broken(_)))
+}
+
+/// Fixed-width table of the checks, one row per integration.
+pub fn summary_table(checks: &[Check]) -> Vec<String> {
+    let rows: Vec<[&str; 4]> = checks
+        .iter()
+        .map(|c| {
+            let (status, detail) = match &c.status {
+                Status::Enabled(d) => ("enabled", d.as_str()),
+                Status::Disabled(d) => ("disabled", d.as_str()),
+                Status::Broken(d) => ("BROKEN", d.as_str()),
+            };
+            [c.integration, status, c.features, detail]
+        })
+        .collect();
+
+    let header = ["Integration", "Status", "Features", "Detail"];
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_row = |cells: [&str; 4]| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        padded.join("  ").trim_end().to_string()
+    };
+    std::iter::once(format_row(header))
+        .chain(rows.into_iter().map(format_row))
+        .collect()
+}
+
+impl Handler {
+    /// Probes each configured integration so a bad URL or secret shows up at
+    /// startup instead of the first time someone runs a command.
+    pub(crate) async fn validate_integrations(&self, http: &Http) -> Vec<Check> {
+        let discord = match tokio::time::timeout(PROBE_TIMEOUT, http.get_current_user()).await {
+            Ok(Ok(user)) => Status::Enabled(format!("logged in as {}", user.name)),
+            Ok(Err(e)) => Status::Broken(format!("token rejected: {}", e)),
+            Err(_) => Status::Broken("Discord API timed out".to_string()),
+        };
+
+        let llm = match &self.llm {
+            None => Status::Disabled("LLAMA_API_URL not set".to_string()),
+            Some(llm) => match llm.probe(PROBE_TIMEOUT).await {
+                Ok(description) => Status::Enabled(description),
+                Err(e) => Status::Broken(e),
+            },
+        };
+
+        let battlenet = match &self.blizzard {
+            None => Status::Disabled("BATTLENET_CLIENT_ID/SECRET not set".to_string()),
+            Some(blizzard) => match tokio::time::timeout(PROBE_TIMEOUT, blizzard.token()).await {
+                Ok(Ok(_)) => Status::Enabled(format!(
+                    "{} region, {}",
+                    self.wow_region.slug(),
+                    self.wow_version.label()
+                )),
+                Ok(Err(e)) => Status::Broken(e),
+                Err(_) => Status::Broken("Battle.net OAuth timed out".to_string()),
+            },
+        };
+
+        vec![
+            Check {
+                integration: "Discord",
+                features: "all",
+                status: discord,
+            },
+            Check {
+                integration: "llama.cpp",
+                features: "chat, insults",
+                status: llm,
+            },
+            Check {
+                integration: "Battle.net",
+                features: "wow, levelcheck",
+                status: battlenet,
+            },
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_table() {
+        let checks = vec![
+            Check {
+                integration: "Discord",
+                features: "all",
+                status: Status::Enabled("logged in as Bot".to_string()),
+            },
+            Check {
+                integration: "llama.cpp",
+                features: "chat",
+                status: Status::Broken("connection refused".to_string()),
+            },
+        ];
+        assert_eq!(
+            summary_table(&checks),
+            [
+                "Integration  Status   Features  Detail",
+                "Discord      enabled  all       logged in as Bot",
+                "llama.cpp    BROKEN   chat      connection refused",
+            ]
+        );
+        assert!(has_failures(&checks));
+        assert!(!has_failures(&checks[..1]));
+    }
+}
@@ -0,0 +1,476 @@
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, Request, State};
+use axum::http::{header, HeaderMap, Method, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Form, Router};
+use base64::Engine;
+use serde::Deserialize;
+use serenity::http::Http;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{error, info, warn};
+
+use crate::events::BotEvent;
+use crate::{db, scheduler, wow, Handler};
+
+mod api;
+
+/// Shared state for every web request.
+struct WebState {
+    handler: Arc<Handler>,
+    http: Arc<Http>,
+    token: String,
+}
+
+/// Serves the admin dashboard, the `/api` JSON API and the `/ws/events` stream on
+/// `addr` in the background. Every request must carry `token`, either as a bearer
+/// token or as the password of HTTP basic auth (which browsers prompt for).
+/// WebSocket clients that can't set headers may pass `?token=` instead.
+pub fn spawn(handler: Arc<Handler>, http: Arc<Http>, addr: SocketAddr, token: String) {
+    let state = Arc::new(WebState { handler, http, token });
+    tokio::spawn(async move {
+        let listener = match tokio::net::TcpListener::bind(addr).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Failed to bind web dashboard to {}: {}", addr, e);
+                return;
+            }
+        };
+        info!("Web dashboard listening on http://{}", addr);
+        if let Err(e) = axum::serve(listener, router(state)).await {
+            error!("Web dashboard stopped: {}", e);
+        }
+    });
+}
+
+fn router(state: Arc<WebState>) -> Router {
+    Router::new()
+        .route("/", get(dashboard))
+        .route("/system-prompt", post(set_system_prompt))
+        .route("/cap", post(set_cap))
+        .route("/characters/add", post(add_character))
+        .route("/characters/remove", post(remove_character))
+        .route("/schedules", post(set_schedules))
+        .nest("/api", api::router())
+        .route("/ws/events", get(events_socket))
+        .layer(middleware::from_fn(reject_cross_site))
+        .layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state)
+}
+
+/// Compares without bailing at the first differing byte, so response timing
+/// doesn't leak how much of a guess was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// The secret presented in an `Authorization` header: a bearer token or the
+/// password half of basic auth.
+fn presented_token(authorization: &str) -> Option<String> {
+    if let Some(token) = authorization.strip_prefix("Bearer ") {
+        return Some(token.trim().to_string());
+    }
+    let encoded = authorization.strip_prefix("Basic ")?;
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .ok()?;
+    let credentials = String::from_utf8(decoded).ok()?;
+    let (_, password) = credentials.split_once(':')?;
+    Some(password.to_string())
+}
+
+async fn require_token(State(state): State<Arc<WebState>>, request: Request, next: Next) -> Response {
+    let header_token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(presented_token);
+    // Browsers can't put headers on a WebSocket handshake
+    let query_token = || {
+        if !request.uri().path().starts_with("/ws/") {
+            return None;
+        }
+        let Query(mut query) = Query::<HashMap<String, String>>::try_from_uri(request.uri()).ok()?;
+        query.remove("token")
+    };
+    let authorized = header_token
+        .or_else(query_token)
+        .is_some_and(|t| constant_time_eq(t.as_bytes(), state.token.as_bytes()));
+    if authorized {
+        return next.run(request).await;
+    }
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Basic realm=\"discord-bot\"")],
+        "Unauthorized",
+    )
+        .into_response()
+}
+
+/// Whether a request comes from the dashboard's own pages. Browsers attach
+/// basic auth to form posts from any site, so a request whose `Origin` (or,
+/// without one, `Referer`) names another site is forged. Clients that send
+/// neither, like curl with a bearer token, aren't browsers and pass.
+fn same_origin(headers: &HeaderMap) -> bool {
+    let Some(source) = headers.get(header::ORIGIN).or_else(|| headers.get(header::REFERER)) else {
+        return true;
+    };
+    let host = headers.get(header::HOST).and_then(|v| v.to_str().ok());
+    let authority = source
+        .to_str()
+        .ok()
+        .and_then(|source| source.split_once("://"))
+        .map(|(_, rest)| rest.split(['/', '?', '#']).next().unwrap_or_default());
+    host.is_some() && authority == host
+}
+
+/// Refuses cross-site requests that change something; see [`same_origin`].
+async fn reject_cross_site(request: Request, next: Next) -> Response {
+    let safe = matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    if safe || same_origin(request.headers()) {
+        return next.run(request).await;
+    }
+    warn!("Refused a cross-site {} to {}", request.method(), request.uri().path());
+    (StatusCode::FORBIDDEN, "Cross-site request refused").into_response()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn config(conn: &rusqlite::Connection, key: &str) -> String {
+    db::get_config(conn, None, key).ok().flatten().unwrap_or_default()
+}
+
+async fn render(state: &WebState, notice: Option<String>) -> Html<String> {
+    let conn = state.handler.db.lock().await;
+    let system_prompt = config(&conn, "system_prompt");
+    let cap = db::get_response_cap(&conn, None);
+    let characters: Vec<_> = db::get_tracked_character_details(&conn)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|c| {
+            let level = db::latest_snapshot(&conn, &c.name).ok().flatten().map(|s| s.level);
+            (c, level)
+        })
+        .collect();
+    let stats = db::usage_stats(&conn).unwrap_or_default();
+    let pvp_channel = config(&conn, "pvp_report_channel");
+    let milestone_channel = config(&conn, "milestone_channel");
+    let milestone_levels = db::get_config(&conn, None, "milestone_levels")
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| wow::DEFAULT_MILESTONES.to_string());
+    let race_message = config(&conn, "race_message");
+    drop(conn);
+
+    let notice = notice
+        .map(|n| format!("<p class=\"notice\">{}</p>", escape(&n.replace("**", ""))))
+        .unwrap_or_default();
+
+    let character_rows: String = characters
+        .iter()
+        .map(|(c, level)| {
+            format!(
+                "<tr><td>{name}</td><td>{level}</td><td>{version}</td><td>{added_by}</td>\
+                 <td><form method=\"post\" action=\"/characters/remove\">\
+                 <input type=\"hidden\" name=\"name\" value=\"{name}\">\
+                 <button>Remove</button></form></td></tr>",
+                name = escape(&c.name),
+                level = level.map(|l| l.to_string()).unwrap_or_else(|| "?".to_string()),
+                version = escape(c.game_version.as_deref().unwrap_or("default")),
+                added_by = escape(&c.added_by),
+            )
+        })
+        .collect();
+
+    Html(format!(
+        r#"<!doctype html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Bot dashboard</title>
+<style>
+  body {{ font-family: sans-serif; max-width: 60rem; margin: 2rem auto; padding: 0 1rem; }}
+  textarea {{ width: 100%; height: 8rem; }}
+  table {{ border-collapse: collapse; }}
+  td, th {{ padding: 0.25rem 0.75rem; border-bottom: 1px solid #ddd; text-align: left; }}
+  .notice {{ background: #eef; padding: 0.5rem; }}
+  section {{ margin-bottom: 2rem; }}
+</style>
+</head>
+<body>
+<h1>Bot dashboard</h1>
+{notice}
+<section>
+<h2>Usage</h2>
+<p>{messages} stored messages across {contexts} conversations ·
+{tracked} tracked characters · {snapshots} snapshots</p>
+</section>
+<section>
+<h2>System prompt</h2>
+<form method="post" action="/system-prompt">
+<textarea name="prompt">{system_prompt}</textarea>
+<button>Save</button>
+</form>
+<form method="post" action="/cap">
+<label>Response word cap (1-{max_cap}) <input name="cap" type="number" min="1" max="{max_cap}" value="{cap}"></label>
+<button>Save</button>
+</form>
+</section>
+<section>
+<h2>Tracked characters</h2>
+<table>
+<tr><th>Name</th><th>Level</th><th>Version</th><th>Added by</th><th></th></tr>
+{character_rows}
+</table>
+<form method="post" action="/characters/add">
+<input name="name" placeholder="Character name">
+<button>Track</button>
+</form>
+</section>
+<section>
+<h2>Schedules</h2>
+<form method="post" action="/schedules">
+<p><label>Weekly PvP report channel ID <input name="pvp_report_channel" value="{pvp_channel}"></label></p>
+<p><label>Milestone announcement channel ID <input name="milestone_channel" value="{milestone_channel}"></label></p>
+<p><label>Milestone levels <input name="milestone_levels" value="{milestone_levels}"></label></p>
+<p>Pinned race leaderboard: {race_message}</p>
+<p>Leave a channel empty to turn that post off.</p>
+<button>Save</button>
+</form>
+</section>
+</body>
+</html>"#,
+        messages = stats.messages,
+        contexts = stats.contexts,
+        tracked = stats.tracked_characters,
+        snapshots = stats.snapshots,
+        system_prompt = escape(&system_prompt),
+        max_cap = db::MAX_RESPONSE_CAP,
+        pvp_channel = escape(&pvp_channel),
+        milestone_channel = escape(&milestone_channel),
+        milestone_levels = escape(&milestone_levels),
+        race_message = if race_message.is_empty() {
+            "none".to_string()
+        } else {
+            escape(&race_message)
+        },
+    ))
+}
+
+async fn dashboard(State(state): State<Arc<WebState>>) -> Html<String> {
+    render(&state, None).await
+}
+
+#[derive(Deserialize)]
+struct SystemPromptForm {
+    prompt: String,
+}
+
+async fn set_system_prompt(
+    State(state): State<Arc<WebState>>,
+    Form(form): Form<SystemPromptForm>,
+) -> Html<String> {
+    let notice = {
+        let conn = state.handler.db.lock().await;
+        match db::set_config(&conn, None, "system_prompt", form.prompt.trim()) {
+            Ok(()) => "System prompt updated.".to_string(),
+            Err(e) => {
+                error!("Failed to set system prompt: {}", e);
+                "Failed to update system prompt.".to_string()
+            }
+        }
+    };
+    render(&state, Some(notice)).await
+}
+
+#[derive(Deserialize)]
+struct CapForm {
+    cap: String,
+}
+
+async fn set_cap(State(state): State<Arc<WebState>>, Form(form): Form<CapForm>) -> Html<String> {
+    let notice = match form.cap.trim().parse::<u32>() {
+        Ok(cap) if (1..=db::MAX_RESPONSE_CAP).contains(&cap) => {
+            let conn = state.handler.db.lock().await;
+            match db::set_config(&conn, None, "response_cap", &cap.to_string()) {
+                Ok(()) => format!("Response cap set to {} words.", cap),
+                Err(e) => {
+                    error!("Failed to set response cap: {}", e);
+                    "Failed to update response cap.".to_string()
+                }
+            }
+        }
+        _ => format!("Cap must be between 1 and {}.", db::MAX_RESPONSE_CAP),
+    };
+    render(&state, Some(notice)).await
+}
+
+#[derive(Deserialize)]
+struct CharacterForm {
+    name: String,
+}
+
+async fn add_character(
+    State(state): State<Arc<WebState>>,
+    Form(form): Form<CharacterForm>,
+) -> Html<String> {
+    let name = form.name.trim();
+    let notice = if name.is_empty() {
+        "Enter a character name.".to_string()
+    } else {
+        // Check the character exists (and get its proper capitalization) when we can
+        let name = if state.handler.blizzard.is_some() {
+            state.handler.fetch_wow_character(name).await.map(|c| c.name)
+        } else {
+            Ok(name.to_string())
+        };
+        match name {
+            Ok(name) => {
+                let conn = state.handler.db.lock().await;
+                match db::add_tracked_character(&conn, &name, "dashboard") {
+                    Ok(true) => format!("Now tracking {}.", name),
+                    Ok(false) => format!("{} is already tracked.", name),
+                    Err(e) => {
+                        error!("DB error adding character: {}", e);
+                        "Failed to save character.".to_string()
+                    }
+                }
+            }
+            Err(e) => e,
+        }
+    };
+    render(&state, Some(notice)).await
+}
+
+async fn remove_character(
+    State(state): State<Arc<WebState>>,
+    Form(form): Form<CharacterForm>,
+) -> Html<String> {
+    let notice = state.handler.remove_character(form.name.trim(), "dashboard").await;
+    render(&state, Some(notice)).await
+}
+
+#[derive(Deserialize)]
+struct SchedulesForm {
+    pvp_report_channel: String,
+    milestone_channel: String,
+    milestone_levels: String,
+}
+
+/// Stores a channel ID setting, or clears it when left blank.
+fn set_channel(conn: &rusqlite::Connection, key: &str, value: &str) -> Result<(), String> {
+    let value = value.trim();
+    if value.is_empty() {
+        db::delete_config(conn, None, key).map_err(|e| e.to_string())?;
+        return Ok(());
+    }
+    value
+        .parse::<u64>()
+        .map_err(|_| format!("`{}` is not a channel ID.", value))?;
+    db::set_config(conn, None, key, value).map_err(|e| e.to_string())
+}
+
+async fn set_schedules(
+    State(state): State<Arc<WebState>>,
+    Form(form): Form<SchedulesForm>,
+) -> Html<String> {
+    let notice = {
+        let conn = state.handler.db.lock().await;
+        let levels = wow::parse_milestones(&form.milestone_levels);
+        let result = set_channel(&conn, "pvp_report_channel", &form.pvp_report_channel)
+            .and_then(|_| set_channel(&conn, "milestone_channel", &form.milestone_channel))
+            .and_then(|_| {
+                if levels.is_empty() {
+                    return Err("Enter at least one milestone level.".to_string());
+                }
+                let levels: Vec<_> = levels.iter().map(u32::to_string).collect();
+                db::set_config(&conn, None, "milestone_levels", &levels.join(",")).map_err(|e| e.to_string())
+            });
+        match result {
+            Ok(()) => "Schedules updated.".to_string(),
+            Err(e) => e,
+        }
+    };
+    render(&state, Some(notice)).await
+}
+
+async fn events_socket(State(state): State<Arc<WebState>>, upgrade: WebSocketUpgrade) -> Response {
+    let events = state.handler.subscribe();
+    upgrade.on_upgrade(move |socket| stream_events(socket, events))
+}
+
+/// Forwards bot events to one WebSocket client as JSON text frames until either
+/// side goes away. Anything the client sends is ignored.
+async fn stream_events(mut socket: WebSocket, mut events: broadcast::Receiver<BotEvent>) {
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => {
+                    let frame = Message::Text(event.to_json(scheduler::unix_now()));
+                    if socket.send(frame).await.is_err() {
+                        return;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Event stream client fell behind, skipped {} events", skipped);
+                }
+                Err(RecvError::Closed) => return,
+            },
+            incoming = socket.recv() => match incoming {
+                Some(Ok(_)) => {}
+                _ => return,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presented_token() {
+        assert_eq!(presented_token("Bearer secret").as_deref(), Some("secret"));
+        // "admin:secret"
+        assert_eq!(presented_token("Basic YWRtaW46c2VjcmV0").as_deref(), Some("secret"));
+        assert_eq!(presented_token("Basic not-base64!"), None);
+        assert_eq!(presented_token("Token secret"), None);
+    }
+
+    #[test]
+    fn test_same_origin() {
+        let headers = |pairs: &[(header::HeaderName, &str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.insert(name, value.parse().unwrap());
+            }
+            headers
+        };
+        let host = (header::HOST, "bot.example:8080");
+        assert!(same_origin(&headers(&[host.clone(), (header::ORIGIN, "http://bot.example:8080")])));
+        assert!(same_origin(&headers(&[host.clone(), (header::REFERER, "https://bot.example:8080/?notice=1")])));
+        assert!(!same_origin(&headers(&[host.clone(), (header::ORIGIN, "https://evil.example")])));
+        assert!(!same_origin(&headers(&[host.clone(), (header::ORIGIN, "null")])));
+        assert!(!same_origin(&headers(&[host.clone(), (header::REFERER, "http://bot.example:8080.evil.example/")])));
+        assert!(!same_origin(&headers(&[(header::ORIGIN, "http://bot.example:8080")])));
+        // Not a browser
+        assert!(same_origin(&headers(&[host])));
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+    }
+}
@@ -0,0 +1,210 @@
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post, put};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use serenity::model::id::ChannelId;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::error;
+
+use super::WebState;
+use crate::db;
+use crate::markdown::DISCORD_MESSAGE_MAX;
+
+const DEFAULT_MESSAGE_LIMIT: usize = 50;
+
+/// The JSON admin API, mounted under `/api`.
+pub(super) fn router() -> Router<Arc<WebState>> {
+    Router::new()
+        .route("/config", get(list_config))
+        .route("/config/:key", put(set_config).delete(delete_config))
+        .route("/characters", get(list_characters).post(add_character))
+        .route("/characters/:name", delete(remove_character))
+        .route("/messages/:context", get(list_messages).delete(clear_messages))
+        .route("/send", post(send_message))
+}
+
+/// An error response: `{"error": "..."}` with the given status.
+struct ApiError(StatusCode, String);
+
+impl ApiError {
+    fn db(e: rusqlite::Error) -> ApiError {
+        error!("API database error: {}", e);
+        ApiError(StatusCode::INTERNAL_SERVER_ERROR, "Database error".to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+async fn list_config(State(state): State<Arc<WebState>>) -> ApiResult<Json<BTreeMap<String, String>>> {
+    let conn = state.handler.db.lock().await;
+    let entries = db::get_all_config(&conn).map_err(ApiError::db)?;
+    Ok(Json(entries.into_iter().collect()))
+}
+
+#[derive(Deserialize)]
+struct ConfigValue {
+    value: String,
+}
+
+async fn set_config(
+    State(state): State<Arc<WebState>>,
+    Path(key): Path<String>,
+    Json(body): Json<ConfigValue>,
+) -> ApiResult<StatusCode> {
+    let conn = state.handler.db.lock().await;
+    db::set_config(&conn, None, &key, &body.value).map_err(ApiError::db)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn delete_config(State(state): State<Arc<WebState>>, Path(key): Path<String>) -> ApiResult<StatusCode> {
+    let conn = state.handler.db.lock().await;
+    match db::delete_config(&conn, None, &key).map_err(ApiError::db)? {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err(ApiError(StatusCode::NOT_FOUND, format!("No config key `{}`", key))),
+    }
+}
+
+#[derive(Serialize)]
+struct Character {
+    name: String,
+    added_by: String,
+    added_at: i64,
+    game_version: Option<String>,
+    level: Option<u32>,
+}
+
+async fn list_characters(State(state): State<Arc<WebState>>) -> ApiResult<Json<Vec<Character>>> {
+    let conn = state.handler.db.lock().await;
+    let characters = db::get_tracked_character_details(&conn)
+        .map_err(ApiError::db)?
+        .into_iter()
+        .map(|c| Character {
+            level: db::latest_snapshot(&conn, &c.name).ok().flatten().map(|s| s.level),
+            name: c.name,
+            added_by: c.added_by,
+            added_at: c.added_at,
+            game_version: c.game_version,
+        })
+        .collect();
+    Ok(Json(characters))
+}
+
+#[derive(Deserialize)]
+struct NewCharacter {
+    name: String,
+    #[serde(default)]
+    added_by: Option<String>,
+}
+
+async fn add_character(
+    State(state): State<Arc<WebState>>,
+    Json(body): Json<NewCharacter>,
+) -> ApiResult<(StatusCode, Json<Value>)> {
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Err(ApiError(StatusCode::BAD_REQUEST, "`name` is required".to_string()));
+    }
+    // Check the character exists (and get its proper capitalization) when we can
+    let name = if state.handler.blizzard.is_some() {
+        state
+            .handler
+            .fetch_wow_character(name)
+            .await
+            .map_err(|e| ApiError(StatusCode::NOT_FOUND, e.replace("**", "")))?
+            .name
+    } else {
+        name.to_string()
+    };
+
+    let conn = state.handler.db.lock().await;
+    let added_by = body.added_by.as_deref().unwrap_or("api");
+    let created = db::add_tracked_character(&conn, &name, added_by).map_err(ApiError::db)?;
+    let status = if created { StatusCode::CREATED } else { StatusCode::OK };
+    Ok((status, Json(json!({ "name": name, "created": created }))))
+}
+
+async fn remove_character(State(state): State<Arc<WebState>>, Path(name): Path<String>) -> ApiResult<StatusCode> {
+    let conn = state.handler.db.lock().await;
+    match db::remove_tracked_character(&conn, &name).map_err(ApiError::db)? {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err(ApiError(StatusCode::NOT_FOUND, format!("{} is not being tracked", name))),
+    }
+}
+
+#[derive(Deserialize)]
+struct MessageQuery {
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct Message {
+    role: String,
+    content: String,
+}
+
+/// Conversation history for a context key: a channel ID, or `channel:user` in
+/// per-user context mode.
+async fn list_messages(
+    State(state): State<Arc<WebState>>,
+    Path(context): Path<String>,
+    Query(query): Query<MessageQuery>,
+) -> ApiResult<Json<Vec<Message>>> {
+    let conn = state.handler.db.lock().await;
+    let limit = query.limit.unwrap_or(DEFAULT_MESSAGE_LIMIT);
+    let messages = db::get_recent_messages(&conn, &context, limit)
+        .map_err(ApiError::db)?
+        .into_iter()
+        .map(|m| Message {
+            role: m.role,
+            content: m.content,
+        })
+        .collect();
+    Ok(Json(messages))
+}
+
+async fn clear_messages(State(state): State<Arc<WebState>>, Path(context): Path<String>) -> ApiResult<Json<Value>> {
+    let conn = state.handler.db.lock().await;
+    let deleted = db::clear_messages(&conn, &context).map_err(ApiError::db)?;
+    Ok(Json(json!({ "deleted": deleted })))
+}
+
+#[derive(Deserialize)]
+struct SendRequest {
+    channel_id: String,
+    content: String,
+}
+
+async fn send_message(
+    State(state): State<Arc<WebState>>,
+    Json(body): Json<SendRequest>,
+) -> ApiResult<Json<Value>> {
+    let channel_id = body
+        .channel_id
+        .parse::<u64>()
+        .ok()
+        .filter(|id| *id != 0)
+        .map(ChannelId::new)
+        .ok_or_else(|| ApiError(StatusCode::BAD_REQUEST, "`channel_id` must be a channel ID".to_string()))?;
+    if body.content.trim().is_empty() || body.content.chars().count() > DISCORD_MESSAGE_MAX {
+        return Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            format!("`content` must be 1-{} characters", DISCORD_MESSAGE_MAX),
+        ));
+    }
+
+    let sent = channel_id.say(&state.http, &body.content).await.map_err(|e| {
+        error!("API failed to send message: {:?}", e);
+        ApiError(StatusCode::BAD_GATEWAY, format!("Discord rejected the message: {}", e))
+    })?;
+    Ok(Json(json!({ "message_id": sent.id.to_string() })))
+}
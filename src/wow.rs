@@ -0,0 +1,1306 @@
+use futures::future::join_all;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+use serenity::builder::{
+    CreateActionRow, CreateAttachment, CreateButton, CreateEmbed, CreateEmbedFooter,
+    CreateMessage, EditMessage,
+};
+use serenity::http::Http;
+use serenity::model::id::{ChannelId, GuildId, MessageId};
+use tracing::{error, warn};
+
+use crate::db::{self, TrackedCharacter};
+use crate::{insults, render, scheduler, Handler};
+
+const REALM_SLUG: &str = "nightslayer";
+pub const REALM_NAME: &str = "Nightslayer";
+const DAY_SECS: i64 = 24 * 60 * 60;
+pub const DEFAULT_SLACKER_DAYS: u32 = 3;
+pub const DEFAULT_MILESTONES: &str = "40,60";
+pub const MAX_LEVEL: u32 = 60;
+/// How far back `!race` looks when measuring leveling speed.
+const RACE_VELOCITY_WINDOW_SECS: i64 = 7 * DAY_SECS;
+/// Recently obtained pets or mounts listed by `!pets` and `!mounts`.
+const RECENT_COLLECTION_ENTRIES: usize = 5;
+/// Token price updates shown in the `!wowtoken` sparkline.
+const TOKEN_HISTORY: usize = 24;
+const COPPER_PER_GOLD: u64 = 100 * 100;
+
+/// Battle.net API region. Credentials work in every region, but characters only
+/// exist in the one their realm belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Region {
+    Us,
+    Eu,
+    Kr,
+    Tw,
+}
+
+impl Region {
+    pub fn from_name(name: &str) -> Option<Region> {
+        match name.to_lowercase().as_str() {
+            "us" => Some(Region::Us),
+            "eu" => Some(Region::Eu),
+            "kr" => Some(Region::Kr),
+            "tw" => Some(Region::Tw),
+            _ => None,
+        }
+    }
+
+    pub fn slug(self) -> &'static str {
+        match self {
+            Region::Us => "us",
+            Region::Eu => "eu",
+            Region::Kr => "kr",
+            Region::Tw => "tw",
+        }
+    }
+
+    pub fn api_base(self) -> String {
+        format!("https://{}.api.blizzard.com", self.slug())
+    }
+
+    fn locale(self) -> &'static str {
+        match self {
+            Region::Us => "en_US",
+            Region::Eu => "en_GB",
+            Region::Kr => "ko_KR",
+            Region::Tw => "zh_TW",
+        }
+    }
+}
+
+/// Which flavour of WoW a character lives in; each has its own profile namespace.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameVersion {
+    ClassicEra,
+    Anniversary,
+    Cataclysm,
+    Retail,
+}
+
+impl GameVersion {
+    pub const ALL: [GameVersion; 4] = [
+        GameVersion::ClassicEra,
+        GameVersion::Anniversary,
+        GameVersion::Cataclysm,
+        GameVersion::Retail,
+    ];
+
+    /// Name stored in the database and accepted by commands.
+    pub fn name(self) -> &'static str {
+        match self {
+            GameVersion::ClassicEra => "era",
+            GameVersion::Anniversary => "anniversary",
+            GameVersion::Cataclysm => "cata",
+            GameVersion::Retail => "retail",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            GameVersion::ClassicEra => "Classic Era",
+            GameVersion::Anniversary => "Anniversary",
+            GameVersion::Cataclysm => "Cataclysm Classic",
+            GameVersion::Retail => "Retail",
+        }
+    }
+
+    /// Accepts our own names as well as Blizzard's namespace prefixes.
+    pub fn from_name(name: &str) -> Option<GameVersion> {
+        match name.to_lowercase().as_str() {
+            "era" | "classic1x" | "vanilla" => Some(GameVersion::ClassicEra),
+            "anniversary" | "classicann" | "fresh" => Some(GameVersion::Anniversary),
+            "cata" | "cataclysm" | "classic" => Some(GameVersion::Cataclysm),
+            "retail" | "live" => Some(GameVersion::Retail),
+            _ => None,
+        }
+    }
+
+    pub fn namespace(self, region: Region) -> String {
+        match self {
+            GameVersion::ClassicEra => format!("profile-classic1x-{}", region.slug()),
+            GameVersion::Anniversary => format!("profile-classicann-{}", region.slug()),
+            GameVersion::Cataclysm => format!("profile-classic-{}", region.slug()),
+            GameVersion::Retail => format!("profile-{}", region.slug()),
+        }
+    }
+}
+
+/// A character collection from the profile API.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Collection {
+    Pets,
+    Mounts,
+}
+
+impl Collection {
+    /// The endpoint's last path segment, also the key used in the database.
+    pub fn name(self) -> &'static str {
+        match self {
+            Collection::Pets => "pets",
+            Collection::Mounts => "mounts",
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            Collection::Pets => "Pets",
+            Collection::Mounts => "Mounts",
+        }
+    }
+
+    /// `(id, name)` of every entry in a collection response. Pets are
+    /// individual companions (two of a species are two pets); mounts are kinds.
+    fn entries(self, response: &Value) -> Vec<(u64, String)> {
+        let (list, id_path, name_path): (&str, &[&str], &[&str]) = match self {
+            Collection::Pets => ("pets", &["id"], &["species", "name"]),
+            Collection::Mounts => ("mounts", &["mount", "id"], &["mount", "name"]),
+        };
+        let field = |entry: &Value, path: &[&str]| path.iter().fold(entry.clone(), |v, key| v[key].clone());
+        response[list]
+            .as_array()
+            .map(|entries| {
+                entries
+                    .iter()
+                    .filter_map(|entry| {
+                        let id = field(entry, id_path).as_u64()?;
+                        let name = field(entry, name_path).as_str()?.to_string();
+                        Some((id, name))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenIndex {
+    /// Unix milliseconds.
+    last_updated_timestamp: i64,
+    /// In copper.
+    price: u64,
+}
+
+/// Gold with thousands separators, e.g. `212,345g`, dropping silver and copper.
+pub fn format_gold(copper: u64) -> String {
+    let digits = (copper / COPPER_PER_GOLD).to_string();
+    let mut grouped = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    format!("{}g", grouped)
+}
+
+/// A one-line chart of `values` in block characters, lowest to highest.
+pub fn sparkline(values: &[u64]) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let (Some(&min), Some(&max)) = (values.iter().min(), values.iter().max()) else {
+        return String::new();
+    };
+    values
+        .iter()
+        .map(|&v| match max - min {
+            0 => BLOCKS[3],
+            range => BLOCKS[((v - min) * (BLOCKS.len() as u64 - 1) / range) as usize],
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct CompletedQuests {
+    quests: Vec<QuestRef>,
+}
+
+#[derive(Deserialize)]
+struct QuestRef {
+    id: u32,
+}
+
+#[derive(Deserialize)]
+struct MediaAssets {
+    assets: Vec<MediaAsset>,
+}
+
+#[derive(Deserialize)]
+struct MediaAsset {
+    key: String,
+    value: String,
+}
+
+#[derive(Deserialize)]
+pub struct WowCharacter {
+    pub name: String,
+    pub level: u32,
+    pub race: WowEnum,
+    pub character_class: WowEnum,
+    pub experience: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct PvpSummary {
+    #[serde(default)]
+    pub honorable_kills: u32,
+    pub honor_level: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct WowEnum {
+    #[serde(default)]
+    pub id: u32,
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct ProfessionsSummary {
+    #[serde(default)]
+    pub primaries: Vec<Profession>,
+    #[serde(default)]
+    pub secondaries: Vec<Profession>,
+}
+
+/// Classic namespaces report skill directly on the profession; retail splits it
+/// into per-expansion tiers.
+#[derive(Deserialize)]
+pub struct Profession {
+    pub profession: WowEnum,
+    skill_points: Option<u32>,
+    max_skill_points: Option<u32>,
+    #[serde(default)]
+    tiers: Vec<ProfessionTier>,
+}
+
+#[derive(Deserialize)]
+struct ProfessionTier {
+    skill_points: u32,
+    max_skill_points: u32,
+}
+
+impl Profession {
+    /// Current and maximum skill, using the most recent tier when tiered.
+    pub fn skill(&self) -> Option<(u32, u32)> {
+        match (self.skill_points, self.max_skill_points) {
+            (Some(points), Some(max)) => Some((points, max)),
+            _ => self.tiers.last().map(|t| (t.skill_points, t.max_skill_points)),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self.skill() {
+            Some((points, max)) => format!("{} {}/{}", self.profession.name, points, max),
+            None => self.profession.name.clone(),
+        }
+    }
+}
+
+/// Factions shown by `!rep` when no faction is named: attunement, raid and
+/// pre-raid reputations that matter in classic.
+const KEY_FACTIONS: &[&str] = &[
+    "Argent Dawn",
+    "Hydraxian Waterlords",
+    "Thorium Brotherhood",
+    "Timbermaw Hold",
+    "Cenarion Circle",
+    "Zandalar Tribe",
+    "Brood of Nozdormu",
+];
+
+#[derive(Deserialize)]
+pub struct ReputationsSummary {
+    #[serde(default)]
+    pub reputations: Vec<Reputation>,
+}
+
+#[derive(Deserialize)]
+pub struct Reputation {
+    pub faction: WowEnum,
+    pub standing: Standing,
+}
+
+#[derive(Deserialize)]
+pub struct Standing {
+    pub value: u32,
+    pub max: u32,
+    pub name: String,
+}
+
+impl Standing {
+    pub fn describe(&self) -> String {
+        if self.max == 0 {
+            self.name.clone()
+        } else {
+            format!("{} {}/{}", self.name, self.value, self.max)
+        }
+    }
+}
+
+/// Reputations matching `faction` (case-insensitive substring), or the key
+/// classic factions when no filter is given.
+pub fn select_reputations<'a>(reps: &'a [Reputation], faction: Option<&str>) -> Vec<&'a Reputation> {
+    match faction {
+        Some(filter) => {
+            let filter = filter.to_lowercase();
+            reps.iter()
+                .filter(|r| r.faction.name.to_lowercase().contains(&filter))
+                .collect()
+        }
+        None => KEY_FACTIONS
+            .iter()
+            .filter_map(|key| reps.iter().find(|r| r.faction.name == *key))
+            .collect(),
+    }
+}
+
+pub struct LevelUp {
+    pub name: String,
+    pub from: u32,
+    pub to: u32,
+}
+
+/// Parses a comma-separated list of milestone levels, ignoring junk entries.
+pub fn parse_milestones(value: &str) -> Vec<u32> {
+    let mut levels: Vec<u32> = value
+        .split(',')
+        .filter_map(|v| v.trim().parse().ok())
+        .collect();
+    levels.sort_unstable();
+    levels.dedup();
+    levels
+}
+
+/// Milestones passed when going from level `from` to `to`, in ascending order.
+pub fn crossed_milestones(from: u32, to: u32, milestones: &[u32]) -> Vec<u32> {
+    milestones
+        .iter()
+        .copied()
+        .filter(|&m| from < m && m <= to)
+        .collect()
+}
+
+/// Levels per day between two snapshots, or `None` if no time has passed.
+pub fn leveling_velocity(from: &db::CharacterSnapshot, to_level: u32, now: i64) -> Option<f64> {
+    let elapsed_days = (now - from.taken_at) as f64 / DAY_SECS as f64;
+    if elapsed_days <= 0.0 {
+        return None;
+    }
+    Some(to_level.saturating_sub(from.level) as f64 / elapsed_days)
+}
+
+/// Projected unix time of reaching [`MAX_LEVEL`] at `velocity` levels per day.
+/// `None` if the character isn't progressing.
+pub fn project_max_level(level: u32, velocity: f64, now: i64) -> Option<i64> {
+    if level >= MAX_LEVEL {
+        return Some(now);
+    }
+    if velocity <= 0.0 {
+        return None;
+    }
+    let days = (MAX_LEVEL - level) as f64 / velocity;
+    Some(now + (days * DAY_SECS as f64) as i64)
+}
+
+/// Prefix for the `custom_id` of the character list page buttons.
+pub const LIST_PAGE_PREFIX: &str = "charlist:";
+const LIST_PAGE_SIZE: usize = 10;
+
+pub fn list_page_count(total: usize) -> usize {
+    total.div_ceil(LIST_PAGE_SIZE).max(1)
+}
+
+/// Embed and prev/next buttons for one page (0-based) of the tracked character list.
+pub fn list_page(characters: &[TrackedCharacter], page: usize) -> (CreateEmbed, Vec<CreateActionRow>) {
+    let pages = list_page_count(characters.len());
+    let page = page.min(pages - 1);
+
+    let description = if characters.is_empty() {
+        "No characters tracked. Use `!character add <name>` to add one.".to_string()
+    } else {
+        characters
+            .iter()
+            .skip(page * LIST_PAGE_SIZE)
+            .take(LIST_PAGE_SIZE)
+            .map(|c| format!("**{}** — added by <@{}> <t:{}:R>", c.name, c.added_by, c.added_at))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    let embed = CreateEmbed::new()
+        .title(format!("Tracked characters ({})", characters.len()))
+        .description(description)
+        .footer(CreateEmbedFooter::new(format!("Page {}/{}", page + 1, pages)));
+
+    if pages == 1 {
+        return (embed, vec![]);
+    }
+
+    let buttons = vec![
+        CreateButton::new(format!("{}{}", LIST_PAGE_PREFIX, page.saturating_sub(1)))
+            .label("◀ Prev")
+            .disabled(page == 0),
+        CreateButton::new(format!("{}{}", LIST_PAGE_PREFIX, page + 1))
+            .label("Next ▶")
+            .disabled(page + 1 >= pages),
+    ];
+    (embed, vec![CreateActionRow::Buttons(buttons)])
+}
+
+impl Handler {
+    /// The game version to query for `name`: the character's own setting, then the
+    /// `wow_version` config, then the `WOW_GAME_VERSION` default.
+    pub(crate) async fn game_version_for(&self, name: &str) -> GameVersion {
+        let conn = self.db.lock().await;
+        let character = db::get_tracked_character(&conn, name)
+            .ok()
+            .flatten()
+            .and_then(|c| c.game_version);
+        character
+            .or_else(|| db::get_config(&conn, None, "wow_version").ok().flatten())
+            .and_then(|v| GameVersion::from_name(&v))
+            .unwrap_or(self.wow_version)
+    }
+
+    /// GETs a character profile endpoint, e.g. `""` for the summary or `"/professions"`.
+    async fn fetch_character_endpoint<T: DeserializeOwned>(
+        &self,
+        name: &str,
+        endpoint: &str,
+    ) -> Result<T, String> {
+        let version = self.game_version_for(name).await;
+        self.fetch_character_endpoint_in(name, endpoint, version).await
+    }
+
+    async fn fetch_character_endpoint_in<T: DeserializeOwned>(
+        &self,
+        name: &str,
+        endpoint: &str,
+        version: GameVersion,
+    ) -> Result<T, String> {
+        let blizzard = self.blizzard.as_ref().ok_or("Battle.net not configured")?;
+        let path = format!(
+            "/profile/wow/character/{}/{}{}?namespace={}&locale={}",
+            REALM_SLUG,
+            name.to_lowercase(),
+            endpoint,
+            version.namespace(self.wow_region),
+            self.wow_region.locale()
+        );
+
+        let Some(value) = blizzard.get_json(&path).await? else {
+            return Err(format!(
+                "Character **{}** not found on {} ({}).",
+                name,
+                REALM_NAME,
+                version.label()
+            ));
+        };
+        serde_json::from_value(value).map_err(|e| format!("Failed to parse character data: {}", e))
+    }
+
+    pub(crate) async fn fetch_wow_character(&self, name: &str) -> Result<WowCharacter, String> {
+        self.fetch_character_endpoint(name, "").await
+    }
+
+    /// Like [`Handler::fetch_wow_character`] but with an explicit game version,
+    /// for `--version` overrides.
+    pub(crate) async fn fetch_wow_character_in(
+        &self,
+        name: &str,
+        version: GameVersion,
+    ) -> Result<WowCharacter, String> {
+        self.fetch_character_endpoint_in(name, "", version).await
+    }
+
+    /// The class icon image from the static media API. Icons are the same in every
+    /// game version, so this always asks retail.
+    async fn fetch_class_icon(&self, class_id: u32) -> Result<Vec<u8>, String> {
+        let blizzard = self.blizzard.as_ref().ok_or("Battle.net not configured")?;
+        let path = format!(
+            "/data/wow/media/playable-class/{}?namespace=static-{}&locale={}",
+            class_id,
+            self.wow_region.slug(),
+            self.wow_region.locale()
+        );
+        let media = blizzard.get_json(&path).await?.ok_or("Class has no media")?;
+        let media: MediaAssets =
+            serde_json::from_value(media).map_err(|e| format!("Failed to parse media: {}", e))?;
+
+        let icon = media
+            .assets
+            .into_iter()
+            .find(|a| a.key == "icon")
+            .ok_or("Class has no icon")?;
+        blizzard.download(&icon.value).await
+    }
+
+    async fn fetch_wow_token(&self) -> Result<TokenIndex, String> {
+        let blizzard = self.blizzard.as_ref().ok_or("Battle.net not configured")?;
+        let path = format!(
+            "/data/wow/token/index?namespace=dynamic-{}&locale={}",
+            self.wow_region.slug(),
+            self.wow_region.locale()
+        );
+        let token = blizzard.get_json(&path).await?.ok_or("No token price in this region")?;
+        serde_json::from_value(token).map_err(|e| format!("Failed to parse token price: {}", e))
+    }
+
+    /// Fetches the current token price and stores it if Blizzard has updated it.
+    async fn record_wow_token(&self) -> Result<TokenIndex, String> {
+        let token = self.fetch_wow_token().await?;
+        let conn = self.db.lock().await;
+        if let Err(e) = db::record_wow_token_price(&conn, token.last_updated_timestamp / 1000, token.price) {
+            error!("Failed to store token price: {}", e);
+        }
+        Ok(token)
+    }
+
+    /// Polls the token price on the scheduler tick, for the `!wowtoken` history.
+    pub(crate) async fn poll_wow_token(&self) {
+        if let Err(e) = self.record_wow_token().await {
+            warn!("Failed to poll the WoW token price: {}", e);
+        }
+    }
+
+    /// `!wowtoken`: the current retail token price and a sparkline of recent ones.
+    pub(crate) async fn wow_token_report(&self) -> String {
+        let token = match self.record_wow_token().await {
+            Ok(token) => token,
+            Err(e) => return format!("Couldn't get the token price: {}", e),
+        };
+        let history = {
+            let conn = self.db.lock().await;
+            db::get_wow_token_prices(&conn, TOKEN_HISTORY).unwrap_or_default()
+        };
+        let mut response = format!(
+            "**WoW Token ({} retail)** — {} (updated <t:{}:R>)\n",
+            self.wow_region.slug().to_uppercase(),
+            format_gold(token.price),
+            token.last_updated_timestamp / 1000
+        );
+        let prices: Vec<u64> = history.iter().map(|&(_, price)| price).collect();
+        if let (Some(low), Some(high)) = (prices.iter().min(), prices.iter().max()) {
+            if prices.len() > 1 {
+                response.push_str(&format!(
+                    "Last {} updates: {} (low {}, high {})\n",
+                    prices.len(),
+                    sparkline(&prices),
+                    format_gold(*low),
+                    format_gold(*high)
+                ));
+            }
+        }
+        response.push_str("A token buys 30 days of game time or Battle.net balance.");
+        response
+    }
+
+    /// `!pets <name>` / `!mounts <name>`: how many the character has and which
+    /// are new since earlier lookups and polls.
+    pub(crate) async fn collection_report(&self, name: &str, collection: Collection) -> String {
+        let endpoint = format!("/collections/{}", collection.name());
+        let response: Value = match self.fetch_character_endpoint(name, &endpoint).await {
+            Ok(response) => response,
+            Err(e) => return e,
+        };
+        let entries = collection.entries(&response);
+        let conn = self.db.lock().await;
+        if let Err(e) = db::sync_collection(&conn, name, collection.name(), &entries, scheduler::unix_now()) {
+            error!("Failed to store {} for {}: {}", collection.name(), name, e);
+        }
+        let recent = db::recent_collection_entries(&conn, name, collection.name(), RECENT_COLLECTION_ENTRIES)
+            .unwrap_or_default();
+
+        let mut response = format!("**{} — {}**: {} collected\n", collection.title(), name, entries.len());
+        if recent.is_empty() {
+            response.push_str("Nothing new since I started keeping track.");
+        } else {
+            response.push_str(&format!("Recently obtained: {}", recent.join(", ")));
+        }
+        response
+    }
+
+    /// A line for the level check insult prompt about what the character has
+    /// collected, from the counts stored at the last lookup; `None` if unknown.
+    pub(crate) async fn collection_material(&self, name: &str) -> Option<String> {
+        let conn = self.db.lock().await;
+        let count = |collection: Collection| db::collection_count(&conn, name, collection.name()).ok().flatten();
+        match (count(Collection::Mounts), count(Collection::Pets)) {
+            (None, None) => None,
+            (mounts, pets) => {
+                let parts: Vec<String> = [(mounts, "mounts"), (pets, "pets")]
+                    .into_iter()
+                    .filter_map(|(count, what)| count.map(|c| format!("{} {}", c, what)))
+                    .collect();
+                Some(format!("They have collected {}.", parts.join(" and ")))
+            }
+        }
+    }
+
+    /// IDs of the quests a character has completed. Classic realms mostly
+    /// don't offer this and answer 404.
+    pub(crate) async fn fetch_completed_quests(&self, name: &str) -> Result<Vec<u32>, String> {
+        let completed: CompletedQuests = self.fetch_character_endpoint(name, "/quests/completed").await?;
+        Ok(completed.quests.into_iter().map(|q| q.id).collect())
+    }
+
+    /// The URL of a character's avatar render, from the character media endpoint.
+    pub(crate) async fn fetch_character_avatar(&self, name: &str, version: GameVersion) -> Result<String, String> {
+        let media: MediaAssets = self.fetch_character_endpoint_in(name, "/character-media", version).await?;
+        media
+            .assets
+            .into_iter()
+            .find(|a| a.key == "avatar")
+            .map(|a| a.value)
+            .ok_or_else(|| format!("{} has no avatar", name))
+    }
+
+    /// [`Handler::fetch_character_avatar`] in the character's own game version,
+    /// or `None` (logged) if there isn't one.
+    pub(crate) async fn character_avatar(&self, name: &str) -> Option<String> {
+        let version = self.game_version_for(name).await;
+        self.fetch_character_avatar(name, version)
+            .await
+            .map_err(|e| warn!("No avatar for {}: {}", name, e))
+            .ok()
+    }
+
+    /// The `!character info` reply: an embed with the character's avatar as the
+    /// thumbnail and a rendered character card as the image. The avatar or card
+    /// is left off if it can't be fetched or drawn.
+    pub(crate) async fn character_info(
+        &self,
+        name: &str,
+        version: Option<GameVersion>,
+    ) -> Result<CreateMessage, String> {
+        let version = match version {
+            Some(version) => version,
+            None => self.game_version_for(name).await,
+        };
+        let (character, avatar) = tokio::join!(
+            self.fetch_wow_character_in(name, version),
+            self.fetch_character_avatar(name, version)
+        );
+        let character = character?;
+        let summary = format!(
+            "Level {} {} {}",
+            character.level, character.race.name, character.character_class.name
+        );
+        let mut embed = CreateEmbed::new()
+            .title(&character.name)
+            .description(summary)
+            .footer(CreateEmbedFooter::new(format!("{} ({})", REALM_NAME, version.label())));
+        match avatar {
+            Ok(url) => embed = embed.thumbnail(url),
+            Err(e) => warn!("No avatar for {}: {}", character.name, e),
+        }
+
+        let icon = match self.fetch_class_icon(character.character_class.id).await {
+            Ok(icon) => Some(icon),
+            Err(e) => {
+                warn!("No class icon for {}: {}", character.character_class.name, e);
+                None
+            }
+        };
+        let info = render::CardInfo {
+            name: &character.name,
+            level: character.level,
+            race: &character.race.name,
+            class: &character.character_class.name,
+            realm: REALM_NAME,
+            version: version.label(),
+        };
+        match render::character_card(&info, icon.as_deref()) {
+            Ok(png) => {
+                let filename = format!("{}.png", character.name.to_lowercase());
+                let embed = embed.image(format!("attachment://{}", filename));
+                Ok(CreateMessage::new().embed(embed).add_file(CreateAttachment::bytes(png, filename)))
+            }
+            Err(e) => {
+                warn!("Failed to render card for {}: {}", character.name, e);
+                Ok(CreateMessage::new().embed(embed))
+            }
+        }
+    }
+
+    pub(crate) async fn fetch_pvp_summary(&self, name: &str) -> Result<PvpSummary, String> {
+        self.fetch_character_endpoint(name, "/pvp-summary").await
+    }
+
+    pub(crate) async fn pvp_report(&self, name: &str) -> String {
+        let summary = match self.fetch_pvp_summary(name).await {
+            Ok(summary) => summary,
+            Err(e) => return e,
+        };
+
+        let mut response = format!("**PvP — {}**\n  Honorable kills: {}\n", name, summary.honorable_kills);
+        if let Some(level) = summary.honor_level {
+            response.push_str(&format!("  Honor level: {}\n", level));
+        }
+
+        // Compare against the snapshot from a week ago if the poller has one
+        let week_ago = {
+            let conn = self.db.lock().await;
+            db::snapshot_at_or_before(&conn, name, scheduler::unix_now() - scheduler::WEEK_SECS)
+                .ok()
+                .flatten()
+        };
+        if let Some(kills) = week_ago.and_then(|s| s.honorable_kills) {
+            response.push_str(&format!(
+                "  This week: +{} kills\n",
+                summary.honorable_kills.saturating_sub(kills)
+            ));
+        }
+        response
+    }
+
+    /// Fetches every tracked character and stores a snapshot for those that progressed.
+    /// Returns the characters that gained levels since their previous snapshot.
+    pub(crate) async fn snapshot_characters(&self) -> Vec<LevelUp> {
+        let names = {
+            let conn = self.db.lock().await;
+            db::get_tracked_characters(&conn).unwrap_or_default()
+        };
+        let mut level_ups = Vec::new();
+
+        for name in names {
+            let character = match self.fetch_wow_character(&name).await {
+                Ok(c) => c,
+                Err(e) => {
+                    warn!("Snapshot of {} failed: {}", name, e);
+                    continue;
+                }
+            };
+            // PvP data is optional; a failure shouldn't lose the level snapshot
+            let pvp = self.fetch_pvp_summary(&name).await.ok();
+
+            let snapshot = db::CharacterSnapshot {
+                name: character.name,
+                level: character.level,
+                experience: character.experience,
+                honorable_kills: pvp.as_ref().map(|p| p.honorable_kills),
+                honor_level: pvp.and_then(|p| p.honor_level),
+                taken_at: scheduler::unix_now(),
+            };
+            let conn = self.db.lock().await;
+            let previous = db::latest_snapshot(&conn, &name).ok().flatten();
+            if let Err(e) = db::record_snapshot(&conn, &snapshot) {
+                error!("Failed to store snapshot for {}: {}", name, e);
+                continue;
+            }
+            if let Some(previous) = previous {
+                if snapshot.level > previous.level {
+                    level_ups.push(LevelUp {
+                        name: snapshot.name,
+                        from: previous.level,
+                        to: snapshot.level,
+                    });
+                }
+            }
+        }
+        level_ups
+    }
+
+    /// Tracked characters by level, each with a projected level-60 date based on
+    /// the last week of snapshots.
+    pub(crate) async fn race_leaderboard(&self) -> String {
+        let now = scheduler::unix_now();
+        let conn = self.db.lock().await;
+        let names = db::get_tracked_characters(&conn).unwrap_or_default();
+
+        let mut rows: Vec<(u32, String)> = Vec::new();
+        for name in names {
+            let Ok(Some(latest)) = db::latest_snapshot(&conn, &name) else {
+                continue;
+            };
+            let baseline = db::snapshot_at_or_before(&conn, &name, now - RACE_VELOCITY_WINDOW_SECS)
+                .ok()
+                .flatten()
+                .or_else(|| db::earliest_snapshot(&conn, &name).ok().flatten());
+
+            let eta = if latest.level >= MAX_LEVEL {
+                "🏁 done".to_string()
+            } else {
+                let velocity = baseline.and_then(|b| leveling_velocity(&b, latest.level, now));
+                match velocity.and_then(|v| project_max_level(latest.level, v, now).map(|eta| (v, eta))) {
+                    Some((v, eta)) => format!("{:.1} lvl/day, {} by <t:{}:D>", v, MAX_LEVEL, eta),
+                    None => "stalled".to_string(),
+                }
+            };
+            rows.push((latest.level, format!("{} — Level {} — {}", latest.name, latest.level, eta)));
+        }
+
+        if rows.is_empty() {
+            return "No level data yet. The poller needs a little time.".to_string();
+        }
+
+        rows.sort_by_key(|(level, _)| std::cmp::Reverse(*level));
+
+        let mut response = format!("**Race to {}**\n", MAX_LEVEL);
+        for (i, (_, line)) in rows.iter().enumerate() {
+            response.push_str(&format!("  {}. {}\n", i + 1, line));
+        }
+        response.push_str(&format!("-# Updated <t:{}:R>", now));
+        response
+    }
+
+    /// Level-over-time chart for one tracked character, or the whole roster when
+    /// `name` is `None`. Built from stored snapshots only.
+    pub(crate) async fn level_chart(&self, name: Option<&str>) -> Result<CreateAttachment, String> {
+        let (title, series) = {
+            let conn = self.db.lock().await;
+            let (title, names) = match name {
+                Some(name) => {
+                    let character = db::get_tracked_character(&conn, name)
+                        .map_err(|e| format!("Failed to load character: {}", e))?
+                        .ok_or_else(|| format!("**{}** is not being tracked.", name))?;
+                    (format!("{} — level over time", character.name), vec![character.name])
+                }
+                None => (
+                    "Tracked characters — level over time".to_string(),
+                    db::get_tracked_characters(&conn).unwrap_or_default(),
+                ),
+            };
+            let series: Vec<render::LevelSeries> = names
+                .into_iter()
+                .map(|name| {
+                    let points = db::get_snapshots(&conn, &name)
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|s| (s.taken_at, s.level))
+                        .collect();
+                    render::LevelSeries { name, points }
+                })
+                .filter(|s| !s.points.is_empty())
+                .collect();
+            (title, series)
+        };
+        if series.is_empty() {
+            return Err("No level data yet. The poller needs a little time.".to_string());
+        }
+
+        let png = render::level_chart(&title, &series, scheduler::unix_now())?;
+        Ok(CreateAttachment::bytes(png, "levels.png"))
+    }
+
+    /// Edits the pinned `!race` leaderboard, if one has been set up.
+    pub(crate) async fn update_race_message(&self, http: &Http) {
+        let target = {
+            let conn = self.db.lock().await;
+            db::get_config(&conn, None, "race_message").ok().flatten()
+        };
+        let Some((channel, message)) = target.as_deref().and_then(|v| v.split_once(':')) else {
+            return;
+        };
+        let (Ok(channel), Ok(message)) = (channel.parse::<u64>(), message.parse::<u64>()) else {
+            return;
+        };
+
+        let leaderboard = self.race_leaderboard().await;
+        let edit = EditMessage::new().content(leaderboard);
+        if let Err(why) = ChannelId::new(channel)
+            .edit_message(http, MessageId::new(message), edit)
+            .await
+        {
+            warn!("Failed to update race leaderboard: {:?}", why);
+        }
+    }
+
+    /// Posts a congratulation for every milestone crossed in `level_ups`,
+    /// pinging whoever added the character.
+    pub(crate) async fn announce_milestones(&self, http: &Http, level_ups: &[LevelUp]) {
+        let (channel, milestones) = {
+            let conn = self.db.lock().await;
+            let Some(channel) = db::get_config(&conn, None, "milestone_channel")
+                .ok()
+                .flatten()
+                .and_then(|v| v.parse::<u64>().ok())
+            else {
+                return;
+            };
+            let milestones = parse_milestones(
+                &db::get_config(&conn, None, "milestone_levels")
+                    .ok()
+                    .flatten()
+                    .unwrap_or_else(|| DEFAULT_MILESTONES.to_string()),
+            );
+            (ChannelId::new(channel), milestones)
+        };
+        // The mode and style of the guild the channel is in, looked up at the first announcement
+        let mut settings = None;
+
+        for level_up in level_ups {
+            // Several milestones at once (long gap between polls) only get one announcement
+            let Some(milestone) = crossed_milestones(level_up.from, level_up.to, &milestones).pop() else {
+                continue;
+            };
+
+            let owner = {
+                let conn = self.db.lock().await;
+                db::get_tracked_character(&conn, &level_up.name)
+                    .ok()
+                    .flatten()
+                    .map(|c| c.added_by)
+            };
+
+            let mut announcement = match &owner {
+                Some(owner) => format!("🎉 <@{}> **{}** just hit level **{}**!", owner, level_up.name, milestone),
+                None => format!("🎉 **{}** just hit level **{}**!", level_up.name, milestone),
+            };
+            if self.llm.is_some() {
+                let (mode, style) = match settings {
+                    Some(settings) => settings,
+                    None => {
+                        let guild_id = channel.to_channel(http).await.ok().and_then(|c| c.guild()).map(|c| c.guild_id);
+                        *settings.insert((self.report_mode(guild_id).await, self.insult_style(guild_id).await))
+                    }
+                };
+                let about = format!("just reached level {} in WoW", milestone);
+                let target = match mode {
+                    insults::Mode::Hype => Some(insults::Target::Hype {
+                        name: &level_up.name,
+                        about: Some(&about),
+                    }),
+                    insults::Mode::Roast if self.insultable(&level_up.name).await => Some(insults::Target::Milestone {
+                        name: &level_up.name,
+                        level: milestone,
+                    }),
+                    insults::Mode::Roast => None,
+                };
+                if let Some(target) = target {
+                    if let Some(congrats) = self.insult(style, target).await {
+                        announcement.push_str(&format!("\n*{}*", congrats));
+                    }
+                }
+            }
+
+            if let Err(why) = channel.say(http, &announcement).await {
+                error!("Failed to post milestone announcement: {:?}", why);
+            }
+        }
+    }
+
+    /// Honor gained by each tracked character over the last week, from snapshots.
+    pub(crate) async fn weekly_pvp_report(&self) -> String {
+        let now = scheduler::unix_now();
+        let conn = self.db.lock().await;
+        let names = db::get_tracked_characters(&conn).unwrap_or_default();
+
+        // (kills gained, report line)
+        let mut rows: Vec<(u32, String)> = Vec::new();
+        for name in names {
+            let Ok(Some(latest)) = db::latest_snapshot(&conn, &name) else {
+                continue;
+            };
+            let Some(kills) = latest.honorable_kills else {
+                continue;
+            };
+            let before = db::snapshot_at_or_before(&conn, &name, now - scheduler::WEEK_SECS)
+                .ok()
+                .flatten();
+            let gained = before
+                .as_ref()
+                .and_then(|b| b.honorable_kills)
+                .map_or(0, |k| kills.saturating_sub(k));
+            let mut line = format!("  {} — +{} kills ({} total)", latest.name, gained, kills);
+            if let (Some(old), Some(new)) = (before.and_then(|b| b.honor_level), latest.honor_level) {
+                if new != old {
+                    line.push_str(&format!(", honor level {} → {}", old, new));
+                }
+            }
+            rows.push((gained, line));
+        }
+
+        if rows.is_empty() {
+            return "No PvP data yet. Check back next week.".to_string();
+        }
+
+        rows.sort_by_key(|(gained, _)| std::cmp::Reverse(*gained));
+
+        let mut response = String::from("**Weekly PvP Report**\n");
+        for (_, line) in rows {
+            response.push_str(&line);
+            response.push('\n');
+        }
+        response
+    }
+
+    /// Tracked characters with no level/XP progress in the last `window_days`,
+    /// longest idle first, with a persona-flavored verdict if the LLM is available.
+    pub(crate) async fn slackers_report(&self, guild_id: Option<GuildId>, window_days: u32) -> String {
+        let now = scheduler::unix_now();
+        let slackers = {
+            let conn = self.db.lock().await;
+            let names = db::get_tracked_characters(&conn).unwrap_or_default();
+            let mut slackers: Vec<(String, i64)> = names
+                .into_iter()
+                .filter_map(|name| {
+                    let since = db::last_level_progress(&conn, &name).ok().flatten()?;
+                    let idle_days = (now - since) / DAY_SECS;
+                    (idle_days >= window_days as i64).then_some((name, idle_days))
+                })
+                .collect();
+            slackers.sort_by_key(|(_, days)| std::cmp::Reverse(*days));
+            slackers
+        };
+
+        if slackers.is_empty() {
+            return format!(
+                "Nobody has been idle for {}+ days. Suspicious, but fine.",
+                window_days
+            );
+        }
+
+        let mut response = format!("**Slackers — no progress in {}+ days**\n", window_days);
+        for (name, days) in &slackers {
+            response.push_str(&format!("  {} — {} days idle\n", name, days));
+        }
+
+        let mut roastable = Vec::new();
+        for (name, days) in &slackers {
+            if self.insultable(name).await {
+                roastable.push(format!("{} ({} days)", name, days));
+            }
+        }
+        if self.llm.is_some() && !roastable.is_empty() {
+            let style = self.insult_style(guild_id).await;
+            let list = roastable.join(", ");
+            if let Some(verdict) = self.insult(style, insults::Target::Slackers { list: &list }).await {
+                response.push_str(&format!("\n*{}*", verdict));
+            }
+        }
+        response
+    }
+
+    pub(crate) async fn fetch_professions(&self, name: &str) -> Result<ProfessionsSummary, String> {
+        self.fetch_character_endpoint(name, "/professions").await
+    }
+
+    pub(crate) async fn reputation_embed(&self, name: &str, faction: Option<&str>) -> Result<CreateEmbed, String> {
+        let summary: ReputationsSummary = self.fetch_character_endpoint(name, "/reputations").await?;
+        let selected = select_reputations(&summary.reputations, faction);
+
+        let embed = CreateEmbed::new().title(format!("Reputation — {}", name));
+        if selected.is_empty() {
+            let description = match faction {
+                Some(f) => format!("No reputation matching **{}**.", f),
+                None => "No standing with any of the usual factions. Get to work.".to_string(),
+            };
+            return Ok(embed.description(description));
+        }
+
+        // Embeds allow at most 25 fields
+        Ok(embed.fields(
+            selected
+                .into_iter()
+                .take(25)
+                .map(|r| (r.faction.name.clone(), r.standing.describe(), true)),
+        ))
+    }
+
+    pub(crate) async fn professions_report(&self, name: &str) -> String {
+        let summary = match self.fetch_professions(name).await {
+            Ok(summary) => summary,
+            Err(e) => return e,
+        };
+
+        if summary.primaries.is_empty() && summary.secondaries.is_empty() {
+            return format!("**{}** has no professions. Useless.", name);
+        }
+
+        let mut response = format!("**Professions — {}**\n", name);
+        for p in &summary.primaries {
+            response.push_str(&format!("  {}\n", p.describe()));
+        }
+        if !summary.secondaries.is_empty() {
+            let secondaries: Vec<_> = summary.secondaries.iter().map(Profession::describe).collect();
+            response.push_str(&format!("  *Secondary:* {}\n", secondaries.join(", ")));
+        }
+        response
+    }
+
+    /// Tracked characters that have `profession`, highest skill first.
+    pub(crate) async fn crafters_report(&self, profession: &str) -> String {
+        let names = {
+            let conn = self.db.lock().await;
+            db::get_tracked_characters(&conn).unwrap_or_default()
+        };
+
+        if names.is_empty() {
+            return "No characters tracked. Use `!character add <name>` to add one.".to_string();
+        }
+
+        let futures: Vec<_> = names.iter().map(|name| self.fetch_professions(name)).collect();
+        let results = join_all(futures).await;
+
+        let mut crafters: Vec<(String, Option<(u32, u32)>)> = Vec::new();
+        let mut failures = 0;
+        for (name, result) in names.iter().zip(results) {
+            let Ok(summary) = result else {
+                failures += 1;
+                continue;
+            };
+            let found = summary
+                .primaries
+                .iter()
+                .chain(summary.secondaries.iter())
+                .find(|p| p.profession.name.eq_ignore_ascii_case(profession));
+            if let Some(p) = found {
+                crafters.push((name.clone(), p.skill()));
+            }
+        }
+
+        crafters.sort_by_key(|(_, skill)| std::cmp::Reverse(skill.map(|(points, _)| points)));
+
+        let mut response = if crafters.is_empty() {
+            format!("Nobody tracked has **{}**.\n", profession)
+        } else {
+            let mut response = format!("**Crafters — {}**\n", profession);
+            for (name, skill) in &crafters {
+                match skill {
+                    Some((points, max)) => response.push_str(&format!("  {} — {}/{}\n", name, points, max)),
+                    None => response.push_str(&format!("  {}\n", name)),
+                }
+            }
+            response
+        };
+        if failures > 0 {
+            response.push_str(&format!("  ⚠ Couldn't fetch professions for {} character(s)\n", failures));
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collection_entries() {
+        let pets = serde_json::json!({ "pets": [
+            { "id": 11, "species": { "id": 1, "name": "Mechanical Squirrel" }, "level": 1 },
+            { "id": 12, "species": { "id": 1, "name": "Mechanical Squirrel" }, "level": 3 },
+        ]});
+        assert_eq!(Collection::Pets.entries(&pets).len(), 2);
+
+        let mounts = serde_json::json!({ "mounts": [{ "mount": { "id": 6, "name": "Brown Horse" } }] });
+        assert_eq!(Collection::Mounts.entries(&mounts), [(6, "Brown Horse".to_string())]);
+        assert!(Collection::Mounts.entries(&pets).is_empty());
+    }
+
+    #[test]
+    fn test_format_gold() {
+        assert_eq!(format_gold(212_345_678_901), "21,234,567g");
+        assert_eq!(format_gold(999 * COPPER_PER_GOLD + 55), "999g");
+        assert_eq!(format_gold(0), "0g");
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[1, 5, 8]), "▁▅█");
+        assert_eq!(sparkline(&[7, 7]), "▄▄");
+        assert_eq!(sparkline(&[]), "");
+    }
+
+    #[test]
+    fn test_profession_skill_flat_and_tiered() {
+        let classic: Profession = serde_json::from_str(
+            r#"{"profession": {"name": "Tailoring"}, "skill_points": 225, "max_skill_points": 300}"#,
+        )
+        .unwrap();
+        assert_eq!(classic.skill(), Some((225, 300)));
+        assert_eq!(classic.describe(), "Tailoring 225/300");
+
+        let retail: Profession = serde_json::from_str(
+            r#"{"profession": {"name": "Alchemy"}, "tiers": [
+                {"skill_points": 300, "max_skill_points": 300, "tier": {"name": "Classic Alchemy"}},
+                {"skill_points": 42, "max_skill_points": 100, "tier": {"name": "Dragon Isles Alchemy"}}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(retail.skill(), Some((42, 100)));
+
+        let bare: Profession = serde_json::from_str(r#"{"profession": {"name": "Fishing"}}"#).unwrap();
+        assert_eq!(bare.skill(), None);
+        assert_eq!(bare.describe(), "Fishing");
+    }
+
+    #[test]
+    fn test_select_reputations() {
+        let summary: ReputationsSummary = serde_json::from_str(
+            r#"{"reputations": [
+                {"faction": {"name": "Timbermaw Hold"}, "standing": {"raw": 1, "value": 100, "max": 3000, "tier": 3, "name": "Neutral"}},
+                {"faction": {"name": "Argent Dawn"}, "standing": {"raw": 1, "value": 2500, "max": 12000, "tier": 5, "name": "Honored"}},
+                {"faction": {"name": "Darnassus"}, "standing": {"raw": 1, "value": 0, "max": 0, "tier": 7, "name": "Exalted"}}
+            ]}"#,
+        )
+        .unwrap();
+
+        // Key factions come out in the fixed order, non-key factions are skipped
+        let names: Vec<_> = select_reputations(&summary.reputations, None)
+            .iter()
+            .map(|r| r.faction.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Argent Dawn", "Timbermaw Hold"]);
+
+        let found = select_reputations(&summary.reputations, Some("darn"));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].standing.describe(), "Exalted");
+        assert_eq!(summary.reputations[1].standing.describe(), "Honored 2500/12000");
+    }
+
+    #[test]
+    fn test_milestones() {
+        assert_eq!(parse_milestones("60, 40,junk,40"), vec![40, 60]);
+        assert!(parse_milestones("").is_empty());
+
+        let milestones = [40, 60];
+        assert_eq!(crossed_milestones(39, 40, &milestones), vec![40]);
+        assert_eq!(crossed_milestones(38, 61, &milestones), vec![40, 60]);
+        assert!(crossed_milestones(40, 41, &milestones).is_empty());
+        assert!(crossed_milestones(10, 12, &milestones).is_empty());
+    }
+
+    #[test]
+    fn test_game_version_namespaces() {
+        assert_eq!(
+            GameVersion::Anniversary.namespace(Region::Us),
+            "profile-classicann-us"
+        );
+        assert_eq!(GameVersion::ClassicEra.namespace(Region::Eu), "profile-classic1x-eu");
+        assert_eq!(GameVersion::Cataclysm.namespace(Region::Kr), "profile-classic-kr");
+        assert_eq!(GameVersion::Retail.namespace(Region::Tw), "profile-tw");
+        assert_eq!(Region::Eu.locale(), "en_GB");
+
+        for version in GameVersion::ALL {
+            assert_eq!(GameVersion::from_name(version.name()), Some(version));
+        }
+        assert_eq!(GameVersion::from_name("ClassicAnn"), Some(GameVersion::Anniversary));
+        assert_eq!(GameVersion::from_name("wotlk"), None);
+        assert_eq!(Region::from_name("EU"), Some(Region::Eu));
+        assert_eq!(Region::from_name("cn"), None);
+    }
+
+    #[test]
+    fn test_race_projection() {
+        let day = DAY_SECS;
+        let from = db::CharacterSnapshot {
+            name: "Pyuul".to_string(),
+            level: 20,
+            experience: None,
+            honorable_kills: None,
+            honor_level: None,
+            taken_at: 0,
+        };
+
+        // 20 -> 30 over 5 days is 2 levels/day, so 30 more levels take 15 days
+        let v = leveling_velocity(&from, 30, 5 * day).unwrap();
+        assert!((v - 2.0).abs() < 1e-9);
+        assert_eq!(project_max_level(30, v, 5 * day), Some(20 * day));
+
+        assert_eq!(leveling_velocity(&from, 30, 0), None);
+        assert_eq!(project_max_level(30, 0.0, 5 * day), None);
+        assert_eq!(project_max_level(60, 0.0, 5 * day), Some(5 * day));
+    }
+}